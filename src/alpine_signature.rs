@@ -0,0 +1,209 @@
+//! GPG verification of the Alpine ISO (`acornos download --verify-gpg`).
+//!
+//! A pin or the mirror's `.sha256` says which ISO is expected, not who
+//! released it. Alpine signs every release ISO (`<iso>.asc`) with the
+//! release key of Natanael Copa; with `--verify-gpg` that signature is
+//! checked before the ISO is trusted.
+//!
+//! The `ALPINE_KEYS` in distro-spec are apk's RSA package keys, which gpg
+//! cannot use. The release key is therefore fetched from alpinelinux.org
+//! into a throwaway keyring, and only a good signature made by the
+//! fingerprint pinned here ([`ALPINE_RELEASE_KEY_FINGERPRINT`]) passes.
+
+use anyhow::{bail, Context, Result};
+use std::ffi::OsString;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use crate::download::{Download, Fetcher};
+use crate::signing::ToolRunner;
+
+/// Armored Alpine release key.
+pub const ALPINE_RELEASE_KEY_URL: &str = "https://alpinelinux.org/keys/ncopa.asc";
+
+/// Fingerprint of the Alpine release key (Natanael Copa).
+pub const ALPINE_RELEASE_KEY_FINGERPRINT: &str = "0482D84022F52DF1C4E7CD43293ACD0907D9495A";
+
+/// Throwaway keyring next to the ISO.
+const KEYRING_DIR: &str = ".alpine-gnupg";
+
+/// Whether gpg can be run on the host.
+pub fn gpg_available(runner: &dyn ToolRunner) -> bool {
+    runner
+        .run("gpg", &["--version".into()])
+        .map(|o| o.success)
+        .unwrap_or(false)
+}
+
+/// Verify the downloaded ISO of `iso` against its release signature,
+/// fetched from the primary mirror.
+pub fn verify_iso(runner: &dyn ToolRunner, fetcher: &dyn Fetcher, iso: &Download) -> Result<()> {
+    if !gpg_available(runner) {
+        bail!("--verify-gpg needs gpg on the host. Install: sudo dnf install gnupg2");
+    }
+    let sig_url = format!("{}.asc", iso.url);
+    let signature = fetcher
+        .fetch_text(&sig_url)
+        .with_context(|| format!("Could not fetch the release signature {}", sig_url))?;
+    let key = fetcher
+        .fetch_text(ALPINE_RELEASE_KEY_URL)
+        .context("Could not fetch the Alpine release key")?;
+    verify_with(runner, &iso.dest, &signature, &key)
+}
+
+/// Verify `file` against the armored `signature`, with `key` imported into
+/// a fresh keyring; only [`ALPINE_RELEASE_KEY_FINGERPRINT`] is accepted.
+pub fn verify_with(runner: &dyn ToolRunner, file: &Path, signature: &str, key: &str) -> Result<()> {
+    let dir = file.parent().unwrap_or(Path::new("."));
+    let home = dir.join(KEYRING_DIR);
+    let _ = fs::remove_dir_all(&home);
+    fs::create_dir_all(&home).with_context(|| format!("Failed to create {}", home.display()))?;
+    fs::set_permissions(&home, fs::Permissions::from_mode(0o700))?;
+    let result = verify_in(runner, &home, file, signature, key);
+    let _ = fs::remove_dir_all(&home);
+    result
+}
+
+fn verify_in(
+    runner: &dyn ToolRunner,
+    home: &Path,
+    file: &Path,
+    signature: &str,
+    key: &str,
+) -> Result<()> {
+    let key_path = home.join("release-key.asc");
+    let sig_path = home.join("iso.asc");
+    fs::write(&key_path, key)?;
+    fs::write(&sig_path, signature)?;
+    let gpg = |args: Vec<OsString>| {
+        let mut all: Vec<OsString> = vec!["--batch".into(), "--homedir".into(), home.into()];
+        all.extend(args);
+        runner.run("gpg", &all).context("Failed to run gpg")
+    };
+
+    let import = gpg(vec!["--import".into(), key_path.into()])?;
+    if !import.success {
+        bail!("Importing the Alpine release key failed: {}", import.stderr);
+    }
+    // Status lines go to stderr, which the runner captures
+    let verify = gpg(vec![
+        "--status-fd".into(),
+        "2".into(),
+        "--verify".into(),
+        sig_path.into(),
+        file.into(),
+    ])?;
+    let signers = valid_signers(&verify.stderr);
+    if !verify.success || signers.is_empty() {
+        bail!(
+            "{} does not carry a good Alpine release signature: {}",
+            file.display(),
+            verify.stderr
+        );
+    }
+    if !signers
+        .iter()
+        .any(|fpr| fpr.eq_ignore_ascii_case(ALPINE_RELEASE_KEY_FINGERPRINT))
+    {
+        bail!(
+            "{} is signed by {}, not the Alpine release key {}",
+            file.display(),
+            signers.join(", "),
+            ALPINE_RELEASE_KEY_FINGERPRINT
+        );
+    }
+    Ok(())
+}
+
+/// Fingerprints of good signatures in gpg's status output: the signing
+/// (sub)key and its primary key, from each `VALIDSIG` line.
+fn valid_signers(status: &str) -> Vec<&str> {
+    status
+        .lines()
+        .filter_map(|l| l.strip_prefix("[GNUPG:] VALIDSIG "))
+        .flat_map(|rest| {
+            let fields: Vec<&str> = rest.split_whitespace().collect();
+            [fields.first().copied(), fields.last().copied()]
+        })
+        .flatten()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signing::ToolOutput;
+    use std::cell::RefCell;
+
+    /// Answers `--verify` with the given status output.
+    struct MockGpg {
+        status: String,
+        calls: RefCell<Vec<Vec<OsString>>>,
+    }
+
+    impl ToolRunner for MockGpg {
+        fn run(&self, _program: &str, args: &[OsString]) -> std::io::Result<ToolOutput> {
+            self.calls.borrow_mut().push(args.to_vec());
+            let verify = args.iter().any(|a| a == "--verify");
+            Ok(ToolOutput {
+                success: !verify || self.status.contains("GOODSIG"),
+                stderr: if verify {
+                    self.status.clone()
+                } else {
+                    String::new()
+                },
+            })
+        }
+    }
+
+    fn status(fingerprint: &str) -> String {
+        format!(
+            "[GNUPG:] NEWSIG\n[GNUPG:] GOODSIG 293ACD0907D9495A Natanael Copa\n\
+             [GNUPG:] VALIDSIG {0} 2025-12-03 1764756000 0 4 0 1 10 00 {0}\n",
+            fingerprint
+        )
+    }
+
+    #[test]
+    fn test_only_the_release_key_passes() {
+        let dir = tempfile::tempdir().unwrap();
+        let iso = dir.path().join("alpine.iso");
+        fs::write(&iso, "iso").unwrap();
+
+        let good = MockGpg {
+            status: status(ALPINE_RELEASE_KEY_FINGERPRINT),
+            calls: RefCell::new(Vec::new()),
+        };
+        verify_with(&good, &iso, "sig", "key").unwrap();
+        let calls = good.calls.borrow();
+        assert!(calls[0].contains(&"--import".into()));
+        assert_eq!(calls[0][2], dir.path().join(KEYRING_DIR).into_os_string());
+        assert!(!dir.path().join(KEYRING_DIR).exists());
+
+        let other = MockGpg {
+            status: status(&"A".repeat(40)),
+            calls: RefCell::new(Vec::new()),
+        };
+        let err = verify_with(&other, &iso, "sig", "key")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("not the Alpine release key"), "{}", err);
+
+        let bad = MockGpg {
+            status: "[GNUPG:] BADSIG 293ACD0907D9495A Natanael Copa\n".to_string(),
+            calls: RefCell::new(Vec::new()),
+        };
+        let err = verify_with(&bad, &iso, "sig", "key")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("does not carry a good"), "{}", err);
+    }
+
+    #[test]
+    fn test_valid_signers_include_primary_key() {
+        let status = "[GNUPG:] VALIDSIG SUBKEY 2025-12-03 1764756000 0 4 0 1 10 00 PRIMARY\n";
+        assert_eq!(valid_signers(status), ["SUBKEY", "PRIMARY"]);
+        assert!(valid_signers("[GNUPG:] GOODSIG X\n").is_empty());
+    }
+}
@@ -0,0 +1,361 @@
+//! Alpine mirror and release (`--alpine-mirror`, `--alpine-version`).
+//!
+//! By default the Extended ISO, apk-tools-static and the package repos come
+//! from dl-cdn.alpinelinux.org and the release pinned in deps/alpine.rhai.
+//! `--alpine-mirror URL` (or `ALPINE_MIRROR`) points every Alpine download
+//! and the rootfs's `/etc/apk/repositories` at another mirror, and
+//! `--alpine-version X.Y` (or `ALPINE_VERSION`) builds from another stable
+//! branch: its X.Y.0 Extended ISO, verified against the mirror's published
+//! `.sha256`, and its main/community repos. apk-tools-static stays on the
+//! pinned branch; it installs from any.
+//!
+//! Both are exported so the recipes, which swap them into their URLs at run
+//! time, and child builds see the same source. Both are part of the rootfs
+//! input hash and the Alpine rootfs cache key, so switching rebuilds.
+//!
+//! The ISO and apk-tools-static downloads fall back to other mirrors when
+//! the primary one fails: `--mirror URL` entries (or `ALPINE_MIRRORS`), in
+//! order, then dl-cdn and dl-2. Fallbacks only serve files; checksums still
+//! come from the primary mirror or a pinned value, so fallbacks are not part
+//! of any hash.
+
+use anyhow::{bail, Result};
+use std::fmt;
+
+/// Environment variable carrying the mirror.
+pub const MIRROR_ENV: &str = "ALPINE_MIRROR";
+
+/// Environment variable carrying the fallback mirrors (space-separated).
+pub const MIRRORS_ENV: &str = "ALPINE_MIRRORS";
+
+/// Environment variable carrying the branch override.
+pub const VERSION_ENV: &str = "ALPINE_VERSION";
+
+/// Mirror the recipes' URLs are written against.
+pub const DEFAULT_MIRROR: &str = "https://dl-cdn.alpinelinux.org/alpine";
+
+/// Built-in fallbacks, tried after the primary and `--mirror` entries.
+pub const FALLBACK_MIRRORS: &[&str] = &[DEFAULT_MIRROR, "https://dl-2.alpinelinux.org/alpine"];
+
+/// Release pinned in deps/alpine.rhai.
+pub const DEFAULT_RELEASE: &str = "3.23.2";
+
+/// Where Alpine comes from for this build.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlpineSource {
+    /// Mirror base URL, without a trailing `/` (`.../alpine`).
+    pub mirror: String,
+    /// Branch override (`3.22`), `None` for the pinned release.
+    pub version: Option<String>,
+    /// User fallback mirrors (`--mirror`), without a trailing `/`.
+    pub fallbacks: Vec<String>,
+}
+
+impl Default for AlpineSource {
+    fn default() -> Self {
+        Self {
+            mirror: DEFAULT_MIRROR.to_string(),
+            version: None,
+            fallbacks: Vec::new(),
+        }
+    }
+}
+
+impl AlpineSource {
+    /// Validated source; `None` keeps the default.
+    pub fn new(mirror: Option<&str>, version: Option<&str>) -> Result<Self> {
+        let mirror = match mirror.map(str::trim).filter(|m| !m.is_empty()) {
+            Some(m) => parse_mirror(m)?,
+            None => DEFAULT_MIRROR.to_string(),
+        };
+        let version = version.map(str::trim).filter(|v| !v.is_empty());
+        if let Some(v) = version {
+            validate_version(v)?;
+        }
+        Ok(Self {
+            mirror,
+            version: version.filter(|v| *v != default_branch()).map(String::from),
+            fallbacks: Vec::new(),
+        })
+    }
+
+    /// With the validated fallback mirrors `mirrors`, in order.
+    pub fn with_fallbacks(mut self, mirrors: &[String]) -> Result<Self> {
+        self.fallbacks = mirrors
+            .iter()
+            .flat_map(|m| m.split_whitespace())
+            .map(parse_mirror)
+            .collect::<Result<_>>()?;
+        Ok(self)
+    }
+
+    /// Source from `ALPINE_MIRROR` / `ALPINE_VERSION` / `ALPINE_MIRRORS`.
+    pub fn from_env() -> Result<Self> {
+        let mirror = std::env::var(MIRROR_ENV).ok();
+        let version = std::env::var(VERSION_ENV).ok();
+        let mirrors: Vec<String> = std::env::var(MIRRORS_ENV).into_iter().collect();
+        Self::new(mirror.as_deref(), version.as_deref())?.with_fallbacks(&mirrors)
+    }
+
+    /// Source for this run: each CLI flag, else its environment variable.
+    pub fn resolve(
+        mirror: Option<&str>,
+        version: Option<&str>,
+        fallbacks: &[String],
+    ) -> Result<Self> {
+        let env_mirror = std::env::var(MIRROR_ENV).ok();
+        let env_version = std::env::var(VERSION_ENV).ok();
+        let env_mirrors: Vec<String> = std::env::var(MIRRORS_ENV).into_iter().collect();
+        Self::new(
+            mirror.or(env_mirror.as_deref()),
+            version.or(env_version.as_deref()),
+        )?
+        .with_fallbacks(if fallbacks.is_empty() {
+            &env_mirrors
+        } else {
+            fallbacks
+        })
+    }
+
+    /// Export for recipes and child builds (defaults are unset).
+    ///
+    /// Must be called before any threads are spawned.
+    pub fn export(&self) {
+        if self.mirror == DEFAULT_MIRROR {
+            std::env::remove_var(MIRROR_ENV);
+        } else {
+            std::env::set_var(MIRROR_ENV, &self.mirror);
+        }
+        match &self.version {
+            Some(v) => std::env::set_var(VERSION_ENV, v),
+            None => std::env::remove_var(VERSION_ENV),
+        }
+        if self.fallbacks.is_empty() {
+            std::env::remove_var(MIRRORS_ENV);
+        } else {
+            std::env::set_var(MIRRORS_ENV, self.fallbacks.join(" "));
+        }
+    }
+
+    /// Mirrors to download from, in order: the primary, the `--mirror`
+    /// entries, then the built-in fallbacks.
+    pub fn mirrors(&self) -> Vec<String> {
+        let mut mirrors = vec![self.mirror.clone()];
+        for mirror in self
+            .fallbacks
+            .iter()
+            .map(String::as_str)
+            .chain(FALLBACK_MIRRORS.iter().copied())
+        {
+            if !mirrors.iter().any(|m| m == mirror) {
+                mirrors.push(mirror.to_string());
+            }
+        }
+        mirrors
+    }
+
+    /// [`rewrite`](Self::rewrite) of `url` on every mirror, primary first.
+    pub fn mirror_urls(&self, url: &str) -> Vec<String> {
+        let primary = self.rewrite(url);
+        let Some(path) = primary.strip_prefix(&self.mirror) else {
+            return vec![primary];
+        };
+        self.mirrors()
+            .iter()
+            .map(|mirror| format!("{}{}", mirror, path))
+            .collect()
+    }
+
+    /// Repository branch (`3.23`).
+    pub fn branch(&self) -> String {
+        self.version.clone().unwrap_or_else(default_branch)
+    }
+
+    /// Extended ISO release (`3.23.2`, or `X.Y.0` for an override).
+    pub fn release(&self) -> String {
+        match &self.version {
+            Some(v) => format!("{}.0", v),
+            None => DEFAULT_RELEASE.to_string(),
+        }
+    }
+
+    /// Repository URL, as written to `/etc/apk/repositories`.
+    pub fn repo_url(&self, repo: &str) -> String {
+        format!("{}/v{}/{}", self.mirror, self.branch(), repo)
+    }
+
+    /// `url` (or ISO file name) written for the default source, rewritten
+    /// for this one. Only release downloads follow the branch override.
+    pub fn rewrite(&self, url: &str) -> String {
+        let mut url = url.replace(DEFAULT_MIRROR, &self.mirror);
+        if self.version.is_some() {
+            url = url
+                .replace(
+                    &format!("/v{}/releases/", default_branch()),
+                    &format!("/v{}/releases/", self.branch()),
+                )
+                .replace(
+                    &format!("-{}-", DEFAULT_RELEASE),
+                    &format!("-{}-", self.release()),
+                );
+        }
+        url
+    }
+
+    /// Input-hash component; empty for the default source.
+    pub fn fingerprint(&self) -> String {
+        let mut parts = Vec::new();
+        if self.mirror != DEFAULT_MIRROR {
+            parts.push(format!("mirror={}", self.mirror));
+        }
+        if let Some(v) = &self.version {
+            parts.push(format!("version={}", v));
+        }
+        parts.join(",")
+    }
+}
+
+impl fmt::Display for AlpineSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Alpine {} from {}", self.release(), self.mirror)
+    }
+}
+
+/// `mirror` without its trailing `/`, if it is an http(s) URL.
+fn parse_mirror(mirror: &str) -> Result<String> {
+    let mirror = mirror.trim();
+    if !mirror.starts_with("http://") && !mirror.starts_with("https://") {
+        bail!(
+            "Invalid Alpine mirror '{}': expected an http(s) URL of the directory \
+             holding v3.23/, e.g. {}",
+            mirror,
+            DEFAULT_MIRROR
+        );
+    }
+    Ok(mirror.trim_end_matches('/').to_string())
+}
+
+/// Branch of the pinned release (`3.23`).
+fn default_branch() -> String {
+    DEFAULT_RELEASE
+        .rsplit_once('.')
+        .map(|(branch, _)| branch.to_string())
+        .unwrap_or_default()
+}
+
+/// Check a `--alpine-version` is a stable branch (`X.Y`).
+pub fn validate_version(version: &str) -> Result<()> {
+    let valid = version.split_once('.').is_some_and(|(major, minor)| {
+        [major, minor]
+            .iter()
+            .all(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+    });
+    if !valid {
+        bail!(
+            "Invalid Alpine version '{}': expected a stable branch like {} \
+             (not a point release like {}; edge has no Extended ISO)",
+            version,
+            default_branch(),
+            DEFAULT_RELEASE
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::download::ALPINE_ISO_URL;
+
+    #[test]
+    fn test_source_rewrites_urls() {
+        let default = AlpineSource::new(None, Some("3.23")).unwrap();
+        assert_eq!(default, AlpineSource::default());
+        assert_eq!(default.fingerprint(), "");
+        assert_eq!(default.rewrite(ALPINE_ISO_URL), ALPINE_ISO_URL);
+        assert_eq!(
+            default.repo_url("main"),
+            "https://dl-cdn.alpinelinux.org/alpine/v3.23/main"
+        );
+
+        let source = AlpineSource::new(Some("http://mirror.corp/alpine/"), Some("3.22")).unwrap();
+        assert_eq!(source.mirror, "http://mirror.corp/alpine");
+        assert_eq!(
+            source.rewrite(ALPINE_ISO_URL),
+            "http://mirror.corp/alpine/v3.22/releases/x86_64/alpine-extended-3.22.0-x86_64.iso"
+        );
+        // apk-tools-static stays on the pinned branch
+        assert_eq!(
+            source.rewrite("https://dl-cdn.alpinelinux.org/alpine/v3.23/main/x86_64/apk.apk"),
+            "http://mirror.corp/alpine/v3.23/main/x86_64/apk.apk"
+        );
+        assert_eq!(
+            source.repo_url("community"),
+            "http://mirror.corp/alpine/v3.22/community"
+        );
+        assert_eq!(
+            source.fingerprint(),
+            "mirror=http://mirror.corp/alpine,version=3.22"
+        );
+
+        for bad in ["3.22.1", "edge", "v3.22", "3", "3.x"] {
+            let err = AlpineSource::new(None, Some(bad)).unwrap_err().to_string();
+            assert!(
+                err.contains("expected a stable branch like 3.23"),
+                "{}",
+                bad
+            );
+        }
+        assert!(AlpineSource::new(Some("mirror.corp"), None).is_err());
+    }
+
+    #[test]
+    fn test_mirror_order_and_urls() {
+        let default = AlpineSource::default();
+        assert_eq!(default.mirrors(), FALLBACK_MIRRORS);
+        assert_eq!(
+            default.mirror_urls(ALPINE_ISO_URL)[1],
+            "https://dl-2.alpinelinux.org/alpine/v3.23/releases/x86_64/alpine-extended-3.23.2-x86_64.iso"
+        );
+
+        let source = AlpineSource::new(Some("http://mirror.corp/alpine"), None)
+            .unwrap()
+            .with_fallbacks(&[
+                "https://near.example/alpine/ https://dl-2.alpinelinux.org/alpine".into(),
+            ])
+            .unwrap();
+        assert_eq!(
+            source.mirrors(),
+            [
+                "http://mirror.corp/alpine",
+                "https://near.example/alpine",
+                "https://dl-2.alpinelinux.org/alpine",
+                DEFAULT_MIRROR,
+            ]
+        );
+        assert_eq!(
+            source.mirror_urls("https://dl-cdn.alpinelinux.org/alpine/v3.23/main/x86_64/apk.apk")
+                [1],
+            "https://near.example/alpine/v3.23/main/x86_64/apk.apk"
+        );
+        // Fallbacks serve verified files; they do not change the rootfs
+        assert_eq!(source.fingerprint(), "mirror=http://mirror.corp/alpine");
+        // Other hosts have no mirrors
+        assert_eq!(
+            default.mirror_urls("https://busybox.net/busybox"),
+            ["https://busybox.net/busybox"]
+        );
+        assert!(default.with_fallbacks(&["ftp://mirror".into()]).is_err());
+    }
+
+    #[test]
+    fn test_defaults_match_recipe() {
+        let recipe = std::fs::read_to_string(
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("deps/alpine.rhai"),
+        )
+        .unwrap();
+        assert!(recipe.contains(&format!("version: \"{}\"", DEFAULT_RELEASE)));
+        assert!(recipe.contains(&format!("\"{}\"", DEFAULT_MIRROR)));
+        assert!(ALPINE_ISO_URL.starts_with(DEFAULT_MIRROR));
+    }
+}
@@ -0,0 +1,240 @@
+//! Parser for the APK installed database (`lib/apk/db/installed`).
+//!
+//! The database is a sequence of blank-line separated records, one per
+//! package, with single-letter `K:value` fields. Only the fields AcornOS
+//! reports on are kept.
+
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+/// Location of the installed database inside a rootfs.
+pub const INSTALLED_DB: &str = "lib/apk/db/installed";
+
+/// An installed package.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ApkPackage {
+    /// `P:` package name.
+    pub name: String,
+    /// `V:` version, e.g. `1.36.1-r29`.
+    pub version: String,
+    /// `A:` architecture (empty if absent).
+    pub arch: String,
+    /// `o:` origin (source) package (empty if absent).
+    pub origin: String,
+    /// `L:` license expression (empty if absent).
+    pub license: String,
+}
+
+/// Parse installed database content. Records without a name are skipped.
+///
+/// Packages are returned sorted by name.
+pub fn parse_installed(content: &str) -> Vec<ApkPackage> {
+    let mut packages = Vec::new();
+    let mut current: Option<ApkPackage> = None;
+
+    let mut flush = |pkg: &mut Option<ApkPackage>| {
+        if let Some(pkg) = pkg.take().filter(|p| !p.name.is_empty()) {
+            packages.push(pkg);
+        }
+    };
+
+    for line in content.lines() {
+        if line.trim().is_empty() {
+            flush(&mut current);
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let pkg = current.get_or_insert_with(|| ApkPackage {
+            name: String::new(),
+            version: String::new(),
+            arch: String::new(),
+            origin: String::new(),
+            license: String::new(),
+        });
+        match key {
+            "P" => pkg.name = value.to_string(),
+            "V" => pkg.version = value.to_string(),
+            "A" => pkg.arch = value.to_string(),
+            "o" => pkg.origin = value.to_string(),
+            "L" => pkg.license = value.to_string(),
+            _ => {}
+        }
+    }
+    flush(&mut current);
+
+    packages.sort();
+    packages
+}
+
+/// Files owned by each package, as rootfs-relative paths.
+///
+/// `R:` entries are relative to the preceding `F:` directory.
+pub fn parse_installed_files(content: &str) -> BTreeMap<String, Vec<String>> {
+    let mut files: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut name = String::new();
+    let mut dir = String::new();
+
+    for line in content.lines() {
+        if line.trim().is_empty() {
+            name.clear();
+            dir.clear();
+            continue;
+        }
+        match line.split_once(':') {
+            Some(("P", value)) => name = value.to_string(),
+            Some(("F", value)) => dir = value.to_string(),
+            Some(("R", value)) if !name.is_empty() => {
+                let path = if dir.is_empty() {
+                    value.to_string()
+                } else {
+                    format!("{}/{}", dir, value)
+                };
+                files.entry(name.clone()).or_default().push(path);
+            }
+            _ => {}
+        }
+    }
+    files
+}
+
+/// Read and parse the installed database of a rootfs.
+pub fn installed_packages(rootfs: &Path) -> Result<Vec<ApkPackage>> {
+    let db = rootfs.join(INSTALLED_DB);
+    let content = fs::read_to_string(&db)
+        .with_context(|| format!("Failed to read APK database {}", db.display()))?;
+    Ok(parse_installed(&content))
+}
+
+/// Read the installed database of a rootfs and list each package's files.
+pub fn installed_files(rootfs: &Path) -> Result<BTreeMap<String, Vec<String>>> {
+    let db = rootfs.join(INSTALLED_DB);
+    let content = fs::read_to_string(&db)
+        .with_context(|| format!("Failed to read APK database {}", db.display()))?;
+    Ok(parse_installed_files(&content))
+}
+
+/// Owning package of each file in the installed database.
+#[derive(Debug, Clone, Default)]
+pub struct FileOwners {
+    owners: HashMap<String, String>,
+}
+
+impl FileOwners {
+    /// Index the files of `lib/apk/db/installed` content by path.
+    pub fn parse(content: &str) -> Self {
+        let mut owners = HashMap::new();
+        for (package, files) in parse_installed_files(content) {
+            for file in files {
+                owners.insert(file, package.clone());
+            }
+        }
+        Self { owners }
+    }
+
+    /// Read and index the installed database of a rootfs.
+    pub fn load(rootfs: &Path) -> Result<Self> {
+        let db = rootfs.join(INSTALLED_DB);
+        let content = fs::read_to_string(&db)
+            .with_context(|| format!("Failed to read APK database {}", db.display()))?;
+        Ok(Self::parse(&content))
+    }
+
+    /// Package owning the rootfs-relative `path`. With merged /usr a file
+    /// found at `bin/x` may be listed as `usr/bin/x`, or the other way round.
+    pub fn owner(&self, path: &str) -> Option<&str> {
+        let other = match path.strip_prefix("usr/") {
+            Some(rest) => rest.to_string(),
+            None => format!("usr/{}", path),
+        };
+        self.owners
+            .get(path)
+            .or_else(|| self.owners.get(&other))
+            .map(String::as_str)
+    }
+}
+
+/// What keeps `apk` from working in a staged rootfs: the staged
+/// equivalent of `apk info` listing alpine-base on the booted system.
+pub fn staged_problems(staging: &Path) -> Vec<String> {
+    let mut problems = Vec::new();
+    for file in ["etc/apk/repositories", "etc/apk/world"] {
+        let content = fs::read_to_string(staging.join(file)).unwrap_or_default();
+        if content.trim().is_empty() {
+            problems.push(format!("{} missing or empty", file));
+        }
+    }
+    let keys = fs::read_dir(staging.join("etc/apk/keys"))
+        .map(|mut d| d.next().is_some())
+        .unwrap_or(false);
+    if !keys {
+        problems.push("etc/apk/keys has no keys".to_string());
+    }
+    match installed_packages(staging) {
+        Ok(packages) if packages.iter().any(|p| p.name == "alpine-base") => {}
+        Ok(_) => problems.push(format!("{} does not list alpine-base", INSTALLED_DB)),
+        Err(_) => problems.push(format!("{} missing", INSTALLED_DB)),
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_installed() {
+        let db = "C:Q1abc=\nP:musl\nV:1.2.5-r8\nA:x86_64\no:musl\nF:lib\nR:ld-musl-x86_64.so.1\n\n\
+                  C:Q1def=\nP:busybox\nV:1.37.0-r12\nA:x86_64\no:busybox\nL:GPL-2.0-only\n\n\
+                  C:Q1orphan=\nV:0\n";
+        let packages = parse_installed(db);
+        assert_eq!(packages.len(), 2);
+        assert_eq!(packages[0].name, "busybox");
+        assert_eq!(packages[0].version, "1.37.0-r12");
+        assert_eq!(packages[1].name, "musl");
+        assert_eq!(packages[1].arch, "x86_64");
+        assert_eq!(packages[1].origin, "musl");
+        assert_eq!(packages[0].license, "GPL-2.0-only");
+        assert!(parse_installed("").is_empty());
+
+        let files = parse_installed_files(db);
+        assert_eq!(files["musl"], ["lib/ld-musl-x86_64.so.1"]);
+        assert!(!files.contains_key("busybox"));
+    }
+
+    #[test]
+    fn test_file_owners() {
+        let owners = FileOwners::parse(
+            "P:busybox\nF:bin\nR:busybox\n\n\
+             P:zlib\nF:usr/lib\nR:libz.so.1\nR:libz.so.1.3.1\n\n",
+        );
+        assert_eq!(owners.owner("bin/busybox"), Some("busybox"));
+        assert_eq!(owners.owner("usr/bin/busybox"), Some("busybox"));
+        assert_eq!(owners.owner("lib/libz.so.1"), Some("zlib"));
+        assert_eq!(owners.owner("usr/lib/libz.so.1.3.1"), Some("zlib"));
+        assert_eq!(owners.owner("etc/hostname"), None);
+    }
+
+    #[test]
+    fn test_staged_problems() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        assert_eq!(staged_problems(root).len(), 4);
+
+        fs::create_dir_all(root.join("etc/apk/keys")).unwrap();
+        fs::create_dir_all(root.join("lib/apk/db")).unwrap();
+        fs::write(root.join("etc/apk/repositories"), "https://m/v3.23/main\n").unwrap();
+        fs::write(root.join("etc/apk/world"), "alpine-base\n").unwrap();
+        fs::write(root.join("etc/apk/keys/alpine.rsa.pub"), "key").unwrap();
+        fs::write(root.join(INSTALLED_DB), "P:musl\nV:1\n\n").unwrap();
+        assert_eq!(
+            staged_problems(root),
+            ["lib/apk/db/installed does not list alpine-base"]
+        );
+        fs::write(root.join(INSTALLED_DB), "P:alpine-base\nV:1\n\n").unwrap();
+        assert!(staged_problems(root).is_empty());
+    }
+}
@@ -0,0 +1,728 @@
+//! Supplementary package installation (Tiers 1-3) with apk.static.
+//!
+//! Replaces a single `apk add --allow-untrusted` of every package with:
+//!
+//! - tier lists read from `deps/packages.rhai` and validated up front
+//! - signature checking always: missing Alpine keys are installed from
+//!   distro-spec first, and nothing is ever installed `--allow-untrusted`
+//! - apk output streamed line by line and parsed, so a failure names the
+//!   package that broke instead of ending in a wall of output
+//! - retries for transient errors from the remote repos (a failing local
+//!   ISO repo is never retried; it will not get better)
+//! - one apk run per tier, recorded in `.packages-tiers`, so a Tier 2
+//!   failure keeps the Tier 1 work and a rerun resumes where it stopped
+//!
+//! On success the packages recipe's markers are written, so the recipe
+//! sees the packages as built and only runs its install-time checks.
+
+use anyhow::{bail, Context, Result};
+use std::ffi::OsString;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use distro_spec::acorn::packages::ALPINE_KEYS;
+
+use crate::download::RetryPolicy;
+use crate::package_overlay::{PackageOverlay, OVERLAY_STATE};
+use crate::privilege::RootWrapper;
+
+/// Recipe holding the tier lists and `PACKAGE_LIST_VERSION`.
+pub const PACKAGES_RECIPE: &str = "deps/packages.rhai";
+
+/// Completed tiers, one `<version>:<tier>` per line, under `downloads/`.
+pub const TIERS_STATE: &str = ".packages-tiers";
+
+/// Lines of apk output kept for errors that can't be attributed.
+const TAIL_LINES: usize = 20;
+
+/// A named group of packages installed in one apk run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageTier {
+    pub name: String,
+    pub packages: Vec<String>,
+}
+
+/// Parse `let TIER<n>_<NAME> = [ "pkg", ... ];` arrays from a rhai recipe.
+pub fn parse_tiers(recipe: &str) -> Result<Vec<PackageTier>> {
+    let mut tiers = Vec::new();
+    let mut current: Option<PackageTier> = None;
+
+    for line in recipe.lines() {
+        let code = line.split("//").next().unwrap_or("").trim();
+        if let Some(tier) = current.as_mut() {
+            for item in code.split(',') {
+                let item = item.trim().trim_end_matches("];").trim_end_matches(']');
+                let item = item.trim();
+                if !item.is_empty() {
+                    tier.packages.push(item.trim_matches('"').to_string());
+                }
+            }
+            if code.contains(']') {
+                tiers.extend(current.take());
+            }
+            continue;
+        }
+        if let Some(rest) = code.strip_prefix("let TIER") {
+            let Some((name, value)) = rest.split_once('=') else {
+                continue;
+            };
+            if !value.trim().starts_with('[') {
+                continue;
+            }
+            current = Some(PackageTier {
+                name: format!("TIER{}", name.trim()),
+                packages: Vec::new(),
+            });
+        }
+    }
+
+    if let Some(tier) = current {
+        bail!(
+            "Unterminated package list {} in {}",
+            tier.name,
+            PACKAGES_RECIPE
+        );
+    }
+    if tiers.is_empty() {
+        bail!("No TIER package lists found in {}", PACKAGES_RECIPE);
+    }
+    Ok(tiers)
+}
+
+/// `PACKAGE_LIST_VERSION` from the recipe.
+pub fn parse_list_version(recipe: &str) -> Option<String> {
+    recipe.lines().find_map(|line| {
+        let rest = line.trim().strip_prefix("let PACKAGE_LIST_VERSION")?;
+        let value = rest.split_once('=')?.1;
+        Some(
+            value
+                .trim()
+                .trim_end_matches(';')
+                .trim_matches('"')
+                .to_string(),
+        )
+    })
+}
+
+/// Reject invalid or duplicate package names before apk runs.
+pub fn validate_tiers(tiers: &[PackageTier]) -> Result<()> {
+    let mut seen: Vec<(&str, &str)> = Vec::new();
+    for tier in tiers {
+        if tier.packages.is_empty() {
+            bail!("Package tier {} is empty", tier.name);
+        }
+        for pkg in &tier.packages {
+            if !is_valid_package_name(pkg) {
+                bail!("Invalid package name '{}' in {}", pkg, tier.name);
+            }
+            if let Some((_, other)) = seen.iter().find(|(p, _)| p == pkg) {
+                bail!(
+                    "Package '{}' is listed in both {} and {}",
+                    pkg,
+                    other,
+                    tier.name
+                );
+            }
+            seen.push((pkg, &tier.name));
+        }
+    }
+    Ok(())
+}
+
+/// Whether `name` is a valid Alpine package name.
+pub fn is_valid_package_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "+-._".contains(c))
+}
+
+/// Category of an apk failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApkErrorKind {
+    /// Package not in any repository (or missing from the mirror).
+    MissingPackage,
+    /// Network or mirror hiccup on a remote repo; worth retrying.
+    Transient,
+    /// Untrusted or bad signature.
+    Signature,
+    /// Anything else (conflicts, local repo failures, disk errors).
+    Other,
+}
+
+/// One failure reported by apk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApkError {
+    pub kind: ApkErrorKind,
+    /// Package the error is about, if apk named one.
+    pub package: Option<String>,
+    /// Original output line.
+    pub line: String,
+}
+
+/// Strip `-<version>-r<rel>` from an apk `name-version-rN` string.
+fn package_name(nvr: &str) -> &str {
+    let mut parts = nvr.rsplitn(3, '-');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(rel), Some(ver), Some(name))
+            if rel.len() > 1
+                && rel.starts_with('r')
+                && rel[1..].chars().all(|c| c.is_ascii_digit())
+                && ver.starts_with(|c: char| c.is_ascii_digit()) =>
+        {
+            name
+        }
+        _ => nvr,
+    }
+}
+
+fn classify(message: &str, subject: &str) -> ApkErrorKind {
+    let msg = message.to_ascii_lowercase();
+    let remote = !subject.starts_with('/');
+    if msg.contains("signature") {
+        ApkErrorKind::Signature
+    } else if remote
+        && [
+            "temporary error",
+            "network error",
+            "timed out",
+            "connection",
+            "dns",
+        ]
+        .iter()
+        .any(|p| msg.contains(p))
+    {
+        ApkErrorKind::Transient
+    } else if msg.contains("no such package")
+        || msg.contains("not found")
+        || msg.contains("no such file")
+    {
+        ApkErrorKind::MissingPackage
+    } else {
+        ApkErrorKind::Other
+    }
+}
+
+/// Extract failures from apk output.
+///
+/// Repository warnings (`WARNING: fetching <url>: ...`) are included since
+/// they usually explain a following "no such package".
+pub fn parse_apk_output(lines: &[String]) -> Vec<ApkError> {
+    let mut errors = Vec::new();
+    let mut in_select = false;
+
+    for line in lines {
+        let trimmed = line.trim();
+        if let Some(rest) = trimmed
+            .strip_prefix("ERROR: ")
+            .or_else(|| trimmed.strip_prefix("WARNING: "))
+        {
+            in_select = rest.starts_with("unable to select packages");
+            if in_select {
+                continue;
+            }
+            let is_warning = trimmed.starts_with("WARNING");
+            let rest = rest
+                .strip_prefix("fetching ")
+                .or_else(|| rest.strip_prefix("Ignoring "))
+                .unwrap_or(rest);
+            // "<subject>: <message>"; URLs contain ':' so split after the scheme
+            let split_from = rest.find("://").map(|i| i + 3).unwrap_or(0);
+            let Some(pos) = rest[split_from..].find(": ").map(|p| p + split_from) else {
+                if !is_warning {
+                    errors.push(ApkError {
+                        kind: classify(rest, ""),
+                        package: None,
+                        line: line.clone(),
+                    });
+                }
+                continue;
+            };
+            let (subject, message) = (&rest[..pos], &rest[pos + 2..]);
+            let is_repo = subject.contains("://") || subject.starts_with('/');
+            let kind = classify(message, subject);
+            if is_warning && !is_repo {
+                continue;
+            }
+            errors.push(ApkError {
+                kind,
+                package: (!is_repo).then(|| package_name(subject).to_string()),
+                line: line.clone(),
+            });
+            continue;
+        }
+
+        // "  <pkg> (no such package):" inside an "unable to select" block
+        if in_select && line.starts_with("  ") && !line.starts_with("    ") {
+            let (pkg, reason) = trimmed.split_once(' ').unwrap_or((trimmed, ""));
+            errors.push(ApkError {
+                kind: classify(reason, pkg),
+                package: Some(pkg.to_string()),
+                line: line.clone(),
+            });
+        }
+    }
+    errors
+}
+
+/// Whether a failed run is worth retrying.
+fn is_retryable(errors: &[ApkError]) -> bool {
+    errors.iter().any(|e| e.kind == ApkErrorKind::Transient)
+        && !errors.iter().any(|e| e.kind == ApkErrorKind::Signature)
+}
+
+/// Runs apk, streaming each output line to `on_line`.
+pub trait ApkRunner {
+    /// Returns whether apk succeeded; errors only if it could not be started.
+    fn run(&self, args: &[OsString], on_line: &mut dyn FnMut(&str)) -> std::io::Result<bool>;
+}
+
+/// Runs the bootstrap apk.static on the host, under `wrapper` when the
+/// build is not root (apk chowns everything it installs).
+pub struct SystemApk {
+    pub apk_static: PathBuf,
+    pub wrapper: RootWrapper,
+}
+
+impl ApkRunner for SystemApk {
+    fn run(&self, args: &[OsString], on_line: &mut dyn FnMut(&str)) -> std::io::Result<bool> {
+        let mut child = self
+            .wrapper
+            .command(&self.apk_static, args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // apk writes progress to stdout and errors to stderr; interleave both
+        let (tx, rx) = mpsc::channel();
+        let readers: Vec<_> = [
+            child
+                .stdout
+                .take()
+                .map(|s| Box::new(s) as Box<dyn std::io::Read + Send>),
+            child
+                .stderr
+                .take()
+                .map(|s| Box::new(s) as Box<dyn std::io::Read + Send>),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|stream| {
+            let tx = tx.clone();
+            std::thread::spawn(move || {
+                for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                    let _ = tx.send(line);
+                }
+            })
+        })
+        .collect();
+        drop(tx);
+
+        for line in rx {
+            on_line(&line);
+        }
+        for reader in readers {
+            let _ = reader.join();
+        }
+        Ok(child.wait()?.success())
+    }
+}
+
+/// Whether Alpine signing keys are installed in the rootfs.
+pub fn keys_present(rootfs: &Path) -> bool {
+    fs::read_dir(rootfs.join("etc/apk/keys"))
+        .map(|entries| {
+            entries
+                .flatten()
+                .any(|e| e.file_name().to_string_lossy().ends_with(".pub"))
+        })
+        .unwrap_or(false)
+}
+
+/// Whether all `packages` are in the rootfs's `etc/apk/world`.
+fn in_world(rootfs: &Path, packages: &[String]) -> bool {
+    let world = fs::read_to_string(rootfs.join("etc/apk/world")).unwrap_or_default();
+    packages
+        .iter()
+        .all(|p| world.split_whitespace().any(|w| w == p))
+}
+
+/// Arguments for `apk add` of `packages` into `rootfs`.
+pub fn apk_add_args(rootfs: &Path, packages: &[String]) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec![
+        "--root".into(),
+        rootfs.into(),
+        "--no-progress".into(),
+        "add".into(),
+    ];
+    args.extend(packages.iter().map(OsString::from));
+    args
+}
+
+/// Arguments for `apk del` of `packages` from `rootfs`.
+pub fn apk_del_args(rootfs: &Path, packages: &[String]) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec![
+        "--root".into(),
+        rootfs.into(),
+        "--no-progress".into(),
+        "del".into(),
+    ];
+    args.extend(packages.iter().map(OsString::from));
+    args
+}
+
+/// Install one tier, retrying transient remote failures.
+pub fn install_tier(
+    runner: &dyn ApkRunner,
+    rootfs: &Path,
+    tier: &PackageTier,
+    policy: RetryPolicy,
+    sleep: &dyn Fn(Duration),
+) -> Result<()> {
+    let args = apk_add_args(rootfs, &tier.packages);
+    let mut attempt = 0;
+
+    loop {
+        let mut lines = Vec::new();
+        let ok = runner
+            .run(&args, &mut |line| {
+                log::info!("    {}", line);
+                lines.push(line.to_string());
+            })
+            .context("Failed to run apk.static")?;
+        if ok {
+            return Ok(());
+        }
+
+        let errors = parse_apk_output(&lines);
+        if is_retryable(&errors) && attempt < policy.retries {
+            attempt += 1;
+            let delay = policy.delay(attempt);
+            log::warn!(
+                "{}: transient repository error, retry {}/{} in {}s",
+                tier.name,
+                attempt,
+                policy.retries,
+                delay.as_secs()
+            );
+            sleep(delay);
+            continue;
+        }
+
+        bail!("{}", failure_message(tier, &errors, &lines));
+    }
+}
+
+/// Error text naming the packages that broke.
+fn failure_message(tier: &PackageTier, errors: &[ApkError], lines: &[String]) -> String {
+    let mut msg = format!("apk failed installing {}", tier.name);
+    let attributed: Vec<&ApkError> = errors.iter().filter(|e| e.package.is_some()).collect();
+
+    if attributed.is_empty() {
+        let start = lines.len().saturating_sub(TAIL_LINES);
+        msg.push_str(":\n");
+        for line in errors.iter().map(|e| &e.line).chain(&lines[start..]) {
+            msg.push_str(&format!("  {}\n", line));
+        }
+        return msg;
+    }
+
+    msg.push_str(". Failing package(s):\n");
+    for error in attributed {
+        let pkg = error.package.as_deref().unwrap_or_default();
+        let origin = if tier.packages.iter().any(|p| p == pkg) {
+            String::new()
+        } else {
+            format!(" (dependency pulled in by {})", tier.name)
+        };
+        msg.push_str(&format!(
+            "  {}{}: {:?}\n    {}\n",
+            pkg,
+            origin,
+            error.kind,
+            error.line.trim()
+        ));
+    }
+    msg
+}
+
+/// Install all tiers from `deps/packages.rhai` into `downloads/rootfs`.
+///
+/// `acorn-packages.toml`, if present, is applied to the tiers first (see
+/// [`crate::package_overlay`]).
+pub fn install_supplementary(base_dir: &Path) -> Result<()> {
+    let downloads = base_dir.join("downloads");
+    let rootfs = downloads.join("rootfs");
+    let apk_static = crate::download_check::apk_tools_dir(base_dir).join("sbin/apk.static");
+    let recipe_path = base_dir.join(PACKAGES_RECIPE);
+
+    let recipe = fs::read_to_string(&recipe_path)
+        .with_context(|| format!("Failed to read {}", recipe_path.display()))?;
+    let mut tiers = parse_tiers(&recipe)?;
+    validate_tiers(&tiers)?;
+    let version = parse_list_version(&recipe)
+        .with_context(|| format!("PACKAGE_LIST_VERSION missing in {}", PACKAGES_RECIPE))?;
+    let overlay = PackageOverlay::load(base_dir)?.unwrap_or_default();
+    overlay.apply(&mut tiers);
+
+    let version_file = downloads.join(".packages-version");
+    let manifest_file = downloads.join(".packages-manifest");
+    let overlay_file = downloads.join(OVERLAY_STATE);
+    if fs::read_to_string(&version_file)
+        .map(|v| v.trim() == version)
+        .unwrap_or(false)
+        && manifest_file.exists()
+        && fs::read_to_string(&overlay_file).unwrap_or_default().trim() == overlay.fingerprint()
+    {
+        log::info!("[SKIP] Packages already installed (version {})", version);
+        return Ok(());
+    }
+    if !apk_static.exists() {
+        bail!(
+            "apk.static not found at {}.\nRun 'acornos download alpine' first.",
+            apk_static.display()
+        );
+    }
+
+    if !keys_present(&rootfs) {
+        log::info!("Installing the Alpine signing keys into the rootfs");
+        distro_builder::alpine::keys::install_keys(&rootfs, ALPINE_KEYS)?;
+    }
+    if !keys_present(&rootfs) {
+        bail!(
+            "No Alpine signing keys in {}; refusing to install packages unverified",
+            rootfs.join("etc/apk/keys").display()
+        );
+    }
+
+    let state_file = downloads.join(TIERS_STATE);
+    let state = fs::read_to_string(&state_file).unwrap_or_default();
+    let wrapper = RootWrapper::detect();
+    match wrapper {
+        RootWrapper::Root => {}
+        RootWrapper::Unavailable => log::warn!(
+            "Not root and neither user namespaces nor fakeroot are available; \
+             apk may fail to set file ownership"
+        ),
+        _ => log::debug!("Running apk unprivileged via {:?}", wrapper),
+    }
+    let runner = SystemApk {
+        apk_static,
+        wrapper,
+    };
+
+    let world = fs::read_to_string(rootfs.join("etc/apk/world")).unwrap_or_default();
+    let removed: Vec<String> = overlay
+        .remove
+        .iter()
+        .filter(|p| world.split_whitespace().any(|w| w == *p))
+        .cloned()
+        .collect();
+    if !removed.is_empty() {
+        log::info!("Removing {} (package overlay)...", removed.join(", "));
+        let ok = runner
+            .run(&apk_del_args(&rootfs, &removed), &mut |line| {
+                log::info!("    {}", line)
+            })
+            .context("Failed to run apk.static")?;
+        if !ok {
+            bail!("apk del failed for {}", removed.join(", "));
+        }
+    }
+
+    for tier in &tiers {
+        let marker = format!("{}:{}", version, tier.name);
+        // The world check catches a rootfs recreated since the marker was written
+        if state.lines().any(|l| l == marker) && in_world(&rootfs, &tier.packages) {
+            log::info!("[SKIP] {} already installed", tier.name);
+            continue;
+        }
+        log::info!(
+            "Installing {} ({} packages)...",
+            tier.name,
+            tier.packages.len()
+        );
+        install_tier(
+            &runner,
+            &rootfs,
+            tier,
+            RetryPolicy::from_env(),
+            &std::thread::sleep,
+        )?;
+        let mut state = fs::read_to_string(&state_file).unwrap_or_default();
+        state.push_str(&marker);
+        state.push('\n');
+        fs::write(&state_file, state)?;
+    }
+
+    // Markers read by packages.rhai's is_built()/build()
+    let installed = crate::apk::installed_packages(&rootfs)?;
+    fs::write(&manifest_file, installed.len().to_string())?;
+    fs::write(&version_file, &version)?;
+    fs::write(&overlay_file, overlay.fingerprint())?;
+    log::info!("  {} packages installed in total", installed.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    fn lines(transcript: &str) -> Vec<String> {
+        transcript.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_parse_tiers_from_recipe() {
+        let recipe =
+            fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join(PACKAGES_RECIPE))
+                .unwrap();
+        let tiers = parse_tiers(&recipe).unwrap();
+        let names: Vec<&str> = tiers.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["TIER1_CORE", "TIER2_DAILY", "TIER3_LIVE"]);
+        assert_eq!(tiers[0].packages[0], "eudev");
+        assert!(tiers[2].packages.contains(&"squashfs-tools".to_string()));
+        validate_tiers(&tiers).unwrap();
+        assert!(parse_list_version(&recipe).is_some());
+
+        let bad = vec![PackageTier {
+            name: "TIER1".into(),
+            packages: vec!["vim; rm -rf /".into()],
+        }];
+        assert!(validate_tiers(&bad).is_err());
+        let dup = vec![
+            PackageTier {
+                name: "TIER1".into(),
+                packages: vec!["vim".into()],
+            },
+            PackageTier {
+                name: "TIER2".into(),
+                packages: vec!["vim".into()],
+            },
+        ];
+        assert!(validate_tiers(&dup).is_err());
+    }
+
+    #[test]
+    fn test_parse_missing_package_transcript() {
+        let transcript = "\
+fetch https://dl-cdn.alpinelinux.org/alpine/v3.23/main/x86_64/APKINDEX.tar.gz
+fetch https://dl-cdn.alpinelinux.org/alpine/v3.23/community/x86_64/APKINDEX.tar.gz
+ERROR: unable to select packages:
+  sof-firmwar (no such package):
+    required by: world[sof-firmwar]";
+        let errors = parse_apk_output(&lines(transcript));
+        assert_eq!(
+            errors,
+            vec![ApkError {
+                kind: ApkErrorKind::MissingPackage,
+                package: Some("sof-firmwar".into()),
+                line: "  sof-firmwar (no such package):".into(),
+            }]
+        );
+        assert!(!is_retryable(&errors));
+    }
+
+    #[test]
+    fn test_parse_mirror_lag_and_transient_transcripts() {
+        // ISO snapshot lags the live repo: index names a version the mirror dropped
+        let lag = "\
+(12/40) Installing linux-firmware-other (20251125-r0)
+ERROR: linux-firmware-other-20251125-r0: package mentioned in index not found (try 'apk update')
+1 error; 412 MiB in 80 packages";
+        let errors = parse_apk_output(&lines(lag));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].package.as_deref(), Some("linux-firmware-other"));
+        assert_eq!(errors[0].kind, ApkErrorKind::MissingPackage);
+
+        let flaky = "\
+WARNING: fetching https://dl-cdn.alpinelinux.org/alpine/v3.23/community: temporary error (try again later)
+ERROR: unable to select packages:
+  htop (no such package):
+    required by: world[htop]";
+        let errors = parse_apk_output(&lines(flaky));
+        assert_eq!(errors[0].kind, ApkErrorKind::Transient);
+        assert_eq!(errors[0].package, None);
+        assert_eq!(errors[1].package.as_deref(), Some("htop"));
+        assert!(is_retryable(&errors));
+
+        // The local ISO repo never counts as transient
+        let local =
+            "WARNING: Ignoring /build/downloads/iso-contents/apks: No such file or directory";
+        let errors = parse_apk_output(&lines(local));
+        assert_eq!(errors[0].kind, ApkErrorKind::MissingPackage);
+        assert!(!is_retryable(&errors));
+
+        let untrusted = "ERROR: curl-8.11.1-r0: UNTRUSTED signature";
+        let errors = parse_apk_output(&lines(untrusted));
+        assert_eq!(errors[0].kind, ApkErrorKind::Signature);
+        assert_eq!(errors[0].package.as_deref(), Some("curl"));
+    }
+
+    struct MockApk {
+        transcripts: RefCell<Vec<(&'static str, bool)>>,
+        calls: RefCell<Vec<Vec<OsString>>>,
+    }
+
+    impl ApkRunner for MockApk {
+        fn run(&self, args: &[OsString], on_line: &mut dyn FnMut(&str)) -> std::io::Result<bool> {
+            self.calls.borrow_mut().push(args.to_vec());
+            let (out, ok) = self.transcripts.borrow_mut().remove(0);
+            out.lines().for_each(&mut *on_line);
+            Ok(ok)
+        }
+    }
+
+    #[test]
+    fn test_install_tier_retries_and_attribution() {
+        let tier = PackageTier {
+            name: "TIER2_DAILY".into(),
+            packages: vec!["htop".into(), "vim".into()],
+        };
+        let policy = RetryPolicy {
+            retries: 2,
+            backoff: Duration::from_secs(1),
+        };
+        let transient =
+            "ERROR: vim-9.1.1105-r0: temporary error (try again later)\n1 error; 20 MiB in 10 packages";
+
+        let apk = MockApk {
+            transcripts: RefCell::new(vec![(transient, false), ("OK: 30 MiB", true)]),
+            calls: RefCell::new(Vec::new()),
+        };
+        install_tier(&apk, Path::new("/r"), &tier, policy, &|_| {}).unwrap();
+        assert_eq!(apk.calls.borrow().len(), 2);
+        assert!(!apk.calls.borrow()[0].contains(&"--allow-untrusted".into()));
+
+        // Missing package is not retried and is named in the error
+        let apk = MockApk {
+            transcripts: RefCell::new(vec![(
+                "ERROR: unable to select packages:\n  libfoo (no such package):\n    required by: htop-3.3.0-r0[so:libfoo.so.1]",
+                false,
+            )]),
+            calls: RefCell::new(Vec::new()),
+        };
+        let err = install_tier(&apk, Path::new("/r"), &tier, policy, &|_| {})
+            .unwrap_err()
+            .to_string();
+        assert_eq!(apk.calls.borrow().len(), 1);
+        assert!(err.contains("libfoo (dependency pulled in by TIER2_DAILY): MissingPackage"));
+
+        // Retries are bounded
+        let apk = MockApk {
+            transcripts: RefCell::new(vec![(transient, false); 3]),
+            calls: RefCell::new(Vec::new()),
+        };
+        let err = install_tier(&apk, Path::new("/r"), &tier, policy, &|_| {})
+            .unwrap_err()
+            .to_string();
+        assert_eq!(apk.calls.borrow().len(), 3);
+        assert!(err.contains("vim: Transient"));
+    }
+}
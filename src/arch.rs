@@ -0,0 +1,294 @@
+//! Target CPU architecture (`--arch`).
+//!
+//! x86_64 is the default and the only architecture with systemd-boot UKIs.
+//! aarch64 builds take the Alpine aarch64 ISO, boot through a standalone
+//! GRUB `BOOTAA64.EFI`, and run under `qemu-system-aarch64 -machine virt`
+//! with AAVMF/QEMU_EFI firmware.
+//!
+//! Every target is built with the host's pinned x86_64 apk.static
+//! (`--arch <target>`). The target's package scripts still run target
+//! binaries, so a foreign target needs qemu-user registered with
+//! binfmt_misc; [`Arch::check_build_host`] says so before apk fails with an
+//! exec format error.
+//!
+//! The selected architecture is exported as `ACORNOS_ARCH` so the recipes
+//! (`deps/alpine.rhai`) and parallel child builds see the same target.
+
+use anyhow::{bail, Result};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Environment variable carrying the target architecture.
+pub const ARCH_ENV: &str = "ACORNOS_ARCH";
+
+/// aarch64 UEFI firmware, in lookup order (Fedora, Debian/Ubuntu, Arch).
+const AARCH64_FIRMWARE: &[&str] = &[
+    "/usr/share/edk2/aarch64/QEMU_EFI.fd",
+    "/usr/share/AAVMF/AAVMF_CODE.fd",
+    "/usr/share/qemu-efi-aarch64/QEMU_EFI.fd",
+    "/usr/share/edk2/aarch64/QEMU_EFI.silent.fd",
+];
+
+/// Where the kernel lists binfmt_misc handlers.
+const BINFMT_MISC_DIR: &str = "/proc/sys/fs/binfmt_misc";
+
+/// ELF `e_machine` values.
+const EM_X86_64: u16 = 0x3e;
+const EM_AARCH64: u16 = 0xb7;
+
+/// Target architecture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Arch {
+    #[default]
+    X86_64,
+    Aarch64,
+}
+
+impl Arch {
+    pub const ALL: [Arch; 2] = [Arch::X86_64, Arch::Aarch64];
+
+    /// Architecture name, as used by Alpine (`apks/<name>`, ISO file names).
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::X86_64 => "x86_64",
+            Self::Aarch64 => "aarch64",
+        }
+    }
+
+    /// Package directory inside the Alpine ISO.
+    pub fn apks_dir(self) -> String {
+        format!("apks/{}", self.name())
+    }
+
+    /// Removable-media EFI loader path on the ISO.
+    pub fn efi_boot_path(self) -> &'static str {
+        match self {
+            Self::X86_64 => "/EFI/BOOT/BOOTX64.EFI",
+            Self::Aarch64 => "/EFI/BOOT/BOOTAA64.EFI",
+        }
+    }
+
+    /// `grub-mkstandalone --format` for the EFI loader.
+    pub fn grub_format(self) -> &'static str {
+        match self {
+            Self::X86_64 => "x86_64-efi",
+            Self::Aarch64 => "arm64-efi",
+        }
+    }
+
+    /// QEMU system emulator binary.
+    pub fn qemu_binary(self) -> &'static str {
+        match self {
+            Self::X86_64 => "qemu-system-x86_64",
+            Self::Aarch64 => "qemu-system-aarch64",
+        }
+    }
+
+    /// Serial console device the kernel logs to.
+    pub fn serial_console(self) -> &'static str {
+        match self {
+            Self::X86_64 => "ttyS0",
+            Self::Aarch64 => "ttyAMA0",
+        }
+    }
+
+    /// Whether this is the architecture of the build host.
+    pub fn is_host(self) -> bool {
+        std::env::consts::ARCH == self.name()
+    }
+
+    /// UEFI firmware for QEMU (OVMF on x86_64, AAVMF/QEMU_EFI on aarch64).
+    pub fn find_firmware(self) -> Option<PathBuf> {
+        match self {
+            Self::X86_64 => distro_builder::qemu::find_ovmf(),
+            Self::Aarch64 => AARCH64_FIRMWARE
+                .iter()
+                .map(PathBuf::from)
+                .find(|p| p.exists()),
+        }
+    }
+
+    /// Install hint for the UEFI firmware.
+    pub fn firmware_hint(self) -> &'static str {
+        match self {
+            Self::X86_64 => {
+                "Install OVMF:\n\
+                 - Fedora/RHEL: sudo dnf install edk2-ovmf\n\
+                 - Debian/Ubuntu: sudo apt install ovmf\n\
+                 - Arch: sudo pacman -S edk2-ovmf"
+            }
+            Self::Aarch64 => {
+                "Install AAVMF:\n\
+                 - Fedora/RHEL: sudo dnf install edk2-aarch64\n\
+                 - Debian/Ubuntu: sudo apt install qemu-efi-aarch64\n\
+                 - Arch: sudo pacman -S edk2-aarch64"
+            }
+        }
+    }
+
+    /// Install hint for the QEMU emulator.
+    pub fn qemu_hint(self) -> &'static str {
+        match self {
+            Self::X86_64 => "sudo dnf install qemu-system-x86",
+            Self::Aarch64 => "sudo dnf install qemu-system-aarch64",
+        }
+    }
+
+    /// Install hint for qemu-user with binfmt_misc registration.
+    pub fn binfmt_hint(self) -> &'static str {
+        "Install qemu-user with binfmt_misc registration:\n\
+         - Fedora/RHEL: sudo dnf install qemu-user-static\n\
+         - Debian/Ubuntu: sudo apt install qemu-user-static binfmt-support\n\
+         - Arch: sudo pacman -S qemu-user-static qemu-user-static-binfmt"
+    }
+
+    /// Whether `binfmt_dir` has an enabled handler for this architecture
+    /// (`qemu-aarch64`, or however the distro named it).
+    pub fn binfmt_registered(self, binfmt_dir: &Path) -> bool {
+        let Ok(entries) = std::fs::read_dir(binfmt_dir) else {
+            return false;
+        };
+        entries.flatten().any(|entry| {
+            entry.file_name().to_string_lossy().contains(self.name())
+                && std::fs::read_to_string(entry.path())
+                    .is_ok_and(|text| text.starts_with("enabled"))
+        })
+    }
+
+    /// Whether this host can build the rootfs for this architecture: the
+    /// pinned apk.static is x86_64, and a foreign target's package scripts
+    /// need qemu-user through binfmt_misc.
+    pub fn check_build_host(self) -> Result<()> {
+        if !Self::X86_64.is_host() {
+            bail!(
+                "apk-tools-static is pinned for x86_64 build hosts only (this host is {})",
+                std::env::consts::ARCH
+            );
+        }
+        if !self.is_host() && !self.binfmt_registered(Path::new(BINFMT_MISC_DIR)) {
+            bail!(
+                "Building the {} rootfs runs its package scripts through qemu-user, \
+                 but no {} handler is registered in {}.\n{}",
+                self,
+                self,
+                BINFMT_MISC_DIR,
+                self.binfmt_hint()
+            );
+        }
+        Ok(())
+    }
+
+    /// Architecture from `ACORNOS_ARCH` (x86_64 if unset).
+    pub fn from_env() -> Result<Self> {
+        match std::env::var(ARCH_ENV) {
+            Ok(v) if !v.trim().is_empty() => v.parse(),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Architecture for this run: the CLI flag, else `ACORNOS_ARCH`.
+    pub fn resolve(flag: Option<Arch>) -> Result<Self> {
+        match flag {
+            Some(arch) => Ok(arch),
+            None => Self::from_env(),
+        }
+    }
+
+    /// Export as `ACORNOS_ARCH` for recipes and child builds.
+    ///
+    /// Must be called before any threads are spawned.
+    pub fn export(self) {
+        std::env::set_var(ARCH_ENV, self.name());
+    }
+
+    /// Architecture of an ELF binary, if it is one of ours.
+    pub fn of_elf(path: &Path) -> Option<Self> {
+        let mut header = [0u8; 20];
+        File::open(path).ok()?.read_exact(&mut header).ok()?;
+        if &header[..4] != b"\x7fELF" {
+            return None;
+        }
+        match u16::from_le_bytes([header[18], header[19]]) {
+            EM_X86_64 => Some(Self::X86_64),
+            EM_AARCH64 => Some(Self::Aarch64),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Arch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Arch {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "x86_64" | "amd64" => Ok(Self::X86_64),
+            "aarch64" | "arm64" => Ok(Self::Aarch64),
+            other => bail!(
+                "Unknown architecture '{}' (expected x86_64 or aarch64)",
+                other
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_arch_parse_and_paths() {
+        for arch in Arch::ALL {
+            assert_eq!(arch.name().parse::<Arch>().unwrap(), arch);
+        }
+        assert_eq!("arm64".parse::<Arch>().unwrap(), Arch::Aarch64);
+        assert!("riscv64".parse::<Arch>().is_err());
+        assert_eq!(Arch::resolve(Some(Arch::Aarch64)).unwrap(), Arch::Aarch64);
+
+        assert_eq!(Arch::Aarch64.apks_dir(), "apks/aarch64");
+        assert_eq!(Arch::Aarch64.efi_boot_path(), "/EFI/BOOT/BOOTAA64.EFI");
+        assert_eq!(Arch::Aarch64.grub_format(), "arm64-efi");
+        assert_eq!(Arch::X86_64.qemu_binary(), "qemu-system-x86_64");
+    }
+
+    #[test]
+    fn test_of_elf() {
+        let dir = tempfile::tempdir().unwrap();
+        let elf = |machine: u16| {
+            let mut header = vec![0u8; 64];
+            header[..4].copy_from_slice(b"\x7fELF");
+            header[18..20].copy_from_slice(&machine.to_le_bytes());
+            header
+        };
+        fs::write(dir.path().join("arm"), elf(EM_AARCH64)).unwrap();
+        fs::write(dir.path().join("x86"), elf(EM_X86_64)).unwrap();
+        fs::write(dir.path().join("script"), "#!/bin/sh\nexit 0\n").unwrap();
+
+        assert_eq!(Arch::of_elf(&dir.path().join("arm")), Some(Arch::Aarch64));
+        assert_eq!(Arch::of_elf(&dir.path().join("x86")), Some(Arch::X86_64));
+        assert_eq!(Arch::of_elf(&dir.path().join("script")), None);
+        assert_eq!(Arch::of_elf(&dir.path().join("missing")), None);
+    }
+
+    #[test]
+    fn test_binfmt_registered() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(!Arch::Aarch64.binfmt_registered(dir.path()));
+        fs::write(dir.path().join("status"), "enabled\n").unwrap();
+        fs::write(dir.path().join("qemu-aarch64"), "disabled\ninterpreter x\n").unwrap();
+        assert!(!Arch::Aarch64.binfmt_registered(dir.path()));
+        fs::write(dir.path().join("qemu-aarch64"), "enabled\ninterpreter x\n").unwrap();
+        assert!(Arch::Aarch64.binfmt_registered(dir.path()));
+        assert!(!Arch::X86_64.binfmt_registered(dir.path()));
+        assert!(!Arch::Aarch64.binfmt_registered(&dir.path().join("missing")));
+    }
+}
@@ -0,0 +1,140 @@
+//! Kernel cmdline edits for the live boot entries.
+//!
+//! `acornos iso --cmdline-append/--cmdline-remove` (and
+//! `ACORNOS_CMDLINE_APPEND`) change the options of every live entry, so the
+//! systemd-boot UKIs and the GRUB menus (non-x86_64, `--bios`) boot alike.
+//! The installed-system UKIs are left alone: debugging options for one
+//! live boot should not follow into every install.
+
+use anyhow::{bail, Result};
+
+/// Options appended to every live entry, before those from the CLI.
+pub const CMDLINE_APPEND_ENV: &str = "ACORNOS_CMDLINE_APPEND";
+
+/// Characters the GRUB `linux` line (unquoted) would misread.
+const UNSAFE_CHARS: &[char] = &['\'', '"', '\\', '{', '}', ';', '$', '#', '`'];
+
+/// Options to add to and drop from the live entries' cmdlines.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CmdlineEdit {
+    /// Appended in order, after the entry's own options.
+    pub append: Vec<String>,
+    /// Dropped if present: `quiet` drops `quiet`, `console` every `console=`.
+    pub remove: Vec<String>,
+}
+
+impl CmdlineEdit {
+    /// From whitespace-separated option lists, rejecting options the boot
+    /// menus cannot hold.
+    pub fn new(append: &[String], remove: &[String]) -> Result<Self> {
+        let split = |lists: &[String]| -> Result<Vec<String>> {
+            let mut options = Vec::new();
+            for option in lists.iter().flat_map(|l| l.split_whitespace()) {
+                validate_option(option)?;
+                options.push(option.to_string());
+            }
+            Ok(options)
+        };
+        Ok(Self {
+            append: split(append)?,
+            remove: split(remove)?,
+        })
+    }
+
+    /// Like [`new`](Self::new), with `ACORNOS_CMDLINE_APPEND` appended first.
+    pub fn with_env(append: &[String], remove: &[String]) -> Result<Self> {
+        let mut lists: Vec<String> = std::env::var(CMDLINE_APPEND_ENV).into_iter().collect();
+        lists.extend(append.iter().cloned());
+        Self::new(&lists, remove)
+    }
+
+    /// Whether the edit changes nothing.
+    pub fn is_empty(&self) -> bool {
+        self.append.is_empty() && self.remove.is_empty()
+    }
+
+    /// `cmdline` with the removals dropped and the additions appended.
+    pub fn apply(&self, cmdline: &str) -> String {
+        let mut options: Vec<&str> = cmdline
+            .split_whitespace()
+            .filter(|opt| !self.removes(opt))
+            .collect();
+        options.extend(self.append.iter().map(String::as_str));
+        options.join(" ")
+    }
+
+    fn removes(&self, option: &str) -> bool {
+        let key = option.split_once('=').map_or(option, |(key, _)| key);
+        self.remove
+            .iter()
+            .any(|r| r == option || (!r.contains('=') && r == key))
+    }
+
+    /// Stable description for rebuild hashes; empty when the edit is empty.
+    pub fn fingerprint(&self) -> String {
+        if self.is_empty() {
+            return String::new();
+        }
+        // ';' cannot occur in an option
+        format!(
+            "append={};remove={}",
+            self.append.join(" "),
+            self.remove.join(" ")
+        )
+    }
+}
+
+fn validate_option(option: &str) -> Result<()> {
+    if let Some(c) = option
+        .chars()
+        .find(|c| c.is_control() || UNSAFE_CHARS.contains(c))
+    {
+        bail!(
+            "Kernel cmdline option '{}' contains {:?}, which the boot menus cannot hold",
+            option,
+            c
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_cmdline_edit() {
+        let edit = CmdlineEdit::new(
+            &strings(&["loglevel=7", "acpi=off  rd.shell"]),
+            &strings(&["quiet", "console"]),
+        )
+        .unwrap();
+        assert_eq!(
+            edit.append,
+            strings(&["loglevel=7", "acpi=off", "rd.shell"])
+        );
+        assert_eq!(
+            edit.apply("console=tty0 console=ttyS0,115200 quiet rw"),
+            "rw loglevel=7 acpi=off rd.shell"
+        );
+        assert_eq!(
+            edit.fingerprint(),
+            "append=loglevel=7 acpi=off rd.shell;remove=quiet console"
+        );
+
+        // A key=value removal only drops that exact option
+        let edit = CmdlineEdit::new(&[], &strings(&["console=tty0"])).unwrap();
+        assert_eq!(edit.apply("console=tty0 console=ttyS0"), "console=ttyS0");
+
+        assert!(CmdlineEdit::default().is_empty());
+        assert_eq!(CmdlineEdit::default().fingerprint(), "");
+        assert_eq!(CmdlineEdit::default().apply(" quiet  rw "), "quiet rw");
+
+        assert!(CmdlineEdit::new(&strings(&["init=/bin/sh;reboot"]), &[]).is_err());
+        assert!(CmdlineEdit::new(&strings(&["x='y'"]), &[]).is_err());
+    }
+}
@@ -0,0 +1,738 @@
+//! Full initramfs for the installed system.
+//!
+//! The tiny initramfs only knows how to find the ISO and mount the live
+//! rootfs image. Installed systems boot from a disk partition instead, so
+//! this flavor extends the tiny build with:
+//!
+//! - ext4/vfat modules (skipped when the kernel has them built in), plus
+//!   whatever they depend on according to `modules.dep`
+//! - `e2fsck` from the Alpine rootfs, with its shared libraries
+//! - optionally `cryptsetup` + dm-crypt for LUKS root
+//! - `init_installed.template`, which resolves `root=` (LABEL/UUID/PARTUUID/
+//!   device), fscks it, mounts it rw and switch_roots into it
+//!
+//! The base archive comes from recinit; the extra binaries and modules are
+//! appended as a second gzip'd cpio (the kernel unpacks concatenated archives
+//! in order). The result is `initramfs-installed.cpio.gz`, shipped on the ISO
+//! under `/install/` for recstrap to place.
+
+use anyhow::{bail, Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use super::init_template::{recinit_vars, validate_init_template};
+use crate::paths::OutputPaths;
+use distro_builder::alpine::extract::ExtractPaths;
+use distro_spec::acorn::{BOOT_DEVICE_PROBE_ORDER, CPIO_GZIP_LEVEL, ISO_LABEL};
+use recinit::{download_and_cache_busybox, find_kernel_modules_dir, ModulePreset, TinyConfig};
+
+/// Output filename of the installed-system initramfs.
+pub const INITRAMFS_INSTALLED_OUTPUT: &str = "initramfs-installed.cpio.gz";
+
+/// Where the installed initramfs lands on the ISO.
+pub const INITRAMFS_INSTALLED_ISO_PATH: &str = "install/initramfs-installed.cpio.gz";
+
+/// Init template for the installed boot path.
+pub const INSTALLED_TEMPLATE: &str = "profile/init_installed.template";
+
+/// Filesystem modules for the root and ESP, dependencies first.
+pub const FS_MODULES: &[&str] = &[
+    "crc16",
+    "mbcache",
+    "jbd2",
+    "ext4",
+    "fat",
+    "vfat",
+    "nls_cp437",
+    "nls_iso8859-1",
+];
+
+/// Device-mapper modules needed to unlock a LUKS root.
+pub const LUKS_MODULES: &[&str] = &["dm-mod", "dm-crypt"];
+
+/// Binaries copied from the Alpine rootfs into /sbin.
+const FSCK_BINARIES: &[&str] = &["e2fsck"];
+const LUKS_BINARIES: &[&str] = &["cryptsetup"];
+
+/// Directories searched for binaries in the Alpine rootfs.
+const BIN_DIRS: &[&str] = &["sbin", "usr/sbin", "bin", "usr/bin"];
+
+/// Directories searched for shared libraries in the Alpine rootfs.
+const LIB_DIRS: &[&str] = &["lib", "usr/lib"];
+
+/// Build the installed-system initramfs.
+pub fn build_full_initramfs(base_dir: &Path, luks: bool) -> Result<()> {
+    let output = OutputPaths::for_base(base_dir);
+    let output_dir = output.dir.clone();
+    let rootfs = ExtractPaths::new(base_dir).rootfs;
+
+    log::info!(
+        "=== Building Installed Initramfs{} ===\n",
+        if luks { " (LUKS)" } else { "" }
+    );
+
+    if !rootfs.join("bin").exists() {
+        bail!(
+            "Rootfs not found at {}.\nRun 'acornos extract' first.",
+            rootfs.display()
+        );
+    }
+
+    let downloads_dir = base_dir.join("downloads");
+    crate::download::ensure_busybox(base_dir, crate::arch::Arch::from_env()?)?;
+    let busybox_path = download_and_cache_busybox(&downloads_dir)?;
+
+    let modules_dir = find_kernel_modules_dir(&output.kernel_modules())?;
+
+    // The init loads exactly these, so dependencies go in the list too
+    let closure = resolve_modules(&modules_dir, &installed_modules(luks))?;
+    let modules = closure.names.clone();
+    let output_path = output.installed_initramfs();
+    let base_tmp = output_dir.join(format!("{}.base.tmp", INITRAMFS_INSTALLED_OUTPUT));
+
+    let luks_flag = if luks { "1" } else { "0" }.to_string();
+    let mut vars = recinit_vars(ISO_LABEL, "", BOOT_DEVICE_PROBE_ORDER, "");
+    vars.push(("INSTALLED_MODULES", modules.join(" ")));
+    vars.push(("LUKS", luks_flag.clone()));
+    validate_init_template(
+        base_dir,
+        INSTALLED_TEMPLATE,
+        &vars,
+        &["BOOT_MODULES", "INSTALLED_MODULES", "LUKS"],
+        Some(&busybox_path),
+        &output_dir.join("init_installed.check"),
+    )?;
+
+    let config = TinyConfig {
+        modules_dir: modules_dir.clone(),
+        busybox_path,
+        template_path: base_dir.join(INSTALLED_TEMPLATE),
+        output: base_tmp.clone(),
+        iso_label: ISO_LABEL.to_string(),
+        rootfs_path: String::new(),
+        live_overlay_image_path: None,
+        live_overlay_path: None,
+        boot_devices: BOOT_DEVICE_PROBE_ORDER
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+        module_preset: ModulePreset::Live,
+        gzip_level: CPIO_GZIP_LEVEL,
+        check_builtin: true,
+        extra_template_vars: vec![
+            ("INSTALLED_MODULES".to_string(), modules.join(" ")),
+            ("LUKS".to_string(), luks_flag),
+        ],
+    };
+    recinit::build_tiny_initramfs(&config, true)?;
+
+    // Second archive: binaries, their libraries, and the extra modules
+    let mut binaries: Vec<&str> = FSCK_BINARIES.to_vec();
+    if luks {
+        binaries.extend_from_slice(LUKS_BINARIES);
+    }
+    let mut entries = binary_entries(&rootfs, &binaries)?;
+    entries.push(CpioEntry::symlink("sbin/fsck.ext4", "e2fsck"));
+    entries.extend(closure_entries(&modules_dir, &closure)?);
+
+    append_cpio(&base_tmp, &entries, &output_path)?;
+
+    log::info!("\n  Output: {}", output_path.display());
+    Ok(())
+}
+
+/// Append `entries` as a second gzip'd cpio to the recinit archive at
+/// `base`, writing the result atomically to `output`. `base` is removed.
+pub(crate) fn append_cpio(base: &Path, entries: &[CpioEntry], output: &Path) -> Result<()> {
+    let extra_tmp = output.with_extension("extra.tmp");
+    let mut cpio = Vec::new();
+    write_newc(&mut cpio, entries)?;
+    gzip_to(&cpio, &extra_tmp)?;
+
+    // Concatenate base + extra, then atomically move into place
+    let out_tmp = output.with_extension("tmp");
+    let mut combined = fs::read(base)?;
+    combined.extend(fs::read(&extra_tmp)?);
+    fs::write(&out_tmp, combined)?;
+    let _ = fs::remove_file(base);
+    let _ = fs::remove_file(&extra_tmp);
+    fs::rename(&out_tmp, output)?;
+    Ok(())
+}
+
+/// `binaries` from the Alpine rootfs as /sbin entries, with their shared
+/// libraries.
+pub(crate) fn binary_entries(rootfs: &Path, binaries: &[&str]) -> Result<Vec<CpioEntry>> {
+    let mut entries = Vec::new();
+    for name in binaries {
+        let path = find_binary(rootfs, name)
+            .with_context(|| format!("{} not found in {}", name, rootfs.display()))?;
+        entries.push(CpioEntry::file(format!("sbin/{}", name), &path)?);
+        log::info!("  Added /sbin/{}", name);
+    }
+    for lib in resolve_libraries(rootfs, binaries)? {
+        let rel = lib.strip_prefix(rootfs)?.to_string_lossy().into_owned();
+        entries.push(CpioEntry::file(rel, &lib)?);
+    }
+    Ok(entries)
+}
+
+/// `modules` and their dependencies from `modules_dir` under
+/// /lib/modules/<kver>/ (see [`resolve_modules`]).
+pub(crate) fn module_entries(modules_dir: &Path, modules: &[&str]) -> Result<Vec<CpioEntry>> {
+    closure_entries(modules_dir, &resolve_modules(modules_dir, modules)?)
+}
+
+/// Kernel modules to load, dependencies first.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct ModuleClosure {
+    /// Module names in load order.
+    pub names: Vec<String>,
+    /// Module files, relative to the modules dir, in the same order.
+    pub files: Vec<String>,
+    /// How many of them were not asked for, only needed by those that were.
+    pub pulled_in: usize,
+}
+
+/// `modules` and everything they need according to `modules.dep`, in load
+/// order. Built-in modules are skipped; a module that is neither built in
+/// nor present, or a dependency missing from `modules_dir`, is an error.
+pub(crate) fn resolve_modules(modules_dir: &Path, modules: &[&str]) -> Result<ModuleClosure> {
+    let deps = match fs::read_to_string(modules_dir.join("modules.dep")) {
+        Ok(content) => parse_modules_dep(&content),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            log::warn!(
+                "No modules.dep in {}; module dependencies are not resolved",
+                modules_dir.display()
+            );
+            BTreeMap::new()
+        }
+        Err(e) => {
+            return Err(e).with_context(|| {
+                format!("Failed to read modules.dep in {}", modules_dir.display())
+            })
+        }
+    };
+
+    let mut closure = ModuleClosure::default();
+    let mut seen = BTreeSet::new();
+    for module in modules {
+        let listed = deps
+            .keys()
+            .find(|file| module_stem_matches(file.rsplit('/').next().unwrap_or(file), module));
+        let file = match listed {
+            Some(file) => file.clone(),
+            None => match find_module(modules_dir, module)? {
+                Some(path) => path
+                    .strip_prefix(modules_dir)?
+                    .to_string_lossy()
+                    .into_owned(),
+                None if is_builtin(modules_dir, module) => {
+                    log::info!("  {} is built into the kernel", module);
+                    continue;
+                }
+                None => bail!(
+                    "Kernel module '{}' is neither built-in nor present in {}",
+                    module,
+                    modules_dir.display()
+                ),
+            },
+        };
+        add_module(modules_dir, &deps, &file, module, &mut seen, &mut closure)?;
+    }
+
+    closure.pulled_in = closure
+        .names
+        .iter()
+        .filter(|name| {
+            !modules
+                .iter()
+                .any(|m| m.replace('-', "_") == name.replace('-', "_"))
+        })
+        .count();
+    if closure.pulled_in > 0 {
+        log::info!("  {} dependency module(s) pulled in", closure.pulled_in);
+    }
+    Ok(closure)
+}
+
+/// Add `file` to `closure` after the modules it depends on.
+fn add_module(
+    modules_dir: &Path,
+    deps: &BTreeMap<String, Vec<String>>,
+    file: &str,
+    needed_by: &str,
+    seen: &mut BTreeSet<String>,
+    closure: &mut ModuleClosure,
+) -> Result<()> {
+    if !seen.insert(file.to_string()) {
+        return Ok(());
+    }
+    if !modules_dir.join(file).is_file() {
+        bail!(
+            "Kernel module {} (needed by '{}') is in modules.dep but missing from {}",
+            file,
+            needed_by,
+            modules_dir.display()
+        );
+    }
+    for dep in deps.get(file).into_iter().flatten() {
+        add_module(modules_dir, deps, dep, needed_by, seen, closure)?;
+    }
+    closure.names.push(module_name(file));
+    closure.files.push(file.to_string());
+    Ok(())
+}
+
+/// `closure` as entries under /lib/modules/<kver>/, keeping the layout.
+pub(crate) fn closure_entries(
+    modules_dir: &Path,
+    closure: &ModuleClosure,
+) -> Result<Vec<CpioEntry>> {
+    let kver = modules_dir
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .context("Kernel modules dir has no version component")?;
+    closure
+        .files
+        .iter()
+        .map(|file| {
+            CpioEntry::file(
+                format!("lib/modules/{}/{}", kver, file),
+                &modules_dir.join(file),
+            )
+        })
+        .collect()
+}
+
+/// `modules.dep`: each module file with the files it depends on, all
+/// relative to the modules dir.
+fn parse_modules_dep(content: &str) -> BTreeMap<String, Vec<String>> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (file, deps) = line.split_once(':')?;
+            Some((
+                file.trim().to_string(),
+                deps.split_whitespace().map(str::to_string).collect(),
+            ))
+        })
+        .collect()
+}
+
+/// Module name of a module file (`kernel/fs/ext4/ext4.ko.gz` -> `ext4`).
+fn module_name(file: &str) -> String {
+    let base = file.rsplit('/').next().unwrap_or(file);
+    base.split(".ko").next().unwrap_or(base).to_string()
+}
+
+/// Modules loaded by the installed init, in load order.
+pub fn installed_modules(luks: bool) -> Vec<&'static str> {
+    let mut modules = FS_MODULES.to_vec();
+    if luks {
+        modules.extend_from_slice(LUKS_MODULES);
+    }
+    modules
+}
+
+/// Find a binary in the Alpine rootfs.
+fn find_binary(rootfs: &Path, name: &str) -> Option<PathBuf> {
+    BIN_DIRS
+        .iter()
+        .map(|dir| rootfs.join(dir).join(name))
+        .find(|p| p.is_file())
+}
+
+/// Shared libraries (including the dynamic loader) needed by `binaries`.
+fn resolve_libraries(rootfs: &Path, binaries: &[&str]) -> Result<BTreeSet<PathBuf>> {
+    let mut pending: Vec<PathBuf> = binaries
+        .iter()
+        .filter_map(|b| find_binary(rootfs, b))
+        .collect();
+    let mut seen = BTreeSet::new();
+
+    while let Some(path) = pending.pop() {
+        let data = fs::read(&path)?;
+        let deps = elf_dependencies(&data)
+            .with_context(|| format!("Failed to parse ELF {}", path.display()))?;
+        for dep in deps {
+            let name = Path::new(&dep)
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or(dep.clone());
+            let lib = LIB_DIRS
+                .iter()
+                .map(|dir| rootfs.join(dir).join(&name))
+                .find(|p| p.exists())
+                .with_context(|| format!("Library {} not found in {}", dep, rootfs.display()))?;
+            if seen.insert(lib.clone()) {
+                pending.push(lib);
+            }
+        }
+    }
+    Ok(seen)
+}
+
+/// PT_INTERP and DT_NEEDED entries of a little-endian ELF64 file.
+///
+/// Static binaries have neither and return an empty list.
+pub fn elf_dependencies(data: &[u8]) -> Result<Vec<String>> {
+    const PT_LOAD: u32 = 1;
+    const PT_DYNAMIC: u32 = 2;
+    const PT_INTERP: u32 = 3;
+    const DT_NEEDED: u64 = 1;
+    const DT_STRTAB: u64 = 5;
+
+    if data.len() < 64 || &data[..4] != b"\x7fELF" {
+        bail!("not an ELF file");
+    }
+    if data[4] != 2 || data[5] != 1 {
+        bail!("only little-endian ELF64 is supported");
+    }
+
+    let u16_at = |off: usize| -> Result<u16> {
+        let b = data.get(off..off + 2).context("truncated ELF")?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    };
+    let u32_at = |off: usize| -> Result<u32> {
+        let b = data.get(off..off + 4).context("truncated ELF")?;
+        Ok(u32::from_le_bytes(b.try_into()?))
+    };
+    let u64_at = |off: usize| -> Result<u64> {
+        let b = data.get(off..off + 8).context("truncated ELF")?;
+        Ok(u64::from_le_bytes(b.try_into()?))
+    };
+    let cstr_at = |off: usize| -> Result<String> {
+        let tail = data.get(off..).context("truncated ELF")?;
+        let end = tail
+            .iter()
+            .position(|&b| b == 0)
+            .context("unterminated string")?;
+        Ok(String::from_utf8_lossy(&tail[..end]).into_owned())
+    };
+
+    let phoff = u64_at(0x20)? as usize;
+    let phentsize = u16_at(0x36)? as usize;
+    let phnum = u16_at(0x38)? as usize;
+
+    let mut deps = Vec::new();
+    let mut loads = Vec::new();
+    let mut dynamic = None;
+    for i in 0..phnum {
+        let ph = phoff + i * phentsize;
+        let offset = u64_at(ph + 0x08)?;
+        let vaddr = u64_at(ph + 0x10)?;
+        let filesz = u64_at(ph + 0x20)?;
+        match u32_at(ph)? {
+            PT_LOAD => loads.push((vaddr, offset, filesz)),
+            PT_DYNAMIC => dynamic = Some((offset as usize, filesz as usize)),
+            PT_INTERP => deps.push(cstr_at(offset as usize)?),
+            _ => {}
+        }
+    }
+
+    let Some((dyn_off, dyn_size)) = dynamic else {
+        return Ok(deps);
+    };
+
+    let mut needed = Vec::new();
+    let mut strtab_vaddr = None;
+    for off in (dyn_off..dyn_off + dyn_size).step_by(16) {
+        match u64_at(off)? {
+            0 => break,
+            DT_NEEDED => needed.push(u64_at(off + 8)?),
+            DT_STRTAB => strtab_vaddr = Some(u64_at(off + 8)?),
+            _ => {}
+        }
+    }
+
+    if needed.is_empty() {
+        return Ok(deps);
+    }
+    let strtab_vaddr = strtab_vaddr.context("DT_NEEDED without DT_STRTAB")?;
+    let strtab = loads
+        .iter()
+        .find(|(vaddr, _, filesz)| strtab_vaddr >= *vaddr && strtab_vaddr < vaddr + filesz)
+        .map(|(vaddr, offset, _)| strtab_vaddr - vaddr + offset)
+        .context("DT_STRTAB outside any PT_LOAD segment")?;
+    for name_off in needed {
+        deps.push(cstr_at((strtab + name_off) as usize)?);
+    }
+    Ok(deps)
+}
+
+/// Module file names compare with '-' and '_' treated as equal.
+fn module_stem_matches(file_name: &str, module: &str) -> bool {
+    let Some(stem) = file_name
+        .split(".ko")
+        .next()
+        .filter(|_| file_name.contains(".ko"))
+    else {
+        return false;
+    };
+    stem.replace('-', "_") == module.replace('-', "_")
+}
+
+/// Find `module` under `modules_dir/kernel`.
+pub(crate) fn find_module(modules_dir: &Path, module: &str) -> Result<Option<PathBuf>> {
+    fn walk(dir: &Path, module: &str) -> Result<Option<PathBuf>> {
+        let mut entries: Vec<_> = fs::read_dir(dir)?.collect::<Result<_, _>>()?;
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            let path = entry.path();
+            if path.is_dir() {
+                if let Some(found) = walk(&path, module)? {
+                    return Ok(Some(found));
+                }
+            } else if module_stem_matches(&entry.file_name().to_string_lossy(), module) {
+                return Ok(Some(path));
+            }
+        }
+        Ok(None)
+    }
+
+    let kernel_dir = modules_dir.join("kernel");
+    if !kernel_dir.is_dir() {
+        return Ok(None);
+    }
+    walk(&kernel_dir, module)
+}
+
+/// Whether `module` is listed in `modules.builtin`.
+pub(crate) fn is_builtin(modules_dir: &Path, module: &str) -> bool {
+    fs::read_to_string(modules_dir.join("modules.builtin"))
+        .map(|content| {
+            content.lines().any(|line| {
+                line.rsplit('/')
+                    .next()
+                    .is_some_and(|f| module_stem_matches(f, module))
+            })
+        })
+        .unwrap_or(false)
+}
+
+/// A file or symlink in a newc archive.
+#[derive(Debug, Clone)]
+pub(crate) struct CpioEntry {
+    path: String,
+    mode: u32,
+    data: Vec<u8>,
+}
+
+impl CpioEntry {
+    /// Regular file, keeping the executable bit of the source.
+    pub(crate) fn file(path: String, src: &Path) -> Result<Self> {
+        use std::os::unix::fs::PermissionsExt;
+        let perms = fs::metadata(src)?.permissions().mode() & 0o777;
+        Ok(Self {
+            path,
+            mode: 0o100000 | perms,
+            data: fs::read(src).with_context(|| format!("Failed to read {}", src.display()))?,
+        })
+    }
+
+    /// Regular file with in-memory content.
+    pub(crate) fn bytes(path: &str, mode: u32, data: &[u8]) -> Self {
+        Self {
+            path: path.to_string(),
+            mode: 0o100000 | (mode & 0o7777),
+            data: data.to_vec(),
+        }
+    }
+
+    pub(crate) fn symlink(path: &str, target: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            mode: 0o120777,
+            data: target.as_bytes().to_vec(),
+        }
+    }
+
+    /// Archive path (no leading `/`).
+    pub(crate) fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+/// Write entries as a newc ("070701") cpio archive, creating parent dirs.
+///
+/// Owner is root and mtimes are zero so the output is reproducible.
+fn write_newc(out: &mut Vec<u8>, entries: &[CpioEntry]) -> Result<()> {
+    fn header(out: &mut Vec<u8>, ino: u32, mode: u32, nlink: u32, name: &str, size: usize) {
+        let fields = [ino, mode, 0, 0, nlink, 0, size as u32, 0, 0, 0, 0];
+        out.extend_from_slice(b"070701");
+        for field in fields {
+            out.extend_from_slice(format!("{:08x}", field).as_bytes());
+        }
+        out.extend_from_slice(format!("{:08x}{:08x}", name.len() + 1, 0).as_bytes());
+        out.extend_from_slice(name.as_bytes());
+        out.push(0);
+        pad4(out);
+    }
+    fn pad4(out: &mut Vec<u8>) {
+        while !out.len().is_multiple_of(4) {
+            out.push(0);
+        }
+    }
+
+    let mut dirs = BTreeSet::new();
+    for entry in entries {
+        let mut parent = Path::new(&entry.path).parent();
+        while let Some(dir) = parent.filter(|d| !d.as_os_str().is_empty()) {
+            dirs.insert(dir.to_string_lossy().into_owned());
+            parent = dir.parent();
+        }
+    }
+
+    let mut ino = 1;
+    for dir in &dirs {
+        header(out, ino, 0o040755, 2, dir, 0);
+        ino += 1;
+    }
+    for entry in entries {
+        header(out, ino, entry.mode, 1, &entry.path, entry.data.len());
+        out.extend_from_slice(&entry.data);
+        pad4(out);
+        ino += 1;
+    }
+    header(out, 0, 0, 1, "TRAILER!!!", 0);
+    Ok(())
+}
+
+/// Compress `data` with gzip into `output`.
+fn gzip_to(data: &[u8], output: &Path) -> Result<()> {
+    let mut child = Command::new("gzip")
+        .arg("-c")
+        .arg(format!("-{}", CPIO_GZIP_LEVEL))
+        .stdin(Stdio::piped())
+        .stdout(fs::File::create(output)?)
+        .spawn()
+        .context("Failed to run gzip. Install: sudo dnf install gzip")?;
+    child
+        .stdin
+        .take()
+        .context("gzip stdin unavailable")?
+        .write_all(data)?;
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("gzip failed with {}", status);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_elf_dependencies() {
+        let exe = fs::read(std::env::current_exe().unwrap()).unwrap();
+        // Test binaries may be static or dynamic; either way they must parse
+        elf_dependencies(&exe).unwrap();
+        assert!(elf_dependencies(b"#!/bin/sh\necho not elf\n").is_err());
+
+        // Never executed: x86_64 musl binary, readable on any host
+        let fixture = include_bytes!("../../testdata/hello-musl-x86_64");
+        assert_eq!(
+            elf_dependencies(fixture).unwrap(),
+            ["/lib/ld-musl-x86_64.so.1", "libc.musl-x86_64.so.1"]
+        );
+    }
+
+    #[test]
+    fn test_module_names_and_builtin() {
+        assert!(module_stem_matches("dm-crypt.ko.zst", "dm-crypt"));
+        assert!(module_stem_matches("nls_iso8859-1.ko", "nls_iso8859_1"));
+        assert!(!module_stem_matches("ext4.ko", "ext2"));
+        assert!(!module_stem_matches("ext4", "ext4"));
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("modules.builtin"),
+            "kernel/fs/ext4/ext4.ko\nkernel/lib/crc16.ko\n",
+        )
+        .unwrap();
+        assert!(is_builtin(dir.path(), "ext4"));
+        assert!(!is_builtin(dir.path(), "vfat"));
+        assert_eq!(installed_modules(true).last(), Some(&"dm-crypt"));
+        assert!(!installed_modules(false).contains(&"dm-mod"));
+    }
+
+    #[test]
+    fn test_module_dependency_closure() {
+        let dir = tempfile::tempdir().unwrap();
+        let modules_dir = dir.path().join("6.6.0-lts");
+        let files = [
+            "kernel/fs/ext4/ext4.ko.gz",
+            "kernel/fs/jbd2/jbd2.ko.gz",
+            "kernel/fs/mbcache.ko.gz",
+            "kernel/lib/crc16.ko.gz",
+            "kernel/drivers/md/dm-crypt.ko.gz",
+        ];
+        for file in files {
+            let path = modules_dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, file).unwrap();
+        }
+        // jbd2 -> crc16 is a second level below ext4; dm-mod is not shipped
+        fs::write(
+            modules_dir.join("modules.dep"),
+            "kernel/fs/ext4/ext4.ko.gz: kernel/fs/jbd2/jbd2.ko.gz kernel/fs/mbcache.ko.gz\n\
+             kernel/fs/jbd2/jbd2.ko.gz: kernel/lib/crc16.ko.gz\n\
+             kernel/fs/mbcache.ko.gz:\n\
+             kernel/lib/crc16.ko.gz:\n\
+             kernel/drivers/md/dm-crypt.ko.gz: kernel/drivers/md/dm-mod.ko.gz\n",
+        )
+        .unwrap();
+        fs::write(
+            modules_dir.join("modules.builtin"),
+            "kernel/fs/fat/vfat.ko\n",
+        )
+        .unwrap();
+
+        let closure = resolve_modules(&modules_dir, &["ext4", "vfat"]).unwrap();
+        assert_eq!(closure.names, ["crc16", "jbd2", "mbcache", "ext4"]);
+        assert_eq!(closure.files[0], "kernel/lib/crc16.ko.gz");
+        assert_eq!(closure.pulled_in, 3);
+        let entries = closure_entries(&modules_dir, &closure).unwrap();
+        assert_eq!(
+            entries[3].path(),
+            "lib/modules/6.6.0-lts/kernel/fs/ext4/ext4.ko.gz"
+        );
+
+        // Listing a dependency explicitly does not count it as pulled in
+        let closure = resolve_modules(&modules_dir, &["crc16", "jbd2"]).unwrap();
+        assert_eq!((closure.names.len(), closure.pulled_in), (2, 0));
+
+        let err = resolve_modules(&modules_dir, &["dm-crypt"])
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("dm-mod.ko.gz (needed by 'dm-crypt')"),
+            "{}",
+            err
+        );
+        let err = resolve_modules(&modules_dir, &["btrfs"])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("neither built-in nor present"), "{}", err);
+    }
+
+    #[test]
+    fn test_write_newc() {
+        let mut out = Vec::new();
+        write_newc(&mut out, &[CpioEntry::symlink("sbin/fsck.ext4", "e2fsck")]).unwrap();
+        let text = String::from_utf8_lossy(&out);
+        assert!(out.starts_with(b"070701"));
+        assert_eq!(out.len() % 4, 0);
+        // Ownership lives in the headers (uid, gid 0), not on disk
+        assert_eq!(&out[22..38], b"0000000000000000");
+        // Parent directory is emitted before the entry
+        assert!(text.find("sbin\0").unwrap() < text.find("sbin/fsck.ext4\0").unwrap());
+        assert!(text.contains("e2fsck"));
+        assert!(text.contains("TRAILER!!!\0"));
+    }
+}
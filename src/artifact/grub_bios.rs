@@ -0,0 +1,235 @@
+//! Legacy BIOS boot for x86_64 ISOs (`--bios`).
+//!
+//! reciso only sets up UEFI boot. With `--bios` the reciso ISO is
+//! post-processed: a GRUB i386-pc El Torito image (`cdboot.img` followed by
+//! a `grub-mkstandalone` core with the menu embedded) becomes the default
+//! boot entry, the UEFI image extracted from the reciso ISO is added back as
+//! the second entry, and GRUB's hybrid MBR keeps the image bootable from USB
+//! sticks. The kernel and live initramfs are added at the paths the GRUB EFI
+//! boot of the other arches uses (see `grub_efi`).
+//!
+//! The menu is the `grub_efi` one, preceded by serial console setup so the
+//! headless boot test sees it.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::cmdline::CmdlineEdit;
+use super::grub_efi::{self, grub_cfg, MKSTANDALONE};
+use crate::arch::Arch;
+use crate::cmd_args::prefixed;
+use crate::progress;
+use distro_builder::process::{which, Cmd};
+
+/// GRUB i386-pc platform files (Fedora `grub2-pc-modules`, Debian `grub-pc-bin`).
+pub const GRUB_PC_DIR: &str = "/usr/lib/grub/i386-pc";
+
+/// El Torito BIOS boot image path on the ISO.
+pub const BIOS_IMAGE_ISO_PATH: &str = "boot/grub/bios.img";
+
+/// El Torito boot catalog path on the ISO.
+const BOOT_CATALOG_ISO_PATH: &str = "boot/grub/boot.cat";
+
+/// Modules preloaded into the core image.
+const GRUB_MODULES: &str = "biosdisk part_gpt part_msdos iso9660 search search_label linux normal \
+     configfile echo serial terminal";
+
+/// Bytes of the boot image the BIOS loads (`cdboot.img` loads the rest).
+const BOOT_LOAD_SIZE: u32 = 2048;
+
+/// Files used to build and install the BIOS boot path.
+#[derive(Debug, Clone)]
+pub struct BiosBoot {
+    /// `cdboot.img` + core image, the default El Torito entry.
+    pub image: PathBuf,
+    /// UEFI El Torito image taken from the reciso ISO.
+    pub efi_image: PathBuf,
+    /// GRUB's hybrid MBR for USB boot.
+    pub hybrid_mbr: PathBuf,
+}
+
+/// `grub-mkstandalone` and the i386-pc platform files, or an error naming
+/// the missing package.
+pub fn check_bios_prerequisites() -> Result<()> {
+    if !MKSTANDALONE.iter().any(|t| which(t).is_some()) {
+        bail!("--bios needs grub-mkstandalone. Install: sudo dnf install grub2-tools-extra");
+    }
+    let dir = Path::new(GRUB_PC_DIR);
+    for file in ["cdboot.img", "boot_hybrid.img"] {
+        if !dir.join(file).exists() {
+            bail!(
+                "--bios needs the GRUB i386-pc files ({} not found).\nInstall: sudo dnf install grub2-pc-modules",
+                dir.join(file).display()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// GRUB menu for BIOS boot, mirrored on the serial console.
+pub fn bios_grub_cfg(label: &str, cmdline: &CmdlineEdit) -> String {
+    format!(
+        "serial --unit=0 --speed=115200\n\
+         terminal_input console serial\n\
+         terminal_output console serial\n{}",
+        grub_cfg(Arch::X86_64, label, cmdline)
+    )
+}
+
+/// Build the BIOS boot image into `work_dir` and extract the UEFI image of
+/// `iso` next to it.
+pub fn prepare_bios_boot(
+    iso: &Path,
+    label: &str,
+    cmdline: &CmdlineEdit,
+    work_dir: &Path,
+) -> Result<BiosBoot> {
+    check_bios_prerequisites()?;
+    let tool = MKSTANDALONE
+        .iter()
+        .find(|t| which(t).is_some())
+        .context("grub-mkstandalone not found. Install: sudo dnf install grub2-tools-extra")?;
+    let grub_pc = Path::new(GRUB_PC_DIR);
+
+    let _ = fs::remove_dir_all(work_dir);
+    fs::create_dir_all(work_dir)?;
+    let cfg = work_dir.join("grub.cfg");
+    fs::write(&cfg, bios_grub_cfg(label, cmdline))?;
+    let core = work_dir.join("core.img");
+
+    Cmd::new(tool)
+        .arg("--format=i386-pc")
+        .arg_path(&prefixed("--directory=", grub_pc))
+        .arg_path(&prefixed("--output=", &core))
+        .arg(format!("--modules={}", GRUB_MODULES))
+        .arg(format!("--install-modules={}", GRUB_MODULES))
+        .args(["--locales=", "--fonts=", "--themes="])
+        .arg_path(&prefixed("boot/grub/grub.cfg=", &cfg))
+        .error_msg("grub-mkstandalone (i386-pc) failed")
+        .run()?;
+
+    let mut image = fs::read(grub_pc.join("cdboot.img"))?;
+    image.extend(fs::read(&core)?);
+    let image_path = work_dir.join("bios.img");
+    fs::write(&image_path, image)?;
+
+    let extracted = work_dir.join("boot-images");
+    fs::create_dir_all(&extracted)?;
+    Cmd::new("xorriso")
+        .args(["-osirrox", "on", "-indev"])
+        .arg_path(iso)
+        .arg("-extract_boot_images")
+        .arg_path(&extracted)
+        .error_msg("xorriso failed to extract the UEFI boot image")
+        .run()?;
+    let mut names: Vec<String> = fs::read_dir(&extracted)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    let efi_name = pick_efi_image(&names).with_context(|| {
+        format!(
+            "No UEFI boot image found in {} (extracted: {})",
+            iso.display(),
+            names.join(", ")
+        )
+    })?;
+
+    Ok(BiosBoot {
+        image: image_path,
+        efi_image: extracted.join(efi_name),
+        hybrid_mbr: grub_pc.join("boot_hybrid.img"),
+    })
+}
+
+/// The UEFI image among `xorriso -extract_boot_images` output
+/// (`eltorito_img1_uefi.img`, or an appended EFI partition).
+pub fn pick_efi_image(names: &[String]) -> Option<&str> {
+    names
+        .iter()
+        .find(|n| n.starts_with("eltorito_") && n.contains("uefi"))
+        .or_else(|| names.iter().find(|n| n.contains("efi")))
+        .map(String::as_str)
+}
+
+/// Rewrite `input` into `output` with BIOS as the default boot entry and
+/// UEFI as the second, plus a hybrid MBR for USB boot.
+pub fn add_bios_boot(
+    input: &Path,
+    output: &Path,
+    kernel: &Path,
+    initramfs: &Path,
+    boot: &BiosBoot,
+) -> Result<()> {
+    let _ = fs::remove_file(output);
+    let arch = Arch::X86_64;
+    let efi_image = grub_efi::efi_image_iso_path(arch);
+
+    let mut cmd = Command::new("xorriso");
+    cmd.arg("-indev")
+        .arg(input)
+        .arg("-outdev")
+        .arg(output)
+        .args(["-boot_image", "any", "discard"]);
+    let mut map = |src: &Path, dest: &str| {
+        cmd.arg("-map").arg(src).arg(format!("/{}", dest));
+    };
+    map(kernel, &grub_efi::kernel_iso_path(arch));
+    map(initramfs, &grub_efi::initramfs_iso_path(arch));
+    map(&boot.image, BIOS_IMAGE_ISO_PATH);
+    map(&boot.efi_image, &efi_image);
+
+    // BIOS first: legacy firmware only looks at the default entry
+    cmd.args(["-boot_image", "grub"])
+        .arg(format!("bin_path=/{}", BIOS_IMAGE_ISO_PATH))
+        .args(["-boot_image", "grub", "grub2_boot_info=on"])
+        .args(["-boot_image", "any", "platform_id=0x00"])
+        .args(["-boot_image", "any", "emul_type=no_emulation"])
+        .args(["-boot_image", "any"])
+        .arg(format!("load_size={}", BOOT_LOAD_SIZE))
+        .args(["-boot_image", "any", "boot_info_table=on"])
+        .args(["-boot_image", "any"])
+        .arg(format!("cat_path=/{}", BOOT_CATALOG_ISO_PATH))
+        .args(["-boot_image", "any", "next"])
+        .args(["-boot_image", "any"])
+        .arg(format!("efi_path=/{}", efi_image));
+
+    // Hybrid MBR with an EFI partition, as grub-mkrescue lays it out
+    cmd.args(["-boot_image", "grub"])
+        .arg(prefixed("grub2_mbr=", &boot.hybrid_mbr))
+        .args(["-boot_image", "any", "partition_offset=16"])
+        .args(["-boot_image", "any", "partition_table=on"])
+        .args(["-boot_image", "any", "efi_boot_part=--efi-boot-image"]);
+    progress::run(
+        "ISO (BIOS)",
+        &mut cmd,
+        "xorriso failed to add the BIOS boot entry",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bios_grub_cfg_and_efi_image() {
+        let cfg = bios_grub_cfg("ACORNOS", &CmdlineEdit::default());
+        assert!(cfg.starts_with("serial --unit=0 --speed=115200\n"));
+        assert!(cfg.contains("terminal_output console serial"));
+        assert!(cfg.contains("linux /boot/x86_64/vmlinuz root=LABEL=ACORNOS"));
+        assert!(cfg.contains("console=ttyS0,115200"));
+
+        let names = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            pick_efi_image(&names(&["eltorito_catalog.img", "eltorito_img1_uefi.img"])),
+            Some("eltorito_img1_uefi.img")
+        );
+        assert_eq!(
+            pick_efi_image(&names(&["gpt_part2_efi.img"])),
+            Some("gpt_part2_efi.img")
+        );
+        assert_eq!(pick_efi_image(&names(&["eltorito_catalog.img"])), None);
+    }
+}
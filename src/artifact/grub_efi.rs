@@ -0,0 +1,230 @@
+//! Standalone GRUB EFI boot for non-x86_64 ISOs.
+//!
+//! reciso only knows systemd-boot with x86_64 UKIs. For other arches the
+//! reciso ISO is post-processed: a standalone GRUB loader
+//! (`grub-mkstandalone --format=arm64-efi`, modules taken from the Alpine
+//! rootfs so they match the target) is installed as the removable-media
+//! loader, the kernel and live initramfs are added next to it, and the El
+//! Torito EFI boot image is replaced by a FAT image holding that loader.
+//!
+//! The menu mirrors the UKI entries from distro-spec, with the serial
+//! console of the target arch.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::cmdline::CmdlineEdit;
+use super::uki::live_entries_with;
+use crate::arch::Arch;
+use crate::cmd_args::prefixed;
+use crate::progress;
+use distro_builder::process::{which, Cmd};
+
+/// `grub-mkstandalone` names, Fedora first.
+pub(crate) const MKSTANDALONE: &[&str] = &["grub2-mkstandalone", "grub-mkstandalone"];
+
+/// Modules preloaded into the standalone image.
+const GRUB_MODULES: &str =
+    "part_gpt part_msdos iso9660 fat search search_label linux normal configfile echo";
+
+/// Headroom added to the EFI boot image beyond the loader itself.
+const EFI_IMAGE_SLACK_KB: u64 = 1024;
+
+/// Kernel path on the ISO for `arch`.
+pub fn kernel_iso_path(arch: Arch) -> String {
+    format!("boot/{}/vmlinuz", arch)
+}
+
+/// Live initramfs path on the ISO for `arch`.
+pub fn initramfs_iso_path(arch: Arch) -> String {
+    format!("boot/{}/initramfs-live.img", arch)
+}
+
+/// El Torito EFI boot image path on the ISO for `arch`.
+pub fn efi_image_iso_path(arch: Arch) -> String {
+    format!("boot/{}/efiboot.img", arch)
+}
+
+/// GRUB menu with one entry per UKI entry, with `cmdline` applied.
+///
+/// `console=` options from the entries are replaced by the arch's serial
+/// console.
+pub fn grub_cfg(arch: Arch, label: &str, cmdline: &CmdlineEdit) -> String {
+    let mut cfg = format!(
+        "set timeout=3\n\
+         set default=0\n\
+         search --no-floppy --label {} --set=root\n",
+        label
+    );
+    let consoles = [
+        "tty0".to_string(),
+        format!("{},115200", arch.serial_console()),
+    ];
+    for entry in live_entries_with(cmdline) {
+        let cmdline = entry.cmdline(label, &consoles);
+        cfg.push_str(&format!(
+            "\nmenuentry '{}' {{\n    linux /{} {}\n    initrd /{}\n}}\n",
+            entry.name,
+            kernel_iso_path(arch),
+            cmdline,
+            initramfs_iso_path(arch)
+        ));
+    }
+    cfg
+}
+
+/// Build the standalone GRUB EFI loader into `work_dir`.
+///
+/// `rootfs` is the Alpine rootfs for `arch`; its `usr/lib/grub/<format>`
+/// provides the GRUB core and modules.
+pub fn build_grub_efi(
+    rootfs: &Path,
+    arch: Arch,
+    label: &str,
+    cmdline: &CmdlineEdit,
+    work_dir: &Path,
+) -> Result<PathBuf> {
+    let modules = rootfs.join("usr/lib/grub").join(arch.grub_format());
+    if !modules.is_dir() {
+        bail!(
+            "GRUB {} modules not found at {}.\nRun 'acornos download alpine --arch {}' first.",
+            arch.grub_format(),
+            modules.display(),
+            arch
+        );
+    }
+    let tool = MKSTANDALONE
+        .iter()
+        .find(|t| which(t).is_some())
+        .context("grub-mkstandalone not found. Install: sudo dnf install grub2-tools-extra")?;
+
+    fs::create_dir_all(work_dir)?;
+    let cfg = work_dir.join("grub.cfg");
+    fs::write(&cfg, grub_cfg(arch, label, cmdline))?;
+    let efi_name = Path::new(arch.efi_boot_path())
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let output = work_dir.join(efi_name);
+
+    Cmd::new(tool)
+        .arg(format!("--format={}", arch.grub_format()))
+        .arg_path(&prefixed("--directory=", &modules))
+        .arg_path(&prefixed("--output=", &output))
+        .arg(format!("--modules={}", GRUB_MODULES))
+        .arg_path(&prefixed("boot/grub/grub.cfg=", &cfg))
+        .error_msg("grub-mkstandalone failed")
+        .run()?;
+    Ok(output)
+}
+
+/// FAT image holding `loader` as the removable-media EFI loader.
+pub fn build_efi_image(loader: &Path, arch: Arch, output: &Path) -> Result<()> {
+    let size_kb = fs::metadata(loader)?.len().div_ceil(1024) + EFI_IMAGE_SLACK_KB;
+    let _ = fs::remove_file(output);
+
+    Cmd::new("mkfs.fat")
+        .args(["-C", "-n", "EFIBOOT"])
+        .arg_path(output)
+        .arg(size_kb.to_string())
+        .error_msg("mkfs.fat failed. Install: sudo dnf install dosfstools")
+        .run()?;
+    Cmd::new("mmd")
+        .arg("-i")
+        .arg_path(output)
+        .args(["::/EFI", "::/EFI/BOOT"])
+        .error_msg("mmd failed. Install: sudo dnf install mtools")
+        .run()?;
+    Cmd::new("mcopy")
+        .arg("-i")
+        .arg_path(output)
+        .arg_path(loader)
+        .arg(format!("::{}", arch.efi_boot_path()))
+        .error_msg("mcopy failed. Install: sudo dnf install mtools")
+        .run()?;
+    Ok(())
+}
+
+/// Rewrite `input` into `output` with GRUB as the only EFI boot entry.
+pub fn add_grub_boot(
+    input: &Path,
+    output: &Path,
+    arch: Arch,
+    kernel: &Path,
+    initramfs: &Path,
+    loader: &Path,
+    efi_image: &Path,
+) -> Result<()> {
+    let _ = fs::remove_file(output);
+
+    let mut cmd = Command::new("xorriso");
+    cmd.arg("-indev")
+        .arg(input)
+        .arg("-outdev")
+        .arg(output)
+        .args(["-boot_image", "any", "discard"]);
+    let mut map = |src: &Path, dest: &str| {
+        cmd.arg("-map").arg(src).arg(format!("/{}", dest));
+    };
+    map(kernel, &kernel_iso_path(arch));
+    map(initramfs, &initramfs_iso_path(arch));
+    map(loader, arch.efi_boot_path().trim_start_matches('/'));
+    map(efi_image, &efi_image_iso_path(arch));
+    cmd.args(["-boot_image", "any"])
+        .arg(format!("efi_path=/{}", efi_image_iso_path(arch)));
+    progress::run(
+        "ISO (GRUB EFI)",
+        &mut cmd,
+        "xorriso failed to add the GRUB EFI boot entry",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::artifact::uki::live_entries;
+
+    #[test]
+    fn test_grub_cfg() {
+        let cfg = grub_cfg(Arch::Aarch64, "ACORNOS", &CmdlineEdit::default());
+        assert!(cfg.contains("search --no-floppy --label ACORNOS --set=root"));
+        assert!(cfg.contains("linux /boot/aarch64/vmlinuz root=LABEL=ACORNOS"));
+        assert!(cfg.contains("console=ttyAMA0,115200"));
+        assert!(!cfg.contains("ttyS0"));
+        assert!(cfg.contains("initrd /boot/aarch64/initramfs-live.img"));
+        assert_eq!(cfg.matches("menuentry").count(), live_entries().len());
+        assert!(cfg.contains("menuentry 'AcornOS (Persistent)'"));
+        assert!(cfg.contains("acorn.persist=LABEL=ACORN-PERSIST\n"));
+
+        // One menuentry per boot entry, each booting the arch's kernel
+        let consoles = ["tty0".to_string(), "ttyAMA0,115200".to_string()];
+        let blocks: Vec<&str> = cfg.split("\nmenuentry ").skip(1).collect();
+        assert_eq!(blocks.len(), live_entries().len());
+        for (block, entry) in blocks.iter().zip(live_entries()) {
+            assert_eq!(cfg.matches(&format!("'{}' {{", entry.name)).count(), 1);
+            assert!(block.starts_with(&format!("'{}' {{", entry.name)));
+            assert!(block.contains(&format!(
+                "linux /boot/aarch64/vmlinuz {}\n",
+                entry.cmdline("ACORNOS", &consoles)
+            )));
+        }
+        let emergency = blocks
+            .iter()
+            .find(|b| b.starts_with("'Emergency'"))
+            .unwrap();
+        assert!(emergency.split_whitespace().any(|opt| opt == "emergency"));
+
+        // --cmdline-append/--cmdline-remove reach every menuentry
+        let edit = CmdlineEdit::new(&["loglevel=7".into()], &["console".into()]).unwrap();
+        let cfg = grub_cfg(Arch::Aarch64, "ACORNOS", &edit);
+        let linux_lines: Vec<&str> = cfg.lines().filter(|l| l.contains("linux /")).collect();
+        assert_eq!(linux_lines.len(), live_entries().len());
+        for line in linux_lines {
+            assert!(line.ends_with(" loglevel=7"));
+            // The arch's consoles are not the entries' own options
+            assert!(line.contains("console=ttyAMA0,115200"));
+        }
+    }
+}
@@ -0,0 +1,636 @@
+//! Init script template checks.
+//!
+//! recinit fills `{{PLACEHOLDER}}` tokens in the `profile/init_*.template`
+//! scripts with plain string replacement, so a typo (`{{ISO_LABLE}}`) or a
+//! dropped placeholder ships a broken `/init` that only fails at boot.
+//! Before each initramfs build the template is checked against the
+//! placeholders that will be filled, rendered the same way, and the result
+//! parsed with `sh -n` (the target busybox when it runs on this host).
+//!
+//! The initramfs only has the applet links recinit creates, so a command
+//! the script runs by bare name (`blkid`) may be missing at boot. After the
+//! build, [`check_init_commands`] matches the script's command words
+//! against the archive and the `busybox X` calls against `busybox --list`.
+
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use crate::arch::Arch;
+
+/// Replace `{{KEY}}` placeholders; unknown placeholders are left as they are.
+pub fn render_template(template: &str, vars: &[(&str, String)]) -> String {
+    vars.iter().fold(template.to_string(), |out, (key, value)| {
+        out.replace(&format!("{{{{{}}}}}", key), value)
+    })
+}
+
+/// `{{...}}` tokens in `text`; an unterminated `{{` yields the rest of its
+/// line.
+pub fn placeholders(text: &str) -> BTreeSet<String> {
+    let mut found = BTreeSet::new();
+    for line in text.lines() {
+        let mut rest = line;
+        while let Some(start) = rest.find("{{") {
+            rest = &rest[start + 2..];
+            match rest.find("}}") {
+                Some(end) => {
+                    found.insert(rest[..end].to_string());
+                    rest = &rest[end + 2..];
+                }
+                None => {
+                    found.insert(rest.to_string());
+                    break;
+                }
+            }
+        }
+    }
+    found
+}
+
+/// Fail on placeholders nobody fills and on `required` ones the template
+/// lost.
+pub fn check_placeholders(
+    name: &str,
+    template: &str,
+    known: &[&str],
+    required: &[&str],
+) -> Result<()> {
+    let found = placeholders(template);
+    let unknown: Vec<String> = found
+        .iter()
+        .filter(|p| !known.contains(&p.as_str()))
+        .map(|p| format!("{{{{{}}}}}", p))
+        .collect();
+    let missing: Vec<String> = required
+        .iter()
+        .filter(|r| !found.contains(**r))
+        .map(|r| format!("{{{{{}}}}}", r))
+        .collect();
+
+    let mut problems = Vec::new();
+    if !unknown.is_empty() {
+        problems.push(format!("unknown placeholders {}", unknown.join(", ")));
+    }
+    if !missing.is_empty() {
+        problems.push(format!(
+            "required placeholders not found: {}",
+            missing.join(", ")
+        ));
+    }
+    if !problems.is_empty() {
+        bail!(
+            "Init template {}: {}.\nKnown placeholders: {}",
+            name,
+            problems.join("; "),
+            known.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Fail if rendering left any `{{...}}` token behind.
+pub fn check_rendered(name: &str, rendered: &str) -> Result<()> {
+    let left = placeholders(rendered);
+    if !left.is_empty() {
+        let left: Vec<String> = left.iter().map(|p| format!("{{{{{}}}}}", p)).collect();
+        bail!(
+            "Init template {}: unreplaced {} after rendering",
+            name,
+            left.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Parse `script` with `sh -n`, using `busybox` when it runs on this host.
+pub fn check_shell_syntax(
+    name: &str,
+    script: &str,
+    busybox: Option<&Path>,
+    scratch: &Path,
+) -> Result<()> {
+    fs::write(scratch, script).with_context(|| format!("Failed to write {}", scratch.display()))?;
+    // Command, not Cmd: the busybox path may not be UTF-8
+    let mut cmd = match busybox.filter(|_| Arch::from_env().is_ok_and(Arch::is_host)) {
+        Some(busybox) => {
+            let mut cmd = Command::new(busybox);
+            cmd.arg("sh");
+            cmd
+        }
+        None => Command::new("sh"),
+    };
+    let result = cmd.arg("-n").arg(scratch).output();
+    let _ = fs::remove_file(scratch);
+    let output = result.context("Failed to run sh -n")?;
+    if !output.status.success() {
+        bail!(
+            "Init template {} renders to invalid shell:\n{}",
+            name,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Check the template at `base_dir/name` before recinit renders it, and
+/// return the rendered script.
+///
+/// `vars` are the values filled in (recinit's with representative values,
+/// plus the extra template vars), `required` the placeholders the template
+/// must keep. The rendered script is written to `scratch` for `sh -n`.
+pub fn validate_init_template(
+    base_dir: &Path,
+    name: &str,
+    vars: &[(&str, String)],
+    required: &[&str],
+    busybox: Option<&Path>,
+    scratch: &Path,
+) -> Result<String> {
+    let template = fs::read_to_string(base_dir.join(name))
+        .with_context(|| format!("Failed to read {}", name))?;
+    let known: Vec<&str> = vars.iter().map(|(k, _)| *k).collect();
+    check_placeholders(name, &template, &known, required)?;
+    let rendered = render_template(&template, vars);
+    check_rendered(name, &rendered)?;
+    check_shell_syntax(name, &rendered, busybox, scratch)?;
+    Ok(rendered)
+}
+
+/// ash builtins (busybox defaults); never looked up on `PATH`.
+const SHELL_BUILTINS: &[&str] = &[
+    "[", "[[", ".", ":", "alias", "break", "cd", "command", "continue", "echo", "eval", "exec",
+    "exit", "export", "false", "getopts", "hash", "kill", "local", "printf", "pwd", "read",
+    "readonly", "return", "set", "shift", "source", "test", "trap", "true", "type", "ulimit",
+    "umask", "unalias", "unset", "wait",
+];
+
+/// Reserved words that may precede a command.
+const SHELL_KEYWORDS: &[&str] = &[
+    "!", "{", "}", "if", "then", "else", "elif", "fi", "do", "done", "while", "until", "esac",
+];
+
+/// Commands an init script runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InitCommands {
+    /// Run by name or path, resolved through `PATH` inside the initramfs.
+    pub commands: BTreeSet<String>,
+    /// Run as `busybox NAME`.
+    pub applets: BTreeSet<String>,
+    /// `PATH` the script sets (empty if it does not).
+    pub path: Vec<String>,
+}
+
+/// Split a shell script into simple commands (lists of words), following
+/// quotes, `$(...)` and backticks, and dropping comments. Good enough for
+/// init scripts; heredocs are not understood.
+fn shell_segments(script: &str) -> (Vec<Vec<String>>, BTreeSet<String>) {
+    #[derive(Clone, Copy, PartialEq, Default)]
+    enum Quote {
+        #[default]
+        None,
+        Single,
+        Double,
+    }
+    let mut segments = Vec::new();
+    let mut functions = BTreeSet::new();
+    let mut segment: Vec<String> = Vec::new();
+    let mut word = String::new();
+    let mut quote = Quote::None;
+    // Outer (segment, word, quote) while inside `$(...)` or backticks
+    let mut stack: Vec<(Vec<String>, String, Quote, char)> = Vec::new();
+    let mut chars = script.chars().peekable();
+
+    fn end_word(word: &mut String, segment: &mut Vec<String>) {
+        if !word.is_empty() {
+            segment.push(std::mem::take(word));
+        }
+    }
+    fn end_segment(word: &mut String, segment: &mut Vec<String>, out: &mut Vec<Vec<String>>) {
+        end_word(word, segment);
+        if !segment.is_empty() {
+            out.push(std::mem::take(segment));
+        }
+    }
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Quote::Single, '\'') => quote = Quote::None,
+            (Quote::Single, c) => word.push(c),
+            (_, '\\') => match chars.next() {
+                Some('\n') | None => {}
+                Some(c) => word.push(c),
+            },
+            (Quote::Double, '"') => quote = Quote::None,
+            (_, '(') if word.ends_with('$') => {
+                // Command substitution: its commands are a separate segment
+                let outer = (std::mem::take(&mut segment), word.clone(), quote, ')');
+                stack.push(outer);
+                word.clear();
+                quote = Quote::None;
+            }
+            (_, '`') if stack.last().is_some_and(|s| s.3 == '`') => {
+                end_segment(&mut word, &mut segment, &mut segments);
+                (segment, word, quote, _) = stack.pop().unwrap_or_default();
+            }
+            (_, '`') => {
+                let outer = (std::mem::take(&mut segment), word.clone() + "$", quote, '`');
+                stack.push(outer);
+                word.clear();
+                quote = Quote::None;
+            }
+            (Quote::None, ')') if stack.last().is_some_and(|s| s.3 == ')') => {
+                end_segment(&mut word, &mut segment, &mut segments);
+                (segment, word, quote, _) = stack.pop().unwrap_or_default();
+            }
+            (Quote::Double, c) => word.push(c),
+            (Quote::None, '\'') => quote = Quote::Single,
+            (Quote::None, '"') => quote = Quote::Double,
+            (Quote::None, '#') if word.is_empty() => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            (Quote::None, ' ' | '\t') => end_word(&mut word, &mut segment),
+            // `2>&1`, `>&2`
+            (Quote::None, '&') if word.ends_with(['>', '<']) => word.push('&'),
+            (Quote::None, '\n' | ';' | '&' | '|') => {
+                end_segment(&mut word, &mut segment, &mut segments)
+            }
+            (Quote::None, '(') => {
+                // `name() {` defines a function; otherwise a subshell
+                if chars.peek() == Some(&')') {
+                    chars.next();
+                    if segment.is_empty() && !word.is_empty() {
+                        functions.insert(std::mem::take(&mut word));
+                    }
+                }
+                end_segment(&mut word, &mut segment, &mut segments);
+            }
+            // Case patterns (`*.xz)`) keep their parenthesis, see `command_word`
+            (Quote::None, ')') => {
+                word.push(')');
+                end_word(&mut word, &mut segment);
+            }
+            (Quote::None, c) => word.push(c),
+        }
+    }
+    end_segment(&mut word, &mut segment, &mut segments);
+    (segments, functions)
+}
+
+/// The command a simple command runs: the first word after keywords,
+/// assignments, redirections and case patterns. `None` for `for`/`case`
+/// headers and words that are only known at run time.
+fn command_word(segment: &[String]) -> Option<(&str, &[String])> {
+    let mut words = segment;
+    loop {
+        let (first, rest) = words.split_first()?;
+        let is_assignment = first.split_once('=').is_some_and(|(name, _)| is_name(name));
+        let is_redirect = first
+            .trim_start_matches(|c: char| c.is_ascii_digit())
+            .starts_with(['<', '>']);
+        if matches!(first.as_str(), "for" | "case" | "in") {
+            return None;
+        } else if is_redirect {
+            // `>` alone takes the next word as its target
+            let bare = first.trim_start_matches(|c: char| {
+                c.is_ascii_digit() || c == '<' || c == '>' || c == '&'
+            });
+            words = if bare.is_empty() && !first.ends_with('&') {
+                rest.get(1..)?
+            } else {
+                rest
+            };
+        } else if SHELL_KEYWORDS.contains(&first.as_str())
+            || is_assignment
+            || first.ends_with(')')
+            || first == "exec"
+        {
+            words = rest;
+        } else if first.contains(['$', '`']) {
+            return None;
+        } else {
+            return Some((first, rest));
+        }
+    }
+}
+
+fn is_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Commands run by `script` (builtins and its own functions left out).
+pub fn init_commands(script: &str) -> InitCommands {
+    let (segments, functions) = shell_segments(script);
+    let mut found = InitCommands::default();
+    for segment in &segments {
+        if let Some(value) = segment.iter().find_map(|w| w.strip_prefix("PATH=")) {
+            if found.path.is_empty() && !value.contains('$') {
+                found.path = value.split(':').map(str::to_string).collect();
+            }
+        }
+        let Some((command, args)) = command_word(segment) else {
+            continue;
+        };
+        let name = command.rsplit('/').next().unwrap_or(command);
+        if name == "busybox" {
+            if let Some(applet) = args.first().filter(|a| !a.starts_with('-')) {
+                if !applet.contains(['$', '`']) {
+                    found.applets.insert(applet.clone());
+                }
+            }
+            found.commands.insert(command.to_string());
+        } else if !SHELL_BUILTINS.contains(&command) && !functions.contains(command) {
+            found.commands.insert(command.to_string());
+        }
+    }
+    found
+}
+
+/// Applets of `busybox`, from `busybox --list`.
+pub fn busybox_applets(busybox: &Path) -> Result<BTreeSet<String>> {
+    let output = Command::new(busybox)
+        .arg("--list")
+        .output()
+        .with_context(|| format!("Failed to run {}", busybox.display()))?;
+    if !output.status.success() {
+        bail!("busybox --list failed");
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Fail on commands the init script runs that the initramfs does not have.
+///
+/// `files` are the archive's paths (`bin/busybox`); bare command names are
+/// looked up in the script's `PATH` (default `/bin:/sbin:/usr/bin:/usr/sbin`).
+/// With `applets` (from [`busybox_applets`]), `busybox X` calls and the
+/// archive's links in the bin dirs must name real applets.
+pub fn check_init_commands(
+    name: &str,
+    commands: &InitCommands,
+    files: &BTreeSet<String>,
+    applets: Option<&BTreeSet<String>>,
+) -> Result<()> {
+    let default_path = ["/bin", "/sbin", "/usr/bin", "/usr/sbin"].map(str::to_string);
+    let path = if commands.path.is_empty() {
+        &default_path[..]
+    } else {
+        &commands.path[..]
+    };
+    let present = |command: &str| {
+        if command.contains('/') {
+            files.contains(command.trim_start_matches('/'))
+        } else {
+            path.iter()
+                .any(|dir| files.contains(&format!("{}/{}", dir.trim_matches('/'), command)))
+        }
+    };
+
+    let mut problems = Vec::new();
+    let missing: Vec<&str> = commands
+        .commands
+        .iter()
+        .map(String::as_str)
+        .filter(|c| !present(c))
+        .collect();
+    if !missing.is_empty() {
+        problems.push(format!(
+            "runs {} but the initramfs has no such command in {} \
+             (call it as `busybox NAME`, or add it to recinit's applet links)",
+            missing.join(", "),
+            path.join(":")
+        ));
+    }
+
+    if let Some(applets) = applets {
+        let unknown: Vec<&str> = commands
+            .applets
+            .iter()
+            .map(String::as_str)
+            .filter(|a| !applets.contains(*a))
+            .collect();
+        if !unknown.is_empty() {
+            problems.push(format!(
+                "calls busybox {} which this busybox does not provide",
+                unknown.join(", ")
+            ));
+        }
+        let links: Vec<&str> = files
+            .iter()
+            .filter_map(|f| {
+                let (dir, file) = f.rsplit_once('/')?;
+                let bin_dir = matches!(dir, "bin" | "sbin" | "usr/bin" | "usr/sbin");
+                (bin_dir && file != "busybox" && !applets.contains(file)).then_some(f.as_str())
+            })
+            .collect();
+        if !links.is_empty() {
+            problems.push(format!(
+                "has applet links busybox does not provide: {}",
+                links.join(", ")
+            ));
+        }
+    }
+
+    if !problems.is_empty() {
+        bail!("Init script {}: {}", name, problems.join("; "));
+    }
+    Ok(())
+}
+
+/// recinit's placeholders with the values it would use; `BOOT_MODULES` is
+/// computed by recinit, so a representative list stands in.
+pub fn recinit_vars(
+    iso_label: &str,
+    rootfs_path: &str,
+    boot_devices: &[&str],
+    live_overlay: &str,
+) -> Vec<(&'static str, String)> {
+    vec![
+        ("ISO_LABEL", iso_label.to_string()),
+        ("ROOTFS_PATH", rootfs_path.to_string()),
+        ("BOOT_DEVICES", boot_devices.join(" ")),
+        ("BOOT_MODULES", "virtio_blk sr_mod isofs".to_string()),
+        ("LIVE_OVERLAY_PATH", live_overlay.to_string()),
+        ("LIVE_OVERLAY_IMAGE_PATH", live_overlay.to_string()),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const TEMPLATE: &str = r#"#!/bin/busybox sh
+LABEL="{{ISO_LABEL}}"
+for dev in {{BOOT_DEVICES}}; do
+    busybox mount -t {{ROOTFS_FSTYPE}} "$dev" /rootfs && break
+done
+modprobe -a {{BOOT_MODULES}}
+"#;
+
+    fn vars() -> Vec<(&'static str, String)> {
+        let mut vars = recinit_vars(
+            "ACORNOS",
+            "live/filesystem.erofs",
+            &["/dev/sr0", "/dev/vda"],
+            "live/overlay",
+        );
+        vars.push(("ROOTFS_FSTYPE", "erofs".to_string()));
+        vars.extend(crate::artifact::persistence::template_vars());
+        vars.extend(crate::artifact::netinstall::template_vars());
+        vars
+    }
+
+    #[test]
+    fn test_placeholders_checked_and_rendered() {
+        let vars = vars();
+        let known: Vec<&str> = vars.iter().map(|(k, _)| *k).collect();
+        assert_eq!(
+            placeholders(TEMPLATE).into_iter().collect::<Vec<_>>(),
+            ["BOOT_DEVICES", "BOOT_MODULES", "ISO_LABEL", "ROOTFS_FSTYPE"]
+        );
+        check_placeholders("t", TEMPLATE, &known, &["ISO_LABEL", "BOOT_MODULES"]).unwrap();
+
+        let typo = TEMPLATE.replace("{{ISO_LABEL}}", "{{ISO_LABLE}}");
+        let err = check_placeholders("t", &typo, &known, &["ISO_LABEL"])
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("unknown placeholders {{ISO_LABLE}}"),
+            "{}",
+            err
+        );
+        assert!(
+            err.contains("required placeholders not found: {{ISO_LABEL}}"),
+            "{}",
+            err
+        );
+
+        let dropped = TEMPLATE.replace("{{BOOT_MODULES}}", "virtio_blk");
+        assert!(check_placeholders("t", &dropped, &known, &["BOOT_MODULES"]).is_err());
+
+        let rendered = render_template(TEMPLATE, &vars);
+        assert!(rendered.contains("for dev in /dev/sr0 /dev/vda; do"));
+        check_rendered("t", &rendered).unwrap();
+        let broken = render_template(&TEMPLATE.replace("{{ISO_LABEL}}", "{{ISO_LABEL}"), &vars);
+        assert!(check_rendered("t", &broken)
+            .unwrap_err()
+            .to_string()
+            .contains("{{ISO_LABEL}"));
+    }
+
+    #[test]
+    fn test_shell_syntax_and_real_templates() {
+        let dir = tempdir().unwrap();
+        let scratch = dir.path().join("init.check");
+        check_shell_syntax("t", &render_template(TEMPLATE, &vars()), None, &scratch).unwrap();
+        let err = check_shell_syntax("t", "if true; then\necho\n", None, &scratch).unwrap_err();
+        assert!(err.to_string().contains("invalid shell"));
+        assert!(!scratch.exists());
+
+        // The shipped live template passes with the variables the build fills
+        validate_init_template(
+            Path::new(env!("CARGO_MANIFEST_DIR")),
+            crate::artifact::initramfs::LIVE_TEMPLATE,
+            &vars(),
+            &[
+                "ISO_LABEL",
+                "ROOTFS_PATH",
+                "BOOT_MODULES",
+                "ROOTFS_FSTYPE",
+                "PERSIST_MODULES",
+                "PERSIST_DIR",
+            ],
+            None,
+            &scratch,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_init_commands_found_and_checked() {
+        let script = r#"#!/bin/busybox sh
+export PATH=/bin
+msg() {
+    busybox echo "initramfs: $1"
+}
+KVER=$(busybox ls /lib/modules/ 2>/dev/null | busybox head -1)
+for tty in ttyAMA0 ttyS0; do
+    if [ -c /dev/$tty ]; then
+        exec > /dev/$tty 2>&1 < /dev/$tty
+    fi
+done
+case "$MODPATH" in
+    *.xz) busybox xz -d -k "$MODPATH" 2>/dev/null; MODPATH="${MODPATH%.xz}" ;;
+esac
+[ -z "$ROOT_LABEL" ] && ROOT_LABEL="ACORNOS" # blkid in a comment
+DEV=`blkid -L "$ROOT_LABEL"`
+msg "found $DEV" || modprobe \
+    loop
+'/bin/busybox' "switch_root" /newroot "$INIT_PATH"
+exec busybox sh
+"#;
+        let found = init_commands(script);
+        assert_eq!(found.path, ["/bin"]);
+        assert_eq!(
+            found
+                .commands
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>(),
+            ["/bin/busybox", "blkid", "busybox", "modprobe"]
+        );
+        assert_eq!(
+            found.applets.iter().map(String::as_str).collect::<Vec<_>>(),
+            ["echo", "head", "ls", "sh", "switch_root", "xz"]
+        );
+
+        let files: BTreeSet<String> = ["init", "bin/busybox", "bin/modprobe", "bin/nosuch"]
+            .map(str::to_string)
+            .into();
+        let applets: BTreeSet<String> = ["echo", "head", "ls", "modprobe", "sh", "switch_root"]
+            .map(str::to_string)
+            .into();
+        let err = check_init_commands("init", &found, &files, Some(&applets))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("runs blkid but the initramfs has no such command in /bin"),
+            "{}",
+            err
+        );
+        assert!(err.contains("calls busybox xz which"), "{}", err);
+        assert!(
+            err.contains("applet links busybox does not provide: bin/nosuch"),
+            "{}",
+            err
+        );
+
+        // The shipped live template only runs what the initramfs has
+        let template = fs::read_to_string(
+            Path::new(env!("CARGO_MANIFEST_DIR")).join(crate::artifact::initramfs::LIVE_TEMPLATE),
+        )
+        .unwrap();
+        let found = init_commands(&render_template(&template, &vars()));
+        assert_eq!(
+            found
+                .commands
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>(),
+            ["/sbin/e2fsck", "busybox"]
+        );
+        let files: BTreeSet<String> = ["bin/busybox".to_string(), "sbin/e2fsck".to_string()].into();
+        check_init_commands("init", &found, &files, None).unwrap();
+    }
+}
@@ -9,44 +9,331 @@
 //! 2. Kernel extracts initramfs to rootfs, runs /init
 //! 3. /init (busybox sh script):
 //!    a. Mount /proc, /sys, /dev
-//!    b. Find boot device by LABEL=ACORNOS
+//!    b. Find boot device by LABEL= (ACORNOS unless built with --label)
 //!    c. Mount ISO read-only
-//!    d. Mount filesystem.erofs via loop device
-//!    e. Create overlay: EROFS (lower) + tmpfs (upper)
+//!    d. Mount the rootfs image (EROFS or squashfs) via loop device
+//!    e. Create overlay: rootfs (lower) + tmpfs (upper), or the
+//!       persistence partition with `acorn.persist=` (see `persistence`)
 //!    f. switch_root to overlay
 //! 4. OpenRC (PID 1) takes over
 //! ```
+//!
+//! # Compression
+//!
+//! gzip by default; `--initramfs-compression xz|zstd` recompresses recinit's
+//! gzip archive (the kernel decompresses all three). The output is named by
+//! compressor (`initramfs-live.cpio.gz|.xz|.zst`) and the choice recorded in
+//! the output directory, like the rootfs format, so the ISO, rebuild checks
+//! and manifest pick up the file that was built.
+//!
+//! `e2fsck` and the ext4 modules for the persistence partition are appended
+//! to recinit's archive as a second cpio, as the installed initramfs does.
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::io::Write;
 use std::path::Path;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
 
+use super::full_initramfs::append_cpio;
+use super::init_template::{
+    busybox_applets, check_init_commands, init_commands, recinit_vars, validate_init_template,
+};
+use super::iso_label::IsoLabel;
+use super::netinstall;
+use super::persistence;
+use super::rootfs::RootfsFormat;
+use crate::arch::Arch;
+use crate::paths::OutputPaths;
+use distro_builder::alpine::extract::ExtractPaths;
+use distro_builder::process::which;
 use distro_spec::acorn::{
-    BOOT_DEVICE_PROBE_ORDER, CPIO_GZIP_LEVEL, INITRAMFS_LIVE_OUTPUT, ISO_LABEL,
-    LIVE_OVERLAY_ISO_PATH, ROOTFS_ISO_PATH,
+    BOOT_DEVICE_PROBE_ORDER, CPIO_GZIP_LEVEL, INITRAMFS_LIVE_OUTPUT, LIVE_OVERLAY_ISO_PATH,
 };
 use recinit::{download_and_cache_busybox, find_kernel_modules_dir, ModulePreset, TinyConfig};
 
-/// Build the tiny initramfs using recinit.
-pub fn build_tiny_initramfs(base_dir: &Path) -> Result<()> {
-    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
+/// Records how the current live initramfs is compressed.
+pub const COMPRESSION_MARKER: &str = ".initramfs-compression";
+
+/// Compressor for the live initramfs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compressor {
+    #[default]
+    Gzip,
+    Xz,
+    Zstd,
+}
+
+impl Compressor {
+    pub const ALL: [Compressor; 3] = [Compressor::Gzip, Compressor::Xz, Compressor::Zstd];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Xz => "xz",
+            Self::Zstd => "zstd",
+        }
+    }
+
+    /// File extension after `.cpio`.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Gzip => "gz",
+            Self::Xz => "xz",
+            Self::Zstd => "zst",
+        }
+    }
+
+    /// Host binary that does the compression.
+    pub fn tool(self) -> &'static str {
+        self.name()
+    }
+
+    /// Package providing [`Compressor::tool`].
+    pub fn install_cmd(self) -> &'static str {
+        match self {
+            Self::Gzip => "sudo dnf install gzip",
+            Self::Xz => "sudo dnf install xz",
+            Self::Zstd => "sudo dnf install zstd",
+        }
+    }
+
+    pub fn default_level(self) -> u32 {
+        match self {
+            Self::Gzip => CPIO_GZIP_LEVEL,
+            Self::Xz => 6,
+            Self::Zstd => 19,
+        }
+    }
+
+    /// Highest level accepted (zstd beyond 19 needs `--ultra` and more
+    /// memory than the early boot environment should spend).
+    pub fn max_level(self) -> u32 {
+        match self {
+            Self::Gzip | Self::Xz => 9,
+            Self::Zstd => 19,
+        }
+    }
+
+    /// Compressor arguments to write `level` output to stdout. xz must use
+    /// CRC32 checks: the kernel's decoder does not do CRC64.
+    fn args(self, level: u32) -> Vec<String> {
+        let level = format!("-{}", level);
+        match self {
+            Self::Gzip => vec![level, "-c".to_string()],
+            Self::Xz => vec!["--check=crc32".to_string(), level, "-c".to_string()],
+            Self::Zstd => vec![level, "-q".to_string(), "-c".to_string()],
+        }
+    }
+}
+
+impl fmt::Display for Compressor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Compressor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "gz" => Ok(Self::Gzip),
+            "xz" => Ok(Self::Xz),
+            "zstd" | "zst" => Ok(Self::Zstd),
+            other => bail!(
+                "Unknown initramfs compression '{}' (expected gzip, xz or zstd)",
+                other
+            ),
+        }
+    }
+}
+
+/// Compressor and level of the live initramfs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InitramfsCompression {
+    pub compressor: Compressor,
+    pub level: u32,
+}
+
+impl Default for InitramfsCompression {
+    fn default() -> Self {
+        Self {
+            compressor: Compressor::Gzip,
+            level: CPIO_GZIP_LEVEL,
+        }
+    }
+}
+
+impl InitramfsCompression {
+    /// `compressor` at `level` (its default if `None`).
+    pub fn new(compressor: Compressor, level: Option<u32>) -> Result<Self> {
+        let level = level.unwrap_or_else(|| compressor.default_level());
+        if !(1..=compressor.max_level()).contains(&level) {
+            bail!(
+                "{} level {} out of range (1-{})",
+                compressor,
+                level,
+                compressor.max_level()
+            );
+        }
+        Ok(Self { compressor, level })
+    }
+
+    /// Live initramfs file name in the output directory.
+    pub fn output_name(self) -> String {
+        match self.compressor {
+            Compressor::Gzip => INITRAMFS_LIVE_OUTPUT.to_string(),
+            other => format!(
+                "{}.{}",
+                INITRAMFS_LIVE_OUTPUT
+                    .strip_suffix(".gz")
+                    .unwrap_or(INITRAMFS_LIVE_OUTPUT),
+                other.extension()
+            ),
+        }
+    }
+
+    /// Artifact store kind (gzip keeps the original `initramfs`).
+    pub fn artifact_kind(self) -> &'static str {
+        match self.compressor {
+            Compressor::Gzip => "initramfs",
+            Compressor::Xz => "initramfs_xz",
+            Compressor::Zstd => "initramfs_zstd",
+        }
+    }
+
+    /// Compression of the last built live initramfs (gzip if none recorded).
+    pub fn load(output_dir: &Path) -> Self {
+        fs::read_to_string(output_dir.join(COMPRESSION_MARKER))
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// Record this as the compression of the current live initramfs.
+    pub fn save(self, output_dir: &Path) -> Result<()> {
+        fs::write(output_dir.join(COMPRESSION_MARKER), self.to_string())?;
+        Ok(())
+    }
+}
 
-    // Download/cache busybox
+impl fmt::Display for InitramfsCompression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.compressor, self.level)
+    }
+}
+
+/// `zstd:19`, or just `zstd` for its default level.
+impl FromStr for InitramfsCompression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (compressor, level) = match s.trim().split_once(':') {
+            Some((compressor, level)) => (
+                compressor,
+                Some(
+                    level
+                        .parse()
+                        .with_context(|| format!("Invalid compression level '{}'", level))?,
+                ),
+            ),
+            None => (s.trim(), None),
+        };
+        Self::new(compressor.parse()?, level)
+    }
+}
+
+/// Init template for the live boot path.
+pub const LIVE_TEMPLATE: &str = "profile/init_tiny.template";
+
+/// Placeholders the live template must keep.
+const LIVE_REQUIRED: &[&str] = &[
+    "ISO_LABEL",
+    "ROOTFS_PATH",
+    "ROOTFS_FSTYPE",
+    "BOOT_MODULES",
+    "BOOT_DEVICES",
+    "LIVE_OVERLAY_PATH",
+    "PERSIST_MODULES",
+    "PERSIST_DIR",
+    "NET_MODULES",
+];
+
+/// Build the tiny initramfs using recinit, compressed with `compression`,
+/// looking for the boot medium by `label`.
+pub fn build_tiny_initramfs(
+    base_dir: &Path,
+    compression: InitramfsCompression,
+    label: &IsoLabel,
+) -> Result<()> {
+    let output = OutputPaths::for_base(base_dir);
+    let output_dir = output.dir.clone();
+    let compressor = compression.compressor;
+    if which(compressor.tool()).is_none() {
+        bail!(
+            "{} not found (needed for --initramfs-compression {}).\nInstall: {}",
+            compressor.tool(),
+            compressor,
+            compressor.install_cmd()
+        );
+    }
+
+    // Download/cache busybox (resumable; recinit then uses the cached copy)
     let downloads_dir = base_dir.join("downloads");
+    crate::download::ensure_busybox(base_dir, crate::arch::Arch::from_env()?)?;
     let busybox_path = download_and_cache_busybox(&downloads_dir)?;
 
     // Find kernel modules directory
-    let modules_base = output_dir.join("staging/usr/lib/modules");
+    let modules_base = output.kernel_modules();
     let modules_dir = find_kernel_modules_dir(&modules_base)?;
 
-    let output_path = output_dir.join(INITRAMFS_LIVE_OUTPUT);
+    // recinit always gzips; other compressors recompress its archive
+    let output_path = output.live_initramfs(compression);
+    let base_tmp = output_dir.join(format!("{}.base.tmp", INITRAMFS_LIVE_OUTPUT));
+    let gzip_path = match compressor {
+        Compressor::Gzip => output_path.clone(),
+        _ => output_dir.join(format!("{}.tmp", INITRAMFS_LIVE_OUTPUT)),
+    };
+
+    // Mount whatever format the rootfs was built in
+    let format = RootfsFormat::load(&output_dir);
 
+    let mut vars = recinit_vars(
+        label.as_str(),
+        &format.iso_path(),
+        BOOT_DEVICE_PROBE_ORDER,
+        LIVE_OVERLAY_ISO_PATH,
+    );
+    vars.push(("ROOTFS_FSTYPE", format.fs_type().to_string()));
+    vars.extend(persistence::template_vars());
+    vars.extend(netinstall::template_vars());
+    let script = validate_init_template(
+        base_dir,
+        LIVE_TEMPLATE,
+        &vars,
+        LIVE_REQUIRED,
+        Some(&busybox_path),
+        &output_dir.join("init_tiny.check"),
+    )?;
+
+    let mut extra_template_vars = vec![("ROOTFS_FSTYPE".to_string(), format.fs_type().to_string())];
+    extra_template_vars.extend(
+        persistence::template_vars()
+            .into_iter()
+            .chain(netinstall::template_vars())
+            .map(|(key, value)| (key.to_string(), value)),
+    );
     let config = TinyConfig {
-        modules_dir,
-        busybox_path,
-        template_path: base_dir.join("profile/init_tiny.template"),
-        output: output_path.clone(),
-        iso_label: ISO_LABEL.to_string(),
-        rootfs_path: ROOTFS_ISO_PATH.to_string(),
+        modules_dir: modules_dir.clone(),
+        busybox_path: busybox_path.clone(),
+        template_path: base_dir.join(LIVE_TEMPLATE),
+        output: base_tmp.clone(),
+        iso_label: label.to_string(),
+        rootfs_path: format.iso_path(),
         live_overlay_image_path: Some(LIVE_OVERLAY_ISO_PATH.to_string()),
         live_overlay_path: Some(LIVE_OVERLAY_ISO_PATH.to_string()),
         boot_devices: BOOT_DEVICE_PROBE_ORDER
@@ -54,24 +341,93 @@ pub fn build_tiny_initramfs(base_dir: &Path) -> Result<()> {
             .map(|s| s.to_string())
             .collect(),
         module_preset: ModulePreset::Live,
-        gzip_level: CPIO_GZIP_LEVEL,
+        gzip_level: match compressor {
+            Compressor::Gzip => compression.level,
+            _ => CPIO_GZIP_LEVEL,
+        },
         check_builtin: true,
-        extra_template_vars: Vec::new(),
+        extra_template_vars,
     };
 
     recinit::build_tiny_initramfs(&config, true)?;
 
+    // Persistence: e2fsck and the ext4 modules, appended as a second archive
+    let rootfs = ExtractPaths::new(base_dir).rootfs;
+    let extras = persistence::initramfs_entries(&rootfs, &modules_dir)
+        .context("Failed to collect the persistence tools (run 'acornos extract' first)")?;
+    let extra_paths: Vec<&str> = extras.iter().map(|e| e.path()).collect();
+
     // Verify the built initramfs
-    verify_initramfs(&output_path)?;
+    verify_initramfs(&base_tmp, &script, &busybox_path, &extra_paths)?;
+    append_cpio(&base_tmp, &extras, &gzip_path)?;
+
+    if gzip_path != output_path {
+        let result = recompress(&gzip_path, &output_path, compression);
+        let _ = fs::remove_file(&gzip_path);
+        result?;
+        log::info!(
+            "  Compressed with {} -{}: {}",
+            compressor,
+            compression.level,
+            output_path.display()
+        );
+    }
+    compression.save(&output_dir)?;
+    label.save(&output_dir)?;
 
     Ok(())
 }
 
-/// Verify the initramfs contains essential files.
-fn verify_initramfs(path: &Path) -> Result<()> {
-    use fsdbg::cpio::CpioReader;
+/// Recompress the gzip cpio archive `gz` into `output` with `compression`.
+pub(super) fn recompress(
+    gz: &Path,
+    output: &Path,
+    compression: InitramfsCompression,
+) -> Result<()> {
+    let decompressed = Command::new("gzip")
+        .arg("-dc")
+        .arg(gz)
+        .output()
+        .context("Failed to run gzip. Install: sudo dnf install gzip")?;
+    if !decompressed.status.success() {
+        bail!(
+            "gzip -dc {} failed: {}",
+            gz.display(),
+            String::from_utf8_lossy(&decompressed.stderr).trim()
+        );
+    }
 
-    print!("  Verifying initramfs... ");
+    let compressor = compression.compressor;
+    let mut child = Command::new(compressor.tool())
+        .args(compressor.args(compression.level))
+        .stdin(Stdio::piped())
+        .stdout(fs::File::create(output)?)
+        .spawn()
+        .with_context(|| {
+            format!(
+                "Failed to run {}. Install: {}",
+                compressor.tool(),
+                compressor.install_cmd()
+            )
+        })?;
+    child
+        .stdin
+        .take()
+        .context("compressor stdin unavailable")?
+        .write_all(&decompressed.stdout)?;
+    let status = child.wait()?;
+    if !status.success() {
+        let _ = fs::remove_file(output);
+        bail!("{} failed with {}", compressor.tool(), status);
+    }
+    Ok(())
+}
+
+/// Verify the initramfs contains essential files and every command the
+/// rendered init `script` runs, counting the `extra` paths appended after
+/// recinit.
+fn verify_initramfs(path: &Path, script: &str, busybox: &Path, extra: &[&str]) -> Result<()> {
+    use fsdbg::cpio::CpioReader;
 
     let reader = CpioReader::open(path)
         .map_err(|e| anyhow::anyhow!("Failed to open initramfs for verification: {}", e))?;
@@ -99,12 +455,31 @@ fn verify_initramfs(path: &Path) -> Result<()> {
     // }
 
     if missing.is_empty() {
-        println!("OK");
+        let files: BTreeSet<String> = reader
+            .entries()
+            .iter()
+            .map(|e| {
+                e.path
+                    .trim_start_matches("./")
+                    .trim_start_matches('/')
+                    .to_string()
+            })
+            .chain(extra.iter().map(|p| p.to_string()))
+            .collect();
+        // The target busybox only lists its applets when it runs here
+        let applets = if Arch::from_env().is_ok_and(Arch::is_host) {
+            Some(busybox_applets(busybox)?)
+        } else {
+            log::info!("  [SKIP] busybox applet check (target busybox does not run on this host)");
+            None
+        };
+        check_init_commands("/init", &init_commands(script), &files, applets.as_ref())?;
+        log::info!("  Verifying initramfs... OK");
         Ok(())
     } else {
-        println!("FAILED");
+        log::info!("  Verifying initramfs... FAILED");
         for item in &missing {
-            println!("    ✗ {} - Missing", item);
+            log::warn!("✗ {} - Missing", item);
         }
         bail!(
             "Initramfs verification FAILED: {} missing items.\n\
@@ -113,3 +488,67 @@ fn verify_initramfs(path: &Path) -> Result<()> {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_compression_names_and_levels() {
+        let default = InitramfsCompression::default();
+        assert_eq!(default.output_name(), INITRAMFS_LIVE_OUTPUT);
+        assert_eq!(default.artifact_kind(), "initramfs");
+
+        let zstd: InitramfsCompression = "zstd".parse().unwrap();
+        assert_eq!(zstd.level, 19);
+        assert_eq!(zstd.output_name(), "initramfs-live.cpio.zst");
+        assert_eq!(
+            zstd.to_string().parse::<InitramfsCompression>().unwrap(),
+            zstd
+        );
+
+        let xz = InitramfsCompression::new(Compressor::Xz, Some(9)).unwrap();
+        assert_eq!(xz.output_name(), "initramfs-live.cpio.xz");
+        assert_eq!("xz:9".parse::<InitramfsCompression>().unwrap(), xz);
+
+        assert!(InitramfsCompression::new(Compressor::Gzip, Some(10)).is_err());
+        assert!(InitramfsCompression::new(Compressor::Zstd, Some(0)).is_err());
+        assert!("lz4".parse::<Compressor>().is_err());
+        assert!("xz:fast".parse::<InitramfsCompression>().is_err());
+
+        let dir = tempdir().unwrap();
+        assert_eq!(InitramfsCompression::load(dir.path()), default);
+        xz.save(dir.path()).unwrap();
+        assert_eq!(InitramfsCompression::load(dir.path()), xz);
+    }
+
+    #[test]
+    fn test_recompress_round_trip() {
+        let dir = tempdir().unwrap();
+        let data = b"070701 cpio payload".repeat(64);
+        let gz = dir.path().join("initramfs.cpio.gz");
+        fs::write(dir.path().join("initramfs.cpio"), &data).unwrap();
+        let status = Command::new("gzip")
+            .arg(dir.path().join("initramfs.cpio"))
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        for compressor in [Compressor::Xz, Compressor::Zstd] {
+            if which(compressor.tool()).is_none() {
+                continue;
+            }
+            let compression = InitramfsCompression::new(compressor, None).unwrap();
+            let output = dir.path().join(compression.output_name());
+            recompress(&gz, &output, compression).unwrap();
+            let back = Command::new(compressor.tool())
+                .args(["-dc"])
+                .arg(&output)
+                .output()
+                .unwrap();
+            assert!(back.status.success());
+            assert_eq!(back.stdout, data);
+        }
+    }
+}
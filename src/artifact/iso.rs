@@ -6,40 +6,149 @@
 //! Boot flow:
 //! 1. systemd-boot discovers UKIs in EFI/Linux/
 //! 2. UKI loads kernel + tiny initramfs
-//! 3. init_tiny mounts EROFS as lower layer
+//! 3. init_tiny mounts the rootfs image (EROFS or squashfs) as lower layer
 //! 4. init_tiny mounts /live/overlay from ISO as middle layer
-//! 5. init_tiny mounts tmpfs as upper layer (for writes)
+//! 5. init_tiny mounts tmpfs as upper layer (for writes), or the persistence
+//!    partition for the "AcornOS (Persistent)" entry
 //! 6. switch_root -> OpenRC
+//!
+//! The netinstall variant (see `netinstall`) has no rootfs image or live
+//! overlay; its entries boot the installer in the initramfs instead.
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use std::env;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+use super::cmdline::CmdlineEdit;
+use super::full_initramfs::INITRAMFS_INSTALLED_ISO_PATH;
+use super::grub_bios;
+use super::grub_efi;
+use super::initramfs::InitramfsCompression;
+use super::iso_extra::{apply_iso_changes, collect_iso_extras, IsoExtra};
+use super::iso_label::IsoLabel;
+use super::live_profile::{self, LiveProfile};
+use super::live_ssh;
+use super::live_user::LiveUser;
+use super::netinstall::{build_netinstall_initramfs, IsoVariant, NETINSTALL_CMDLINE};
+use super::rootfs::RootfsFormat;
+use super::uki;
+use crate::arch::Arch;
+use crate::build_info::BuildInfo;
+use crate::component::definitions::{LIVE_OVERLAY, LIVE_OVERLAY_RELEASE, LIVE_USER};
+use crate::component::executor::execute_into;
+use crate::component::Component;
+use crate::paths::OutputPaths;
+use distro_builder::alpine::extract::ExtractPaths;
 use distro_builder::artifact::live_overlay::{
     create_openrc_live_overlay, InittabVariant, LiveOverlayConfig,
 };
-use distro_spec::acorn::{
-    INITRAMFS_LIVE_OUTPUT, ISO_FILENAME, ISO_LABEL, OS_ID, OS_NAME, OS_VERSION, ROOTFS_NAME,
-    UKI_ENTRIES,
-};
+use distro_spec::acorn::{OS_ID, OS_NAME, OS_VERSION};
+
+/// Options for ISO creation.
+#[derive(Debug, Clone, Default)]
+pub struct IsoOptions {
+    /// Extras from the CLI (`profile/iso-extra/` is always included).
+    pub extras: Vec<IsoExtra>,
+    /// User that tty1 autologins as in the live session.
+    pub live_user: LiveUser,
+    /// Target architecture (non-x86_64 boots through GRUB, see `grub_efi`).
+    pub arch: Arch,
+    /// Also build the installed-system UKIs into the live system (needs `ukify`).
+    pub with_ukis: bool,
+    /// Add a legacy BIOS boot entry next to UEFI (x86_64, see `grub_bios`).
+    pub bios: bool,
+    /// Let `acornos run --ssh` log in as root (see `live_ssh`).
+    pub ssh: bool,
+    /// Volume label; the live initramfs must have been built for it.
+    pub label: IsoLabel,
+    /// Kernel cmdline edits for every live entry (`--cmdline-append`).
+    pub cmdline: CmdlineEdit,
+    /// Live ISO, or the netinstall ISO without the rootfs image.
+    pub variant: IsoVariant,
+    /// Testing (serial autologin, test markers) or release live session.
+    pub profile: LiveProfile,
+}
+
+impl IsoOptions {
+    /// Cmdline edits for the boot entries: `--cmdline-append`, plus
+    /// `acorn.netinstall=1` for the netinstall variant.
+    pub fn boot_cmdline(&self) -> CmdlineEdit {
+        let mut cmdline = self.cmdline.clone();
+        if self.variant == IsoVariant::Netinstall {
+            cmdline.append.push(NETINSTALL_CMDLINE.to_string());
+        }
+        cmdline
+    }
+}
+
+/// Artifacts the ISO is made from, as the last builds left them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct IsoInputs {
+    kernel: PathBuf,
+    /// Live initramfs, in the compression it was last built with.
+    initramfs: PathBuf,
+    format: RootfsFormat,
+    /// Rootfs image, in the format it was last built in.
+    rootfs: PathBuf,
+    installed_initramfs: PathBuf,
+    rootfs_staging: PathBuf,
+    live_overlay: PathBuf,
+}
+
+impl IsoInputs {
+    fn load(paths: &OutputPaths) -> Self {
+        let format = RootfsFormat::load(&paths.dir);
+        Self {
+            kernel: paths.kernel(),
+            initramfs: paths.live_initramfs(InitramfsCompression::load(&paths.dir)),
+            format,
+            rootfs: paths.rootfs_image(format),
+            installed_initramfs: paths.installed_initramfs(),
+            rootfs_staging: paths.rootfs_staging(),
+            live_overlay: paths.live_overlay(),
+        }
+    }
+}
 
 /// Create ISO using reciso with systemd-boot + UKIs.
 pub fn create_iso(base_dir: &Path) -> Result<()> {
-    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
-    let kernel = output_dir.join("staging/boot/vmlinuz");
-    let initramfs = output_dir.join(INITRAMFS_LIVE_OUTPUT);
-    let rootfs = output_dir.join(ROOTFS_NAME);
-    let label = env::var("ISO_LABEL").unwrap_or_else(|_| ISO_LABEL.to_string());
-    let iso_output = output_dir.join(ISO_FILENAME);
-    let iso_tmp = output_dir.join(format!("{}.tmp", ISO_FILENAME));
+    create_iso_with(base_dir, &IsoOptions::default())
+}
 
-    println!("=== Building AcornOS ISO ===\n");
+/// Create ISO with the given options.
+pub fn create_iso_with(base_dir: &Path, options: &IsoOptions) -> Result<()> {
+    let paths = OutputPaths::for_base(base_dir);
+    let output_dir = paths.dir.clone();
+    let inputs = IsoInputs::load(&paths);
+    let IsoInputs {
+        kernel,
+        mut initramfs,
+        format,
+        rootfs,
+        installed_initramfs,
+        live_overlay,
+        ..
+    } = inputs.clone();
+    let label = options.label.as_str();
+    let netinstall = options.variant == IsoVariant::Netinstall;
+    let iso_filename = options.variant.iso_filename();
+    let iso_output = paths.iso_variant(options.variant);
+    let iso_tmp = output_dir.join(format!("{}.tmp", iso_filename));
+    let cmdline = options.boot_cmdline();
+    let reproducible = crate::reproducible::epoch()?;
+
+    if netinstall {
+        log::info!("=== Building AcornOS Netinstall ISO ===\n");
+    } else {
+        log::info!("=== Building AcornOS ISO ===\n");
+    }
 
     // Validate inputs
-    if !rootfs.exists() {
+    if !netinstall && !rootfs.exists() {
         bail!(
-            "EROFS rootfs not found at {}.\nRun 'acornos build rootfs' first.",
+            "{} rootfs not found at {}.\nRun 'acornos build rootfs' first.",
+            format.fs_type().to_uppercase(),
             rootfs.display()
         );
     }
@@ -55,84 +164,301 @@ pub fn create_iso(base_dir: &Path) -> Result<()> {
             kernel.display()
         );
     }
+    let built_for = IsoLabel::load(&output_dir);
+    if built_for != options.label {
+        bail!(
+            "Live initramfs looks for the ISO label {}, not {}; the ISO would not boot.\n\
+             Run 'acornos initramfs --label {}' first.",
+            built_for,
+            options.label,
+            options.label
+        );
+    }
 
-    // Create live overlay
-    create_live_overlay(&output_dir)?;
+    // Resolve extras up front so bad specs fail before the expensive build
+    let mut extras = collect_iso_extras(base_dir, &options.extras)?;
 
-    // Build reciso config — systemd-boot + UKIs (write to .tmp for atomicity)
-    let mut config = reciso::IsoConfig::new(&kernel, &initramfs, &rootfs, &label, &iso_tmp)
-        .with_os_release(OS_NAME, OS_ID, OS_VERSION)
-        .with_overlay(output_dir.join("live-overlay"));
+    if options.bios {
+        if options.arch != Arch::X86_64 {
+            bail!(
+                "--bios is x86_64-only ({} has no legacy BIOS)",
+                options.arch
+            );
+        }
+        grub_bios::check_bios_prerequisites()?;
+    }
 
-    // Add UKI entries from distro-spec
-    for entry in UKI_ENTRIES {
+    // The rest only goes into the live system
+    if netinstall && (options.with_ukis || options.ssh) {
+        bail!("--with-ukis and --ssh need the live system; the netinstall ISO has none");
+    }
+    if options.profile == LiveProfile::Release {
+        if netinstall {
+            bail!("--profile release needs the live system; the netinstall ISO has none");
+        }
+        if options.live_user == LiveUser::None {
+            bail!("--profile release autologins only the live user; it needs --live-user live");
+        }
+    }
+
+    // Installed-system initramfs for recstrap, when it has been built
+    if options.with_ukis {
+        if options.arch != Arch::X86_64 {
+            bail!(
+                "--with-ukis is x86_64-only ({} boots through GRUB)",
+                options.arch
+            );
+        }
+        uki::check_uki_prerequisites()?;
+        if !installed_initramfs.exists() {
+            bail!(
+                "Installed UKIs need the installed initramfs at {}.\nRun 'acornos initramfs --installed' first.",
+                installed_initramfs.display()
+            );
+        }
+    }
+    if netinstall {
+        // Nothing on the ISO runs recstrap
+    } else if installed_initramfs.exists() {
+        extras.push(IsoExtra {
+            src: installed_initramfs.clone(),
+            dest: INITRAMFS_INSTALLED_ISO_PATH.to_string(),
+        });
+    } else {
+        log::info!("  [SKIP] Installed initramfs not built (run 'acornos initramfs --installed')");
+    }
+
+    // Build provenance for the booted system (the ISO's own hash can't be in it)
+    extras.push(IsoExtra {
+        src: crate::manifest::write_manifest(base_dir, false)?,
+        dest: crate::manifest::MANIFEST_ISO_PATH.to_string(),
+    });
+    if !netinstall {
+        extras.push(IsoExtra {
+            src: live_profile::write_marker(&output_dir, options.profile)?,
+            dest: live_profile::PROFILE_ISO_PATH.to_string(),
+        });
+    }
+
+    if netinstall {
+        // The installer, apk.static and the NIC drivers ride on the live initramfs
+        initramfs = build_netinstall_initramfs(base_dir, options.arch, &initramfs)?;
+    } else {
+        prepare_live_system(base_dir, options, &output_dir, &inputs)?;
+    }
+
+    // Build reciso config — systemd-boot + UKIs (write to .tmp for atomicity).
+    // reciso always packs a rootfs image: the netinstall ISO gets an empty
+    // one, removed again below.
+    let rootfs = if netinstall {
+        let placeholder = output_dir
+            .join("netinstall-rootfs")
+            .join(format.image_name());
+        fs::create_dir_all(placeholder.parent().unwrap_or(&output_dir))?;
+        fs::write(&placeholder, "")?;
+        placeholder
+    } else {
+        rootfs
+    };
+    let mut config = reciso::IsoConfig::new(&kernel, &initramfs, &rootfs, label, &iso_tmp)
+        .with_os_release(OS_NAME, OS_ID, OS_VERSION);
+    if !netinstall {
+        config = config.with_overlay(live_overlay.clone());
+    }
+
+    // Add UKI entries from distro-spec, plus the persistent one
+    for entry in uki::live_entries_with(&cmdline) {
         config.ukis.push(reciso::UkiSource::Build {
             name: entry.name.to_string(),
-            extra_cmdline: entry.extra_cmdline.to_string(),
+            extra_cmdline: entry.extra_cmdline,
             filename: entry.filename.to_string(),
         });
     }
 
     reciso::create_iso(&config)?;
 
+    // Add extra payloads after the standard artifacts (boot images replayed),
+    // and the version and build id as the volume application id
+    let removals = if netinstall {
+        vec![format.iso_path()]
+    } else {
+        Vec::new()
+    };
+    let application_id = BuildInfo::detect(base_dir).application_id();
+    log::info!(
+        "  Adding {} ISO extra(s), application id '{}'...",
+        extras.len(),
+        application_id
+    );
+    let extras_tmp = output_dir.join(format!("{}.extras.tmp", iso_filename));
+    let result = apply_iso_changes(
+        &iso_tmp,
+        &extras_tmp,
+        &extras,
+        &removals,
+        Some(&application_id),
+    );
+    let _ = fs::remove_file(&iso_tmp);
+    result?;
+    fs::rename(&extras_tmp, &iso_tmp)?;
+
+    // Non-x86_64: replace systemd-boot with a standalone GRUB for the arch
+    if options.arch != Arch::X86_64 {
+        log::info!("  Adding {} GRUB EFI boot...", options.arch);
+        let work = output_dir.join(format!("grub-{}", options.arch));
+        let rootfs_dir = ExtractPaths::new(base_dir).rootfs;
+        let loader = grub_efi::build_grub_efi(&rootfs_dir, options.arch, label, &cmdline, &work)?;
+        let efi_image = work.join("efiboot.img");
+        grub_efi::build_efi_image(&loader, options.arch, &efi_image)?;
+        let grub_tmp = output_dir.join(format!("{}.grub.tmp", iso_filename));
+        let result = grub_efi::add_grub_boot(
+            &iso_tmp,
+            &grub_tmp,
+            options.arch,
+            &kernel,
+            &initramfs,
+            &loader,
+            &efi_image,
+        );
+        let _ = fs::remove_file(&iso_tmp);
+        result?;
+        fs::rename(&grub_tmp, &iso_tmp)?;
+    } else if options.bios {
+        log::info!("  Adding legacy BIOS boot...");
+        let work = output_dir.join("grub-bios");
+        let boot = grub_bios::prepare_bios_boot(&iso_tmp, label, &cmdline, &work)?;
+        let bios_tmp = output_dir.join(format!("{}.bios.tmp", iso_filename));
+        let result = grub_bios::add_bios_boot(&iso_tmp, &bios_tmp, &kernel, &initramfs, &boot);
+        let _ = fs::remove_file(&iso_tmp);
+        result?;
+        fs::rename(&bios_tmp, &iso_tmp)?;
+    }
+
+    // Last pass, after every other xorriso run: all dates SOURCE_DATE_EPOCH
+    if let Some(epoch) = reproducible {
+        log::info!("  Setting all ISO dates to {} (--reproducible)...", epoch);
+        let dates_tmp = output_dir.join(format!("{}.dates.tmp", iso_filename));
+        let result = crate::reproducible::normalize_iso(&iso_tmp, &dates_tmp, epoch);
+        let _ = fs::remove_file(&iso_tmp);
+        result?;
+        fs::rename(&dates_tmp, &iso_tmp)?;
+    }
+
     // Atomic rename to final destination
     fs::rename(&iso_tmp, &iso_output)?;
 
     // Verify ISO contents
-    verify_iso(&iso_output)?;
+    verify_iso(&iso_output, format, options)?;
 
-    print_iso_summary(&iso_output);
+    // Checksum sidecar (+ signature when ACORNOS_SIGNING_KEY is set)
+    crate::signing::finalize_release(&crate::signing::SystemRunner, &iso_output)?;
+
+    crate::rebuild::cache_iso_options_hash(base_dir, options);
+
+    print_iso_summary(&iso_output, &extras);
+    Ok(())
+}
+
+/// Live overlay (with `--ssh` keys and `--with-ukis` UKIs), package
+/// manifest and build environment for the live system.
+fn prepare_live_system(
+    base_dir: &Path,
+    options: &IsoOptions,
+    output_dir: &Path,
+    inputs: &IsoInputs,
+) -> Result<()> {
+    let live_overlay = &inputs.live_overlay;
+    create_live_overlay(base_dir, output_dir, options.live_user, options.profile)?;
+
+    // SSH access for `acornos run --ssh`, in the overlay only
+    if options.ssh {
+        let home = env::var_os("HOME")
+            .map(PathBuf::from)
+            .context("--ssh needs $HOME to find ~/.ssh/id_*.pub")?;
+        live_ssh::apply_live_ssh(live_overlay, &inputs.rootfs_staging, &home)?;
+    }
+
+    // Installed-system UKIs, at /usr/share/acornos/ukis/ on the live system.
+    // The live UKIs are built by reciso straight into EFI/Linux/.
+    if options.with_ukis {
+        let ukis_dir = live_overlay.join(uki::INSTALLED_UKIS_LIVE_PATH);
+        fs::create_dir_all(&ukis_dir)?;
+        uki::build_installed_ukis(&inputs.kernel, &inputs.installed_initramfs, &ukis_dir)?;
+    }
+
+    // Package manifest, also readable at /usr/share/acornos/ on the live system
+    let package_manifest = crate::package_manifest::write_package_manifest(base_dir, output_dir)?;
+    log::info!("  Package manifest: {}", package_manifest.display());
+
+    // Tool versions and inputs, at /usr/share/acornos/build-env.json too
+    let build_env = crate::build_env::write_build_env(base_dir, output_dir)?;
+    log::info!("  Build environment: {}", build_env.display());
     Ok(())
 }
 
 /// Verify ISO contains required boot components.
-fn verify_iso(path: &Path) -> Result<()> {
+fn verify_iso(path: &Path, format: RootfsFormat, options: &IsoOptions) -> Result<()> {
     use fsdbg::iso::IsoReader;
 
-    print!("  Verifying ISO... ");
-
     let reader = match IsoReader::open(path) {
         Ok(r) => r,
         Err(e) => {
             let err_str = e.to_string();
             if err_str.contains("isoinfo not found") || err_str.contains("isoinfo") {
-                println!("SKIPPED (isoinfo not available)");
+                log::info!("  Verifying ISO... SKIPPED (isoinfo not available)");
                 return Ok(());
             }
-            println!("FAILED");
+            log::info!("  Verifying ISO... FAILED");
             bail!("Failed to open ISO: {}", e);
         }
     };
 
-    let required = [
-        "/EFI/BOOT/BOOTX64.EFI",
-        "/live/filesystem.erofs",
-        "/live/overlay",
-    ];
+    let (arch, bios) = (options.arch, options.bios);
+    let rootfs_path = format!("/{}", format.iso_path().trim_start_matches('/'));
+    let mut required = vec![arch.efi_boot_path()];
+    // The netinstall ISO boots the installer, not the live system
+    if options.variant == IsoVariant::Live {
+        required.extend([rootfs_path.as_str(), "/live/overlay"]);
+    }
 
     let mut missing = Vec::new();
-    for item in &required {
+    for item in required {
         if !reader.exists(item) {
-            missing.push(*item);
+            missing.push(item.to_string());
         }
     }
 
-    // Check at least one UKI exists in EFI/Linux/
-    let has_uki = reader
-        .entries()
-        .iter()
-        .any(|e| e.path.starts_with("/EFI/Linux/") && e.path.ends_with(".efi"));
-    if !has_uki {
-        missing.push("EFI/Linux/*.efi (no UKI found)");
+    // x86_64 boots UKIs from EFI/Linux/; other arches boot GRUB's kernel
+    if arch == Arch::X86_64 {
+        let has_uki = reader
+            .entries()
+            .iter()
+            .any(|e| e.path.starts_with("/EFI/Linux/") && e.path.ends_with(".efi"));
+        if !has_uki {
+            missing.push("EFI/Linux/*.efi (no UKI found)".to_string());
+        }
+    }
+    // GRUB (non-x86_64 EFI, or BIOS) boots the kernel next to its loader
+    if arch != Arch::X86_64 || bios {
+        let kernel = format!("/{}", grub_efi::kernel_iso_path(arch));
+        if !reader.exists(&kernel) {
+            missing.push(kernel);
+        }
+    }
+    if bios {
+        let image = format!("/{}", grub_bios::BIOS_IMAGE_ISO_PATH);
+        if !reader.exists(&image) {
+            missing.push(image);
+        }
     }
 
     if missing.is_empty() {
-        println!("OK");
+        log::info!("  Verifying ISO... OK");
         Ok(())
     } else {
-        println!("FAILED");
+        log::info!("  Verifying ISO... FAILED");
         for item in &missing {
-            println!("    ✗ {} - Missing", item);
+            log::warn!("✗ {} - Missing", item);
         }
         bail!(
             "ISO verification failed: {} items missing. The ISO will not boot correctly.",
@@ -141,9 +467,21 @@ fn verify_iso(path: &Path) -> Result<()> {
     }
 }
 
-/// Create live overlay using shared infrastructure.
-fn create_live_overlay(output_dir: &Path) -> Result<()> {
-    let base_dir = output_dir.parent().unwrap_or(Path::new("."));
+/// Create live overlay using shared infrastructure, then apply the live
+/// overlay components (see [`live_overlay_components`]).
+///
+/// The shared files (shadow, fstab, acpi handlers, sysctl) are written by
+/// distro-builder's `create_openrc_live_overlay`, shared with the other
+/// distros; changes to their content or modes belong there. The components
+/// replace its inittab with
+/// [`live_inittab`](crate::component::inittab::live_inittab), so the rootfs
+/// and the live session share one set of getty lines, and add the rest.
+pub(crate) fn create_live_overlay(
+    base_dir: &Path,
+    output_dir: &Path,
+    live_user: LiveUser,
+    profile: LiveProfile,
+) -> Result<()> {
     let profile_overlay = base_dir.join("profile/live-overlay");
 
     let config = LiveOverlayConfig {
@@ -158,21 +496,164 @@ fn create_live_overlay(output_dir: &Path) -> Result<()> {
     };
 
     create_openrc_live_overlay(output_dir, &config)?;
-    Ok(())
+    let paths = OutputPaths::new(output_dir);
+    execute_into(
+        base_dir,
+        &paths.rootfs_staging(),
+        &paths.live_overlay(),
+        &live_overlay_components(profile, live_user),
+    )
+}
+
+/// What [`create_live_overlay`] adds to the shared overlay: the session of
+/// `profile`, then the live user if there is one.
+fn live_overlay_components(profile: LiveProfile, live_user: LiveUser) -> Vec<&'static Component> {
+    let mut components = vec![match profile {
+        LiveProfile::Testing => &LIVE_OVERLAY,
+        LiveProfile::Release => &LIVE_OVERLAY_RELEASE,
+    }];
+    if live_user.name().is_some() {
+        components.push(&LIVE_USER);
+    }
+    components
 }
 
 /// Print summary after ISO creation.
-fn print_iso_summary(iso_output: &Path) {
-    println!("\n=== AcornOS ISO Created ===");
-    println!("  Output: {}", iso_output.display());
+fn print_iso_summary(iso_output: &Path, extras: &[IsoExtra]) {
+    log::info!("\n=== AcornOS ISO Created ===");
+    log::info!("  Output: {}", iso_output.display());
     match fs::metadata(iso_output) {
         Ok(meta) => {
-            println!("  Size: {} MB", meta.len() / 1024 / 1024);
+            log::info!("  Size: {} MB", meta.len() / 1024 / 1024);
         }
         Err(e) => {
-            eprintln!("  [WARN] Could not read ISO size: {}", e);
+            log::warn!("Could not read ISO size: {}", e);
         }
     }
-    println!("\nTo run in QEMU:");
-    println!("  cargo run -- run");
+    if !extras.is_empty() {
+        let mut top_level: Vec<&str> = extras.iter().map(|e| e.top_level()).collect();
+        top_level.sort_unstable();
+        top_level.dedup();
+        log::info!("  Extras: /{}", top_level.join(", /"));
+    }
+    log::info!("\nTo run in QEMU:");
+    log::info!("  cargo run -- run");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::artifact::full_initramfs::INITRAMFS_INSTALLED_OUTPUT;
+    use crate::artifact::Compressor;
+
+    #[test]
+    fn test_iso_inputs_follow_the_last_builds() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path();
+        // As recorded by build_rootfs_image and build_tiny_initramfs
+        RootfsFormat::Squashfs.save(out).unwrap();
+        let zstd = InitramfsCompression::new(Compressor::Zstd, None).unwrap();
+        zstd.save(out).unwrap();
+
+        let inputs = IsoInputs::load(&OutputPaths::new(out));
+        assert_eq!(inputs.format, RootfsFormat::Squashfs);
+        assert_eq!(inputs.rootfs, out.join(RootfsFormat::Squashfs.image_name()));
+        assert_eq!(inputs.initramfs, out.join(zstd.output_name()));
+        assert_eq!(
+            inputs.installed_initramfs,
+            out.join(INITRAMFS_INSTALLED_OUTPUT)
+        );
+        assert_eq!(inputs.kernel, out.join("staging/boot/vmlinuz"));
+        assert_eq!(inputs.rootfs_staging, out.join("rootfs-staging"));
+        assert_eq!(inputs.live_overlay, out.join("live-overlay"));
+    }
+
+    #[test]
+    fn test_live_overlay_components() {
+        use crate::artifact::live_profile::TEST_INSTRUMENTATION;
+        use crate::artifact::persistence::{make_persistence_script, MAKE_PERSISTENCE_PATH};
+        use crate::component::inittab::live_inittab;
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let staging = dir.path().join("rootfs-staging");
+        fs::create_dir_all(staging.join("etc")).unwrap();
+        fs::write(
+            staging.join("etc/passwd"),
+            "root:x:0:0:root:/root:/bin/sh\n",
+        )
+        .unwrap();
+        fs::write(staging.join("etc/group"), "root:x:0:\nwheel:x:10:root\n").unwrap();
+        fs::write(staging.join("etc/shadow"), "root:*:19000::::::\n").unwrap();
+        // As left by create_openrc_live_overlay and profile/live-overlay
+        let shared_overlay = |overlay: &Path| {
+            fs::create_dir_all(overlay.join("etc/profile.d")).unwrap();
+            fs::write(overlay.join("etc/shadow"), "root::19000::::::\n").unwrap();
+            fs::write(overlay.join(TEST_INSTRUMENTATION), "echo ready\n").unwrap();
+        };
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        fn walk(root: &Path, dir: &Path, files: &mut Vec<String>) {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    walk(root, &path, files);
+                } else {
+                    files.push(path.strip_prefix(root).unwrap().display().to_string());
+                }
+            }
+        }
+        let files = |overlay: &Path| {
+            let mut files = Vec::new();
+            walk(overlay, overlay, &mut files);
+            files.sort();
+            files
+        };
+
+        let testing = dir.path().join("testing");
+        shared_overlay(&testing);
+        let components = live_overlay_components(LiveProfile::Testing, LiveUser::None);
+        execute_into(dir.path(), &staging, &testing, &components).unwrap();
+        assert_eq!(
+            files(&testing),
+            [
+                "etc/inittab",
+                "etc/profile.d/00-acorn-test.sh",
+                "etc/shadow",
+                MAKE_PERSISTENCE_PATH,
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(testing.join("etc/inittab")).unwrap(),
+            live_inittab("root")
+        );
+        assert_eq!(
+            fs::read_to_string(testing.join(MAKE_PERSISTENCE_PATH)).unwrap(),
+            make_persistence_script()
+        );
+        assert_eq!(mode(&testing.join(MAKE_PERSISTENCE_PATH)), 0o755);
+
+        let release = dir.path().join("release");
+        shared_overlay(&release);
+        let components = live_overlay_components(LiveProfile::Release, LiveUser::Live);
+        execute_into(dir.path(), &staging, &release, &components).unwrap();
+        assert_eq!(
+            files(&release),
+            [
+                "etc/doas.conf",
+                "etc/group",
+                "etc/inittab",
+                "etc/passwd",
+                "etc/shadow",
+                MAKE_PERSISTENCE_PATH,
+            ]
+        );
+        // Root stays locked, as in the rootfs staging
+        let shadow = fs::read_to_string(release.join("etc/shadow")).unwrap();
+        assert!(shadow.starts_with("root:*:"));
+        assert_eq!(mode(&release.join("etc/doas.conf")), 0o600);
+        let inittab = fs::read_to_string(release.join("etc/inittab")).unwrap();
+        assert!(inittab.contains("--autologin live --noclear tty1"));
+        assert!(!inittab.contains("--autologin root"));
+        assert_eq!(mode(&release.join(MAKE_PERSISTENCE_PATH)), 0o755);
+    }
 }
@@ -0,0 +1,695 @@
+//! Compare two built ISOs (`acornos diff-iso`).
+//!
+//! Both ISOs are listed with `xorriso -find / -exec lsdl` and compared by
+//! path and size. The rootfs image inside each is extracted and listed too
+//! (`unsquashfs -lls` for squashfs, an erofsfuse or, as root, loop mount
+//! for EROFS), so a changed `filesystem.erofs` is broken down into the
+//! files that changed. The build manifest, `/etc/os-release` and every
+//! `grub.cfg` are diffed line by line.
+//!
+//! Sizes are compared, not contents: a file rewritten with the same size
+//! does not show up. The manifest records artifact hashes, so a rebuilt
+//! artifact still shows there.
+
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use super::RootfsFormat;
+use crate::clean::human_bytes;
+use crate::manifest::{json_str, MANIFEST_ISO_PATH};
+use distro_builder::process::{self, Cmd};
+
+/// Changes listed per category in the text report.
+const NOTABLE: usize = 15;
+
+/// Regular files and symlinks of a filesystem, path -> size.
+pub type Listing = BTreeMap<String, u64>;
+
+/// Parse `xorriso -find / -exec lsdl` output: `ls -l` style lines ending
+/// in a quoted absolute path. Directories are left out.
+pub fn parse_lsdl(output: &str) -> Listing {
+    let mut listing = Listing::new();
+    for line in output.lines() {
+        let Some((fields, path)) = line.split_once(" '/") else {
+            continue;
+        };
+        let fields: Vec<&str> = fields.split_whitespace().collect();
+        if fields.len() < 5 || fields[0].starts_with('d') {
+            continue;
+        }
+        let Ok(size) = fields[4].parse() else {
+            continue;
+        };
+        let path = path.strip_suffix('\'').unwrap_or(path);
+        let path = path.split("' -> '").next().unwrap_or(path);
+        listing.insert(format!("/{}", path.replace("'\"'\"'", "'")), size);
+    }
+    listing
+}
+
+/// Parse `unsquashfs -lls` output: `perms owner size date time path`,
+/// paths under `squashfs-root`. Only regular files and symlinks are kept.
+pub fn parse_unsquashfs_lls(output: &str) -> Listing {
+    let mut listing = Listing::new();
+    for line in output.lines() {
+        if !(line.starts_with('-') || line.starts_with('l')) {
+            continue;
+        }
+        let mut rest = line;
+        let mut fields = Vec::new();
+        for _ in 0..5 {
+            let Some((field, tail)) = rest.trim_start().split_once(char::is_whitespace) else {
+                break;
+            };
+            fields.push(field);
+            rest = tail;
+        }
+        let (Some(size), Some(path)) = (
+            fields.get(2).and_then(|s| s.parse().ok()),
+            rest.trim_start()
+                .strip_prefix(super::rootfs::UNSQUASHFS_ROOT),
+        ) else {
+            continue;
+        };
+        let path = path.split(" -> ").next().unwrap_or(path);
+        listing.insert(path.to_string(), size);
+    }
+    listing
+}
+
+/// Regular files and symlinks under `root`, as absolute paths.
+pub fn walk_tree(root: &Path) -> Result<Listing> {
+    fn walk(root: &Path, dir: &Path, listing: &mut Listing) -> Result<()> {
+        for entry in
+            fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?
+        {
+            let entry = entry?;
+            let path = entry.path();
+            let meta = fs::symlink_metadata(&path)?;
+            if meta.is_dir() {
+                walk(root, &path, listing)?;
+            } else if meta.is_file() || meta.file_type().is_symlink() {
+                let rel = path.strip_prefix(root)?;
+                listing.insert(format!("/{}", rel.display()), meta.len());
+            }
+        }
+        Ok(())
+    }
+    let mut listing = Listing::new();
+    walk(root, root, &mut listing)?;
+    Ok(listing)
+}
+
+/// Files added, removed and resized between two listings.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListingDiff {
+    pub added: Vec<(String, u64)>,
+    pub removed: Vec<(String, u64)>,
+    /// Path, old size, new size.
+    pub changed: Vec<(String, u64, u64)>,
+}
+
+impl ListingDiff {
+    pub fn between(old: &Listing, new: &Listing) -> Self {
+        let mut diff = Self::default();
+        for (path, &size) in old {
+            match new.get(path) {
+                None => diff.removed.push((path.clone(), size)),
+                Some(&new_size) if new_size != size => {
+                    diff.changed.push((path.clone(), size, new_size))
+                }
+                Some(_) => {}
+            }
+        }
+        for (path, &size) in new {
+            if !old.contains_key(path) {
+                diff.added.push((path.clone(), size));
+            }
+        }
+        // Biggest changes first
+        diff.added.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        diff.removed
+            .sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        diff.changed
+            .sort_by_key(|(path, old, new)| (std::cmp::Reverse(old.abs_diff(*new)), path.clone()));
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    fn to_text(&self, title: &str) -> String {
+        let mut out = format!(
+            "{}: {} added, {} removed, {} changed\n",
+            title,
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len()
+        );
+        let more = |out: &mut String, total: usize| {
+            if total > NOTABLE {
+                out.push_str(&format!("    ... and {} more\n", total - NOTABLE));
+            }
+        };
+        for (path, size) in self.added.iter().take(NOTABLE) {
+            out.push_str(&format!("  + {} ({})\n", path, human_bytes(*size)));
+        }
+        more(&mut out, self.added.len());
+        for (path, size) in self.removed.iter().take(NOTABLE) {
+            out.push_str(&format!("  - {} ({})\n", path, human_bytes(*size)));
+        }
+        more(&mut out, self.removed.len());
+        for (path, old, new) in self.changed.iter().take(NOTABLE) {
+            let sign = if new > old { '+' } else { '-' };
+            out.push_str(&format!(
+                "  ~ {} {} -> {} ({}{})\n",
+                path,
+                human_bytes(*old),
+                human_bytes(*new),
+                sign,
+                human_bytes(old.abs_diff(*new))
+            ));
+        }
+        more(&mut out, self.changed.len());
+        out
+    }
+
+    fn to_json(&self) -> String {
+        let file = |(path, size): &(String, u64)| {
+            format!("{{ \"path\": {}, \"size\": {} }}", json_str(path), size)
+        };
+        let added: Vec<String> = self.added.iter().map(file).collect();
+        let removed: Vec<String> = self.removed.iter().map(file).collect();
+        let changed: Vec<String> = self
+            .changed
+            .iter()
+            .map(|(path, old, new)| {
+                format!(
+                    "{{ \"path\": {}, \"old_size\": {}, \"new_size\": {} }}",
+                    json_str(path),
+                    old,
+                    new
+                )
+            })
+            .collect();
+        format!(
+            "{{\n    \"added\": [{}],\n    \"removed\": [{}],\n    \"changed\": [{}]\n  }}",
+            json_rows(&added),
+            json_rows(&removed),
+            json_rows(&changed)
+        )
+    }
+}
+
+/// Lines only in the old or only in the new version of a text file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextDiff {
+    /// What the file is (`os-release`, `/boot/grub/grub.cfg`, ...).
+    pub name: String,
+    pub removed: Vec<String>,
+    pub added: Vec<String>,
+}
+
+impl TextDiff {
+    /// The diff of `old` and `new` (`None` for a file missing on that
+    /// side), or `None` if they are the same.
+    pub fn between(name: &str, old: Option<&str>, new: Option<&str>) -> Option<Self> {
+        if old == new {
+            return None;
+        }
+        let lines = |text: Option<&str>| -> Vec<String> {
+            text.map(|t| t.lines().map(str::to_string).collect())
+                .unwrap_or_default()
+        };
+        let (old_lines, new_lines) = (lines(old), lines(new));
+        let mut removed: Vec<String> = old_lines
+            .iter()
+            .filter(|l| !new_lines.contains(l))
+            .cloned()
+            .collect();
+        let mut added: Vec<String> = new_lines
+            .iter()
+            .filter(|l| !old_lines.contains(l))
+            .cloned()
+            .collect();
+        if old.is_none() {
+            removed.insert(0, "(missing)".to_string());
+        }
+        if new.is_none() {
+            added.insert(0, "(missing)".to_string());
+        }
+        Some(Self {
+            name: name.to_string(),
+            removed,
+            added,
+        })
+    }
+}
+
+/// Everything that differs between two ISOs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IsoDiff {
+    pub iso: ListingDiff,
+    /// Inside the rootfs images; `None` if they could not be listed.
+    pub rootfs: Option<ListingDiff>,
+    /// Why the rootfs images were not compared.
+    pub rootfs_skipped: Option<String>,
+    pub texts: Vec<TextDiff>,
+}
+
+impl IsoDiff {
+    /// Compare `old` and `new`, with scratch files under `work_dir`.
+    pub fn compare(old: &Path, new: &Path, work_dir: &Path) -> Result<Self> {
+        let _ = fs::remove_dir_all(work_dir);
+        fs::create_dir_all(work_dir)?;
+        let result = Self::compare_in(old, new, work_dir);
+        let _ = fs::remove_dir_all(work_dir);
+        result
+    }
+
+    fn compare_in(old: &Path, new: &Path, work_dir: &Path) -> Result<Self> {
+        let (old_dir, new_dir) = (work_dir.join("old"), work_dir.join("new"));
+        let old_side = IsoSide::read(old, &old_dir)?;
+        let new_side = IsoSide::read(new, &new_dir)?;
+
+        let mut diff = Self {
+            iso: ListingDiff::between(&old_side.listing, &new_side.listing),
+            ..Self::default()
+        };
+        match (&old_side.rootfs, &new_side.rootfs) {
+            (Ok(old_rootfs), Ok(new_rootfs)) => {
+                diff.rootfs = Some(ListingDiff::between(
+                    &old_rootfs.listing,
+                    &new_rootfs.listing,
+                ));
+                diff.texts.extend(TextDiff::between(
+                    "os-release",
+                    old_rootfs.os_release.as_deref(),
+                    new_rootfs.os_release.as_deref(),
+                ));
+            }
+            (Err(e), _) | (_, Err(e)) => diff.rootfs_skipped = Some(format!("{:#}", e)),
+        }
+
+        let mut text_paths = vec![format!("/{}", MANIFEST_ISO_PATH)];
+        for path in old_side.listing.keys().chain(new_side.listing.keys()) {
+            if path.ends_with("/grub.cfg") && !text_paths.contains(path) {
+                text_paths.push(path.clone());
+            }
+        }
+        for path in &text_paths {
+            let old_text = old_side.text(path)?;
+            let new_text = new_side.text(path)?;
+            diff.texts.extend(TextDiff::between(
+                path,
+                old_text.as_deref(),
+                new_text.as_deref(),
+            ));
+        }
+        Ok(diff)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iso.is_empty()
+            && self.rootfs.as_ref().is_none_or(ListingDiff::is_empty)
+            && self.texts.is_empty()
+    }
+
+    /// Counts plus the biggest changes per category.
+    pub fn to_text(&self) -> String {
+        if self.is_empty() {
+            let mut out = "No differences\n".to_string();
+            if let Some(reason) = &self.rootfs_skipped {
+                out.push_str(&format!("  [SKIP] Rootfs contents: {}\n", reason));
+            }
+            return out;
+        }
+        let mut out = self.iso.to_text("ISO files");
+        match (&self.rootfs, &self.rootfs_skipped) {
+            (Some(rootfs), _) => out.push_str(&rootfs.to_text("Rootfs files")),
+            (None, Some(reason)) => {
+                out.push_str(&format!("  [SKIP] Rootfs contents: {}\n", reason))
+            }
+            (None, None) => {}
+        }
+        for text in &self.texts {
+            out.push_str(&format!("{}:\n", text.name));
+            for line in &text.removed {
+                out.push_str(&format!("  - {}\n", line));
+            }
+            for line in &text.added {
+                out.push_str(&format!("  + {}\n", line));
+            }
+        }
+        out
+    }
+
+    /// The full diff, every file and line.
+    pub fn to_json(&self, old: &Path, new: &Path) -> String {
+        let lines = |lines: &[String]| -> String {
+            let rows: Vec<String> = lines.iter().map(|l| json_str(l)).collect();
+            format!("[{}]", rows.join(", "))
+        };
+        let texts: Vec<String> = self
+            .texts
+            .iter()
+            .map(|t| {
+                format!(
+                    "{{ \"name\": {}, \"removed\": {}, \"added\": {} }}",
+                    json_str(&t.name),
+                    lines(&t.removed),
+                    lines(&t.added)
+                )
+            })
+            .collect();
+        format!(
+            "{{\n  \"old\": {},\n  \"new\": {},\n  \"identical\": {},\n  \"iso\": {},\n  \"rootfs\": {},\n  \"rootfs_skipped\": {},\n  \"files\": [{}]\n}}\n",
+            json_str(&old.display().to_string()),
+            json_str(&new.display().to_string()),
+            self.is_empty(),
+            self.iso.to_json(),
+            self.rootfs
+                .as_ref()
+                .map(ListingDiff::to_json)
+                .unwrap_or_else(|| "null".to_string()),
+            self.rootfs_skipped
+                .as_deref()
+                .map(json_str)
+                .unwrap_or_else(|| "null".to_string()),
+            json_rows(&texts)
+        )
+    }
+}
+
+/// `rows` as the inside of a JSON array, one per line.
+fn json_rows(rows: &[String]) -> String {
+    if rows.is_empty() {
+        return String::new();
+    }
+    format!("\n      {}\n    ", rows.join(",\n      "))
+}
+
+/// Listing and os-release of a rootfs image.
+struct RootfsContents {
+    listing: Listing,
+    os_release: Option<String>,
+}
+
+/// One ISO, listed.
+struct IsoSide<'a> {
+    iso: &'a Path,
+    work_dir: &'a Path,
+    listing: Listing,
+    rootfs: Result<RootfsContents>,
+}
+
+impl<'a> IsoSide<'a> {
+    fn read(iso: &'a Path, work_dir: &'a Path) -> Result<Self> {
+        if !iso.is_file() {
+            bail!("ISO not found at {}", iso.display());
+        }
+        fs::create_dir_all(work_dir)?;
+        let output = Cmd::new("xorriso")
+            .arg("-indev")
+            .arg_path(iso)
+            .args(["-find", "/", "-exec", "lsdl"])
+            .error_msg("xorriso failed to list the ISO. Install: sudo dnf install xorriso")
+            .run()?;
+        let listing = parse_lsdl(&output.stdout);
+        let mut side = Self {
+            iso,
+            work_dir,
+            listing,
+            rootfs: Err(anyhow::anyhow!("no rootfs image")),
+        };
+        side.rootfs = side.read_rootfs();
+        Ok(side)
+    }
+
+    /// Copy `iso_path` out of the ISO, if it is there.
+    fn extract(&self, iso_path: &str) -> Result<Option<std::path::PathBuf>> {
+        if !self.listing.contains_key(iso_path) {
+            return Ok(None);
+        }
+        let dest = self
+            .work_dir
+            .join(iso_path.trim_start_matches('/').replace('/', "_"));
+        Cmd::new("xorriso")
+            .args(["-osirrox", "on", "-indev"])
+            .arg_path(self.iso)
+            .arg("-extract")
+            .arg(iso_path)
+            .arg_path(&dest)
+            .error_msg(format!("xorriso failed to extract {}", iso_path))
+            .run()?;
+        Ok(Some(dest))
+    }
+
+    fn text(&self, iso_path: &str) -> Result<Option<String>> {
+        match self.extract(iso_path)? {
+            Some(path) => Ok(Some(
+                String::from_utf8_lossy(&fs::read(&path)?).into_owned(),
+            )),
+            None => Ok(None),
+        }
+    }
+
+    fn read_rootfs(&self) -> Result<RootfsContents> {
+        for format in RootfsFormat::ALL {
+            if let Some(image) = self.extract(&format!("/{}", format.iso_path()))? {
+                let contents = read_image(format, &image, &self.work_dir.join("rootfs"));
+                let _ = fs::remove_file(&image);
+                return contents;
+            }
+        }
+        bail!("{} has no rootfs image", self.iso.display())
+    }
+}
+
+/// How the files of a rootfs image are listed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ImageReader {
+    /// `unsquashfs -lls` and `-cat`
+    Unsquashfs,
+    /// Unprivileged FUSE mount
+    Erofsfuse,
+    /// Kernel loop mount (root only)
+    LoopMount,
+}
+
+/// Best available reader for `format`.
+pub(crate) fn image_reader(
+    format: RootfsFormat,
+    has_tool: impl Fn(&str) -> bool,
+    is_root: bool,
+) -> Option<ImageReader> {
+    let tool = match format {
+        RootfsFormat::Squashfs if has_tool("unsquashfs") => Some(ImageReader::Unsquashfs),
+        RootfsFormat::Erofs if has_tool("erofsfuse") => Some(ImageReader::Erofsfuse),
+        _ => None,
+    };
+    tool.or(is_root.then_some(ImageReader::LoopMount))
+}
+
+/// Files and os-release of `image`, mounted at `mnt` if need be.
+fn read_image(format: RootfsFormat, image: &Path, mnt: &Path) -> Result<RootfsContents> {
+    let Some(reader) = image_reader(format, process::exists, crate::privilege::is_root()) else {
+        bail!(
+            "cannot list the {} image (install {}, or run as root)",
+            format,
+            match format {
+                RootfsFormat::Erofs => "erofsfuse",
+                RootfsFormat::Squashfs => "unsquashfs",
+            }
+        );
+    };
+    if reader == ImageReader::Unsquashfs {
+        let listing = Cmd::new("unsquashfs")
+            .arg("-lls")
+            .arg_path(image)
+            .error_msg("unsquashfs could not read the rootfs image")
+            .run()?
+            .stdout;
+        let os_release = ["etc/os-release", "usr/lib/os-release"]
+            .iter()
+            .find_map(|path| {
+                Cmd::new("unsquashfs")
+                    .arg("-cat")
+                    .arg_path(image)
+                    .arg(path)
+                    .allow_fail()
+                    .run()
+                    .ok()
+                    .filter(|r| r.success())
+                    .map(|r| r.stdout)
+            });
+        return Ok(RootfsContents {
+            listing: parse_unsquashfs_lls(&listing),
+            os_release,
+        });
+    }
+
+    fs::create_dir_all(mnt)?;
+    let mounted = if reader == ImageReader::Erofsfuse {
+        Cmd::new("erofsfuse").arg_path(image).arg_path(mnt)
+    } else {
+        Cmd::new("mount")
+            .args(["-t", format.fs_type(), "-o", "loop,ro"])
+            .arg_path(image)
+            .arg_path(mnt)
+    }
+    .error_msg("Failed to mount the rootfs image")
+    .run();
+    let contents = mounted.and_then(|_| {
+        Ok(RootfsContents {
+            listing: walk_tree(mnt)?,
+            os_release: ["etc/os-release", "usr/lib/os-release"]
+                .iter()
+                .find_map(|path| fs::read_to_string(mnt.join(path)).ok()),
+        })
+    });
+    if reader == ImageReader::Erofsfuse {
+        let _ = Cmd::new("fusermount")
+            .arg("-u")
+            .arg_path(mnt)
+            .allow_fail()
+            .run();
+    } else {
+        let _ = Cmd::new("umount").arg_path(mnt).allow_fail().run();
+    }
+    let _ = fs::remove_dir(mnt);
+    contents
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_listings() {
+        let lsdl = "\
+xorriso 1.5.6 : RockRidge filesystem manipulator
+drwxr-xr-x    1 0        0               0 Jan  1 00:00 '/'
+drwxr-xr-x    1 0        0               0 Jan  1 00:00 '/boot'
+-rw-r--r--    1 0        0         9123456 Jan  1 00:00 '/boot/vmlinuz'
+-rw-r--r--    1 0        0             321 Jan  1 00:00 '/boot/grub/grub.cfg'
+-rw-r--r--    1 0        0              42 Jan  1 00:00 '/it'\"'\"'s here'
+";
+        let listing = parse_lsdl(lsdl);
+        assert_eq!(
+            listing.into_iter().collect::<Vec<_>>(),
+            [
+                ("/boot/grub/grub.cfg".to_string(), 321),
+                ("/boot/vmlinuz".to_string(), 9123456),
+                ("/it's here".to_string(), 42),
+            ]
+        );
+
+        let lls = "\
+Parallel unsquashfs: Using 8 processors
+drwxr-xr-x root/root                45 2024-01-09 12:00 squashfs-root
+-rwxr-xr-x root/root            799344 2024-01-09 12:00 squashfs-root/bin/busybox
+lrwxrwxrwx root/root                 7 2024-01-09 12:00 squashfs-root/bin/sh -> busybox
+crw-r--r-- root/root             5,  1 2024-01-09 12:00 squashfs-root/dev/console
+-rw-r--r-- root/root                12 2024-01-09 12:00 squashfs-root/etc/my file
+";
+        let listing = parse_unsquashfs_lls(lls);
+        assert_eq!(
+            listing.into_iter().collect::<Vec<_>>(),
+            [
+                ("/bin/busybox".to_string(), 799344),
+                ("/bin/sh".to_string(), 7),
+                ("/etc/my file".to_string(), 12),
+            ]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("etc")).unwrap();
+        fs::write(dir.path().join("etc/hostname"), "acornos\n").unwrap();
+        std::os::unix::fs::symlink("/nonexistent", dir.path().join("link")).unwrap();
+        let listing = walk_tree(dir.path()).unwrap();
+        assert_eq!(listing.get("/etc/hostname"), Some(&8));
+        assert_eq!(listing.get("/link"), Some(&12));
+        assert_eq!(listing.len(), 2);
+    }
+
+    #[test]
+    fn test_iso_diff_report() {
+        let old: Listing = [("/a", 10), ("/b", 20), ("/c", 30)]
+            .into_iter()
+            .map(|(p, s)| (p.to_string(), s))
+            .collect();
+        let mut new = old.clone();
+        new.remove("/a");
+        new.insert("/b".to_string(), 2068);
+        new.insert("/d".to_string(), 5);
+
+        let diff = IsoDiff {
+            iso: ListingDiff::between(&old, &new),
+            rootfs: Some(ListingDiff::between(&old, &old)),
+            rootfs_skipped: None,
+            texts: TextDiff::between(
+                "os-release",
+                Some("NAME=AcornOS\nVERSION_ID=1\n"),
+                Some("NAME=AcornOS\nVERSION_ID=2\n"),
+            )
+            .into_iter()
+            .chain(TextDiff::between(
+                "/boot/grub/grub.cfg",
+                Some("same"),
+                Some("same"),
+            ))
+            .collect(),
+        };
+        assert!(!diff.is_empty());
+        assert_eq!(
+            diff.to_text(),
+            "ISO files: 1 added, 1 removed, 1 changed\n  \
+             + /d (5 B)\n  \
+             - /a (10 B)\n  \
+             ~ /b 20 B -> 2.0 KiB (+2.0 KiB)\n\
+             Rootfs files: 0 added, 0 removed, 0 changed\n\
+             os-release:\n  \
+             - VERSION_ID=1\n  \
+             + VERSION_ID=2\n"
+        );
+        let json = diff.to_json(Path::new("old.iso"), Path::new("new.iso"));
+        assert!(json.contains("\"identical\": false"), "{}", json);
+        assert!(
+            json.contains("{ \"path\": \"/b\", \"old_size\": 20, \"new_size\": 2068 }"),
+            "{}",
+            json
+        );
+        assert!(json.contains("\"removed\": [\"VERSION_ID=1\"]"), "{}", json);
+
+        let same = IsoDiff {
+            rootfs_skipped: Some("cannot list the erofs image".to_string()),
+            ..IsoDiff::default()
+        };
+        assert!(same.is_empty());
+        assert!(same.to_text().starts_with("No differences\n"));
+        assert_eq!(
+            TextDiff::between("manifest", None, Some("x"))
+                .unwrap()
+                .removed,
+            ["(missing)"]
+        );
+    }
+
+    #[test]
+    fn test_image_reader() {
+        let has = |tools: &'static [&'static str]| move |t: &str| tools.contains(&t);
+        assert_eq!(
+            image_reader(RootfsFormat::Squashfs, has(&["unsquashfs"]), false),
+            Some(ImageReader::Unsquashfs)
+        );
+        assert_eq!(
+            image_reader(RootfsFormat::Erofs, has(&["unsquashfs"]), true),
+            Some(ImageReader::LoopMount)
+        );
+        assert_eq!(image_reader(RootfsFormat::Erofs, has(&[]), false), None);
+    }
+}
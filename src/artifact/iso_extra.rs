@@ -0,0 +1,284 @@
+//! Extra payloads injected into the ISO root.
+//!
+//! Anything under `profile/iso-extra/` is copied into the ISO root after the
+//! standard artifacts, plus ad-hoc additions from `acornos iso --iso-extra
+//! <src>[:dest]`. Reserved boot paths cannot be targeted, so extras can never
+//! clobber the kernel, UKIs, or the rootfs.
+//!
+//! reciso builds the bootable ISO; extras are then mapped in with xorriso,
+//! replaying the existing boot images unchanged.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+use crate::progress;
+
+/// Profile directory whose contents are always added to the ISO root.
+pub const ISO_EXTRA_PROFILE_DIR: &str = "profile/iso-extra";
+
+/// Top-level ISO directories owned by the boot layout.
+pub const RESERVED_ISO_PATHS: &[&str] = &["boot", "live", "EFI"];
+
+/// A file or directory to add to the ISO.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IsoExtra {
+    /// Source on the host.
+    pub src: PathBuf,
+    /// Destination relative to the ISO root.
+    pub dest: String,
+}
+
+impl IsoExtra {
+    /// Top-level ISO entry this extra lands in.
+    pub fn top_level(&self) -> &str {
+        self.dest.split('/').next().unwrap_or(&self.dest)
+    }
+}
+
+/// Parse a `<src>[:dest]` spec. Without `dest`, the source's file name is used.
+pub fn parse_iso_extra(spec: &str) -> Result<IsoExtra> {
+    let (src, dest) = match spec.split_once(':') {
+        Some((src, dest)) => (src, Some(dest)),
+        None => (spec, None),
+    };
+
+    if src.is_empty() {
+        bail!("Invalid --iso-extra '{}': source path is empty", spec);
+    }
+
+    let src = PathBuf::from(src);
+    let dest = match dest {
+        Some(dest) => dest.to_string(),
+        None => src
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .with_context(|| format!("Invalid --iso-extra '{}': no file name", spec))?,
+    };
+
+    let extra = IsoExtra {
+        src,
+        dest: normalize_dest(&dest)?,
+    };
+    validate_extra(&extra)?;
+    Ok(extra)
+}
+
+/// Normalize an ISO destination to a clean relative path.
+fn normalize_dest(dest: &str) -> Result<String> {
+    let mut parts = Vec::new();
+    for component in Path::new(dest).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().into_owned()),
+            Component::RootDir | Component::CurDir => {}
+            _ => bail!("Invalid ISO destination '{}': must not contain '..'", dest),
+        }
+    }
+    if parts.is_empty() {
+        bail!(
+            "Invalid ISO destination '{}': resolves to the ISO root",
+            dest
+        );
+    }
+    Ok(parts.join("/"))
+}
+
+/// Reject extras that target reserved boot paths.
+pub fn validate_extra(extra: &IsoExtra) -> Result<()> {
+    let top = extra.top_level();
+    if let Some(reserved) = RESERVED_ISO_PATHS
+        .iter()
+        .find(|r| r.eq_ignore_ascii_case(top))
+    {
+        bail!(
+            "ISO extra '{}' -> /{} collides with reserved path /{}/ (kernel, UKIs, rootfs live there)",
+            extra.src.display(),
+            extra.dest,
+            reserved
+        );
+    }
+    Ok(())
+}
+
+/// Collect extras from `profile/iso-extra/` followed by CLI additions.
+///
+/// Sources must exist, and no two extras may target the same destination.
+pub fn collect_iso_extras(base_dir: &Path, cli: &[IsoExtra]) -> Result<Vec<IsoExtra>> {
+    let mut extras = Vec::new();
+
+    let profile_dir = base_dir.join(ISO_EXTRA_PROFILE_DIR);
+    if profile_dir.is_dir() {
+        let mut entries: Vec<_> = fs::read_dir(&profile_dir)?.collect::<Result<_, _>>()?;
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            let extra = IsoExtra {
+                src: entry.path(),
+                dest: entry.file_name().to_string_lossy().into_owned(),
+            };
+            validate_extra(&extra)?;
+            extras.push(extra);
+        }
+    }
+
+    for extra in cli {
+        if !extra.src.exists() {
+            bail!("ISO extra source not found: {}", extra.src.display());
+        }
+        if let Some(existing) = extras.iter().find(|e| e.dest == extra.dest) {
+            bail!(
+                "ISO extra destination /{} is provided by both {} and {}",
+                extra.dest,
+                existing.src.display(),
+                extra.src.display()
+            );
+        }
+        extras.push(extra.clone());
+    }
+
+    Ok(extras)
+}
+
+/// All files (recursively) contributed by the extras, for rebuild hashing.
+pub fn extra_files(extras: &[IsoExtra]) -> Result<Vec<PathBuf>> {
+    fn walk(path: &Path, out: &mut Vec<PathBuf>) -> Result<()> {
+        if path.is_dir() {
+            let mut entries: Vec<_> = fs::read_dir(path)?.collect::<Result<_, _>>()?;
+            entries.sort_by_key(|e| e.file_name());
+            for entry in entries {
+                walk(&entry.path(), out)?;
+            }
+        } else {
+            out.push(path.to_path_buf());
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    for extra in extras {
+        walk(&extra.src, &mut files)?;
+    }
+    Ok(files)
+}
+
+/// Map extras into an existing ISO, writing a new ISO with boot images preserved.
+pub fn apply_iso_extras(input: &Path, output: &Path, extras: &[IsoExtra]) -> Result<()> {
+    apply_iso_changes(input, output, extras, &[], None)
+}
+
+/// Like [`apply_iso_extras`], also removing the ISO paths `removals`.
+pub fn apply_iso_changes(
+    input: &Path,
+    output: &Path,
+    extras: &[IsoExtra],
+    removals: &[String],
+    application_id: Option<&str>,
+) -> Result<()> {
+    let _ = fs::remove_file(output);
+
+    let mut cmd = Command::new("xorriso");
+    cmd.arg("-indev")
+        .arg(input)
+        .arg("-outdev")
+        .arg(output)
+        .args(["-boot_image", "any", "replay"]);
+
+    for extra in extras {
+        cmd.arg("-map")
+            .arg(&extra.src)
+            .arg(format!("/{}", extra.dest));
+    }
+    for path in removals {
+        cmd.arg("-rm")
+            .arg(format!("/{}", path.trim_start_matches('/')));
+    }
+    // Kept by the later passes, which load the volume descriptor
+    if let Some(id) = application_id {
+        cmd.arg("-application_id").arg(id);
+    }
+
+    progress::run(
+        "ISO extras",
+        &mut cmd,
+        "xorriso failed to add ISO extras. Install: sudo dnf install xorriso",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_iso_extra_default_dest() {
+        let extra = parse_iso_extra("/tmp/autoinstall.yaml").unwrap();
+        assert_eq!(extra.src, PathBuf::from("/tmp/autoinstall.yaml"));
+        assert_eq!(extra.dest, "autoinstall.yaml");
+    }
+
+    #[test]
+    fn test_parse_iso_extra_explicit_dest() {
+        let extra = parse_iso_extra("drivers:/vendor/drivers/").unwrap();
+        assert_eq!(extra.dest, "vendor/drivers");
+        assert_eq!(extra.top_level(), "vendor");
+    }
+
+    #[test]
+    fn test_reserved_paths_rejected() {
+        for spec in [
+            "vmlinuz:boot/vmlinuz",
+            "x:live/filesystem.erofs",
+            "y:efi/BOOT",
+        ] {
+            let err = parse_iso_extra(spec).unwrap_err().to_string();
+            assert!(err.contains("reserved"), "{}: {}", spec, err);
+        }
+        assert!(parse_iso_extra("x:../escape").is_err());
+        assert!(parse_iso_extra("x:/").is_err());
+    }
+
+    #[test]
+    fn test_collect_profile_and_cli_extras() {
+        let dir = tempdir().unwrap();
+        let profile = dir.path().join(ISO_EXTRA_PROFILE_DIR);
+        fs::create_dir_all(profile.join("docs")).unwrap();
+        fs::write(profile.join("docs/README"), "hi").unwrap();
+        fs::write(profile.join("autoinstall.yaml"), "x").unwrap();
+        let cli_src = dir.path().join("driver.ko");
+        fs::write(&cli_src, "ko").unwrap();
+
+        let cli = vec![IsoExtra {
+            src: cli_src.clone(),
+            dest: "vendor/driver.ko".to_string(),
+        }];
+        let extras = collect_iso_extras(dir.path(), &cli).unwrap();
+        let dests: Vec<&str> = extras.iter().map(|e| e.dest.as_str()).collect();
+        assert_eq!(dests, vec!["autoinstall.yaml", "docs", "vendor/driver.ko"]);
+
+        let files = extra_files(&extras).unwrap();
+        assert_eq!(files.len(), 3);
+        assert!(files.contains(&profile.join("docs/README")));
+    }
+
+    #[test]
+    fn test_collect_rejects_duplicates_and_missing() {
+        let dir = tempdir().unwrap();
+        let profile = dir.path().join(ISO_EXTRA_PROFILE_DIR);
+        fs::create_dir_all(&profile).unwrap();
+        fs::write(profile.join("autoinstall.yaml"), "x").unwrap();
+        let other = dir.path().join("other.yaml");
+        fs::write(&other, "y").unwrap();
+
+        let dup = vec![IsoExtra {
+            src: other,
+            dest: "autoinstall.yaml".to_string(),
+        }];
+        assert!(collect_iso_extras(dir.path(), &dup).is_err());
+
+        let missing = vec![IsoExtra {
+            src: dir.path().join("nope"),
+            dest: "nope".to_string(),
+        }];
+        assert!(collect_iso_extras(dir.path(), &missing).is_err());
+    }
+}
@@ -0,0 +1,163 @@
+//! ISO volume label (`--label`).
+//!
+//! The label is how the live initramfs finds its boot medium
+//! (`root=LABEL=` in the UKI and GRUB cmdlines, `LABEL=` in the init
+//! script), so it is baked into the initramfs as well as set on the ISO.
+//! The live initramfs build records the label it used in the output
+//! directory, like the compression, and the ISO build refuses to package
+//! an initramfs that looks for a different one.
+//!
+//! Labels must be valid ISO 9660 volume identifiers: up to 32 characters,
+//! uppercase letters, digits, `_` and `-` (which xorriso and blkid accept).
+
+use anyhow::{bail, Result};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use distro_spec::acorn::ISO_LABEL;
+
+/// Records which label the current live initramfs was built for.
+pub const LABEL_MARKER: &str = ".iso-label";
+
+/// Environment variable overriding the default label.
+pub const LABEL_ENV: &str = "ISO_LABEL";
+
+/// Longest ISO 9660 volume identifier.
+const MAX_LEN: usize = 32;
+
+/// A validated ISO volume label.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct IsoLabel(String);
+
+impl Default for IsoLabel {
+    fn default() -> Self {
+        Self(ISO_LABEL.to_string())
+    }
+}
+
+impl IsoLabel {
+    /// `label` if it is a valid volume label.
+    pub fn new(label: &str) -> Result<Self> {
+        if label.is_empty() {
+            bail!("ISO label must not be empty");
+        }
+        if label.len() > MAX_LEN {
+            bail!(
+                "ISO label '{}' is {} characters long (at most {})",
+                label,
+                label.len(),
+                MAX_LEN
+            );
+        }
+        if let Some(bad) = label
+            .chars()
+            .find(|c| !(c.is_ascii_uppercase() || c.is_ascii_digit() || *c == '_' || *c == '-'))
+        {
+            let hint = if bad.is_ascii_lowercase() {
+                format!(" (use '{}')", label.to_ascii_uppercase())
+            } else {
+                String::new()
+            };
+            bail!(
+                "ISO label '{}' contains '{}'; only A-Z, 0-9, '_' and '-' are allowed{}",
+                label,
+                bad,
+                hint
+            );
+        }
+        Ok(Self(label.to_string()))
+    }
+
+    /// `cli`, else `$ISO_LABEL`, else the distro default.
+    pub fn resolve(cli: Option<IsoLabel>) -> Result<Self> {
+        if let Some(label) = cli {
+            return Ok(label);
+        }
+        match std::env::var(LABEL_ENV) {
+            Ok(label) if !label.is_empty() => {
+                Self::new(&label).map_err(|e| anyhow::anyhow!("Invalid ${}: {}", LABEL_ENV, e))
+            }
+            _ => Ok(Self::default()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether this is the distro's own label.
+    pub fn is_default(&self) -> bool {
+        self.0 == ISO_LABEL
+    }
+
+    /// Label the current live initramfs was built for (the default if
+    /// none recorded).
+    pub fn load(output_dir: &Path) -> Self {
+        fs::read_to_string(output_dir.join(LABEL_MARKER))
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// Record this as the label of the current live initramfs.
+    pub fn save(&self, output_dir: &Path) -> Result<()> {
+        fs::write(output_dir.join(LABEL_MARKER), &self.0)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for IsoLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for IsoLabel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::new(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iso_label_validation() {
+        assert_eq!(IsoLabel::default().as_str(), ISO_LABEL);
+        assert!(IsoLabel::default().is_default());
+        for good in ["ACORNOS", "ACORN_TEST-2", &"A".repeat(32)] {
+            assert_eq!(good.parse::<IsoLabel>().unwrap().as_str(), good);
+        }
+        for (bad, message) in [
+            ("", "must not be empty"),
+            (&"A".repeat(33), "33 characters long"),
+            (
+                "AcornOS",
+                "contains 'c'; only A-Z, 0-9, '_' and '-' are allowed (use 'ACORNOS')",
+            ),
+            ("ACORN OS", "contains ' '"),
+            ("ACORN.OS", "contains '.'"),
+        ] {
+            let err = bad.parse::<IsoLabel>().unwrap_err().to_string();
+            assert!(err.contains(message), "{}: {}", bad, err);
+        }
+    }
+
+    #[test]
+    fn test_iso_label_marker() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(IsoLabel::load(dir.path()), IsoLabel::default());
+        let label = IsoLabel::new("ACORN_DEV").unwrap();
+        label.save(dir.path()).unwrap();
+        assert_eq!(IsoLabel::load(dir.path()), label);
+        assert_eq!(IsoLabel::resolve(Some(label.clone())).unwrap(), label);
+        // A corrupt marker falls back to the default
+        fs::write(dir.path().join(LABEL_MARKER), "bad label").unwrap();
+        assert_eq!(IsoLabel::load(dir.path()), IsoLabel::default());
+    }
+}
@@ -0,0 +1,305 @@
+//! Offline ISO inspection (`acornos verify-iso`).
+//!
+//! Lists a built ISO with `xorriso` instead of booting it, so a broken
+//! image is caught in seconds: volume label, EFI loader, rootfs image, live
+//! overlay, the kernel/initramfs the boot path expects (UKIs on x86_64,
+//! GRUB's kernel elsewhere or with `--bios`), the SHA512 sidecar, and the
+//! El Torito EFI image (listed with `mdir`, from mtools). Results use the
+//! preflight [`CheckResult`] so the checklist reads the same.
+
+use anyhow::{bail, Result};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use super::full_initramfs::INITRAMFS_INSTALLED_ISO_PATH;
+use super::grub_bios::{pick_efi_image, BIOS_IMAGE_ISO_PATH};
+use super::grub_efi;
+use super::RootfsFormat;
+use crate::arch::Arch;
+use crate::preflight::CheckResult;
+use crate::signing::{sha512_path, verify_sha512};
+use distro_builder::process::{which, Cmd};
+
+/// Volume id and file list of an ISO.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IsoListing {
+    pub volume_id: Option<String>,
+    /// Absolute paths (`/live/overlay/etc/inittab`), files and directories.
+    pub paths: BTreeSet<String>,
+}
+
+impl IsoListing {
+    /// List `iso` with `xorriso -pvd_info -find /`.
+    pub fn read(iso: &Path) -> Result<Self> {
+        let result = Cmd::new("xorriso")
+            .arg("-indev")
+            .arg_path(iso)
+            .args(["-pvd_info", "-find", "/"])
+            .error_msg("xorriso failed to list the ISO. Install: sudo dnf install xorriso")
+            .run()?;
+        Ok(Self::parse(&result.stdout))
+    }
+
+    /// Parse `xorriso -pvd_info -find /` output: a `Volume Id : ...` line
+    /// and one quoted path per entry.
+    pub fn parse(output: &str) -> Self {
+        let mut listing = Self::default();
+        for line in output.lines() {
+            if let Some(rest) = line.strip_prefix("Volume Id") {
+                let id = rest.trim_start().trim_start_matches(':').trim();
+                if !id.is_empty() {
+                    listing.volume_id = Some(id.to_string());
+                }
+            } else if let Some(path) = line.trim().strip_prefix("'/") {
+                let path = path.strip_suffix('\'').unwrap_or(path);
+                listing
+                    .paths
+                    .insert(format!("/{}", path.replace("'\"'\"'", "'")));
+            }
+        }
+        listing
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.paths
+            .contains(&format!("/{}", path.trim_start_matches('/')))
+    }
+}
+
+/// Checks on the ISO listing; `label` is the expected volume label.
+pub fn check_listing(listing: &IsoListing, arch: Arch, label: &str) -> Vec<CheckResult> {
+    let mut checks = Vec::new();
+    let rebuild = "Rebuild with: cargo run -- iso";
+
+    checks.push(match &listing.volume_id {
+        Some(id) if id == label => CheckResult::pass("Volume label", id.clone()),
+        Some(id) => CheckResult::fail(
+            "Volume label",
+            format!("{} (expected {})", id, label),
+            "root=LABEL= will not find the ISO. Rebuild the initramfs and ISO with a matching --label",
+        ),
+        None => CheckResult::fail("Volume label", "no volume id found", rebuild),
+    });
+
+    let mut require = |name: &str, path: &str| {
+        checks.push(if listing.contains(path) {
+            CheckResult::pass(name, path.trim_start_matches('/'))
+        } else {
+            CheckResult::fail(
+                name,
+                format!("{} missing", path.trim_start_matches('/')),
+                rebuild,
+            )
+        });
+    };
+    require("EFI loader", arch.efi_boot_path());
+    require("Live overlay", "/live/overlay");
+    require("Live inittab", "/live/overlay/etc/inittab");
+
+    // x86_64 boots UKIs from EFI/Linux/; GRUB boots the kernel directly
+    let bios = listing.contains(BIOS_IMAGE_ISO_PATH);
+    if arch != Arch::X86_64 || bios {
+        require("Kernel", &grub_efi::kernel_iso_path(arch));
+        require("Live initramfs", &grub_efi::initramfs_iso_path(arch));
+    }
+    if arch != Arch::X86_64 {
+        require("GRUB EFI image", &grub_efi::efi_image_iso_path(arch));
+    }
+
+    let rootfs = RootfsFormat::ALL
+        .into_iter()
+        .map(|f| f.iso_path())
+        .find(|p| listing.contains(p));
+    checks.push(match rootfs {
+        Some(path) => CheckResult::pass("Rootfs image", path),
+        None => CheckResult::fail(
+            "Rootfs image",
+            format!("{} missing", RootfsFormat::default().iso_path()),
+            rebuild,
+        ),
+    });
+
+    if arch == Arch::X86_64 {
+        let ukis: Vec<&str> = listing
+            .paths
+            .iter()
+            .filter(|p| p.starts_with("/EFI/Linux/") && p.ends_with(".efi"))
+            .map(|p| p.trim_start_matches("/EFI/Linux/"))
+            .collect();
+        checks.push(if ukis.is_empty() {
+            CheckResult::fail("Boot UKIs", "no EFI/Linux/*.efi", rebuild)
+        } else {
+            CheckResult::pass("Boot UKIs", ukis.join(", "))
+        });
+    }
+    if bios {
+        checks.push(CheckResult::pass("BIOS boot", BIOS_IMAGE_ISO_PATH));
+    }
+
+    checks.push(if listing.contains(INITRAMFS_INSTALLED_ISO_PATH) {
+        CheckResult::pass("Installed initramfs", INITRAMFS_INSTALLED_ISO_PATH)
+    } else {
+        CheckResult::warn(
+            "Installed initramfs",
+            "not on the ISO (optional; installs need 'acornos initramfs --installed')",
+        )
+    });
+    checks
+}
+
+/// `iso` against its `.sha512` sidecar.
+pub fn check_checksum(iso: &Path) -> CheckResult {
+    let sidecar = sha512_path(iso);
+    if !sidecar.exists() {
+        return CheckResult::fail(
+            "SHA512",
+            format!("{} not found", sidecar.display()),
+            "The build writes it next to the ISO; rebuild, or create it with sha512sum",
+        );
+    }
+    match verify_sha512(iso) {
+        Ok(()) => CheckResult::pass("SHA512", "matches sidecar"),
+        Err(e) => CheckResult::fail(
+            "SHA512",
+            e.to_string(),
+            "The ISO changed after the build or is truncated; rebuild or re-download it",
+        ),
+    }
+}
+
+/// Whether the El Torito EFI image of `iso` holds the loader for `arch`.
+pub fn check_efi_image(iso: &Path, arch: Arch, work_dir: &Path) -> CheckResult {
+    let name = "EFI boot image";
+    if which("mdir").is_none() {
+        return CheckResult::fail(
+            name,
+            "mdir not found",
+            "Install mtools: sudo dnf install mtools",
+        );
+    }
+    match efi_image_loaders(iso, work_dir) {
+        Ok(files) => {
+            let loader = arch.efi_boot_path().rsplit('/').next().unwrap_or_default();
+            if files.iter().any(|f| f.eq_ignore_ascii_case(loader)) {
+                CheckResult::pass(name, format!("contains EFI/BOOT/{}", loader))
+            } else {
+                CheckResult::fail(
+                    name,
+                    format!("EFI/BOOT/{} missing (found: {})", loader, files.join(", ")),
+                    "UEFI firmware boots from this image; rebuild with: cargo run -- iso",
+                )
+            }
+        }
+        Err(e) => CheckResult::fail(name, format!("{:#}", e), "Rebuild with: cargo run -- iso"),
+    }
+}
+
+/// File names in `EFI/BOOT` of the El Torito EFI image.
+fn efi_image_loaders(iso: &Path, work_dir: &Path) -> Result<Vec<String>> {
+    let _ = fs::remove_dir_all(work_dir);
+    fs::create_dir_all(work_dir)?;
+    Cmd::new("xorriso")
+        .args(["-osirrox", "on", "-indev"])
+        .arg_path(iso)
+        .arg("-extract_boot_images")
+        .arg_path(work_dir)
+        .error_msg("xorriso failed to extract the boot images")
+        .run()?;
+    let mut names: Vec<String> = fs::read_dir(work_dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    let Some(image) = pick_efi_image(&names) else {
+        bail!("no EFI El Torito image (extracted: {})", names.join(", "));
+    };
+    let image = work_dir.join(image);
+    let result = Cmd::new("mdir")
+        .args(["-b", "-i"])
+        .arg_path(&image)
+        .arg("::/EFI/BOOT")
+        .error_msg("mdir could not read EFI/BOOT in the EFI image")
+        .run()?;
+    Ok(result
+        .stdout
+        .lines()
+        .filter_map(|l| l.trim().rsplit('/').next())
+        .filter(|n| !n.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// All checks for `iso`, with scratch files under `work_dir`.
+pub fn verify_iso_file(iso: &Path, arch: Arch, label: &str, work_dir: &Path) -> Vec<CheckResult> {
+    let mut checks = match IsoListing::read(iso) {
+        Ok(listing) => check_listing(&listing, arch, label),
+        Err(e) => vec![CheckResult::fail(
+            "ISO listing",
+            format!("{:#}", e),
+            "Is this an ISO 9660 image?",
+        )],
+    };
+    checks.push(check_checksum(iso));
+    checks.push(check_efi_image(iso, arch, work_dir));
+    let _ = fs::remove_dir_all(work_dir);
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LISTING: &str = "\
+xorriso 1.5.6 : RockRidge filesystem manipulator
+Volume Id    : ACORNOS
+Volume Set Id: ACORNOS
+'/'
+'/EFI'
+'/EFI/BOOT'
+'/EFI/BOOT/BOOTX64.EFI'
+'/EFI/Linux'
+'/EFI/Linux/acornos.efi'
+'/live'
+'/live/filesystem.erofs'
+'/live/overlay'
+'/live/overlay/etc'
+'/live/overlay/etc/inittab'
+";
+
+    #[test]
+    fn test_parse_and_check_listing() {
+        let listing = IsoListing::parse(LISTING);
+        assert_eq!(listing.volume_id.as_deref(), Some("ACORNOS"));
+        assert!(listing.contains("live/overlay/etc/inittab"));
+        assert!(listing.contains("/EFI/BOOT/BOOTX64.EFI"));
+
+        let checks = check_listing(&listing, Arch::X86_64, "ACORNOS");
+        assert!(checks.iter().all(|c| c.passed), "{:?}", checks);
+        let ukis = checks.iter().find(|c| c.name == "Boot UKIs").unwrap();
+        assert_eq!(ukis.message, "acornos.efi");
+
+        // Wrong label, no inittab, and an aarch64 ISO without GRUB's kernel
+        let broken = IsoListing::parse(
+            &LISTING
+                .replace("Volume Id    : ACORNOS", "Volume Id    : OTHER")
+                .replace("'/live/overlay/etc/inittab'\n", ""),
+        );
+        let failed: Vec<String> = check_listing(&broken, Arch::X86_64, "ACORNOS")
+            .into_iter()
+            .filter(|c| !c.passed)
+            .map(|c| c.name)
+            .collect();
+        assert_eq!(failed, ["Volume label", "Live inittab"]);
+
+        let failed: Vec<String> = check_listing(&listing, Arch::Aarch64, "ACORNOS")
+            .into_iter()
+            .filter(|c| !c.passed)
+            .map(|c| c.name)
+            .collect();
+        assert_eq!(
+            failed,
+            ["EFI loader", "Kernel", "Live initramfs", "GRUB EFI image"]
+        );
+    }
+}
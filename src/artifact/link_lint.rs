@@ -0,0 +1,380 @@
+//! Symlink lint for the rootfs staging tree.
+//!
+//! Runs right before image creation, next to `verify_staging`. Catches links
+//! that only fail at boot: runlevel entries whose init script was removed,
+//! busybox applet links in the bin dirs, and libraries left dangling by
+//! CopyAllLibraries.
+//!
+//! Targets are resolved inside the staging root: absolute targets are
+//! staging-relative, `..` cannot climb above the root, and chains longer
+//! than `MAX_LINK_HOPS` are reported as loops.
+
+use anyhow::{bail, Result};
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Same limit as the kernel's MAXSYMLINKS.
+pub const MAX_LINK_HOPS: usize = 40;
+
+/// Directories whose dangling links are reported as broken applets.
+const APPLET_DIRS: &[&str] = &["bin", "sbin", "usr/bin", "usr/sbin"];
+
+/// Outcome of resolving a path inside the staging root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// Resolved to an existing path (absolute, inside the root).
+    Found(PathBuf),
+    /// Some component does not exist.
+    Missing,
+    /// Too many symlink hops (cycle or absurd chain).
+    Loop,
+}
+
+/// Resolve `rel` (relative to `root`) following symlinks within `root`.
+pub fn resolve_in_root(root: &Path, rel: &Path) -> Resolution {
+    let mut pending: VecDeque<OsString> = components(rel).into();
+    let mut resolved = PathBuf::new();
+    let mut hops = 0;
+
+    while let Some(part) = pending.pop_front() {
+        if part == ".." {
+            resolved.pop();
+            continue;
+        }
+        let candidate = resolved.join(&part);
+        let meta = match fs::symlink_metadata(root.join(&candidate)) {
+            Ok(meta) => meta,
+            Err(_) => return Resolution::Missing,
+        };
+        if !meta.file_type().is_symlink() {
+            resolved = candidate;
+            continue;
+        }
+
+        hops += 1;
+        if hops > MAX_LINK_HOPS {
+            return Resolution::Loop;
+        }
+        let target = match fs::read_link(root.join(&candidate)) {
+            Ok(target) => target,
+            Err(_) => return Resolution::Missing,
+        };
+        if target.is_absolute() {
+            resolved = PathBuf::new();
+        }
+        for part in components(&target).into_iter().rev() {
+            pending.push_front(part);
+        }
+    }
+
+    Resolution::Found(root.join(resolved))
+}
+
+/// Path components as strings, with `..` kept and `/` and `.` dropped.
+fn components(path: &Path) -> Vec<OsString> {
+    path.components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part.to_os_string()),
+            Component::ParentDir => Some(OsString::from("..")),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Category of a link problem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LinkIssueKind {
+    /// `etc/runlevels/<level>/<svc>` without `etc/init.d/<svc>` (always an error).
+    OrphanedRunlevel,
+    /// Dangling link in a bin dir (usually a busybox applet).
+    BrokenApplet,
+    /// Any other dangling link.
+    Dangling,
+    /// Symlink cycle.
+    Loop,
+}
+
+impl fmt::Display for LinkIssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::OrphanedRunlevel => "orphaned runlevel links",
+            Self::BrokenApplet => "broken applet links",
+            Self::Dangling => "dangling links",
+            Self::Loop => "symlink loops",
+        })
+    }
+}
+
+/// A single problem found in the staging tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkIssue {
+    pub kind: LinkIssueKind,
+    /// Path relative to the staging root.
+    pub path: PathBuf,
+    /// Raw link target (empty for non-symlink runlevel entries).
+    pub target: PathBuf,
+}
+
+/// All link problems in a staging tree.
+#[derive(Debug, Default)]
+pub struct LinkReport {
+    pub issues: Vec<LinkIssue>,
+}
+
+impl LinkReport {
+    /// Whether an issue fails the build.
+    pub fn is_error(kind: LinkIssueKind, strict: bool) -> bool {
+        strict || kind == LinkIssueKind::OrphanedRunlevel
+    }
+
+    /// Number of issues that fail the build.
+    pub fn error_count(&self, strict: bool) -> usize {
+        self.issues
+            .iter()
+            .filter(|i| Self::is_error(i.kind, strict))
+            .count()
+    }
+
+    /// Print issues grouped by category.
+    pub fn print(&self, strict: bool) {
+        let mut kinds: Vec<LinkIssueKind> = self.issues.iter().map(|i| i.kind).collect();
+        kinds.sort_unstable();
+        kinds.dedup();
+        for kind in kinds {
+            let issues: Vec<&LinkIssue> = self.issues.iter().filter(|i| i.kind == kind).collect();
+            let level = if Self::is_error(kind, strict) {
+                "ERROR"
+            } else {
+                "WARN"
+            };
+            log::info!("  [{}] {} {}:", level, issues.len(), kind);
+            for issue in issues {
+                if issue.target.as_os_str().is_empty() {
+                    log::info!("    /{}", issue.path.display());
+                } else {
+                    log::info!(
+                        "    /{} -> {}",
+                        issue.path.display(),
+                        issue.target.display()
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Walk the staging tree and collect link problems.
+pub fn lint_staging(root: &Path) -> Result<LinkReport> {
+    let mut report = LinkReport::default();
+    walk(root, Path::new(""), &mut report)?;
+    check_runlevels(root, &mut report)?;
+    report
+        .issues
+        .sort_by(|a, b| (a.kind, &a.path).cmp(&(b.kind, &b.path)));
+    Ok(report)
+}
+
+fn walk(root: &Path, rel: &Path, report: &mut LinkReport) -> Result<()> {
+    let mut entries: Vec<_> = fs::read_dir(root.join(rel))?.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        let path = rel.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            walk(root, &path, report)?;
+            continue;
+        }
+        if !file_type.is_symlink() || path.starts_with("etc/runlevels") {
+            continue;
+        }
+
+        let kind = match resolve_in_root(root, &path) {
+            Resolution::Found(_) => continue,
+            Resolution::Loop => LinkIssueKind::Loop,
+            Resolution::Missing
+                if APPLET_DIRS
+                    .iter()
+                    .any(|d| Some(Path::new(d)) == path.parent()) =>
+            {
+                LinkIssueKind::BrokenApplet
+            }
+            Resolution::Missing => LinkIssueKind::Dangling,
+        };
+        report.issues.push(LinkIssue {
+            kind,
+            target: fs::read_link(root.join(&path))?,
+            path,
+        });
+    }
+    Ok(())
+}
+
+/// Flag `etc/runlevels/*/*` entries without a matching init script.
+fn check_runlevels(root: &Path, report: &mut LinkReport) -> Result<()> {
+    let runlevels = root.join("etc/runlevels");
+    if !runlevels.is_dir() {
+        return Ok(());
+    }
+
+    for level in fs::read_dir(&runlevels)? {
+        let level = level?;
+        if !level.file_type()?.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(level.path())? {
+            let entry = entry?;
+            let script = Path::new("etc/init.d").join(entry.file_name());
+            if matches!(resolve_in_root(root, &script), Resolution::Found(p) if p.is_file()) {
+                continue;
+            }
+            let path = Path::new("etc/runlevels")
+                .join(level.file_name())
+                .join(entry.file_name());
+            report.issues.push(LinkIssue {
+                kind: LinkIssueKind::OrphanedRunlevel,
+                target: fs::read_link(entry.path()).unwrap_or_default(),
+                path,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Lint the staging tree, printing a report and failing on errors.
+///
+/// Orphaned runlevel links always fail; other problems fail only when
+/// `strict` is set (`--strict-links`).
+pub fn check_staging_links(staging: &Path, strict: bool) -> Result<()> {
+    log::info!("\n  Checking staging symlinks...");
+    let report = lint_staging(staging)?;
+
+    if report.issues.is_empty() {
+        log::info!("  ✓ No broken symlinks");
+        return Ok(());
+    }
+
+    report.print(strict);
+    let errors = report.error_count(strict);
+    if errors > 0 {
+        bail!(
+            "Staging symlink check FAILED: {} error(s).\n\
+             Fix the component definitions{}.",
+            errors,
+            if strict {
+                " or drop --strict-links"
+            } else {
+                ""
+            }
+        );
+    }
+    log::info!(
+        "  {} warning(s); pass --strict-links to make them fatal",
+        report.issues.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_resolve_absolute_and_relative() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("usr/lib")).unwrap();
+        fs::create_dir_all(root.join("lib")).unwrap();
+        fs::write(root.join("usr/lib/libz.so.1.3"), "").unwrap();
+
+        // Relative target with ..
+        symlink("../usr/lib/libz.so.1.3", root.join("lib/libz.so.1")).unwrap();
+        // Absolute target is staging-relative, not host-relative
+        symlink("/usr/lib/libz.so.1.3", root.join("usr/lib/libz.so")).unwrap();
+        // Chain through another link
+        symlink("libz.so", root.join("usr/lib/libz.so.chain")).unwrap();
+        // .. above the root is clamped, like chroot
+        symlink("../../../../usr/lib/libz.so.1.3", root.join("lib/deep")).unwrap();
+        // Absolute target that only exists on the host
+        symlink("/proc/self/exe", root.join("lib/host-only")).unwrap();
+
+        let expected = Resolution::Found(root.join("usr/lib/libz.so.1.3"));
+        for link in [
+            "lib/libz.so.1",
+            "usr/lib/libz.so",
+            "usr/lib/libz.so.chain",
+            "lib/deep",
+        ] {
+            assert_eq!(resolve_in_root(root, Path::new(link)), expected, "{}", link);
+        }
+        assert_eq!(
+            resolve_in_root(root, Path::new("lib/host-only")),
+            Resolution::Missing
+        );
+    }
+
+    #[test]
+    fn test_resolve_through_symlinked_dir_and_loops() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("usr/lib")).unwrap();
+        fs::write(root.join("usr/lib/ld.so"), "").unwrap();
+        symlink("usr/lib", root.join("lib64")).unwrap();
+        assert_eq!(
+            resolve_in_root(root, Path::new("lib64/ld.so")),
+            Resolution::Found(root.join("usr/lib/ld.so"))
+        );
+
+        symlink("b", root.join("a")).unwrap();
+        symlink("/a", root.join("b")).unwrap();
+        symlink("self", root.join("self")).unwrap();
+        assert_eq!(resolve_in_root(root, Path::new("a")), Resolution::Loop);
+        assert_eq!(resolve_in_root(root, Path::new("self")), Resolution::Loop);
+    }
+
+    #[test]
+    fn test_lint_staging_categories_and_strictness() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        for d in ["etc/init.d", "etc/runlevels/default", "bin", "usr/lib"] {
+            fs::create_dir_all(root.join(d)).unwrap();
+        }
+        fs::write(root.join("etc/init.d/sshd"), "").unwrap();
+        fs::write(root.join("bin/busybox"), "").unwrap();
+        symlink("/etc/init.d/sshd", root.join("etc/runlevels/default/sshd")).unwrap();
+        symlink("/etc/init.d/gone", root.join("etc/runlevels/default/gone")).unwrap();
+        symlink("/bin/busybox", root.join("bin/ls")).unwrap();
+        symlink("/bin/missing", root.join("bin/vi")).unwrap();
+        symlink("libgone.so.1", root.join("usr/lib/libgone.so")).unwrap();
+
+        let report = lint_staging(root).unwrap();
+        let found: Vec<(LinkIssueKind, &str)> = report
+            .issues
+            .iter()
+            .map(|i| (i.kind, i.path.to_str().unwrap()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    LinkIssueKind::OrphanedRunlevel,
+                    "etc/runlevels/default/gone"
+                ),
+                (LinkIssueKind::BrokenApplet, "bin/vi"),
+                (LinkIssueKind::Dangling, "usr/lib/libgone.so"),
+            ]
+        );
+        assert_eq!(report.error_count(false), 1);
+        assert_eq!(report.error_count(true), 3);
+        assert!(check_staging_links(root, false).is_err());
+
+        fs::remove_file(root.join("etc/runlevels/default/gone")).unwrap();
+        assert!(check_staging_links(root, false).is_ok());
+        assert!(check_staging_links(root, true).is_err());
+    }
+}
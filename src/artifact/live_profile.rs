@@ -0,0 +1,136 @@
+//! Testing or release live ISO (`acornos iso --profile`).
+//!
+//! The testing profile (the default) is what `acornos test` and the
+//! install-tests boot: root autologins on the serial console, root has an
+//! empty password, and `00-acorn-test.sh` prints the `___SHELL_READY___`
+//! markers. The release profile is for ISOs handed to users:
+//!
+//! - no `00-acorn-test.sh` and no empty-password shadow from
+//!   `profile/live-overlay` (root stays locked, as in the rootfs)
+//! - a normal login getty on ttyS0
+//! - tty1 autologins as the live user, so it needs `--live-user live`
+//!
+//! Both write [`PROFILE_ISO_PATH`] to the ISO root, holding the profile
+//! name, so `acornos test` can refuse a release ISO instead of timing out
+//! on markers that never come. ISOs from before the marker are testing ISOs.
+
+use anyhow::{bail, Result};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use distro_builder::process::Cmd;
+
+/// Profile marker in the ISO root.
+pub const PROFILE_ISO_PATH: &str = ".acorn-profile";
+
+/// Marker written in the output dir, then added to the ISO.
+pub const PROFILE_MARKER: &str = "acorn-profile";
+
+/// Test instrumentation in the live overlay.
+pub const TEST_INSTRUMENTATION: &str = "etc/profile.d/00-acorn-test.sh";
+
+/// Which live session the ISO boots into.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LiveProfile {
+    /// Serial root autologin and test markers, for `acornos test`.
+    #[default]
+    Testing,
+    /// No test instrumentation, locked root, login on ttyS0.
+    Release,
+}
+
+impl fmt::Display for LiveProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Testing => "testing",
+            Self::Release => "release",
+        })
+    }
+}
+
+impl FromStr for LiveProfile {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "testing" => Ok(Self::Testing),
+            "release" => Ok(Self::Release),
+            other => bail!(
+                "Unknown ISO profile '{}' (expected testing or release)",
+                other
+            ),
+        }
+    }
+}
+
+/// Drop the test instrumentation and the empty-password shadow from the
+/// live overlay. The live user then seeds shadow from the rootfs staging.
+pub fn strip_test_instrumentation(overlay: &Path) -> Result<()> {
+    for file in [TEST_INSTRUMENTATION, "etc/shadow"] {
+        match fs::remove_file(overlay.join(file)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Write the profile marker into `output_dir`, for the ISO root.
+pub fn write_marker(output_dir: &Path, profile: LiveProfile) -> Result<PathBuf> {
+    let path = output_dir.join(PROFILE_MARKER);
+    fs::write(&path, format!("{}\n", profile))?;
+    Ok(path)
+}
+
+/// Profile of a built ISO, read from its marker; `Testing` without one.
+pub fn read_iso_profile(iso: &Path, scratch: &Path) -> Result<LiveProfile> {
+    let _ = fs::remove_file(scratch);
+    Cmd::new("xorriso")
+        .args(["-osirrox", "on", "-indev"])
+        .arg_path(iso)
+        .arg("-extract")
+        .arg(format!("/{}", PROFILE_ISO_PATH))
+        .arg_path(scratch)
+        .allow_fail()
+        .run()?;
+    let content = fs::read_to_string(scratch).ok();
+    let _ = fs::remove_file(scratch);
+    match content {
+        Some(content) => content.parse(),
+        None => Ok(LiveProfile::Testing),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_marker_and_release_overlay() {
+        let dir = tempfile::tempdir().unwrap();
+        let overlay = dir.path().join("overlay");
+        fs::create_dir_all(overlay.join("etc/profile.d")).unwrap();
+        fs::write(
+            overlay.join(TEST_INSTRUMENTATION),
+            "echo ___SHELL_READY___\n",
+        )
+        .unwrap();
+        fs::write(overlay.join("etc/profile.d/live-docs.sh"), "").unwrap();
+        fs::write(overlay.join("etc/shadow"), "root::19000:0:99999:7:::\n").unwrap();
+
+        strip_test_instrumentation(&overlay).unwrap();
+        // Idempotent
+        strip_test_instrumentation(&overlay).unwrap();
+        assert!(!overlay.join(TEST_INSTRUMENTATION).exists());
+        assert!(!overlay.join("etc/shadow").exists());
+        assert!(overlay.join("etc/profile.d/live-docs.sh").exists());
+
+        let marker = write_marker(dir.path(), LiveProfile::Release).unwrap();
+        let profile: LiveProfile = fs::read_to_string(marker).unwrap().parse().unwrap();
+        assert_eq!(profile, LiveProfile::Release);
+        assert_eq!(LiveProfile::default().to_string(), "testing");
+        assert!("debug".parse::<LiveProfile>().is_err());
+    }
+}
@@ -0,0 +1,131 @@
+//! SSH login to the live session (`--ssh`), for `acornos run --ssh`.
+//!
+//! The live overlay gets root's `authorized_keys` (the host user's
+//! `~/.ssh/id_*.pub`), a pre-generated ed25519 host key so sshd is up
+//! without waiting for `sshd-keygen`, and an explicit sshd enable. Nothing
+//! is written to the rootfs staging tree, so the rootfs image never
+//! carries the keys.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use distro_builder::process::Cmd;
+
+/// Host key generated into the overlay.
+const HOST_KEY: &str = "etc/ssh/ssh_host_ed25519_key";
+
+/// The host user's public keys (`~/.ssh/id_*.pub`), sorted.
+pub fn host_public_keys(home: &Path) -> Vec<PathBuf> {
+    let mut keys: Vec<PathBuf> = fs::read_dir(home.join(".ssh"))
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with("id_") && n.ends_with(".pub"))
+        })
+        .collect();
+    keys.sort();
+    keys
+}
+
+/// `authorized_keys` from the given public key files.
+pub fn authorized_keys(keys: &[PathBuf]) -> Result<String> {
+    let mut out = String::new();
+    for key in keys {
+        let text =
+            fs::read_to_string(key).with_context(|| format!("Failed to read {}", key.display()))?;
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            out.push_str(line.trim());
+            out.push('\n');
+        }
+    }
+    Ok(out)
+}
+
+/// Whether a built live overlay allows `acornos run --ssh` logins.
+pub fn overlay_has_ssh(overlay: &Path) -> bool {
+    overlay.join("root/.ssh/authorized_keys").is_file()
+}
+
+/// Add SSH access to the live overlay.
+///
+/// `staging` is the rootfs staging tree (checked for sshd), `home` the
+/// host user's home directory.
+pub fn apply_live_ssh(overlay: &Path, staging: &Path, home: &Path) -> Result<()> {
+    if !staging.join("etc/init.d/sshd").exists() {
+        bail!("--ssh needs the ssh component, which is disabled for this rootfs");
+    }
+    let keys = host_public_keys(home);
+    if keys.is_empty() {
+        bail!(
+            "--ssh found no public keys at {}/id_*.pub.\nCreate one with: ssh-keygen -t ed25519",
+            home.join(".ssh").display()
+        );
+    }
+
+    let ssh_dir = overlay.join("root/.ssh");
+    fs::create_dir_all(&ssh_dir)?;
+    fs::set_permissions(&ssh_dir, fs::Permissions::from_mode(0o700))?;
+    let authorized = ssh_dir.join("authorized_keys");
+    fs::write(&authorized, authorized_keys(&keys)?)?;
+    fs::set_permissions(&authorized, fs::Permissions::from_mode(0o600))?;
+
+    let host_key = overlay.join(HOST_KEY);
+    fs::create_dir_all(host_key.parent().unwrap_or(overlay))?;
+    let _ = fs::remove_file(&host_key);
+    let _ = fs::remove_file(host_key.with_extension("pub"));
+    Cmd::new("ssh-keygen")
+        .args(["-q", "-t", "ed25519", "-N", "", "-C", "acornos-live", "-f"])
+        .arg_path(&host_key)
+        .error_msg("ssh-keygen failed. Install: sudo dnf install openssh")
+        .run()?;
+
+    let enable = overlay.join("etc/runlevels/default/sshd");
+    fs::create_dir_all(enable.parent().unwrap_or(overlay))?;
+    if fs::symlink_metadata(&enable).is_err() {
+        symlink("/etc/init.d/sshd", &enable)?;
+    }
+
+    log::info!(
+        "  Live SSH: {} key(s) for root, host key {}",
+        keys.len(),
+        HOST_KEY
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_host_public_keys_and_authorized_keys() {
+        let dir = tempdir().unwrap();
+        let ssh = dir.path().join(".ssh");
+        fs::create_dir_all(&ssh).unwrap();
+        fs::write(ssh.join("id_rsa.pub"), "ssh-rsa AAAA user@host\n").unwrap();
+        fs::write(ssh.join("id_ed25519.pub"), "ssh-ed25519 BBBB user@host\n\n").unwrap();
+        fs::write(ssh.join("id_ed25519"), "PRIVATE").unwrap();
+        fs::write(ssh.join("known_hosts.pub"), "not a key").unwrap();
+
+        let keys = host_public_keys(dir.path());
+        assert_eq!(keys, [ssh.join("id_ed25519.pub"), ssh.join("id_rsa.pub")]);
+        assert_eq!(
+            authorized_keys(&keys).unwrap(),
+            "ssh-ed25519 BBBB user@host\nssh-rsa AAAA user@host\n"
+        );
+        assert!(host_public_keys(&dir.path().join("nohome")).is_empty());
+
+        // No sshd in staging: refused before anything is written
+        let overlay = dir.path().join("overlay");
+        let err = apply_live_ssh(&overlay, &dir.path().join("staging"), dir.path());
+        assert!(err.unwrap_err().to_string().contains("ssh component"));
+        assert!(!overlay_has_ssh(&overlay));
+    }
+}
@@ -0,0 +1,255 @@
+//! Optional non-root live user for the live ISO.
+//!
+//! With `--live-user live` (the default) the live overlay gains a `live` user
+//! (uid 1000, groups wheel/audio/video), wheel gets passwordless doas, and
+//! tty1 autologins as that user. ttyS0 keeps root autologin for the test
+//! harness, whose built-in checks run `doas id -u` as the live user
+//! (except on release ISOs, see [`super::live_profile`]).
+//!
+//! The overlay replaces whole files, so passwd/group/shadow/doas.conf are
+//! seeded from the rootfs staging before the user is added (the live
+//! inittab is already in the overlay, see [`crate::component::inittab`]). The home
+//! directory is created from /etc/skel at boot by the `live-user-home`
+//! service, so it lives on the tmpfs upper layer.
+
+use anyhow::{bail, Result};
+use std::fmt;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::component::accounts::append_entry;
+
+/// Name of the non-root live user.
+pub const LIVE_USER_NAME: &str = "live";
+
+/// UID/GID of the live user.
+pub const LIVE_USER_UID: u32 = 1000;
+
+/// Supplementary groups for the live user (Alpine GIDs).
+pub const LIVE_USER_GROUPS: &[(&str, u32)] = &[("wheel", 10), ("audio", 18), ("video", 27)];
+
+/// Overlay files seeded from staging before they are modified.
+const SEEDED_FILES: &[&str] = &["passwd", "group", "shadow", "doas.conf", "inittab"];
+
+/// Which user the live session logs into on tty1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LiveUser {
+    /// Root autologin on tty1 (legacy behaviour, some test rigs rely on it).
+    None,
+    /// Autologin as the `live` user with doas rights.
+    #[default]
+    Live,
+}
+
+impl LiveUser {
+    /// User name, if a live user is created.
+    pub fn name(self) -> Option<&'static str> {
+        match self {
+            Self::None => None,
+            Self::Live => Some(LIVE_USER_NAME),
+        }
+    }
+}
+
+impl fmt::Display for LiveUser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name().unwrap_or("none"))
+    }
+}
+
+impl FromStr for LiveUser {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "none" => Ok(Self::None),
+            "live" => Ok(Self::Live),
+            other => bail!("Unknown live user '{}' (expected none or live)", other),
+        }
+    }
+}
+
+/// A user account to ensure in an /etc tree.
+#[derive(Debug, Clone)]
+pub struct UserSpec<'a> {
+    pub name: &'a str,
+    pub uid: u32,
+    pub gid: u32,
+    pub home: &'a str,
+    pub shell: &'a str,
+    /// Supplementary groups as (name, gid); created if missing.
+    pub groups: &'a [(&'a str, u32)],
+}
+
+/// Ensure a user, its primary group, and its group memberships exist.
+///
+/// Idempotent: existing entries are kept, missing ones are appended.
+pub fn ensure_user(etc: &Path, user: &UserSpec) -> Result<()> {
+    let passwd = etc.join("passwd");
+    let content = fs::read_to_string(&passwd).unwrap_or_default();
+    let line = format!(
+        "{}:x:{}:{}:{}:{}:{}",
+        user.name, user.uid, user.gid, user.name, user.home, user.shell
+    );
+    fs::write(&passwd, append_entry(&content, user.name, &line))?;
+
+    // Empty password, like root on the live ISO
+    let shadow = etc.join("shadow");
+    let content = fs::read_to_string(&shadow).unwrap_or_default();
+    let line = format!("{}::19000:0:99999:7:::", user.name);
+    fs::write(&shadow, append_entry(&content, user.name, &line))?;
+    fs::set_permissions(&shadow, fs::Permissions::from_mode(0o600))?;
+
+    let group = etc.join("group");
+    let mut content = fs::read_to_string(&group).unwrap_or_default();
+    content = append_entry(
+        &content,
+        user.name,
+        &format!("{}:x:{}:", user.name, user.gid),
+    );
+    for (name, gid) in user.groups {
+        content = append_entry(&content, name, &format!("{}:x:{}:", name, gid));
+        content = add_group_member(&content, name, user.name);
+    }
+    fs::write(&group, content)?;
+
+    Ok(())
+}
+
+/// Add `user` to the member list of `group` in /etc/group content.
+fn add_group_member(content: &str, group: &str, user: &str) -> String {
+    let prefix = format!("{}:", group);
+    let mut out = String::new();
+    for line in content.lines() {
+        if line.starts_with(&prefix) {
+            let (head, members) = line.rsplit_once(':').unwrap_or((line, ""));
+            let mut list: Vec<&str> = members.split(',').filter(|m| !m.is_empty()).collect();
+            if !list.contains(&user) {
+                list.push(user);
+            }
+            out.push_str(&format!("{}:{}\n", head, list.join(",")));
+        } else {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// doas rule granting the live session's wheel group passwordless root.
+pub const DOAS_RULE: &str = "permit nopass :wheel";
+
+/// Switch the tty1 getty to autologin as `user`, leaving other TTYs untouched.
+pub fn set_tty1_autologin(inittab: &str, user: &str) -> String {
+    crate::component::inittab::set_autologin(inittab, "tty1", user)
+}
+
+/// Add the live user to the live overlay.
+///
+/// `staging_etc` is the rootfs staging /etc, used to seed overlay copies.
+pub fn apply_live_user(overlay: &Path, staging_etc: &Path, live_user: LiveUser) -> Result<()> {
+    let Some(name) = live_user.name() else {
+        return Ok(());
+    };
+
+    let etc = overlay.join("etc");
+    fs::create_dir_all(&etc)?;
+    for file in SEEDED_FILES {
+        let src = staging_etc.join(file);
+        if !etc.join(file).exists() && src.exists() {
+            fs::copy(&src, etc.join(file))?;
+        }
+    }
+
+    let home = format!("/home/{}", name);
+    ensure_user(
+        &etc,
+        &UserSpec {
+            name,
+            uid: LIVE_USER_UID,
+            gid: LIVE_USER_UID,
+            home: &home,
+            shell: distro_spec::acorn::DEFAULT_SHELL,
+            groups: LIVE_USER_GROUPS,
+        },
+    )?;
+
+    let doas = etc.join("doas.conf");
+    let content = fs::read_to_string(&doas).unwrap_or_default();
+    // Last matching rule wins, so this one overrides `permit persist :wheel`
+    if !content.lines().any(|l| l.trim() == DOAS_RULE) {
+        fs::write(&doas, format!("{}{}\n", content, DOAS_RULE))?;
+    }
+    // doas.conf must be owned by root and not group/world writable
+    fs::set_permissions(&doas, fs::Permissions::from_mode(0o600))?;
+
+    let inittab = etc.join("inittab");
+    let content = fs::read_to_string(&inittab).unwrap_or_default();
+    fs::write(&inittab, set_tty1_autologin(&content, name))?;
+
+    log::info!("  Live user: {} (tty1 autologin, doas nopass)", name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_apply_live_user() {
+        let dir = tempdir().unwrap();
+        let staging_etc = dir.path().join("staging/etc");
+        fs::create_dir_all(&staging_etc).unwrap();
+        fs::write(
+            staging_etc.join("passwd"),
+            "root:x:0:0:root:/root:/bin/sh\n",
+        )
+        .unwrap();
+        fs::write(staging_etc.join("group"), "root:x:0:\nwheel:x:10:root\n").unwrap();
+        fs::write(staging_etc.join("doas.conf"), "permit persist :wheel\n").unwrap();
+        fs::write(
+            staging_etc.join("inittab"),
+            "tty1::respawn:/sbin/agetty --autologin root --noclear tty1 linux\n\
+             ttyS0::respawn:/sbin/agetty --autologin root -L 115200 ttyS0 vt100\n",
+        )
+        .unwrap();
+
+        let overlay = dir.path().join("overlay");
+        apply_live_user(&overlay, &staging_etc, LiveUser::Live).unwrap();
+        // Idempotent
+        apply_live_user(&overlay, &staging_etc, LiveUser::Live).unwrap();
+
+        let etc = overlay.join("etc");
+        let passwd = fs::read_to_string(etc.join("passwd")).unwrap();
+        assert!(passwd.starts_with("root:x:0:0"));
+        assert_eq!(
+            passwd.matches("live:x:1000:1000:live:/home/live:").count(),
+            1
+        );
+
+        let group = fs::read_to_string(etc.join("group")).unwrap();
+        assert!(group.contains("wheel:x:10:root,live\n"));
+        assert!(group.contains("audio:x:18:live\n"));
+        assert!(group.contains("video:x:27:live\n"));
+        assert!(group.contains("live:x:1000:\n"));
+
+        let doas = fs::read_to_string(etc.join("doas.conf")).unwrap();
+        assert_eq!(doas, "permit persist :wheel\npermit nopass :wheel\n");
+
+        let inittab = fs::read_to_string(etc.join("inittab")).unwrap();
+        assert!(inittab.contains("--autologin live --noclear tty1"));
+        assert!(inittab.contains("--autologin root -L 115200 ttyS0"));
+    }
+
+    #[test]
+    fn test_live_user_none_is_noop() {
+        let dir = tempdir().unwrap();
+        apply_live_user(dir.path(), dir.path(), LiveUser::None).unwrap();
+        assert!(!dir.path().join("etc").exists());
+        assert_eq!("none".parse::<LiveUser>().unwrap(), LiveUser::None);
+        assert!("root".parse::<LiveUser>().is_err());
+    }
+}
@@ -3,17 +3,128 @@
 //! This module contains builders for the various artifacts needed
 //! to create a bootable AcornOS ISO:
 //!
-//! - `rootfs` - Creates the EROFS rootfs image (filesystem.erofs)
-//! - `initramfs` - Creates the tiny boot initramfs
+//! - `rootfs` - Creates the rootfs image (EROFS by default, or squashfs)
+//! - `initramfs` - Creates the tiny boot initramfs (gzip, xz or zstd)
+//! - `init_template` - Placeholder and `sh -n` checks for the init templates
+//! - `full_initramfs` - Creates the installed-system initramfs (fsck, LUKS)
 //! - `uki` - Builds Unified Kernel Images (UKIs) for boot
+//! - `cmdline` - Kernel cmdline edits for the live entries (`--cmdline-append`)
 //! - `iso` - Packages everything into a bootable ISO
+//! - `iso_extra` - Extra payloads injected into the ISO root
+//! - `iso_label` - The ISO volume label the live initramfs boots from
+//! - `iso_verify` - Offline checks of a built ISO (`verify-iso`)
+//! - `iso_diff` - What changed between two built ISOs (`diff-iso`)
+//! - `grub_efi` - Standalone GRUB EFI boot for non-x86_64 ISOs
+//! - `grub_bios` - Optional legacy BIOS boot for x86_64 ISOs
+//! - `netboot` - Kernel, initramfs and rootfs for HTTP netboot (iPXE/GRUB)
+//! - `netinstall` - Netinstall ISO variant without the rootfs image
+//! - `live_user` - Optional non-root live user in the live overlay
+//! - `live_profile` - Testing or release live session (`--profile`)
+//! - `live_ssh` - Optional SSH login to the live session (`--ssh`)
+//! - `link_lint` - Dangling/orphaned symlink checks for the staging tree
+//! - `openrc_lint` - Unsatisfied OpenRC `need` dependencies in the staging tree
+//! - `persistence` - Persistent live overlay partition (`acorn.persist=`)
 
+pub mod cmdline;
+pub mod full_initramfs;
+pub mod grub_bios;
+pub mod grub_efi;
+pub mod init_template;
 pub mod initramfs;
 pub mod iso;
+pub mod iso_diff;
+pub mod iso_extra;
+pub mod iso_label;
+pub mod iso_verify;
+pub mod link_lint;
+pub mod live_profile;
+pub mod live_ssh;
+pub mod live_user;
+pub mod netboot;
+pub mod netinstall;
+pub mod openrc_lint;
+pub mod persistence;
 pub mod rootfs;
 pub mod uki;
 
-pub use initramfs::build_tiny_initramfs;
-pub use iso::create_iso;
-pub use rootfs::build_rootfs;
+pub use cmdline::CmdlineEdit;
+pub use full_initramfs::build_full_initramfs;
+pub use initramfs::{build_tiny_initramfs, Compressor, InitramfsCompression};
+pub use iso::{create_iso, create_iso_with, IsoOptions};
+pub use iso_label::IsoLabel;
+pub use netboot::{build_netboot, NetbootOptions};
+pub use netinstall::IsoVariant;
+pub use rootfs::{build_rootfs_image, RootfsFormat, RootfsOptions};
 pub use uki::{build_installed_ukis, build_live_ukis};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::process::Command;
+
+    use distro_builder::process::which;
+
+    /// Checkouts under a directory with a space or a non-ASCII name used to
+    /// break argument construction; run what the host has tools for there.
+    #[test]
+    fn test_builds_under_space_and_non_ascii_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("acorn build ü, x");
+        fs::create_dir_all(&dir).unwrap();
+
+        // Initramfs: init syntax check, then gzip -> zstd/xz recompression
+        init_template::check_shell_syntax("init", "echo ok\n", None, &dir.join("init.sh")).unwrap();
+        let data = b"070701 cpio payload".repeat(64);
+        fs::write(dir.join("initramfs.cpio"), &data).unwrap();
+        let status = Command::new("gzip")
+            .arg(dir.join("initramfs.cpio"))
+            .status()
+            .unwrap();
+        assert!(status.success());
+        for compressor in [Compressor::Zstd, Compressor::Xz] {
+            if which(compressor.tool()).is_none() {
+                continue;
+            }
+            let compression = InitramfsCompression::new(compressor, None).unwrap();
+            let output = dir.join(compression.output_name());
+            initramfs::recompress(&dir.join("initramfs.cpio.gz"), &output, compression).unwrap();
+            assert!(output.exists());
+        }
+
+        // ISO: checksum sidecar, then extras mapped into a real ISO
+        let iso = dir.join("acornos.iso");
+        fs::write(&iso, "not yet an iso").unwrap();
+        crate::signing::write_sha512(&iso).unwrap();
+        crate::signing::verify_sha512(&iso).unwrap();
+        if which("xorriso").is_some() {
+            let root = dir.join("iso root");
+            fs::create_dir_all(&root).unwrap();
+            fs::write(root.join("README"), "acorn").unwrap();
+            let status = Command::new("xorriso")
+                .args(["-as", "mkisofs", "-quiet", "-o"])
+                .arg(&iso)
+                .arg(&root)
+                .status()
+                .unwrap();
+            assert!(status.success());
+            let extra = iso_extra::IsoExtra {
+                src: dir.join("initramfs.cpio.gz"),
+                dest: "boot/extra.gz".to_string(),
+            };
+            let out = dir.join("acornos extras.iso");
+            iso_extra::apply_iso_extras(&iso, &out, &[extra]).unwrap();
+            assert!(out.exists());
+        }
+        if ["mkfs.fat", "mmd", "mcopy"]
+            .iter()
+            .all(|t| which(t).is_some())
+        {
+            let loader = dir.join("BOOTAA64.EFI");
+            fs::write(&loader, vec![0u8; 4096]).unwrap();
+            let image = dir.join("efi boot.img");
+            grub_efi::build_efi_image(&loader, crate::arch::Arch::Aarch64, &image).unwrap();
+            assert!(image.exists());
+        }
+    }
+}
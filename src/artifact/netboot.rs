@@ -0,0 +1,423 @@
+//! Netboot artifact set (kernel + initramfs + rootfs over HTTP).
+//!
+//! `output/netboot/` is meant to be served as-is by an HTTP server:
+//!
+//! - `vmlinuz` - the kernel
+//! - `initramfs-netboot.cpio.gz` - recinit base rendered from
+//!   `init_netboot.template`, plus NIC modules, a udhcpc script and the
+//!   live overlay
+//! - the rootfs image (`filesystem.erofs` by default)
+//! - `boot.ipxe` and `grub-netboot.cfg` - ready-to-use boot stanzas
+//!
+//! At boot the init DHCPs, wgets `acorn.rootfs_url=` into a tmpfs, checks
+//! it against `acorn.rootfs_sha256=` when given, and then mounts it the
+//! same way the ISO boot does.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::full_initramfs::{append_cpio, find_module, is_builtin, CpioEntry};
+use super::init_template::{recinit_vars, render_template, validate_init_template};
+use super::iso::create_live_overlay;
+use super::live_profile::LiveProfile;
+use super::live_user::LiveUser;
+use super::rootfs::RootfsFormat;
+use crate::offline::sha256_file;
+use crate::paths::OutputPaths;
+use crate::tree_copy::{link_target, special_file};
+use distro_spec::acorn::{BOOT_DEVICE_PROBE_ORDER, CPIO_GZIP_LEVEL, ISO_LABEL, KERNEL_FILENAME};
+use recinit::{download_and_cache_busybox, find_kernel_modules_dir, ModulePreset, TinyConfig};
+
+/// Netboot directory under the output dir.
+pub const NETBOOT_DIR: &str = "netboot";
+
+/// Init template for the netboot path.
+pub const NETBOOT_TEMPLATE: &str = "profile/init_netboot.template";
+
+/// Netboot initramfs filename.
+pub const INITRAMFS_NETBOOT_OUTPUT: &str = "initramfs-netboot.cpio.gz";
+
+/// iPXE script filename.
+pub const IPXE_SCRIPT: &str = "boot.ipxe";
+
+/// Example GRUB stanza filename.
+pub const GRUB_STANZA: &str = "grub-netboot.cfg";
+
+/// Default base URL when none is given; iPXE expands `${next-server}`.
+pub const DEFAULT_IPXE_BASE_URL: &str = "http://${next-server}/acornos";
+
+/// Where the live overlay sits inside the netboot initramfs.
+const NETBOOT_OVERLAY: &str = "live-overlay";
+
+/// udhcpc hook script inside the netboot (and netinstall) initramfs.
+pub(crate) const UDHCPC_SCRIPT: &str = "etc/udhcpc.script";
+
+/// Console arguments, matching the serial-enabled live boot.
+const CONSOLE_CMDLINE: &str = "console=tty0 console=ttyS0,115200";
+
+/// NIC drivers (and their dependencies, first) for common lab hardware and QEMU.
+pub const NET_MODULES: &[&str] = &[
+    "af_packet",
+    "failover",
+    "net_failover",
+    "virtio_net",
+    "e1000",
+    "e1000e",
+    "igb",
+    "libphy",
+    "realtek",
+    "r8169",
+];
+
+/// Configures the interface from a udhcpc lease (busybox has no default script).
+const UDHCPC_SCRIPT_BODY: &str = r#"#!/bin/busybox sh
+case "$1" in
+    deconfig)
+        busybox ifconfig "$interface" 0.0.0.0
+        ;;
+    bound|renew)
+        busybox ifconfig "$interface" "$ip" netmask "${subnet:-255.255.255.0}" up
+        if [ -n "$router" ]; then
+            busybox route del default 2>/dev/null
+            for r in $router; do
+                busybox route add default gw "$r" dev "$interface"
+                break
+            done
+        fi
+        : > /etc/resolv.conf
+        for d in $dns; do
+            busybox echo "nameserver $d" >> /etc/resolv.conf
+        done
+        ;;
+esac
+"#;
+
+/// Options for the netboot build.
+#[derive(Debug, Clone, Default)]
+pub struct NetbootOptions {
+    /// URL the netboot directory is served from, e.g. `http://10.0.0.1/acornos`.
+    pub base_url: Option<String>,
+    /// User that tty1 autologins as.
+    pub live_user: LiveUser,
+}
+
+/// Netboot-specific placeholders of the init template.
+fn netboot_vars(format: RootfsFormat) -> Vec<(&'static str, String)> {
+    vec![
+        ("NET_MODULES", NET_MODULES.join(" ")),
+        ("ROOTFS_FSTYPE", format.fs_type().to_string()),
+        ("ROOTFS_NAME", format.image_name().to_string()),
+        ("UDHCPC_SCRIPT", format!("/{}", UDHCPC_SCRIPT)),
+        ("NETBOOT_OVERLAY", format!("/{}", NETBOOT_OVERLAY)),
+    ]
+}
+
+/// Render the netboot-specific placeholders of the init template.
+///
+/// `{{BOOT_MODULES}}` is left in place for recinit.
+pub fn render_netboot_init(template: &str, format: RootfsFormat) -> String {
+    render_template(template, &netboot_vars(format))
+}
+
+/// Kernel cmdline for netbooting from `base_url`.
+fn netboot_cmdline(base_url: &str, image: &str, sha256: &str) -> String {
+    format!(
+        "acorn.rootfs_url={}/{} acorn.rootfs_sha256={} {}",
+        base_url.trim_end_matches('/'),
+        image,
+        sha256,
+        CONSOLE_CMDLINE
+    )
+}
+
+/// iPXE script loading the kernel and initramfs relative to the script.
+pub fn ipxe_script(base_url: Option<&str>, image: &str, sha256: &str) -> String {
+    let base = base_url.unwrap_or(DEFAULT_IPXE_BASE_URL);
+    format!(
+        "#!ipxe\n\
+         # AcornOS netboot. Serve this directory over HTTP and chain this script.\n\
+         dhcp\n\
+         kernel {}/{} initrd={} {}\n\
+         initrd {}/{}\n\
+         boot\n",
+        base.trim_end_matches('/'),
+        KERNEL_FILENAME,
+        INITRAMFS_NETBOOT_OUTPUT,
+        netboot_cmdline(base, image, sha256),
+        base.trim_end_matches('/'),
+        INITRAMFS_NETBOOT_OUTPUT,
+    )
+}
+
+/// Example GRUB menuentry for GRUB's HTTP netboot.
+pub fn grub_stanza(base_url: Option<&str>, image: &str, sha256: &str) -> String {
+    // GRUB addresses files as (http,<host>)/<path>
+    let (host, path) = match base_url.and_then(|u| u.strip_prefix("http://")) {
+        Some(rest) => {
+            let rest = rest.trim_end_matches('/');
+            match rest.split_once('/') {
+                Some((host, path)) => (host.to_string(), format!("/{}", path)),
+                None => (rest.to_string(), String::new()),
+            }
+        }
+        None => ("$net_default_server".to_string(), "/acornos".to_string()),
+    };
+    let url = format!("http://{}{}", host, path);
+    format!(
+        "# AcornOS netboot. Add to grub.cfg on the PXE server.\n\
+         menuentry 'AcornOS (netboot)' {{\n\
+         \x20   insmod http\n\
+         \x20   linux (http,{host}){path}/{kernel} {cmdline}\n\
+         \x20   initrd (http,{host}){path}/{initrd}\n\
+         }}\n",
+        host = host,
+        path = path,
+        kernel = KERNEL_FILENAME,
+        initrd = INITRAMFS_NETBOOT_OUTPUT,
+        cmdline = netboot_cmdline(&url, image, sha256),
+    )
+}
+
+/// Build `output/netboot/`.
+pub fn build_netboot(base_dir: &Path, options: &NetbootOptions) -> Result<PathBuf> {
+    let paths = OutputPaths::for_base(base_dir);
+    let output_dir = paths.dir.clone();
+    let netboot_dir = output_dir.join(NETBOOT_DIR);
+    let format = RootfsFormat::load(&output_dir);
+    let rootfs_image = paths.rootfs_image(format);
+    let kernel = paths.kernel();
+
+    log::info!("=== Building Netboot Artifacts ===\n");
+
+    if !rootfs_image.exists() {
+        bail!(
+            "Rootfs image not found at {}.\nRun 'acornos build rootfs' first.",
+            rootfs_image.display()
+        );
+    }
+    if !kernel.exists() {
+        bail!(
+            "Kernel not found at {}.\nRun 'cargo xtask kernels build acorn' first.",
+            kernel.display()
+        );
+    }
+
+    fs::create_dir_all(&netboot_dir)?;
+
+    let downloads_dir = base_dir.join("downloads");
+    crate::download::ensure_busybox(base_dir, crate::arch::Arch::from_env()?)?;
+    let busybox_path = download_and_cache_busybox(&downloads_dir)?;
+    let modules_dir = find_kernel_modules_dir(&paths.kernel_modules())?;
+
+    let mut vars = recinit_vars(ISO_LABEL, "", BOOT_DEVICE_PROBE_ORDER, "");
+    vars.extend(netboot_vars(format));
+    validate_init_template(
+        base_dir,
+        NETBOOT_TEMPLATE,
+        &vars,
+        &[
+            "BOOT_MODULES",
+            "NET_MODULES",
+            "ROOTFS_FSTYPE",
+            "ROOTFS_NAME",
+        ],
+        Some(&busybox_path),
+        &output_dir.join("init_netboot.check"),
+    )?;
+
+    // Render our placeholders first; recinit fills in the boot modules
+    let template = fs::read_to_string(base_dir.join(NETBOOT_TEMPLATE))
+        .with_context(|| format!("Failed to read {}", NETBOOT_TEMPLATE))?;
+    let rendered = output_dir.join("init_netboot.rendered");
+    fs::write(&rendered, render_netboot_init(&template, format))?;
+
+    let output_path = netboot_dir.join(INITRAMFS_NETBOOT_OUTPUT);
+    let base_tmp = output_dir.join(format!("{}.base.tmp", INITRAMFS_NETBOOT_OUTPUT));
+    let config = TinyConfig {
+        modules_dir: modules_dir.clone(),
+        busybox_path,
+        template_path: rendered.clone(),
+        output: base_tmp.clone(),
+        iso_label: ISO_LABEL.to_string(),
+        rootfs_path: String::new(),
+        live_overlay_image_path: None,
+        live_overlay_path: None,
+        boot_devices: BOOT_DEVICE_PROBE_ORDER
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+        module_preset: ModulePreset::Live,
+        gzip_level: CPIO_GZIP_LEVEL,
+        check_builtin: true,
+        extra_template_vars: Vec::new(),
+    };
+    let result = recinit::build_tiny_initramfs(&config, true);
+    let _ = fs::remove_file(&rendered);
+    result?;
+
+    let mut entries = network_entries(&modules_dir)?;
+
+    create_live_overlay(
+        base_dir,
+        &output_dir,
+        options.live_user,
+        LiveProfile::Testing,
+    )?;
+    entries.extend(dir_entries(&paths.live_overlay(), NETBOOT_OVERLAY)?);
+
+    append_cpio(&base_tmp, &entries, &output_path)?;
+
+    // Kernel and rootfs next to the initramfs, so the directory is self-contained
+    copy_or_link(&kernel, &netboot_dir.join(KERNEL_FILENAME))?;
+    copy_or_link(&rootfs_image, &netboot_dir.join(format.image_name()))?;
+
+    let sha256 = sha256_file(&rootfs_image)?;
+    let base_url = options.base_url.as_deref();
+    fs::write(
+        netboot_dir.join(IPXE_SCRIPT),
+        ipxe_script(base_url, format.image_name(), &sha256),
+    )?;
+    fs::write(
+        netboot_dir.join(GRUB_STANZA),
+        grub_stanza(base_url, format.image_name(), &sha256),
+    )?;
+
+    log::info!("\n  Output: {}", netboot_dir.display());
+    log::info!("    {}", KERNEL_FILENAME);
+    log::info!("    {}", INITRAMFS_NETBOOT_OUTPUT);
+    log::info!("    {} (sha256 {})", format.image_name(), sha256);
+    log::info!("    {}, {}", IPXE_SCRIPT, GRUB_STANZA);
+    Ok(netboot_dir)
+}
+
+/// The udhcpc script and the NIC drivers from `modules_dir`, for an
+/// initramfs that DHCPs.
+pub(crate) fn network_entries(modules_dir: &Path) -> Result<Vec<CpioEntry>> {
+    let mut entries = vec![CpioEntry::bytes(
+        UDHCPC_SCRIPT,
+        0o755,
+        UDHCPC_SCRIPT_BODY.as_bytes(),
+    )];
+
+    // NIC drivers are best effort: a missing one only matters on that hardware
+    let kver = modules_dir
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .context("Kernel modules dir has no version component")?;
+    for module in NET_MODULES {
+        match find_module(modules_dir, module)? {
+            Some(path) => {
+                let rel = path.strip_prefix(modules_dir)?.to_string_lossy();
+                entries.push(CpioEntry::file(
+                    format!("lib/modules/{}/{}", kver, rel),
+                    &path,
+                )?);
+            }
+            None if is_builtin(modules_dir, module) => {}
+            None => log::warn!("NIC module '{}' not available", module),
+        }
+    }
+    Ok(entries)
+}
+
+/// Files and symlinks under `dir`, placed under `prefix` in the archive.
+fn dir_entries(dir: &Path, prefix: &str) -> Result<Vec<CpioEntry>> {
+    let mut entries = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let mut children: Vec<_> = fs::read_dir(&current)
+            .and_then(|entries| entries.collect::<Result<_, _>>())
+            .with_context(|| format!("Failed to read {}", current.display()))?;
+        children.sort_by_key(|e| e.file_name());
+        for child in children {
+            let path = child.path();
+            let rel = format!("{}/{}", prefix, path.strip_prefix(dir)?.display());
+            let file_type = child
+                .file_type()
+                .with_context(|| format!("Failed to stat {}", path.display()))?;
+            if let Some(kind) = special_file(&file_type) {
+                log::warn!("Skipping {} ({})", path.display(), kind);
+            } else if file_type.is_symlink() {
+                // The overlay is laid over the image root
+                let target = link_target(&path, dir, Path::new(""))?;
+                entries.push(CpioEntry::symlink(&rel, &target.to_string_lossy()));
+            } else if file_type.is_dir() {
+                pending.push(path);
+            } else {
+                entries.push(
+                    CpioEntry::file(rel, &path)
+                        .with_context(|| format!("Failed to read {}", path.display()))?,
+                );
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Hard link `src` to `dest` (same filesystem), falling back to a copy.
+fn copy_or_link(src: &Path, dest: &Path) -> Result<()> {
+    let _ = fs::remove_file(dest);
+    if fs::hard_link(src, dest).is_err() {
+        fs::copy(src, dest)
+            .with_context(|| format!("Failed to copy {} to {}", src.display(), dest.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_netboot_init() {
+        let template =
+            fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join(NETBOOT_TEMPLATE))
+                .unwrap();
+        let init = render_netboot_init(&template, RootfsFormat::Erofs);
+
+        // Only recinit's placeholder is left
+        let placeholders: Vec<&str> = init.match_indices("{{").map(|(i, _)| &init[i..]).collect();
+        assert_eq!(placeholders.len(), 1);
+        assert!(placeholders[0].starts_with("{{BOOT_MODULES}}"));
+
+        assert!(init.contains("acorn.rootfs_url=*) ROOTFS_URL="));
+        assert!(init.contains("acorn.rootfs_sha256=*) ROOTFS_SHA256="));
+        assert!(init.contains("busybox wget -q -O \"$IMAGE\" \"$ROOTFS_URL\""));
+        assert!(init.contains("busybox sha256sum \"$IMAGE\""));
+        assert!(init.contains("emergency_shell \"Rootfs checksum mismatch\""));
+        assert!(init.contains("IMAGE=\"/netroot/filesystem.erofs\""));
+        assert!(init.contains("-s /etc/udhcpc.script"));
+        assert!(init.contains("busybox mount -t erofs -o ro /dev/loop0 /rootfs"));
+        assert!(init.contains("virtio_net e1000"));
+
+        // Verification happens before the image is mounted
+        assert!(init.find("sha256sum").unwrap() < init.find("losetup").unwrap());
+
+        let squashfs = render_netboot_init(&template, RootfsFormat::Squashfs);
+        assert!(squashfs.contains("IMAGE=\"/netroot/filesystem.squashfs\""));
+    }
+
+    #[test]
+    fn test_boot_stanzas() {
+        let sha = "ab".repeat(32);
+        let ipxe = ipxe_script(None, "filesystem.erofs", &sha);
+        assert!(ipxe.starts_with("#!ipxe\n"));
+        assert!(ipxe.contains(
+            "kernel http://${next-server}/acornos/vmlinuz initrd=initramfs-netboot.cpio.gz \
+             acorn.rootfs_url=http://${next-server}/acornos/filesystem.erofs"
+        ));
+        assert!(ipxe.contains(&format!("acorn.rootfs_sha256={}", sha)));
+
+        let grub = grub_stanza(
+            Some("http://10.0.0.1:8080/pxe/acorn/"),
+            "filesystem.erofs",
+            &sha,
+        );
+        assert!(grub.contains("linux (http,10.0.0.1:8080)/pxe/acorn/vmlinuz"));
+        assert!(grub.contains("acorn.rootfs_url=http://10.0.0.1:8080/pxe/acorn/filesystem.erofs"));
+        assert!(grub.contains("initrd (http,10.0.0.1:8080)/pxe/acorn/initramfs-netboot.cpio.gz"));
+        assert!(grub_stanza(None, "filesystem.erofs", &sha)
+            .contains("(http,$net_default_server)/acornos/"));
+    }
+}
@@ -0,0 +1,254 @@
+//! Netinstall ISO variant (`acornos iso --variant netinstall`).
+//!
+//! A small ISO for PXE-less installs and tiny USB sticks: kernel, GRUB or
+//! the UKI, and an initramfs, but no rootfs image. Every boot entry carries
+//! `acorn.netinstall=1`, on which the live init skips the rootfs image,
+//! DHCPs with busybox udhcpc and runs `/netinstall`.
+//!
+//! The netinstall initramfs is the live initramfs with a second archive
+//! appended (as persistence does for e2fsck):
+//!
+//! - `/netinstall` - rendered from `profile/netinstall.template`; installs
+//!   the Tier 0 packages with apk.static onto a partition the user picks
+//! - `/sbin/apk.static` - from the cached apk-tools-static
+//! - `/etc/apk/keys`, `/etc/apk/repositories` - from the source rootfs, so
+//!   the install trusts the same keys and uses the build's mirror
+//! - the udhcpc script and NIC drivers netboot uses
+//! - `/etc/os-release` with `VARIANT_ID=netinstall` and the build id
+//!
+//! The ISO is written to `acornos-netinstall.iso`, next to the live one,
+//! with its own rebuild hashes.
+
+use anyhow::{bail, Context, Result};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use super::full_initramfs::{append_cpio, CpioEntry};
+use super::init_template::validate_init_template;
+use super::netboot::{network_entries, NET_MODULES, UDHCPC_SCRIPT};
+use crate::arch::Arch;
+use crate::build_info::BuildInfo;
+use crate::paths::OutputPaths;
+use distro_builder::alpine::extract::ExtractPaths;
+use recinit::find_kernel_modules_dir;
+
+/// Kernel option every netinstall boot entry carries.
+pub const NETINSTALL_CMDLINE: &str = "acorn.netinstall=1";
+
+/// Netinstall ISO filename.
+pub const NETINSTALL_ISO_FILENAME: &str = "acornos-netinstall.iso";
+
+/// Netinstall initramfs filename.
+pub const INITRAMFS_NETINSTALL_OUTPUT: &str = "initramfs-netinstall.cpio.gz";
+
+/// Installer script template.
+pub const NETINSTALL_TEMPLATE: &str = "profile/netinstall.template";
+
+/// Where the installer sits in the initramfs.
+const NETINSTALL_SCRIPT: &str = "netinstall";
+
+/// apk-tools-static inside the initramfs.
+const APK_STATIC: &str = "sbin/apk.static";
+
+/// Which ISO to build.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IsoVariant {
+    /// Live system with the rootfs image.
+    #[default]
+    Live,
+    /// Installer that fetches packages from the network; no rootfs image.
+    Netinstall,
+}
+
+impl IsoVariant {
+    /// ISO filename in the output dir.
+    pub fn iso_filename(self) -> &'static str {
+        match self {
+            Self::Live => distro_spec::acorn::ISO_FILENAME,
+            Self::Netinstall => NETINSTALL_ISO_FILENAME,
+        }
+    }
+}
+
+impl fmt::Display for IsoVariant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Live => "live",
+            Self::Netinstall => "netinstall",
+        })
+    }
+}
+
+impl FromStr for IsoVariant {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "live" => Ok(Self::Live),
+            "netinstall" => Ok(Self::Netinstall),
+            other => bail!(
+                "Unknown ISO variant '{}' (expected live or netinstall)",
+                other
+            ),
+        }
+    }
+}
+
+/// Netinstall placeholders of the live init template.
+pub fn template_vars() -> Vec<(&'static str, String)> {
+    vec![
+        ("NET_MODULES", NET_MODULES.join(" ")),
+        ("UDHCPC_SCRIPT", format!("/{}", UDHCPC_SCRIPT)),
+    ]
+}
+
+/// apk.static for the netinstall initramfs, which runs it on the target:
+/// the pinned one the download step cached on x86_64, else the one
+/// apk-tools-static installed (signature-checked) into the target rootfs.
+pub fn apk_static(base_dir: &Path, arch: Arch) -> PathBuf {
+    if arch == Arch::X86_64 {
+        crate::download_check::apk_tools_dir(base_dir).join("sbin/apk.static")
+    } else {
+        ExtractPaths::new(base_dir).rootfs.join("sbin/apk.static")
+    }
+}
+
+/// Build `initramfs-netinstall.cpio.gz` from the live initramfs at
+/// `live_initramfs`.
+pub fn build_netinstall_initramfs(
+    base_dir: &Path,
+    arch: Arch,
+    live_initramfs: &Path,
+) -> Result<PathBuf> {
+    let paths = OutputPaths::for_base(base_dir);
+    let output = paths.netinstall_initramfs();
+    let rootfs = ExtractPaths::new(base_dir).rootfs;
+
+    let apk = apk_static(base_dir, arch);
+    if !apk.is_file() {
+        bail!(
+            "apk.static not found at {}.\nRun 'acornos download alpine' first.",
+            apk.display()
+        );
+    }
+
+    let packages = crate::source_rootfs::current_packages(base_dir)?.join(" ");
+    let script = validate_init_template(
+        base_dir,
+        NETINSTALL_TEMPLATE,
+        &[("PACKAGES", packages)],
+        &["PACKAGES"],
+        None,
+        &paths.dir.join("netinstall.check"),
+    )?;
+
+    let os_release = BuildInfo::detect(base_dir).os_release(IsoVariant::Netinstall);
+    let mut entries = vec![
+        CpioEntry::bytes(NETINSTALL_SCRIPT, 0o755, script.as_bytes()),
+        CpioEntry::file(APK_STATIC.to_string(), &apk)?,
+        CpioEntry::bytes("etc/os-release", 0o644, os_release.as_bytes()),
+    ];
+    entries.extend(apk_config_entries(&rootfs)?);
+    let modules_dir = find_kernel_modules_dir(&paths.kernel_modules())?;
+    entries.extend(network_entries(&modules_dir)?);
+
+    // append_cpio consumes its base
+    let base_tmp = paths
+        .dir
+        .join(format!("{}.base.tmp", INITRAMFS_NETINSTALL_OUTPUT));
+    fs::copy(live_initramfs, &base_tmp)
+        .with_context(|| format!("Failed to copy {}", live_initramfs.display()))?;
+    append_cpio(&base_tmp, &entries, &output)?;
+    log::info!("  Netinstall initramfs: {}", output.display());
+    Ok(output)
+}
+
+/// The apk keys and repositories of `rootfs`, at the same paths.
+fn apk_config_entries(rootfs: &Path) -> Result<Vec<CpioEntry>> {
+    let repositories = rootfs.join("etc/apk/repositories");
+    if !repositories.is_file() {
+        bail!(
+            "{} not found.\nRun 'acornos download alpine' first.",
+            repositories.display()
+        );
+    }
+    let mut entries = vec![CpioEntry::file(
+        "etc/apk/repositories".to_string(),
+        &repositories,
+    )?];
+
+    let keys_dir = rootfs.join("etc/apk/keys");
+    let mut keys: Vec<_> = fs::read_dir(&keys_dir)
+        .and_then(|entries| entries.collect::<Result<_, _>>())
+        .with_context(|| format!("Failed to read {}", keys_dir.display()))?;
+    keys.sort_by_key(|e| e.file_name());
+    for key in keys {
+        let name = key.file_name().to_string_lossy().into_owned();
+        entries.push(CpioEntry::file(
+            format!("etc/apk/keys/{}", name),
+            &key.path(),
+        )?);
+    }
+    if entries.len() == 1 {
+        bail!("No apk keys in {}", keys_dir.display());
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::artifact::init_template::render_template;
+
+    #[test]
+    fn test_variant_and_installer_script() {
+        assert_eq!(
+            "netinstall".parse::<IsoVariant>().unwrap(),
+            IsoVariant::Netinstall
+        );
+        assert_eq!(IsoVariant::default().iso_filename(), "acornos.iso");
+        assert_eq!(
+            IsoVariant::Netinstall.iso_filename(),
+            "acornos-netinstall.iso"
+        );
+        assert!("mini".parse::<IsoVariant>().is_err());
+
+        let template =
+            fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join(NETINSTALL_TEMPLATE))
+                .unwrap();
+        let script = render_template(&template, &[("PACKAGES", "alpine-base openrc".into())]);
+        assert!(script.contains("PACKAGES=\"alpine-base openrc\""));
+        assert!(script.contains("/sbin/apk.static --root \"$TARGET\" --initdb"));
+    }
+
+    #[test]
+    fn test_apk_config_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let rootfs = dir.path();
+        fs::create_dir_all(rootfs.join("etc/apk/keys")).unwrap();
+        fs::write(
+            rootfs.join("etc/apk/repositories"),
+            "https://mirror/alpine/v3.23/main\n",
+        )
+        .unwrap();
+        assert!(apk_config_entries(rootfs)
+            .unwrap_err()
+            .to_string()
+            .starts_with("No apk keys"));
+
+        for key in ["b.rsa.pub", "a.rsa.pub"] {
+            fs::write(rootfs.join("etc/apk/keys").join(key), "key").unwrap();
+        }
+        let entries = apk_config_entries(rootfs).unwrap();
+        assert_eq!(
+            entries.iter().map(|e| e.path()).collect::<Vec<_>>(),
+            [
+                "etc/apk/repositories",
+                "etc/apk/keys/a.rsa.pub",
+                "etc/apk/keys/b.rsa.pub"
+            ]
+        );
+    }
+}
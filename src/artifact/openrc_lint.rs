@@ -0,0 +1,276 @@
+//! OpenRC dependency check for the rootfs staging tree.
+//!
+//! Runs right after the symlink lint (which already fails on runlevel
+//! entries without an init script). Starting from every service enabled in
+//! `etc/runlevels/*/`, it follows the `need` lines of each script's
+//! `depend()` block and fails when a needed service is neither an init
+//! script in `etc/init.d/` nor `provide`d by one: OpenRC refuses to start
+//! such a service at boot.
+//!
+//! Only `need` is checked; `use`, `want`, `after` and `before` are soft and
+//! may name services that are not installed.
+
+use anyhow::{bail, Result};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs;
+use std::path::Path;
+
+use super::link_lint::{resolve_in_root, Resolution};
+
+/// Words ending a `need`/`provide` list in a shell statement.
+const SHELL_SEPARATORS: &[&str] = &["&&", "||", "then", "else", "fi", "do", "done", "{", "}"];
+
+/// Hard dependencies and provided names from a script's `depend()` block.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Depend {
+    pub need: Vec<String>,
+    pub provide: Vec<String>,
+}
+
+/// An enabled (or needed) service whose `need` cannot be satisfied.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MissingDependency {
+    pub service: String,
+    pub missing: String,
+}
+
+/// Parse the `depend()` block of an init script.
+///
+/// Handles one statement per line or several separated by `;`, and
+/// `need` behind simple conditionals (`[ ... ] && need foo`). Words with
+/// shell expansions are skipped.
+pub fn parse_depend(script: &str) -> Depend {
+    let mut depend = Depend::default();
+    let mut in_block = false;
+    let mut depth = 0usize;
+
+    for line in script.lines() {
+        let code = line.split('#').next().unwrap_or("").trim();
+        if !in_block {
+            if !code.starts_with("depend()") {
+                continue;
+            }
+            in_block = true;
+        }
+        let body = code.strip_prefix("depend()").unwrap_or(code);
+
+        for statement in body.split(';') {
+            let mut list: Option<&mut Vec<String>> = None;
+            let mut at_start = true;
+            for word in statement.split_whitespace() {
+                if SHELL_SEPARATORS.contains(&word) {
+                    list = None;
+                    at_start = true;
+                    continue;
+                }
+                match word {
+                    "need" if at_start => list = Some(&mut depend.need),
+                    "provide" if at_start => list = Some(&mut depend.provide),
+                    _ => {
+                        if let Some(list) = list.as_deref_mut() {
+                            if !word.contains('$') && !word.starts_with('-') {
+                                list.push(word.trim_matches('"').to_string());
+                            }
+                        }
+                    }
+                }
+                at_start = false;
+            }
+        }
+
+        let (opened, closed) = (code.matches('{').count(), code.matches('}').count());
+        depth = (depth + opened).saturating_sub(closed);
+        if depth == 0 && closed > 0 {
+            break;
+        }
+    }
+    depend
+}
+
+/// Read the init script for `service`, following links inside `root`.
+fn read_script(root: &Path, service: &str) -> Option<String> {
+    match resolve_in_root(root, &Path::new("etc/init.d").join(service)) {
+        Resolution::Found(path) if path.is_file() => fs::read_to_string(path).ok(),
+        _ => None,
+    }
+}
+
+/// Services enabled in any runlevel.
+fn enabled_services(root: &Path) -> Result<BTreeSet<String>> {
+    let mut enabled = BTreeSet::new();
+    let runlevels = root.join("etc/runlevels");
+    if !runlevels.is_dir() {
+        return Ok(enabled);
+    }
+    for level in fs::read_dir(&runlevels)? {
+        let level = level?;
+        if !level.file_type()?.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(level.path())? {
+            enabled.insert(entry?.file_name().to_string_lossy().into_owned());
+        }
+    }
+    Ok(enabled)
+}
+
+/// Unsatisfied `need`s of the enabled services and everything they need.
+pub fn lint_services(root: &Path) -> Result<Vec<MissingDependency>> {
+    let init_d = root.join("etc/init.d");
+    let mut scripts: BTreeMap<String, Depend> = BTreeMap::new();
+    if init_d.is_dir() {
+        for entry in fs::read_dir(&init_d)? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if let Some(text) = read_script(root, &name) {
+                scripts.insert(name, parse_depend(&text));
+            }
+        }
+    }
+
+    // Virtual names (net, logger, ...) map to the scripts providing them
+    let mut providers: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (name, depend) in &scripts {
+        providers.entry(name).or_default().push(name);
+        for provided in &depend.provide {
+            providers.entry(provided).or_default().push(name);
+        }
+    }
+
+    let mut missing = BTreeSet::new();
+    let mut seen = BTreeSet::new();
+    // Runlevel entries without a script are reported by the symlink lint
+    let mut queue: VecDeque<String> = enabled_services(root)?
+        .into_iter()
+        .filter(|s| scripts.contains_key(s))
+        .collect();
+    while let Some(service) = queue.pop_front() {
+        if !seen.insert(service.clone()) {
+            continue;
+        }
+        for need in &scripts[&service].need {
+            match providers.get(need.as_str()) {
+                Some(names) => queue.extend(names.iter().map(|n| n.to_string())),
+                None => {
+                    missing.insert(MissingDependency {
+                        service: service.clone(),
+                        missing: need.clone(),
+                    });
+                }
+            }
+        }
+    }
+    Ok(missing.into_iter().collect())
+}
+
+/// Check service dependencies in the staging tree, failing on any
+/// unsatisfied `need`.
+pub fn check_openrc_services(staging: &Path) -> Result<()> {
+    log::info!("\n  Checking OpenRC service dependencies...");
+    let missing = lint_services(staging)?;
+    if missing.is_empty() {
+        log::info!("  ✓ All enabled services have their dependencies");
+        return Ok(());
+    }
+
+    log::error!("{} unsatisfied service dependencies:", missing.len());
+    for m in &missing {
+        log::info!(
+            "    {} needs {} (no /etc/init.d/{})",
+            m.service,
+            m.missing,
+            m.missing
+        );
+    }
+    bail!(
+        "OpenRC service check FAILED: {} missing dependencies.\n\
+         Add the init scripts to the component definitions or stop enabling the services.",
+        missing.len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_depend() {
+        let script = r#"#!/sbin/openrc-run
+name="sshd"
+depend() {
+	need net # network first
+	use logger dns
+	after firewall; provide ssh-server
+	[ -n "$extra" ] && need localmount $extra
+}
+
+start_pre() {
+	need bogus
+}
+"#;
+        let depend = parse_depend(script);
+        assert_eq!(depend.need, ["net", "localmount"]);
+        assert_eq!(depend.provide, ["ssh-server"]);
+
+        let one_line = parse_depend("depend() { need dev; provide console; }\nneed nope\n");
+        assert_eq!(one_line.need, ["dev"]);
+        assert_eq!(one_line.provide, ["console"]);
+    }
+
+    #[test]
+    fn test_lint_services_catches_missing_needs() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        for d in ["etc/init.d", "etc/runlevels/default", "etc/runlevels/boot"] {
+            fs::create_dir_all(root.join(d)).unwrap();
+        }
+        let script = |name: &str, depend: &str| {
+            fs::write(
+                root.join("etc/init.d").join(name),
+                format!("#!/sbin/openrc-run\ndepend() {{\n{}\n}}\n", depend),
+            )
+            .unwrap();
+        };
+        let enable = |level: &str, name: &str| {
+            symlink(
+                format!("/etc/init.d/{}", name),
+                root.join("etc/runlevels").join(level).join(name),
+            )
+            .unwrap();
+        };
+
+        script("networking", "provide net");
+        script("sshd", "need net\nuse logger");
+        script("agetty", "need localmount");
+        symlink("agetty", root.join("etc/init.d/agetty.ttyS0")).unwrap();
+        script("chronyd", "need ntp-helper");
+        enable("boot", "networking");
+        enable("default", "sshd");
+        enable("default", "agetty.ttyS0");
+        // Not enabled or needed: its missing dependency does not matter
+        script("unused", "need nothing-here");
+        // Dangling enable: left to the symlink lint
+        enable("default", "gone");
+
+        let missing = lint_services(root).unwrap();
+        assert_eq!(
+            missing,
+            vec![MissingDependency {
+                service: "agetty.ttyS0".to_string(),
+                missing: "localmount".to_string(),
+            }]
+        );
+        assert!(check_openrc_services(root).is_err());
+
+        // Needed but not enabled services are followed too
+        script("localmount", "need chronyd");
+        let missing = lint_services(root).unwrap();
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].service, "chronyd");
+        assert_eq!(missing[0].missing, "ntp-helper");
+
+        script("ntp-helper", "");
+        assert!(check_openrc_services(root).is_ok());
+    }
+}
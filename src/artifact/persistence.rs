@@ -0,0 +1,169 @@
+//! Persistent live sessions (`acorn.persist=LABEL=<label>`).
+//!
+//! By default the live overlay's upper layer is tmpfs and every change is
+//! lost at reboot. With `acorn.persist=` on the cmdline, the tiny initramfs
+//! finds that ext4 partition, fscks and mounts it, and keeps the overlay
+//! upper/work dirs in [`PERSIST_DIR`] on it instead (falling back to tmpfs
+//! if the partition is missing or damaged). The partition stays mounted at
+//! `/media/persistence`.
+//!
+//! The ISO gets an "AcornOS (Persistent)" boot entry for [`PERSIST_LABEL`],
+//! and the live overlay ships `acorn-make-persistence /dev/sdX` to format a
+//! partition with that label.
+
+use anyhow::Result;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use super::full_initramfs::{binary_entries, module_entries, CpioEntry};
+
+/// Kernel cmdline parameter naming the persistence partition.
+pub const PERSIST_PARAM: &str = "acorn.persist";
+
+/// Filesystem label the persistent boot entry looks for.
+pub const PERSIST_LABEL: &str = "ACORN-PERSIST";
+
+/// Directory on the partition holding the overlay upper/work dirs.
+pub const PERSIST_DIR: &str = "acornos";
+
+/// Where the partition is mounted in the live system.
+pub const PERSIST_MOUNT: &str = "/media/persistence";
+
+/// ext4 modules for the persistence partition, dependencies first.
+pub const PERSIST_MODULES: &[&str] = &["crc16", "mbcache", "jbd2", "ext4"];
+
+/// Binaries the init needs from the Alpine rootfs.
+const PERSIST_BINARIES: &[&str] = &["e2fsck"];
+
+/// Boot menu name of the persistent live entry.
+pub const PERSISTENT_ENTRY_NAME: &str = "AcornOS (Persistent)";
+
+/// UKI filename of the persistent live entry.
+pub const PERSISTENT_UKI_FILENAME: &str = "acornos-persistent.efi";
+
+/// Helper script path in the live overlay.
+pub const MAKE_PERSISTENCE_PATH: &str = "usr/local/sbin/acorn-make-persistence";
+
+/// Cmdline option selecting the [`PERSIST_LABEL`] partition.
+pub fn persist_cmdline() -> String {
+    format!("{}=LABEL={}", PERSIST_PARAM, PERSIST_LABEL)
+}
+
+/// Live init template variables for persistence.
+pub fn template_vars() -> Vec<(&'static str, String)> {
+    vec![
+        ("PERSIST_MODULES", PERSIST_MODULES.join(" ")),
+        ("PERSIST_DIR", PERSIST_DIR.to_string()),
+        ("PERSIST_MOUNT", PERSIST_MOUNT.to_string()),
+    ]
+}
+
+/// Extra live initramfs entries: `e2fsck` with its libraries and the ext4
+/// modules (unless built in).
+pub(crate) fn initramfs_entries(rootfs: &Path, modules_dir: &Path) -> Result<Vec<CpioEntry>> {
+    let mut entries = binary_entries(rootfs, PERSIST_BINARIES)?;
+    entries.extend(module_entries(modules_dir, PERSIST_MODULES)?);
+    Ok(entries)
+}
+
+/// `acorn-make-persistence`: formats a device as the persistence partition
+/// after confirmation (`--yes` skips it).
+pub fn make_persistence_script() -> String {
+    format!(
+        r#"#!/bin/sh
+# acorn-make-persistence - format a device for persistent AcornOS live sessions
+set -e
+
+LABEL="{label}"
+
+usage() {{
+    echo "Usage: acorn-make-persistence [--yes] DEVICE" >&2
+    echo "Formats DEVICE as ext4 labelled $LABEL. ALL DATA ON IT IS LOST." >&2
+    exit 2
+}}
+
+YES=""
+case "$1" in
+    -y|--yes) YES=1; shift ;;
+    -h|--help) usage ;;
+esac
+[ $# -eq 1 ] || usage
+DEV="$1"
+
+if [ "$(id -u)" -ne 0 ]; then
+    echo "acorn-make-persistence: must be run as root" >&2
+    exit 1
+fi
+if [ ! -b "$DEV" ]; then
+    echo "acorn-make-persistence: $DEV is not a block device" >&2
+    exit 1
+fi
+if grep -q "^$DEV" /proc/mounts; then
+    echo "acorn-make-persistence: $DEV (or a partition on it) is mounted" >&2
+    exit 1
+fi
+
+if [ -z "$YES" ]; then
+    printf "Erase ALL data on %s and format it as %s? Type YES to continue: " "$DEV" "$LABEL"
+    read -r answer
+    if [ "$answer" != "YES" ]; then
+        echo "Aborted."
+        exit 1
+    fi
+fi
+
+mkfs.ext4 -F -q -L "$LABEL" "$DEV"
+
+echo "$DEV is ready. Reboot and pick \"{entry}\" to keep changes on it"
+echo "(or add {cmdline} to the kernel cmdline)."
+"#,
+        label = PERSIST_LABEL,
+        entry = PERSISTENT_ENTRY_NAME,
+        cmdline = persist_cmdline(),
+    )
+}
+
+/// Install `acorn-make-persistence` into the live overlay at `overlay`.
+pub fn install_make_persistence(overlay: &Path) -> Result<()> {
+    let path = overlay.join(MAKE_PERSISTENCE_PATH);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, make_persistence_script())?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::artifact::init_template::check_shell_syntax;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_make_persistence_script() {
+        let dir = tempdir().unwrap();
+        install_make_persistence(dir.path()).unwrap();
+        let path = dir.path().join(MAKE_PERSISTENCE_PATH);
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o755
+        );
+
+        let script = fs::read_to_string(&path).unwrap();
+        assert!(script.contains("LABEL=\"ACORN-PERSIST\""));
+        assert!(script.contains("mkfs.ext4 -F -q -L \"$LABEL\" \"$DEV\""));
+        assert!(script.contains("acorn.persist=LABEL=ACORN-PERSIST"));
+        // ext4 labels are at most 16 bytes
+        assert!(PERSIST_LABEL.len() <= 16);
+
+        check_shell_syntax(
+            "acorn-make-persistence",
+            &script,
+            None,
+            &dir.path().join("check"),
+        )
+        .unwrap();
+    }
+}
@@ -1,112 +1,657 @@
-//! EROFS rootfs builder - creates the AcornOS system image.
+//! Rootfs builder - creates the AcornOS system image.
 //!
-//! The rootfs (EROFS) serves as BOTH:
+//! The rootfs (EROFS by default, squashfs optionally) serves as BOTH:
 //! - Live boot environment (mounted read-only with tmpfs overlay)
 //! - Installation source (extracted to disk by recstrap)
 //!
+//! The format is chosen at build time and recorded in the output directory,
+//! so the initramfs and ISO stages always agree with the image that was built.
+//!
 //! # Atomicity
 //!
 //! Uses Gentoo-style "work directory" pattern:
 //! - Build into `.work` files (rootfs-staging.work, filesystem.erofs.work)
 //! - Only swap to final locations after successful completion
 //! - If cancelled mid-build, existing artifacts are preserved
+//!
+//! Unless `--clean-staging` is given, the previous `rootfs-staging` is moved
+//! back into the work location and only components whose hash changed are
+//! re-run (see [`crate::component::builder`]). Its hashes file is removed
+//! first, so a failed or cancelled build always falls back to a full one.
+//! An incremental build that fails is retried once from a clean staging
+//! tree, and `verify_staging` still checks the result either way.
+//!
+//! # Resuming
+//!
+//! A build that fails after some components finished keeps
+//! `rootfs-staging.work` and its checkpoints (see
+//! [`crate::component::checkpoint`]). `--resume` continues in that tree
+//! from the failed component instead of starting over.
+//!
+//! A source rootfs missing binaries or files the components copy fails the
+//! build before staging is touched, listing all of them; `--keep-going`
+//! goes ahead and fails at the first such component instead.
+//!
+//! # Image verification
+//!
+//! The finished image is checked before it replaces the previous one
+//! (`fsck.erofs`/`unsquashfs -l`, else an erofsfuse or loop mount that
+//! re-checks the required binaries and configs). An image that fails is
+//! kept as `<image>.work.bad` for inspection.
+//!
+//! # Compression threads
+//!
+//! `--jobs N` (default: the physical cores) is passed to the image tool as
+//! `mkfs.erofs --workers=N` or `mksquashfs -processors N`, if the installed
+//! tool lists that option in its help; older erofs-utils compress on one
+//! thread. The thread count is part of the image phase's name in
+//! `build-timings.json`, so builds with different settings can be compared.
+//!
+//! # Reproducible builds
+//!
+//! With `--reproducible` (see [`crate::reproducible`]) the staging mtimes
+//! and the image's own timestamps are set to `SOURCE_DATE_EPOCH`, and an
+//! EROFS image gets a UUID derived from it.
 
 use anyhow::{bail, Context, Result};
+use std::collections::BTreeSet;
+use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
 
-use distro_builder::process;
+use distro_builder::process::{self, Cmd};
 use distro_spec::acorn::verification;
 use distro_spec::acorn::{
-    EROFS_CHUNK_SIZE, EROFS_COMPRESSION, EROFS_COMPRESSION_LEVEL, ROOTFS_NAME,
+    EROFS_CHUNK_SIZE, EROFS_COMPRESSION, EROFS_COMPRESSION_LEVEL, ROOTFS_ISO_PATH, ROOTFS_NAME,
 };
 
-use crate::component::{build_system, BuildContext};
-use distro_builder::alpine::extract::ExtractPaths;
+use super::link_lint::check_staging_links;
+use super::openrc_lint::check_openrc_services;
+use crate::component::{
+    build_system, BuildContext, Checkpoint, ComponentHashes, COMPONENT_HASHES_FILE,
+};
+use crate::paths::OutputPaths;
+use crate::progress;
 
-/// Build the EROFS rootfs using the component system.
-pub fn build_rootfs(base_dir: &Path) -> Result<()> {
-    println!("=== Building AcornOS System Image (EROFS) ===\n");
+/// Squashfs image name (EROFS uses `ROOTFS_NAME` from distro-spec).
+const SQUASHFS_NAME: &str = "filesystem.squashfs";
 
-    check_host_tools()?;
+/// Squashfs compression (kernel needs CONFIG_SQUASHFS_ZSTD).
+const SQUASHFS_COMPRESSION: &str = "zstd";
+const SQUASHFS_COMPRESSION_LEVEL: u32 = 19;
 
-    let paths = ExtractPaths::new(base_dir);
-    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
+/// Records which format the current rootfs image was built in.
+pub const FORMAT_MARKER: &str = ".rootfs-format";
 
-    // Verify rootfs exists
-    if !paths.rootfs.exists() || !paths.rootfs.join("bin").exists() {
-        bail!(
-            "Rootfs not found at {}.\n\
-             Run 'acornos extract' first.",
-            paths.rootfs.display()
-        );
+/// Root directory `unsquashfs -l` prefixes listed paths with.
+pub(crate) const UNSQUASHFS_ROOT: &str = "squashfs-root";
+
+/// Files sshd needs at runtime. Missing helpers only fail at connection time,
+/// so they are checked here to catch regressions at build time.
+const SSH_REQUIRED_FILES: &[&str] = &[
+    "usr/sbin/sshd",
+    "usr/bin/ssh-keygen",
+    "usr/lib/ssh/sshd-session",
+    "usr/lib/ssh/sftp-server",
+    "etc/ssh/sshd_config",
+    "etc/init.d/sshd",
+    "etc/init.d/sshd-keygen",
+];
+
+/// Filesystem format of the rootfs image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RootfsFormat {
+    #[default]
+    Erofs,
+    Squashfs,
+}
+
+impl RootfsFormat {
+    pub const ALL: [RootfsFormat; 2] = [RootfsFormat::Erofs, RootfsFormat::Squashfs];
+
+    /// Filesystem type, as passed to `mount -t`.
+    pub fn fs_type(self) -> &'static str {
+        match self {
+            Self::Erofs => "erofs",
+            Self::Squashfs => "squashfs",
+        }
     }
 
+    /// Image file name in the output directory.
+    pub fn image_name(self) -> &'static str {
+        match self {
+            Self::Erofs => ROOTFS_NAME,
+            Self::Squashfs => SQUASHFS_NAME,
+        }
+    }
+
+    /// Image path inside the ISO.
+    pub fn iso_path(self) -> String {
+        match self {
+            Self::Erofs => ROOTFS_ISO_PATH.to_string(),
+            Self::Squashfs => ROOTFS_ISO_PATH.replace(ROOTFS_NAME, SQUASHFS_NAME),
+        }
+    }
+
+    /// Artifact store kind for images of this format.
+    pub fn artifact_kind(self) -> &'static str {
+        match self {
+            Self::Erofs => "rootfs_erofs",
+            Self::Squashfs => "rootfs_squashfs",
+        }
+    }
+
+    /// Format of the last built rootfs (EROFS if none recorded).
+    pub fn load(output_dir: &Path) -> Self {
+        fs::read_to_string(output_dir.join(FORMAT_MARKER))
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// Record this as the format of the current rootfs.
+    pub fn save(self, output_dir: &Path) -> Result<()> {
+        fs::write(output_dir.join(FORMAT_MARKER), self.fs_type())?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for RootfsFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.fs_type())
+    }
+}
+
+impl FromStr for RootfsFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "erofs" => Ok(Self::Erofs),
+            "squashfs" => Ok(Self::Squashfs),
+            other => bail!(
+                "Unknown rootfs format '{}' (expected erofs or squashfs)",
+                other
+            ),
+        }
+    }
+}
+
+/// Options for the rootfs image build.
+#[derive(Debug, Clone, Default)]
+pub struct RootfsOptions {
+    /// Fail on any dangling symlink in staging (orphaned runlevel links
+    /// always do).
+    pub strict_links: bool,
+    /// Skip verifying the finished image.
+    pub skip_verify: bool,
+    /// Never reuse the previous staging tree.
+    pub clean_staging: bool,
+    /// Continue the work tree of a failed build.
+    pub resume: bool,
+    /// Run components despite sources missing from the rootfs.
+    pub keep_going: bool,
+    /// Compression threads of the image tool (default: physical cores).
+    pub jobs: Option<usize>,
+}
+
+/// Build the rootfs image in `format` using the component system.
+pub fn build_rootfs_image(
+    base_dir: &Path,
+    format: RootfsFormat,
+    options: &RootfsOptions,
+) -> Result<()> {
+    let RootfsOptions {
+        strict_links,
+        skip_verify,
+        clean_staging,
+        resume,
+        keep_going,
+        jobs,
+    } = *options;
+    let jobs = jobs.unwrap_or_else(default_jobs);
+    let reproducible = crate::reproducible::epoch()?;
+    log::info!(
+        "=== Building AcornOS System Image ({}) ===\n",
+        format.fs_type().to_uppercase()
+    );
+
+    check_host_tools(format)?;
+
+    let output = OutputPaths::for_base(base_dir);
+    let output_dir = output.dir.clone();
+
+    // A rootfs left half-installed by an interrupted apk run is recreated
+    crate::source_rootfs::ensure_complete(base_dir)?;
+
     // Gentoo-style: separate "work" vs "final" locations
     let work_staging = output_dir.join("rootfs-staging.work");
-    let work_output = output_dir.join("filesystem.erofs.work");
-    let final_staging = output_dir.join("rootfs-staging");
-    let final_output = output_dir.join(ROOTFS_NAME);
+    let work_output = output_dir.join(format!("{}.work", format.image_name()));
+    let final_staging = output.rootfs_staging();
+    let final_output = output.rootfs_image(format);
+
+    let resuming =
+        resume && work_staging.is_dir() && Checkpoint::new(&output_dir, true).has_progress();
+    if resume && !resuming {
+        log::warn!("No interrupted build to resume; building normally");
+    }
+    let checkpoint = Checkpoint::new(&output_dir, resuming);
+    if !resuming {
+        checkpoint.clear()?;
+    }
+
+    let hashes_file = output_dir.join(COMPONENT_HASHES_FILE);
+    let previous = ComponentHashes::load(&hashes_file)
+        .filter(|_| !clean_staging && !resuming && final_staging.is_dir());
+    // The hashes describe rootfs-staging, which is about to change
+    let _ = fs::remove_file(&hashes_file);
 
     // Clean work directories only (preserve final)
-    let _ = fs::remove_dir_all(&work_staging);
     let _ = fs::remove_file(&work_output);
-    fs::create_dir_all(&work_staging)?;
+    if resuming {
+        log::info!("Resuming in {}", work_staging.display());
+    } else if previous.is_some() {
+        let _ = fs::remove_dir_all(&work_staging);
+        log::info!("Reusing {} (incremental)", final_staging.display());
+        fs::rename(&final_staging, &work_staging)
+            .context("Failed to move rootfs-staging to rootfs-staging.work")?;
+    } else {
+        let _ = fs::remove_dir_all(&work_staging);
+        fs::create_dir_all(&work_staging)?;
+    }
 
-    // Build into work directory (may fail — final is preserved)
-    let build_result = (|| -> Result<()> {
+    // Build into work directory (may fail — final image is preserved)
+    let build_result = (|| -> Result<ComponentHashes> {
         let ctx = BuildContext::new(base_dir, &work_staging, "acornos extract")?;
-        build_system(&ctx)?;
+        let hashes = match build_system(&ctx, previous.as_ref(), &checkpoint, keep_going) {
+            Err(e) if previous.is_some() => {
+                log::warn!(
+                    "Incremental staging build failed ({:#}); rebuilding from scratch",
+                    e
+                );
+                build_system(&ctx, None, &checkpoint.restart(), keep_going)?
+            }
+            result => result?,
+        };
 
-        // Verify staging before creating EROFS
+        // Verify staging before creating the image
         verify_staging(&work_staging)?;
+        check_staging_links(&work_staging, strict_links)?;
+        check_openrc_services(&work_staging)?;
 
-        // Build EROFS from staging
-        println!("\nCreating EROFS from staging...");
-        println!("  Source: {}", work_staging.display());
-        println!(
-            "  Compression: {} (level {})",
-            EROFS_COMPRESSION, EROFS_COMPRESSION_LEVEL
-        );
+        log::info!("\nCreating {} from staging...", format.fs_type());
+        log::info!("  Source: {}", work_staging.display());
+        let started = std::time::Instant::now();
+        if let Some(epoch) = reproducible {
+            log::info!("  Reproducible: all times set to {}", epoch);
+            crate::reproducible::normalize_mtimes(&work_staging, epoch)?;
+        }
+        let threads = create_image(format, &work_staging, &work_output, jobs, reproducible)?;
+        crate::timing::record_since(&image_phase_name(format, threads), started);
 
-        distro_builder::create_erofs(
-            &work_staging,
-            &work_output,
-            EROFS_COMPRESSION,
-            EROFS_COMPRESSION_LEVEL,
-            EROFS_CHUNK_SIZE,
-        )?;
-        Ok(())
+        if skip_verify {
+            log::info!("\n  [SKIP] Image verification (--skip-verify)");
+            return Ok(hashes);
+        }
+        if let Err(e) = verify_image(format, &work_output) {
+            if let Some(bad) = keep_bad_image(&work_output) {
+                log::info!("  Kept failed image at {}", bad.display());
+            }
+            return Err(e);
+        }
+        Ok(hashes)
     })();
 
-    // On failure, clean up work files and propagate error
-    if let Err(e) = build_result {
-        let _ = fs::remove_dir_all(&work_staging);
-        let _ = fs::remove_file(&work_output);
-        return Err(e);
-    }
+    // On failure, clean up work files (staging only if there is nothing
+    // to resume) and propagate error
+    let hashes = match build_result {
+        Ok(hashes) => hashes,
+        Err(e) => {
+            let _ = fs::remove_file(&work_output);
+            if checkpoint.has_progress() {
+                let at = checkpoint
+                    .failed()
+                    .map(|c| format!(" in component '{}'", c))
+                    .unwrap_or_default();
+                log::info!(
+                    "\n  Rootfs build failed{}; kept {}",
+                    at,
+                    work_staging.display()
+                );
+                log::info!("  After fixing the cause, continue with: acornos build --resume");
+            } else {
+                let _ = fs::remove_dir_all(&work_staging);
+            }
+            return Err(e);
+        }
+    };
 
     // Atomic swap (only reached if build succeeded)
-    println!("\nSwapping work files to final locations...");
+    log::info!("\nSwapping work files to final locations...");
     let _ = fs::remove_dir_all(&final_staging);
     let _ = fs::remove_file(&final_output);
     fs::rename(&work_staging, &final_staging)
         .context("Failed to move rootfs-staging.work to rootfs-staging")?;
-    fs::rename(&work_output, &final_output)
-        .context("Failed to move filesystem.erofs.work to filesystem.erofs")?;
+    fs::rename(&work_output, &final_output).with_context(|| {
+        format!(
+            "Failed to move {}.work to {}",
+            format.image_name(),
+            format.image_name()
+        )
+    })?;
+    hashes.write(&hashes_file)?;
+    checkpoint.clear()?;
 
-    println!("\n=== EROFS Build Complete ===");
-    println!("  Output: {}", final_output.display());
+    // An image in the other format would be stale now
+    for other in RootfsFormat::ALL.iter().filter(|f| **f != format) {
+        let _ = fs::remove_file(output.rootfs_image(*other));
+    }
+    format.save(&output_dir)?;
+
+    log::info!("\n=== Rootfs Build Complete ({}) ===", format);
+    log::info!("  Output: {}", final_output.display());
     if let Ok(meta) = fs::metadata(&final_output) {
-        println!("  Size: {} MB", meta.len() / 1024 / 1024);
+        log::info!("  Size: {} MB", meta.len() / 1024 / 1024);
+    }
+
+    Ok(())
+}
+
+/// Compression threads when `--jobs` is not given: the physical cores, or
+/// the available parallelism if /proc/cpuinfo does not list them.
+pub fn default_jobs() -> usize {
+    fs::read_to_string("/proc/cpuinfo")
+        .ok()
+        .and_then(|cpuinfo| physical_cores(&cpuinfo))
+        .or_else(|| std::thread::available_parallelism().ok().map(|n| n.get()))
+        .unwrap_or(1)
+}
+
+/// Distinct (package, core) pairs in /proc/cpuinfo.
+fn physical_cores(cpuinfo: &str) -> Option<usize> {
+    let mut cores = BTreeSet::new();
+    let mut package = "";
+    for line in cpuinfo.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        match key.trim() {
+            "physical id" => package = value.trim(),
+            "core id" => {
+                cores.insert((package, value.trim()));
+            }
+            _ => {}
+        }
+    }
+    (!cores.is_empty()).then_some(cores.len())
+}
+
+/// Tool option setting the compression threads, and the help argument
+/// whose output lists it.
+fn jobs_option(format: RootfsFormat) -> (&'static str, &'static str, &'static str) {
+    match format {
+        RootfsFormat::Erofs => ("mkfs.erofs", "--help", "--workers"),
+        RootfsFormat::Squashfs => ("mksquashfs", "-help", "-processors"),
+    }
+}
+
+/// Arguments asking the image tool for `jobs` compression threads.
+fn jobs_args(format: RootfsFormat, jobs: usize) -> Vec<String> {
+    match format {
+        RootfsFormat::Erofs => vec![format!("--workers={}", jobs)],
+        RootfsFormat::Squashfs => vec!["-processors".to_string(), jobs.to_string()],
+    }
+}
+
+/// Whether `help` (a tool's help output) lists `option`.
+fn help_lists(help: &str, option: &str) -> bool {
+    help.split(|c: char| c.is_whitespace() || c == ',' || c == '=' || c == '[')
+        .any(|word| word == option)
+}
+
+/// Whether the installed image tool for `format` can set its threads.
+fn tool_takes_jobs(format: RootfsFormat) -> bool {
+    let (tool, help, option) = jobs_option(format);
+    // Help goes to stdout or stderr, and may exit non-zero
+    Command::new(tool)
+        .arg(help)
+        .output()
+        .map(|out| {
+            help_lists(&String::from_utf8_lossy(&out.stdout), option)
+                || help_lists(&String::from_utf8_lossy(&out.stderr), option)
+        })
+        .unwrap_or(false)
+}
+
+/// Timing phase of the image tool, with the threads it was given.
+fn image_phase_name(format: RootfsFormat, threads: Option<usize>) -> String {
+    match threads {
+        Some(jobs) => format!("{} image ({} jobs)", format.fs_type(), jobs),
+        None => format!("{} image (tool default threads)", format.fs_type()),
+    }
+}
+
+/// Create the filesystem image from the staging directory.
+///
+/// Returns the compression threads passed to the tool, `None` if it has
+/// no such option. With `reproducible`, the image's own times (and UUID)
+/// are fixed to that epoch.
+fn create_image(
+    format: RootfsFormat,
+    staging: &Path,
+    output: &Path,
+    jobs: usize,
+    reproducible: Option<u64>,
+) -> Result<Option<usize>> {
+    let threads = if tool_takes_jobs(format) {
+        log::info!("  Jobs: {}", jobs);
+        Some(jobs)
+    } else {
+        log::info!(
+            "  Note: this {} cannot set its compression threads; --jobs ignored",
+            jobs_option(format).0
+        );
+        None
+    };
+    let parallel = threads.map_or_else(Vec::new, |jobs| jobs_args(format, jobs));
+
+    match format {
+        RootfsFormat::Erofs => {
+            log::info!(
+                "  Compression: {} (level {})",
+                EROFS_COMPRESSION,
+                EROFS_COMPRESSION_LEVEL
+            );
+            // --all-root: the staging tree is owned by whoever built it
+            progress::run(
+                "EROFS",
+                Command::new("mkfs.erofs")
+                    .arg(format!(
+                        "-z{},{}",
+                        EROFS_COMPRESSION, EROFS_COMPRESSION_LEVEL
+                    ))
+                    .arg(format!("-C{}", EROFS_CHUNK_SIZE))
+                    .arg("--all-root")
+                    .args(&parallel)
+                    .args(
+                        reproducible
+                            .map(crate::reproducible::erofs_args)
+                            .unwrap_or_default(),
+                    )
+                    .arg(output)
+                    .arg(staging),
+                "mkfs.erofs failed. Install: sudo dnf install erofs-utils",
+            )?;
+        }
+        RootfsFormat::Squashfs => {
+            log::info!(
+                "  Compression: {} (level {})",
+                SQUASHFS_COMPRESSION,
+                SQUASHFS_COMPRESSION_LEVEL
+            );
+            // -progress: keep the progress bar with captured output
+            progress::run(
+                "squashfs",
+                Command::new("mksquashfs")
+                    .arg(staging)
+                    .arg(output)
+                    .args(["-comp", SQUASHFS_COMPRESSION])
+                    .arg("-Xcompression-level")
+                    .arg(SQUASHFS_COMPRESSION_LEVEL.to_string())
+                    .args(["-all-root", "-noappend", "-progress"])
+                    .args(&parallel)
+                    .args(
+                        reproducible
+                            .map(crate::reproducible::squashfs_args)
+                            .unwrap_or_default(),
+                    ),
+                "mksquashfs failed. Install: sudo dnf install squashfs-tools",
+            )?;
+        }
     }
+    Ok(threads)
+}
+
+/// How a built image is checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ImageCheck {
+    /// `fsck.erofs` (walks every inode and decompresses the data)
+    FsckErofs,
+    /// `unsquashfs -l` (reads the whole directory table)
+    ListSquashfs,
+    /// Unprivileged FUSE mount
+    Erofsfuse,
+    /// Kernel loop mount (root only)
+    LoopMount,
+}
 
+/// Best available check for `format`.
+pub(crate) fn image_check(
+    format: RootfsFormat,
+    has_tool: impl Fn(&str) -> bool,
+    is_root: bool,
+) -> Option<ImageCheck> {
+    let tool_check = match format {
+        RootfsFormat::Erofs if has_tool("fsck.erofs") => Some(ImageCheck::FsckErofs),
+        RootfsFormat::Erofs if has_tool("erofsfuse") => Some(ImageCheck::Erofsfuse),
+        RootfsFormat::Squashfs if has_tool("unsquashfs") => Some(ImageCheck::ListSquashfs),
+        _ => None,
+    };
+    tool_check.or(is_root.then_some(ImageCheck::LoopMount))
+}
+
+/// Required binaries and configs for which `present` is false.
+fn missing_required(present: impl Fn(&str) -> bool) -> Vec<&'static str> {
+    verification::REQUIRED_BINARIES
+        .iter()
+        .chain(verification::REQUIRED_CONFIGS)
+        .filter(|p| !present(p))
+        .copied()
+        .collect()
+}
+
+/// Paths (relative, no leading slash) from `unsquashfs -l` output.
+fn unsquashfs_paths(listing: &str) -> Vec<&str> {
+    listing
+        .lines()
+        .filter_map(|l| l.trim().strip_prefix(UNSQUASHFS_ROOT))
+        .map(|p| p.trim_start_matches('/'))
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+/// Verify the built image is readable and contains the required files.
+fn verify_image(format: RootfsFormat, image: &Path) -> Result<()> {
+    let Some(check) = image_check(format, process::exists, crate::privilege::is_root()) else {
+        log::warn!(
+            "No way to verify the {} image (install {}, or build as root)",
+            format,
+            match format {
+                RootfsFormat::Erofs => "fsck.erofs or erofsfuse",
+                RootfsFormat::Squashfs => "unsquashfs",
+            }
+        );
+        return Ok(());
+    };
+
+    log::info!("\n  Verifying {} image ({:?})...", format, check);
+    let missing = match check {
+        ImageCheck::FsckErofs => {
+            Cmd::new("fsck.erofs")
+                .arg_path(image)
+                .error_msg("fsck.erofs found a corrupt or truncated image")
+                .run()?;
+            Vec::new()
+        }
+        ImageCheck::ListSquashfs => {
+            let listing = Cmd::new("unsquashfs")
+                .arg("-l")
+                .arg_path(image)
+                .error_msg("unsquashfs could not read the image (corrupt or truncated?)")
+                .run()?
+                .stdout;
+            let paths = unsquashfs_paths(&listing);
+            missing_required(|p| paths.contains(&p))
+        }
+        ImageCheck::Erofsfuse | ImageCheck::LoopMount => {
+            let mnt = image.with_extension("mnt");
+            fs::create_dir_all(&mnt)?;
+            let mounted = if check == ImageCheck::Erofsfuse {
+                Cmd::new("erofsfuse").arg_path(image).arg_path(&mnt)
+            } else {
+                Cmd::new("mount")
+                    .args(["-t", format.fs_type(), "-o", "loop,ro"])
+                    .arg_path(image)
+                    .arg_path(&mnt)
+            }
+            .error_msg("Failed to mount the image (corrupt or truncated?)")
+            .run();
+            let missing = mounted.map(|_| {
+                // Absolute symlinks must not resolve against the host
+                missing_required(|p| mnt.join(p).symlink_metadata().is_ok())
+            });
+            if check == ImageCheck::Erofsfuse {
+                let _ = Cmd::new("fusermount")
+                    .arg("-u")
+                    .arg_path(&mnt)
+                    .allow_fail()
+                    .run();
+            } else {
+                let _ = Cmd::new("umount").arg_path(&mnt).allow_fail().run();
+            }
+            let _ = fs::remove_dir(&mnt);
+            missing?
+        }
+    };
+
+    if !missing.is_empty() {
+        for item in &missing {
+            log::warn!("✗ {} - Missing from image", item);
+        }
+        bail!(
+            "Image verification FAILED: {} required files missing from {}",
+            missing.len(),
+            image.display()
+        );
+    }
+    log::info!("  ✓ Image verification PASSED");
     Ok(())
 }
 
+/// Keep a failed image as `<image>.bad`, replacing an older one.
+fn keep_bad_image(image: &Path) -> Option<PathBuf> {
+    let mut bad = image.as_os_str().to_os_string();
+    bad.push(".bad");
+    let bad = PathBuf::from(bad);
+    let _ = fs::remove_file(&bad);
+    fs::rename(image, &bad).ok().map(|_| bad)
+}
+
 /// Verify the staging directory contains required files before creating EROFS.
 fn verify_staging(staging: &Path) -> Result<()> {
-    println!("\n  Verifying staging directory...");
+    log::info!("\n  Verifying staging directory...");
 
     let mut missing = Vec::new();
     let mut passed = 0;
@@ -139,6 +684,23 @@ fn verify_staging(staging: &Path) -> Result<()> {
         }
     }
 
+    // Check sshd and its helper binaries
+    for file in SSH_REQUIRED_FILES {
+        if staging.join(file).exists() {
+            passed += 1;
+        } else {
+            missing.push(*file);
+        }
+    }
+
+    // apk must work on the installed system
+    let apk_problems = crate::apk::staged_problems(staging);
+    if apk_problems.is_empty() {
+        passed += 1;
+    } else {
+        missing.extend(apk_problems.iter().map(String::as_str));
+    }
+
     // Check init.d directory has services
     let init_d = staging.join(verification::REQUIRED_SERVICE_DIR);
     if init_d.is_dir()
@@ -166,12 +728,12 @@ fn verify_staging(staging: &Path) -> Result<()> {
     let total = passed + missing.len();
 
     if missing.is_empty() {
-        println!("  ✓ Verification PASSED ({}/{} checks)", passed, total);
+        log::info!("  ✓ Verification PASSED ({}/{} checks)", passed, total);
         Ok(())
     } else {
-        println!("  ✗ Verification FAILED ({}/{} checks)", passed, total);
+        log::info!("  ✗ Verification FAILED ({}/{} checks)", passed, total);
         for item in &missing {
-            println!("    ✗ {} - Missing", item);
+            log::warn!("✗ {} - Missing", item);
         }
         bail!(
             "Rootfs verification FAILED: {} missing files.\n\
@@ -182,16 +744,191 @@ fn verify_staging(staging: &Path) -> Result<()> {
 }
 
 /// Check that required host tools are available.
-fn check_host_tools() -> Result<()> {
-    if !process::exists("mkfs.erofs") {
-        bail!(
+fn check_host_tools(format: RootfsFormat) -> Result<()> {
+    match format {
+        RootfsFormat::Erofs if !process::exists("mkfs.erofs") => bail!(
             "mkfs.erofs not found. Install erofs-utils:\n\
              On Fedora: sudo dnf install erofs-utils\n\
              On Ubuntu: sudo apt install erofs-utils\n\
              On Arch: sudo pacman -S erofs-utils\n\
              \n\
              NOTE: erofs-utils 1.5+ required for lz4hc compression."
+        ),
+        RootfsFormat::Squashfs if !process::exists("mksquashfs") => bail!(
+            "mksquashfs not found. Install squashfs-tools:\n\
+             On Fedora: sudo dnf install squashfs-tools\n\
+             On Ubuntu: sudo apt install squashfs-tools\n\
+             On Arch: sudo pacman -S squashfs-tools"
+        ),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_rootfs_format_paths_agree() {
+        for format in RootfsFormat::ALL {
+            assert!(format.iso_path().ends_with(format.image_name()));
+            assert!(format.image_name().ends_with(format.fs_type()));
+            assert_eq!(format.to_string().parse::<RootfsFormat>().unwrap(), format);
+        }
+        assert!("ext4".parse::<RootfsFormat>().is_err());
+    }
+
+    #[test]
+    fn test_image_check_selection() {
+        let has = |tools: &'static [&'static str]| move |t: &str| tools.contains(&t);
+        assert_eq!(
+            image_check(RootfsFormat::Erofs, has(&["fsck.erofs", "erofsfuse"]), true),
+            Some(ImageCheck::FsckErofs)
+        );
+        assert_eq!(
+            image_check(RootfsFormat::Erofs, has(&["erofsfuse"]), false),
+            Some(ImageCheck::Erofsfuse)
+        );
+        assert_eq!(
+            image_check(RootfsFormat::Erofs, has(&[]), true),
+            Some(ImageCheck::LoopMount)
         );
+        assert_eq!(image_check(RootfsFormat::Erofs, has(&[]), false), None);
+        assert_eq!(
+            image_check(RootfsFormat::Squashfs, has(&["unsquashfs"]), false),
+            Some(ImageCheck::ListSquashfs)
+        );
+
+        let listing = "Parallel unsquashfs: Using 8 processors\n\
+                       squashfs-root\n\
+                       squashfs-root/bin\n\
+                       squashfs-root/bin/busybox\n";
+        assert_eq!(unsquashfs_paths(listing), vec!["bin", "bin/busybox"]);
+    }
+
+    #[test]
+    fn test_keep_bad_image() {
+        let dir = tempdir().unwrap();
+        let work = dir.path().join("filesystem.erofs.work");
+        fs::write(&work, "truncated").unwrap();
+        fs::write(dir.path().join("filesystem.erofs.work.bad"), "older").unwrap();
+
+        let bad = keep_bad_image(&work).unwrap();
+        assert_eq!(bad, dir.path().join("filesystem.erofs.work.bad"));
+        assert!(!work.exists());
+        assert_eq!(fs::read_to_string(&bad).unwrap(), "truncated");
+    }
+
+    #[test]
+    fn test_rootfs_format_marker() {
+        let dir = tempdir().unwrap();
+        assert_eq!(RootfsFormat::load(dir.path()), RootfsFormat::Erofs);
+        RootfsFormat::Squashfs.save(dir.path()).unwrap();
+        assert_eq!(RootfsFormat::load(dir.path()), RootfsFormat::Squashfs);
+    }
+
+    #[test]
+    fn test_image_jobs_options() {
+        let cpuinfo = "processor\t: 0\nphysical id\t: 0\ncore id\t\t: 0\n\n\
+                       processor\t: 1\nphysical id\t: 0\ncore id\t\t: 0\n\n\
+                       processor\t: 2\nphysical id\t: 0\ncore id\t\t: 1\n\n\
+                       processor\t: 3\nphysical id\t: 1\ncore id\t\t: 0\n";
+        assert_eq!(physical_cores(cpuinfo), Some(3));
+        assert_eq!(physical_cores("processor\t: 0\nBogoMIPS\t: 50.00\n"), None);
+        assert!(default_jobs() >= 1);
+
+        assert!(help_lists(
+            " --workers=#          set the number of worker threads",
+            "--workers"
+        ));
+        assert!(help_lists(
+            "-processors <number>\tUse <number> processors",
+            "-processors"
+        ));
+        assert!(!help_lists(
+            " --all-root           make all files owned by root",
+            "--workers"
+        ));
+        assert_eq!(jobs_args(RootfsFormat::Erofs, 16), ["--workers=16"]);
+        assert_eq!(jobs_args(RootfsFormat::Squashfs, 4), ["-processors", "4"]);
+        assert_eq!(
+            image_phase_name(RootfsFormat::Erofs, Some(16)),
+            "erofs image (16 jobs)"
+        );
+    }
+
+    /// Files, symlinks and directories under `root`, by relative path.
+    fn tree_contents(root: &Path) -> Vec<(PathBuf, String)> {
+        let mut contents = Vec::new();
+        let mut pending = vec![root.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            for entry in fs::read_dir(&dir).unwrap() {
+                let path = entry.unwrap().path();
+                let rel = path.strip_prefix(root).unwrap().to_path_buf();
+                if path.is_symlink() {
+                    let target = fs::read_link(&path).unwrap();
+                    contents.push((rel, format!("-> {}", target.display())));
+                } else if path.is_dir() {
+                    contents.push((rel, "/".to_string()));
+                    pending.push(path);
+                } else {
+                    contents.push((rel, fs::read_to_string(&path).unwrap()));
+                }
+            }
+        }
+        contents.sort();
+        contents
+    }
+
+    /// Builds a small tree in both formats with one and with several
+    /// threads, and compares what the images unpack to. Needs mkfs.erofs,
+    /// fsck.erofs, mksquashfs and unsquashfs:
+    ///
+    /// `cargo test image_jobs -- --ignored --nocapture`
+    #[test]
+    #[ignore = "needs mkfs.erofs, fsck.erofs, mksquashfs and unsquashfs"]
+    fn test_image_jobs_keep_content() {
+        let dir = tempdir().unwrap();
+        let staging = dir.path().join("staging");
+        for i in 0..256 {
+            let sub = staging.join(format!("usr/share/{}", i % 16));
+            fs::create_dir_all(&sub).unwrap();
+            let line = format!("acorn {} {}\n", i, "x".repeat(i * 7 % 97));
+            fs::write(sub.join(format!("file{}", i)), line.repeat(64 + i)).unwrap();
+        }
+        std::os::unix::fs::symlink("usr/share/0/file0", staging.join("link")).unwrap();
+
+        for format in RootfsFormat::ALL {
+            let mut trees = Vec::new();
+            for jobs in [1, default_jobs().max(2)] {
+                let image = dir.path().join(format!("{}-{}", jobs, format.image_name()));
+                let started = std::time::Instant::now();
+                let threads = create_image(format, &staging, &image, jobs, None).unwrap();
+                eprintln!(
+                    "{}: {:?} ({} bytes)",
+                    image_phase_name(format, threads),
+                    started.elapsed(),
+                    fs::metadata(&image).unwrap().len()
+                );
+
+                let unpacked = dir.path().join(format!("{}-{}", format, jobs));
+                let status = match format {
+                    RootfsFormat::Erofs => Command::new("fsck.erofs")
+                        .arg(format!("--extract={}", unpacked.display()))
+                        .arg(&image)
+                        .status(),
+                    RootfsFormat::Squashfs => Command::new("unsquashfs")
+                        .arg("-d")
+                        .arg(&unpacked)
+                        .arg(&image)
+                        .status(),
+                };
+                assert!(status.unwrap().success());
+                trees.push(tree_contents(&unpacked));
+            }
+            assert_eq!(trees[0], tree_contents(&staging));
+            assert_eq!(trees[0], trees[1]);
+        }
     }
-    Ok(())
 }
@@ -5,17 +5,104 @@
 //!
 //! This module provides AcornOS-specific wrappers around recuki, handling:
 //! - OS branding (AcornOS name/version in boot menu)
-//! - Predefined UKI entries (live, emergency, debug, installed)
+//! - Predefined UKI entries (live, emergency, debug, persistent, installed)
 //! - Base cmdline construction from distro-spec constants
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use std::path::{Path, PathBuf};
 
-use distro_spec::acorn::{
-    ISO_LABEL, OS_ID, OS_NAME, OS_VERSION, UKI_ENTRIES, UKI_INSTALLED_ENTRIES,
-};
+use distro_spec::acorn::{OS_ID, OS_NAME, OS_VERSION, UKI_ENTRIES, UKI_INSTALLED_ENTRIES};
 use recuki::UkiConfig;
 
+use super::cmdline::CmdlineEdit;
+use super::iso_label::IsoLabel;
+use super::persistence::{persist_cmdline, PERSISTENT_ENTRY_NAME, PERSISTENT_UKI_FILENAME};
+
+/// Installed-system UKIs in the live system, for recstrap to copy to the ESP.
+pub const INSTALLED_UKIS_LIVE_PATH: &str = "usr/share/acornos/ukis";
+
+/// `ukify` outside `PATH` (Fedora ships it under systemd's libexec dir).
+const UKIFY_PATHS: &[&str] = &["/usr/lib/systemd/ukify", "/usr/libexec/systemd/ukify"];
+
+/// systemd EFI stub the UKIs are assembled on.
+pub const UKI_EFI_STUB: &str = "/usr/lib/systemd/boot/efi/linuxx64.efi.stub";
+
+/// A live boot menu entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiveEntry {
+    pub name: &'static str,
+    pub extra_cmdline: String,
+    pub filename: &'static str,
+}
+
+impl LiveEntry {
+    /// Kernel cmdline booting the ISO labeled `label`: `root=LABEL=`, then
+    /// `consoles` in place of the entry's own `console=` options (unless
+    /// empty), then the rest of the entry's options.
+    pub fn cmdline(&self, label: &str, consoles: &[String]) -> String {
+        let mut cmdline = vec![format!("root=LABEL={}", label)];
+        cmdline.extend(consoles.iter().map(|c| format!("console={}", c)));
+        cmdline.extend(
+            self.extra_cmdline
+                .split_whitespace()
+                .filter(|opt| consoles.is_empty() || !opt.starts_with("console="))
+                .map(str::to_string),
+        );
+        cmdline.join(" ")
+    }
+}
+
+/// Live boot entries: distro-spec's `UKI_ENTRIES`, then the persistent
+/// entry (the default entry's cmdline plus `acorn.persist=`).
+pub fn live_entries() -> Vec<LiveEntry> {
+    live_entries_with(&CmdlineEdit::default())
+}
+
+/// Live boot entries with `edit` applied to each entry's options.
+pub fn live_entries_with(edit: &CmdlineEdit) -> Vec<LiveEntry> {
+    let mut entries: Vec<LiveEntry> = UKI_ENTRIES
+        .iter()
+        .map(|e| LiveEntry {
+            name: e.name,
+            extra_cmdline: e.extra_cmdline.to_string(),
+            filename: e.filename,
+        })
+        .collect();
+    let default_cmdline = UKI_ENTRIES.first().map_or("", |e| e.extra_cmdline);
+    entries.push(LiveEntry {
+        name: PERSISTENT_ENTRY_NAME,
+        extra_cmdline: format!("{} {}", default_cmdline, persist_cmdline())
+            .trim_start()
+            .to_string(),
+        filename: PERSISTENT_UKI_FILENAME,
+    });
+    for entry in &mut entries {
+        entry.extra_cmdline = edit.apply(&entry.extra_cmdline);
+    }
+    entries
+}
+
+/// Locate `ukify`.
+pub fn find_ukify() -> Option<PathBuf> {
+    distro_builder::process::which("ukify")
+        .map(PathBuf::from)
+        .or_else(|| UKIFY_PATHS.iter().map(PathBuf::from).find(|p| p.is_file()))
+}
+
+/// Fail early, with install hints, if UKIs cannot be built on this host.
+pub fn check_uki_prerequisites() -> Result<()> {
+    if find_ukify().is_none() {
+        bail!("ukify not found (needed for --with-ukis). Install: sudo dnf install systemd-ukify");
+    }
+    if !Path::new(UKI_EFI_STUB).exists() {
+        bail!(
+            "systemd EFI stub not found at {} (needed for --with-ukis). Install: sudo dnf install systemd-boot-unsigned",
+            UKI_EFI_STUB
+        );
+    }
+    Ok(())
+}
+
 /// Build a UKI from kernel + initramfs + cmdline.
 ///
 /// Uses `recuki` library which wraps `ukify` from systemd.
@@ -27,7 +114,7 @@ use recuki::UkiConfig;
 /// * `cmdline` - Kernel command line string
 /// * `output` - Path for the output .efi file
 pub fn build_uki(kernel: &Path, initramfs: &Path, cmdline: &str, output: &Path) -> Result<()> {
-    println!("  Building UKI: {}", output.display());
+    log::info!("  Building UKI: {}", output.display());
 
     let config = UkiConfig::new(kernel, initramfs, cmdline, output)
         .with_os_release(OS_NAME, OS_ID, OS_VERSION);
@@ -45,37 +132,35 @@ pub fn build_uki(kernel: &Path, initramfs: &Path, cmdline: &str, output: &Path)
 /// * `kernel` - Path to the kernel image
 /// * `initramfs` - Path to the tiny live initramfs (mounts EROFS + overlay)
 /// * `output_dir` - Directory to write UKIs to
+/// * `label` - ISO volume label the live initramfs was built for
 ///
 /// # Cmdline
 ///
-/// Uses `root=LABEL=ACORNOS` - the ISO must use this label.
+/// Uses `root=LABEL=<label>` - the ISO must use this label.
 /// systemd-boot auto-discovers UKIs in EFI/Linux/ and presents them as boot menu entries.
 ///
 /// # Returns
 ///
 /// Vector of paths to the created UKI files.
-pub fn build_live_ukis(kernel: &Path, initramfs: &Path, output_dir: &Path) -> Result<Vec<PathBuf>> {
-    println!("Building UKIs for live ISO boot...");
-
-    // Base cmdline for live boot
-    // Console parameters are in each UKI entry's extra_cmdline (from distro-spec)
-    let base_cmdline = format!("root=LABEL={}", ISO_LABEL);
+pub fn build_live_ukis(
+    kernel: &Path,
+    initramfs: &Path,
+    output_dir: &Path,
+    label: &IsoLabel,
+) -> Result<Vec<PathBuf>> {
+    log::info!("Building UKIs for live ISO boot...");
 
     let mut outputs = Vec::new();
 
-    for entry in UKI_ENTRIES {
-        let cmdline = if entry.extra_cmdline.is_empty() {
-            base_cmdline.clone()
-        } else {
-            format!("{} {}", base_cmdline, entry.extra_cmdline)
-        };
-
+    for entry in live_entries() {
+        // Console parameters are in each UKI entry's extra_cmdline (from distro-spec)
+        let cmdline = entry.cmdline(label.as_str(), &[]);
         let output = output_dir.join(entry.filename);
         build_uki(kernel, initramfs, &cmdline, &output)?;
         outputs.push(output);
     }
 
-    println!("  Created {} live UKIs", outputs.len());
+    log::info!("  Created {} live UKIs", outputs.len());
     Ok(outputs)
 }
 
@@ -104,7 +189,7 @@ pub fn build_installed_ukis(
     initramfs: &Path,
     output_dir: &Path,
 ) -> Result<Vec<PathBuf>> {
-    println!("Building UKIs for installed systems...");
+    log::info!("Building UKIs for installed systems...");
 
     // Base cmdline for installed systems
     // Uses root=LABEL=root - user must label their root partition accordingly
@@ -126,21 +211,36 @@ pub fn build_installed_ukis(
         outputs.push(output);
     }
 
-    println!("  Created {} installed UKIs", outputs.len());
+    log::info!("  Created {} installed UKIs", outputs.len());
     Ok(outputs)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use distro_spec::acorn::ISO_LABEL;
 
     #[test]
     fn test_base_cmdline_format() {
-        let cmdline = format!("root=LABEL={}", ISO_LABEL);
-
-        assert!(cmdline.contains(&format!("root=LABEL={}", ISO_LABEL)));
+        let entry = LiveEntry {
+            name: "Emergency",
+            extra_cmdline: "console=ttyS0,115200 emergency".to_string(),
+            filename: "acornos-emergency.efi",
+        };
         // Console parameters come from entry extra_cmdline, not base cmdline
-        assert!(!cmdline.contains("console="));
+        assert_eq!(
+            entry.cmdline(ISO_LABEL, &[]),
+            format!("root=LABEL={} console=ttyS0,115200 emergency", ISO_LABEL)
+        );
+        let consoles = ["tty0".to_string(), "ttyAMA0,115200".to_string()];
+        assert_eq!(
+            entry.cmdline("ACORNOS", &consoles),
+            "root=LABEL=ACORNOS console=tty0 console=ttyAMA0,115200 emergency"
+        );
+        assert_eq!(
+            live_entries()[0].cmdline(ISO_LABEL, &[]).split(' ').next(),
+            Some(format!("root=LABEL={}", ISO_LABEL).as_str())
+        );
     }
 
     #[test]
@@ -154,6 +254,18 @@ mod tests {
         assert!(UKI_ENTRIES
             .iter()
             .any(|e| e.filename == "acornos-debug.efi"));
+
+        // The persistent entry follows them, on the default entry's cmdline
+        let live = live_entries();
+        assert_eq!(live.len(), UKI_ENTRIES.len() + 1);
+        let persistent = live.last().unwrap();
+        assert_eq!(persistent.name, "AcornOS (Persistent)");
+        assert!(persistent
+            .extra_cmdline
+            .starts_with(UKI_ENTRIES[0].extra_cmdline));
+        assert!(persistent
+            .extra_cmdline
+            .ends_with("acorn.persist=LABEL=ACORN-PERSIST"));
     }
 
     #[test]
@@ -0,0 +1,258 @@
+//! Boot-time history of `acornos test` (`output/boot-times.jsonl`).
+//!
+//! Every boot test appends one JSON line: when it ran, the SHA512 of the
+//! ISO it booted (from the `.sha512` sidecar), and the time from power-on
+//! to the kernel, to OpenRC and to the ready shell. `acornos test
+//! --history` prints the last runs, so a slow drift in boot time shows up
+//! next to the ISO that introduced it.
+//!
+//! `--baseline <secs>` fails the test when shell-ready is more than
+//! `--baseline-tolerance` percent slower than the baseline.
+
+use anyhow::{bail, Context, Result};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::manifest::{json_str, parse_json, JsonValue};
+use crate::package_manifest::iso8601;
+
+/// History file in the output dir.
+pub const BOOT_HISTORY_FILENAME: &str = "boot-times.jsonl";
+
+/// Runs shown by `--history` without a count.
+pub const DEFAULT_HISTORY_RUNS: usize = 10;
+
+/// Default `--baseline-tolerance`, in percent.
+pub const DEFAULT_BASELINE_TOLERANCE: f64 = 10.0;
+
+/// One boot test run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BootRecord {
+    /// `YYYY-MM-DDTHH:MM:SSZ`.
+    pub timestamp: String,
+    /// From the ISO's sidecar; `None` if it has none.
+    pub iso_sha512: Option<String>,
+    /// Seconds from power-on; `None` if the stage was not reached.
+    pub kernel: Option<f64>,
+    pub openrc: Option<f64>,
+    pub shell_ready: Option<f64>,
+    pub passed: bool,
+    /// Booted with KVM (TCG times are not comparable).
+    pub kvm: bool,
+}
+
+impl BootRecord {
+    /// Record for a run finishing now.
+    pub fn now(
+        iso_sha512: Option<String>,
+        stages: [Option<Duration>; 3],
+        passed: bool,
+        kvm: bool,
+    ) -> Self {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let [kernel, openrc, shell_ready] = stages.map(|s| s.map(|d| d.as_secs_f64()));
+        Self {
+            timestamp: iso8601(secs),
+            iso_sha512,
+            kernel,
+            openrc,
+            shell_ready,
+            passed,
+            kvm,
+        }
+    }
+
+    /// One JSONL line, without the newline.
+    pub fn to_json_line(&self) -> String {
+        let secs = |s: Option<f64>| s.map_or("null".to_string(), |s| format!("{:.3}", s));
+        format!(
+            "{{\"timestamp\": {}, \"iso_sha512\": {}, \"kernel_seconds\": {}, \
+             \"openrc_seconds\": {}, \"shell_ready_seconds\": {}, \"passed\": {}, \"kvm\": {}}}",
+            json_str(&self.timestamp),
+            self.iso_sha512
+                .as_deref()
+                .map_or("null".to_string(), json_str),
+            secs(self.kernel),
+            secs(self.openrc),
+            secs(self.shell_ready),
+            self.passed,
+            self.kvm
+        )
+    }
+
+    /// Parse a line written by [`Self::to_json_line`].
+    pub fn parse(line: &str) -> Option<Self> {
+        let record = parse_json(line)?;
+        let field = |key| record.get(key);
+        let secs = |key| match field(key)? {
+            JsonValue::Null => Some(None),
+            v => v.as_f64().map(Some),
+        };
+        let string = |key| match field(key)? {
+            JsonValue::Null => Some(None),
+            v => v.as_str().map(|s| Some(s.to_string())),
+        };
+        Some(Self {
+            timestamp: string("timestamp")??,
+            iso_sha512: string("iso_sha512")?,
+            kernel: secs("kernel_seconds")?,
+            openrc: secs("openrc_seconds")?,
+            shell_ready: secs("shell_ready_seconds")?,
+            passed: field("passed")?.as_bool()?,
+            kvm: field("kvm")?.as_bool()?,
+        })
+    }
+}
+
+/// SHA512 recorded in the sidecar of `iso`, if there is one.
+pub fn iso_sha512(iso: &Path) -> Option<String> {
+    let content = fs::read_to_string(crate::signing::sha512_path(iso)).ok()?;
+    content.split_whitespace().next().map(str::to_lowercase)
+}
+
+/// Append `record` to the history in `output_dir`.
+pub fn append(output_dir: &Path, record: &BootRecord) -> Result<()> {
+    let path = output_dir.join(BOOT_HISTORY_FILENAME);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", record.to_json_line())
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// The last `count` runs in `output_dir`, oldest first. Unreadable lines
+/// are skipped.
+pub fn load(output_dir: &Path, count: usize) -> Vec<BootRecord> {
+    let content = fs::read_to_string(output_dir.join(BOOT_HISTORY_FILENAME)).unwrap_or_default();
+    let records: Vec<BootRecord> = content.lines().filter_map(BootRecord::parse).collect();
+    let skip = records.len().saturating_sub(count);
+    records.into_iter().skip(skip).collect()
+}
+
+/// Print `records` as a table.
+pub fn print_history(records: &[BootRecord]) {
+    let secs = |s: Option<f64>| s.map_or("-".to_string(), |s| format!("{:.1}s", s));
+    println!(
+        "  {:<20} {:<12} {:>8} {:>8} {:>8}  {:<4}  Result",
+        "Timestamp", "ISO", "Kernel", "OpenRC", "Shell", "Accel"
+    );
+    for r in records {
+        println!(
+            "  {:<20} {:<12} {:>8} {:>8} {:>8}  {:<4}  {}",
+            r.timestamp,
+            r.iso_sha512
+                .as_deref()
+                .map_or("-", |s| &s[..s.len().min(12)]),
+            secs(r.kernel),
+            secs(r.openrc),
+            secs(r.shell_ready),
+            if r.kvm { "kvm" } else { "tcg" },
+            if r.passed { "PASS" } else { "FAIL" }
+        );
+    }
+}
+
+/// Shell-ready time a test must stay within (`--baseline`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Baseline {
+    pub seconds: f64,
+    /// Allowed slowdown, in percent.
+    pub tolerance: f64,
+}
+
+impl Baseline {
+    pub fn new(seconds: f64, tolerance: f64) -> Result<Self> {
+        if !seconds.is_finite() || seconds <= 0.0 || tolerance.is_nan() || tolerance < 0.0 {
+            bail!(
+                "Invalid baseline {}s +{}%: expected a positive time and a non-negative tolerance",
+                seconds,
+                tolerance
+            );
+        }
+        Ok(Self { seconds, tolerance })
+    }
+
+    /// Slowest shell-ready time that still passes.
+    pub fn limit(&self) -> f64 {
+        self.seconds * (1.0 + self.tolerance / 100.0)
+    }
+
+    /// Fail if `shell_ready` regressed past the limit.
+    pub fn check(&self, shell_ready: Duration) -> Result<()> {
+        let measured = shell_ready.as_secs_f64();
+        if measured > self.limit() {
+            bail!(
+                "Boot time regression: shell ready after {:.1}s, {:.0}% slower than the {:.1}s \
+                 baseline (tolerance {}%)",
+                measured,
+                (measured / self.seconds - 1.0) * 100.0,
+                self.seconds,
+                self.tolerance
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_history_roundtrip_and_baseline() {
+        let dir = tempdir().unwrap();
+        let record = |shell: Option<u64>, passed| BootRecord {
+            timestamp: "2026-10-16T09:30:00Z".to_string(),
+            iso_sha512: Some("ab12".repeat(32)),
+            kernel: Some(4.0),
+            openrc: Some(7.25),
+            shell_ready: shell.map(|s| s as f64),
+            passed,
+            kvm: true,
+        };
+        let slow = BootRecord {
+            iso_sha512: None,
+            kvm: false,
+            ..record(None, false)
+        };
+        for r in [record(Some(10), true), record(Some(12), true), slow.clone()] {
+            append(dir.path(), &r).unwrap();
+        }
+        fs::write(
+            dir.path().join(BOOT_HISTORY_FILENAME),
+            fs::read_to_string(dir.path().join(BOOT_HISTORY_FILENAME)).unwrap() + "garbage\n",
+        )
+        .unwrap();
+
+        let line = record(Some(10), true).to_json_line();
+        assert!(line.contains("\"openrc_seconds\": 7.250"));
+        assert!(line.contains("\"shell_ready_seconds\": 10.000"));
+
+        let last = load(dir.path(), 2);
+        assert_eq!(last, [record(Some(12), true), slow]);
+        assert_eq!(load(dir.path(), DEFAULT_HISTORY_RUNS).len(), 3);
+        assert!(load(&dir.path().join("missing"), 5).is_empty());
+
+        let baseline = Baseline::new(10.0, DEFAULT_BASELINE_TOLERANCE).unwrap();
+        assert!(baseline.check(Duration::from_secs(11)).is_ok());
+        let err = baseline
+            .check(Duration::from_secs(12))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("20% slower than the 10.0s baseline"),
+            "{}",
+            err
+        );
+        assert!(Baseline::new(0.0, 10.0).is_err());
+        assert!(Baseline::new(10.0, -1.0).is_err());
+    }
+}
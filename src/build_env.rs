@@ -0,0 +1,357 @@
+//! Build environment description (`output/build-env.json`).
+//!
+//! When an ISO cannot be reproduced elsewhere, the first question is what
+//! differed between the two hosts. `acornos env export` records the host,
+//! the image tool versions (probed like `preflight` does), checksums of the
+//! inputs actually in `downloads/`, the repo's `git describe` and the
+//! environment overrides the build reads. `acornos env diff <file>`
+//! compares the current environment with an exported one and marks the
+//! fields likely to change the artifacts.
+//!
+//! Fields are flat `section.name` keys with string (or null) values, so
+//! the file diffs well as text too. Every ISO carries a copy at
+//! `/usr/share/acornos/build-env.json`.
+
+use anyhow::{bail, Context, Result};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::arch::{Arch, ARCH_ENV};
+use crate::manifest::{git_describe, json_str, parse_json, JsonValue};
+use crate::offline::{sha256_file, OfflineCache, OFFLINE_ENV};
+use crate::paths::OutputPaths;
+
+/// Build environment filename in the output directory.
+pub const BUILD_ENV_FILENAME: &str = "build-env.json";
+
+/// Where the description lands in the live system.
+pub const BUILD_ENV_LIVE_PATH: &str = "usr/share/acornos/build-env.json";
+
+/// Bump when fields are removed or change meaning.
+pub const BUILD_ENV_SCHEMA_VERSION: u32 = 1;
+
+/// Image and boot tools, with the flag printing their version.
+const TOOLS: &[(&str, &str)] = &[
+    ("xorriso", "-version"),
+    ("mksquashfs", "-version"),
+    ("mkfs.erofs", "--version"),
+    ("grub2-mkstandalone", "--version"),
+    ("grub-mkstandalone", "--version"),
+];
+
+/// Environment overrides the build reads.
+const ENV_OVERRIDES: &[&str] = &[
+    "ISO_LABEL",
+    crate::reproducible::SOURCE_DATE_EPOCH_ENV,
+    crate::reproducible::REPRODUCIBLE_ENV,
+    crate::alpine_source::MIRROR_ENV,
+    crate::alpine_source::VERSION_ENV,
+    ARCH_ENV,
+    OFFLINE_ENV,
+    crate::component::selection::DISABLE_ENV,
+    crate::paths::BASE_DIR_ENV,
+    crate::paths::OUTPUT_DIR_ENV,
+    crate::download_cache::CACHE_ENV,
+];
+
+/// Fields that do not change what gets built (where it runs, where files go).
+const INCIDENTAL: &[&str] = &[
+    "host.",
+    "tool.qemu",
+    "env.ACORNOS_BASE_DIR",
+    "env.ACORNOS_OUTPUT_DIR",
+    "env.ACORNOS_DOWNLOAD_CACHE",
+];
+
+/// The recorded environment, in field order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildEnv {
+    pub fields: Vec<(String, Option<String>)>,
+}
+
+impl BuildEnv {
+    /// Describe this host and the inputs under `base_dir`.
+    pub fn collect(base_dir: &Path) -> Self {
+        let mut env = Self::default();
+        let os_release = fs::read_to_string("/etc/os-release").unwrap_or_default();
+        env.set("host.distro", os_release_name(&os_release));
+        env.set(
+            "host.kernel",
+            fs::read_to_string("/proc/sys/kernel/osrelease")
+                .ok()
+                .map(|r| r.trim().to_string()),
+        );
+        env.set("host.arch", Some(std::env::consts::ARCH.to_string()));
+
+        for (tool, flag) in TOOLS {
+            env.set(
+                &format!("tool.{}", tool),
+                crate::preflight::tool_version(tool, flag),
+            );
+        }
+        let qemu = Arch::from_env().unwrap_or_default().qemu_binary();
+        env.set(
+            "tool.qemu",
+            crate::preflight::tool_version(qemu, "--version"),
+        );
+
+        let cache = OfflineCache::new(base_dir);
+        let checksum = |path: &Path| path.is_file().then(|| sha256_file(path).ok()).flatten();
+        env.set(
+            "input.alpine_iso",
+            cache
+                .iso
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned()),
+        );
+        env.set("input.alpine_iso.sha256", checksum(&cache.iso));
+        env.set("input.apk_tools.sha256", checksum(&cache.apk_static));
+        env.set("input.busybox.sha256", checksum(&cache.busybox));
+        env.set("source.git_describe", git_describe(base_dir));
+
+        for var in ENV_OVERRIDES {
+            env.set(&format!("env.{}", var), std::env::var(var).ok());
+        }
+        env
+    }
+
+    pub fn set(&mut self, key: &str, value: Option<String>) {
+        self.fields.push((key.to_string(), value));
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, v)| v.as_deref())
+    }
+
+    /// The fields that can change the artifacts.
+    pub fn without_incidental(&self) -> Self {
+        Self {
+            fields: self
+                .fields
+                .iter()
+                .filter(|(key, _)| !is_incidental(key))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Render as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        let rows: Vec<String> = self
+            .fields
+            .iter()
+            .map(|(key, value)| {
+                format!(
+                    "    {}: {}",
+                    json_str(key),
+                    value.as_deref().map_or("null".to_string(), json_str)
+                )
+            })
+            .collect();
+        format!(
+            "{{\n  \"schema_version\": {},\n  \"fields\": {{\n{}\n  }}\n}}\n",
+            BUILD_ENV_SCHEMA_VERSION,
+            rows.join(",\n")
+        )
+    }
+
+    /// Parse JSON written by [`BuildEnv::to_json`].
+    pub fn parse(json: &str) -> Option<Self> {
+        let fields = parse_json(json)?
+            .get("fields")?
+            .as_object()?
+            .iter()
+            .map(|(key, value)| match value {
+                JsonValue::Null => Some((key.clone(), None)),
+                value => Some((key.clone(), Some(value.as_str()?.to_string()))),
+            })
+            .collect::<Option<_>>()?;
+        Some(Self { fields })
+    }
+
+    /// Fields that differ from `other`, in this description's order.
+    pub fn diff(&self, other: &Self) -> Vec<FieldChange> {
+        let mut keys: Vec<&str> = self.fields.iter().map(|(k, _)| k.as_str()).collect();
+        for (key, _) in &other.fields {
+            if !keys.contains(&key.as_str()) {
+                keys.push(key);
+            }
+        }
+        keys.into_iter()
+            .filter(|key| self.get(key) != other.get(key))
+            .map(|key| FieldChange {
+                key: key.to_string(),
+                before: self.get(key).map(str::to_string),
+                after: other.get(key).map(str::to_string),
+            })
+            .collect()
+    }
+}
+
+/// A field whose value differs between two descriptions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldChange {
+    pub key: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl FieldChange {
+    /// Whether this difference is likely to change the artifacts.
+    pub fn affects_artifacts(&self) -> bool {
+        !is_incidental(&self.key)
+    }
+}
+
+fn is_incidental(key: &str) -> bool {
+    INCIDENTAL.iter().any(|prefix| key.starts_with(prefix))
+}
+
+/// Human-readable `changes` since the description exported to `exported`.
+pub fn render_diff(exported: &Path, changes: &[FieldChange]) -> String {
+    if changes.is_empty() {
+        return format!("No differences from {}\n", exported.display());
+    }
+    let mut out = format!(
+        "Differences from {} (! = likely to change the artifacts):\n",
+        exported.display()
+    );
+    let show = |v: &Option<String>| v.clone().unwrap_or_else(|| "(unset)".to_string());
+    for change in changes {
+        let _ = writeln!(
+            out,
+            "  {} {}: {} -> {}",
+            if change.affects_artifacts() { "!" } else { " " },
+            change.key,
+            show(&change.before),
+            show(&change.after)
+        );
+    }
+    out
+}
+
+/// `PRETTY_NAME` (else `NAME`) from os-release.
+fn os_release_name(os_release: &str) -> Option<String> {
+    let field = |name: &str| {
+        os_release.lines().find_map(|line| {
+            let value = line.strip_prefix(name)?.strip_prefix('=')?;
+            Some(value.trim().trim_matches('"').to_string())
+        })
+    };
+    field("PRETTY_NAME").or_else(|| field("NAME"))
+}
+
+/// Collect the description and write it to the output dir and the live
+/// overlay. A reproducible build leaves the incidental fields (host, output
+/// dir) out of the live copy, which would otherwise differ between builds.
+pub fn write_build_env(base_dir: &Path, output_dir: &Path) -> Result<PathBuf> {
+    let env = BuildEnv::collect(base_dir);
+    let json = env.to_json();
+    let path = output_dir.join(BUILD_ENV_FILENAME);
+    fs::write(&path, &json).with_context(|| format!("Failed to write {}", path.display()))?;
+    let live = OutputPaths::new(output_dir)
+        .live_overlay()
+        .join(BUILD_ENV_LIVE_PATH);
+    if let Some(parent) = live.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let live_json = if crate::reproducible::epoch()?.is_some() {
+        env.without_incidental().to_json()
+    } else {
+        json
+    };
+    fs::write(&live, &live_json).with_context(|| format!("Failed to write {}", live.display()))?;
+    Ok(path)
+}
+
+/// Compare this host with the description exported to `exported`.
+pub fn diff_with(base_dir: &Path, exported: &Path) -> Result<String> {
+    let json = fs::read_to_string(exported)
+        .with_context(|| format!("Failed to read {}", exported.display()))?;
+    let Some(previous) = BuildEnv::parse(&json) else {
+        bail!(
+            "{} is not a build environment description (see 'acornos env export')",
+            exported.display()
+        );
+    };
+    let changes = previous.diff(&BuildEnv::collect(base_dir));
+    Ok(render_diff(exported, &changes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> BuildEnv {
+        let mut env = BuildEnv::default();
+        env.set("host.kernel", Some("6.9.4".to_string()));
+        env.set("tool.mkfs.erofs", Some("1.7.1".to_string()));
+        env.set(
+            "input.alpine_iso",
+            Some("alpine \"extended\".iso".to_string()),
+        );
+        env.set("env.ISO_LABEL", None);
+        env
+    }
+
+    #[test]
+    fn test_build_env_json_round_trip() {
+        let env = sample();
+        let json = env.to_json();
+        assert!(json.contains("    \"tool.mkfs.erofs\": \"1.7.1\",\n"));
+        assert!(json.contains("    \"env.ISO_LABEL\": null\n"));
+        assert_eq!(BuildEnv::parse(&json), Some(env));
+        assert_eq!(BuildEnv::parse("{}"), None);
+
+        assert_eq!(
+            os_release_name("NAME=Fedora Linux\nPRETTY_NAME=\"Fedora Linux 40\"\n").as_deref(),
+            Some("Fedora Linux 40")
+        );
+        assert_eq!(os_release_name("ID=alpine\n"), None);
+    }
+
+    #[test]
+    fn test_build_env_diff() {
+        let before = sample();
+        let mut after = sample();
+        after.fields[0].1 = Some("6.10.0".to_string());
+        after.fields[1].1 = Some("1.8.2".to_string());
+        after.fields[3].1 = Some("TEST".to_string());
+        after.set("source.git_describe", Some("v0.1-3-gabc".to_string()));
+
+        let changes = before.diff(&after);
+        let keys: Vec<&str> = changes.iter().map(|c| c.key.as_str()).collect();
+        assert_eq!(
+            keys,
+            [
+                "host.kernel",
+                "tool.mkfs.erofs",
+                "env.ISO_LABEL",
+                "source.git_describe"
+            ]
+        );
+        assert!(!changes[0].affects_artifacts());
+        assert!(changes[1].affects_artifacts());
+        assert_eq!(before.without_incidental().get("host.kernel"), None);
+        assert_eq!(
+            before.without_incidental().get("tool.mkfs.erofs"),
+            Some("1.7.1")
+        );
+
+        let file = Path::new("build-env.json");
+        let text = render_diff(file, &changes);
+        assert!(
+            text.contains("\n    host.kernel: 6.9.4 -> 6.10.0\n"),
+            "{}",
+            text
+        );
+        assert!(text.contains("\n  ! tool.mkfs.erofs: 1.7.1 -> 1.8.2\n"));
+        assert!(text.contains("\n  ! env.ISO_LABEL: (unset) -> TEST\n"));
+        assert!(text.contains("\n  ! source.git_describe: (unset) -> v0.1-3-gabc\n"));
+        assert!(render_diff(file, &before.diff(&sample())).starts_with("No differences"));
+    }
+}
@@ -0,0 +1,198 @@
+//! Version and build metadata of an image (`/etc/os-release`, ISO
+//! application id, `acornos status`).
+//!
+//! - `VERSION_ID`: the project version, from `VERSION` in the base dir,
+//!   else the crate version
+//! - `BUILD_ID`: `git describe --always --dirty` of the monorepo (of the
+//!   base dir outside one), so a bug report names the nightly it came from
+//! - `ACORNOS_BUILD_DATE`: when the build ran, `SOURCE_DATE_EPOCH` for
+//!   reproducible builds
+//!
+//! The version and build id are part of the rootfs and ISO rebuild hashes:
+//! a new commit rebuilds both (the branding component is the only one that
+//! re-runs), so the metadata never lags behind the tree.
+
+use std::fs;
+use std::path::Path;
+
+use crate::artifact::IsoVariant;
+
+/// Project version file in the base dir (one line, e.g. `0.2.0`).
+pub const VERSION_FILE: &str = "VERSION";
+
+/// Suffix `git describe --dirty` appends for uncommitted changes.
+const DIRTY_SUFFIX: &str = "-dirty";
+
+/// Longest ISO 9660 application id.
+const APPLICATION_ID_MAX: usize = 128;
+
+/// What the image says about its own build.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildInfo {
+    pub version_id: String,
+    /// `None` outside a git checkout (e.g. a source tarball).
+    pub build_id: Option<String>,
+    /// UTC, `YYYY-MM-DDThh:mm:ssZ`.
+    pub build_date: String,
+}
+
+impl BuildInfo {
+    /// Metadata of a build from `base_dir` now.
+    pub fn detect(base_dir: &Path) -> Self {
+        let repo = crate::kernel::monorepo_root(base_dir).unwrap_or_else(|_| base_dir.into());
+        Self {
+            version_id: project_version(base_dir),
+            build_id: crate::manifest::git_describe(&repo),
+            build_date: utc_date(crate::manifest::build_timestamp()),
+        }
+    }
+
+    /// Whether the tree had uncommitted changes.
+    pub fn dirty(&self) -> bool {
+        self.build_id
+            .as_deref()
+            .is_some_and(|id| id.ends_with(DIRTY_SUFFIX))
+    }
+
+    /// os-release of the `variant` system.
+    pub fn os_release(&self, variant: IsoVariant) -> String {
+        let build_id = self.build_id.as_deref().unwrap_or("unknown");
+        format!(
+            "NAME=\"AcornOS\"\n\
+             ID=acornos\n\
+             ID_LIKE=alpine\n\
+             VERSION=\"{version} ({build_id})\"\n\
+             VERSION_ID={version}\n\
+             BUILD_ID={build_id}\n\
+             VARIANT=\"{variant_name}\"\n\
+             VARIANT_ID={variant}\n\
+             PRETTY_NAME=\"AcornOS {version}\"\n\
+             ACORNOS_BUILD_DATE={date}\n\
+             HOME_URL=\"https://levitateos.org/acorn\"\n\
+             BUG_REPORT_URL=\"https://github.com/levitateos/levitateos/issues\"\n",
+            version = self.version_id,
+            build_id = build_id,
+            variant_name = variant_name(variant),
+            variant = variant,
+            date = self.build_date,
+        )
+    }
+
+    /// ISO volume application id, e.g. `ACORNOS 0.1.0 v0.1-3-gabc1234`.
+    pub fn application_id(&self) -> String {
+        let mut id = format!("ACORNOS {}", self.version_id);
+        if let Some(build_id) = &self.build_id {
+            id.push(' ');
+            id.push_str(build_id);
+        }
+        id.chars().take(APPLICATION_ID_MAX).collect()
+    }
+
+    /// Stable description for rebuild hashes. The date is left out: it
+    /// changes on every run, and reproducible builds hash their epoch anyway.
+    pub fn fingerprint(&self) -> String {
+        format!(
+            "version={} build={}",
+            self.version_id,
+            self.build_id.as_deref().unwrap_or("")
+        )
+    }
+}
+
+fn variant_name(variant: IsoVariant) -> &'static str {
+    match variant {
+        IsoVariant::Live => "Live",
+        IsoVariant::Netinstall => "Netinstall",
+    }
+}
+
+/// `VERSION` of `base_dir`, else the crate version.
+pub fn project_version(base_dir: &Path) -> String {
+    fs::read_to_string(base_dir.join(VERSION_FILE))
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| env!("CARGO_PKG_VERSION").to_string())
+}
+
+/// `epoch` as `YYYY-MM-DDThh:mm:ssZ`.
+fn utc_date(epoch: u64) -> String {
+    let d = crate::reproducible::utc_digits(epoch);
+    format!(
+        "{}-{}-{}T{}:{}:{}Z",
+        &d[0..4],
+        &d[4..6],
+        &d[6..8],
+        &d[8..10],
+        &d[10..12],
+        &d[12..14]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_os_release_content() {
+        let info = BuildInfo {
+            version_id: "0.2.0".to_string(),
+            build_id: Some("v0.1-3-gabc1234".to_string()),
+            build_date: utc_date(1_700_000_000),
+        };
+        assert_eq!(info.build_date, "2023-11-14T22:13:20Z");
+        let text = info.os_release(IsoVariant::Live);
+        for line in [
+            "VERSION_ID=0.2.0\n",
+            "VERSION=\"0.2.0 (v0.1-3-gabc1234)\"\n",
+            "BUILD_ID=v0.1-3-gabc1234\n",
+            "VARIANT_ID=live\n",
+            "ACORNOS_BUILD_DATE=2023-11-14T22:13:20Z\n",
+        ] {
+            assert!(text.contains(line), "{}: {}", line, text);
+        }
+        assert!(!text.contains("Alpine"));
+        assert!(info
+            .os_release(IsoVariant::Netinstall)
+            .contains("VARIANT_ID=netinstall\n"));
+        assert_eq!(info.application_id(), "ACORNOS 0.2.0 v0.1-3-gabc1234");
+        assert!(!info.dirty());
+
+        let dir = tempdir().unwrap();
+        assert_eq!(project_version(dir.path()), env!("CARGO_PKG_VERSION"));
+        fs::write(dir.path().join(VERSION_FILE), "1.2.3\n").unwrap();
+        assert_eq!(project_version(dir.path()), "1.2.3");
+    }
+
+    #[test]
+    fn test_dirty_tree_flagged() {
+        let dir = tempdir().unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args(["-c", "user.name=t", "-c", "user.email=t@example.com"])
+                .args(args)
+                .current_dir(dir.path())
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {:?}", args);
+        };
+        git(&["init", "-q"]);
+        fs::write(dir.path().join("README"), "acorn\n").unwrap();
+        git(&["add", "README"]);
+        git(&["commit", "-q", "-m", "init"]);
+
+        let clean = BuildInfo::detect(dir.path());
+        assert!(clean.build_id.is_some());
+        assert!(!clean.dirty());
+
+        fs::write(dir.path().join("README"), "changed\n").unwrap();
+        let dirty = BuildInfo::detect(dir.path());
+        assert!(dirty.dirty(), "{:?}", dirty.build_id);
+        assert!(dirty
+            .os_release(IsoVariant::Live)
+            .contains(&format!("BUILD_ID={}-dirty\n", clean.build_id.unwrap())));
+    }
+}
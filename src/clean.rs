@@ -0,0 +1,285 @@
+//! Scoped cleanup of build state (`acornos clean`).
+//!
+//! Three categories, each confined to one directory:
+//!
+//! - **output** - everything in the output dir except the input-hash files
+//!   (ISO, rootfs image, initramfs, staging, iso-root, work files)
+//! - **cache** - the `.*.hash` rebuild/store keys and the `.store-meta/`
+//!   checksums in the output dir and the recipe state markers in
+//!   `downloads/`, so recipes and builds re-run
+//! - **downloads** - the whole `downloads/` directory
+//!
+//! Symlinks are removed, never followed, and each category root must be a
+//! real directory: a root that is itself a symlink is refused, so nothing
+//! outside the base/output/downloads directories can be touched.
+
+use anyhow::{bail, Context, Result};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::apk_install::TIERS_STATE;
+use crate::output_lock::LOCK_FILE;
+use crate::package_overlay::OVERLAY_STATE;
+use crate::store_meta::STORE_META_DIR;
+
+/// Recipe state markers under `downloads/` (read by the recipes' `is_built`).
+pub const RECIPE_MARKERS: &[&str] = &[
+    ".alpine-built-version",
+    ".packages-version",
+    ".packages-manifest",
+    ".packages-installed",
+    TIERS_STATE,
+    OVERLAY_STATE,
+];
+
+/// What to clean.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CleanTarget {
+    /// Output artifacts; downloads and hashes are kept.
+    Output,
+    /// The downloads directory.
+    Downloads,
+    /// Rebuild hashes and recipe state.
+    Cache,
+    /// All of the above.
+    All,
+}
+
+/// Bytes and entries freed in one category.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CleanSummary {
+    pub category: &'static str,
+    pub bytes: u64,
+    pub entries: usize,
+}
+
+impl fmt::Display for CleanSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<10} {:>10} freed ({} entries)",
+            self.category,
+            human_bytes(self.bytes),
+            self.entries
+        )
+    }
+}
+
+/// Clean `target` under `base_dir` / `output_dir`.
+pub fn clean(base_dir: &Path, output_dir: &Path, target: CleanTarget) -> Result<Vec<CleanSummary>> {
+    let downloads = base_dir.join("downloads");
+    let mut summaries = Vec::new();
+
+    // Cache first, so hash files are counted there rather than as output
+    if matches!(target, CleanTarget::Cache | CleanTarget::All) {
+        let mut summary = remove_children(output_dir, "cache", is_hash_file)?;
+        let markers = remove_children(&downloads, "cache", |name| RECIPE_MARKERS.contains(&name))?;
+        summary.bytes += markers.bytes;
+        summary.entries += markers.entries;
+        summaries.push(summary);
+    }
+    if matches!(target, CleanTarget::Output | CleanTarget::All) {
+        // The lock this clean runs under: a new file would let a waiting
+        // run and the next one each hold a lock at once
+        summaries.push(remove_children(output_dir, "output", |name| {
+            !is_hash_file(name) && name != LOCK_FILE
+        })?);
+    }
+    if matches!(target, CleanTarget::Downloads | CleanTarget::All) {
+        let mut summary = CleanSummary {
+            category: "downloads",
+            bytes: 0,
+            entries: 0,
+        };
+        if downloads.symlink_metadata().is_ok() {
+            guard_root(base_dir)?;
+            summary.bytes = remove_entry(base_dir, &downloads)?;
+            summary.entries = 1;
+        }
+        summaries.push(summary);
+    }
+    Ok(summaries)
+}
+
+/// Rebuild and artifact-store key files (`.rootfs-inputs.hash` etc.) and
+/// the store checksums.
+pub(crate) fn is_hash_file(name: &str) -> bool {
+    (name.starts_with('.') && name.ends_with(".hash")) || name == STORE_META_DIR
+}
+
+/// Refuse to clean a root that is a symlink (it would point elsewhere).
+pub(crate) fn guard_root(root: &Path) -> Result<()> {
+    let meta = root
+        .symlink_metadata()
+        .with_context(|| format!("Failed to stat {}", root.display()))?;
+    if meta.file_type().is_symlink() {
+        bail!(
+            "Refusing to clean {}: it is a symlink to {}",
+            root.display(),
+            fs::read_link(root)
+                .map(|t| t.display().to_string())
+                .unwrap_or_default()
+        );
+    }
+    if !meta.is_dir() {
+        bail!("Refusing to clean {}: not a directory", root.display());
+    }
+    Ok(())
+}
+
+/// Remove direct children of `root` whose names match `select`.
+fn remove_children(
+    root: &Path,
+    category: &'static str,
+    select: impl Fn(&str) -> bool,
+) -> Result<CleanSummary> {
+    let mut summary = CleanSummary {
+        category,
+        bytes: 0,
+        entries: 0,
+    };
+    if root.symlink_metadata().is_err() {
+        return Ok(summary);
+    }
+    guard_root(root)?;
+
+    let mut children: Vec<PathBuf> = fs::read_dir(root)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    children.sort();
+    for child in children {
+        let name = child.file_name().unwrap_or_default().to_string_lossy();
+        if select(&name) {
+            summary.bytes += remove_entry(root, &child)?;
+            summary.entries += 1;
+        }
+    }
+    Ok(summary)
+}
+
+/// Remove `path`, a direct child of `root`, without following symlinks.
+///
+/// Returns the bytes freed.
+pub(crate) fn remove_entry(root: &Path, path: &Path) -> Result<u64> {
+    if path.parent() != Some(root) {
+        bail!(
+            "Refusing to remove {}: not inside {}",
+            path.display(),
+            root.display()
+        );
+    }
+    let meta = path.symlink_metadata()?;
+    let bytes = tree_size(path)?;
+    if meta.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+    .with_context(|| format!("Failed to remove {}", path.display()))?;
+    Ok(bytes)
+}
+
+/// Apparent size of `path`; symlinks count as themselves, not their targets.
+pub(crate) fn tree_size(path: &Path) -> Result<u64> {
+    let meta = path.symlink_metadata()?;
+    if !meta.is_dir() {
+        return Ok(if meta.is_file() { meta.len() } else { 0 });
+    }
+    let mut total = 0;
+    for entry in fs::read_dir(path)? {
+        total += tree_size(&entry?.path())?;
+    }
+    Ok(total)
+}
+
+/// Format a byte count with binary units.
+pub fn human_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_clean_scopes() {
+        let base = tempdir().unwrap();
+        let output = base.path().join("output");
+        let downloads = base.path().join("downloads");
+        fs::create_dir_all(output.join("staging/boot")).unwrap();
+        fs::create_dir_all(downloads.join("rootfs")).unwrap();
+        fs::write(output.join("acornos.iso"), vec![0u8; 4096]).unwrap();
+        fs::write(output.join("staging/boot/vmlinuz"), vec![0u8; 100]).unwrap();
+        fs::write(output.join(".rootfs-inputs.hash"), "abc").unwrap();
+        fs::create_dir_all(output.join(".store-meta/rootfs")).unwrap();
+        fs::write(output.join(".store-meta/rootfs/abc"), "size").unwrap();
+        fs::write(downloads.join(".packages-version"), "1.0.2").unwrap();
+        fs::write(downloads.join("alpine.iso"), "iso").unwrap();
+        fs::write(output.join(LOCK_FILE), "pid 1").unwrap();
+
+        let summaries = clean(base.path(), &output, CleanTarget::Output).unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].bytes, 4196);
+        assert_eq!(summaries[0].entries, 2);
+        assert!(!output.join("acornos.iso").exists());
+        assert!(output.join(".rootfs-inputs.hash").exists());
+        assert!(output.join(".store-meta/rootfs/abc").exists());
+        assert!(output.join(LOCK_FILE).exists());
+        assert!(downloads.join("alpine.iso").exists());
+
+        let summaries = clean(base.path(), &output, CleanTarget::Cache).unwrap();
+        assert_eq!(summaries[0].entries, 3);
+        assert_eq!(summaries[0].bytes, 12);
+        assert!(!output.join(".store-meta").exists());
+        assert!(!output.join(".rootfs-inputs.hash").exists());
+        assert!(!downloads.join(".packages-version").exists());
+        assert!(downloads.join("alpine.iso").exists());
+
+        let summaries = clean(base.path(), &output, CleanTarget::All).unwrap();
+        assert_eq!(summaries.len(), 3);
+        assert!(!downloads.exists());
+        assert!(output.join(LOCK_FILE).exists());
+    }
+
+    #[test]
+    fn test_clean_never_follows_symlinks() {
+        let base = tempdir().unwrap();
+        let outside = tempdir().unwrap();
+        fs::write(outside.path().join("precious"), "keep").unwrap();
+
+        let output = base.path().join("output");
+        fs::create_dir_all(&output).unwrap();
+        symlink(outside.path(), output.join("linked-dir")).unwrap();
+        symlink(outside.path().join("precious"), output.join("linked-file")).unwrap();
+        clean(base.path(), &output, CleanTarget::Output).unwrap();
+        assert!(output.join("linked-dir").symlink_metadata().is_err());
+        assert!(outside.path().join("precious").exists());
+
+        // A root that is itself a symlink is refused
+        symlink(outside.path(), base.path().join("output-link")).unwrap();
+        let linked_output = base.path().join("output-link");
+        assert!(clean(base.path(), &linked_output, CleanTarget::Output).is_err());
+        assert!(outside.path().join("precious").exists());
+    }
+
+    #[test]
+    fn test_human_bytes() {
+        assert_eq!(human_bytes(512), "512 B");
+        assert_eq!(human_bytes(1536), "1.5 KiB");
+        assert_eq!(human_bytes(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+}
@@ -0,0 +1,64 @@
+//! Path arguments for external commands.
+//!
+//! A path formatted into an argument with `display()` loses any non-UTF8
+//! bytes, and one formatted into a QEMU option breaks at the first comma.
+//! These build such arguments as `OsString`s instead; pass a path on its
+//! own with `Cmd::arg_path`.
+
+use std::ffi::OsString;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+
+/// `prefix` immediately followed by `path` (`--directory=<path>`), as a
+/// `PathBuf` so it can go through `Cmd::arg_path`.
+pub fn prefixed(prefix: &str, path: &Path) -> PathBuf {
+    let mut arg = OsString::from(prefix);
+    arg.push(path);
+    PathBuf::from(arg)
+}
+
+/// `path` as a QEMU option value, commas doubled (QEMU's escape for `,`).
+pub fn qemu_escape(path: &Path) -> OsString {
+    let mut out = Vec::with_capacity(path.as_os_str().len());
+    for &b in path.as_os_str().as_bytes() {
+        out.push(b);
+        if b == b',' {
+            out.push(b',');
+        }
+    }
+    OsString::from_vec(out)
+}
+
+/// QEMU option list `opts` with `path` as its last value
+/// (`if=none,file=<path>`).
+pub fn qemu_opts(opts: &str, path: &Path) -> OsString {
+    let mut arg = OsString::from(opts);
+    arg.push(qemu_escape(path));
+    arg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+
+    #[test]
+    fn test_path_arguments_are_byte_exact() {
+        let odd = Path::new(OsStr::from_bytes(b"/tmp/acorn b\xfcild,x/grub.cfg"));
+        assert!(odd.to_str().is_none());
+
+        let flag = prefixed("boot/grub/grub.cfg=", odd);
+        assert_eq!(
+            flag.as_os_str().as_bytes(),
+            b"boot/grub/grub.cfg=/tmp/acorn b\xfcild,x/grub.cfg"
+        );
+        assert_eq!(
+            qemu_opts("if=none,id=cd0,file=", odd).as_bytes(),
+            b"if=none,id=cd0,file=/tmp/acorn b\xfcild,,x/grub.cfg"
+        );
+        assert_eq!(
+            qemu_escape(Path::new("/out/acornos.iso")),
+            "/out/acornos.iso"
+        );
+    }
+}
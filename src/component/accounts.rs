@@ -0,0 +1,369 @@
+//! System users and groups in the staging /etc.
+//!
+//! Alpine packages allocate their own system accounts in the source
+//! rootfs (apk post-install scripts), and component definitions add more
+//! (`user("sshd", 22, ...)`). Staging's passwd, group and shadow are
+//! seeded from the source rootfs once, before the first account is added,
+//! so none of the packaged accounts go missing. The fixed IDs chosen in
+//! definitions are then checked against them:
+//!
+//! - an existing account with the same name is kept, with its ID
+//! - an ID already taken by a different name fails the build
+//!
+//! Every user added gets a locked shadow entry (`!`), and so does any
+//! passwd user the source shadow lacks.
+
+use anyhow::{bail, Result};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+/// Last password change (days since the epoch) written to shadow entries.
+const SHADOW_LASTCHG: u32 = 19000;
+
+/// passwd used when the source rootfs has none.
+const DEFAULT_PASSWD: &str = "root:x:0:0:root:/root:/bin/sh\n\
+                              nobody:x:65534:65534:nobody:/:/sbin/nologin\n";
+
+/// group used when the source rootfs has none.
+const DEFAULT_GROUP: &str = "root:x:0:\n\
+                             wheel:x:10:\n\
+                             nobody:x:65534:\n";
+
+/// Copy passwd, group and shadow from `source` into `staging` unless
+/// staging has them already, then give every passwd user a shadow entry.
+pub fn seed(source: &Path, staging: &Path) -> Result<()> {
+    let etc = staging.join("etc");
+    fs::create_dir_all(&etc)?;
+    for (file, default) in [("passwd", DEFAULT_PASSWD), ("group", DEFAULT_GROUP)] {
+        let dst = etc.join(file);
+        if !dst.exists() {
+            match fs::read_to_string(source.join("etc").join(file)) {
+                Ok(content) => fs::write(&dst, content)?,
+                Err(_) => fs::write(&dst, default)?,
+            }
+        }
+    }
+
+    let shadow = etc.join("shadow");
+    let mut content = match fs::read_to_string(&shadow) {
+        Ok(content) => content,
+        Err(_) => fs::read_to_string(source.join("etc/shadow")).unwrap_or_default(),
+    };
+    let passwd = fs::read_to_string(etc.join("passwd"))?;
+    for entry in passwd.lines().filter_map(Entry::parse) {
+        content = append_entry(&content, entry.name, &locked_shadow(entry.name));
+    }
+    write_shadow(&shadow, &content)
+}
+
+/// Ensure the group `name`, with `gid` unless it already exists.
+///
+/// Returns the gid the group has.
+pub fn ensure_group(source: &Path, staging: &Path, name: &str, gid: u32) -> Result<u32> {
+    seed(source, staging)?;
+    let path = staging.join("etc/group");
+    let content = fs::read_to_string(&path)?;
+    let entries: Vec<Entry> = content.lines().filter_map(Entry::parse).collect();
+
+    if let Some(existing) = entries.iter().find(|e| e.name == name) {
+        if existing.id != gid {
+            log::info!(
+                "  Group {} already exists with gid {} (wanted {}); keeping it",
+                name,
+                existing.id,
+                gid
+            );
+        }
+        return Ok(existing.id);
+    }
+    if let Some(other) = entries.iter().find(|e| e.id == gid) {
+        bail!(
+            "Cannot add group {}: gid {} is already used by group {}",
+            name,
+            gid,
+            other.name
+        );
+    }
+    fs::write(
+        &path,
+        append_entry(&content, name, &format!("{}:x:{}:", name, gid)),
+    )?;
+    Ok(gid)
+}
+
+/// Ensure the user `name`, with `uid` unless it already exists.
+///
+/// The primary group is `gid`; if no group has it but a group named like
+/// the user exists (kept by [`ensure_group`] with another gid), that one.
+/// Returns the uid the user has.
+pub fn ensure_user(
+    source: &Path,
+    staging: &Path,
+    name: &str,
+    uid: u32,
+    gid: u32,
+    home: &str,
+    shell: &str,
+) -> Result<u32> {
+    seed(source, staging)?;
+    let path = staging.join("etc/passwd");
+    let content = fs::read_to_string(&path)?;
+    let entries: Vec<Entry> = content.lines().filter_map(Entry::parse).collect();
+
+    if let Some(existing) = entries.iter().find(|e| e.name == name) {
+        if existing.id != uid {
+            log::info!(
+                "  User {} already exists with uid {} (wanted {}); keeping it",
+                name,
+                existing.id,
+                uid
+            );
+        }
+        return Ok(existing.id);
+    }
+    if let Some(other) = entries.iter().find(|e| e.id == uid) {
+        bail!(
+            "Cannot add user {}: uid {} is already used by user {}",
+            name,
+            uid,
+            other.name
+        );
+    }
+
+    let groups = fs::read_to_string(staging.join("etc/group"))?;
+    let groups: Vec<Entry> = groups.lines().filter_map(Entry::parse).collect();
+    let gid = if groups.iter().any(|g| g.id == gid) {
+        gid
+    } else {
+        groups.iter().find(|g| g.name == name).map_or(gid, |g| g.id)
+    };
+
+    let line = format!("{}:x:{}:{}:{}:{}:{}", name, uid, gid, name, home, shell);
+    fs::write(&path, append_entry(&content, name, &line))?;
+
+    let shadow = staging.join("etc/shadow");
+    let content = fs::read_to_string(&shadow).unwrap_or_default();
+    write_shadow(&shadow, &append_entry(&content, name, &locked_shadow(name)))?;
+    Ok(uid)
+}
+
+/// Replace the password field of `name` in the staging shadow (empty for
+/// the passwordless live root).
+pub fn set_shadow_password(staging: &Path, name: &str, password: &str) -> Result<()> {
+    let path = staging.join("etc/shadow");
+    let content = fs::read_to_string(&path)?;
+    let prefix = format!("{}:", name);
+    let mut found = false;
+    let mut out = String::new();
+    for line in content.lines() {
+        match line.strip_prefix(&prefix) {
+            Some(rest) => {
+                found = true;
+                let rest = rest.split_once(':').map_or("", |(_, rest)| rest);
+                out.push_str(&format!("{}{}:{}\n", prefix, password, rest));
+            }
+            None => {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+    }
+    if !found {
+        bail!("No shadow entry for {}", name);
+    }
+    write_shadow(&path, &out)
+}
+
+/// Give every group of the staging /etc/group a gshadow entry, keeping
+/// the ones already there.
+pub fn sync_gshadow(staging: &Path) -> Result<()> {
+    let path = staging.join("etc/gshadow");
+    let mut content = fs::read_to_string(&path).unwrap_or_default();
+    let groups = fs::read_to_string(staging.join("etc/group"))?;
+    for entry in groups.lines().filter_map(Entry::parse) {
+        content = append_entry(&content, entry.name, &format!("{}:::", entry.name));
+    }
+    write_shadow(&path, &content)
+}
+
+/// Name and numeric ID (third field) of a passwd or group line.
+struct Entry<'a> {
+    name: &'a str,
+    id: u32,
+}
+
+impl<'a> Entry<'a> {
+    fn parse(line: &'a str) -> Option<Self> {
+        let mut fields = line.split(':');
+        let name = fields.next().filter(|n| !n.is_empty())?;
+        let id = fields.nth(1)?.parse().ok()?;
+        Some(Self { name, id })
+    }
+}
+
+/// Shadow entry with a locked password.
+fn locked_shadow(name: &str) -> String {
+    format!("{}:!:{}:0:99999:7:::", name, SHADOW_LASTCHG)
+}
+
+/// Append `line` unless an entry for `name` already exists.
+pub(crate) fn append_entry(content: &str, name: &str, line: &str) -> String {
+    let prefix = format!("{}:", name);
+    let mut out = content.to_string();
+    if !content.lines().any(|l| l.starts_with(&prefix)) {
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+fn write_shadow(path: &Path, content: &str) -> Result<()> {
+    fs::write(path, content)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    /// Source rootfs with Alpine's accounts, including a packaged `chrony`.
+    fn source_rootfs(dir: &Path) {
+        fs::create_dir_all(dir.join("etc")).unwrap();
+        fs::write(
+            dir.join("etc/passwd"),
+            "root:x:0:0:root:/root:/bin/sh\n\
+             ntp:x:123:123:NTP:/var/empty:/sbin/nologin\n\
+             chrony:x:101:102:chrony:/var/lib/chrony:/sbin/nologin\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("etc/group"),
+            "root:x:0:\nntp:x:123:\nchrony:x:102:\n",
+        )
+        .unwrap();
+        fs::write(dir.join("etc/shadow"), "root:*::0:::::\n").unwrap();
+    }
+
+    #[test]
+    fn test_accounts_seed_reuse_and_conflict() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("rootfs");
+        let staging = dir.path().join("staging");
+        source_rootfs(&source);
+
+        // Fresh add: seeded from the source, then appended with a locked password
+        assert_eq!(ensure_group(&source, &staging, "sshd", 22).unwrap(), 22);
+        assert_eq!(
+            ensure_user(
+                &source,
+                &staging,
+                "sshd",
+                22,
+                22,
+                "/var/empty/sshd",
+                "/sbin/nologin"
+            )
+            .unwrap(),
+            22
+        );
+        let passwd = fs::read_to_string(staging.join("etc/passwd")).unwrap();
+        assert!(passwd.contains("ntp:x:123:123:"));
+        assert!(passwd.ends_with("sshd:x:22:22:sshd:/var/empty/sshd:/sbin/nologin\n"));
+        let shadow = fs::read_to_string(staging.join("etc/shadow")).unwrap();
+        assert!(shadow.starts_with("root:*::0:::::\n"));
+        assert!(shadow.contains("ntp:!:19000:"));
+        assert!(shadow.contains("sshd:!:19000:0:99999:7:::\n"));
+        let mode = fs::metadata(staging.join("etc/shadow"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // Pre-existing: the packaged chrony keeps its IDs
+        assert_eq!(ensure_group(&source, &staging, "chrony", 123).unwrap(), 102);
+        assert_eq!(
+            ensure_user(
+                &source,
+                &staging,
+                "chrony",
+                123,
+                123,
+                "/var/lib/chrony",
+                "/sbin/nologin"
+            )
+            .unwrap(),
+            101
+        );
+        let passwd = fs::read_to_string(staging.join("etc/passwd")).unwrap();
+        assert_eq!(
+            passwd.lines().filter(|l| l.starts_with("chrony:")).count(),
+            1
+        );
+
+        // Conflict: the ID belongs to another name
+        let err = ensure_user(
+            &source,
+            &staging,
+            "timesync",
+            123,
+            123,
+            "/",
+            "/sbin/nologin",
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(
+            err.contains("uid 123 is already used by user ntp"),
+            "{}",
+            err
+        );
+        let err = ensure_group(&source, &staging, "timesync", 22)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("gid 22 is already used by group sshd"),
+            "{}",
+            err
+        );
+
+        set_shadow_password(&staging, "root", "").unwrap();
+        sync_gshadow(&staging).unwrap();
+        let shadow = fs::read_to_string(staging.join("etc/shadow")).unwrap();
+        assert!(shadow.starts_with("root:::0:::::\n"), "{}", shadow);
+        let gshadow = fs::read_to_string(staging.join("etc/gshadow")).unwrap();
+        assert_eq!(gshadow, "root:::\nntp:::\nchrony:::\nsshd:::\n");
+    }
+
+    #[test]
+    fn test_primary_group_follows_kept_group() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("rootfs");
+        let staging = dir.path().join("staging");
+        source_rootfs(&source);
+        fs::write(
+            source.join("etc/group"),
+            "root:x:0:\nntp:x:123:\nsshd:x:105:\n",
+        )
+        .unwrap();
+
+        assert_eq!(ensure_group(&source, &staging, "sshd", 22).unwrap(), 105);
+        ensure_user(
+            &source,
+            &staging,
+            "sshd",
+            22,
+            22,
+            "/var/empty/sshd",
+            "/sbin/nologin",
+        )
+        .unwrap();
+        let passwd = fs::read_to_string(staging.join("etc/passwd")).unwrap();
+        assert!(passwd.contains("sshd:x:22:105:"), "{}", passwd);
+    }
+}
@@ -2,16 +2,120 @@
 //!
 //! This module provides the main entry point for building the AcornOS
 //! system image using the component system.
-
-use anyhow::Result;
+//!
+//! # Incremental staging
+//!
+//! Each component's hash (its ops, the Alpine package database and the
+//! executor code) is recorded in `output/.component-hashes.json`. When
+//! rebuilding into the previous staging tree, components whose hash is
+//! unchanged and whose declared outputs still exist are skipped.
+//!
+//! Components with custom ops that read outside the Alpine rootfs (kernel
+//! modules, `profile/`, local tool builds) always run. Once a tracked
+//! component runs, every later tracked component runs too, since it may
+//! edit files the earlier one just rewrote. A staging tree that holds a
+//! component disabled since (see [`super::selection`]) is never reused.
+//!
+//! Every finished component also leaves a [`super::checkpoint`] marker, so
+//! a build that fails part way can be resumed with `--resume`.
+//!
+//! # Source validation
+//!
+//! Before anything runs, the binaries, files and init scripts every
+//! enabled component copies are looked up in the source rootfs, and a
+//! build missing any fails once with the full list, grouped by component.
+//! With `--keep-going` the list is only a warning and each component fails
+//! on its own when it runs.
+
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
 use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::Path;
+use std::time::Instant;
 
+use distro_builder::cache;
 use distro_builder::LicenseTracker;
 use distro_builder::PackageManager;
 
+use crate::license_report::LicenseReport;
+use crate::manifest::json_str;
+use crate::term::Fields;
+
+use super::checkpoint::Checkpoint;
 use super::definitions::ALL_COMPONENTS;
 use super::executor;
-use super::BuildContext;
+use super::{custom, selection, BuildContext, Component, Op};
+
+/// Per-component hashes of `rootfs-staging`, in the output dir.
+pub const COMPONENT_HASHES_FILE: &str = ".component-hashes.json";
+
+/// Code interpreting the ops, relative to the project root.
+const EXECUTOR_SOURCES: &[&str] = &[
+    "src/component/executor.rs",
+    "src/component/firmware.rs",
+    "src/component/custom/mod.rs",
+    "src/component/custom/branding.rs",
+    "src/component/custom/ssh.rs",
+];
+
+/// Alpine package database, relative to the source rootfs.
+const APK_INSTALLED_DB: &str = "lib/apk/db/installed";
+
+/// Component name -> hash, as recorded after a staging build.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ComponentHashes(BTreeMap<String, String>);
+
+impl ComponentHashes {
+    /// Load the hashes file, if present and readable.
+    pub fn load(path: &Path) -> Option<Self> {
+        Self::parse(&fs::read_to_string(path).ok()?)
+    }
+
+    /// Parse the flat `{ "name": "hash", ... }` object written by [`Self::to_json`].
+    pub fn parse(json: &str) -> Option<Self> {
+        let body = json.trim().strip_prefix('{')?.strip_suffix('}')?;
+        let mut hashes = BTreeMap::new();
+        for entry in body.lines().map(|l| l.trim().trim_end_matches(',')) {
+            if entry.is_empty() {
+                continue;
+            }
+            let (name, hash) = entry.split_once("\": \"")?;
+            hashes.insert(
+                name.strip_prefix('"')?.to_string(),
+                hash.strip_suffix('"')?.to_string(),
+            );
+        }
+        Some(Self(hashes))
+    }
+
+    pub fn to_json(&self) -> String {
+        let rows: Vec<String> = self
+            .0
+            .iter()
+            .map(|(name, hash)| format!("  {}: {}", json_str(name), json_str(hash)))
+            .collect();
+        format!("{{\n{}\n}}\n", rows.join(",\n"))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_json())
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    pub fn insert(&mut self, name: &str, hash: String) {
+        self.0.insert(name.to_string(), hash);
+    }
+
+    /// Components recorded as built.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+}
 
 /// Build the complete AcornOS system.
 ///
@@ -29,68 +133,340 @@ use super::BuildContext;
 /// # Arguments
 ///
 /// * `ctx` - Build context with source and staging paths
+/// * `previous` - Hashes recorded for the staging tree already at
+///   `ctx.staging`; `None` wipes staging and runs every component
+/// * `checkpoint` - Where finished components are recorded; with
+///   `resume`, staging is kept and recorded components are skipped
+/// * `keep_going` - Warn about sources missing from the rootfs instead of
+///   failing before the first component runs
+///
+/// Returns the hashes to record for the new staging tree.
 ///
 /// # Errors
 ///
 /// Returns an error if any component fails to execute.
 /// ALL operations are required - there is no "optional".
-pub fn build_system(ctx: &BuildContext) -> Result<()> {
-    println!("\n=== Building AcornOS System ===\n");
+pub fn build_system(
+    ctx: &BuildContext,
+    previous: Option<&ComponentHashes>,
+    checkpoint: &Checkpoint,
+    keep_going: bool,
+) -> Result<ComponentHashes> {
+    log::info!("\n=== Building AcornOS System ===\n");
+
+    // Enabled components in dependency order (components.toml, --disable-component)
+    let components = selection::selected_components(&ctx.base_dir)?;
+    let skipped: Vec<&str> = ALL_COMPONENTS
+        .iter()
+        .map(|c| c.name)
+        .filter(|name| !components.iter().any(|c| c.name == *name))
+        .collect();
+    if !skipped.is_empty() {
+        log::info!("Disabled components: {}\n", skipped.join(", "));
+    }
+
+    // Every missing source at once, rather than one component per build
+    let report = missing_sources_report(&ctx.source, &components);
+    if !report.is_empty() {
+        if !keep_going {
+            bail!(
+                "{}\nUpdate the component definitions, or pass --keep-going to build until the first failing component.",
+                report
+            );
+        }
+        log::warn!("{}", report);
+    }
+
+    // A staging tree holding a now-disabled component is rebuilt from scratch
+    let previous = previous.filter(|p| selection::staging_reusable(p.names(), &components));
+
+    // Markers of the interrupted build, or a fresh start
+    let resumed = if checkpoint.resume {
+        let markers = checkpoint.load();
+        checkpoint.clear_failure();
+        log::info!("Resuming: {} component(s) checkpointed", markers.len());
+        Some(markers)
+    } else {
+        checkpoint.clear()?;
+        None
+    };
 
     // Prepare staging directory
-    prepare_staging(ctx)?;
+    prepare_staging(&ctx.staging, previous.is_some() || resumed.is_some())?;
 
     // Track licenses for all binaries we copy
     let tracker = LicenseTracker::new(ctx.source.clone(), PackageManager::Apk);
+    let state = executor::ExecState::default();
+
+    let stamp = inputs_stamp(ctx);
+    let mut hashes = ComponentHashes::default();
+    let mut invalidated = false;
+    let mut resuming = resumed.is_some();
+
+    // Execute all components, timing each for build-timings.json
+    for component in components.iter().copied() {
+        let stamp = stamp
+            .as_deref()
+            .map(|s| component_stamp(&ctx.base_dir, component, s));
+        let hash = stamp.as_deref().and_then(|s| component_hash(component, s));
+        let checkpoint_hash = stamp.as_deref().map(|s| ops_hash(component, s));
+        resuming &= match (&resumed, &checkpoint_hash) {
+            (Some(markers), Some(hash)) => {
+                markers.get(component.name).map(|m| &m.hash) == Some(hash)
+            }
+            _ => false,
+        };
+        let unchanged = match (previous, &hash) {
+            (Some(previous), Some(hash)) => {
+                !invalidated
+                    && previous.get(component.name) == Some(hash.as_str())
+                    && outputs_present(&ctx.staging, component)
+            }
+            _ => false,
+        };
+
+        if resuming || unchanged {
+            let why = if resuming {
+                "checkpointed"
+            } else {
+                "unchanged"
+            };
+            log::info!("[SKIP] {} ({})", component.name, why);
+            register_licenses(component, &tracker);
+            state.record_writes(&ctx.source, &ctx.staging, component);
+            state.record_copies(&ctx.source, component)?;
+            crate::timing::record_skipped(&format!("component: {}", component.name));
+        } else {
+            let started = Instant::now();
+            if let Err(e) = executor::execute(ctx, component, &tracker, &state) {
+                checkpoint.record_failure(component)?;
+                return Err(e);
+            }
+            crate::timing::record_since(&format!("component: {}", component.name), started);
+            invalidated |= hash.is_some();
+        }
+        if let Some(checkpoint_hash) = &checkpoint_hash {
+            checkpoint.record(component, checkpoint_hash)?;
+        }
+
+        if let Some(hash) = hash {
+            hashes.insert(component.name, hash);
+        }
+    }
+
+    let conflicts = state.conflicts(&components);
+    if !conflicts.is_empty() {
+        bail!(
+            "Components overwrite each other's files:\n  {}\n\
+             If the later component replaces the file on purpose, list the path in its `overrides`.",
+            conflicts.join("\n  ")
+        );
+    }
 
-    // Execute all components
-    for component in ALL_COMPONENTS {
-        executor::execute(ctx, component, &tracker)?;
+    // The package of every copied file, so its license ships too
+    let licenses = LicenseReport::load(&ctx.source, &state.copied())?;
+    for package in licenses.package_names() {
+        tracker.register_package(package);
     }
 
     // Copy license files for all redistributed packages
     let license_count = tracker.copy_licenses(&ctx.source, &ctx.staging)?;
-    println!("  Copied licenses for {} packages", license_count);
+    log::info!("  Copied licenses for {} packages", license_count);
+    let report = licenses.write(&crate::paths::output_dir(&ctx.base_dir))?;
 
-    println!("\n=== System Build Complete ===\n");
+    log::info!("\n=== System Build Complete ===\n");
 
     // Print summary
     print_summary(ctx)?;
 
-    Ok(())
+    if !licenses.unattributed.is_empty() {
+        log::warn!(
+            "{} copied file(s) are owned by no apk package, so no license ships for them \
+             (see {}):\n  {}",
+            licenses.unattributed.len(),
+            report.display(),
+            licenses.unattributed.join("\n  ")
+        );
+    }
+
+    Ok(hashes)
+}
+
+/// Everything `components` copy that is missing from `source`, grouped
+/// by component; empty if nothing is.
+fn missing_sources_report(source: &Path, components: &[&Component]) -> String {
+    let mut total = 0;
+    let mut groups = Vec::new();
+    for component in components {
+        let missing: Vec<String> = component
+            .ops
+            .iter()
+            .flat_map(|op| executor::missing_sources(source, op))
+            .collect();
+        if !missing.is_empty() {
+            total += missing.len();
+            groups.push(format!(
+                "  {}:\n    {}",
+                component.name,
+                missing.join("\n    ")
+            ));
+        }
+    }
+    if groups.is_empty() {
+        return String::new();
+    }
+    format!(
+        "{} item(s) missing from the source rootfs {} in {} component(s):\n{}",
+        total,
+        source.display(),
+        groups.len(),
+        groups.join("\n")
+    )
 }
 
 /// Prepare the staging directory.
 ///
-/// Creates a clean staging directory for the build.
-fn prepare_staging(ctx: &BuildContext) -> Result<()> {
-    println!("Preparing staging directory: {}", ctx.staging.display());
+/// Creates a clean staging directory for the build, unless `reuse` keeps
+/// the existing tree for an incremental build.
+fn prepare_staging(staging: &Path, reuse: bool) -> Result<()> {
+    log::info!("Preparing staging directory: {}", staging.display());
 
     // Remove existing staging directory
-    if ctx.staging.exists() {
-        fs::remove_dir_all(&ctx.staging)?;
+    if staging.exists() && !reuse {
+        fs::remove_dir_all(staging)?;
     }
 
     // Create fresh staging directory
-    fs::create_dir_all(&ctx.staging)?;
+    fs::create_dir_all(staging)?;
 
     Ok(())
 }
 
+/// Hash of the inputs shared by every tracked component, or `None` if any
+/// is missing (every component then runs).
+fn inputs_stamp(ctx: &BuildContext) -> Option<String> {
+    let mut inputs = vec![ctx.source.join(APK_INSTALLED_DB)];
+    inputs.extend(EXECUTOR_SOURCES.iter().map(|p| ctx.base_dir.join(p)));
+    let refs: Vec<&Path> = inputs.iter().map(|p| p.as_path()).collect();
+    cache::hash_files(&refs)
+}
+
+/// `stamp` plus the configuration the custom ops of `component` read
+/// (the firmware policy), so changing it reruns the component.
+fn component_stamp(base_dir: &Path, component: &Component, stamp: &str) -> String {
+    let mut stamp = stamp.to_string();
+    for op in component.ops {
+        if let Op::Custom(op) = op {
+            let config = custom::config_fingerprint(base_dir, *op);
+            if !config.is_empty() {
+                stamp.push(':');
+                stamp.push_str(&config);
+            }
+        }
+    }
+    stamp
+}
+
+/// Hash of `component` given the shared inputs `stamp`.
+///
+/// `None` for components that always run.
+fn component_hash(component: &Component, stamp: &str) -> Option<String> {
+    if component
+        .ops
+        .iter()
+        .any(|op| matches!(op, Op::Custom(c) if c.reads_outside_rootfs()))
+    {
+        return None;
+    }
+    Some(ops_hash(component, stamp))
+}
+
+/// Hash of the ops of `component` and `stamp`, whatever they read.
+fn ops_hash(component: &Component, stamp: &str) -> String {
+    // Only compared against hashes written by this same binary
+    let mut hasher = DefaultHasher::new();
+    format!("{:?}", component.ops).hash(&mut hasher);
+    stamp.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Staging paths an op is known to create; custom and user ops declare none.
+fn declared_outputs(op: &Op) -> Vec<String> {
+    let under = |dir: &str, names: &[&str]| -> Vec<String> {
+        names.iter().map(|n| format!("{}/{}", dir, n)).collect()
+    };
+    match op {
+        Op::Dir(path) | Op::DirMode(path, _) => vec![path.to_string()],
+        Op::Dirs(paths) => paths.iter().map(|p| p.to_string()).collect(),
+        Op::WriteFile(path, _) | Op::WriteFileMode(path, _, _) => vec![path.to_string()],
+        Op::Symlink(link, _) => vec![link.to_string()],
+        Op::CopyFile(path) | Op::CopyTree(path) => vec![path.to_string()],
+        Op::Bin(name) => under("usr/bin", &[name]),
+        Op::Sbin(name) => under("usr/sbin", &[name]),
+        Op::Bins(names) => under("usr/bin", names),
+        Op::Sbins(names) => under("usr/sbin", names),
+        Op::OpenrcEnable(service, runlevel) => {
+            vec![format!("etc/runlevels/{}/{}", runlevel, service)]
+        }
+        Op::OpenrcScripts(scripts) => under("etc/init.d", scripts),
+        Op::OpenrcConf(service, _) => under("etc/conf.d", &[service]),
+        // Package file lists live in the source rootfs's apk database
+        Op::Package(_) | Op::User { .. } | Op::Group { .. } | Op::Custom(_) => Vec::new(),
+    }
+}
+
+/// Whether every declared output of `component` exists in `staging`.
+fn outputs_present(staging: &Path, component: &Component) -> bool {
+    component
+        .ops
+        .iter()
+        .flat_map(declared_outputs)
+        .all(|path| fs::symlink_metadata(staging.join(path)).is_ok())
+}
+
+/// Register the licenses a skipped component would have registered.
+fn register_licenses(component: &Component, tracker: &LicenseTracker) {
+    for op in component.ops {
+        match op {
+            Op::Bin(name) | Op::Sbin(name) => tracker.register_binary(name),
+            Op::Bins(names) | Op::Sbins(names) => {
+                for name in *names {
+                    tracker.register_binary(name);
+                }
+            }
+            Op::Custom(op) => {
+                if let Some(package) = custom::license_package(*op) {
+                    tracker.register_package(package);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
 /// Print a summary of the built system.
 fn print_summary(ctx: &BuildContext) -> Result<()> {
     // Count files and directories
     let (files, dirs, symlinks) = count_items(&ctx.staging)?;
 
-    println!("Build Summary:");
-    println!("  Staging: {}", ctx.staging.display());
-    println!("  Files: {}", files);
-    println!("  Directories: {}", dirs);
-    println!("  Symlinks: {}", symlinks);
-
     // Calculate total size
     let size = dir_size(&ctx.staging)?;
-    println!("  Total size: {:.1} MB", size as f64 / 1024.0 / 1024.0);
+
+    log::info!("Build Summary:");
+    log::info!(
+        "{}",
+        Fields::new()
+            .row("Staging", ctx.staging.display().to_string())
+            .row("Files", files.to_string())
+            .row("Directories", dirs.to_string())
+            .row("Symlinks", symlinks.to_string())
+            .row(
+                "Total size",
+                format!("{:.1} MB", size as f64 / 1024.0 / 1024.0)
+            )
+            .render(2)
+            .trim_end()
+    );
 
     // Verify essential files exist
     let essential_files = [
@@ -109,9 +485,8 @@ fn print_summary(ctx: &BuildContext) -> Result<()> {
     }
 
     if !missing.is_empty() {
-        println!("\n  WARNING: Missing essential files:");
         for file in &missing {
-            println!("    - {}", file);
+            log::warn!("Missing essential file: {}", file);
         }
     }
 
@@ -193,4 +568,113 @@ mod tests {
         assert_eq!(dirs, 1);
         assert_eq!(symlinks, 1);
     }
+
+    #[test]
+    fn test_component_hashes_round_trip() {
+        let mut hashes = ComponentHashes::default();
+        hashes.insert("Filesystem", "00ff".to_string());
+        hashes.insert("Branding", "1234".to_string());
+        let parsed = ComponentHashes::parse(&hashes.to_json()).unwrap();
+        assert_eq!(parsed, hashes);
+        assert_eq!(parsed.get("Branding"), Some("1234"));
+        assert_eq!(
+            ComponentHashes::parse("{\n}\n"),
+            Some(ComponentHashes::default())
+        );
+        assert!(ComponentHashes::parse("not json").is_none());
+    }
+
+    #[test]
+    fn test_component_hash_tracking() {
+        use super::super::definitions::{LIVE_FINAL, MODULES};
+        use super::super::{dir, write_file};
+
+        static OPS: &[Op] = &[dir("etc"), write_file("etc/motd", "hi")];
+        let component = Component {
+            name: "Test",
+            phase: super::super::Phase::Config,
+            requires: &[],
+            overrides: &[],
+            ops: OPS,
+        };
+        let hash = component_hash(&component, "stamp").unwrap();
+        assert_eq!(component_hash(&component, "stamp"), Some(hash.clone()));
+        assert_ne!(component_hash(&component, "other"), Some(hash.clone()));
+        assert!(component_hash(&MODULES, "stamp").is_none());
+        assert!(component_hash(&LIVE_FINAL, "stamp").is_none());
+        // Checkpoints cover every component, and a new source rootfs invalidates them
+        assert_eq!(ops_hash(&component, "stamp"), hash);
+        assert_ne!(ops_hash(&MODULES, "stamp"), ops_hash(&MODULES, "other"));
+
+        let dir = tempdir().unwrap();
+        assert!(!outputs_present(dir.path(), &component));
+        fs::create_dir(dir.path().join("etc")).unwrap();
+        fs::write(dir.path().join("etc/motd"), "hi").unwrap();
+        assert!(outputs_present(dir.path(), &component));
+    }
+
+    #[test]
+    fn test_missing_sources_report() {
+        use super::super::{bins, copy_file, openrc_scripts, sbin, Phase};
+
+        const TOOLS: Component = Component {
+            name: "tools",
+            phase: Phase::Binaries,
+            requires: &[],
+            overrides: &[],
+            ops: &[bins(&["ip", "ss", "lsblk"]), copy_file("etc/fstab")],
+        };
+        const NETWORK: Component = Component {
+            name: "network",
+            phase: Phase::Services,
+            requires: &[],
+            overrides: &[],
+            ops: &[sbin("dhcpcd"), openrc_scripts(&["networking", "dhcpcd"])],
+        };
+
+        let dir = tempdir().unwrap();
+        let source = dir.path();
+        fs::create_dir_all(source.join("bin")).unwrap();
+        fs::create_dir_all(source.join("usr/sbin")).unwrap();
+        fs::create_dir_all(source.join("etc/init.d")).unwrap();
+        fs::write(source.join("bin/ss"), "").unwrap();
+        std::os::unix::fs::symlink("/bin/busybox", source.join("bin/ip")).unwrap();
+        fs::write(source.join("usr/sbin/dhcpcd"), "").unwrap();
+        fs::write(source.join("etc/init.d/networking"), "").unwrap();
+
+        let report = missing_sources_report(source, &[&TOOLS, &NETWORK]);
+        assert_eq!(
+            report,
+            format!(
+                "3 item(s) missing from the source rootfs {} in 2 component(s):\n  \
+                 tools:\n    binary lsblk\n    file etc/fstab\n  \
+                 network:\n    file etc/init.d/dhcpcd",
+                source.display()
+            )
+        );
+
+        fs::write(source.join("bin/lsblk"), "").unwrap();
+        fs::write(source.join("etc/fstab"), "").unwrap();
+        assert_eq!(missing_sources_report(source, &[&TOOLS]), String::new());
+    }
+
+    #[test]
+    fn test_prepare_staging_drops_disabled_component_files() {
+        let dir = tempdir().unwrap();
+        let staging = dir.path().join("staging");
+        fs::create_dir_all(staging.join("etc/ssh")).unwrap();
+        fs::write(staging.join("etc/ssh/sshd_config"), "").unwrap();
+
+        let mut previous = ComponentHashes::default();
+        previous.insert("filesystem", "1".to_string());
+        previous.insert("ssh", "2".to_string());
+        let mut selection = selection::ComponentSelection::default();
+        selection.disable(["ssh"]);
+        let enabled = selection.resolve(ALL_COMPONENTS).unwrap();
+
+        let reuse = selection::staging_reusable(previous.names(), &enabled);
+        prepare_staging(&staging, reuse).unwrap();
+        assert!(staging.is_dir());
+        assert!(!staging.join("etc/ssh/sshd_config").exists());
+    }
 }
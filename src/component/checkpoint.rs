@@ -0,0 +1,193 @@
+//! Build checkpoints for `acornos build --resume`.
+//!
+//! While [`super::build_system`] runs, every component that finishes
+//! leaves a marker in `output/.build-state/` holding its phase and hash.
+//! When a later component fails, `rootfs-staging.work` is kept instead of
+//! deleted. A `--resume` build then reuses that tree, skips components
+//! whose marker matches and continues from the first failed or missing one.
+//!
+//! The hash covers the component's ops and the shared input stamp (Alpine
+//! package database and executor code), so markers written against
+//! another source rootfs never match. Unlike the incremental hashes, it
+//! is recorded for every component, including ones that read outside the
+//! rootfs: resuming is explicit, and the failed component always re-runs.
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::Component;
+
+/// Checkpoint directory in the output dir.
+pub const BUILD_STATE_DIR: &str = ".build-state";
+
+/// Marker naming the component that failed.
+const FAILED_MARKER: &str = "failed";
+
+/// Extension of the per-component markers.
+const MARKER_EXT: &str = "done";
+
+/// A recorded component: its phase and hash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Marker {
+    pub phase: String,
+    pub hash: String,
+}
+
+impl Marker {
+    fn parse(text: &str) -> Option<Self> {
+        let mut phase = None;
+        let mut hash = None;
+        for line in text.lines() {
+            match line.split_once('=') {
+                Some(("phase", value)) => phase = Some(value.to_string()),
+                Some(("hash", value)) => hash = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        Some(Self {
+            phase: phase?,
+            hash: hash?,
+        })
+    }
+}
+
+/// Checkpoints of the staging build in one output dir.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    dir: PathBuf,
+    /// Honor the markers already there instead of clearing them.
+    pub resume: bool,
+}
+
+impl Checkpoint {
+    pub fn new(output_dir: &Path, resume: bool) -> Self {
+        Self {
+            dir: output_dir.join(BUILD_STATE_DIR),
+            resume,
+        }
+    }
+
+    /// The same checkpoint, starting over.
+    pub fn restart(&self) -> Self {
+        Self {
+            dir: self.dir.clone(),
+            resume: false,
+        }
+    }
+
+    /// Remove every marker.
+    pub fn clear(&self) -> Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove {}", self.dir.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Markers of the components that finished, by name.
+    pub fn load(&self) -> BTreeMap<String, Marker> {
+        let mut markers = BTreeMap::new();
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return markers;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(MARKER_EXT) {
+                continue;
+            }
+            let name = path.file_stem().unwrap_or_default().to_string_lossy();
+            if let Some(marker) = fs::read_to_string(&path)
+                .ok()
+                .and_then(|t| Marker::parse(&t))
+            {
+                markers.insert(name.into_owned(), marker);
+            }
+        }
+        markers
+    }
+
+    /// Whether any component has finished since the markers were cleared.
+    pub fn has_progress(&self) -> bool {
+        !self.load().is_empty()
+    }
+
+    /// Record that `component` finished with `hash`.
+    pub fn record(&self, component: &Component, hash: &str) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!("{}.{}", component.name, MARKER_EXT));
+        fs::write(
+            &path,
+            format!("phase={:?}\nhash={}\n", component.phase, hash),
+        )
+        .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Record that `component` failed.
+    pub fn record_failure(&self, component: &Component) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.dir.join(FAILED_MARKER), component.name)?;
+        Ok(())
+    }
+
+    /// The component that failed last, if any.
+    pub fn failed(&self) -> Option<String> {
+        fs::read_to_string(self.dir.join(FAILED_MARKER))
+            .ok()
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+    }
+
+    /// Forget a recorded failure (the component is running again).
+    pub fn clear_failure(&self) {
+        let _ = fs::remove_file(self.dir.join(FAILED_MARKER));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::Phase;
+    use tempfile::tempdir;
+
+    const NETWORK: Component = Component {
+        name: "network",
+        phase: Phase::Services,
+        requires: &[],
+        overrides: &[],
+        ops: &[],
+    };
+
+    #[test]
+    fn test_checkpoint_markers() {
+        let dir = tempdir().unwrap();
+        let checkpoint = Checkpoint::new(dir.path(), true);
+        assert!(!checkpoint.has_progress());
+        assert_eq!(checkpoint.failed(), None);
+
+        checkpoint.record(&NETWORK, "00ff").unwrap();
+        checkpoint.record_failure(&NETWORK).unwrap();
+        fs::write(dir.path().join(BUILD_STATE_DIR).join("stray.done"), "junk").unwrap();
+
+        let markers = checkpoint.load();
+        assert_eq!(markers.len(), 1);
+        assert_eq!(
+            markers["network"],
+            Marker {
+                phase: "Services".into(),
+                hash: "00ff".into()
+            }
+        );
+        assert_eq!(checkpoint.failed().as_deref(), Some("network"));
+        checkpoint.clear_failure();
+        assert_eq!(checkpoint.failed(), None);
+
+        let restarted = checkpoint.restart();
+        assert!(!restarted.resume);
+        restarted.clear().unwrap();
+        restarted.clear().unwrap();
+        assert!(!checkpoint.has_progress());
+    }
+}
@@ -0,0 +1,102 @@
+//! apk state of the installed system.
+//!
+//! recstrap installs the rootfs image as it is, so without this a new
+//! system cannot `apk add` anything. The source rootfs's own
+//! `repositories` starts with the ISO's `apks/` directory on the build
+//! host, so a fresh one is written for the build's mirror and branch; the
+//! database and `world` are the source rootfs's, so apk knows what the
+//! image holds and what was asked for.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+
+use crate::alpine_source::AlpineSource;
+use crate::component::BuildContext;
+use crate::fast_copy::Copier;
+use distro_spec::acorn::packages::ALPINE_KEYS;
+
+/// The apk database directory, in either root.
+const DB_DIR: &str = "lib/apk/db";
+
+/// `repositories` for `source`: its main and community repositories.
+pub fn repositories(source: &AlpineSource) -> String {
+    format!(
+        "{}\n{}\n",
+        source.repo_url("main"),
+        source.repo_url("community")
+    )
+}
+
+/// `world` from the source rootfs's: sorted, one package per line.
+pub fn world(text: &str) -> String {
+    let mut packages: Vec<&str> = text.split_whitespace().collect();
+    packages.sort_unstable();
+    packages.dedup();
+    packages.iter().map(|p| format!("{}\n", p)).collect()
+}
+
+/// Write repositories, keys, the database and world into staging.
+pub fn setup_apk(ctx: &BuildContext) -> Result<()> {
+    let source = AlpineSource::from_env()?;
+    let etc_apk = ctx.staging.join("etc/apk");
+    fs::create_dir_all(&etc_apk)?;
+    fs::write(etc_apk.join("repositories"), repositories(&source))?;
+    distro_builder::alpine::keys::install_keys(&ctx.staging, ALPINE_KEYS)?;
+
+    let src_world = ctx.source.join("etc/apk/world");
+    let text = fs::read_to_string(&src_world).with_context(|| {
+        format!(
+            "Failed to read {} (run 'acornos download alpine')",
+            src_world.display()
+        )
+    })?;
+    let world = world(&text);
+    if world.is_empty() {
+        bail!("{} lists no packages", src_world.display());
+    }
+    fs::write(etc_apk.join("world"), &world)?;
+
+    // installed, triggers, scripts.tar; the lock is apk's own
+    let src_db = ctx.source.join(DB_DIR);
+    let dst_db = ctx.staging.join(DB_DIR);
+    fs::create_dir_all(&dst_db)?;
+    let mut entries: Vec<_> = fs::read_dir(&src_db)
+        .and_then(|entries| entries.collect::<Result<_, _>>())
+        .with_context(|| format!("Failed to read {}", src_db.display()))?;
+    entries.sort_by_key(|e| e.file_name());
+    let copier = Copier::new(false);
+    for entry in entries {
+        if entry.file_name() == "lock" || !entry.file_type()?.is_file() {
+            continue;
+        }
+        copier.copy(&entry.path(), &dst_db.join(entry.file_name()))?;
+    }
+
+    log::info!(
+        "  apk: {} packages in world, repositories for {}",
+        world.lines().count(),
+        source
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repositories_and_world() {
+        let source =
+            AlpineSource::new(Some("https://mirror.example/alpine/"), Some("3.22")).unwrap();
+        assert_eq!(
+            repositories(&source),
+            "https://mirror.example/alpine/v3.22/main\n\
+             https://mirror.example/alpine/v3.22/community\n"
+        );
+        assert_eq!(
+            world("openrc\nalpine-base\n  linux-lts openrc\n"),
+            "alpine-base\nlinux-lts\nopenrc\n"
+        );
+        assert_eq!(world("\n"), "");
+    }
+}
@@ -5,61 +5,37 @@
 use anyhow::Result;
 use std::fs;
 
-use crate::component::BuildContext;
+use crate::artifact::IsoVariant;
+use crate::build_info::BuildInfo;
+use crate::component::{accounts, BuildContext};
+use crate::tree_copy::copy_tree;
+
+/// Write os-release (and its FreeDesktop canonical copy in /usr/lib) with
+/// the version and build id of this build. The rootfs only ships on the
+/// live ISO.
+pub fn write_os_release(ctx: &BuildContext) -> Result<()> {
+    let os_release = BuildInfo::detect(&ctx.base_dir).os_release(IsoVariant::Live);
+    for path in ["etc/os-release", "usr/lib/os-release"] {
+        let path = ctx.staging.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, &os_release)?;
+    }
+    Ok(())
+}
 
 /// Create essential /etc configuration files.
 pub fn create_etc_files(ctx: &BuildContext) -> Result<()> {
     let staging = &ctx.staging;
 
-    // /etc/passwd - base system users
-    // Copy from source if exists, otherwise create minimal
-    // Note: Use /bin/sh (POSIX standard) not /bin/ash - busybox always creates /bin/sh
-    let passwd_src = ctx.source.join("etc/passwd");
-    let passwd_dst = staging.join("etc/passwd");
-    if passwd_src.exists() {
-        fs::copy(&passwd_src, &passwd_dst)?;
-    } else {
-        fs::write(
-            &passwd_dst,
-            "root:x:0:0:root:/root:/bin/sh\n\
-             nobody:x:65534:65534:nobody:/:/sbin/nologin\n",
-        )?;
-    }
-
-    // /etc/group - base system groups
-    let group_src = ctx.source.join("etc/group");
-    let group_dst = staging.join("etc/group");
-    if group_src.exists() {
-        fs::copy(&group_src, &group_dst)?;
-    } else {
-        fs::write(
-            &group_dst,
-            "root:x:0:\n\
-             wheel:x:10:\n\
-             nobody:x:65534:\n",
-        )?;
-    }
-
-    // /etc/shadow - password hashes (root with no password for live)
-    let shadow_dst = staging.join("etc/shadow");
-    fs::write(
-        &shadow_dst,
-        "root::19000:0:99999:7:::\n\
-         nobody:!:19000:0:99999:7:::\n",
-    )?;
-    // Make shadow readable only by root
-    use std::os::unix::fs::PermissionsExt;
-    fs::set_permissions(&shadow_dst, fs::Permissions::from_mode(0o600))?;
-
-    // /etc/gshadow
-    let gshadow_dst = staging.join("etc/gshadow");
-    fs::write(
-        &gshadow_dst,
-        "root:::\n\
-         wheel:::\n\
-         nobody:::\n",
-    )?;
-    fs::set_permissions(&gshadow_dst, fs::Permissions::from_mode(0o600))?;
+    // /etc/passwd, /etc/group, /etc/shadow - merged, not overwritten:
+    // the accounts added by components in earlier phases (sshd, chrony)
+    // and by Alpine's packages must all survive.
+    accounts::seed(&ctx.source, staging)?;
+    // Root with no password for live
+    accounts::set_shadow_password(staging, "root", "")?;
+    accounts::sync_gshadow(staging)?;
 
     // /etc/securetty - allow root login on various terminals
     fs::write(
@@ -152,7 +128,7 @@ pub fn copy_timezone_data(ctx: &BuildContext) -> Result<()> {
     let zoneinfo_src = ctx.source.join("usr/share/zoneinfo");
     let zoneinfo_dst = staging.join("usr/share/zoneinfo");
     if zoneinfo_src.exists() {
-        copy_tree(&zoneinfo_src, &zoneinfo_dst)?;
+        copy_tree(&zoneinfo_src, &zoneinfo_dst, staging)?;
     }
 
     // Set default timezone to UTC
@@ -167,40 +143,3 @@ pub fn copy_timezone_data(ctx: &BuildContext) -> Result<()> {
 
     Ok(())
 }
-
-/// Copy a directory tree recursively.
-fn copy_tree(src: &std::path::Path, dst: &std::path::Path) -> Result<()> {
-    if !src.exists() {
-        return Ok(());
-    }
-
-    if src.is_file() {
-        if let Some(parent) = dst.parent() {
-            fs::create_dir_all(parent)?;
-        }
-        fs::copy(src, dst)?;
-        return Ok(());
-    }
-
-    fs::create_dir_all(dst)?;
-
-    for entry in fs::read_dir(src)? {
-        let entry = entry?;
-        let src_path = entry.path();
-        let dst_path = dst.join(entry.file_name());
-
-        if src_path.is_symlink() {
-            let target = fs::read_link(&src_path)?;
-            if dst_path.exists() || dst_path.is_symlink() {
-                fs::remove_file(&dst_path)?;
-            }
-            std::os::unix::fs::symlink(&target, &dst_path)?;
-        } else if src_path.is_dir() {
-            copy_tree(&src_path, &dst_path)?;
-        } else {
-            fs::copy(&src_path, &dst_path)?;
-        }
-    }
-
-    Ok(())
-}
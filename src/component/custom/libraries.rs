@@ -0,0 +1,60 @@
+//! Copying every shared library of the Alpine rootfs into staging.
+//!
+//! Hundreds of MB on every rebuild, so the files go through a
+//! [`Copier`]: reflinked where the filesystem can, hardlinked with
+//! `--hardlink-copies`, copied otherwise.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::component::BuildContext;
+use crate::fast_copy::Copier;
+
+/// The shared objects (`*.so*`, files and links) directly in each of
+/// `dirs` of `root`, sorted per dir.
+pub fn library_files(root: &Path, dirs: &[&str]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for dir in dirs {
+        let Ok(entries) = fs::read_dir(root.join(dir)) else {
+            continue;
+        };
+        let mut libraries: Vec<PathBuf> = entries
+            .flatten()
+            .filter(|e| e.file_name().to_string_lossy().contains(".so"))
+            .filter(|e| e.file_type().is_ok_and(|t| t.is_file() || t.is_symlink()))
+            .map(|e| e.path())
+            .collect();
+        libraries.sort();
+        files.extend(libraries);
+    }
+    files
+}
+
+/// Copy the shared objects (`*.so*`, files and links) directly in each of
+/// `dirs` from the source rootfs into staging.
+pub fn copy_all_libraries(ctx: &BuildContext, dirs: &[&str]) -> Result<()> {
+    let copier = Copier::for_rootfs();
+    for dir in dirs {
+        if ctx.source.join(dir).is_dir() {
+            let dst_dir = ctx.staging.join(dir);
+            fs::create_dir_all(&dst_dir)
+                .with_context(|| format!("Failed to create {}", dst_dir.display()))?;
+        }
+    }
+    // Sorted, so the tree is created in the same order in every build
+    for src in library_files(&ctx.source, dirs) {
+        let dst = ctx.staging.join(src.strip_prefix(&ctx.source)?);
+        if fs::symlink_metadata(&src)?.file_type().is_symlink() {
+            if fs::symlink_metadata(&dst).is_ok() {
+                fs::remove_file(&dst)?;
+            }
+            std::os::unix::fs::symlink(fs::read_link(&src)?, &dst)
+                .with_context(|| format!("Failed to copy symlink {}", src.display()))?;
+        } else {
+            copier.copy(&src, &dst)?;
+        }
+    }
+    log::info!("  {}", copier.summary("Libraries"));
+    Ok(())
+}
@@ -5,8 +5,20 @@
 use anyhow::Result;
 use std::fs;
 
+use crate::artifact::init_template::{check_rendered, render_template};
+use crate::artifact::RootfsFormat;
+use crate::component::inittab::{live_inittab, release_live_inittab};
 use crate::component::BuildContext;
-use distro_spec::acorn::{LIVE_ISSUE_MESSAGE, OS_NAME};
+use distro_spec::acorn::{LIVE_ISSUE_MESSAGE, OS_ID, OS_NAME};
+
+/// Guided installer, relative to staging.
+pub const INSTALLER_PATH: &str = "usr/bin/acorn-install";
+
+/// Where the live initramfs leaves the boot medium mounted.
+pub const LIVE_MEDIA_MOUNT: &str = "/media/cdrom";
+
+/// Size of the EFI system partition the installer creates.
+pub const ESP_SIZE: &str = "512M";
 
 /// Create welcome message for live ISO.
 pub fn create_welcome_message(ctx: &BuildContext) -> Result<()> {
@@ -28,8 +40,8 @@ echo ""
 echo "Welcome to {} Live!"
 echo ""
 echo "To install {} to disk:"
-echo "  1. Partition your disk (fdisk, parted, or gdisk)"
-echo "  2. Run: recstrap /dev/sdX"
+echo "  Guided:  doas acorn-install   (erases the disk you pick)"
+echo "  Manual:  partition, format and mount, then run recstrap"
 echo ""
 echo "For help: docs-tui (if available) or visit https://levitateos.org/acorn/docs"
 echo ""
@@ -45,8 +57,9 @@ echo ""
     // Copy test instrumentation scripts from profile/live-overlay/etc/profile.d/
     let overlay_profile_d = ctx.base_dir.join("profile/live-overlay/etc/profile.d");
     if overlay_profile_d.exists() {
-        for entry in fs::read_dir(&overlay_profile_d)? {
-            let entry = entry?;
+        let mut entries: Vec<_> = fs::read_dir(&overlay_profile_d)?.collect::<Result<_, _>>()?;
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
             let path = entry.path();
             if path.is_file() {
                 let file_name = entry.file_name();
@@ -91,6 +104,24 @@ pub fn create_live_overlay(ctx: &BuildContext) -> Result<()> {
     Ok(())
 }
 
+/// Write the testing live session's inittab into the live overlay.
+pub fn write_live_inittab(ctx: &BuildContext) -> Result<()> {
+    write_inittab(ctx, &live_inittab("root"))
+}
+
+/// Write the release live session's inittab into the live overlay; tty1
+/// autologin is set with the live user.
+pub fn write_release_live_inittab(ctx: &BuildContext) -> Result<()> {
+    write_inittab(ctx, &release_live_inittab("root"))
+}
+
+fn write_inittab(ctx: &BuildContext, content: &str) -> Result<()> {
+    let etc = ctx.staging.join("etc");
+    fs::create_dir_all(&etc)?;
+    fs::write(etc.join("inittab"), content)?;
+    Ok(())
+}
+
 /// Copy recstrap installer tools.
 ///
 /// recstrap is the AcornOS equivalent of pacstrap - it extracts
@@ -116,7 +147,7 @@ pub fn copy_recstrap(ctx: &BuildContext) -> Result<()> {
             use std::os::unix::fs::PermissionsExt;
             fs::set_permissions(&dst, fs::Permissions::from_mode(0o755))?;
             found_recstrap = true;
-            println!("  Copied recstrap installer tool");
+            log::debug!("  Copied recstrap installer tool");
             break;
         }
     }
@@ -165,7 +196,7 @@ exit 1
         fs::write(&dst, placeholder)?;
         use std::os::unix::fs::PermissionsExt;
         fs::set_permissions(&dst, fs::Permissions::from_mode(0o755))?;
-        println!("  Created recstrap placeholder (binary not found)");
+        log::info!("  Created recstrap placeholder (binary not found)");
     }
 
     // Also copy recfstab and recchroot if available
@@ -182,7 +213,7 @@ exit 1
                 fs::copy(candidate, &dst)?;
                 use std::os::unix::fs::PermissionsExt;
                 fs::set_permissions(&dst, fs::Permissions::from_mode(0o755))?;
-                println!("  Copied {} tool", tool);
+                log::debug!("  Copied {} tool", tool);
                 break;
             }
         }
@@ -190,3 +221,241 @@ exit 1
 
     Ok(())
 }
+
+/// Shell functions that find the rootfs image on the live medium and copy
+/// it onto the target. Shared by `acorn-install` and the install test's
+/// extract step, so the test copies the system exactly as the installer does.
+pub(crate) const ROOTFS_IMAGE_FUNCTIONS: &str = r#"# Rootfs image on the boot medium, in whichever format the ISO was built
+# with: sets IMAGE and FSTYPE from the fstype:path entries in $IMAGES
+find_rootfs_image() {
+    IMAGE=""
+    FSTYPE=""
+    for entry in $IMAGES; do
+        if [ -f "$MEDIA/${entry#*:}" ]; then
+            FSTYPE="${entry%%:*}"
+            IMAGE="$MEDIA/${entry#*:}"
+            break
+        fi
+    done
+}
+
+# Copy the system in $IMAGE onto $TARGET, mounted at $SOURCE meanwhile
+copy_rootfs_image() {
+    mkdir -p "$SOURCE"
+    mount -t "$FSTYPE" -o ro,loop "$IMAGE" "$SOURCE"
+    cp -a "$SOURCE"/. "$TARGET"/
+    umount "$SOURCE"
+}
+"#;
+
+/// Guided installer script; `{{...}}` placeholders are filled by
+/// [`installer_script`].
+const INSTALLER_TEMPLATE: &str = r#"#!/bin/sh
+# acorn-install - guided {{OS_NAME}} installer
+#
+# Erases one disk, creates a GPT with an EFI system partition and an ext4
+# root, copies the live rootfs image onto it and installs GRUB.
+set -e
+
+MEDIA="{{MEDIA_MOUNT}}"
+IMAGES="{{ROOTFS_IMAGES}}"
+ESP_SIZE="{{ESP_SIZE}}"
+TARGET=/mnt/acorn-target
+SOURCE=/mnt/acorn-source
+
+die() {
+    echo "acorn-install: $*" >&2
+    exit 1
+}
+
+cleanup() {
+    for m in "$TARGET/sys" "$TARGET/proc" "$TARGET/dev" "$TARGET/boot" "$TARGET" "$SOURCE"; do
+        umount "$m" 2>/dev/null || true
+    done
+}
+
+{{ROOTFS_IMAGE_FUNCTIONS}}
+[ "$(id -u)" = 0 ] || die "must run as root (try: doas acorn-install)"
+
+find_rootfs_image
+[ -n "$IMAGE" ] || die "no rootfs image under $MEDIA (is the live medium mounted?)"
+
+# Whole disks from /sys/block, without the medium we booted from
+BOOT_DEV=$(awk -v m="$MEDIA" '$2 == m { print $1 }' /proc/mounts)
+list_disks() {
+    for sys in /sys/block/*; do
+        name=${sys##*/}
+        case "$name" in
+            loop* | ram* | zram* | sr* | fd* | dm-* | md*) continue ;;
+        esac
+        case "$BOOT_DEV" in
+            /dev/"$name"*) continue ;;
+        esac
+        sectors=$(cat "$sys/size")
+        [ "$sectors" -gt 0 ] || continue
+        model=$(cat "$sys/device/model" 2>/dev/null || true)
+        echo "$name $((sectors / 2097152))G ${model:-unknown}"
+    done
+}
+
+DISKS=$(list_disks)
+[ -n "$DISKS" ] || die "no disk to install to"
+
+echo "{{OS_NAME}} installer"
+echo ""
+echo "Disks:"
+echo "$DISKS" | while read -r name size model; do
+    printf "  /dev/%-10s %6s  %s\n" "$name" "$size" "$model"
+done
+echo ""
+printf "Install to which disk (e.g. %s)? " "$(echo "$DISKS" | head -n 1 | cut -d ' ' -f 1)"
+read -r DISK
+DISK=${DISK#/dev/}
+echo "$DISKS" | grep -q "^$DISK " || die "not a listed disk: $DISK"
+DEV="/dev/$DISK"
+
+printf "Erase ALL data on %s? Type YES to continue: " "$DEV"
+read -r answer
+[ "$answer" = "YES" ] || die "aborted"
+
+printf "Hostname [{{OS_ID}}]: "
+read -r HOSTNAME
+HOSTNAME=${HOSTNAME:-{{OS_ID}}}
+
+# nvme0n1 -> nvme0n1p1, sda -> sda1
+case "$DISK" in
+    *[0-9]) PART="${DEV}p" ;;
+    *) PART="$DEV" ;;
+esac
+
+trap cleanup EXIT
+
+echo "Partitioning $DEV..."
+sgdisk --zap-all "$DEV" >/dev/null
+sgdisk -n "1:0:+$ESP_SIZE" -t 1:ef00 -c 1:EFI \
+    -n 2:0:0 -t 2:8300 -c 2:root "$DEV" >/dev/null
+udevadm settle 2>/dev/null || sleep 2
+
+echo "Formatting..."
+mkfs.fat -F 32 -n EFI "${PART}1" >/dev/null
+mkfs.ext4 -F -q -L root "${PART}2"
+
+mkdir -p "$TARGET"
+mount "${PART}2" "$TARGET"
+mkdir -p "$TARGET/boot"
+mount "${PART}1" "$TARGET/boot"
+
+echo "Copying the system from $IMAGE..."
+copy_rootfs_image
+
+if command -v recfstab >/dev/null 2>&1; then
+    recfstab "$TARGET" >"$TARGET/etc/fstab"
+else
+    {
+        echo "UUID=$(blkid -s UUID -o value "${PART}2") / ext4 defaults 0 1"
+        echo "UUID=$(blkid -s UUID -o value "${PART}1") /boot vfat defaults 0 2"
+    } >"$TARGET/etc/fstab"
+fi
+echo "$HOSTNAME" >"$TARGET/etc/hostname"
+
+# Run a command inside the new system
+if command -v recchroot >/dev/null 2>&1; then
+    in_target() { recchroot "$TARGET" "$@"; }
+else
+    mount --bind /dev "$TARGET/dev"
+    mount -t proc proc "$TARGET/proc"
+    mount -t sysfs sysfs "$TARGET/sys"
+    in_target() { chroot "$TARGET" "$@"; }
+fi
+
+case "$(uname -m)" in
+    aarch64) GRUB_TARGET=arm64-efi ;;
+    riscv64) GRUB_TARGET=riscv64-efi ;;
+    *) GRUB_TARGET=x86_64-efi ;;
+esac
+if in_target sh -c 'command -v grub-install' >/dev/null 2>&1; then
+    echo "Installing GRUB ($GRUB_TARGET)..."
+    in_target grub-install --target="$GRUB_TARGET" --efi-directory=/boot \
+        --bootloader-id="{{OS_NAME}}" --removable
+    in_target grub-mkconfig -o /boot/grub/grub.cfg
+else
+    echo "WARNING: grub-install is not in the installed system; add a bootloader before rebooting" >&2
+fi
+
+echo "Set the root password:"
+until in_target passwd root; do
+    echo "Try again."
+done
+
+echo ""
+echo "{{OS_NAME}} is installed on $DEV. Remove the live medium and reboot."
+"#;
+
+/// Render the guided installer for the rootfs images the ISO may carry.
+pub fn installer_script() -> Result<String> {
+    let images: Vec<String> = RootfsFormat::ALL
+        .iter()
+        .map(|format| format!("{}:{}", format.fs_type(), format.iso_path()))
+        .collect();
+    let vars = [
+        ("OS_NAME", OS_NAME.to_string()),
+        ("OS_ID", OS_ID.to_string()),
+        ("MEDIA_MOUNT", LIVE_MEDIA_MOUNT.to_string()),
+        ("ROOTFS_IMAGES", images.join(" ")),
+        ("ESP_SIZE", ESP_SIZE.to_string()),
+        ("ROOTFS_IMAGE_FUNCTIONS", ROOTFS_IMAGE_FUNCTIONS.to_string()),
+    ];
+    let script = render_template(INSTALLER_TEMPLATE, &vars);
+    check_rendered("acorn-install", &script)?;
+    Ok(script)
+}
+
+/// Install the guided installer (`acorn-install`).
+pub fn create_installer(ctx: &BuildContext) -> Result<()> {
+    let dst = ctx.staging.join(INSTALLER_PATH);
+    if let Some(parent) = dst.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&dst, installer_script()?)?;
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(&dst, fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::artifact::init_template::{check_shell_syntax, placeholders};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_installer_script() {
+        let found = placeholders(INSTALLER_TEMPLATE);
+        assert_eq!(
+            found.iter().map(String::as_str).collect::<Vec<_>>(),
+            [
+                "ESP_SIZE",
+                "MEDIA_MOUNT",
+                "OS_ID",
+                "OS_NAME",
+                "ROOTFS_IMAGES",
+                "ROOTFS_IMAGE_FUNCTIONS"
+            ]
+        );
+
+        let script = installer_script().unwrap();
+        assert!(placeholders(&script).is_empty());
+        assert!(script.contains(&format!(
+            "IMAGES=\"erofs:{} squashfs:",
+            distro_spec::acorn::ROOTFS_ISO_PATH
+        )));
+        assert!(script.contains("MEDIA=\"/media/cdrom\""));
+        assert!(script.contains(ROOTFS_IMAGE_FUNCTIONS));
+        assert!(script.contains("\ncopy_rootfs_image\n"));
+        assert!(script.contains("HOSTNAME=${HOSTNAME:-acornos}"));
+        assert!(script.contains("--bootloader-id=\"AcornOS\""));
+
+        let dir = tempdir().unwrap();
+        check_shell_syntax("acorn-install", &script, None, &dir.path().join("check")).unwrap();
+    }
+}
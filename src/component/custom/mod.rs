@@ -3,27 +3,73 @@
 //! These operations have complex logic that doesn't fit the declarative pattern.
 //! Each module handles a specific domain of custom operations.
 
+mod apk;
 mod branding;
+mod libraries;
 mod live;
+mod ssh;
+
+pub(crate) use live::ROOTFS_IMAGE_FUNCTIONS;
+pub use live::{ESP_SIZE, LIVE_MEDIA_MOUNT};
 
 use anyhow::Result;
+use std::path::{Path, PathBuf};
 
 use distro_builder::LicenseTracker;
 use distro_spec::shared::auth::ssh::SSHD_CONFIG_SETTINGS;
 use distro_spec::shared::busybox::{COMMON_APPLETS, SBIN_APPLETS};
 use distro_spec::shared::components::{FHS_SYMLINKS, VAR_SYMLINKS};
-use distro_spec::shared::firmware::WIFI_FIRMWARE_DIRS;
 use distro_spec::shared::modules::MODULE_METADATA_FILES;
 use distro_spec::shared::paths::LIBRARY_DIRS;
 
 use super::BuildContext;
 use super::CustomOp;
 
+/// Package whose license covers the content an operation copies.
+pub fn license_package(op: CustomOp) -> Option<&'static str> {
+    match op {
+        CustomOp::CopyModules => Some("linux-lts"),
+        CustomOp::CopyFirmware => Some("linux-firmware"),
+        CustomOp::CopyTimezoneData => Some("tzdata"),
+        CustomOp::CopyAllLibraries => Some("musl"),
+        CustomOp::SetupSsh => Some("openssh"),
+        _ => None,
+    }
+}
+
+/// Files of the source rootfs an operation copies as they are, for the
+/// license report; empty for ops that generate their files.
+pub fn copied_sources(source: &Path, op: CustomOp) -> Vec<PathBuf> {
+    match op {
+        CustomOp::CopyAllLibraries => libraries::library_files(source, LIBRARY_DIRS),
+        _ => Vec::new(),
+    }
+}
+
+/// Configuration an operation reads besides its own definition, for the
+/// component hash; empty if none.
+pub fn config_fingerprint(base_dir: &Path, op: CustomOp) -> String {
+    match op {
+        CustomOp::CopyFirmware => super::firmware::FirmwarePolicy::load(base_dir)
+            .map(|policy| policy.fingerprint())
+            .unwrap_or_else(|e| format!("invalid: {:#}", e)),
+        CustomOp::SetupApk => crate::alpine_source::AlpineSource::from_env()
+            .map(|source| source.fingerprint())
+            .unwrap_or_else(|e| format!("invalid: {:#}", e)),
+        CustomOp::WriteOsRelease => crate::build_info::BuildInfo::detect(base_dir).fingerprint(),
+        _ => String::new(),
+    }
+}
+
 /// Execute a custom operation.
 ///
 /// Some operations copy content that requires license tracking. The tracker
 /// is used to register packages for license compliance.
 pub fn execute(ctx: &BuildContext, op: CustomOp, tracker: &LicenseTracker) -> Result<()> {
+    if let Some(package) = license_package(op) {
+        tracker.register_package(package);
+    }
+
     match op {
         // Filesystem operations (no content copying)
         CustomOp::CreateFhsSymlinks => {
@@ -31,6 +77,7 @@ pub fn execute(ctx: &BuildContext, op: CustomOp, tracker: &LicenseTracker) -> Re
         }
 
         // Branding (config files only)
+        CustomOp::WriteOsRelease => branding::write_os_release(ctx),
         CustomOp::CreateEtcFiles => branding::create_etc_files(ctx),
         CustomOp::CreateSecurityConfig => branding::create_security_config(ctx),
 
@@ -46,47 +93,54 @@ pub fn execute(ctx: &BuildContext, op: CustomOp, tracker: &LicenseTracker) -> Re
             distro_builder::alpine::filesystem::setup_device_manager(ctx)
         }
 
-        // Kernel modules - register kernel package
-        CustomOp::CopyModules => {
-            tracker.register_package("linux-lts");
-            distro_builder::alpine::modules::copy_modules(
-                ctx,
-                "acornos build kernel",
-                MODULE_METADATA_FILES,
-            )
-        }
+        // Kernel modules (linux-lts)
+        CustomOp::CopyModules => distro_builder::alpine::modules::copy_modules(
+            ctx,
+            "acornos build kernel",
+            MODULE_METADATA_FILES,
+        ),
 
-        // Firmware - register linux-firmware package
-        CustomOp::CopyWifiFirmware => {
-            tracker.register_package("linux-firmware");
-            distro_builder::alpine::firmware::copy_firmware_dirs(ctx, WIFI_FIRMWARE_DIRS)
-        }
+        // Firmware (linux-firmware)
+        CustomOp::CopyFirmware => super::firmware::copy_firmware(ctx),
 
-        // Timezone - register tzdata package
-        CustomOp::CopyTimezoneData => {
-            tracker.register_package("tzdata");
-            branding::copy_timezone_data(ctx)
-        }
+        // Timezone (tzdata)
+        CustomOp::CopyTimezoneData => branding::copy_timezone_data(ctx),
 
         // Live ISO (generated content, no third-party packages)
         CustomOp::CreateWelcomeMessage => live::create_welcome_message(ctx),
         CustomOp::CreateLiveOverlay => live::create_live_overlay(ctx),
         CustomOp::CopyRecstrap => live::copy_recstrap(ctx),
+        CustomOp::CreateInstaller => live::create_installer(ctx),
 
-        // Libraries - register musl (the libc providing most .so files)
-        CustomOp::CopyAllLibraries => {
-            tracker.register_package("musl");
-            distro_builder::alpine::filesystem::copy_all_libraries(ctx, LIBRARY_DIRS)
-        }
+        // Libraries (musl, the libc providing most .so files)
+        CustomOp::CopyAllLibraries => libraries::copy_all_libraries(ctx, LIBRARY_DIRS),
 
-        // SSH - register openssh package
+        // SSH (openssh)
         CustomOp::SetupSsh => {
-            tracker.register_package("openssh");
-            distro_builder::alpine::ssh::setup_ssh(ctx, "root@acornos", SSHD_CONFIG_SETTINGS)
+            distro_builder::alpine::ssh::setup_ssh(ctx, "root@acornos", SSHD_CONFIG_SETTINGS)?;
+            ssh::finalize_ssh(ctx)
         }
 
+        // apk state (repositories, keys, database, world)
+        CustomOp::SetupApk => apk::setup_apk(ctx),
+
         // Stage test scripts (no package tracking - local scripts)
         CustomOp::InstallStageTests => install_stage_tests(ctx),
+
+        // Live overlay (staging is the overlay, source the rootfs staging)
+        CustomOp::WriteLiveInittab => live::write_live_inittab(ctx),
+        CustomOp::WriteReleaseLiveInittab => live::write_release_live_inittab(ctx),
+        CustomOp::StripTestInstrumentation => {
+            crate::artifact::live_profile::strip_test_instrumentation(&ctx.staging)
+        }
+        CustomOp::InstallMakePersistence => {
+            crate::artifact::persistence::install_make_persistence(&ctx.staging)
+        }
+        CustomOp::CreateLiveUser => crate::artifact::live_user::apply_live_user(
+            &ctx.staging,
+            &ctx.source.join("etc"),
+            crate::artifact::live_user::LiveUser::Live,
+        ),
     }
 }
 
@@ -121,8 +175,9 @@ fn install_stage_tests(ctx: &BuildContext) -> Result<()> {
 
     // Copy all .sh scripts to /usr/local/bin/
     let mut script_count = 0;
-    for entry in fs::read_dir(&test_scripts_src)? {
-        let entry = entry?;
+    let mut entries: Vec<_> = fs::read_dir(&test_scripts_src)?.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
         let path = entry.path();
 
         if path.is_file() && path.extension().is_some_and(|ext| ext == "sh") {
@@ -149,8 +204,9 @@ fn install_stage_tests(ctx: &BuildContext) -> Result<()> {
     // Copy lib/ directory to /usr/local/lib/stage-tests/
     let lib_src = test_scripts_src.join("lib");
     if lib_src.exists() {
-        for entry in fs::read_dir(&lib_src)? {
-            let entry = entry?;
+        let mut entries: Vec<_> = fs::read_dir(&lib_src)?.collect::<Result<_, _>>()?;
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
             let path = entry.path();
 
             if path.is_file() {
@@ -173,11 +229,11 @@ fn install_stage_tests(ctx: &BuildContext) -> Result<()> {
         }
     }
 
-    println!(
+    log::info!(
         "  Installed {} stage test scripts to /usr/local/bin/",
         script_count
     );
-    println!("  Installed stage test libraries to /usr/local/lib/stage-tests/");
+    log::debug!("  Installed stage test libraries to /usr/local/lib/stage-tests/");
 
     Ok(())
 }
@@ -0,0 +1,124 @@
+//! SSH custom operations.
+//!
+//! Hardens sshd_config for the live image and defers host key generation
+//! to first boot, so every booted system gets its own keys.
+
+use anyhow::Result;
+use std::fs;
+
+use crate::component::BuildContext;
+
+/// AcornOS sshd settings, applied on top of the shared SSHD_CONFIG_SETTINGS.
+///
+/// Root may only log in with a key; passwords are never accepted.
+pub const SSHD_HARDENING: &[(&str, &str)] = &[
+    ("PermitRootLogin", "prohibit-password"),
+    ("PasswordAuthentication", "no"),
+    ("KbdInteractiveAuthentication", "no"),
+    ("PermitEmptyPasswords", "no"),
+    ("PubkeyAuthentication", "yes"),
+];
+
+/// OpenRC script that generates missing host keys before sshd starts.
+const SSHD_KEYGEN_INIT: &str = r#"#!/sbin/openrc-run
+# Generate SSH host keys on first boot (never baked into the image)
+
+description="Generate SSH host keys"
+
+depend() {
+    need localmount
+    before sshd
+}
+
+start() {
+    ebegin "Generating SSH host keys"
+    /usr/bin/ssh-keygen -A
+    eend $?
+}
+"#;
+
+/// Harden sshd_config and replace build-time host keys with first-boot generation.
+pub fn finalize_ssh(ctx: &BuildContext) -> Result<()> {
+    let ssh_dir = ctx.staging.join("etc/ssh");
+    let config_path = ssh_dir.join("sshd_config");
+
+    let existing = fs::read_to_string(&config_path).unwrap_or_default();
+    fs::write(&config_path, apply_sshd_settings(&existing, SSHD_HARDENING))?;
+
+    // Host keys baked into the EROFS would be shared by every machine that boots it
+    if ssh_dir.is_dir() {
+        for entry in fs::read_dir(&ssh_dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with("ssh_host_") && name.contains("_key") {
+                fs::remove_file(entry.path())?;
+            }
+        }
+    }
+
+    let init_script = ctx.staging.join("etc/init.d/sshd-keygen");
+    fs::write(&init_script, SSHD_KEYGEN_INIT)?;
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(&init_script, fs::Permissions::from_mode(0o755))?;
+
+    Ok(())
+}
+
+/// Apply settings to an sshd_config, overriding any existing values.
+///
+/// sshd uses the first value it reads for each keyword, so existing active
+/// lines for the same keywords are commented out and the settings are placed
+/// at the top of the file (ahead of any `Match` blocks).
+pub fn apply_sshd_settings(config: &str, settings: &[(&str, &str)]) -> String {
+    let mut out = String::from("# AcornOS hardening (managed by acornos build)\n");
+    for (key, value) in settings {
+        out.push_str(&format!("{} {}\n", key, value));
+    }
+    out.push('\n');
+
+    for line in config.lines() {
+        let keyword = line.split_whitespace().next().unwrap_or("");
+        let overridden = settings
+            .iter()
+            .any(|(key, _)| key.eq_ignore_ascii_case(keyword));
+        if overridden {
+            out.push_str("# ");
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_sshd_settings_overrides_existing() {
+        let config = "PermitRootLogin yes\n#PasswordAuthentication yes\nUsePAM no\n";
+        let result = apply_sshd_settings(config, SSHD_HARDENING);
+
+        let active: Vec<&str> = result
+            .lines()
+            .filter(|l| !l.starts_with('#') && !l.is_empty())
+            .collect();
+        assert!(active.contains(&"PermitRootLogin prohibit-password"));
+        assert!(active.contains(&"PasswordAuthentication no"));
+        assert!(active.contains(&"UsePAM no"));
+        assert!(!active.contains(&"PermitRootLogin yes"));
+        assert!(result.contains("# PermitRootLogin yes"));
+    }
+
+    #[test]
+    fn test_apply_sshd_settings_precede_match_blocks() {
+        let config = "Match User backup\n    PasswordAuthentication yes\n";
+        let result = apply_sshd_settings(config, SSHD_HARDENING);
+
+        let setting = result.find("PasswordAuthentication no").unwrap();
+        let match_block = result.find("Match User backup").unwrap();
+        assert!(setting < match_block);
+    }
+}
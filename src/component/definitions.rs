@@ -9,14 +9,17 @@
 //! - OPENRC: Set up OpenRC init system
 //! - NETWORK: Network configuration and services
 //! - BRANDING: AcornOS identity files (os-release, hostname, MOTD)
+//! - APK: apk repositories, keys, database and world for installed systems
 //! - FIRMWARE: WiFi and hardware firmware
 //! - FINAL: Welcome message, live overlay, installer tools
+//! - LIVE_OVERLAY: Live session files, built into `live-overlay/` by
+//!   [`super::executor::execute_into`] rather than into staging
 
 use distro_builder::component::Phase;
 
 use super::{
     bin, copy_file, copy_tree, custom, dir, dir_mode, dirs, group, openrc_conf, openrc_enable,
-    openrc_scripts, symlink, user, write_file, write_file_mode, Component, CustomOp,
+    openrc_scripts, package, symlink, user, write_file, write_file_mode, Component, CustomOp,
 };
 
 // =============================================================================
@@ -65,6 +68,8 @@ const FHS_DIRS: &[&str] = &[
 pub static FILESYSTEM: Component = Component {
     name: "filesystem",
     phase: Phase::Filesystem,
+    requires: &[],
+    overrides: &[],
     ops: &[
         dirs(FHS_DIRS),
         // Merged /usr symlinks - Alpine uses merged-usr
@@ -75,9 +80,9 @@ pub static FILESYSTEM: Component = Component {
         dir_mode("var/tmp", 0o1777),
         // /root with restricted permissions
         dir_mode("root", 0o700),
-        // CRITICAL: Copy ALL shared libraries from source rootfs
-        // Host ldd (glibc) can't detect musl dependencies, so we copy everything
-        // This MUST happen before any binaries are copied
+        // Copy ALL shared libraries from source rootfs. Binaries' own
+        // libraries are resolved from their ELF headers; this still covers
+        // libraries only loaded at runtime (dlopen)
         custom(CustomOp::CopyAllLibraries),
     ],
 };
@@ -93,6 +98,8 @@ pub static FILESYSTEM: Component = Component {
 pub static BUSYBOX: Component = Component {
     name: "busybox",
     phase: Phase::Binaries,
+    requires: &["filesystem"],
+    overrides: &[],
     ops: &[
         // Copy busybox binary
         bin("busybox"),
@@ -167,6 +174,8 @@ const ADDITIONAL_SBINS: &[&str] = &[
 pub static UTILITIES: Component = Component {
     name: "utilities",
     phase: Phase::Binaries,
+    requires: &["filesystem"],
+    overrides: &[],
     ops: &[
         // Placeholder - temporarily disabled
         // Will be re-enabled when packages.rhai is run to install bash, vim, etc.
@@ -206,8 +215,7 @@ const OPENRC_SCRIPTS: &[&str] = &[
     // Note: urandom doesn't exist in Alpine - seedrng handles random seed
     // Services
     // Temporarily disabled - require packages.rhai for:
-    // "sshd",     // requires openssh
-    // "chronyd",  // requires chrony
+    // (sshd and chronyd are copied by the SSH and chrony components)
     // "dhcpcd",   // requires dhcpcd
     // "iwd",      // requires iwd
     "local",
@@ -228,6 +236,8 @@ const RUNLEVEL_DIRS: &[&str] = &[
 pub static OPENRC: Component = Component {
     name: "openrc",
     phase: Phase::Init,
+    requires: &["busybox"],
+    overrides: &[],
     ops: &[
         // OpenRC directories
         dir("etc/init.d"),
@@ -283,6 +293,8 @@ pub static OPENRC: Component = Component {
 pub static DEVICE_MANAGER: Component = Component {
     name: "eudev",
     phase: Phase::Init,
+    requires: &["openrc"],
+    overrides: &[],
     ops: &[
         // Copy udev rules
         copy_tree("etc/udev"),
@@ -298,6 +310,8 @@ pub static DEVICE_MANAGER: Component = Component {
 pub static MODULES: Component = Component {
     name: "modules",
     phase: Phase::Init,
+    requires: &["filesystem"],
+    overrides: &[],
     ops: &[
         // Copy kernel modules to EROFS staging root
         custom(CustomOp::CopyModules),
@@ -323,6 +337,8 @@ iface eth0 inet dhcp
 pub static NETWORK: Component = Component {
     name: "network",
     phase: Phase::Services,
+    requires: &["openrc"],
+    overrides: &[],
     ops: &[
         // Network configuration directories
         dir("etc/network"),
@@ -355,22 +371,30 @@ pub static NETWORK: Component = Component {
 pub static SSH: Component = Component {
     name: "ssh",
     phase: Phase::Services,
+    requires: &["openrc"],
+    overrides: &[],
     ops: &[
         // SSH directories
         dir("etc/ssh"),
         dir_mode("var/empty/sshd", 0o755),
         dir_mode("run/sshd", 0o755),
+        // SSH daemon with everything its package ships: sshd calls
+        // sshd-session for each connection (OpenSSH 9.8+ split
+        // architecture), and sftp-server backs the sftp subsystem
+        package("openssh-server"),
+        package("openssh-sftp-server"),
+        bin("ssh-keygen"),
         // Copy SSH configuration
         copy_tree("etc/ssh"),
-        // Copy SSH helper binaries (OpenSSH 9.8+ split architecture)
-        // These are REQUIRED: sshd calls sshd-session for each connection
-        copy_tree("usr/lib/ssh"),
+        // OpenRC init script for sshd
+        openrc_scripts(&["sshd"]),
         // sshd user and group
         group("sshd", 22),
         user("sshd", 22, 22, "/var/empty/sshd", "/sbin/nologin"),
-        // Generate host keys and configure sshd for live ISO
+        // Harden sshd_config (key-only root) and defer host keys to first boot
         custom(CustomOp::SetupSsh),
-        // Enable sshd in default runlevel
+        // Enable host key generation and sshd in default runlevel
+        openrc_enable("sshd-keygen", "default"),
         openrc_enable("sshd", "default"),
     ],
 };
@@ -379,18 +403,19 @@ pub static SSH: Component = Component {
 pub static CHRONY: Component = Component {
     name: "chrony",
     phase: Phase::Services,
+    requires: &["openrc"],
+    overrides: &[],
     ops: &[
         // Chrony directories
         dir("var/lib/chrony"),
         dir("var/log/chrony"),
-        // Copy chrony configuration
-        copy_tree("etc/chrony"),
+        // chronyd, chronyc and /etc/chrony/chrony.conf
+        package("chrony"),
+        openrc_scripts(&["chronyd"]),
         // chrony user
         group("chrony", 123),
         user("chrony", 123, 123, "/var/lib/chrony", "/sbin/nologin"),
-        // DISABLED: chronyd needs config file
-        // TODO: Create /etc/chrony/chrony.conf and re-enable
-        // openrc_enable("chronyd", "default"),
+        openrc_enable("chronyd", "default"),
     ],
 };
 
@@ -398,16 +423,6 @@ pub static CHRONY: Component = Component {
 // Phase 6: Config
 // =============================================================================
 
-/// AcornOS os-release content.
-const OS_RELEASE: &str = r#"NAME="AcornOS"
-ID=acornos
-ID_LIKE=alpine
-VERSION_ID=1.0
-PRETTY_NAME="AcornOS"
-HOME_URL="https://levitateos.org/acorn"
-BUG_REPORT_URL="https://github.com/levitateos/levitateos/issues"
-"#;
-
 /// AcornOS MOTD.
 const MOTD: &str = r#"
     _                          ___  ____
@@ -432,11 +447,11 @@ const ISSUE: &str = "AcornOS \\n \\l\n\n";
 pub static BRANDING: Component = Component {
     name: "branding",
     phase: Phase::Config,
+    requires: &["filesystem"],
+    overrides: &[],
     ops: &[
-        // OS identity
-        write_file("etc/os-release", OS_RELEASE),
-        // Canonical location per FreeDesktop os-release spec
-        write_file("usr/lib/os-release", OS_RELEASE),
+        // OS identity, in /etc and /usr/lib (FreeDesktop), with the build's version
+        custom(CustomOp::WriteOsRelease),
         write_file("etc/hostname", "acornos\n"),
         write_file("etc/motd", MOTD),
         write_file("etc/issue", ISSUE),
@@ -452,28 +467,12 @@ pub static BRANDING: Component = Component {
     ],
 };
 
-/// Base inittab content (standard login, no autologin).
-/// This is for installed systems. LIVE_FINAL overrides this with autologin.
-const BASE_INITTAB: &str = "# /etc/inittab - AcornOS\n\n\
-::sysinit:/sbin/openrc sysinit\n\
-::sysinit:/sbin/openrc boot\n\
-::wait:/sbin/openrc default\n\n\
-# Standard login on TTYs (no autologin for installed systems)\n\
-tty1::respawn:/sbin/agetty --noclear tty1 linux\n\
-tty2::respawn:/sbin/agetty tty2 linux\n\
-tty3::respawn:/sbin/agetty tty3 linux\n\
-tty4::respawn:/sbin/agetty tty4 linux\n\
-tty5::respawn:/sbin/agetty tty5 linux\n\
-tty6::respawn:/sbin/agetty tty6 linux\n\n\
-# Serial console\n\
-ttyS0::respawn:/sbin/agetty -L 115200 ttyS0 vt100\n\n\
-::shutdown:/sbin/openrc shutdown\n\
-::ctrlaltdel:/sbin/reboot\n";
-
 /// System configuration component.
 pub static SYSCONFIG: Component = Component {
     name: "sysconfig",
     phase: Phase::Config,
+    requires: &["branding"],
+    overrides: &[],
     ops: &[
         // fstab (minimal for live)
         write_file(
@@ -490,17 +489,30 @@ pub static SYSCONFIG: Component = Component {
             "etc/shells",
             "/bin/sh\n/bin/ash\n/bin/bash\n/usr/bin/bash\n",
         ),
-        // CRITICAL: Base inittab for all systems (installed and live)
-        // LIVE_FINAL overrides this with autologin version for live ISO
-        write_file_mode("etc/inittab", BASE_INITTAB, 0o644),
-        // APK repositories - allows `apk add` to work post-boot
-        // Configured with Alpine v3.23 main + community repositories
-        copy_file("etc/apk/repositories"),
+        // CRITICAL: inittab for all systems; the live overlay adds autologin
+        write_file_mode("etc/inittab", super::inittab::INITTAB, 0o644),
         // Copy timezone data
         custom(CustomOp::CopyTimezoneData),
     ],
 };
 
+// =============================================================================
+// Phase 7: Packages
+// =============================================================================
+
+/// apk component.
+///
+/// Lets `apk add` work post-boot and after recstrap: main + community of
+/// the build's Alpine mirror and branch, Alpine's signing keys, and the
+/// source rootfs's database and world.
+pub static APK: Component = Component {
+    name: "apk",
+    phase: Phase::Packages,
+    requires: &["filesystem"],
+    overrides: &[],
+    ops: &[dir("etc/apk/keys"), custom(CustomOp::SetupApk)],
+};
+
 // =============================================================================
 // Phase 8: Firmware
 // =============================================================================
@@ -509,13 +521,15 @@ pub static SYSCONFIG: Component = Component {
 pub static FIRMWARE: Component = Component {
     name: "firmware",
     phase: Phase::Firmware,
+    requires: &["filesystem"],
+    overrides: &[],
     ops: &[
-        // WiFi firmware only for live ISO
-        // This reduces the EROFS size to < 500MB while maintaining
-        // the ability to add drivers post-boot via apk for real hardware.
-        // Packages are installed (so distro-spec is satisfied) but only
-        // essential WiFi firmware is copied to the staging rootfs.
-        custom(CustomOp::CopyWifiFirmware),
+        // Only the firmware the policy selects (WiFi by default, see
+        // firmware.toml / --firmware). This keeps the EROFS small while
+        // maintaining the ability to add drivers post-boot via apk for
+        // real hardware. Packages are installed (so distro-spec is
+        // satisfied) but most of linux-firmware stays out of staging.
+        custom(CustomOp::CopyFirmware),
     ],
 };
 
@@ -531,13 +545,52 @@ pub static FIRMWARE: Component = Component {
 pub static CHECKPOINT_TESTS: Component = Component {
     name: "stage-tests",
     phase: Phase::Final,
+    requires: &["busybox"],
+    overrides: &[],
     ops: &[custom(CustomOp::InstallStageTests)],
 };
 
+/// Skeleton ~/.profile for new users (the live user gets it via live-user-home).
+const SKEL_PROFILE: &str = "# ~/.profile - AcornOS\n\
+# /etc/profile (and the welcome script in /etc/profile.d) runs first.\n\n\
+export ENV=\"$HOME/.ashrc\"\n";
+
+/// Skeleton ~/.ashrc for interactive ash shells.
+const SKEL_ASHRC: &str = "# ~/.ashrc - AcornOS\n\n\
+# AcornOS uses doas instead of sudo\n\
+alias sudo=doas\n";
+
+/// Creates the live user's home from /etc/skel on boot (it lives on the tmpfs
+/// upper layer). No-op when the live overlay didn't add a live user.
+const LIVE_USER_HOME_INIT: &str = r#"#!/sbin/openrc-run
+# Populate the live user's home directory from /etc/skel
+
+description="Create live user home"
+
+depend() {
+    need localmount
+}
+
+start() {
+    LIVE_USER="${LIVE_USER:-live}"
+    id "$LIVE_USER" >/dev/null 2>&1 || return 0
+
+    home="/home/$LIVE_USER"
+    ebegin "Creating home for $LIVE_USER"
+    mkdir -p "$home" &&
+        cp -a /etc/skel/. "$home"/ &&
+        chown -R "$LIVE_USER:$LIVE_USER" "$home" &&
+        chmod 700 "$home"
+    eend $?
+}
+"#;
+
 /// Final setup component for live ISO.
 pub static LIVE_FINAL: Component = Component {
     name: "live-final",
     phase: Phase::Final,
+    requires: &["openrc", "branding"],
+    overrides: &[],
     ops: &[
         // Welcome message
         custom(CustomOp::CreateWelcomeMessage),
@@ -545,33 +598,64 @@ pub static LIVE_FINAL: Component = Component {
         custom(CustomOp::CreateLiveOverlay),
         // Installer tools
         custom(CustomOp::CopyRecstrap),
-        // Root autologin for live (both tty1 AND serial for testing)
-        write_file_mode(
-            "etc/inittab",
-            "# /etc/inittab - AcornOS Live\n\n\
-             ::sysinit:/sbin/openrc sysinit\n\
-             ::sysinit:/sbin/openrc boot\n\
-             ::wait:/sbin/openrc default\n\n\
-             # Autologin as root on tty1 (VGA console)\n\
-             tty1::respawn:/sbin/agetty --autologin root --noclear tty1 linux\n\
-             tty2::respawn:/sbin/agetty tty2 linux\n\
-             tty3::respawn:/sbin/agetty tty3 linux\n\n\
-             # Serial console with autologin for QEMU testing\n\
-             ttyS0::respawn:/sbin/agetty --autologin root -L 115200 ttyS0 vt100\n\n\
-             ::shutdown:/sbin/openrc shutdown\n\
-             ::ctrlaltdel:/sbin/reboot\n",
-            0o644,
-        ),
+        custom(CustomOp::CreateInstaller),
+        // Home skeleton + boot-time home creation for the optional live user
+        write_file("etc/skel/.profile", SKEL_PROFILE),
+        write_file("etc/skel/.ashrc", SKEL_ASHRC),
+        write_file_mode("etc/init.d/live-user-home", LIVE_USER_HOME_INIT, 0o755),
+        openrc_enable("live-user-home", "default"),
+    ],
+};
+
+// =============================================================================
+// Live overlay (built into live-overlay/, not staging)
+// =============================================================================
+
+/// Live session of the testing profile: root autologins on tty1 and the
+/// serial console.
+pub static LIVE_OVERLAY: Component = Component {
+    name: "live-overlay",
+    phase: Phase::Final,
+    requires: &[],
+    overrides: &[],
+    ops: &[
+        custom(CustomOp::WriteLiveInittab),
+        custom(CustomOp::InstallMakePersistence),
     ],
 };
 
+/// Live session of the release profile: no test instrumentation, a login
+/// prompt on the serial console.
+pub static LIVE_OVERLAY_RELEASE: Component = Component {
+    name: "live-overlay-release",
+    phase: Phase::Final,
+    requires: &[],
+    overrides: &[],
+    ops: &[
+        custom(CustomOp::StripTestInstrumentation),
+        custom(CustomOp::WriteReleaseLiveInittab),
+        custom(CustomOp::InstallMakePersistence),
+    ],
+};
+
+/// The optional live user, applied after either live overlay component;
+/// takes over tty1 autologin.
+pub static LIVE_USER: Component = Component {
+    name: "live-user",
+    phase: Phase::Final,
+    requires: &[],
+    overrides: &[],
+    ops: &[custom(CustomOp::CreateLiveUser)],
+};
+
 // =============================================================================
 // All Components (for build_system)
 // =============================================================================
 
 /// All components in phase order.
 ///
-/// This list is used by `build_system()` to execute all components.
+/// This list is used by `build_system()` to execute all components, after
+/// dropping disabled ones and checking `requires` (see [`super::selection`]).
 pub static ALL_COMPONENTS: &[&Component] = &[
     // Phase 1: Filesystem
     &FILESYSTEM,
@@ -589,6 +673,8 @@ pub static ALL_COMPONENTS: &[&Component] = &[
     // Phase 6: Config
     &BRANDING,
     &SYSCONFIG,
+    // Phase 7: Packages
+    &APK,
     // Phase 8: Firmware
     &FIRMWARE,
     // Phase 9: Final
@@ -631,8 +717,10 @@ mod tests {
     #[test]
     fn test_branding_content() {
         // Verify branding content is correct
-        assert!(OS_RELEASE.contains("AcornOS"));
         assert!(MOTD.contains("AcornOS"));
-        assert!(!OS_RELEASE.contains("Alpine")); // Should NOT say Alpine
+        assert!(BRANDING
+            .ops
+            .iter()
+            .any(|op| matches!(op, crate::component::Op::Custom(CustomOp::WriteOsRelease))));
     }
 }
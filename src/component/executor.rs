@@ -1,32 +1,719 @@
 //! Component executor - interprets Op variants and performs actual operations.
 //!
 //! Delegates to distro-builder shared infrastructure for common operations.
-//! Only copy_tree (with its warn-and-continue behavior) and custom ops stay local.
+//! Only copy_tree (see [`crate::tree_copy`]) and custom ops stay local.
+//!
+//! [`resolve_op`] turns an op into the [`Action`]s it will take without
+//! touching the filesystem; `-v` output, `acornos plan` and the up-front
+//! [`missing_sources`] check are built on it.
 
 use anyhow::{bail, Context, Result};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use distro_builder::executor::{binaries, directories, files, openrc, users};
-use distro_builder::LicenseTracker;
+use distro_builder::executor::{directories, files, openrc};
+use distro_builder::{LicenseTracker, PackageManager};
 
+use super::accounts;
 use super::BuildContext;
-use super::{Component, Op};
+use super::{Component, CustomOp, Op};
+use crate::artifact::full_initramfs::elf_dependencies;
+use crate::fast_copy::{break_hardlink, Copier};
+use crate::tree_copy::copy_tree_with;
+
+/// Directories binaries are looked up in, relative to the source rootfs.
+const BIN_DIRS: &[&str] = &["usr/bin", "bin", "usr/sbin", "sbin"];
+
+/// Directories libraries are looked up in, relative to a rootfs.
+const LIB_DIRS: &[&str] = &["lib", "usr/lib"];
+
+/// Symlink hops followed before giving up on a loop.
+const MAX_SYMLINKS: usize = 40;
+
+/// Per-build executor state, shared by every component of one build.
+///
+/// Binaries are installed once, each binary's ELF dependencies are read
+/// once, and each library is checked (and copied if missing) once, however
+/// many components ask for them.
+#[derive(Debug, Default)]
+pub struct ExecState {
+    /// Staging paths of the binaries installed so far.
+    installed: RefCell<BTreeSet<PathBuf>>,
+    /// Interpreter and DT_NEEDED entries per resolved source binary.
+    deps: RefCell<HashMap<PathBuf, Vec<String>>>,
+    /// Libraries known to be in staging.
+    libraries: RefCell<BTreeSet<String>>,
+    /// Components that wrote each staging path, in build order.
+    writers: RefCell<BTreeMap<PathBuf, Vec<&'static str>>>,
+    /// Files copied from the source rootfs, relative to it, for the
+    /// license report.
+    copied: RefCell<BTreeSet<PathBuf>>,
+}
+
+impl ExecState {
+    /// Record the files and symlinks `component` writes into staging.
+    pub fn record_writes(&self, source: &Path, staging: &Path, component: &Component) {
+        let mut writers = self.writers.borrow_mut();
+        for op in component.ops {
+            for path in written_paths(source, staging, op) {
+                let names = writers.entry(path).or_default();
+                if !names.contains(&component.name) {
+                    names.push(component.name);
+                }
+            }
+        }
+    }
+
+    /// Record the source rootfs files `component` copies as they are: the
+    /// binaries with the libraries they load, trees, files, init scripts
+    /// and packages. Skipped components are recorded alike, so incremental
+    /// and full builds ship the same licenses.
+    pub fn record_copies(&self, source: &Path, component: &Component) -> Result<()> {
+        for op in component.ops {
+            let files = copied_sources(self, source, op)?;
+            let mut copied = self.copied.borrow_mut();
+            for file in files {
+                if let Ok(rel) = file.strip_prefix(source) {
+                    copied.insert(rel.to_path_buf());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Files copied from the source rootfs so far, relative to it.
+    pub fn copied(&self) -> Vec<PathBuf> {
+        self.copied.borrow().iter().cloned().collect()
+    }
+
+    /// Paths written by more than one component, except where every later
+    /// writer lists the path in its `overrides`.
+    pub fn conflicts(&self, components: &[&Component]) -> Vec<String> {
+        let overrides = |name: &str, path: &Path| {
+            components
+                .iter()
+                .filter(|c| c.name == name)
+                .any(|c| c.overrides.iter().any(|o| Path::new(o) == path))
+        };
+        self.writers
+            .borrow()
+            .iter()
+            .filter(|(path, names)| names[1..].iter().any(|n| !overrides(n, path)))
+            .map(|(path, names)| {
+                let (last, rest) = names.split_last().unwrap_or((&"", &[]));
+                let verb = if rest.len() == 1 { "both" } else { "all" };
+                format!(
+                    "{} and {} {} write {}",
+                    rest.join(", "),
+                    last,
+                    verb,
+                    path.display()
+                )
+            })
+            .collect()
+    }
+}
 
 /// Execute all operations in a component.
-pub fn execute(ctx: &BuildContext, component: &Component, tracker: &LicenseTracker) -> Result<()> {
-    println!("Installing {}...", component.name);
+///
+/// Missing binaries and libraries are collected and reported together
+/// once every op has run; any other failure stops at the failing op.
+pub fn execute(
+    ctx: &BuildContext,
+    component: &Component,
+    tracker: &LicenseTracker,
+    state: &ExecState,
+) -> Result<()> {
+    log::info!("Installing {}...", component.name);
 
+    let mut missing = Vec::new();
     for op in component.ops {
-        execute_op(ctx, op, tracker)
+        log::debug!("  {}", describe_op(ctx, op));
+        execute_op(ctx, op, tracker, state, &mut missing)
             .with_context(|| format!("in component '{}': {:?}", component.name, op))?;
     }
 
+    if !missing.is_empty() {
+        bail!(
+            "in component '{}': missing binaries or libraries:\n  {}",
+            component.name,
+            missing.join("\n  ")
+        );
+    }
+    state.record_writes(&ctx.source, &ctx.staging, component);
+    state.record_copies(&ctx.source, component)?;
+    Ok(())
+}
+
+/// Execute `components` into `root` instead of staging, reading from
+/// `source`.
+///
+/// For trees built beside the rootfs, like the live overlay, which is
+/// seeded from the rootfs staging.
+pub fn execute_into(
+    base_dir: &Path,
+    source: &Path,
+    root: &Path,
+    components: &[&Component],
+) -> Result<()> {
+    let ctx = BuildContext {
+        base_dir: base_dir.to_path_buf(),
+        source: source.to_path_buf(),
+        staging: root.to_path_buf(),
+    };
+    let tracker = LicenseTracker::new(ctx.source.clone(), PackageManager::Apk);
+    let state = ExecState::default();
+    for component in components {
+        execute(&ctx, component, &tracker, &state)?;
+    }
+    Ok(())
+}
+
+/// What an op does, with its paths resolved but nothing touched.
+///
+/// [`resolve_op`] is shared by the `-v` output and `acornos plan`; the side
+/// effects stay in [`execute_op`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    CreateDir {
+        path: PathBuf,
+        mode: Option<u32>,
+    },
+    WriteFile {
+        path: PathBuf,
+        bytes: usize,
+        mode: Option<u32>,
+    },
+    /// Copy of a required source file.
+    Copy {
+        src: PathBuf,
+        dst: PathBuf,
+    },
+    /// Copy of an optional source tree (skipped if missing).
+    CopyTree {
+        src: PathBuf,
+        dst: PathBuf,
+    },
+    Symlink {
+        link: PathBuf,
+        target: String,
+    },
+    /// An apk package, followed by a copy per file it owns.
+    Package {
+        name: String,
+        files: usize,
+    },
+    Enable {
+        service: String,
+        runlevel: String,
+    },
+    User {
+        name: String,
+        uid: u32,
+        gid: u32,
+    },
+    Group {
+        name: String,
+        gid: u32,
+    },
+    /// Imperative code; its effects are only known by running it.
+    Custom(CustomOp),
+}
+
+impl Action {
+    /// Manifest keyword (`COPY`, `ENABLE`, ...).
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::CreateDir { .. } => "CREATE_DIR",
+            Self::WriteFile { .. } => "WRITE_FILE",
+            Self::Copy { .. } => "COPY",
+            Self::CopyTree { .. } => "COPY_TREE",
+            Self::Symlink { .. } => "SYMLINK",
+            Self::Package { .. } => "PACKAGE",
+            Self::Enable { .. } => "ENABLE",
+            Self::User { .. } => "USER",
+            Self::Group { .. } => "GROUP",
+            Self::Custom(_) => "CUSTOM",
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode =
+            |mode: &Option<u32>| mode.map(|m| format!(" (mode {:o})", m)).unwrap_or_default();
+        write!(f, "{} ", self.kind())?;
+        match self {
+            Self::CreateDir { path, mode: m } => write!(f, "{}{}", path.display(), mode(m)),
+            Self::WriteFile {
+                path,
+                bytes,
+                mode: m,
+            } => write!(f, "{} {} bytes{}", path.display(), bytes, mode(m)),
+            Self::Copy { src, dst } | Self::CopyTree { src, dst } => {
+                write!(f, "{} -> {}", src.display(), dst.display())
+            }
+            Self::Symlink { link, target } => write!(f, "{} -> {}", link.display(), target),
+            Self::Package { name, files } => write!(f, "{} ({} files)", name, files),
+            Self::Enable { service, runlevel } => write!(f, "{} in {}", service, runlevel),
+            Self::User { name, uid, gid } => write!(f, "{} (uid {}, gid {})", name, uid, gid),
+            Self::Group { name, gid } => write!(f, "{} (gid {})", name, gid),
+            Self::Custom(op) => write!(f, "{:?} (not simulated)", op),
+        }
+    }
+}
+
+/// Resolve `op` against the `source` rootfs and `staging` tree.
+pub fn resolve_op(source: &Path, staging: &Path, op: &Op) -> Vec<Action> {
+    let dir = |path: &str, mode: Option<u32>| Action::CreateDir {
+        path: staging.join(path),
+        mode,
+    };
+    let write = |path: &str, content: &str, mode: Option<u32>| Action::WriteFile {
+        path: staging.join(path),
+        bytes: content.len(),
+        mode,
+    };
+    let copy = |path: &str| Action::Copy {
+        src: source.join(path),
+        dst: staging.join(path),
+    };
+    let binary = |name: &str, dest_dir: &str| Action::Copy {
+        src: find_binary(source, name).unwrap_or_else(|| source.join(dest_dir).join(name)),
+        dst: staging.join(dest_dir).join(name),
+    };
+    match op {
+        Op::Dir(path) => vec![dir(path, None)],
+        Op::DirMode(path, mode) => vec![dir(path, Some(*mode))],
+        Op::Dirs(paths) => paths.iter().map(|p| dir(p, None)).collect(),
+        Op::WriteFile(path, content) => vec![write(path, content, None)],
+        Op::WriteFileMode(path, content, mode) => vec![write(path, content, Some(*mode))],
+        Op::Symlink(link, target) => vec![Action::Symlink {
+            link: staging.join(link),
+            target: target.to_string(),
+        }],
+        Op::CopyFile(path) => vec![copy(path)],
+        Op::CopyTree(path) => vec![Action::CopyTree {
+            src: source.join(path),
+            dst: staging.join(path),
+        }],
+        Op::Bin(name) => vec![binary(name, "usr/bin")],
+        Op::Sbin(name) => vec![binary(name, "usr/sbin")],
+        Op::Bins(names) => names.iter().map(|n| binary(n, "usr/bin")).collect(),
+        Op::Sbins(names) => names.iter().map(|n| binary(n, "usr/sbin")).collect(),
+        Op::Package(name) => {
+            let files = package_files(source, name).unwrap_or_default();
+            std::iter::once(Action::Package {
+                name: name.to_string(),
+                files: files.len(),
+            })
+            .chain(files.iter().map(|f| copy(f)))
+            .collect()
+        }
+        Op::OpenrcEnable(service, runlevel) => vec![Action::Enable {
+            service: service.to_string(),
+            runlevel: runlevel.to_string(),
+        }],
+        Op::OpenrcScripts(scripts) => scripts
+            .iter()
+            .map(|s| copy(&format!("etc/init.d/{}", s)))
+            .collect(),
+        Op::OpenrcConf(service, content) => {
+            vec![write(&format!("etc/conf.d/{}", service), content, None)]
+        }
+        Op::User { name, uid, gid, .. } => vec![Action::User {
+            name: name.to_string(),
+            uid: *uid,
+            gid: *gid,
+        }],
+        Op::Group { name, gid } => vec![Action::Group {
+            name: name.to_string(),
+            gid: *gid,
+        }],
+        Op::Custom(op) => vec![Action::Custom(*op)],
+    }
+}
+
+/// What `op` copies from the `source` rootfs that is not there.
+///
+/// Only binaries, copied files and init scripts are checked: they are what
+/// a renamed or dropped Alpine package takes away. Packages, trees and
+/// libraries are still reported when the component runs.
+pub fn missing_sources(source: &Path, op: &Op) -> Vec<String> {
+    let missing_binaries = |names: &[&str]| -> Vec<String> {
+        names
+            .iter()
+            .filter(|name| find_binary(source, name).is_none())
+            .map(|name| format!("binary {}", name))
+            .collect()
+    };
+    match op {
+        Op::Bin(name) | Op::Sbin(name) => missing_binaries(&[name]),
+        Op::Bins(names) | Op::Sbins(names) => missing_binaries(names),
+        Op::CopyFile(_) | Op::OpenrcScripts(_) => resolve_op(source, source, op)
+            .into_iter()
+            .filter_map(|action| match action {
+                Action::Copy { src, .. } if fs::symlink_metadata(&src).is_err() => {
+                    let rel = src.strip_prefix(source).unwrap_or(&src);
+                    Some(format!("file {}", rel.display()))
+                }
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// A binary in the source rootfs (Alpine splits them across the bin dirs).
+fn find_binary(source: &Path, name: &str) -> Option<PathBuf> {
+    BIN_DIRS
+        .iter()
+        .map(|dir| source.join(dir).join(name))
+        .find(|p| fs::symlink_metadata(p).is_ok())
+}
+
+/// Files of `source` that `op` copies as they are (libraries pulled in by
+/// binaries included); what it generates is not listed.
+fn copied_sources(state: &ExecState, source: &Path, op: &Op) -> Result<Vec<PathBuf>> {
+    let binaries = |names: &[&str]| -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        for name in names {
+            let Some(binary) = find_binary(source, name).and_then(|b| resolve_in_root(source, &b))
+            else {
+                continue;
+            };
+            // The binary and its libraries, transitively
+            let mut pending = dependencies(state, binary.clone())?;
+            files.push(binary);
+            while let Some(dep) = pending.pop() {
+                let Some(lib) = find_library(source, &dep) else {
+                    continue;
+                };
+                if !files.contains(&lib) {
+                    pending.extend(dependencies(state, lib.clone())?);
+                    files.push(lib);
+                }
+            }
+        }
+        Ok(files)
+    };
+    Ok(match op {
+        Op::Bin(name) | Op::Sbin(name) => binaries(&[name])?,
+        Op::Bins(names) | Op::Sbins(names) => binaries(names)?,
+        Op::CopyFile(_) | Op::OpenrcScripts(_) => resolve_op(source, source, op)
+            .into_iter()
+            .filter_map(|action| match action {
+                Action::Copy { src, .. } => Some(src),
+                _ => None,
+            })
+            .collect(),
+        Op::CopyTree(path) => {
+            let mut files = Vec::new();
+            tree_files(&source.join(path), &source.join(path), &mut files);
+            files
+        }
+        Op::Package(name) => package_files(source, name)
+            .unwrap_or_default()
+            .iter()
+            .map(|f| source.join(f))
+            .collect(),
+        Op::Custom(op) => super::custom::copied_sources(source, *op),
+        _ => Vec::new(),
+    }
+    .into_iter()
+    .filter(|f| fs::symlink_metadata(f).is_ok_and(|m| !m.is_dir()))
+    .collect())
+}
+
+/// Staging paths `op` writes a file or symlink at, relative to `staging`.
+///
+/// Directories, runlevel links, binaries (installed once, whoever asks)
+/// and passwd entries can be shared between components; custom ops are not
+/// tracked.
+fn written_paths(source: &Path, staging: &Path, op: &Op) -> Vec<PathBuf> {
+    if matches!(op, Op::Bin(_) | Op::Sbin(_) | Op::Bins(_) | Op::Sbins(_)) {
+        return Vec::new();
+    }
+    let mut paths = Vec::new();
+    for action in resolve_op(source, staging, op) {
+        match action {
+            Action::WriteFile { path, .. }
+            | Action::Copy { dst: path, .. }
+            | Action::Symlink { link: path, .. } => paths.push(path),
+            Action::CopyTree { src, dst } => tree_files(&src, &dst, &mut paths),
+            _ => {}
+        }
+    }
+    paths
+        .into_iter()
+        .filter_map(|p| p.strip_prefix(staging).ok().map(Path::to_path_buf))
+        .collect()
+}
+
+/// Push the destination of every non-directory under `src` to `paths`.
+fn tree_files(src: &Path, dst: &Path, paths: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(src) else {
+        if fs::symlink_metadata(src).is_ok() {
+            paths.push(dst.to_path_buf());
+        }
+        return;
+    };
+    let mut entries: Vec<_> = entries.flatten().collect();
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let dst = dst.join(entry.file_name());
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            tree_files(&entry.path(), &dst, paths);
+        } else {
+            paths.push(dst);
+        }
+    }
+}
+
+/// Files apk package `name` owns in the `source` rootfs.
+fn package_files(source: &Path, name: &str) -> Result<Vec<String>> {
+    crate::apk::installed_files(source)?
+        .remove(name)
+        .with_context(|| format!("package '{}' is not installed in the source rootfs", name))
+}
+
+/// An op with the paths it resolves to, for `-v` output.
+fn describe_op(ctx: &BuildContext, op: &Op) -> String {
+    resolve_op(&ctx.source, &ctx.staging, op)
+        .iter()
+        .map(Action::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Copy one binary from the source rootfs into `dest_dir` of staging,
+/// unless this build already installed it.
+///
+/// Its libraries are resolved from its ELF headers by
+/// [`missing_libraries`]; nothing from the target rootfs is run on the
+/// host (glibc's ldd would run the musl loader, and its answer varied by
+/// host).
+fn copy_binary(ctx: &BuildContext, state: &ExecState, name: &str, dest_dir: &str) -> Result<()> {
+    let dest = ctx.staging.join(dest_dir).join(name);
+    if state.installed.borrow().contains(&dest) {
+        log::trace!("    {}: already installed", name);
+        return Ok(());
+    }
+    log::trace!(
+        "    {}: from {} into {}",
+        name,
+        ctx.source.display(),
+        ctx.staging.join(dest_dir).display()
+    );
+    install_binary(&ctx.source, &ctx.staging, name, dest_dir)?;
+    state.installed.borrow_mut().insert(dest);
+    Ok(())
+}
+
+/// Copy binary `name` from the source bin dirs, through its symlinks, to
+/// `dest_dir/name` in staging with its mode.
+fn install_binary(source: &Path, staging: &Path, name: &str, dest_dir: &str) -> Result<PathBuf> {
+    let src = find_binary(source, name)
+        .with_context(|| format!("not in {} of the source rootfs", BIN_DIRS.join(", ")))?;
+    let resolved = resolve_in_root(source, &src)
+        .with_context(|| format!("{} is a dangling symlink", src.display()))?;
+    let dir = staging.join(dest_dir);
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let dst = dir.join(name);
+    if fs::symlink_metadata(&dst).is_ok() {
+        fs::remove_file(&dst)?;
+    }
+    // fs::copy keeps the permission bits
+    fs::copy(&resolved, &dst).with_context(|| format!("Failed to copy {}", resolved.display()))?;
+    Ok(dst)
+}
+
+/// Install `names` into `dest_dir`, adding what is missing (the binary or
+/// any of its libraries) to `missing`.
+fn install_binaries(
+    ctx: &BuildContext,
+    state: &ExecState,
+    tracker: &LicenseTracker,
+    names: &[&str],
+    dest_dir: &str,
+    missing: &mut Vec<String>,
+) -> Result<()> {
+    for name in names {
+        if let Err(e) = copy_binary(ctx, state, name, dest_dir) {
+            missing.push(format!("{}: {}", name, e));
+            continue;
+        }
+        tracker.register_binary(name);
+        let Some(binary) = find_binary(&ctx.source, name) else {
+            continue;
+        };
+        for lib in missing_libraries(&ctx.source, &ctx.staging, state, &binary)? {
+            missing.push(format!(
+                "{}: library {} not in the source rootfs",
+                name, lib
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Install every file of apk package `name` from `source` into `staging`
+/// with its mode, adding a package that is not installed, or a library its
+/// ELF files need that is nowhere, to `missing`.
+fn install_package(
+    source: &Path,
+    staging: &Path,
+    state: &ExecState,
+    name: &str,
+    missing: &mut Vec<String>,
+) -> Result<()> {
+    let files = match package_files(source, name) {
+        Ok(files) => files,
+        Err(e) => {
+            missing.push(format!("{}: {:#}", name, e));
+            return Ok(());
+        }
+    };
+    for file in &files {
+        let src = source.join(file);
+        let dst = staging.join(file);
+        if let Some(parent) = Path::new(file).parent() {
+            create_dirs_like(source, staging, parent)?;
+        }
+        let meta = fs::symlink_metadata(&src)
+            .with_context(|| format!("{} lists {}, which is missing", name, src.display()))?;
+        if fs::symlink_metadata(&dst).is_ok() {
+            fs::remove_file(&dst)?;
+        }
+        if meta.file_type().is_symlink() {
+            std::os::unix::fs::symlink(fs::read_link(&src)?, &dst)?;
+            continue;
+        }
+        // fs::copy keeps the permission bits
+        fs::copy(&src, &dst).with_context(|| format!("Failed to copy {}", src.display()))?;
+        for lib in missing_libraries(source, staging, state, &src)? {
+            missing.push(format!(
+                "{}: library {} not in the source rootfs",
+                file, lib
+            ));
+        }
+        state.installed.borrow_mut().insert(dst);
+    }
+    log::debug!("    {}: {} files", name, files.len());
     Ok(())
 }
 
-/// Execute a single operation.
-fn execute_op(ctx: &BuildContext, op: &Op, tracker: &LicenseTracker) -> Result<()> {
+/// Create `rel` and its missing parents in `staging`, with the modes they
+/// have in `source`.
+fn create_dirs_like(source: &Path, staging: &Path, rel: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut path = PathBuf::new();
+    for component in rel.components() {
+        path.push(component);
+        let dst = staging.join(&path);
+        // Existing entries (merged-/usr symlinks included) are left alone
+        if fs::symlink_metadata(&dst).is_ok() {
+            continue;
+        }
+        fs::create_dir(&dst).with_context(|| format!("Failed to create {}", dst.display()))?;
+        if let Ok(meta) = fs::metadata(source.join(&path)) {
+            let mode = meta.permissions().mode() & 0o7777;
+            fs::set_permissions(&dst, fs::Permissions::from_mode(mode))?;
+        }
+    }
+    Ok(())
+}
+
+/// Libraries `binary` (in `source`) needs, directly or through other
+/// libraries, that are neither in `staging` nor in `source`. Ones only in
+/// `source` are copied into staging, including the dynamic loader from
+/// PT_INTERP.
+fn missing_libraries(
+    source: &Path,
+    staging: &Path,
+    state: &ExecState,
+    binary: &Path,
+) -> Result<Vec<String>> {
+    let mut pending = match resolve_in_root(source, binary) {
+        Some(resolved) => dependencies(state, resolved)?,
+        None => Vec::new(),
+    };
+    let mut missing = Vec::new();
+    while let Some(dep) = pending.pop() {
+        if state.libraries.borrow().contains(&dep) || missing.contains(&dep) {
+            continue;
+        }
+        if find_library(staging, &dep).is_none() {
+            let Some(src) = find_library(source, &dep) else {
+                missing.push(dep);
+                continue;
+            };
+            let dst = staging.join(src.strip_prefix(source)?);
+            if let Some(parent) = dst.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(&src, &dst)
+                .with_context(|| format!("Failed to copy library {}", src.display()))?;
+            log::debug!("    copied {} (not in staging)", dep);
+            pending.extend(dependencies(state, src)?);
+        }
+        state.libraries.borrow_mut().insert(dep);
+    }
+    Ok(missing)
+}
+
+/// ELF dependencies of the file at `resolved`, read once per build.
+/// Scripts and static binaries have none.
+fn dependencies(state: &ExecState, resolved: PathBuf) -> Result<Vec<String>> {
+    if let Some(deps) = state.deps.borrow().get(&resolved) {
+        return Ok(deps.clone());
+    }
+    let deps = elf_dependencies(&fs::read(&resolved)?).unwrap_or_default();
+    state.deps.borrow_mut().insert(resolved, deps.clone());
+    Ok(deps)
+}
+
+/// A library under `root`: an absolute dependency (the PT_INTERP loader)
+/// at its own path, else by file name in the library dirs.
+fn find_library(root: &Path, dep: &str) -> Option<PathBuf> {
+    let name = Path::new(dep).file_name()?;
+    let exact = dep.strip_prefix('/').map(|rel| root.join(rel)).into_iter();
+    exact
+        .chain(LIB_DIRS.iter().map(|dir| root.join(dir).join(name)))
+        .find_map(|p| resolve_in_root(root, &p))
+}
+
+/// Follow `path`'s symlinks inside `root` (absolute targets are relative to
+/// `root`, not the host) to a regular file.
+fn resolve_in_root(root: &Path, path: &Path) -> Option<PathBuf> {
+    let mut current = path.to_path_buf();
+    for _ in 0..MAX_SYMLINKS {
+        match fs::read_link(&current) {
+            Ok(target) => {
+                current = match target.strip_prefix("/") {
+                    Ok(rel) => root.join(rel),
+                    Err(_) => current.parent()?.join(target),
+                };
+            }
+            Err(_) => return current.is_file().then_some(current),
+        }
+    }
+    None
+}
+
+/// Execute a single operation; missing binaries and libraries go to
+/// `missing` instead of failing the op.
+fn execute_op(
+    ctx: &BuildContext,
+    op: &Op,
+    tracker: &LicenseTracker,
+    state: &ExecState,
+    missing: &mut Vec<String>,
+) -> Result<()> {
     match op {
         // Directory operations
         Op::Dir(path) => directories::handle_dir(&ctx.staging, path)?,
@@ -34,48 +721,40 @@ fn execute_op(ctx: &BuildContext, op: &Op, tracker: &LicenseTracker) -> Result<(
         Op::Dirs(paths) => directories::handle_dirs(&ctx.staging, paths)?,
 
         // File operations
-        Op::WriteFile(path, content) => files::handle_writefile(&ctx.staging, path, content)?,
+        // Written in place: a hardlink into the rootfs is broken first
+        Op::WriteFile(path, content) => {
+            break_hardlink(&ctx.staging.join(path))?;
+            files::handle_writefile(&ctx.staging, path, content)?
+        }
         Op::WriteFileMode(path, content, mode) => {
+            break_hardlink(&ctx.staging.join(path))?;
             files::handle_writefilemode(&ctx.staging, path, content, *mode)?
         }
         Op::Symlink(link, target) => files::handle_symlink(&ctx.staging, link, target)?,
-        Op::CopyFile(path) => files::handle_copyfile(&ctx.source, &ctx.staging, path)?,
-        Op::CopyTree(path) => copy_tree(&ctx.source.join(path), &ctx.staging.join(path))?,
+        Op::CopyFile(path) => {
+            break_hardlink(&ctx.staging.join(path))?;
+            files::handle_copyfile(&ctx.source, &ctx.staging, path)?
+        }
+        // Never hardlinked: ops and custom ops edit the staged config in place
+        Op::CopyTree(path) => {
+            let copier = Copier::new(false);
+            copy_tree_with(
+                &ctx.source.join(path),
+                &ctx.staging.join(path),
+                &ctx.staging,
+                &copier,
+            )?;
+            log::debug!("    {}", copier.summary(path));
+        }
 
         // Binary operations
-        Op::Bin(name) => {
-            binaries::copy_binary(&ctx.source, &ctx.staging, name, "usr/bin")?;
-            tracker.register_binary(name);
-        }
-        Op::Sbin(name) => {
-            binaries::copy_binary(&ctx.source, &ctx.staging, name, "usr/sbin")?;
-            tracker.register_binary(name);
-        }
-        Op::Bins(names) => {
-            let mut errors = Vec::new();
-            for name in *names {
-                if let Err(e) = binaries::copy_binary(&ctx.source, &ctx.staging, name, "usr/bin") {
-                    errors.push(format!("{}: {}", name, e));
-                } else {
-                    tracker.register_binary(name);
-                }
-            }
-            if !errors.is_empty() {
-                bail!("Missing binaries:\n  {}", errors.join("\n  "));
-            }
-        }
-        Op::Sbins(names) => {
-            let mut missing = Vec::new();
-            for name in *names {
-                if binaries::copy_binary(&ctx.source, &ctx.staging, name, "usr/sbin").is_err() {
-                    missing.push(*name);
-                } else {
-                    tracker.register_binary(name);
-                }
-            }
-            if !missing.is_empty() {
-                bail!("Missing sbin binaries: {}", missing.join(", "));
-            }
+        Op::Bin(name) => install_binaries(ctx, state, tracker, &[name], "usr/bin", missing)?,
+        Op::Sbin(name) => install_binaries(ctx, state, tracker, &[name], "usr/sbin", missing)?,
+        Op::Bins(names) => install_binaries(ctx, state, tracker, names, "usr/bin", missing)?,
+        Op::Sbins(names) => install_binaries(ctx, state, tracker, names, "usr/sbin", missing)?,
+        Op::Package(name) => {
+            install_package(&ctx.source, &ctx.staging, state, name, missing)?;
+            tracker.register_package(name);
         }
 
         // OpenRC operations
@@ -96,8 +775,12 @@ fn execute_op(ctx: &BuildContext, op: &Op, tracker: &LicenseTracker) -> Result<(
             gid,
             home,
             shell,
-        } => users::handle_user(&ctx.source, &ctx.staging, name, *uid, *gid, home, shell)?,
-        Op::Group { name, gid } => users::handle_group(&ctx.source, &ctx.staging, name, *gid)?,
+        } => {
+            accounts::ensure_user(&ctx.source, &ctx.staging, name, *uid, *gid, home, shell)?;
+        }
+        Op::Group { name, gid } => {
+            accounts::ensure_group(&ctx.source, &ctx.staging, name, *gid)?;
+        }
 
         // Custom operations
         Op::Custom(custom_op) => {
@@ -108,43 +791,261 @@ fn execute_op(ctx: &BuildContext, op: &Op, tracker: &LicenseTracker) -> Result<(
     Ok(())
 }
 
-/// Copy a directory tree recursively.
-///
-/// NOTE: This function logs a warning but continues if the source doesn't exist.
-/// This is intentional for optional config directories (like etc/udev/rules.d).
-fn copy_tree(src: &Path, dst: &Path) -> Result<()> {
-    if !src.exists() {
-        println!("  [WARN] copy_tree: source not found: {}", src.display());
-        return Ok(());
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::Phase;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_missing_libraries_copied_once() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("rootfs");
+        let staging = dir.path().join("staging");
+        let exe = std::env::current_exe().unwrap();
+        // The loader can appear both as PT_INTERP and DT_NEEDED; keep one
+        let mut deps = elf_dependencies(&fs::read(&exe).unwrap()).unwrap();
+        let mut names = BTreeSet::new();
+        deps.retain(|d| names.insert(Path::new(d).file_name().unwrap().to_owned()));
+        assert!(deps.len() >= 2, "{:?}", deps);
+
+        // The test binary, reached through an absolute symlink as in Alpine
+        fs::create_dir_all(source.join("bin")).unwrap();
+        fs::create_dir_all(source.join("usr/bin")).unwrap();
+        fs::copy(&exe, source.join("bin/tool")).unwrap();
+        std::os::unix::fs::symlink("/bin/tool", source.join("usr/bin/tool")).unwrap();
 
-    if src.is_file() {
-        if let Some(parent) = dst.parent() {
-            fs::create_dir_all(parent)?;
+        // First dependency already staged, the last nowhere, the rest only
+        // in the source rootfs
+        let (last, rest) = deps.split_last().unwrap();
+        fs::create_dir_all(staging.join("lib")).unwrap();
+        fs::create_dir_all(source.join("lib")).unwrap();
+        for (i, dep) in rest.iter().enumerate() {
+            let name = Path::new(dep).file_name().unwrap();
+            let root = if i == 0 { &staging } else { &source };
+            fs::write(root.join("lib").join(name), "not elf").unwrap();
         }
-        fs::copy(src, dst)?;
-        return Ok(());
+
+        let state = ExecState::default();
+        let binary = find_binary(&source, "tool").unwrap();
+        assert_eq!(
+            missing_libraries(&source, &staging, &state, &binary).unwrap(),
+            std::slice::from_ref(last)
+        );
+        for dep in &rest[1..] {
+            let name = Path::new(dep).file_name().unwrap();
+            assert!(
+                staging.join("lib").join(name).is_file(),
+                "{} not copied",
+                dep
+            );
+        }
+
+        // Cached: the second binary's libraries are not looked up again
+        let read = state.deps.borrow().len();
+        fs::remove_dir_all(staging.join("lib")).unwrap();
+        fs::copy(&exe, source.join("bin/other")).unwrap();
+        let other = find_binary(&source, "other").unwrap();
+        assert_eq!(
+            missing_libraries(&source, &staging, &state, &other).unwrap(),
+            std::slice::from_ref(last)
+        );
+        assert!(!staging.join("lib").exists());
+        assert_eq!(state.deps.borrow().len(), read + 1);
     }
 
-    fs::create_dir_all(dst)?;
+    #[test]
+    fn test_staged_tree_edits_leave_rootfs_alone() {
+        static SSH_CONFIG: Component = Component {
+            name: "ssh-config",
+            phase: Phase::Services,
+            requires: &[],
+            overrides: &[],
+            ops: &[Op::CopyTree("etc/ssh"), Op::Custom(CustomOp::SetupSsh)],
+        };
 
-    for entry in fs::read_dir(src)? {
-        let entry = entry?;
-        let src_path = entry.path();
-        let dst_path = dst.join(entry.file_name());
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("rootfs");
+        let staging = dir.path().join("staging");
+        fs::create_dir_all(source.join("etc/ssh")).unwrap();
+        fs::create_dir_all(staging.join("etc/init.d")).unwrap();
+        let config = source.join("etc/ssh/sshd_config");
+        fs::write(&config, "PermitRootLogin yes\n").unwrap();
 
-        if src_path.is_symlink() {
-            let target = fs::read_link(&src_path)?;
-            if dst_path.exists() || dst_path.is_symlink() {
-                fs::remove_file(&dst_path)?;
-            }
-            std::os::unix::fs::symlink(&target, &dst_path)?;
-        } else if src_path.is_dir() {
-            copy_tree(&src_path, &dst_path)?;
-        } else {
-            fs::copy(&src_path, &dst_path)?;
+        crate::fast_copy::export(true);
+        execute_into(dir.path(), &source, &staging, &[&SSH_CONFIG]).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&config).unwrap(),
+            "PermitRootLogin yes\n"
+        );
+        let staged = fs::read_to_string(staging.join("etc/ssh/sshd_config")).unwrap();
+        assert!(staged.contains("PermitRootLogin prohibit-password"));
+    }
+
+    #[test]
+    fn test_binary_from_musl_fixture() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("rootfs");
+        let staging = dir.path().join("staging");
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/hello-musl-x86_64");
+
+        // Laid out as in Alpine: libc.musl is a link to the loader
+        fs::create_dir_all(source.join("usr/bin")).unwrap();
+        fs::create_dir_all(source.join("bin")).unwrap();
+        fs::create_dir_all(source.join("lib")).unwrap();
+        fs::copy(&fixture, source.join("usr/bin/hello")).unwrap();
+        // Explicit, so the copied mode does not depend on the checkout's umask
+        fs::set_permissions(
+            source.join("usr/bin/hello"),
+            fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+        std::os::unix::fs::symlink("/usr/bin/hello", source.join("bin/hi")).unwrap();
+        fs::write(source.join("lib/ld-musl-x86_64.so.1"), "loader").unwrap();
+        std::os::unix::fs::symlink(
+            "ld-musl-x86_64.so.1",
+            source.join("lib/libc.musl-x86_64.so.1"),
+        )
+        .unwrap();
+
+        let dst = install_binary(&source, &staging, "hi", "usr/bin").unwrap();
+        assert_eq!(fs::read(&dst).unwrap(), fs::read(&fixture).unwrap());
+        assert_eq!(
+            fs::metadata(&dst).unwrap().permissions().mode() & 0o777,
+            0o755
+        );
+        let state = ExecState::default();
+        assert!(missing_libraries(&source, &staging, &state, &dst)
+            .unwrap()
+            .is_empty());
+        assert!(staging.join("lib/ld-musl-x86_64.so.1").is_file());
+
+        // A missing library is reported by its soname
+        fs::remove_file(source.join("lib/libc.musl-x86_64.so.1")).unwrap();
+        fs::remove_dir_all(&staging).unwrap();
+        let state = ExecState::default();
+        let binary = find_binary(&source, "hello").unwrap();
+        assert_eq!(
+            missing_libraries(&source, &staging, &state, &binary).unwrap(),
+            ["libc.musl-x86_64.so.1"]
+        );
+
+        let err = install_binary(&source, &staging, "nope", "bin").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "not in usr/bin, bin, usr/sbin, sbin of the source rootfs"
+        );
+    }
+
+    #[test]
+    fn test_package_installs_its_files() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("rootfs");
+        let staging = dir.path().join("staging");
+        fs::create_dir_all(source.join("lib/apk/db")).unwrap();
+        fs::create_dir(&staging).unwrap();
+        fs::write(
+            source.join(crate::apk::INSTALLED_DB),
+            "P:chrony\nV:4.6-r0\nF:etc/chrony\nR:chrony.conf\n\
+             F:usr/sbin\nR:chronyd\nR:chronyc\n\n",
+        )
+        .unwrap();
+        fs::create_dir_all(source.join("etc/chrony")).unwrap();
+        fs::set_permissions(source.join("etc/chrony"), fs::Permissions::from_mode(0o750)).unwrap();
+        fs::write(source.join("etc/chrony/chrony.conf"), "pool pool.ntp.org\n").unwrap();
+        fs::create_dir_all(source.join("usr/sbin")).unwrap();
+        fs::write(source.join("usr/sbin/chronyd"), "#!/bin/sh\n").unwrap();
+        fs::set_permissions(
+            source.join("usr/sbin/chronyd"),
+            fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+        std::os::unix::fs::symlink("chronyd", source.join("usr/sbin/chronyc")).unwrap();
+
+        let actions = resolve_op(&source, &staging, &Op::Package("chrony"));
+        assert_eq!(actions[0].to_string(), "PACKAGE chrony (3 files)");
+        assert_eq!(actions.len(), 4);
+
+        let state = ExecState::default();
+        let mut missing = Vec::new();
+        install_package(&source, &staging, &state, "chrony", &mut missing).unwrap();
+        assert!(missing.is_empty(), "{:?}", missing);
+        let mode = |p: &str| fs::metadata(staging.join(p)).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode("etc/chrony"), 0o750);
+        assert_eq!(mode("usr/sbin/chronyd"), 0o755);
+        assert_eq!(
+            fs::read_link(staging.join("usr/sbin/chronyc")).unwrap(),
+            Path::new("chronyd")
+        );
+        assert_eq!(state.installed.borrow().len(), 2);
+
+        install_package(&source, &staging, &state, "openssh-server", &mut missing).unwrap();
+        assert_eq!(missing.len(), 1);
+        assert!(missing[0].contains("not installed"), "{}", missing[0]);
+    }
+
+    #[test]
+    fn test_conflicting_writes() {
+        use crate::component::{copy_tree, symlink, write_file, Phase};
+
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("rootfs");
+        let staging = dir.path().join("staging");
+        fs::create_dir_all(source.join("etc/conf")).unwrap();
+        fs::write(source.join("etc/conf/fstab"), "").unwrap();
+        const SYSCONFIG: Component = Component {
+            name: "sysconfig",
+            phase: Phase::Config,
+            requires: &[],
+            overrides: &[],
+            ops: &[write_file("etc/fstab", ""), write_file("etc/inittab", "")],
+        };
+        const LIVE: Component = Component {
+            name: "live",
+            phase: Phase::Final,
+            requires: &[],
+            overrides: &["etc/inittab"],
+            ops: &[write_file("etc/inittab", "autologin")],
+        };
+        const EXTRA: Component = Component {
+            name: "extra",
+            phase: Phase::Final,
+            requires: &[],
+            overrides: &["etc/inittab"],
+            ops: &[copy_tree("etc/conf"), symlink("etc/fstab", "conf/fstab")],
+        };
+
+        let state = ExecState::default();
+        for c in [&SYSCONFIG, &LIVE] {
+            state.record_writes(&source, &staging, c);
         }
+        assert!(state.conflicts(&[&SYSCONFIG, &LIVE]).is_empty());
+
+        state.record_writes(&source, &staging, &EXTRA);
+        assert_eq!(
+            state.conflicts(&[&SYSCONFIG, &LIVE, &EXTRA]),
+            ["sysconfig and extra both write etc/fstab"]
+        );
+        // Files copied from a tree count too
+        assert!(state
+            .writers
+            .borrow()
+            .contains_key(Path::new("etc/conf/fstab")));
     }
 
-    Ok(())
+    #[test]
+    fn test_components_do_not_conflict() {
+        let dir = tempdir().unwrap();
+        let state = ExecState::default();
+        for component in crate::component::ALL_COMPONENTS {
+            state.record_writes(dir.path(), &dir.path().join("staging"), component);
+        }
+        let conflicts = state.conflicts(crate::component::ALL_COMPONENTS);
+        assert!(conflicts.is_empty(), "{:?}", conflicts);
+    }
 }
@@ -0,0 +1,548 @@
+//! Firmware policy (`firmware.toml`, `--firmware`).
+//!
+//! linux-firmware is installed whole in the source rootfs, but only part of
+//! it is copied into staging. A preset picks the base set:
+//!
+//! - `wifi` (default): the WiFi firmware directories from distro-spec
+//! - `common`: typical laptops and desktops (Intel, Atheros and Realtek
+//!   WiFi/Bluetooth, recent amdgpu generations, i915 GuC/HuC/DMC, Realtek
+//!   NICs)
+//! - `all`: everything
+//!
+//! and a `firmware.toml` at the project root can adjust it:
+//!
+//! ```toml
+//! [firmware]
+//! preset = "common"
+//! include = ["mediatek/*", "brcm"]
+//! exclude = ["amdgpu/gc_12_*"]
+//! ```
+//!
+//! `acornos build --firmware <preset>` overrides the preset and is exported
+//! as `ACORNOS_FIRMWARE` so child builds see it too.
+//!
+//! Globs match paths relative to `lib/firmware`; `*` and `?` never match
+//! `/`, and a glob matching a directory selects everything under it. A
+//! selected symlink pulls in its target. An `include` or `exclude` glob that
+//! matches nothing fails the build; preset globs need not all match, since
+//! Alpine releases move files around.
+
+use anyhow::{bail, Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use distro_spec::shared::firmware::WIFI_FIRMWARE_DIRS;
+
+use super::BuildContext;
+use crate::clean::human_bytes;
+use crate::fast_copy::Copier;
+use crate::toml_subset::{self, Item, Line};
+
+/// Policy file in the project root.
+pub const FIRMWARE_FILE: &str = "firmware.toml";
+
+/// Preset chosen on the command line.
+pub const FIRMWARE_ENV: &str = "ACORNOS_FIRMWARE";
+
+/// Firmware tree, relative to a rootfs.
+const FIRMWARE_DIR: &str = "lib/firmware";
+
+/// The `common` preset: what laptops and desktops from the last years need.
+const COMMON_FIRMWARE: &[&str] = &[
+    // WiFi and Bluetooth
+    "iwlwifi-*",
+    "intel/ibt-*",
+    "ath9k_htc",
+    "ath10k",
+    "ath11k",
+    "ath12k",
+    "ath3k-*",
+    "qca",
+    "rtw88",
+    "rtw89",
+    "rtlwifi",
+    "rtl_bt",
+    "regulatory.db*",
+    // AMD GPUs from RDNA2 and Zen 2 APUs on
+    "amdgpu/renoir_*",
+    "amdgpu/green_sardine_*",
+    "amdgpu/sienna_cichlid_*",
+    "amdgpu/navy_flounder_*",
+    "amdgpu/dimgrey_cavefish_*",
+    "amdgpu/beige_goby_*",
+    "amdgpu/vangogh_*",
+    "amdgpu/yellow_carp_*",
+    "amdgpu/gc_10_3_*",
+    "amdgpu/gc_11_*",
+    "amdgpu/gc_12_*",
+    "amdgpu/psp_13_*",
+    "amdgpu/psp_14_*",
+    "amdgpu/sdma_5_2_*",
+    "amdgpu/sdma_6_*",
+    "amdgpu/sdma_7_*",
+    "amdgpu/smu_13_*",
+    "amdgpu/smu_14_*",
+    "amdgpu/dcn_3_*",
+    "amdgpu/dcn_4_*",
+    "amdgpu/vcn_3_*",
+    "amdgpu/vcn_4_*",
+    "amdgpu/vcn_5_*",
+    // Intel GPU microcontrollers
+    "i915/*_guc_*",
+    "i915/*_huc_*",
+    "i915/*_dmc_*",
+    // Ethernet
+    "rtl_nic",
+];
+
+/// Base set of firmware to copy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FirmwarePreset {
+    /// WiFi firmware only.
+    #[default]
+    Wifi,
+    /// Common laptop/desktop hardware.
+    Common,
+    /// All of linux-firmware.
+    All,
+}
+
+impl FirmwarePreset {
+    fn globs(self) -> &'static [&'static str] {
+        match self {
+            Self::Wifi => WIFI_FIRMWARE_DIRS,
+            Self::Common => COMMON_FIRMWARE,
+            Self::All => &["*"],
+        }
+    }
+}
+
+impl fmt::Display for FirmwarePreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Wifi => "wifi",
+            Self::Common => "common",
+            Self::All => "all",
+        })
+    }
+}
+
+impl FromStr for FirmwarePreset {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "wifi" => Ok(Self::Wifi),
+            "common" => Ok(Self::Common),
+            "all" => Ok(Self::All),
+            other => bail!(
+                "Unknown firmware preset '{}' (expected wifi, common or all)",
+                other
+            ),
+        }
+    }
+}
+
+/// Which firmware goes into the rootfs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FirmwarePolicy {
+    pub preset: FirmwarePreset,
+    /// Extra globs, on top of the preset.
+    pub include: Vec<String>,
+    /// Globs left out even if the preset or `include` selects them.
+    pub exclude: Vec<String>,
+}
+
+impl FirmwarePolicy {
+    /// Policy from `firmware.toml` in `base_dir`, with the preset
+    /// overridden by [`FIRMWARE_ENV`].
+    pub fn load(base_dir: &Path) -> Result<Self> {
+        let path = base_dir.join(FIRMWARE_FILE);
+        let mut policy = if path.exists() {
+            let text = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            Self::parse(&text).with_context(|| format!("Invalid {}", FIRMWARE_FILE))?
+        } else {
+            Self::default()
+        };
+        if let Ok(preset) = std::env::var(FIRMWARE_ENV) {
+            if !preset.is_empty() {
+                policy.preset = preset
+                    .parse()
+                    .with_context(|| format!("Invalid ${}", FIRMWARE_ENV))?;
+            }
+        }
+        Ok(policy)
+    }
+
+    /// Parse `firmware.toml`; errors name the line and key.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut policy = Self::default();
+        let mut in_section = false;
+        for Line { lineno, item } in toml_subset::parse(text)? {
+            let (key, value) = match item {
+                Item::Table(name) if name == "firmware" => {
+                    in_section = true;
+                    continue;
+                }
+                Item::Table(name) | Item::ArrayTable(name) => bail!(
+                    "line {}: unknown section [{}] (expected [firmware])",
+                    lineno,
+                    name
+                ),
+                Item::Entry { key, value } => (key, value),
+            };
+            if !in_section {
+                bail!("line {}: key `{}` outside of [firmware]", lineno, key);
+            }
+            match key.as_str() {
+                "preset" => {
+                    policy.preset = value
+                        .string()
+                        .and_then(|v| v.parse().ok())
+                        .with_context(|| {
+                            format!(
+                                "line {}: key `preset`: expected \"wifi\", \"common\" or \"all\", got {}",
+                                lineno,
+                                value.raw()
+                            )
+                        })?;
+                }
+                "include" | "exclude" => {
+                    let globs = value.strings().with_context(|| {
+                        format!(
+                            "line {}: key `{}`: expected a list of quoted globs",
+                            lineno, key
+                        )
+                    })?;
+                    if key == "include" {
+                        policy.include = globs;
+                    } else {
+                        policy.exclude = globs;
+                    }
+                }
+                other => bail!(
+                    "line {}: unknown key `{}` (expected preset, include or exclude)",
+                    lineno,
+                    other
+                ),
+            }
+        }
+        Ok(policy)
+    }
+
+    /// Recorded in the rootfs input hash; empty for the default policy.
+    pub fn fingerprint(&self) -> String {
+        if *self == Self::default() {
+            return String::new();
+        }
+        format!(
+            "firmware={}+{}-{}",
+            self.preset,
+            self.include.join(","),
+            self.exclude.join(",")
+        )
+    }
+
+    /// Files of `tree` to copy.
+    ///
+    /// Fails if an `include` or `exclude` glob matches nothing.
+    pub fn select(&self, tree: &FirmwareTree) -> Result<BTreeSet<String>> {
+        let unmatched: Vec<&str> = self
+            .include
+            .iter()
+            .chain(&self.exclude)
+            .map(String::as_str)
+            .filter(|glob| !tree.paths().any(|path| glob_matches(glob, path)))
+            .collect();
+        if !unmatched.is_empty() {
+            bail!(
+                "{} glob(s) match nothing in {}: {}",
+                FIRMWARE_FILE,
+                FIRMWARE_DIR,
+                unmatched.join(", ")
+            );
+        }
+
+        let included = |path: &str| {
+            self.preset
+                .globs()
+                .iter()
+                .copied()
+                .chain(self.include.iter().map(String::as_str))
+                .any(|glob| glob_matches(glob, path))
+                && !self.exclude.iter().any(|glob| glob_matches(glob, path))
+        };
+        let mut selected: BTreeSet<String> = tree
+            .paths()
+            .filter(|path| included(path))
+            .map(str::to_string)
+            .collect();
+
+        // A selected link is useless without its target
+        let mut pending: Vec<String> = selected.iter().cloned().collect();
+        while let Some(path) = pending.pop() {
+            let Some(target) = tree.links.get(&path) else {
+                continue;
+            };
+            if tree.contains(target) && selected.insert(target.clone()) {
+                pending.push(target.clone());
+            }
+        }
+        Ok(selected)
+    }
+}
+
+/// Regular files and symlinks under a `lib/firmware` tree.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FirmwareTree {
+    /// Regular files, relative path -> size.
+    pub files: BTreeMap<String, u64>,
+    /// Symlinks, relative path -> target (relative to the tree, if inside it).
+    pub links: BTreeMap<String, String>,
+}
+
+impl FirmwareTree {
+    /// Scan `root`; a missing `root` is an empty tree.
+    pub fn scan(root: &Path) -> Result<Self> {
+        let mut tree = Self::default();
+        if root.is_dir() {
+            tree.scan_dir(root, Path::new(""))?;
+        }
+        Ok(tree)
+    }
+
+    fn scan_dir(&mut self, root: &Path, rel: &Path) -> Result<()> {
+        let dir = root.join(rel);
+        for entry in
+            fs::read_dir(&dir).with_context(|| format!("Failed to read {}", dir.display()))?
+        {
+            let entry = entry?;
+            let rel = rel.join(entry.file_name());
+            let key = rel.to_string_lossy().into_owned();
+            let meta = fs::symlink_metadata(entry.path())?;
+            if meta.is_dir() {
+                self.scan_dir(root, &rel)?;
+            } else if meta.file_type().is_symlink() {
+                let target = fs::read_link(entry.path())?;
+                let target = link_target(&rel, &target).unwrap_or_default();
+                self.links.insert(key, target);
+            } else if meta.is_file() {
+                self.files.insert(key, meta.len());
+            }
+        }
+        Ok(())
+    }
+
+    fn paths(&self) -> impl Iterator<Item = &str> {
+        self.files
+            .keys()
+            .chain(self.links.keys())
+            .map(String::as_str)
+    }
+
+    fn contains(&self, path: &str) -> bool {
+        self.files.contains_key(path) || self.links.contains_key(path)
+    }
+
+    /// Total size of the regular files among `paths` (all if `None`).
+    fn size(&self, paths: Option<&BTreeSet<String>>) -> u64 {
+        self.files
+            .iter()
+            .filter(|(path, _)| paths.is_none_or(|p| p.contains(*path)))
+            .map(|(_, size)| size)
+            .sum()
+    }
+}
+
+/// Target of the link at `rel` (relative to the firmware tree) as a path in
+/// the tree, if it points inside it.
+fn link_target(rel: &Path, target: &Path) -> Option<String> {
+    let joined = match target.strip_prefix("/") {
+        Ok(abs) => abs.strip_prefix(FIRMWARE_DIR).ok()?.to_path_buf(),
+        Err(_) => rel.parent()?.join(target),
+    };
+    let mut path = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::Normal(name) => path.push(name),
+            Component::ParentDir if !path.pop() => return None,
+            _ => {}
+        }
+    }
+    Some(path.to_string_lossy().into_owned())
+}
+
+/// Whether `glob` matches `path` or one of its parent directories.
+pub fn glob_matches(glob: &str, path: &str) -> bool {
+    let glob: Vec<&str> = glob.trim_matches('/').split('/').collect();
+    let path: Vec<&str> = path.split('/').collect();
+    path.len() >= glob.len()
+        && glob
+            .iter()
+            .zip(&path)
+            .all(|(g, p)| segment_matches(g.as_bytes(), p.as_bytes()))
+}
+
+/// `*` and `?` wildcard match of one path segment.
+fn segment_matches(glob: &[u8], name: &[u8]) -> bool {
+    match (glob.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            segment_matches(&glob[1..], name)
+                || (!name.is_empty() && segment_matches(glob, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => segment_matches(&glob[1..], &name[1..]),
+        (Some(g), Some(n)) if g == n => segment_matches(&glob[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+/// Export `--firmware` for child builds.
+///
+/// Must be called before any threads are spawned.
+pub fn export(preset: Option<FirmwarePreset>) {
+    if let Some(preset) = preset {
+        std::env::set_var(FIRMWARE_ENV, preset.to_string());
+    }
+}
+
+/// Copy the firmware the policy selects into staging, reporting how much
+/// of linux-firmware that is.
+pub fn copy_firmware(ctx: &BuildContext) -> Result<()> {
+    let policy = FirmwarePolicy::load(&ctx.base_dir)?;
+    let src_root = ctx.source.join(FIRMWARE_DIR);
+    let dst_root = ctx.staging.join(FIRMWARE_DIR);
+    let tree = FirmwareTree::scan(&src_root)?;
+    let selected = policy.select(&tree)?;
+
+    let copier = Copier::for_rootfs();
+    for path in &selected {
+        let src = src_root.join(path);
+        let dst = dst_root.join(path);
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if tree.links.contains_key(path) {
+            if fs::symlink_metadata(&dst).is_ok() {
+                fs::remove_file(&dst)?;
+            }
+            std::os::unix::fs::symlink(fs::read_link(&src)?, &dst)?;
+        } else {
+            copier.copy(&src, &dst)?;
+        }
+    }
+
+    log::info!(
+        "  Firmware ({}): {} of {} ({} of {} files)",
+        policy.preset,
+        human_bytes(tree.size(Some(&selected))),
+        human_bytes(tree.size(None)),
+        selected.len(),
+        tree.files.len() + tree.links.len()
+    );
+    log::info!("  {}", copier.summary("Firmware copy"));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_firmware_policy_parse() {
+        assert!(glob_matches("iwlwifi-*", "iwlwifi-cc-a0-77.ucode.zst"));
+        assert!(glob_matches("rtl_nic", "rtl_nic/rtl8168h-2.fw"));
+        assert!(glob_matches("i915/*_guc_*", "i915/tgl_guc_70.bin"));
+        assert!(!glob_matches("i915/*_guc_*", "i915/tgl_dmc_ver2_12.bin"));
+        assert!(glob_matches("ath1?k", "ath11k/WCN6855/hw2.0/amss.bin"));
+        assert!(!glob_matches("ath1?k", "ath9k_htc/htc_9271-1.4.0.fw"));
+        assert!(!glob_matches("amdgpu/gc_11_*", "amdgpu"));
+
+        let policy = FirmwarePolicy::parse(
+            "# laptops\n[firmware]\npreset = \"common\"\n\
+             include = [\"mediatek/*\", \"brcm\"]\nexclude = []\n",
+        )
+        .unwrap();
+        assert_eq!(policy.preset, FirmwarePreset::Common);
+        assert_eq!(policy.include, ["mediatek/*", "brcm"]);
+        assert!(policy.exclude.is_empty());
+        assert_eq!(policy.fingerprint(), "firmware=common+mediatek/*,brcm-");
+        assert_eq!(FirmwarePolicy::default().fingerprint(), "");
+
+        let err = |text: &str| format!("{:#}", FirmwarePolicy::parse(text).unwrap_err());
+        assert!(err("preset = \"all\"\n").contains("outside of [firmware]"));
+        assert!(err("[firmware]\npreset = \"most\"\n").contains("line 2: key `preset`"));
+        assert!(err("[firmware]\ninclude = [iwlwifi]\n").contains("quoted globs"));
+        assert!(err("[firmware]\nexclude_all = true\n").contains("unknown key"));
+    }
+
+    #[test]
+    fn test_firmware_selection() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        for (path, size) in [
+            ("iwlwifi-so-a0-gf-a0-89.ucode", 1000),
+            ("amdgpu/gc_11_0_0_mes.bin", 300),
+            ("amdgpu/navi10_ce.bin", 200),
+            ("mellanox/mlxsw_spectrum-13.2000.1122.mfa2", 5000),
+            ("rtl_nic/rtl8168h-2.fw", 10),
+        ] {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, vec![0u8; size]).unwrap();
+        }
+        // Realtek links a renamed blob back to mellanox
+        std::os::unix::fs::symlink(
+            "../mellanox/mlxsw_spectrum-13.2000.1122.mfa2",
+            root.join("rtl_nic/rtl8125a-3.fw"),
+        )
+        .unwrap();
+
+        let tree = FirmwareTree::scan(root).unwrap();
+        assert_eq!(tree.size(None), 6510);
+        assert_eq!(
+            tree.links["rtl_nic/rtl8125a-3.fw"],
+            "mellanox/mlxsw_spectrum-13.2000.1122.mfa2"
+        );
+
+        let mut policy = FirmwarePolicy {
+            preset: FirmwarePreset::Common,
+            ..FirmwarePolicy::default()
+        };
+        let selected = policy.select(&tree).unwrap();
+        assert_eq!(
+            selected.iter().map(String::as_str).collect::<Vec<_>>(),
+            [
+                "amdgpu/gc_11_0_0_mes.bin",
+                "iwlwifi-so-a0-gf-a0-89.ucode",
+                "mellanox/mlxsw_spectrum-13.2000.1122.mfa2",
+                "rtl_nic/rtl8125a-3.fw",
+                "rtl_nic/rtl8168h-2.fw",
+            ]
+        );
+        assert_eq!(tree.size(Some(&selected)), 6310);
+
+        policy.include = vec!["amdgpu/navi*".into()];
+        policy.exclude = vec!["iwlwifi-*".into()];
+        let selected = policy.select(&tree).unwrap();
+        assert!(selected.contains("amdgpu/navi10_ce.bin"));
+        assert!(!selected.contains("iwlwifi-so-a0-gf-a0-89.ucode"));
+
+        policy.include = vec!["brcm".into(), "amdgpu/navi*".into()];
+        let err = policy.select(&tree).unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "firmware.toml glob(s) match nothing in lib/firmware: brcm"
+        );
+
+        assert_eq!(
+            FirmwareTree::scan(&root.join("missing")).unwrap(),
+            FirmwareTree::default()
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! The AcornOS /etc/inittab.
+//!
+//! [`INITTAB`] is the only inittab: the `sysconfig` component writes it to
+//! the rootfs as-is (login prompts, as an installed system needs), and the
+//! live overlay writes [`live_inittab`], the same file with autologin on
+//! tty1 and the serial console ([`release_live_inittab`] for a release
+//! ISO). Edit the getty lines here; the live boot picks them up.
+
+/// Installed-system inittab (standard login, no autologin).
+pub const INITTAB: &str = "# /etc/inittab - AcornOS\n\n\
+::sysinit:/sbin/openrc sysinit\n\
+::sysinit:/sbin/openrc boot\n\
+::wait:/sbin/openrc default\n\n\
+# Standard login on TTYs (the live overlay adds autologin)\n\
+tty1::respawn:/sbin/agetty --noclear tty1 linux\n\
+tty2::respawn:/sbin/agetty tty2 linux\n\
+tty3::respawn:/sbin/agetty tty3 linux\n\
+tty4::respawn:/sbin/agetty tty4 linux\n\
+tty5::respawn:/sbin/agetty tty5 linux\n\
+tty6::respawn:/sbin/agetty tty6 linux\n\n\
+# Serial console\n\
+ttyS0::respawn:/sbin/agetty -L 115200 ttyS0 vt100\n\n\
+::shutdown:/sbin/openrc shutdown\n\
+::ctrlaltdel:/sbin/reboot\n";
+
+/// Serial console the test harness logs in on.
+pub const SERIAL_TTY: &str = "ttyS0";
+
+/// Make the getty on `tty` autologin as `user` (replacing any previous
+/// autologin); other lines are left untouched.
+pub fn set_autologin(inittab: &str, tty: &str, user: &str) -> String {
+    let prefix = format!("{}::respawn:/sbin/agetty ", tty);
+    let mut out = String::new();
+    for line in inittab.lines() {
+        match line.strip_prefix(&prefix) {
+            Some(args) => {
+                out.push_str(&prefix);
+                out.push_str(&format!("--autologin {} ", user));
+                out.push_str(strip_autologin(args));
+            }
+            None => out.push_str(line),
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// agetty arguments without a leading `--autologin USER`.
+fn strip_autologin(args: &str) -> &str {
+    match args.strip_prefix("--autologin ") {
+        Some(rest) => rest.split_once(' ').map_or("", |(_, rest)| rest),
+        None => args,
+    }
+}
+
+/// The live session's inittab: [`INITTAB`] with autologin as `tty1_user`
+/// on tty1 and as root on the serial console.
+pub fn live_inittab(tty1_user: &str) -> String {
+    set_autologin(&release_live_inittab(tty1_user), SERIAL_TTY, "root")
+}
+
+/// The release live session's inittab: [`INITTAB`] with autologin as
+/// `tty1_user` on tty1 only; the serial console gets a login prompt.
+pub fn release_live_inittab(tty1_user: &str) -> String {
+    let inittab = INITTAB.replacen(
+        "# /etc/inittab - AcornOS\n",
+        "# /etc/inittab - AcornOS Live\n",
+        1,
+    );
+    set_autologin(&inittab, "tty1", tty1_user)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_live_inittab_only_adds_autologin() {
+        let live = live_inittab("root");
+        assert!(live.contains("tty1::respawn:/sbin/agetty --autologin root --noclear tty1 linux\n"));
+        assert!(
+            live.contains("ttyS0::respawn:/sbin/agetty --autologin root -L 115200 ttyS0 vt100\n")
+        );
+
+        // Without the autologin flags, the live inittab is the installed one
+        let installed: Vec<&str> = INITTAB.lines().skip(1).collect();
+        let unlogged: Vec<String> = live
+            .lines()
+            .skip(1)
+            .map(|l| l.replace("--autologin root ", ""))
+            .collect();
+        assert_eq!(unlogged, installed);
+        assert_eq!(live.lines().count(), INITTAB.lines().count());
+
+        // Switching the user replaces the autologin instead of stacking it
+        let switched = set_autologin(&live, "tty1", "live");
+        assert!(
+            switched.contains("tty1::respawn:/sbin/agetty --autologin live --noclear tty1 linux\n")
+        );
+        assert_eq!(switched.matches("--autologin").count(), 2);
+
+        // A release ISO logs in on the serial console
+        let release = release_live_inittab("live");
+        assert!(release.starts_with("# /etc/inittab - AcornOS Live\n"));
+        assert_eq!(release.matches("--autologin").count(), 1);
+        assert!(release.contains("ttyS0::respawn:/sbin/agetty -L 115200 ttyS0 vt100\n"));
+    }
+}
@@ -28,14 +28,23 @@
 //! | Shell | bash | ash (busybox) |
 //! | Library paths | /usr/lib64 (glibc) | /usr/lib (musl) |
 
+pub mod accounts;
 pub mod builder;
+pub mod checkpoint;
 pub mod custom;
 pub mod definitions;
 pub mod executor;
-
-pub use builder::build_system;
+pub mod firmware;
+pub mod inittab;
+pub mod plan;
+pub mod selection;
+pub mod service_graph;
+
+pub use builder::{build_system, ComponentHashes, COMPONENT_HASHES_FILE};
+pub use checkpoint::{Checkpoint, BUILD_STATE_DIR};
 pub use definitions::*;
 pub use distro_builder::alpine::context::BuildContext;
+pub use selection::ComponentSelection;
 
 // Re-export from distro-builder for convenience
 pub use distro_builder::component::{Installable, Phase};
@@ -50,6 +59,12 @@ pub struct Component {
     pub name: &'static str,
     /// Build phase (determines ordering).
     pub phase: Phase,
+    /// Components that must be enabled (and run first) for this one.
+    pub requires: &'static [&'static str],
+    /// Staging paths this component deliberately replaces after an earlier
+    /// component wrote them. Any other path written by two components fails
+    /// the build.
+    pub overrides: &'static [&'static str],
     /// Operations to perform.
     pub ops: &'static [Op],
 }
@@ -139,6 +154,12 @@ pub enum Op {
     /// Copy multiple binaries to /usr/sbin.
     Sbins(&'static [&'static str]),
 
+    /// Install every file an apk package owns in the source rootfs (per
+    /// `lib/apk/db/installed`), keeping paths and modes, plus the libraries
+    /// its ELF files need. Brings along the helpers and config a daemon
+    /// needs at runtime, which copying its binary misses.
+    Package(&'static str),
+
     // ─────────────────────────────────────────────────────────────────────
     // OpenRC operations (AcornOS-specific)
     // ─────────────────────────────────────────────────────────────────────
@@ -189,8 +210,10 @@ pub enum CustomOp {
     SetupDeviceManager,
     /// Copy kernel modules and run depmod.
     CopyModules,
-    /// Copy WiFi firmware directories.
-    CopyWifiFirmware,
+    /// Copy the firmware selected by the firmware policy.
+    CopyFirmware,
+    /// Write os-release with the version and build metadata.
+    WriteOsRelease,
     /// Create /etc configuration files.
     CreateEtcFiles,
     /// Create security configuration (login.defs, etc.).
@@ -203,13 +226,44 @@ pub enum CustomOp {
     CreateLiveOverlay,
     /// Copy recstrap installer tools.
     CopyRecstrap,
+    /// Generate the guided installer (`acorn-install`).
+    CreateInstaller,
     /// Copy all shared libraries from source rootfs.
-    /// Required because host glibc ldd can't analyze musl binaries.
+    /// Covers libraries only loaded at runtime (dlopen), which binaries'
+    /// ELF headers do not name.
     CopyAllLibraries,
-    /// Generate SSH host keys and configure sshd.
+    /// Configure and harden sshd (host keys are generated on first boot).
     SetupSsh,
+    /// Write apk repositories, keys, database and world for the installed system.
+    SetupApk,
     /// Install stage test scripts.
     InstallStageTests,
+    /// Write the testing live session's inittab (root autologin on tty1 and
+    /// the serial console).
+    WriteLiveInittab,
+    /// Write the release live session's inittab (login prompt on serial).
+    WriteReleaseLiveInittab,
+    /// Drop the test instrumentation and the empty-password shadow.
+    StripTestInstrumentation,
+    /// Install `acorn-make-persistence`.
+    InstallMakePersistence,
+    /// Create the live user, seeded from the rootfs staging's accounts.
+    CreateLiveUser,
+}
+
+impl CustomOp {
+    /// Whether the op reads inputs outside the Alpine rootfs (kernel build,
+    /// `profile/`, local tool builds), which staging hashes do not track.
+    pub fn reads_outside_rootfs(self) -> bool {
+        matches!(
+            self,
+            Self::CopyModules
+                | Self::CreateWelcomeMessage
+                | Self::CreateLiveOverlay
+                | Self::CopyRecstrap
+                | Self::InstallStageTests
+        )
+    }
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
@@ -276,6 +330,11 @@ pub const fn sbins(names: &'static [&'static str]) -> Op {
     Op::Sbins(names)
 }
 
+/// Install an apk package's files.
+pub const fn package(name: &'static str) -> Op {
+    Op::Package(name)
+}
+
 /// Enable an OpenRC service in a runlevel.
 pub const fn openrc_enable(service: &'static str, runlevel: &'static str) -> Op {
     Op::OpenrcEnable(service, runlevel)
@@ -334,6 +393,7 @@ mod tests {
             openrc_enable("networking", "boot"),
             Op::OpenrcEnable("networking", "boot")
         ));
+        assert!(matches!(package("chrony"), Op::Package("chrony")));
     }
 
     #[test]
@@ -0,0 +1,272 @@
+//! Dry run of the component executor (`acornos plan`).
+//!
+//! Resolves every op of the enabled components against the source rootfs
+//! without writing anything, so a component change can be reviewed without
+//! building the rootfs and diffing staging. Each op becomes one or more
+//! manifest lines:
+//!
+//! ```text
+//! [ssh] COPY /src/usr/sbin/sshd -> /staging/usr/sbin/sshd
+//! [ssh] ENABLE sshd in default
+//! [branding] CUSTOM CreateEtcFiles (not simulated)
+//! ```
+//!
+//! A missing source file is recorded as a would-be failure and planning
+//! continues, so one run reports every problem.
+
+use std::path::Path;
+
+use super::executor::{resolve_op, Action};
+use super::Component;
+use crate::manifest::json_str;
+
+/// One planned action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanEntry {
+    pub component: &'static str,
+    pub action: Action,
+    /// Why the executor would fail here.
+    pub failure: Option<String>,
+    /// Something the executor would skip with a warning.
+    pub warning: Option<String>,
+}
+
+/// Everything the executor would do, in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Plan {
+    pub entries: Vec<PlanEntry>,
+}
+
+impl Plan {
+    /// Plan `components` (in run order) from `source` into `staging`.
+    pub fn build(source: &Path, staging: &Path, components: &[&Component]) -> Self {
+        let mut entries = Vec::new();
+        for component in components {
+            for op in component.ops {
+                for action in resolve_op(source, staging, op) {
+                    let (failure, warning) = match &action {
+                        Action::Copy { src, .. } if src.symlink_metadata().is_err() => {
+                            (Some(format!("source missing: {}", src.display())), None)
+                        }
+                        Action::Package { name, files: 0 } => (
+                            Some(format!(
+                                "package {} not installed in the source rootfs",
+                                name
+                            )),
+                            None,
+                        ),
+                        Action::CopyTree { src, .. } if !src.exists() => (
+                            None,
+                            Some(format!("source missing, skipped: {}", src.display())),
+                        ),
+                        _ => (None, None),
+                    };
+                    entries.push(PlanEntry {
+                        component: component.name,
+                        action,
+                        failure,
+                        warning,
+                    });
+                }
+            }
+        }
+        Self { entries }
+    }
+
+    /// Entries the executor would fail on.
+    pub fn failures(&self) -> impl Iterator<Item = &PlanEntry> {
+        self.entries.iter().filter(|e| e.failure.is_some())
+    }
+
+    /// One line per action, failures and warnings marked.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for entry in &self.entries {
+            out.push_str(&format!("[{}] {}", entry.component, entry.action));
+            if let Some(failure) = &entry.failure {
+                out.push_str(&format!("  [FAIL] {}", failure));
+            }
+            if let Some(warning) = &entry.warning {
+                out.push_str(&format!("  [WARN] {}", warning));
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn to_json(&self) -> String {
+        let opt = |v: &Option<String>| v.as_deref().map(json_str).unwrap_or("null".into());
+        let rows: Vec<String> = self
+            .entries
+            .iter()
+            .map(|e| {
+                let mut fields = vec![
+                    format!("\"component\": {}", json_str(e.component)),
+                    format!("\"op\": {}", json_str(e.action.kind())),
+                ];
+                fields.extend(
+                    action_fields(&e.action)
+                        .into_iter()
+                        .map(|(key, value)| format!("{}: {}", json_str(key), value)),
+                );
+                fields.push(format!("\"failure\": {}", opt(&e.failure)));
+                fields.push(format!("\"warning\": {}", opt(&e.warning)));
+                format!("    {{ {} }}", fields.join(", "))
+            })
+            .collect();
+        format!(
+            "{{\n  \"failures\": {},\n  \"operations\": [\n{}\n  ]\n}}\n",
+            self.failures().count(),
+            rows.join(",\n")
+        )
+    }
+}
+
+/// The action's own fields as JSON values.
+fn action_fields(action: &Action) -> Vec<(&'static str, String)> {
+    let path = |p: &Path| json_str(&p.display().to_string());
+    let mode = |m: &Option<u32>| {
+        m.map(|m| json_str(&format!("{:o}", m)))
+            .unwrap_or("null".into())
+    };
+    match action {
+        Action::CreateDir { path: p, mode: m } => vec![("path", path(p)), ("mode", mode(m))],
+        Action::WriteFile {
+            path: p,
+            bytes,
+            mode: m,
+        } => vec![
+            ("path", path(p)),
+            ("bytes", bytes.to_string()),
+            ("mode", mode(m)),
+        ],
+        Action::Copy { src, dst } | Action::CopyTree { src, dst } => {
+            vec![("src", path(src)), ("dst", path(dst))]
+        }
+        Action::Symlink { link, target } => {
+            vec![("path", path(link)), ("target", json_str(target))]
+        }
+        Action::Package { name, files } => {
+            vec![("name", json_str(name)), ("files", files.to_string())]
+        }
+        Action::Enable { service, runlevel } => vec![
+            ("service", json_str(service)),
+            ("runlevel", json_str(runlevel)),
+        ],
+        Action::User { name, uid, gid } => vec![
+            ("name", json_str(name)),
+            ("uid", uid.to_string()),
+            ("gid", gid.to_string()),
+        ],
+        Action::Group { name, gid } => vec![("name", json_str(name)), ("gid", gid.to_string())],
+        Action::Custom(op) => vec![("name", json_str(&format!("{:?}", op)))],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::{
+        bins, copy_file, copy_tree, custom, dir_mode, openrc_enable, openrc_scripts, write_file,
+        CustomOp, Phase,
+    };
+    use std::fs;
+    use tempfile::tempdir;
+
+    const SERVICE: Component = Component {
+        name: "service",
+        phase: Phase::Services,
+        requires: &[],
+        overrides: &[],
+        ops: &[
+            dir_mode("var/lib/svc", 0o700),
+            bins(&["svcd", "svcctl"]),
+            copy_file("etc/svc.conf"),
+            copy_tree("etc/svc.d"),
+            openrc_scripts(&["svcd"]),
+            openrc_enable("svcd", "default"),
+        ],
+    };
+
+    const CONFIG: Component = Component {
+        name: "config",
+        phase: Phase::Config,
+        requires: &[],
+        overrides: &[],
+        ops: &[
+            write_file("etc/motd", "hello\n"),
+            custom(CustomOp::CreateEtcFiles),
+        ],
+    };
+
+    #[test]
+    fn test_plan_reports_every_missing_source() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("rootfs");
+        let staging = dir.path().join("staging");
+        for file in ["usr/sbin/svcd", "etc/init.d/svcd"] {
+            let path = source.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        let plan = Plan::build(&source, &staging, &[&SERVICE, &CONFIG]);
+        let kinds: Vec<&str> = plan.entries.iter().map(|e| e.action.kind()).collect();
+        assert_eq!(
+            kinds,
+            [
+                "CREATE_DIR",
+                "COPY",
+                "COPY",
+                "COPY",
+                "COPY_TREE",
+                "COPY",
+                "ENABLE",
+                "WRITE_FILE",
+                "CUSTOM"
+            ]
+        );
+        // Nothing was written
+        assert!(!staging.exists());
+
+        // Binaries are found in any bin dir; both missing sources are reported
+        assert_eq!(
+            plan.entries[1].action,
+            Action::Copy {
+                src: source.join("usr/sbin/svcd"),
+                dst: staging.join("usr/bin/svcd"),
+            }
+        );
+        let failures: Vec<&str> = plan
+            .failures()
+            .map(|e| e.failure.as_deref().unwrap())
+            .collect();
+        assert_eq!(failures.len(), 2);
+        assert!(failures[0].ends_with("usr/bin/svcctl"));
+        assert!(failures[1].ends_with("etc/svc.conf"));
+        // A missing tree is skipped by the executor, not a failure
+        assert!(plan.entries[4].warning.is_some());
+
+        let text = plan.to_text();
+        assert!(text.contains(&format!(
+            "[service] CREATE_DIR {} (mode 700)\n",
+            staging.join("var/lib/svc").display()
+        )));
+        assert!(text.contains("[service] ENABLE svcd in default\n"));
+        assert!(text.contains(&format!(
+            "[config] WRITE_FILE {} 6 bytes\n",
+            staging.join("etc/motd").display()
+        )));
+        assert!(text.contains("[config] CUSTOM CreateEtcFiles (not simulated)\n"));
+        assert!(text.contains("  [FAIL] source missing: "));
+
+        let json = plan.to_json();
+        assert!(json.starts_with("{\n  \"failures\": 2,\n"));
+        assert!(json.contains(
+            "{ \"component\": \"service\", \"op\": \"ENABLE\", \"service\": \"svcd\", \
+             \"runlevel\": \"default\", \"failure\": null, \"warning\": null }"
+        ));
+        assert!(json.contains("\"op\": \"WRITE_FILE\""));
+        assert!(json.contains("\"bytes\": 6, \"mode\": null"));
+    }
+}
@@ -0,0 +1,323 @@
+//! Component selection (`components.toml`, `--disable-component`).
+//!
+//! Non-core components can be left out of the rootfs, either in a
+//! `components.toml` at the project root:
+//!
+//! ```toml
+//! [components]
+//! ssh = false
+//! chrony = false
+//! ```
+//!
+//! or with `acornos build --disable-component ssh` (repeatable), which is
+//! exported as `ACORNOS_DISABLE_COMPONENTS` so child builds see it too.
+//!
+//! Before anything runs, the dependency graph (`Component::requires`) is
+//! checked: unknown dependencies and cycles fail, and so does disabling a
+//! component that an enabled component requires.
+
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use super::definitions::ALL_COMPONENTS;
+use super::Component;
+use crate::toml_subset::{self, Item, Line};
+
+/// Selection file in the project root.
+pub const COMPONENTS_FILE: &str = "components.toml";
+
+/// Comma-separated components disabled on the command line.
+pub const DISABLE_ENV: &str = "ACORNOS_DISABLE_COMPONENTS";
+
+/// Components every AcornOS rootfs needs; these cannot be disabled.
+pub const CORE_COMPONENTS: &[&str] = &[
+    "filesystem",
+    "busybox",
+    "utilities",
+    "openrc",
+    "modules",
+    "branding",
+    "sysconfig",
+    "apk",
+    "live-final",
+];
+
+/// Components turned off for this build.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ComponentSelection {
+    pub disabled: BTreeSet<String>,
+}
+
+impl ComponentSelection {
+    /// Selection from `components.toml` in `base_dir` and [`DISABLE_ENV`].
+    pub fn load(base_dir: &Path) -> Result<Self> {
+        let path = base_dir.join(COMPONENTS_FILE);
+        let mut selection = if path.exists() {
+            let text = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            Self::parse(&text).with_context(|| format!("Invalid {}", COMPONENTS_FILE))?
+        } else {
+            Self::default()
+        };
+        if let Ok(names) = std::env::var(DISABLE_ENV) {
+            selection.disable(names.split(',').map(str::trim).filter(|n| !n.is_empty()));
+        }
+        Ok(selection)
+    }
+
+    /// Parse `components.toml`; errors name the line and key.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut selection = Self::default();
+        let mut in_section = false;
+        for Line { lineno, item } in toml_subset::parse(text)? {
+            let (key, value) = match item {
+                Item::Table(name) if name == "components" => {
+                    in_section = true;
+                    continue;
+                }
+                Item::Table(name) | Item::ArrayTable(name) => bail!(
+                    "line {}: unknown section [{}] (expected [components])",
+                    lineno,
+                    name
+                ),
+                Item::Entry { key, value } => (key, value),
+            };
+            if !in_section {
+                bail!("line {}: key `{}` outside of [components]", lineno, key);
+            }
+            match value.bool() {
+                Some(true) => {}
+                Some(false) => selection.disable([key.as_str()]),
+                None => bail!(
+                    "line {}: key `{}`: expected true or false, got {}",
+                    lineno,
+                    key,
+                    value.raw()
+                ),
+            }
+        }
+        Ok(selection)
+    }
+
+    /// Add components to the disabled set.
+    pub fn disable<'a>(&mut self, names: impl IntoIterator<Item = &'a str>) {
+        self.disabled
+            .extend(names.into_iter().map(|n| n.to_string()));
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        !self.disabled.contains(name)
+    }
+
+    /// Recorded in the rootfs input hash; empty when nothing is disabled.
+    pub fn fingerprint(&self) -> String {
+        if self.disabled.is_empty() {
+            return String::new();
+        }
+        let names: Vec<&str> = self.disabled.iter().map(String::as_str).collect();
+        format!("disabled={}", names.join(","))
+    }
+
+    /// Enabled components of `all`, in dependency order.
+    ///
+    /// Ties keep list order, so a list that already respects `requires`
+    /// comes back unchanged.
+    pub fn resolve<'c>(&self, all: &[&'c Component]) -> Result<Vec<&'c Component>> {
+        for name in &self.disabled {
+            if !all.iter().any(|c| c.name == name) {
+                let known: Vec<&str> = all.iter().map(|c| c.name).collect();
+                bail!("Unknown component '{}' (known: {})", name, known.join(", "));
+            }
+            if CORE_COMPONENTS.contains(&name.as_str()) {
+                bail!("Component '{}' is core and cannot be disabled", name);
+            }
+        }
+
+        let order = dependency_order(all)?;
+        for component in all.iter().filter(|c| self.is_enabled(c.name)) {
+            if let Some(dep) = component.requires.iter().find(|d| !self.is_enabled(d)) {
+                bail!(
+                    "Cannot disable component '{}': required by '{}'",
+                    dep,
+                    component.name
+                );
+            }
+        }
+        Ok(order
+            .into_iter()
+            .map(|i| all[i])
+            .filter(|c| self.is_enabled(c.name))
+            .collect())
+    }
+}
+
+/// Export `--disable-component` values for the recipes and child builds.
+///
+/// Must be called before any threads are spawned.
+pub fn export(names: &[String]) {
+    if !names.is_empty() {
+        std::env::set_var(DISABLE_ENV, names.join(","));
+    }
+}
+
+/// Indices of `all` in dependency order, or an error naming a missing
+/// dependency or a cycle.
+pub fn dependency_order(all: &[&Component]) -> Result<Vec<usize>> {
+    let index = |name: &str| all.iter().position(|c| c.name == name);
+    let mut deps: Vec<Vec<usize>> = Vec::with_capacity(all.len());
+    for component in all {
+        let mut resolved = Vec::new();
+        for dep in component.requires {
+            resolved.push(index(dep).with_context(|| {
+                format!(
+                    "Component '{}' requires unknown component '{}'",
+                    component.name, dep
+                )
+            })?);
+        }
+        deps.push(resolved);
+    }
+
+    // Depth-first, visiting components (and their dependencies) in list order
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        New,
+        Active,
+        Done,
+    }
+    fn visit(
+        i: usize,
+        deps: &[Vec<usize>],
+        marks: &mut [Mark],
+        stack: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+    ) -> std::result::Result<(), Vec<usize>> {
+        match marks[i] {
+            Mark::Done => return Ok(()),
+            Mark::Active => {
+                let start = stack.iter().position(|&s| s == i).unwrap_or(0);
+                let mut cycle = stack[start..].to_vec();
+                cycle.push(i);
+                return Err(cycle);
+            }
+            Mark::New => {}
+        }
+        marks[i] = Mark::Active;
+        stack.push(i);
+        for &dep in &deps[i] {
+            visit(dep, deps, marks, stack, order)?;
+        }
+        stack.pop();
+        marks[i] = Mark::Done;
+        order.push(i);
+        Ok(())
+    }
+
+    let mut marks = vec![Mark::New; all.len()];
+    let mut order = Vec::with_capacity(all.len());
+    for i in 0..all.len() {
+        if let Err(cycle) = visit(i, &deps, &mut marks, &mut Vec::new(), &mut order) {
+            let names: Vec<&str> = cycle.iter().map(|&c| all[c].name).collect();
+            bail!("Component dependency cycle: {}", names.join(" -> "));
+        }
+    }
+    Ok(order)
+}
+
+/// Whether the staging tree built from `previous` components can be reused
+/// with `enabled`: it cannot if it holds a component that is now disabled.
+pub fn staging_reusable<'a>(
+    previous: impl IntoIterator<Item = &'a str>,
+    enabled: &[&Component],
+) -> bool {
+    previous
+        .into_iter()
+        .all(|name| enabled.iter().any(|c| c.name == name))
+}
+
+/// Resolve [`ALL_COMPONENTS`] for the selection in `base_dir`.
+pub fn selected_components(base_dir: &Path) -> Result<Vec<&'static Component>> {
+    ComponentSelection::load(base_dir)?.resolve(ALL_COMPONENTS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::{Op, Phase};
+
+    fn component(name: &'static str, requires: &'static [&'static str]) -> Component {
+        Component {
+            name,
+            phase: Phase::Services,
+            requires,
+            overrides: &[],
+            ops: &[] as &[Op],
+        }
+    }
+
+    #[test]
+    fn test_dependency_cycle_and_missing() {
+        let a = component("a", &["c"]);
+        let b = component("b", &["a"]);
+        let c = component("c", &["b"]);
+        let err = dependency_order(&[&a, &b, &c]).unwrap_err().to_string();
+        assert_eq!(err, "Component dependency cycle: a -> c -> b -> a");
+
+        let d = component("d", &["nope"]);
+        let err = dependency_order(&[&d]).unwrap_err().to_string();
+        assert!(err.contains("'d' requires unknown component 'nope'"));
+
+        // Dependencies listed later still come first
+        let x = component("x", &["y"]);
+        let y = component("y", &[]);
+        assert_eq!(dependency_order(&[&x, &y]).unwrap(), [1, 0]);
+    }
+
+    #[test]
+    fn test_real_components_resolve_in_list_order() {
+        let resolved = ComponentSelection::default()
+            .resolve(ALL_COMPONENTS)
+            .unwrap();
+        let names: Vec<&str> = resolved.iter().map(|c| c.name).collect();
+        let listed: Vec<&str> = ALL_COMPONENTS.iter().map(|c| c.name).collect();
+        assert_eq!(names, listed);
+        for core in CORE_COMPONENTS {
+            assert!(listed.contains(core), "unknown core component {}", core);
+        }
+    }
+
+    #[test]
+    fn test_disable_ssh_removes_it_from_staging() {
+        let selection = ComponentSelection::parse("[components]\nssh = false # no sshd\n").unwrap();
+        let enabled = selection.resolve(ALL_COMPONENTS).unwrap();
+        assert!(!enabled.iter().any(|c| c.name == "ssh"));
+        assert_eq!(selection.fingerprint(), "disabled=ssh");
+
+        // A staging tree that still holds ssh is rebuilt from scratch
+        assert!(!staging_reusable(["filesystem", "ssh"], &enabled));
+        assert!(staging_reusable(["filesystem"], &enabled));
+
+        let err = |names: &[&str]| {
+            let mut selection = ComponentSelection::default();
+            selection.disable(names.iter().copied());
+            selection.resolve(ALL_COMPONENTS).unwrap_err().to_string()
+        };
+        assert!(err(&["openrc"]).contains("core"));
+        assert!(err(&["sshd"]).contains("Unknown component 'sshd'"));
+
+        let a = component("a", &[]);
+        let b = component("b", &["a"]);
+        let mut selection = ComponentSelection::default();
+        selection.disable(["a"]);
+        let err = selection.resolve(&[&a, &b]).unwrap_err().to_string();
+        assert_eq!(err, "Cannot disable component 'a': required by 'b'");
+
+        assert!(ComponentSelection::parse("ssh = false\n").is_err());
+        assert!(ComponentSelection::parse("[components]\nssh = no\n")
+            .unwrap_err()
+            .to_string()
+            .contains("line 2: key `ssh`"));
+    }
+}
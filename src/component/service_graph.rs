@@ -0,0 +1,507 @@
+//! OpenRC service graph of the staged rootfs (`acornos graph services`).
+//!
+//! Reads `etc/init.d/*` from the staging tree (or any rootfs), parses each
+//! script's `depend()` block, and combines it with the runlevel links in
+//! `etc/runlevels/` and the `OpenrcEnable` ops of the enabled components.
+//! The result is the start order per runlevel, as a Graphviz DOT file or an
+//! ASCII tree, without booting anything.
+//!
+//! Ordering edges: `need`, `use`/`want` and `after` start the dependency
+//! first; `before` starts the service first. A dependency on a virtual
+//! service (`need dev`) resolves to every script that `provide`s it.
+//!
+//! Anomalies flagged:
+//! - an enabled service whose `need` is enabled in no runlevel
+//! - a service enabled without an init script
+//! - two services each ordered before the other (`before`/`after` cycle)
+//! - an init script enabled in no runlevel
+
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use super::{Component, Op};
+
+/// Runlevels in the order OpenRC enters them at boot.
+pub const RUNLEVEL_ORDER: &[&str] = &["sysinit", "boot", "default", "nonetwork", "shutdown"];
+
+/// Dependencies declared in an init script's `depend()`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Depend {
+    pub need: Vec<String>,
+    /// `use` and `want`.
+    pub uses: Vec<String>,
+    pub after: Vec<String>,
+    pub before: Vec<String>,
+    pub provide: Vec<String>,
+}
+
+/// Parse the `depend()` block of an init script. Variables, `-keyword`
+/// flags and comments are skipped.
+pub fn parse_depend(script: &str) -> Depend {
+    let mut deps = Depend::default();
+    let Some(start) = script.find("depend()") else {
+        return deps;
+    };
+    let rest = &script[start + "depend()".len()..];
+    let Some(open) = rest.find('{') else {
+        return deps;
+    };
+    let mut depth = 0;
+    let mut end = rest.len();
+    for (i, c) in rest[open..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    end = open + i;
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let body = rest[open + 1..end].replace("\\\n", " ");
+    for statement in body.split(['\n', ';']) {
+        let statement = statement.split('#').next().unwrap_or_default();
+        let mut words = statement.split_whitespace();
+        let list = match words.next() {
+            Some("need") => &mut deps.need,
+            Some("use" | "want") => &mut deps.uses,
+            Some("after") => &mut deps.after,
+            Some("before") => &mut deps.before,
+            Some("provide") => &mut deps.provide,
+            _ => continue,
+        };
+        list.extend(
+            words
+                .filter(|w| !w.starts_with('$') && !w.starts_with('-'))
+                .map(String::from),
+        );
+    }
+    deps
+}
+
+/// Kind of an ordering edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EdgeKind {
+    Need,
+    /// `use`, `want` or `after`.
+    After,
+    Before,
+}
+
+/// A problem in the shipped service graph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Anomaly {
+    /// `service` in `runlevel` needs `need`, which no runlevel enables.
+    UnmetNeed {
+        service: String,
+        runlevel: String,
+        need: String,
+    },
+    /// Enabled in `runlevel` without an `etc/init.d` script.
+    NoScript { service: String, runlevel: String },
+    /// `a` and `b` are each ordered before the other.
+    Cycle { a: String, b: String },
+    /// In `etc/init.d` but enabled in no runlevel.
+    NeverEnabled { service: String },
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnmetNeed {
+                service,
+                runlevel,
+                need,
+            } => write!(
+                f,
+                "{} ({}) needs '{}', which is not enabled in any runlevel",
+                service, runlevel, need
+            ),
+            Self::NoScript { service, runlevel } => write!(
+                f,
+                "{} is enabled in {} but has no etc/init.d script",
+                service, runlevel
+            ),
+            Self::Cycle { a, b } => write!(
+                f,
+                "{} and {} are each ordered before the other (before/after cycle)",
+                a, b
+            ),
+            Self::NeverEnabled { service } => {
+                write!(f, "{} is in etc/init.d but enabled in no runlevel", service)
+            }
+        }
+    }
+}
+
+/// Init scripts and runlevel enablements of a rootfs.
+#[derive(Debug, Clone, Default)]
+pub struct ServiceGraph {
+    /// Every `etc/init.d` script and its dependencies.
+    pub services: BTreeMap<String, Depend>,
+    /// Runlevel -> enabled services.
+    pub runlevels: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl ServiceGraph {
+    /// Graph of the rootfs at `root`, plus the `OpenrcEnable` ops of
+    /// `components` (which may not have been staged yet).
+    pub fn load(root: &Path, components: &[&Component]) -> Result<Self> {
+        let mut graph = Self::default();
+
+        let init_d = root.join("etc/init.d");
+        for entry in
+            fs::read_dir(&init_d).with_context(|| format!("Failed to read {}", init_d.display()))?
+        {
+            let path = entry?.path();
+            if !path.is_file() {
+                continue;
+            }
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let script = String::from_utf8_lossy(&fs::read(&path)?).into_owned();
+            graph
+                .services
+                .insert(name.into_owned(), parse_depend(&script));
+        }
+
+        if let Ok(levels) = fs::read_dir(root.join("etc/runlevels")) {
+            for level in levels.flatten() {
+                let runlevel = level.file_name().to_string_lossy().into_owned();
+                let enabled = graph.runlevels.entry(runlevel).or_default();
+                for link in fs::read_dir(level.path())?.flatten() {
+                    enabled.insert(link.file_name().to_string_lossy().into_owned());
+                }
+            }
+        }
+
+        for op in components.iter().flat_map(|c| c.ops) {
+            if let Op::OpenrcEnable(service, runlevel) = op {
+                graph.enable(service, runlevel);
+            }
+        }
+        Ok(graph)
+    }
+
+    /// Enable `service` in `runlevel`.
+    pub fn enable(&mut self, service: &str, runlevel: &str) {
+        self.runlevels
+            .entry(runlevel.to_string())
+            .or_default()
+            .insert(service.to_string());
+    }
+
+    /// Runlevels in boot order; unknown ones last, by name.
+    pub fn runlevel_order(&self) -> Vec<&str> {
+        let mut order: Vec<&str> = RUNLEVEL_ORDER
+            .iter()
+            .copied()
+            .filter(|r| self.runlevels.contains_key(*r))
+            .collect();
+        order.extend(
+            self.runlevels
+                .keys()
+                .map(String::as_str)
+                .filter(|r| !RUNLEVEL_ORDER.contains(r)),
+        );
+        order
+    }
+
+    /// Scripts that are, or `provide`, `name`.
+    fn providers<'a>(&'a self, name: &'a str) -> Vec<&'a str> {
+        self.services
+            .iter()
+            .filter(|(s, d)| *s == name || d.provide.iter().any(|p| p == name))
+            .map(|(s, _)| s.as_str())
+            .collect()
+    }
+
+    fn is_enabled(&self, service: &str) -> bool {
+        self.runlevels.values().any(|s| s.contains(service))
+    }
+
+    /// Ordering edges `(first, then, kind)` between all scripts.
+    pub fn edges(&self) -> BTreeSet<(String, String, EdgeKind)> {
+        let mut edges = BTreeSet::new();
+        for (service, deps) in &self.services {
+            let lists = [
+                (&deps.need, EdgeKind::Need),
+                (&deps.uses, EdgeKind::After),
+                (&deps.after, EdgeKind::After),
+                (&deps.before, EdgeKind::Before),
+            ];
+            for (names, kind) in lists {
+                // `*` (before/after everything) orders nothing specific
+                for name in names.iter().filter(|n| *n != "*") {
+                    for provider in self.providers(name) {
+                        if provider == service {
+                            continue;
+                        }
+                        let (first, then) = match kind {
+                            EdgeKind::Before => (service.as_str(), provider),
+                            _ => (provider, service.as_str()),
+                        };
+                        edges.insert((first.to_string(), then.to_string(), kind));
+                    }
+                }
+            }
+        }
+        edges
+    }
+
+    /// Start order of `runlevel`. Services caught in a cycle come last.
+    pub fn order(&self, runlevel: &str) -> Vec<String> {
+        let Some(enabled) = self.runlevels.get(runlevel) else {
+            return Vec::new();
+        };
+        let edges: BTreeSet<(String, String)> = self
+            .edges()
+            .into_iter()
+            .filter(|(a, b, _)| enabled.contains(a) && enabled.contains(b))
+            .map(|(a, b, _)| (a, b))
+            .collect();
+
+        let mut pending: BTreeSet<&String> = enabled.iter().collect();
+        let mut order = Vec::new();
+        while !pending.is_empty() {
+            let ready = pending
+                .iter()
+                .copied()
+                .find(|s| !edges.iter().any(|(a, b)| b == *s && pending.contains(a)))
+                .or_else(|| pending.first().copied())
+                .expect("pending is not empty");
+            pending.remove(ready);
+            order.push(ready.clone());
+        }
+        order
+    }
+
+    /// Everything suspicious, in a stable order.
+    pub fn anomalies(&self) -> Vec<Anomaly> {
+        let mut anomalies = Vec::new();
+        for runlevel in self.runlevel_order() {
+            for service in &self.runlevels[runlevel] {
+                let Some(deps) = self.services.get(service) else {
+                    anomalies.push(Anomaly::NoScript {
+                        service: service.clone(),
+                        runlevel: runlevel.to_string(),
+                    });
+                    continue;
+                };
+                for need in &deps.need {
+                    if !self.providers(need).iter().any(|p| self.is_enabled(p)) {
+                        anomalies.push(Anomaly::UnmetNeed {
+                            service: service.clone(),
+                            runlevel: runlevel.to_string(),
+                            need: need.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        let ordered: BTreeSet<(String, String)> =
+            self.edges().into_iter().map(|(a, b, _)| (a, b)).collect();
+        for (a, b) in &ordered {
+            if a < b && ordered.contains(&(b.clone(), a.clone())) {
+                anomalies.push(Anomaly::Cycle {
+                    a: a.clone(),
+                    b: b.clone(),
+                });
+            }
+        }
+
+        for service in self.services.keys() {
+            if !self.is_enabled(service) {
+                anomalies.push(Anomaly::NeverEnabled {
+                    service: service.clone(),
+                });
+            }
+        }
+        anomalies
+    }
+
+    /// Graphviz DOT: one cluster per runlevel, enabled services only.
+    /// `need` edges are solid, `use`/`after` dashed, `before` dotted;
+    /// services with an anomaly are red.
+    pub fn to_dot(&self) -> String {
+        let mut flagged = BTreeSet::new();
+        for anomaly in self.anomalies() {
+            match anomaly {
+                Anomaly::UnmetNeed { service, .. } | Anomaly::NoScript { service, .. } => {
+                    flagged.insert(service);
+                }
+                Anomaly::Cycle { a, b } => {
+                    flagged.insert(a);
+                    flagged.insert(b);
+                }
+                Anomaly::NeverEnabled { .. } => {}
+            }
+        }
+
+        let mut out = String::from("digraph openrc {\n    rankdir=LR;\n    node [shape=box];\n");
+        let mut placed = BTreeSet::new();
+        for runlevel in self.runlevel_order() {
+            out.push_str(&format!(
+                "    subgraph \"cluster_{}\" {{\n        label=\"{}\";\n",
+                runlevel, runlevel
+            ));
+            for service in self.order(runlevel) {
+                // A node can only sit in one cluster: the first runlevel
+                if placed.insert(service.clone()) {
+                    let color = if flagged.contains(&service) {
+                        " [color=red]"
+                    } else {
+                        ""
+                    };
+                    out.push_str(&format!("        \"{}\"{};\n", service, color));
+                }
+            }
+            out.push_str("    }\n");
+        }
+        for (first, then, kind) in self.edges() {
+            if !self.is_enabled(&first) || !self.is_enabled(&then) {
+                continue;
+            }
+            let style = match kind {
+                EdgeKind::Need => "solid",
+                EdgeKind::After => "dashed",
+                EdgeKind::Before => "dotted",
+            };
+            out.push_str(&format!(
+                "    \"{}\" -> \"{}\" [style={}];\n",
+                first, then, style
+            ));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Start order per runlevel as a tree, each service with what it waits
+    /// for.
+    pub fn to_ascii(&self) -> String {
+        let edges = self.edges();
+        let mut out = String::new();
+        for runlevel in self.runlevel_order() {
+            out.push_str(runlevel);
+            out.push('\n');
+            let order = self.order(runlevel);
+            for (i, service) in order.iter().enumerate() {
+                let branch = if i + 1 == order.len() {
+                    "└──"
+                } else {
+                    "├──"
+                };
+                let waits: Vec<&str> = edges
+                    .iter()
+                    .filter(|(a, b, _)| b == service && self.is_enabled(a))
+                    .map(|(a, _, _)| a.as_str())
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .collect();
+                if waits.is_empty() {
+                    out.push_str(&format!("{} {}\n", branch, service));
+                } else {
+                    out.push_str(&format!(
+                        "{} {} (after {})\n",
+                        branch,
+                        service,
+                        waits.join(", ")
+                    ));
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_graph_orders_and_flags_anomalies() {
+        assert_eq!(
+            parse_depend(
+                "#!/sbin/openrc-run\ndepend()\n{\n\tneed localmount \\\n\t\tdev # comment\n\
+                 \tuse $LOGGER; after *\n\tprovide net\n\tkeyword -docker\n}\nstart() { :; }\n"
+            ),
+            Depend {
+                need: vec!["localmount".into(), "dev".into()],
+                uses: vec![],
+                after: vec!["*".into()],
+                before: vec![],
+                provide: vec!["net".into()],
+            }
+        );
+
+        let root = tempdir().unwrap();
+        let init_d = root.path().join("etc/init.d");
+        fs::create_dir_all(&init_d).unwrap();
+        for (name, depend) in [
+            ("devfs", ""),
+            ("mdev", "provide dev\n need devfs"),
+            ("networking", "need dev\n after mdev\n provide net"),
+            ("sshd", "need net\n after networking\n before networking"),
+            ("chronyd", "need nfs"),
+            ("unused", ""),
+        ] {
+            fs::write(init_d.join(name), format!("depend() {{\n {}\n}}\n", depend)).unwrap();
+        }
+        let sysinit = root.path().join("etc/runlevels/sysinit");
+        fs::create_dir_all(&sysinit).unwrap();
+        std::os::unix::fs::symlink("/etc/init.d/mdev", sysinit.join("mdev")).unwrap();
+        std::os::unix::fs::symlink("/etc/init.d/devfs", sysinit.join("devfs")).unwrap();
+
+        let mut graph = ServiceGraph::load(root.path(), &[]).unwrap();
+        for (service, runlevel) in [
+            ("networking", "boot"),
+            ("sshd", "default"),
+            ("chronyd", "default"),
+            ("ghost", "default"),
+        ] {
+            graph.enable(service, runlevel);
+        }
+
+        assert_eq!(graph.runlevel_order(), ["sysinit", "boot", "default"]);
+        assert_eq!(graph.order("sysinit"), ["devfs", "mdev"]);
+        assert_eq!(
+            graph.anomalies(),
+            [
+                Anomaly::UnmetNeed {
+                    service: "chronyd".into(),
+                    runlevel: "default".into(),
+                    need: "nfs".into(),
+                },
+                Anomaly::NoScript {
+                    service: "ghost".into(),
+                    runlevel: "default".into(),
+                },
+                Anomaly::Cycle {
+                    a: "networking".into(),
+                    b: "sshd".into(),
+                },
+                Anomaly::NeverEnabled {
+                    service: "unused".into(),
+                },
+            ]
+        );
+
+        let dot = graph.to_dot();
+        assert!(dot.contains("\"devfs\" -> \"mdev\" [style=solid];"));
+        assert!(dot.contains("\"mdev\" -> \"networking\" [style=dashed];"));
+        assert!(dot.contains("\"sshd\" -> \"networking\" [style=dotted];"));
+        assert!(dot.contains("\"chronyd\" [color=red];"));
+        assert!(!dot.contains("unused"));
+        assert!(graph
+            .to_ascii()
+            .starts_with("sysinit\n├── devfs\n└── mdev (after devfs)\nboot\n"));
+    }
+}
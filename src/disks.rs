@@ -0,0 +1,307 @@
+//! Named virtual disk profiles for install testing (`disks.toml`).
+//!
+//! `run` and `test` normally boot with the single `virtual-disk.qcow2`.
+//! With `--profile NAME` they attach the disks of that profile instead,
+//! kept under `output/disks/NAME/` so each layout keeps its own state:
+//!
+//! ```toml
+//! [[small.disk]]
+//! size = "8G"
+//!
+//! [[two-disks.disk]]
+//! size = "20G"
+//! serial = "acorn-root"
+//!
+//! [[two-disks.disk]]
+//! size = "40G"
+//! serial = "acorn-home"
+//! ```
+//!
+//! Disks are attached as virtio disks in the order listed, so the guest
+//! sees the same `/dev/vdX` names every boot; a `serial` also shows up as
+//! `/dev/disk/by-id/virtio-<serial>`. `acornos disks reset NAME` deletes a
+//! profile's disks; they are recreated empty on the next boot.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::qemu::{prepare_disk, validate_disk_size, VmDisk};
+use crate::toml_subset::{self, Item, Line};
+
+/// Profile file in the project root.
+pub const DISKS_FILE: &str = "disks.toml";
+
+/// Directory of the profile disks in the output dir.
+pub const DISKS_DIR: &str = "disks";
+
+/// Longest serial virtio-blk passes on to the guest.
+const MAX_SERIAL_LEN: usize = 20;
+
+/// One disk of a profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskSpec {
+    /// Size for `qemu-img create` (`20G`).
+    pub size: String,
+    pub serial: Option<String>,
+}
+
+/// A named set of disks, attached in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskProfile {
+    pub name: String,
+    pub disks: Vec<DiskSpec>,
+}
+
+impl DiskProfile {
+    /// Directory holding this profile's qcow2 files.
+    pub fn dir(&self, output_dir: &Path) -> PathBuf {
+        output_dir.join(DISKS_DIR).join(&self.name)
+    }
+
+    /// Path of the `index`th disk.
+    pub fn disk_path(&self, output_dir: &Path, index: usize) -> PathBuf {
+        self.dir(output_dir).join(format!("disk{}.qcow2", index))
+    }
+
+    /// Create missing disks (all of them with `fresh`), in order.
+    pub fn prepare(&self, output_dir: &Path, fresh: bool) -> Result<Vec<VmDisk>> {
+        fs::create_dir_all(self.dir(output_dir))?;
+        let mut disks = Vec::new();
+        for (index, spec) in self.disks.iter().enumerate() {
+            let path = self.disk_path(output_dir, index);
+            prepare_disk(&path, &spec.size, fresh)?;
+            disks.push(VmDisk {
+                path,
+                serial: spec.serial.clone(),
+            });
+        }
+        Ok(disks)
+    }
+}
+
+/// The profiles of `disks.toml`, in file order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiskProfiles {
+    pub profiles: Vec<DiskProfile>,
+}
+
+impl DiskProfiles {
+    /// Profiles from `disks.toml` in `base_dir` (none if it is missing).
+    pub fn load(base_dir: &Path) -> Result<Self> {
+        let path = base_dir.join(DISKS_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid {}", DISKS_FILE))
+    }
+
+    /// Parse `disks.toml`; errors name the line.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut profiles: Vec<DiskProfile> = Vec::new();
+        let mut current: Option<usize> = None;
+        for Line { lineno, item } in toml_subset::parse(text)? {
+            let (key, value) = match item {
+                Item::Entry { key, value } => (key, value),
+                Item::Table(header) => bail!(
+                    "line {}: expected [[<profile>.disk]], got [{}]",
+                    lineno,
+                    header
+                ),
+                Item::ArrayTable(header) => {
+                    current = Some(add_disk(&mut profiles, &header, lineno)?);
+                    continue;
+                }
+            };
+            let value = value
+                .string()
+                .with_context(|| format!("line {}: `{}` must be a quoted string", lineno, key))?;
+            let Some(profile) = current else {
+                bail!("line {}: `{}` outside of a [[<profile>.disk]]", lineno, key);
+            };
+            let disk = profiles[profile].disks.last_mut().expect("disk just added");
+            match key.as_str() {
+                "size" => {
+                    validate_disk_size(&value).with_context(|| format!("line {}", lineno))?;
+                    disk.size = value;
+                }
+                "serial" => {
+                    if value.is_empty()
+                        || value.len() > MAX_SERIAL_LEN
+                        || !value
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                    {
+                        bail!(
+                            "line {}: serial '{}' must be 1-{} letters, digits, '-' or '_'",
+                            lineno,
+                            value,
+                            MAX_SERIAL_LEN
+                        );
+                    }
+                    disk.serial = Some(value);
+                }
+                other => bail!(
+                    "line {}: unknown key `{}` (expected size or serial)",
+                    lineno,
+                    other
+                ),
+            }
+        }
+
+        for profile in &profiles {
+            if let Some(index) = profile.disks.iter().position(|d| d.size.is_empty()) {
+                bail!("profile '{}': disk {} has no size", profile.name, index);
+            }
+            let serials: Vec<&str> = profile
+                .disks
+                .iter()
+                .filter_map(|d| d.serial.as_deref())
+                .collect();
+            for (i, serial) in serials.iter().enumerate() {
+                if serials[..i].contains(serial) {
+                    bail!(
+                        "profile '{}': serial '{}' is used more than once",
+                        profile.name,
+                        serial
+                    );
+                }
+            }
+        }
+        Ok(Self { profiles })
+    }
+
+    /// The profile called `name`, or an error listing the known ones.
+    pub fn get(&self, name: &str) -> Result<&DiskProfile> {
+        if self.profiles.is_empty() {
+            bail!(
+                "Disk profile '{}' requested but there is no {} (see 'acornos disks list')",
+                name,
+                DISKS_FILE
+            );
+        }
+        self.profiles
+            .iter()
+            .find(|p| p.name == name)
+            .with_context(|| {
+                let known: Vec<&str> = self.profiles.iter().map(|p| p.name.as_str()).collect();
+                format!(
+                    "Unknown disk profile '{}' (known: {})",
+                    name,
+                    known.join(", ")
+                )
+            })
+    }
+}
+
+/// Delete the disks of `profile`; returns whether there were any.
+pub fn reset(output_dir: &Path, profile: &DiskProfile) -> Result<bool> {
+    let dir = profile.dir(output_dir);
+    if !dir.exists() {
+        return Ok(false);
+    }
+    fs::remove_dir_all(&dir).with_context(|| format!("Failed to remove {}", dir.display()))?;
+    Ok(true)
+}
+
+/// Add a disk for the `[[<profile>.disk]]` header `header`, creating the
+/// profile on first use; returns the profile's index.
+fn add_disk(profiles: &mut Vec<DiskProfile>, header: &str, lineno: usize) -> Result<usize> {
+    let name = header
+        .strip_suffix(".disk")
+        .with_context(|| {
+            format!(
+                "line {}: expected [[<profile>.disk]], got [[{}]]",
+                lineno, header
+            )
+        })?
+        .trim()
+        .trim_matches('"');
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!("line {}: invalid profile name '{}'", lineno, name);
+    }
+    let profile = match profiles.iter().position(|p| p.name == name) {
+        Some(i) => i,
+        None => {
+            profiles.push(DiskProfile {
+                name: name.to_string(),
+                disks: Vec::new(),
+            });
+            profiles.len() - 1
+        }
+    };
+    profiles[profile].disks.push(DiskSpec {
+        size: String::new(),
+        serial: None,
+    });
+    Ok(profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_disk_profiles() {
+        let profiles = DiskProfiles::parse(
+            "# install layouts\n\
+             [[small.disk]]\n\
+             size = \"8G\"\n\
+             \n\
+             [[two-disks.disk]]\n\
+             size = \"20G\"\n\
+             serial = \"acorn-root\"\n\
+             [[two-disks.disk]]\n\
+             size = \"40G\"  # /home\n\
+             serial = \"acorn-home\"\n",
+        )
+        .unwrap();
+        assert_eq!(profiles.profiles.len(), 2);
+        let two = profiles.get("two-disks").unwrap();
+        assert_eq!(
+            two.disks,
+            [
+                DiskSpec {
+                    size: "20G".into(),
+                    serial: Some("acorn-root".into())
+                },
+                DiskSpec {
+                    size: "40G".into(),
+                    serial: Some("acorn-home".into())
+                },
+            ]
+        );
+        assert_eq!(
+            two.disk_path(Path::new("/out"), 1),
+            Path::new("/out/disks/two-disks/disk1.qcow2")
+        );
+        let err = profiles.get("raid").unwrap_err().to_string();
+        assert!(err.contains("known: small, two-disks"), "{}", err);
+        assert!(DiskProfiles::default().get("small").is_err());
+
+        for (bad, message) in [
+            ("size = \"8G\"\n", "outside of a"),
+            ("[small]\nsize = \"8G\"\n", "expected [[<profile>.disk]]"),
+            ("[[a.disk]]\nsize = \"8GB\"\n", "line 2"),
+            ("[[a.disk]]\nserial = \"x\"\n", "has no size"),
+            (
+                "[[a.disk]]\nsize = \"8G\"\nserial = \"has,comma\"\n",
+                "serial 'has,comma'",
+            ),
+            (
+                "[[a.disk]]\nsize = \"8G\"\nserial = \"s\"\n[[a.disk]]\nsize = \"8G\"\nserial = \"s\"\n",
+                "used more than once",
+            ),
+            ("[[a.disk]]\nbus = \"sata\"\n", "unknown key `bus`"),
+        ] {
+            let err = format!("{:#}", DiskProfiles::parse(bad).unwrap_err());
+            assert!(err.contains(message), "{}: {}", bad, err);
+        }
+    }
+}
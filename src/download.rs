@@ -0,0 +1,1078 @@
+//! Resumable downloads for large AcornOS dependencies.
+//!
+//! The Alpine Extended ISO is ~1GB; a dropped connection should not restart
+//! it from zero. Downloads are written to `<dest>.part` and resumed with HTTP
+//! range requests on retry. The checksum is verified on the completed file
+//! before the `.part` is renamed into place, so `dest` only ever holds a
+//! complete, verified file.
+//!
+//! The remote ETag and Content-Length seen when a `.part` was started are
+//! kept next to it (`<dest>.part.meta`); if the remote file has changed by
+//! the next run, the stale `.part` is deleted instead of resumed.
+//!
+//! A download can list fallback URLs (the same file on other Alpine
+//! mirrors, see [`AlpineSource::mirrors`]). Each is tried in turn, with the
+//! retry policy and a per-attempt stall timeout, and the URL that served
+//! the file is appended to `download.log` next to it. The checksum never
+//! comes from the serving mirror: it is pinned, or fetched from the
+//! primary's `.sha256` before the download starts. A pinned file only
+//! compares the `.sha256` as a hint (see [`crate::pins`]).
+//!
+//! The Alpine ISO, apk-tools-static and static busybox are pre-fetched
+//! through this module so the recipes and recinit find them already cached. With a shared download
+//! cache configured (see [`crate::download_cache`]) they are linked from it
+//! instead of downloaded, and stored into it once verified.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use crate::alpine_source::AlpineSource;
+use crate::arch::Arch;
+use crate::download_cache::SharedCache;
+use crate::download_check::parse_sha256;
+use crate::offline::sha256_file;
+use crate::pins::{Pin, Pins};
+
+/// Alpine Extended ISO URL (x86_64, default mirror and release; see
+/// [`alpine_iso_url`] for the build's own).
+///
+/// Canonical source: deps/alpine.rhai (`iso_url`).
+pub const ALPINE_ISO_URL: &str =
+    "https://dl-cdn.alpinelinux.org/alpine/v3.23/releases/x86_64/alpine-extended-3.23.2-x86_64.iso";
+
+/// Static busybox used by the tiny initramfs.
+pub const BUSYBOX_URL: &str =
+    "https://busybox.net/downloads/binaries/1.35.0-x86_64-linux-musl/busybox";
+
+/// apk-tools-static package (x86_64, default mirror).
+///
+/// Canonical source: deps/alpine.rhai (`apk_tools_url`).
+pub const APK_TOOLS_STATIC_URL: &str =
+    "https://dl-cdn.alpinelinux.org/alpine/v3.23/main/x86_64/apk-tools-static-3.0.4-r0.apk";
+
+/// Which URL served each download, next to the downloaded files.
+pub const DOWNLOAD_LOG: &str = "download.log";
+
+/// Environment variable overriding the per-mirror stall timeout (seconds).
+pub const MIRROR_TIMEOUT_ENV: &str = "ACORNOS_MIRROR_TIMEOUT";
+
+/// Default per-mirror stall timeout.
+const DEFAULT_MIRROR_TIMEOUT_SECS: u64 = 30;
+
+/// Slower than this (bytes/s) for the whole timeout counts as stalled.
+const STALL_BYTES_PER_SEC: &str = "1024";
+
+/// Environment variable overriding the retry count.
+pub const RETRIES_ENV: &str = "ACORNOS_DOWNLOAD_RETRIES";
+
+/// Default number of retries after the first attempt.
+const DEFAULT_RETRIES: u32 = 3;
+
+/// A single file to download.
+#[derive(Debug, Clone)]
+pub struct Download {
+    /// Source URL (the primary mirror).
+    pub url: String,
+    /// The same file elsewhere, tried in order when `url` fails.
+    pub mirrors: Vec<String>,
+    /// Final destination (only written once complete and verified).
+    pub dest: PathBuf,
+    /// Expected SHA256, if pinned.
+    pub sha256: Option<String>,
+    /// Where `sha256` was pinned, if a mismatch should say how to update it.
+    pub pinned_by: Option<String>,
+    /// `sha256sum`-format file on the primary mirror: the expected SHA256
+    /// of an unpinned file, only a hint for a pinned one.
+    pub checksum_url: Option<String>,
+}
+
+/// Result of one fetch attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FetchOutcome {
+    /// The `.part` file now holds the complete download.
+    Complete,
+    /// The server refused to resume from the requested offset.
+    RangeNotSupported,
+}
+
+/// Identity of the remote file a `.part` belongs to.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RemoteMeta {
+    pub etag: Option<String>,
+    pub content_length: Option<u64>,
+}
+
+impl RemoteMeta {
+    /// From `curl --head` output; with redirects, the last response counts.
+    pub fn from_headers(headers: &str) -> Self {
+        let mut meta = Self::default();
+        for line in headers.lines() {
+            if line.starts_with("HTTP/") {
+                meta = Self::default();
+                continue;
+            }
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "etag" => meta.etag = Some(value.to_string()),
+                "content-length" => meta.content_length = value.parse().ok(),
+                _ => {}
+            }
+        }
+        meta
+    }
+
+    /// Whether a `.part` of `part_len` bytes, started against `self`, can be
+    /// resumed from a server now reporting `remote`.
+    ///
+    /// ETags are compared when both sides have one, else lengths.
+    pub fn resumable(&self, remote: &Self, part_len: u64) -> bool {
+        if remote.content_length.is_some_and(|len| part_len > len) {
+            return false;
+        }
+        match (&self.etag, &remote.etag) {
+            (Some(saved), Some(current)) => saved == current,
+            _ => match (self.content_length, remote.content_length) {
+                (Some(saved), Some(current)) => saved == current,
+                _ => true,
+            },
+        }
+    }
+
+    fn load(path: &Path) -> Option<Self> {
+        let text = fs::read_to_string(path).ok()?;
+        let mut meta = Self::default();
+        for line in text.lines() {
+            match line.split_once('=') {
+                Some(("etag", value)) => meta.etag = Some(value.to_string()),
+                Some(("content-length", value)) => meta.content_length = value.parse().ok(),
+                _ => {}
+            }
+        }
+        Some(meta)
+    }
+
+    fn write(&self, path: &Path) -> Result<()> {
+        let mut text = String::new();
+        if let Some(etag) = &self.etag {
+            text.push_str(&format!("etag={}\n", etag));
+        }
+        if let Some(len) = self.content_length {
+            text.push_str(&format!("content-length={}\n", len));
+        }
+        fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Transport used to fetch bytes into a `.part` file.
+///
+/// Abstracted so the resume bookkeeping can be tested without a network.
+pub trait Fetcher: Sync {
+    /// Fetch `url` into `part`, appending from `offset` when non-zero.
+    fn fetch(&self, url: &str, part: &Path, offset: u64, quiet: bool) -> Result<FetchOutcome>;
+
+    /// ETag and Content-Length of `url`, if the transport can tell.
+    fn remote_meta(&self, _url: &str) -> Result<Option<RemoteMeta>> {
+        Ok(None)
+    }
+
+    /// Body of the small file at `url` (checksums).
+    fn fetch_text(&self, url: &str) -> Result<String>;
+}
+
+/// Fetcher backed by curl (`--continue-at` for range resume).
+///
+/// A mirror that does not connect, or stays below 1 KB/s, within the
+/// per-mirror timeout (`ACORNOS_MIRROR_TIMEOUT`) fails the attempt.
+pub struct CurlFetcher;
+
+/// Per-mirror stall timeout from `ACORNOS_MIRROR_TIMEOUT`.
+pub fn mirror_timeout() -> Duration {
+    let secs = std::env::var(MIRROR_TIMEOUT_ENV)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|&secs| secs > 0)
+        .unwrap_or(DEFAULT_MIRROR_TIMEOUT_SECS);
+    Duration::from_secs(secs)
+}
+
+/// curl exit code for "HTTP server doesn't support byte ranges".
+const CURL_RANGE_ERROR: i32 = 33;
+
+impl Fetcher for CurlFetcher {
+    fn fetch(&self, url: &str, part: &Path, offset: u64, quiet: bool) -> Result<FetchOutcome> {
+        let mut cmd = Command::new("curl");
+        let timeout = mirror_timeout().as_secs().to_string();
+        cmd.args(["--fail", "--location", "--retry", "0"]);
+        cmd.args(["--connect-timeout", &timeout]);
+        cmd.args([
+            "--speed-limit",
+            STALL_BYTES_PER_SEC,
+            "--speed-time",
+            &timeout,
+        ]);
+        if quiet {
+            cmd.args(["--silent", "--show-error"]);
+        } else {
+            cmd.arg("--progress-bar");
+        }
+        if offset > 0 {
+            cmd.args(["--continue-at", &offset.to_string()]);
+        }
+        cmd.arg("--output").arg(part).arg(url);
+
+        let status = cmd
+            .status()
+            .context("Failed to run curl. Install: sudo dnf install curl")?;
+
+        match status.code() {
+            Some(0) => Ok(FetchOutcome::Complete),
+            Some(CURL_RANGE_ERROR) => Ok(FetchOutcome::RangeNotSupported),
+            _ => bail!("curl failed for {} ({})", url, status),
+        }
+    }
+
+    fn remote_meta(&self, url: &str) -> Result<Option<RemoteMeta>> {
+        let output = Command::new("curl")
+            .args(["--fail", "--location", "--silent", "--show-error", "--head"])
+            .arg(url)
+            .output()
+            .context("Failed to run curl. Install: sudo dnf install curl")?;
+        if !output.status.success() {
+            bail!(
+                "HEAD request for {} failed: {}",
+                url,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let meta = RemoteMeta::from_headers(&String::from_utf8_lossy(&output.stdout));
+        Ok((meta != RemoteMeta::default()).then_some(meta))
+    }
+
+    fn fetch_text(&self, url: &str) -> Result<String> {
+        crate::download_check::fetch_text(url)
+    }
+}
+
+/// Retry count and backoff between attempts.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt.
+    pub retries: u32,
+    /// Base delay, doubled after each failed attempt.
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: DEFAULT_RETRIES,
+            backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Default policy with the retry count taken from `ACORNOS_DOWNLOAD_RETRIES`.
+    pub fn from_env() -> Self {
+        let retries = std::env::var(RETRIES_ENV)
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(DEFAULT_RETRIES);
+        Self {
+            retries,
+            ..Self::default()
+        }
+    }
+
+    /// Delay before retry number `attempt` (1-based).
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.backoff * 2u32.saturating_pow(attempt.saturating_sub(1))
+    }
+}
+
+/// Path of the in-progress file for a destination.
+pub fn part_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    dest.with_file_name(name)
+}
+
+/// Remote identity recorded for a `.part` file.
+pub fn part_meta_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".part.meta");
+    dest.with_file_name(name)
+}
+
+/// Byte offset to resume from (size of an existing `.part`, else 0).
+pub fn resume_offset(part: &Path) -> u64 {
+    fs::metadata(part).map(|m| m.len()).unwrap_or(0)
+}
+
+/// Download a file with resume, retries, and checksum verification.
+///
+/// Returns immediately if `dest` already exists and matches the checksum.
+/// In offline mode anything else is an error naming the missing file.
+pub fn fetch(
+    download: &Download,
+    fetcher: &dyn Fetcher,
+    policy: RetryPolicy,
+    quiet: bool,
+) -> Result<()> {
+    fetch_via(
+        download,
+        fetcher,
+        policy,
+        quiet,
+        SharedCache::from_env().as_ref(),
+    )
+}
+
+/// [`fetch`] through the shared download cache `shared`, if any.
+fn fetch_via(
+    download: &Download,
+    fetcher: &dyn Fetcher,
+    policy: RetryPolicy,
+    quiet: bool,
+    shared: Option<&SharedCache>,
+) -> Result<()> {
+    if let Some(sha256) = &download.sha256 {
+        if download.dest.exists() && checksum_matches(&download.dest, Some(sha256))? {
+            share(shared, &download.dest, sha256);
+            return Ok(());
+        }
+        if let Some(cache) = shared {
+            if cache.restore(&download.dest, sha256)? {
+                if !quiet {
+                    println!(
+                        "  Linked {} from the shared cache {}",
+                        download.dest.display(),
+                        cache.dir.display()
+                    );
+                }
+                return Ok(());
+            }
+        }
+    }
+    if crate::offline::offline_from_env() {
+        // Unpinned, and no mirror to ask for its checksum
+        if download.sha256.is_none() && download.dest.exists() {
+            return Ok(());
+        }
+        return Err(crate::offline::download_refused(
+            &file_name(download),
+            &download.url,
+            &download.dest,
+        ));
+    }
+
+    if let Some(parent) = download.dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // Resolved before fetching, so a fallback mirror never supplies it
+    let expected = match (&download.sha256, &download.checksum_url) {
+        (Some(sha256), hint) => {
+            if let Some(url) = hint {
+                compare_hint(fetcher, url, sha256);
+            }
+            Some(sha256.clone())
+        }
+        (None, Some(url)) => {
+            let text = fetcher
+                .fetch_text(url)
+                .with_context(|| format!("Could not fetch the checksum for {}", download.url))?;
+            Some(parse_sha256(&text).with_context(|| format!("{} holds no SHA256", url))?)
+        }
+        (None, None) => None,
+    };
+    if download.sha256.is_none() && download.dest.exists() {
+        // An unpinned file is kept only if the mirror's checksum vouches
+        // for it (or there is no checksum to compare with at all)
+        if checksum_matches(&download.dest, expected.as_deref())? {
+            return Ok(());
+        }
+        if !quiet {
+            println!(
+                "  {} does not match the mirror's checksum, downloading it again",
+                download.dest.display()
+            );
+        }
+        fs::remove_file(&download.dest)
+            .with_context(|| format!("Failed to remove {}", download.dest.display()))?;
+    }
+
+    let part = part_path(&download.dest);
+    let meta_path = part_meta_path(&download.dest);
+    discard_stale_part(download, fetcher, &part, &meta_path, quiet);
+
+    let mut errors = Vec::new();
+    let mut served_by = None;
+    for url in std::iter::once(&download.url).chain(&download.mirrors) {
+        if !errors.is_empty() {
+            // Only a verified download may mix bytes from two mirrors
+            if expected.is_none() {
+                let _ = fs::remove_file(&part);
+            }
+            if !quiet {
+                println!("  Trying mirror {}", url);
+            }
+        }
+        match fetch_from(url, fetcher, &part, policy, quiet) {
+            Ok(()) => {
+                served_by = Some(url);
+                break;
+            }
+            Err(e) if download.mirrors.is_empty() => return Err(e),
+            Err(e) => {
+                log::warn!("{:#}", e);
+                errors.push(format!("{}: {:#}", url, e));
+            }
+        }
+    }
+    let Some(url) = served_by else {
+        bail!(
+            "Download of {} failed on every mirror (partial file kept at {}):\n  {}",
+            download.url,
+            part.display(),
+            errors.join("\n  ")
+        );
+    };
+
+    let _ = fs::remove_file(&meta_path);
+    if let Some(expected) = &expected {
+        let actual = sha256_file(&part)?;
+        if !actual.eq_ignore_ascii_case(expected) {
+            let _ = fs::remove_file(&part);
+            if let Some(source) = &download.pinned_by {
+                let pin = Pin {
+                    sha256: expected.clone(),
+                    source: source.clone(),
+                };
+                return Err(crate::pins::mismatch_error(
+                    &file_name(download),
+                    &pin,
+                    &actual,
+                ));
+            }
+            bail!("Checksum mismatch for {} (partial file discarded)", url);
+        }
+    }
+
+    fs::rename(&part, &download.dest).with_context(|| {
+        format!(
+            "Failed to move {} into place at {}",
+            part.display(),
+            download.dest.display()
+        )
+    })?;
+    if url != &download.url && !quiet {
+        println!("  Served by mirror {}", url);
+    }
+    if let Err(e) = log_source(&download.dest, url) {
+        log::warn!("{:#}", e);
+    }
+    if let Some(sha256) = &download.sha256 {
+        share(shared, &download.dest, sha256);
+    }
+
+    Ok(())
+}
+
+/// Warn when the mirror's `.sha256` disagrees with the pin. Best effort:
+/// the pin decides either way.
+fn compare_hint(fetcher: &dyn Fetcher, url: &str, pinned: &str) {
+    let Some(remote) = fetcher.fetch_text(url).ok().and_then(|t| parse_sha256(&t)) else {
+        return;
+    };
+    if !remote.eq_ignore_ascii_case(pinned) {
+        log::warn!(
+            "{} lists {}, but the pinned SHA256 is {}; verifying against the pin",
+            url,
+            remote,
+            pinned
+        );
+    }
+}
+
+fn file_name(download: &Download) -> String {
+    download
+        .dest
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| download.url.clone())
+}
+
+/// Fetch `url` into `part`, resuming and retrying per `policy`.
+fn fetch_from(
+    url: &str,
+    fetcher: &dyn Fetcher,
+    part: &Path,
+    policy: RetryPolicy,
+    quiet: bool,
+) -> Result<()> {
+    let mut attempt = 0;
+    loop {
+        let offset = resume_offset(part);
+        if offset > 0 && !quiet {
+            println!("  Resuming {} at {} MB", url, offset / 1024 / 1024);
+        }
+
+        match fetcher.fetch(url, part, offset, quiet) {
+            Ok(FetchOutcome::Complete) => return Ok(()),
+            Ok(FetchOutcome::RangeNotSupported) if offset > 0 => {
+                // Server can't resume: discard the partial file and start clean
+                log::warn!("Server does not support resume, restarting download");
+                fs::remove_file(part)?;
+                continue;
+            }
+            Ok(FetchOutcome::RangeNotSupported) => {
+                bail!("Server rejected a range request at offset 0 for {}", url)
+            }
+            Err(e) if attempt < policy.retries => {
+                attempt += 1;
+                let delay = policy.delay(attempt);
+                log::warn!(
+                    "Download failed ({:#}); retry {}/{} in {}s",
+                    e,
+                    attempt,
+                    policy.retries,
+                    delay.as_secs()
+                );
+                std::thread::sleep(delay);
+            }
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!(
+                        "Download of {} failed after {} attempts (partial file kept at {})",
+                        url,
+                        attempt + 1,
+                        part.display()
+                    )
+                })
+            }
+        }
+    }
+}
+
+/// Append `<unix time> <file> <url>` to the download log next to `dest`.
+fn log_source(dest: &Path, url: &str) -> Result<()> {
+    use std::io::Write;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let log = dest.with_file_name(DOWNLOAD_LOG);
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let name = dest.file_name().unwrap_or_default().to_string_lossy();
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log)
+        .and_then(|mut file| writeln!(file, "{} {} {}", secs, name, url))
+        .with_context(|| format!("Failed to write {}", log.display()))
+}
+
+/// Store a download verified against its pin in the shared cache (best
+/// effort). Unpinned downloads are never shared.
+fn share(shared: Option<&SharedCache>, dest: &Path, sha256: &str) {
+    if let Some(Err(e)) = shared.map(|cache| cache.store(dest, sha256)) {
+        log::warn!("Could not store in the shared download cache: {:#}", e);
+    }
+}
+
+/// Delete a `.part` whose remote file changed since it was started, and
+/// record the current remote identity for the next resume.
+///
+/// Best effort: without a HEAD response the `.part` is resumed as before
+/// and the checksum catches a mismatch.
+fn discard_stale_part(
+    download: &Download,
+    fetcher: &dyn Fetcher,
+    part: &Path,
+    meta_path: &Path,
+    quiet: bool,
+) {
+    let remote = match fetcher.remote_meta(&download.url) {
+        Ok(remote) => remote,
+        Err(e) => {
+            if !quiet {
+                log::warn!("Could not check {}: {:#}", download.url, e);
+            }
+            None
+        }
+    };
+    let Some(remote) = remote else {
+        return;
+    };
+
+    if part.exists() {
+        let saved = RemoteMeta::load(meta_path).unwrap_or_default();
+        if !saved.resumable(&remote, resume_offset(part)) {
+            log::warn!(
+                "{} changed on the server, discarding the partial download",
+                download.url
+            );
+            let _ = fs::remove_file(part);
+        }
+    }
+    if let Err(e) = remote.write(meta_path) {
+        log::warn!("{:#}", e);
+    }
+}
+
+/// Download several files concurrently (quiet per-file output).
+pub fn fetch_all(downloads: &[Download], fetcher: &dyn Fetcher, policy: RetryPolicy) -> Result<()> {
+    let results: Vec<Result<()>> = std::thread::scope(|scope| {
+        let handles: Vec<_> = downloads
+            .iter()
+            .map(|d| scope.spawn(move || fetch(d, fetcher, policy, true)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| {
+                h.join()
+                    .unwrap_or_else(|_| Err(anyhow::anyhow!("download thread panicked")))
+            })
+            .collect()
+    });
+
+    let mut errors = Vec::new();
+    for (download, result) in downloads.iter().zip(results) {
+        match result {
+            Ok(()) => println!("  {} [OK]", download.dest.display()),
+            Err(e) => errors.push(format!("{:#}", e)),
+        }
+    }
+
+    if !errors.is_empty() {
+        bail!(
+            "{} download(s) failed:\n  {}",
+            errors.len(),
+            errors.join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+/// Alpine Extended ISO URL for `arch` from `source`.
+pub fn alpine_iso_url(arch: Arch, source: &AlpineSource) -> String {
+    source.rewrite(&ALPINE_ISO_URL.replace("x86_64", arch.name()))
+}
+
+/// The Alpine Extended ISO, at the location the alpine recipe expects.
+///
+/// Verified against its pin (built in for the x86_64 ISO of the default
+/// release, or from `acorn-pins.toml`), else against the `.sha256` on the
+/// primary mirror.
+pub fn alpine_iso(base_dir: &Path, arch: Arch, source: &AlpineSource) -> Result<Download> {
+    use distro_builder::alpine::extract::ExtractPaths;
+
+    let iso = ExtractPaths::new(base_dir).iso;
+    let name = iso
+        .file_name()
+        .map(|n| source.rewrite(&n.to_string_lossy().replace("x86_64", arch.name())))
+        .unwrap_or_default();
+    let mut urls = source.mirror_urls(&ALPINE_ISO_URL.replace("x86_64", arch.name()));
+    let url = urls.remove(0);
+    let pin = Pins::load(base_dir)?.iso(&name);
+    Ok(Download {
+        checksum_url: Some(format!("{}.sha256", url)),
+        url,
+        mirrors: urls,
+        dest: iso.with_file_name(name),
+        sha256: pin.as_ref().map(|p| p.sha256.clone()),
+        pinned_by: pin.map(|p| p.source),
+    })
+}
+
+/// The pinned x86_64 apk-tools-static package, where the alpine recipe
+/// extracts it. The host's apk.static builds every target (`--arch`).
+pub fn apk_tools_static(base_dir: &Path, source: &AlpineSource) -> Download {
+    use crate::download_check::{apk_tools_dir, APK_TOOLS_STATIC_APK, APK_TOOLS_STATIC_SHA256};
+
+    let mut urls = source.mirror_urls(APK_TOOLS_STATIC_URL);
+    Download {
+        url: urls.remove(0),
+        mirrors: urls,
+        dest: apk_tools_dir(base_dir).join(APK_TOOLS_STATIC_APK),
+        sha256: Some(APK_TOOLS_STATIC_SHA256.to_string()),
+        pinned_by: None,
+        checksum_url: None,
+    }
+}
+
+/// The Alpine downloads `acornos download alpine` pre-fetches: the ISO, and
+/// apk-tools-static unless the recipe already extracted it.
+pub fn alpine_downloads(
+    base_dir: &Path,
+    arch: Arch,
+    source: &AlpineSource,
+) -> Result<Vec<Download>> {
+    let mut downloads = vec![alpine_iso(base_dir, arch, source)?];
+    let apk_static = crate::download_check::apk_tools_dir(base_dir).join("sbin/apk.static");
+    if !apk_static.is_file() {
+        downloads.push(apk_tools_static(base_dir, source));
+    }
+    Ok(downloads)
+}
+
+/// Static busybox, at the location recinit caches it.
+pub fn busybox(base_dir: &Path) -> Result<Download> {
+    let pin = Pins::load(base_dir)?.busybox();
+    Ok(Download {
+        url: BUSYBOX_URL.to_string(),
+        mirrors: Vec::new(),
+        dest: base_dir.join("downloads").join(crate::pins::BUSYBOX_KEY),
+        sha256: pin.as_ref().map(|p| p.sha256.clone()),
+        pinned_by: pin.map(|p| p.source),
+        checksum_url: None,
+    })
+}
+
+/// Ensure static busybox for `arch` is cached.
+///
+/// x86_64 downloads it (resumably) from busybox.net, verified against its
+/// pin in `acorn-pins.toml` if there is one. Other arches copy
+/// `bin/busybox.static` from the Alpine rootfs, which the alpine recipe
+/// installs for them. A cached binary for a different arch is replaced.
+pub fn ensure_busybox(base_dir: &Path, arch: Arch) -> Result<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let download = busybox(base_dir)?;
+    if let Some(cached) = Arch::of_elf(&download.dest).filter(|&a| a != arch) {
+        println!("  Replacing cached {} busybox for {}", cached, arch);
+        fs::remove_file(&download.dest)?;
+    }
+    if arch == Arch::X86_64 {
+        if !download.dest.exists() {
+            println!("Downloading static busybox...");
+        }
+        // Returns at once if the cached binary matches its pin
+        fetch(&download, &CurlFetcher, RetryPolicy::from_env(), false)?;
+    } else if !download.dest.exists() {
+        let src = base_dir.join("downloads/rootfs/bin/busybox.static");
+        if Arch::of_elf(&src) != Some(arch) {
+            bail!(
+                "No {} static busybox at {}. Run 'acornos download alpine --arch {}' first.",
+                arch,
+                src.display(),
+                arch
+            );
+        }
+        fs::copy(&src, &download.dest)
+            .with_context(|| format!("Failed to copy {}", src.display()))?;
+    }
+    fs::set_permissions(&download.dest, fs::Permissions::from_mode(0o755))?;
+    Ok(download.dest)
+}
+
+fn checksum_matches(path: &Path, expected: Option<&str>) -> Result<bool> {
+    match expected {
+        Some(expected) => Ok(sha256_file(path)?.eq_ignore_ascii_case(expected)),
+        None => Ok(true),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::Mutex;
+    use tempfile::tempdir;
+
+    // sha256("acorn")
+    const ACORN_SHA256: &str = "84f0ceca5ebebf54c45888a573b1c2380ec7e8b35289290af603644f04fb1e21";
+
+    /// Serves "acorn" with scripted per-attempt behavior.
+    struct MockFetcher {
+        script: Mutex<Vec<&'static str>>,
+        offsets: Mutex<Vec<u64>>,
+        remote: Option<RemoteMeta>,
+        /// URLs that always refuse the connection.
+        down: Vec<&'static str>,
+        /// URLs `fetch_text` was asked for.
+        texts: Mutex<Vec<String>>,
+    }
+
+    impl MockFetcher {
+        fn new(script: &[&'static str]) -> Self {
+            Self {
+                script: Mutex::new(script.iter().rev().copied().collect()),
+                offsets: Mutex::new(Vec::new()),
+                remote: None,
+                down: Vec::new(),
+                texts: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    impl Fetcher for MockFetcher {
+        fn fetch(&self, url: &str, part: &Path, offset: u64, _quiet: bool) -> Result<FetchOutcome> {
+            if self.down.contains(&url) {
+                bail!("connection refused");
+            }
+            self.offsets.lock().unwrap().push(offset);
+            let step = self.script.lock().unwrap().pop().unwrap_or("complete");
+            let body = b"acorn";
+            let mut file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(part)?;
+            match step {
+                "partial" => {
+                    file.write_all(&body[offset as usize..offset as usize + 2])?;
+                    bail!("connection reset")
+                }
+                "norange" => Ok(FetchOutcome::RangeNotSupported),
+                _ => {
+                    file.write_all(&body[offset as usize..])?;
+                    Ok(FetchOutcome::Complete)
+                }
+            }
+        }
+
+        fn remote_meta(&self, _url: &str) -> Result<Option<RemoteMeta>> {
+            Ok(self.remote.clone())
+        }
+
+        fn fetch_text(&self, url: &str) -> Result<String> {
+            self.texts.lock().unwrap().push(url.to_string());
+            Ok(format!("{}  alpine.iso\n", ACORN_SHA256))
+        }
+    }
+
+    fn no_backoff(retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            retries,
+            backoff: Duration::ZERO,
+        }
+    }
+
+    fn download_to(dir: &Path, sha256: Option<&str>) -> Download {
+        Download {
+            url: "https://example.invalid/alpine.iso".to_string(),
+            mirrors: Vec::new(),
+            dest: dir.join("alpine.iso"),
+            sha256: sha256.map(String::from),
+            pinned_by: None,
+            checksum_url: None,
+        }
+    }
+
+    #[test]
+    fn test_part_path_and_offset() {
+        let dir = tempdir().unwrap();
+        let dest = dir.path().join("alpine.iso");
+        let part = part_path(&dest);
+        assert_eq!(part, dir.path().join("alpine.iso.part"));
+        assert_eq!(resume_offset(&part), 0);
+
+        fs::write(&part, "acor").unwrap();
+        assert_eq!(resume_offset(&part), 4);
+    }
+
+    #[test]
+    fn test_resume_after_failure_renames_on_complete() {
+        let dir = tempdir().unwrap();
+        let download = download_to(dir.path(), Some(ACORN_SHA256));
+        let fetcher = MockFetcher::new(&["partial", "complete"]);
+
+        fetch(&download, &fetcher, no_backoff(1), true).unwrap();
+
+        assert_eq!(*fetcher.offsets.lock().unwrap(), vec![0, 2]);
+        assert_eq!(fs::read_to_string(&download.dest).unwrap(), "acorn");
+        assert!(!part_path(&download.dest).exists());
+    }
+
+    #[test]
+    fn test_range_not_supported_restarts_clean() {
+        let dir = tempdir().unwrap();
+        let download = download_to(dir.path(), Some(ACORN_SHA256));
+        fs::write(part_path(&download.dest), "ac").unwrap();
+        let fetcher = MockFetcher::new(&["norange", "complete"]);
+
+        fetch(&download, &fetcher, no_backoff(0), true).unwrap();
+
+        assert_eq!(*fetcher.offsets.lock().unwrap(), vec![2, 0]);
+        assert_eq!(fs::read_to_string(&download.dest).unwrap(), "acorn");
+    }
+
+    #[test]
+    fn test_checksum_mismatch_never_renames() {
+        let dir = tempdir().unwrap();
+        let download = download_to(dir.path(), Some(&"0".repeat(64)));
+        let fetcher = MockFetcher::new(&["complete"]);
+
+        let err = fetch(&download, &fetcher, no_backoff(0), true).unwrap_err();
+        assert!(err.to_string().contains("Checksum mismatch"));
+        assert!(!download.dest.exists());
+        assert!(!part_path(&download.dest).exists());
+    }
+
+    #[test]
+    fn test_pin_mismatch_says_how_to_update_the_pin() {
+        let dir = tempdir().unwrap();
+        let mut download = download_to(dir.path(), Some(&"0".repeat(64)));
+        download.pinned_by = Some(crate::pins::PINS_FILE.to_string());
+        download.checksum_url = Some(format!("{}.sha256", download.url));
+        let fetcher = MockFetcher::new(&["complete"]);
+
+        let err = fetch(&download, &fetcher, no_backoff(0), true)
+            .unwrap_err()
+            .to_string();
+        // The mirror's .sha256 was only a hint: the pin failed the download
+        assert_eq!(
+            *fetcher.texts.lock().unwrap(),
+            ["https://example.invalid/alpine.iso.sha256"]
+        );
+        assert!(err.contains("Checksum mismatch for alpine.iso"), "{}", err);
+        assert!(err.contains(&format!("\"alpine.iso\" = \"{}\"", ACORN_SHA256)));
+        assert!(!download.dest.exists());
+        assert!(!part_path(&download.dest).exists());
+    }
+
+    #[test]
+    fn test_retries_exhausted_keeps_part() {
+        let dir = tempdir().unwrap();
+        let download = download_to(dir.path(), None);
+        let fetcher = MockFetcher::new(&["partial"]);
+
+        assert!(fetch(&download, &fetcher, no_backoff(0), true).is_err());
+        assert!(!download.dest.exists());
+        assert_eq!(resume_offset(&part_path(&download.dest)), 2);
+    }
+
+    #[test]
+    fn test_stale_part_is_discarded() {
+        let dir = tempdir().unwrap();
+        let download = download_to(dir.path(), Some(ACORN_SHA256));
+        let part = part_path(&download.dest);
+        let meta = part_meta_path(&download.dest);
+        let remote = |etag: &str| RemoteMeta {
+            etag: Some(etag.to_string()),
+            content_length: Some(5),
+        };
+
+        // Interrupted against "v1", which is still current: resumed
+        let mut fetcher = MockFetcher::new(&["partial"]);
+        fetcher.remote = Some(remote("\"v1\""));
+        assert!(fetch(&download, &fetcher, no_backoff(0), true).is_err());
+        assert_eq!(RemoteMeta::load(&meta), Some(remote("\"v1\"")));
+
+        // The server now has "v2": the .part is dropped and fetched from 0
+        let mut fetcher = MockFetcher::new(&["complete"]);
+        fetcher.remote = Some(remote("\"v2\""));
+        fetch(&download, &fetcher, no_backoff(0), true).unwrap();
+        assert_eq!(*fetcher.offsets.lock().unwrap(), vec![0]);
+        assert_eq!(fs::read_to_string(&download.dest).unwrap(), "acorn");
+        assert!(!part.exists());
+        assert!(!meta.exists());
+
+        let headers = "HTTP/2 302\r\nlocation: https://mirror/x\r\n\r\n\
+                       HTTP/2 200\r\nETag: \"abc\"\r\nContent-Length: 1024\r\n";
+        let parsed = RemoteMeta::from_headers(headers);
+        assert_eq!(parsed.etag.as_deref(), Some("\"abc\""));
+        assert_eq!(parsed.content_length, Some(1024));
+        let by_length = RemoteMeta {
+            etag: None,
+            content_length: Some(1024),
+        };
+        assert!(by_length.resumable(&parsed, 512));
+        assert!(!by_length.resumable(&parsed, 2048));
+    }
+
+    #[test]
+    fn test_shared_cache_skips_second_download() {
+        let dir = tempdir().unwrap();
+        let shared = SharedCache {
+            dir: dir.path().join("shared"),
+        };
+        let first = download_to(&dir.path().join("a"), Some(ACORN_SHA256));
+        let second = download_to(&dir.path().join("b"), Some(ACORN_SHA256));
+        let fetcher = MockFetcher::new(&[]);
+
+        fetch_via(&first, &fetcher, no_backoff(0), true, Some(&shared)).unwrap();
+        fetch_via(&second, &fetcher, no_backoff(0), true, Some(&shared)).unwrap();
+
+        assert_eq!(fetcher.offsets.lock().unwrap().len(), 1);
+        assert_eq!(fs::read_to_string(&second.dest).unwrap(), "acorn");
+        assert_eq!(
+            shared.origin(&second.dest),
+            Some(crate::download_cache::Origin::Shared)
+        );
+
+        // Unpinned downloads neither use nor fill the cache, and a stale
+        // unpinned file is replaced when the mirror's checksum disagrees
+        let mut third = download_to(&dir.path().join("c"), None);
+        third.checksum_url = Some(format!("{}.sha256", third.url));
+        fs::create_dir_all(dir.path().join("c")).unwrap();
+        fs::write(&third.dest, "stale").unwrap();
+        fetch_via(&third, &fetcher, no_backoff(0), true, Some(&shared)).unwrap();
+        assert_eq!(fetcher.offsets.lock().unwrap().len(), 2);
+        assert_eq!(fs::read_to_string(&third.dest).unwrap(), "acorn");
+        assert_eq!(
+            shared.origin(&third.dest),
+            Some(crate::download_cache::Origin::Local)
+        );
+    }
+
+    #[test]
+    fn test_mirror_fallback_keeps_primary_checksum() {
+        let dir = tempdir().unwrap();
+        let mut download = download_to(dir.path(), None);
+        download.checksum_url = Some(format!("{}.sha256", download.url));
+        download.mirrors = vec![
+            "https://slow.invalid/alpine.iso".to_string(),
+            "https://near.invalid/alpine.iso".to_string(),
+        ];
+        let mut fetcher = MockFetcher::new(&[]);
+        fetcher.down = vec![
+            "https://example.invalid/alpine.iso",
+            "https://slow.invalid/alpine.iso",
+        ];
+
+        fetch(&download, &fetcher, no_backoff(1), true).unwrap();
+        assert_eq!(fs::read_to_string(&download.dest).unwrap(), "acorn");
+        // The checksum came from the primary, never from the serving mirror
+        assert_eq!(
+            *fetcher.texts.lock().unwrap(),
+            ["https://example.invalid/alpine.iso.sha256"]
+        );
+        let log = fs::read_to_string(dir.path().join(DOWNLOAD_LOG)).unwrap();
+        assert!(log
+            .trim_end()
+            .ends_with(" alpine.iso https://near.invalid/alpine.iso"));
+
+        // Every mirror down: each one's error is reported
+        let dir = tempdir().unwrap();
+        let mut download = download_to(dir.path(), Some(ACORN_SHA256));
+        download.mirrors = vec!["https://slow.invalid/alpine.iso".to_string()];
+        let err = format!(
+            "{:#}",
+            fetch(&download, &fetcher, no_backoff(0), true).unwrap_err()
+        );
+        assert!(err.contains("failed on every mirror"), "{}", err);
+        assert!(err.contains("https://example.invalid/alpine.iso: Download of"));
+        assert!(err.contains("https://slow.invalid/alpine.iso: Download of"));
+        assert!(!dir.path().join(DOWNLOAD_LOG).exists());
+    }
+
+    #[test]
+    fn test_backoff_doubles() {
+        let policy = RetryPolicy {
+            retries: 3,
+            backoff: Duration::from_secs(2),
+        };
+        assert_eq!(policy.delay(1), Duration::from_secs(2));
+        assert_eq!(policy.delay(2), Duration::from_secs(4));
+        assert_eq!(policy.delay(3), Duration::from_secs(8));
+    }
+}
@@ -0,0 +1,248 @@
+//! Download cache shared between checkouts (`ACORNOS_DOWNLOAD_CACHE`).
+//!
+//! Each checkout keeps its downloads in its own `downloads/`, so several
+//! worktrees of the monorepo each fetch the same 1GB Alpine ISO. With a
+//! shared cache, [`crate::download::fetch`] first looks for the file there
+//! and links it into the checkout's `downloads/` (a hardlink, or a symlink
+//! across filesystems), so every path the recipes expect stays the same.
+//! Files downloaded or verified in a checkout are stored back into it.
+//!
+//! The cache is `ACORNOS_DOWNLOAD_CACHE` if set (`0` turns it off), else
+//! `$XDG_CACHE_HOME/acornos/downloads` (`~/.cache/acornos/downloads`) if
+//! that directory exists. Files enter the cache and the checkout only by
+//! atomic rename, so concurrent builds from two worktrees each see either
+//! no file or a complete one.
+//!
+//! Only pinned downloads are shared. Entries live under their pinned SHA256
+//! (`<cache>/<sha256>/<file name>`), so two files with the same name (another
+//! arch, a re-rolled release) never stand in for each other, and the pin is
+//! verified again before a cached file is used.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use crate::offline::sha256_file;
+
+/// Environment variable naming the shared cache directory.
+pub const CACHE_ENV: &str = "ACORNOS_DOWNLOAD_CACHE";
+
+/// Where a cached download in the checkout came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// Downloaded into this checkout only.
+    Local,
+    /// Linked from the shared cache.
+    Shared,
+}
+
+impl Origin {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Local => "local",
+            Self::Shared => "shared cache",
+        }
+    }
+}
+
+/// The shared download cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedCache {
+    pub dir: PathBuf,
+}
+
+impl SharedCache {
+    /// Cache configured for this run, if any.
+    pub fn from_env() -> Option<Self> {
+        Self::resolve(
+            std::env::var_os(CACHE_ENV).map(PathBuf::from),
+            std::env::var_os("XDG_CACHE_HOME").map(PathBuf::from),
+            std::env::var_os("HOME").map(PathBuf::from),
+        )
+    }
+
+    /// `ACORNOS_DOWNLOAD_CACHE`, else an existing XDG cache directory.
+    fn resolve(
+        explicit: Option<PathBuf>,
+        xdg_cache_home: Option<PathBuf>,
+        home: Option<PathBuf>,
+    ) -> Option<Self> {
+        match explicit.filter(|p| !p.as_os_str().is_empty()) {
+            Some(p) if p == Path::new("0") => None,
+            Some(p) => Some(Self {
+                dir: std::path::absolute(&p).unwrap_or(p),
+            }),
+            None => {
+                let cache_home = xdg_cache_home
+                    .filter(|p| p.is_absolute())
+                    .or_else(|| home.map(|h| h.join(".cache")))?;
+                let dir = cache_home.join("acornos").join("downloads");
+                dir.is_dir().then_some(Self { dir })
+            }
+        }
+    }
+
+    /// Shared counterpart of the checkout file `dest` pinned to `sha256`.
+    pub fn path_for(&self, dest: &Path, sha256: &str) -> Option<PathBuf> {
+        Some(
+            self.dir
+                .join(sha256.to_ascii_lowercase())
+                .join(dest.file_name()?),
+        )
+    }
+
+    /// Link the cached copy of `dest` into place, if there is one matching
+    /// the pin `sha256`. Returns whether `dest` now holds it.
+    pub fn restore(&self, dest: &Path, sha256: &str) -> Result<bool> {
+        let Some(shared) = self.path_for(dest, sha256).filter(|p| p.is_file()) else {
+            return Ok(false);
+        };
+        if !sha256_file(&shared)?.eq_ignore_ascii_case(sha256) {
+            log::warn!(
+                "Shared copy {} fails verification, ignoring it",
+                shared.display()
+            );
+            return Ok(false);
+        }
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = temp_path(dest);
+        let _ = fs::remove_file(&tmp);
+        if fs::hard_link(&shared, &tmp).is_err() {
+            std::os::unix::fs::symlink(&shared, &tmp)
+                .with_context(|| format!("Failed to link {}", shared.display()))?;
+        }
+        rename(&tmp, dest)?;
+        Ok(true)
+    }
+
+    /// Store the checkout file `dest`, verified against the pin `sha256`,
+    /// in the cache, unless it is already there.
+    pub fn store(&self, dest: &Path, sha256: &str) -> Result<()> {
+        let Some(shared) = self.path_for(dest, sha256) else {
+            return Ok(());
+        };
+        if self.origin(dest) == Some(Origin::Shared) {
+            return Ok(());
+        }
+        let entry_dir = shared.parent().unwrap_or(&self.dir);
+        fs::create_dir_all(entry_dir)
+            .with_context(|| format!("Failed to create {}", entry_dir.display()))?;
+        let tmp = temp_path(&shared);
+        let _ = fs::remove_file(&tmp);
+        if fs::hard_link(dest, &tmp).is_err() {
+            fs::copy(dest, &tmp).with_context(|| {
+                format!(
+                    "Failed to copy {} into {}",
+                    dest.display(),
+                    self.dir.display()
+                )
+            })?;
+        }
+        rename(&tmp, &shared)
+    }
+
+    /// Where the checkout file `dest` came from; `None` if it is missing.
+    pub fn origin(&self, dest: &Path) -> Option<Origin> {
+        let local = fs::metadata(dest).ok()?;
+        let name = dest.file_name()?;
+        let linked = fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| fs::metadata(entry.path().join(name)).ok())
+            .any(|s| s.dev() == local.dev() && s.ino() == local.ino());
+        Some(if linked {
+            Origin::Shared
+        } else {
+            Origin::Local
+        })
+    }
+}
+
+/// Per-process scratch name next to `path`, renamed over it when complete.
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()))
+}
+
+fn rename(tmp: &Path, dest: &Path) -> Result<()> {
+    fs::rename(tmp, dest).with_context(|| {
+        let _ = fs::remove_file(tmp);
+        format!(
+            "Failed to move {} into place at {}",
+            tmp.display(),
+            dest.display()
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_shared_cache_links_and_stores() {
+        let root = tempdir().unwrap();
+        let cache = SharedCache {
+            dir: root.path().join("shared"),
+        };
+        let a = root.path().join("worktree-a/downloads/alpine.iso");
+        let b = root.path().join("worktree-b/downloads/alpine.iso");
+        // sha256("acorn"), and a pin for another file of the same name
+        let pin = "84f0ceca5ebebf54c45888a573b1c2380ec7e8b35289290af603644f04fb1e21";
+        let other = "0".repeat(64);
+
+        assert!(!cache.restore(&a, pin).unwrap());
+        fs::create_dir_all(a.parent().unwrap()).unwrap();
+        fs::write(&a, "acorn").unwrap();
+        assert_eq!(cache.origin(&a), Some(Origin::Local));
+
+        cache.store(&a, pin).unwrap();
+        assert_eq!(
+            fs::read_to_string(cache.dir.join(pin).join("alpine.iso")).unwrap(),
+            "acorn"
+        );
+        assert!(!cache.restore(&b, &other).unwrap());
+        assert!(cache.restore(&b, pin).unwrap());
+        assert_eq!(fs::read_to_string(&b).unwrap(), "acorn");
+        assert_eq!(cache.origin(&b), Some(Origin::Shared));
+        assert_eq!(cache.origin(&root.path().join("missing")), None);
+        // No scratch files left behind
+        assert_eq!(fs::read_dir(cache.dir.join(pin)).unwrap().count(), 1);
+        assert_eq!(fs::read_dir(b.parent().unwrap()).unwrap().count(), 1);
+
+        // A corrupted entry is ignored rather than linked in
+        let c = root.path().join("worktree-c/downloads/alpine.iso");
+        fs::create_dir_all(cache.dir.join(&other)).unwrap();
+        fs::write(cache.dir.join(&other).join("alpine.iso"), "acorn").unwrap();
+        assert!(!cache.restore(&c, &other).unwrap());
+        assert!(!c.exists());
+
+        let xdg = root.path().join("xdg");
+        assert_eq!(SharedCache::resolve(None, Some(xdg.clone()), None), None);
+        fs::create_dir_all(xdg.join("acornos/downloads")).unwrap();
+        assert_eq!(
+            SharedCache::resolve(None, Some(xdg.clone()), None),
+            Some(SharedCache {
+                dir: xdg.join("acornos/downloads")
+            })
+        );
+        assert_eq!(
+            SharedCache::resolve(Some("0".into()), Some(xdg), None),
+            None
+        );
+        assert_eq!(
+            SharedCache::resolve(Some("/srv/cache".into()), None, None),
+            Some(SharedCache {
+                dir: PathBuf::from("/srv/cache")
+            })
+        );
+    }
+}
@@ -0,0 +1,407 @@
+//! `acornos download check`: verify cached downloads without fetching them.
+//!
+//! Every file `acornos download` would fetch is checked in place: the
+//! Alpine ISO and the apk-tools-static package against their SHA256, the
+//! extracted apk.static and static busybox for presence. The ISO checksum
+//! is its pin (see [`crate::pins`]); an unpinned ISO is checked against the
+//! mirror's tiny `.sha256` when online, else `downloads/alpine-iso.sha256`.
+//! Nothing large is ever
+//! downloaded; a missing or corrupt file fails the check and names the
+//! command that replaces it (`acornos download alpine --force`).
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use crate::alpine_source::AlpineSource;
+use crate::arch::Arch;
+use crate::clean::human_bytes;
+use crate::download::{alpine_iso, busybox, part_meta_path, part_path};
+use crate::download_cache::SharedCache;
+use crate::offline::{sha256_file, CACHED_ISO_CHECKSUM};
+
+/// apk-tools-static package fetched by deps/alpine.rhai.
+///
+/// Canonical source: deps/alpine.rhai (`apk_tools_name`).
+pub const APK_TOOLS_STATIC_APK: &str = "apk-tools-static-3.0.4-r0.apk";
+
+/// Pinned SHA256 of the x86_64 apk-tools-static package.
+///
+/// Canonical source: deps/alpine.rhai (`apk_tools_sha256`).
+pub const APK_TOOLS_STATIC_SHA256: &str =
+    "a6820637fdcbd2e700f5ced9569d0866b28fa999cb0792a6ab19ea66cf18209a";
+
+/// Remedy for anything the alpine recipe fetches.
+const ALPINE_REMEDY: &str = "acornos download alpine --force";
+
+/// Longest wait for the ISO's `.sha256`.
+const CHECKSUM_TIMEOUT_SECS: &str = "30";
+
+/// Expected SHA256 and where it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expected {
+    pub sha256: String,
+    pub source: String,
+}
+
+/// A file `acornos download` leaves in the cache.
+#[derive(Debug, Clone)]
+pub struct CachedFile {
+    pub name: &'static str,
+    pub path: PathBuf,
+    /// `None` when nothing pins it; the file is then only checked for presence.
+    pub expected: Option<Expected>,
+    /// Optional files may be absent (e.g. a package already extracted).
+    pub required: bool,
+    /// Command that fetches it again.
+    pub remedy: &'static str,
+}
+
+/// Outcome of checking one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileStatus {
+    /// Matches its expected SHA256.
+    Verified,
+    /// Present, with no checksum to compare against.
+    Present,
+    Missing,
+    /// Holds the SHA256 actually found.
+    Corrupt(String),
+}
+
+/// One checked file.
+#[derive(Debug, Clone)]
+pub struct FileReport {
+    pub file: CachedFile,
+    pub size: Option<u64>,
+    pub age: Option<Duration>,
+    pub status: FileStatus,
+}
+
+impl FileReport {
+    /// Whether this file fails the check.
+    pub fn failed(&self) -> bool {
+        match self.status {
+            FileStatus::Verified | FileStatus::Present => false,
+            FileStatus::Missing => self.file.required,
+            FileStatus::Corrupt(_) => true,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self.status {
+            FileStatus::Verified => "OK",
+            FileStatus::Present => "OK (unpinned)",
+            FileStatus::Missing if self.file.required => "MISSING",
+            FileStatus::Missing => "not cached",
+            FileStatus::Corrupt(_) => "CORRUPT",
+        }
+    }
+}
+
+/// Check `file` in place.
+pub fn check_file(file: CachedFile) -> Result<FileReport> {
+    let Ok(meta) = fs::metadata(&file.path) else {
+        return Ok(FileReport {
+            file,
+            size: None,
+            age: None,
+            status: FileStatus::Missing,
+        });
+    };
+    let status = match &file.expected {
+        Some(expected) => {
+            let actual = sha256_file(&file.path)?;
+            if actual.eq_ignore_ascii_case(&expected.sha256) {
+                FileStatus::Verified
+            } else {
+                FileStatus::Corrupt(actual)
+            }
+        }
+        None => FileStatus::Present,
+    };
+    let age = meta
+        .modified()
+        .ok()
+        .and_then(|m| SystemTime::now().duration_since(m).ok());
+    Ok(FileReport {
+        file,
+        size: Some(meta.len()),
+        age,
+        status,
+    })
+}
+
+/// First word of a `sha256sum`-format file, if it is a SHA256.
+pub fn parse_sha256(text: &str) -> Option<String> {
+    let hash = text.split_whitespace().next()?;
+    (hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit())).then(|| hash.to_lowercase())
+}
+
+/// apk-tools-static directory of the alpine recipe (the host's, for every
+/// target arch).
+pub fn apk_tools_dir(base_dir: &Path) -> PathBuf {
+    distro_builder::alpine::extract::ExtractPaths::new(base_dir).apk_tools
+}
+
+/// Everything `acornos download` caches for `arch`, with the checksums to
+/// hold them to. Online, only an unpinned ISO's `.sha256` is fetched.
+pub fn cached_files(
+    base_dir: &Path,
+    arch: Arch,
+    source: &AlpineSource,
+    offline: bool,
+) -> Result<Vec<CachedFile>> {
+    let iso = alpine_iso(base_dir, arch, source)?;
+    let downloads = base_dir.join("downloads");
+    let apk_tools = apk_tools_dir(base_dir);
+
+    let mut iso_expected = iso.sha256.clone().map(|sha256| Expected {
+        sha256,
+        source: iso
+            .pinned_by
+            .clone()
+            .unwrap_or_else(|| "pinned".to_string()),
+    });
+    if let (None, false, Some(url)) = (&iso_expected, offline, &iso.checksum_url) {
+        match fetch_text(url).map(|t| parse_sha256(&t)) {
+            Ok(Some(sha256)) => {
+                iso_expected = Some(Expected {
+                    sha256,
+                    source: "mirror .sha256".to_string(),
+                })
+            }
+            Ok(None) => log::warn!("{} holds no SHA256", url),
+            Err(e) => log::warn!("{:#}; using the local checksum", e),
+        }
+    }
+    let cached_checksum = downloads.join(CACHED_ISO_CHECKSUM);
+    let iso_expected = iso_expected.or_else(|| {
+        let sha256 = parse_sha256(&fs::read_to_string(&cached_checksum).ok()?)?;
+        Some(Expected {
+            sha256,
+            source: format!("cached {}", CACHED_ISO_CHECKSUM),
+        })
+    });
+
+    let mut files = vec![
+        CachedFile {
+            name: "Alpine ISO",
+            path: iso.dest,
+            expected: iso_expected,
+            required: true,
+            remedy: ALPINE_REMEDY,
+        },
+        CachedFile {
+            name: "apk-tools-static package",
+            path: apk_tools.join(APK_TOOLS_STATIC_APK),
+            expected: Some(Expected {
+                sha256: APK_TOOLS_STATIC_SHA256.to_string(),
+                source: "pinned".to_string(),
+            }),
+            required: false,
+            remedy: ALPINE_REMEDY,
+        },
+        CachedFile {
+            name: "apk.static",
+            path: apk_tools.join("sbin").join("apk.static"),
+            expected: None,
+            required: true,
+            remedy: ALPINE_REMEDY,
+        },
+    ];
+    let busybox = busybox(base_dir)?;
+    files.push(CachedFile {
+        name: "busybox-static",
+        path: busybox.dest,
+        // Pinned for the busybox.net binary only
+        expected: busybox
+            .sha256
+            .filter(|_| arch == Arch::X86_64)
+            .map(|sha256| Expected {
+                sha256,
+                source: busybox.pinned_by.unwrap_or_else(|| "pinned".to_string()),
+            }),
+        required: true,
+        remedy: if arch == Arch::X86_64 {
+            "acornos download"
+        } else {
+            ALPINE_REMEDY
+        },
+    });
+    Ok(files)
+}
+
+/// Body of `url` (small files only).
+pub(crate) fn fetch_text(url: &str) -> Result<String> {
+    let output = Command::new("curl")
+        .args(["--fail", "--location", "--silent", "--show-error"])
+        .args(["--max-time", CHECKSUM_TIMEOUT_SECS])
+        .arg(url)
+        .output()
+        .context("Failed to run curl. Install: sudo dnf install curl")?;
+    if !output.status.success() {
+        bail!(
+            "Could not fetch {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// `45s`, `12m`, `5h`, `3d`.
+pub fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+/// Print one line per file, and what to run for the failed ones.
+pub fn print_reports(reports: &[FileReport]) {
+    for r in reports {
+        let detail = match (r.size, r.age) {
+            (Some(size), Some(age)) => format!("{}, {} old", human_bytes(size), format_age(age)),
+            (Some(size), None) => human_bytes(size),
+            _ => String::new(),
+        };
+        println!(
+            "  {:<26} [{}] {} {}",
+            format!("{}:", r.file.name),
+            r.label(),
+            r.file.path.display(),
+            if detail.is_empty() {
+                detail
+            } else {
+                format!("({})", detail)
+            }
+        );
+        match (&r.status, &r.file.expected) {
+            (FileStatus::Corrupt(actual), Some(expected)) => {
+                println!(
+                    "      expected {} ({}), found {}",
+                    expected.sha256, expected.source, actual
+                );
+                println!("      fix: {}", r.file.remedy);
+            }
+            (FileStatus::Missing, _) if r.failed() => println!("      fix: {}", r.file.remedy),
+            _ => {}
+        }
+    }
+}
+
+/// Remove the cached ISO, apk-tools and busybox for `arch`, and the shared
+/// cache's copies, so the next download fetches them again (`--force`).
+/// Returns what was removed.
+pub fn discard_downloads(
+    base_dir: &Path,
+    arch: Arch,
+    source: &AlpineSource,
+) -> Result<Vec<PathBuf>> {
+    let iso_download = alpine_iso(base_dir, arch, source)?;
+    let iso = iso_download.dest.clone();
+    let busybox = busybox(base_dir)?.dest;
+    // Only pinned downloads have a shared copy
+    let shared_copies: Vec<PathBuf> = SharedCache::from_env()
+        .zip(iso_download.sha256.as_deref())
+        .and_then(|(shared, sha256)| shared.path_for(&iso, sha256))
+        .into_iter()
+        .collect();
+    let mut removed = Vec::new();
+    for path in [part_path(&iso), part_meta_path(&iso), iso, busybox]
+        .into_iter()
+        .chain(shared_copies)
+    {
+        // symlink_metadata: a link into the shared cache may dangle
+        if fs::symlink_metadata(&path).is_ok() {
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+            removed.push(path);
+        }
+    }
+    let apk_tools = apk_tools_dir(base_dir);
+    if apk_tools.exists() {
+        fs::remove_dir_all(&apk_tools)
+            .with_context(|| format!("Failed to remove {}", apk_tools.display()))?;
+        removed.push(apk_tools);
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    // sha256("acorn")
+    const ACORN_SHA256: &str = "84f0ceca5ebebf54c45888a573b1c2380ec7e8b35289290af603644f04fb1e21";
+
+    #[test]
+    fn test_check_reports_missing_and_corrupt() {
+        let dir = tempdir().unwrap();
+        let file = |name, expected: Option<&str>, required| CachedFile {
+            name,
+            path: dir.path().join(name),
+            expected: expected.map(|sha256| Expected {
+                sha256: sha256.to_string(),
+                source: "pinned".to_string(),
+            }),
+            required,
+            remedy: ALPINE_REMEDY,
+        };
+        for name in ["good", "bad", "unpinned"] {
+            fs::write(dir.path().join(name), "acorn").unwrap();
+        }
+        fs::write(dir.path().join("bad"), "acorn, truncated").unwrap();
+
+        let reports: Vec<FileReport> = [
+            file("good", Some(ACORN_SHA256), true),
+            file("bad", Some(ACORN_SHA256), true),
+            file("unpinned", None, true),
+            file("gone", None, true),
+            file("optional", Some(ACORN_SHA256), false),
+        ]
+        .into_iter()
+        .map(|f| check_file(f).unwrap())
+        .collect();
+        let labels: Vec<(&str, bool)> = reports.iter().map(|r| (r.label(), r.failed())).collect();
+        assert_eq!(
+            labels,
+            [
+                ("OK", false),
+                ("CORRUPT", true),
+                ("OK (unpinned)", false),
+                ("MISSING", true),
+                ("not cached", false),
+            ]
+        );
+        assert_eq!(reports[0].size, Some(5));
+        assert!(reports[0].age.is_some_and(|a| a < Duration::from_secs(60)));
+
+        assert_eq!(
+            parse_sha256(&format!("{}  alpine.iso\n", ACORN_SHA256.to_uppercase())).as_deref(),
+            Some(ACORN_SHA256)
+        );
+        assert_eq!(parse_sha256("<html>404</html>"), None);
+        assert_eq!(format_age(Duration::from_secs(3 * 86400 + 5)), "3d");
+        assert_eq!(format_age(Duration::from_secs(7200)), "2h");
+    }
+
+    #[test]
+    fn test_pins_match_recipe() {
+        let recipe =
+            fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("deps/alpine.rhai"))
+                .unwrap();
+        assert!(recipe.contains(&format!("apk_tools_name: \"{}\"", APK_TOOLS_STATIC_APK)));
+        assert!(recipe.contains(&format!(
+            "apk_tools_sha256: \"{}\"",
+            APK_TOOLS_STATIC_SHA256
+        )));
+    }
+}
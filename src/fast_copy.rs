@@ -0,0 +1,293 @@
+//! Reflink and hardlink copies from the Alpine rootfs into staging.
+//!
+//! The bulk copies (all libraries, firmware, component trees) move
+//! hundreds of MB from `downloads/rootfs` into staging on every rebuild.
+//! Both normally live on the same filesystem, so each file is first cloned
+//! with `FICLONE` (btrfs, xfs: the extents are shared, copy-on-write). Where
+//! the filesystem cannot clone, the library and firmware copies hardlink
+//! if `--hardlink-copies` is given; anything else is copied byte by byte.
+//!
+//! A hardlinked file shares its inode with the rootfs: writing to it or
+//! changing its mode in staging would change the rootfs too. Only files
+//! nothing edits afterwards are hardlinked: component trees (`etc/ssh`
+//! and the like) are config that ops rewrite in place. [`Copier::copy`]
+//! never writes through an existing destination, and the executor's file
+//! ops call [`break_hardlink`] before writing.
+
+use anyhow::{Context, Result};
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::clean::human_bytes;
+
+/// Environment variable enabling hardlinks (`--hardlink-copies`).
+pub const HARDLINK_ENV: &str = "ACORNOS_HARDLINK_COPIES";
+
+/// Byte copies shorter than this do not give a usable copy rate.
+const MIN_RATE_SAMPLE: u64 = 1 << 20;
+
+/// How a file got into staging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    Reflink,
+    Hardlink,
+    Copy,
+}
+
+impl Strategy {
+    const ALL: [Strategy; 3] = [Self::Reflink, Self::Hardlink, Self::Copy];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Reflink => "reflinked",
+            Self::Hardlink => "hardlinked",
+            Self::Copy => "copied",
+        })
+    }
+}
+
+/// Whether `--hardlink-copies` was given (for child builds, via the
+/// environment).
+pub fn hardlinks_from_env() -> bool {
+    std::env::var(HARDLINK_ENV).is_ok_and(|v| v == "1")
+}
+
+/// Export `--hardlink-copies` for child builds.
+///
+/// Must be called before any threads are spawned.
+pub fn export(enabled: bool) {
+    if enabled {
+        std::env::set_var(HARDLINK_ENV, "1");
+    }
+}
+
+/// Copies files with the cheapest strategy that works, counting what each
+/// one did.
+pub struct Copier {
+    hardlink: Cell<bool>,
+    /// Cleared once the filesystem refuses to clone.
+    reflink: Cell<bool>,
+    files: RefCell<[u64; 3]>,
+    bytes: RefCell<[u64; 3]>,
+    copy_time: Cell<Duration>,
+    started: Instant,
+}
+
+impl Copier {
+    /// A copier that hardlinks only if `hardlink` is set.
+    pub fn new(hardlink: bool) -> Self {
+        Self {
+            hardlink: Cell::new(hardlink),
+            reflink: Cell::new(true),
+            files: RefCell::new([0; 3]),
+            bytes: RefCell::new([0; 3]),
+            copy_time: Cell::new(Duration::ZERO),
+            started: Instant::now(),
+        }
+    }
+
+    /// A copier for files from the source rootfs: hardlinks with
+    /// `--hardlink-copies`.
+    pub fn for_rootfs() -> Self {
+        Self::new(hardlinks_from_env())
+    }
+
+    /// Copy the regular file `src` to `dst` with its permission bits,
+    /// replacing whatever `dst` is.
+    pub fn copy(&self, src: &Path, dst: &Path) -> Result<Strategy> {
+        let meta =
+            fs::metadata(src).with_context(|| format!("Failed to stat {}", src.display()))?;
+        if fs::symlink_metadata(dst).is_ok() {
+            fs::remove_file(dst).with_context(|| format!("Failed to replace {}", dst.display()))?;
+        }
+
+        if self.reflink.get() {
+            match reflink(src, dst, meta.permissions().mode()) {
+                Ok(()) => return Ok(self.record(Strategy::Reflink, meta.len(), None)),
+                Err(e) => {
+                    let _ = fs::remove_file(dst);
+                    // Not this file but the filesystem: stop asking
+                    if matches!(
+                        e.raw_os_error(),
+                        Some(libc::EOPNOTSUPP | libc::EXDEV | libc::ENOTTY | libc::ENOSYS)
+                    ) {
+                        self.reflink.set(false);
+                    }
+                }
+            }
+        }
+        if self.hardlink.get() {
+            match fs::hard_link(src, dst) {
+                Ok(()) => return Ok(self.record(Strategy::Hardlink, meta.len(), None)),
+                // Other filesystem, or protected_hardlinks
+                Err(_) => self.hardlink.set(false),
+            }
+        }
+
+        let start = Instant::now();
+        // fs::copy keeps the permission bits
+        fs::copy(src, dst).with_context(|| format!("Failed to copy {}", src.display()))?;
+        Ok(self.record(Strategy::Copy, meta.len(), Some(start.elapsed())))
+    }
+
+    fn record(&self, strategy: Strategy, bytes: u64, took: Option<Duration>) -> Strategy {
+        self.files.borrow_mut()[strategy.index()] += 1;
+        self.bytes.borrow_mut()[strategy.index()] += bytes;
+        if let Some(took) = took {
+            self.copy_time.set(self.copy_time.get() + took);
+        }
+        strategy
+    }
+
+    /// Files copied with `strategy` so far.
+    pub fn count(&self, strategy: Strategy) -> u64 {
+        self.files.borrow()[strategy.index()]
+    }
+
+    /// Estimated time saved by not copying the shared bytes, at the rate
+    /// the byte copies ran at (`None` without enough of them to tell).
+    fn time_saved(&self) -> Option<Duration> {
+        let bytes = self.bytes.borrow();
+        let copied = bytes[Strategy::Copy.index()];
+        let secs = self.copy_time.get().as_secs_f64();
+        if copied < MIN_RATE_SAMPLE || secs <= 0.0 {
+            return None;
+        }
+        let shared = bytes[Strategy::Reflink.index()] + bytes[Strategy::Hardlink.index()];
+        Some(Duration::from_secs_f64(
+            shared as f64 * secs / copied as f64,
+        ))
+    }
+
+    /// One line: how many files each strategy took, and what sharing saved.
+    pub fn summary(&self, what: &str) -> String {
+        let files = self.files.borrow();
+        let bytes = self.bytes.borrow();
+        let strategies: Vec<String> = Strategy::ALL
+            .iter()
+            .filter(|s| files[s.index()] > 0)
+            .map(|s| format!("{} {}", files[s.index()], s))
+            .collect();
+        let mut line = format!(
+            "{}: {} files in {:.1}s",
+            what,
+            files.iter().sum::<u64>(),
+            self.started.elapsed().as_secs_f64()
+        );
+        if !strategies.is_empty() {
+            line += &format!(" ({})", strategies.join(", "));
+        }
+        let shared = bytes[Strategy::Reflink.index()] + bytes[Strategy::Hardlink.index()];
+        if shared > 0 {
+            line += &format!(", {} shared with the rootfs", human_bytes(shared));
+            if let Some(saved) = self.time_saved() {
+                line += &format!(" (~{:.1}s saved)", saved.as_secs_f64());
+            }
+        }
+        line
+    }
+}
+
+/// Clone `src` into the new file `dst` (`FICLONE`).
+fn reflink(src: &Path, dst: &Path, mode: u32) -> io::Result<()> {
+    let from = File::open(src)?;
+    let to = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(mode & 0o7777)
+        .open(dst)?;
+    // SAFETY: FICLONE takes the source descriptor as its argument; both
+    // files stay open for the duration of the call.
+    if unsafe { libc::ioctl(to.as_raw_fd(), libc::FICLONE, from.as_raw_fd()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // The umask applied to open()
+    fs::set_permissions(dst, fs::Permissions::from_mode(mode & 0o7777))
+}
+
+/// Give `path` an inode of its own if it is a regular file with other
+/// links (a hardlink into the rootfs), so changing it leaves the rootfs
+/// alone. Returns whether a link was broken.
+pub fn break_hardlink(path: &Path) -> Result<bool> {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return Ok(false);
+    };
+    if !meta.is_file() || meta.nlink() < 2 {
+        return Ok(false);
+    }
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let tmp = path.with_file_name(format!(".{}.unshared", name));
+    fs::copy(path, &tmp).with_context(|| format!("Failed to copy {}", path.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("Failed to replace {}", path.display()))?;
+    log::trace!("    {}: hardlink broken before changing it", path.display());
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_copy_keeps_mode_and_never_writes_through() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("libz.so.1");
+        fs::write(&src, "zlib").unwrap();
+        fs::set_permissions(&src, fs::Permissions::from_mode(0o755)).unwrap();
+
+        // A stale hardlink to the source in staging is replaced, not written to
+        let dst = dir.path().join("staged.so");
+        fs::hard_link(&src, &dst).unwrap();
+        let copier = Copier::new(false);
+        let strategy = copier.copy(&src, &dst).unwrap();
+        assert_ne!(strategy, Strategy::Hardlink);
+        assert_eq!(fs::read_to_string(&dst).unwrap(), "zlib");
+        assert_eq!(
+            fs::metadata(&dst).unwrap().permissions().mode() & 0o7777,
+            0o755
+        );
+        assert_eq!(fs::metadata(&src).unwrap().nlink(), 1);
+        assert_eq!(copier.count(strategy), 1);
+        assert!(copier
+            .summary("Libraries")
+            .starts_with("Libraries: 1 files in "));
+    }
+
+    #[test]
+    fn test_hardlink_is_broken_before_changes() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("fw.bin");
+        fs::write(&src, "firmware").unwrap();
+        let dst = dir.path().join("staged.bin");
+        let copier = Copier::new(true);
+        // Hardlinked unless the tempdir filesystem clones
+        if copier.copy(&src, &dst).unwrap() == Strategy::Hardlink {
+            assert_eq!(fs::metadata(&src).unwrap().nlink(), 2);
+            assert!(copier.summary("Firmware").contains("1 hardlinked"));
+        }
+
+        let linked = dir.path().join("linked.bin");
+        fs::hard_link(&src, &linked).unwrap();
+        assert!(break_hardlink(&linked).unwrap());
+        fs::write(&linked, "changed").unwrap();
+        fs::set_permissions(&linked, fs::Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(fs::read_to_string(&src).unwrap(), "firmware");
+        assert!(!break_hardlink(&linked).unwrap());
+        assert!(!break_hardlink(&dir.path().join("missing")).unwrap());
+    }
+}
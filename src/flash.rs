@@ -0,0 +1,369 @@
+//! `acornos flash`: write the ISO to a USB stick.
+//!
+//! Raw `dd` will happily overwrite the build host's own disk when the
+//! device name is off by one letter. Before anything is written:
+//!
+//! - the ISO is checked against its `.sha512` sidecar (missing = refused)
+//! - the process must be root, so it cannot fail halfway through
+//! - the target must be a whole disk that is removable (`--force` for
+//!   others) with no mounted partitions, and large enough for the ISO
+//! - its model and size are shown and confirmed (`--yes` skips the prompt)
+//!
+//! The ISO is streamed in 4 MiB blocks (a multiple of any logical block
+//! size, as O_DIRECT needs), fsynced, and the first 2 MiB read back from the
+//! device and compared with the ISO.
+
+use anyhow::{bail, Context, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::clean::human_bytes;
+use crate::progress::{self, ProgressEvent};
+use crate::term::confirm;
+
+/// Bytes per write.
+const BLOCK_SIZE: usize = 4 * 1024 * 1024;
+
+/// Bytes read back from the device after writing.
+const VERIFY_BYTES: u64 = 2 * 1024 * 1024;
+
+/// Progress task name.
+const TASK: &str = "Flashing";
+
+/// What the user asked to skip.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FlashOptions {
+    /// Write to a device that is not marked removable.
+    pub force: bool,
+    /// Do not ask for confirmation.
+    pub yes: bool,
+}
+
+/// A whole-disk block device, as sysfs describes it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockDevice {
+    /// Kernel name (`sdb`).
+    pub name: String,
+    pub path: PathBuf,
+    pub removable: bool,
+    pub model: Option<String>,
+    pub size: u64,
+}
+
+impl BlockDevice {
+    /// Look up `path` (`/dev/sdb`) under the sysfs mounted at `sys`.
+    pub fn inspect(path: &Path, sys: &Path) -> Result<Self> {
+        let resolved = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let name = resolved
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .with_context(|| format!("{} is not a device path", path.display()))?;
+        let dir = sys.join("block").join(&name);
+        if !dir.is_dir() {
+            if sys
+                .join("class/block")
+                .join(&name)
+                .join("partition")
+                .exists()
+            {
+                bail!(
+                    "{} is a partition; flash the whole disk it belongs to",
+                    path.display()
+                );
+            }
+            bail!("{} is not a block device", path.display());
+        }
+
+        let read = |file: &str| {
+            fs::read_to_string(dir.join(file))
+                .ok()
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+        };
+        let sectors: u64 = read("size")
+            .and_then(|s| s.parse().ok())
+            .with_context(|| format!("Cannot read the size of {}", path.display()))?;
+        let vendor = read("device/vendor");
+        let model = match (vendor, read("device/model")) {
+            (Some(vendor), Some(model)) => Some(format!("{} {}", vendor, model)),
+            (vendor, model) => model.or(vendor),
+        };
+        Ok(Self {
+            name,
+            path: path.to_path_buf(),
+            removable: read("removable").as_deref() == Some("1"),
+            model,
+            // sysfs counts 512-byte sectors whatever the device's block size
+            size: sectors * 512,
+        })
+    }
+
+    /// `Kingston DataTraveler, 14.4 GiB, removable`.
+    pub fn describe(&self) -> String {
+        format!(
+            "{}, {}, {}",
+            self.model.as_deref().unwrap_or("unknown model"),
+            human_bytes(self.size),
+            if self.removable {
+                "removable"
+            } else {
+                "NOT removable"
+            }
+        )
+    }
+}
+
+/// Mount points of the device `name` and its partitions, from /proc/mounts.
+pub fn mounted_partitions(name: &str, mounts: &str) -> Vec<String> {
+    let device = format!("/dev/{}", name);
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let source = fields.next()?;
+            let target = fields.next()?;
+            let rest = source.strip_prefix(&device)?;
+            // sdb matches sdb1 and nvme0n1 matches nvme0n1p1, not sdbb
+            let partition = rest.is_empty()
+                || rest.chars().all(|c| c.is_ascii_digit())
+                || rest
+                    .strip_prefix('p')
+                    .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()));
+            partition.then(|| format!("{} on {}", source, target))
+        })
+        .collect()
+}
+
+/// Refuse targets that are not safe to overwrite with `iso_size` bytes.
+pub fn check_target(
+    device: &BlockDevice,
+    iso_size: u64,
+    mounted: &[String],
+    force: bool,
+) -> Result<()> {
+    if !device.removable && !force {
+        bail!(
+            "{} ({}) is not a removable device.\n\
+             If you are sure it is the right disk, pass --force.",
+            device.path.display(),
+            device.describe()
+        );
+    }
+    if !mounted.is_empty() {
+        bail!(
+            "{} is in use; unmount it first:\n  {}",
+            device.path.display(),
+            mounted.join("\n  ")
+        );
+    }
+    if iso_size > device.size {
+        bail!(
+            "{} ({}) is smaller than the ISO ({})",
+            device.path.display(),
+            human_bytes(device.size),
+            human_bytes(iso_size)
+        );
+    }
+    Ok(())
+}
+
+/// Write `iso` to `device_path` after the checks above.
+pub fn flash(iso: &Path, device_path: &Path, options: FlashOptions) -> Result<()> {
+    if !crate::privilege::is_root() {
+        bail!(
+            "Writing to {} needs root; run 'sudo acornos flash {}'",
+            device_path.display(),
+            device_path.display()
+        );
+    }
+    if !iso.exists() {
+        bail!(
+            "ISO not found at {}\nRun 'acornos build' first, or pass --iso <path>.",
+            iso.display()
+        );
+    }
+
+    println!("Checking {} against its SHA512 sidecar...", iso.display());
+    crate::signing::verify_sha512(iso)?;
+
+    let device = BlockDevice::inspect(device_path, Path::new("/sys"))?;
+    let iso_size = fs::metadata(iso)?.len();
+    let mounts = fs::read_to_string("/proc/mounts").context("Failed to read /proc/mounts")?;
+    check_target(
+        &device,
+        iso_size,
+        &mounted_partitions(&device.name, &mounts),
+        options.force,
+    )?;
+
+    println!("Target: {} ({})", device.path.display(), device.describe());
+    if !options.yes
+        && !confirm(&format!(
+            "Write {} to {}? Everything on it will be lost.",
+            iso.display(),
+            device.path.display()
+        ))?
+    {
+        bail!("Aborted; nothing was written");
+    }
+
+    write_image(iso, &device.path)?;
+    // Drop the cached blocks so the check reads what reached the device
+    let _ = std::process::Command::new("blockdev")
+        .arg("--flushbufs")
+        .arg(&device.path)
+        .status();
+    verify_written(iso, &device.path)?;
+
+    println!(
+        "[OK] Wrote {} to {}; it can be removed",
+        human_bytes(iso_size),
+        device.path.display()
+    );
+    Ok(())
+}
+
+/// Stream `iso` to `target` and fsync it.
+pub fn write_image(iso: &Path, target: &Path) -> Result<()> {
+    let mut input = File::open(iso).with_context(|| format!("Failed to open {}", iso.display()))?;
+    let total = input.metadata()?.len();
+    let mut output = OpenOptions::new()
+        .write(true)
+        .open(target)
+        .with_context(|| format!("Failed to open {} for writing", target.display()))?;
+
+    progress::emit(&ProgressEvent::Started {
+        task: TASK.to_string(),
+    });
+    let result = (|| -> Result<()> {
+        let mut buf = vec![0u8; BLOCK_SIZE];
+        let mut written = 0u64;
+        let mut last = None;
+        loop {
+            let n = read_block(&mut input, &mut buf)?;
+            if n == 0 {
+                break;
+            }
+            output
+                .write_all(&buf[..n])
+                .with_context(|| format!("Failed to write to {}", target.display()))?;
+            written += n as u64;
+            let percent = (written * 100 / total.max(1)) as u8;
+            if last != Some(percent) {
+                last = Some(percent);
+                progress::emit(&ProgressEvent::Progress {
+                    task: TASK.to_string(),
+                    percent,
+                });
+            }
+        }
+        output
+            .sync_all()
+            .with_context(|| format!("Failed to sync {}", target.display()))
+    })();
+    progress::emit(&ProgressEvent::Finished {
+        task: TASK.to_string(),
+        success: result.is_ok(),
+    });
+    result
+}
+
+/// Fill `buf` as far as `reader` allows; short only at the end.
+fn read_block(reader: &mut impl Read, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+/// Compare the start of `target` with the start of `iso`.
+pub fn verify_written(iso: &Path, target: &Path) -> Result<()> {
+    let head = |path: &Path| -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        File::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?
+            .take(VERIFY_BYTES)
+            .read_to_end(&mut data)?;
+        Ok(data)
+    };
+    let expected = head(iso)?;
+    let actual = head(target)?;
+    if actual.len() < expected.len() || actual[..expected.len()] != expected[..] {
+        bail!(
+            "{} does not read back as the ISO; the device may be faulty",
+            target.display()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_device_checks() {
+        let sys = tempdir().unwrap();
+        let disk = sys.path().join("block/sdb");
+        fs::create_dir_all(disk.join("device")).unwrap();
+        fs::write(disk.join("size"), "30031872\n").unwrap();
+        fs::write(disk.join("removable"), "1\n").unwrap();
+        fs::write(disk.join("device/vendor"), "Kingston\n").unwrap();
+        fs::write(disk.join("device/model"), "DataTraveler 3.0\n").unwrap();
+        fs::create_dir_all(sys.path().join("class/block/sdb1/partition")).unwrap();
+
+        let device = BlockDevice::inspect(Path::new("/dev/sdb"), sys.path()).unwrap();
+        assert_eq!(device.size, 30031872 * 512);
+        assert_eq!(
+            device.describe(),
+            "Kingston DataTraveler 3.0, 14.3 GiB, removable"
+        );
+        let err = BlockDevice::inspect(Path::new("/dev/sdb1"), sys.path()).unwrap_err();
+        assert!(err.to_string().contains("is a partition"), "{}", err);
+
+        let mounts = "/dev/sda2 / ext4 rw 0 0\n/dev/sdb1 /run/media/usb vfat rw 0 0\n\
+                      /dev/sdbb /mnt ext4 rw 0 0\n/dev/nvme0n1p1 /boot vfat rw 0 0\n";
+        assert_eq!(
+            mounted_partitions("sdb", mounts),
+            ["/dev/sdb1 on /run/media/usb"]
+        );
+        assert_eq!(mounted_partitions("nvme0n1", mounts).len(), 1);
+        assert!(mounted_partitions("sdc", mounts).is_empty());
+
+        check_target(&device, 1 << 30, &[], false).unwrap();
+        let err = check_target(&device, 1 << 30, &mounted_partitions("sdb", mounts), true);
+        assert!(err.unwrap_err().to_string().contains("unmount it first"));
+        let err = check_target(&device, 1 << 40, &[], false).unwrap_err();
+        assert!(err.to_string().contains("smaller than the ISO"));
+        let fixed = BlockDevice {
+            removable: false,
+            ..device
+        };
+        let err = check_target(&fixed, 1 << 30, &[], false).unwrap_err();
+        assert!(err.to_string().contains("--force"), "{}", err);
+        check_target(&fixed, 1 << 30, &[], true).unwrap();
+    }
+
+    #[test]
+    fn test_write_and_verify() {
+        let dir = tempdir().unwrap();
+        let iso = dir.path().join("acornos.iso");
+        let target = dir.path().join("stick");
+        let data: Vec<u8> = (0..BLOCK_SIZE + 12345).map(|i| (i % 251) as u8).collect();
+        fs::write(&iso, &data).unwrap();
+        fs::write(&target, vec![0xffu8; data.len() + 4096]).unwrap();
+
+        write_image(&iso, &target).unwrap();
+        verify_written(&iso, &target).unwrap();
+        assert_eq!(fs::read(&target).unwrap()[..data.len()], data[..]);
+
+        fs::write(&target, b"not an iso").unwrap();
+        assert!(verify_written(&iso, &target).is_err());
+    }
+}
@@ -0,0 +1,301 @@
+//! Stale entries in the output dir (`acornos gc`).
+//!
+//! `clean` removes whole categories; `gc` removes only what the current
+//! build would never write or read again: artifacts of renamed or
+//! abandoned steps, the image of the rootfs format not in use, and the
+//! `*.tmp` / `*.work` leftovers of builds that crashed. Those match names
+//! the build does use, so they are judged by age instead: older than
+//! `--older-than` hours, no running build can still own them.
+//!
+//! `rootfs-staging.work` is kept whatever its age while it has checkpoints
+//! for `build --resume`.
+//!
+//! Only direct children of the output dir are considered, and removal goes
+//! through `clean`, so symlinks are never followed.
+
+use anyhow::Result;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::artifact::full_initramfs::INITRAMFS_INSTALLED_OUTPUT;
+use crate::artifact::initramfs::COMPRESSION_MARKER;
+use crate::artifact::netboot::NETBOOT_DIR;
+use crate::artifact::netinstall::{INITRAMFS_NETINSTALL_OUTPUT, NETINSTALL_ISO_FILENAME};
+use crate::artifact::rootfs::FORMAT_MARKER;
+use crate::artifact::{InitramfsCompression, RootfsFormat};
+use crate::clean::{guard_root, remove_entry, tree_size};
+use distro_spec::acorn::{
+    ISO_FILENAME, QEMU_DISK_FILENAME, QEMU_SERIAL_LOG, UKI_INSTALLED_ENTRIES,
+};
+
+/// Default age after which `*.tmp` / `*.work` entries count as crashed.
+pub const DEFAULT_MAX_AGE_HOURS: u64 = 2;
+
+/// Work tree a `build --resume` continues in.
+const RESUMABLE_STAGING: &str = "rootfs-staging.work";
+
+/// Why an entry is stale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StaleReason {
+    /// Not something the build writes (any more).
+    Unknown,
+    /// Temporary file or work dir of a build that did not finish.
+    Interrupted,
+}
+
+impl StaleReason {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Unknown => "not produced by this build",
+            Self::Interrupted => "left by an interrupted build",
+        }
+    }
+}
+
+/// A stale entry with its size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaleEntry {
+    pub path: PathBuf,
+    pub bytes: u64,
+    pub reason: StaleReason,
+}
+
+/// Names the current build writes directly under `output_dir`.
+pub fn known_outputs(output_dir: &Path) -> BTreeSet<String> {
+    let mut names: BTreeSet<String> = [
+        ISO_FILENAME,
+        INITRAMFS_INSTALLED_OUTPUT,
+        NETINSTALL_ISO_FILENAME,
+        INITRAMFS_NETINSTALL_OUTPUT,
+        "netinstall-rootfs",
+        NETBOOT_DIR,
+        QEMU_DISK_FILENAME,
+        crate::disks::DISKS_DIR,
+        QEMU_SERIAL_LOG,
+        COMPRESSION_MARKER,
+        FORMAT_MARKER,
+        crate::artifact::iso_label::LABEL_MARKER,
+        crate::artifact::live_profile::PROFILE_MARKER,
+        // Kernel and rootfs trees
+        "staging",
+        "kernel-build",
+        "rootfs-staging",
+        "live-overlay",
+        "grub-bios",
+        "verify-iso",
+        "diff-iso",
+        crate::reproducible::VERIFY_DIR,
+        "alpine-rootfs.tar.zst",
+        // Rendered init templates kept for `sh -n`
+        "init_tiny.check",
+        "init_installed.check",
+        "init_netboot.check",
+        "init_netboot.rendered",
+        "netinstall.check",
+        // Reports
+        "openrc-services.dot",
+        crate::boot_history::BOOT_HISTORY_FILENAME,
+        crate::build_env::BUILD_ENV_FILENAME,
+        crate::component::builder::COMPONENT_HASHES_FILE,
+        crate::component::BUILD_STATE_DIR,
+        crate::install_test::INSTALL_TEST_DISK,
+        crate::install_test::INSTALL_TEST_LOG,
+        crate::license_report::LICENSES_REPORT_FILE,
+        crate::manifest::MANIFEST_FILENAME,
+        crate::output_lock::LOCK_FILE,
+        crate::package_manifest::PACKAGE_MANIFEST_FILENAME,
+        crate::package_manifest::PACKAGE_MANIFEST_SPDX_FILENAME,
+        crate::qemu::BOOT_TEST_SUMMARY,
+        crate::qemu::TEST_SERIAL_LOG,
+        crate::store_meta::STORE_META_DIR,
+        crate::timing::TIMINGS_FILENAME,
+        crate::timing::TIMINGS_PART_FILENAME,
+    ]
+    .iter()
+    .map(|name| name.to_string())
+    .collect();
+
+    for iso in [ISO_FILENAME, NETINSTALL_ISO_FILENAME] {
+        let iso = Path::new(iso);
+        for sidecar in [
+            crate::signing::sha512_path(iso),
+            crate::signing::signature_path(iso),
+        ] {
+            names.insert(sidecar.to_string_lossy().into_owned());
+        }
+    }
+    // Only the current format and compression: a build removes the others
+    names.insert(RootfsFormat::load(output_dir).image_name().to_string());
+    names.insert(InitramfsCompression::load(output_dir).output_name());
+    for arch in crate::arch::Arch::ALL {
+        names.insert(format!("grub-{}", arch));
+    }
+    for entry in crate::artifact::uki::live_entries() {
+        names.insert(entry.filename.to_string());
+    }
+    for entry in UKI_INSTALLED_ENTRIES {
+        names.insert(entry.filename.to_string());
+    }
+    names
+}
+
+/// Whether `name` is a rebuild or artifact-store key file
+/// (`.rootfs-inputs.hash` etc.); every step names its own.
+fn is_key_file(name: &str) -> bool {
+    name.starts_with('.') && name.ends_with(".hash")
+}
+
+/// Whether `name` is a temporary file or work dir of a build step.
+fn is_transient(name: &str) -> bool {
+    name.ends_with(".tmp") || name.ends_with(".work")
+}
+
+/// Stale entries of `output_dir`; transient ones count once older than
+/// `max_age` at `now`.
+pub fn find_stale(
+    output_dir: &Path,
+    max_age: Duration,
+    now: SystemTime,
+) -> Result<Vec<StaleEntry>> {
+    if output_dir.symlink_metadata().is_err() {
+        return Ok(Vec::new());
+    }
+    guard_root(output_dir)?;
+
+    let known = known_outputs(output_dir);
+    let resumable = crate::component::Checkpoint::new(output_dir, true).has_progress();
+    let mut children: Vec<PathBuf> = fs::read_dir(output_dir)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    children.sort();
+
+    let mut stale = Vec::new();
+    for path in children {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let reason = if resumable && name == RESUMABLE_STAGING {
+            continue;
+        } else if is_transient(&name) {
+            let modified = path.symlink_metadata()?.modified()?;
+            let age = now.duration_since(modified).unwrap_or_default();
+            if age < max_age {
+                continue;
+            }
+            StaleReason::Interrupted
+        } else if known.contains(name.as_ref()) || is_key_file(&name) {
+            continue;
+        } else {
+            StaleReason::Unknown
+        };
+        stale.push(StaleEntry {
+            bytes: tree_size(&path)?,
+            path,
+            reason,
+        });
+    }
+    Ok(stale)
+}
+
+/// Remove `entries` (from [`find_stale`]); returns the bytes freed.
+pub fn remove_stale(output_dir: &Path, entries: &[StaleEntry]) -> Result<u64> {
+    let mut freed = 0;
+    for entry in entries {
+        freed += remove_entry(output_dir, &entry.path)?;
+    }
+    Ok(freed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_find_stale() {
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("output");
+        fs::create_dir_all(output.join("rootfs-staging/etc")).unwrap();
+        fs::create_dir_all(output.join("filesystem.erofs.work/x")).unwrap();
+        fs::create_dir_all(
+            output
+                .join(crate::store_meta::STORE_META_DIR)
+                .join("rootfs"),
+        )
+        .unwrap();
+        for name in [
+            ISO_FILENAME,
+            "acornos.iso.sha512",
+            "filesystem.erofs",
+            "initramfs-live.cpio.gz",
+            "acornos-live.efi",
+            ".rootfs-inputs.hash",
+            crate::license_report::LICENSES_REPORT_FILE,
+            // Renamed or leftover from an older build
+            ".stale.cache",
+            "filesystem.squashfs",
+            "efiboot.img",
+            "acornos.iso.tmp",
+        ] {
+            fs::write(output.join(name), name).unwrap();
+        }
+
+        // Just written: a running build may own the .tmp and .work entries
+        let now = SystemTime::now();
+        let max_age = Duration::from_secs(3600);
+        let names = |entries: &[StaleEntry]| {
+            entries
+                .iter()
+                .map(|e| e.path.file_name().unwrap().to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+        let stale = find_stale(&output, max_age, now).unwrap();
+        assert_eq!(
+            names(&stale),
+            [".stale.cache", "efiboot.img", "filesystem.squashfs"]
+        );
+        assert_eq!(stale[1].bytes, "efiboot.img".len() as u64);
+        assert!(stale.iter().all(|e| e.reason == StaleReason::Unknown));
+
+        let later = now + Duration::from_secs(2 * 3600);
+        let stale = find_stale(&output, max_age, later).unwrap();
+        assert_eq!(
+            names(&stale),
+            [
+                ".stale.cache",
+                "acornos.iso.tmp",
+                "efiboot.img",
+                "filesystem.erofs.work",
+                "filesystem.squashfs"
+            ]
+        );
+        assert_eq!(stale[1].reason, StaleReason::Interrupted);
+
+        // Checkpoints keep the staging of a failed build for --resume
+        fs::create_dir_all(output.join("rootfs-staging.work/etc")).unwrap();
+        fs::create_dir_all(output.join(crate::component::BUILD_STATE_DIR)).unwrap();
+        fs::write(
+            output
+                .join(crate::component::BUILD_STATE_DIR)
+                .join("filesystem.done"),
+            "phase=Filesystem\nhash=00\n",
+        )
+        .unwrap();
+        assert_eq!(
+            names(&find_stale(&output, max_age, later).unwrap()),
+            names(&stale)
+        );
+
+        let freed = remove_stale(&output, &stale).unwrap();
+        assert!(freed > 0);
+        assert!(find_stale(&output, max_age, later).unwrap().is_empty());
+        assert!(output.join(ISO_FILENAME).exists());
+        assert!(output.join(".rootfs-inputs.hash").exists());
+        assert!(output
+            .join(crate::license_report::LICENSES_REPORT_FILE)
+            .exists());
+        assert!(output.join(crate::store_meta::STORE_META_DIR).exists());
+        assert!(output.join("rootfs-staging/etc").exists());
+        assert!(output.join("rootfs-staging.work/etc").exists());
+    }
+}
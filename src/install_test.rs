@@ -0,0 +1,598 @@
+//! End-to-end install test (`acornos test --install`).
+//!
+//! Boots the testing ISO headless with a blank qcow2 as `/dev/vda` and
+//! installs onto it by typing one script per step into the live shell:
+//! sgdisk and mkfs, recstrap (or the manual extraction acorn-install does
+//! when recstrap is only the placeholder), fstab and hostname, GRUB, a root
+//! password through chpasswd. It then powers the live system off, boots the
+//! disk alone and runs the installed-system checks of `acornos test --disk`
+//! (login prompt, writable root, fstab mounted).
+//!
+//! Every step has its own timeout (x3 under TCG) and is named in the
+//! failure, so a recstrap that hangs reads differently from a GRUB that
+//! fails. The serial output of all steps goes to [`INSTALL_TEST_LOG`],
+//! between a header and a result line per step. The installed disk is kept
+//! for `acornos test --disk` reruns.
+
+use anyhow::{bail, Context, Result};
+use std::fmt;
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::artifact::init_template::{check_rendered, render_template};
+use crate::artifact::RootfsFormat;
+use crate::component::custom::{ESP_SIZE, LIVE_MEDIA_MOUNT, ROOTFS_IMAGE_FUNCTIONS};
+use crate::qemu::{
+    find_firmware, open_serial_log, prepare_disk, require_testing_iso, run_verify_script,
+    select_accel, type_lines, verify_script_input, Accel, BootWatcher, Firmware, SerialVm,
+    TestOptions, VmConfig, VmDisk, TCG_BUDGET_FACTOR,
+};
+use crate::term::{self, Fields, Status};
+use distro_builder::qemu::SerialOutput;
+use distro_spec::acorn::{ISO_FILENAME, OS_NAME};
+
+/// Step-by-step transcript in the output directory.
+pub const INSTALL_TEST_LOG: &str = "install-test.log";
+
+/// Disk the test installs to, in the output directory.
+pub const INSTALL_TEST_DISK: &str = "install-test.qcow2";
+
+/// Size of [`INSTALL_TEST_DISK`].
+const INSTALL_TEST_DISK_SIZE: &str = "8G";
+
+/// Hostname of the installed system; not the live one, which
+/// the installed-system checks reject.
+const TEST_HOSTNAME: &str = "acorn-test";
+
+/// Root password of the installed system.
+pub const TEST_ROOT_PASSWORD: &str = "acorn";
+
+/// Transcript lines quoted in a failure.
+const TAIL_LINES: usize = 20;
+
+/// Start of every step's transcript section.
+const STEP_HEADER: &str = "=== ";
+
+/// Variables and helpers of every step script. Scripts run one at a time
+/// in the live shell, so the mounts of one step are there for the next.
+const STEP_PRELUDE: &str = r#"set -e
+DISK=/dev/vda
+TARGET=/mnt/acorn-target
+in_target() {
+    if command -v recchroot >/dev/null 2>&1; then
+        recchroot "$TARGET" "$@"
+    else
+        chroot "$TARGET" "$@"
+    fi
+}
+"#;
+
+const PARTITION_SCRIPT: &str = r#"sgdisk --zap-all "$DISK"
+sgdisk -n "1:0:+{{ESP_SIZE}}" -t 1:ef00 -c 1:EFI \
+    -n 2:0:0 -t 2:8300 -c 2:root "$DISK"
+udevadm settle 2>/dev/null || sleep 2
+mkfs.fat -F 32 -n EFI "${DISK}1"
+mkfs.ext4 -F -q -L root "${DISK}2"
+mkdir -p "$TARGET"
+mount "${DISK}2" "$TARGET"
+mkdir -p "$TARGET/boot"
+mount "${DISK}1" "$TARGET/boot"
+"#;
+
+/// recstrap when it is the real tool (the placeholder fails even `--help`),
+/// else acorn-install's own image copy.
+const EXTRACT_SCRIPT: &str = r#"MEDIA="{{MEDIA_MOUNT}}"
+IMAGES="{{ROOTFS_IMAGES}}"
+SOURCE=/mnt/acorn-source
+{{ROOTFS_IMAGE_FUNCTIONS}}
+if recstrap --help >/dev/null 2>&1; then
+    echo "Extracting with recstrap"
+    recstrap "$TARGET"
+else
+    echo "recstrap unavailable, copying the rootfs image"
+    find_rootfs_image
+    if [ -z "$IMAGE" ]; then
+        echo "no rootfs image under $MEDIA"
+        exit 1
+    fi
+    copy_rootfs_image
+fi
+if [ ! -e "$TARGET/sbin/init" ]; then
+    echo "no /sbin/init in the extracted system"
+    exit 1
+fi
+"#;
+
+const CONFIGURE_SCRIPT: &str = r#"if command -v recfstab >/dev/null 2>&1; then
+    recfstab "$TARGET" >"$TARGET/etc/fstab"
+else
+    {
+        echo "UUID=$(blkid -s UUID -o value "${DISK}2") / ext4 defaults 0 1"
+        echo "UUID=$(blkid -s UUID -o value "${DISK}1") /boot vfat defaults 0 2"
+    } >"$TARGET/etc/fstab"
+fi
+cat "$TARGET/etc/fstab"
+if ! awk '$2 == "/" { found = 1 } END { exit !found }' "$TARGET/etc/fstab"; then
+    echo "no / entry in the generated fstab"
+    exit 1
+fi
+echo "{{HOSTNAME}}" >"$TARGET/etc/hostname"
+if ! command -v recchroot >/dev/null 2>&1; then
+    mount --bind /dev "$TARGET/dev"
+    mount -t proc proc "$TARGET/proc"
+    mount -t sysfs sysfs "$TARGET/sys"
+fi
+"#;
+
+const BOOTLOADER_SCRIPT: &str = r#"case "$(uname -m)" in
+    aarch64) GRUB_TARGET=arm64-efi ;;
+    riscv64) GRUB_TARGET=riscv64-efi ;;
+    *) GRUB_TARGET=x86_64-efi ;;
+esac
+in_target grub-install --target="$GRUB_TARGET" --efi-directory=/boot \
+    --bootloader-id="{{OS_NAME}}" --removable
+in_target grub-mkconfig -o /boot/grub/grub.cfg
+"#;
+
+const PASSWORD_SCRIPT: &str = r#"echo "root:{{ROOT_PASSWORD}}" | in_target chpasswd
+"#;
+
+/// Unmounts before the power-off, which is typed separately.
+const SHUTDOWN_SCRIPT: &str = r#"sync
+for m in "$TARGET/sys" "$TARGET/proc" "$TARGET/dev" "$TARGET/boot" "$TARGET"; do
+    if mountpoint -q "$m"; then
+        umount "$m"
+    fi
+done
+"#;
+
+/// Steps of the install test, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstallStep {
+    LiveBoot,
+    Partition,
+    Extract,
+    Configure,
+    Bootloader,
+    Password,
+    Shutdown,
+    InstalledBoot,
+}
+
+impl InstallStep {
+    pub const ALL: [Self; 8] = [
+        Self::LiveBoot,
+        Self::Partition,
+        Self::Extract,
+        Self::Configure,
+        Self::Bootloader,
+        Self::Password,
+        Self::Shutdown,
+        Self::InstalledBoot,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::LiveBoot => "live boot",
+            Self::Partition => "partition",
+            Self::Extract => "extract",
+            Self::Configure => "configure",
+            Self::Bootloader => "bootloader",
+            Self::Password => "root password",
+            Self::Shutdown => "shutdown",
+            Self::InstalledBoot => "installed boot",
+        }
+    }
+
+    /// What the step does, for failures.
+    fn action(self) -> &'static str {
+        match self {
+            Self::LiveBoot => "booting the live ISO",
+            Self::Partition => "partitioning and formatting /dev/vda",
+            Self::Extract => "extracting the system (recstrap or the image copy)",
+            Self::Configure => "writing fstab and the hostname",
+            Self::Bootloader => "installing GRUB",
+            Self::Password => "setting the root password",
+            Self::Shutdown => "powering the live system off",
+            Self::InstalledBoot => "booting and checking the installed system",
+        }
+    }
+
+    /// Timeout in KVM seconds; the boots get `acornos test --timeout`.
+    pub fn timeout(self, boot_timeout: u64) -> u64 {
+        match self {
+            Self::LiveBoot | Self::InstalledBoot => boot_timeout,
+            Self::Partition | Self::Configure | Self::Shutdown => 60,
+            Self::Extract => 600,
+            Self::Bootloader => 180,
+            Self::Password => 30,
+        }
+    }
+
+    /// Script typed into the live shell, for the steps that are one.
+    pub fn script(self) -> Result<Option<String>> {
+        let body = match self {
+            Self::LiveBoot | Self::InstalledBoot => return Ok(None),
+            Self::Partition => PARTITION_SCRIPT,
+            Self::Extract => EXTRACT_SCRIPT,
+            Self::Configure => CONFIGURE_SCRIPT,
+            Self::Bootloader => BOOTLOADER_SCRIPT,
+            Self::Password => PASSWORD_SCRIPT,
+            Self::Shutdown => SHUTDOWN_SCRIPT,
+        };
+        let images: Vec<String> = RootfsFormat::ALL
+            .iter()
+            .map(|format| format!("{}:{}", format.fs_type(), format.iso_path()))
+            .collect();
+        let vars = [
+            ("ESP_SIZE", ESP_SIZE.to_string()),
+            ("MEDIA_MOUNT", LIVE_MEDIA_MOUNT.to_string()),
+            ("ROOTFS_IMAGES", images.join(" ")),
+            ("HOSTNAME", TEST_HOSTNAME.to_string()),
+            ("OS_NAME", OS_NAME.to_string()),
+            ("ROOT_PASSWORD", TEST_ROOT_PASSWORD.to_string()),
+            ("ROOTFS_IMAGE_FUNCTIONS", ROOTFS_IMAGE_FUNCTIONS.to_string()),
+        ];
+        let script = render_template(&format!("{}{}", STEP_PRELUDE, body), &vars);
+        check_rendered(self.name(), &script)?;
+        Ok(Some(script))
+    }
+}
+
+impl fmt::Display for InstallStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// How a step failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepFailure {
+    /// Nothing came back within the step's timeout (seconds): it hung.
+    TimedOut(u64),
+    /// The step's script exited nonzero.
+    Exited(i32),
+    /// The guest showed a failure: a boot failure pattern, a failed check.
+    Failed(String),
+}
+
+impl StepFailure {
+    /// Failure of a step script from its exit code, `None` if it passed.
+    pub fn classify(exit_code: Option<i32>, timeout: u64) -> Option<Self> {
+        match exit_code {
+            Some(0) => None,
+            Some(code) => Some(Self::Exited(code)),
+            None => Some(Self::TimedOut(timeout)),
+        }
+    }
+}
+
+/// The step the install test failed at, and how.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstallFailure {
+    pub step: InstallStep,
+    pub kind: StepFailure,
+}
+
+impl fmt::Display for InstallFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (step, action) = (self.step.name(), self.step.action());
+        match &self.kind {
+            StepFailure::TimedOut(secs) => {
+                write!(f, "{}: hung {} (nothing within {}s)", step, action, secs)
+            }
+            StepFailure::Exited(code) => write!(f, "{}: {} failed (exit {})", step, action, code),
+            StepFailure::Failed(reason) => write!(f, "{}: {} failed: {}", step, action, reason),
+        }
+    }
+}
+
+/// The transcript, and the timing of the step being run.
+struct Transcript {
+    path: PathBuf,
+    log: LineWriter<File>,
+    boot_timeout: u64,
+    factor: u32,
+    step_start: Instant,
+}
+
+impl Transcript {
+    fn timeout(&self, step: InstallStep) -> u64 {
+        step.timeout(self.boot_timeout) * u64::from(self.factor)
+    }
+
+    /// Write the step's header; returns its deadline and timeout.
+    fn begin(&mut self, step: InstallStep) -> Result<(Instant, u64)> {
+        let timeout = self.timeout(step);
+        let index = InstallStep::ALL
+            .iter()
+            .position(|s| *s == step)
+            .unwrap_or(0)
+            + 1;
+        writeln!(
+            self.log,
+            "{}[{}/{}] {} (timeout {}s)",
+            STEP_HEADER,
+            index,
+            InstallStep::ALL.len(),
+            step,
+            timeout
+        )?;
+        self.step_start = Instant::now();
+        Ok((self.step_start + Duration::from_secs(timeout), timeout))
+    }
+
+    /// Record the step's result; a failure ends the test with the step's
+    /// last serial lines.
+    fn end(&mut self, step: InstallStep, failure: Option<StepFailure>) -> Result<()> {
+        let secs = self.step_start.elapsed().as_secs_f64();
+        let Some(kind) = failure else {
+            writeln!(self.log, "--- {}: OK ({:.1}s)", step, secs)?;
+            println!("  {} {} ({:.1}s)", term::marker(Status::Ok), step, secs);
+            return Ok(());
+        };
+        let failure = InstallFailure { step, kind };
+        writeln!(self.log, "--- FAIL ({:.1}s) {}", secs, failure)?;
+        println!("  {} {} ({:.1}s)", term::marker(Status::Fail), step, secs);
+        self.log.flush()?;
+        bail!(
+            "Install test failed at {}\n\nLast serial output:\n  {}\n\ntranscript: {}",
+            failure,
+            self.tail().join("\n  "),
+            self.path.display()
+        );
+    }
+
+    /// The last lines of the current step's section.
+    fn tail(&self) -> Vec<String> {
+        let text = std::fs::read_to_string(&self.path).unwrap_or_default();
+        let lines: Vec<&str> = text.lines().collect();
+        let section = lines
+            .iter()
+            .rposition(|l| l.starts_with(STEP_HEADER))
+            .map_or(0, |i| i + 1);
+        let start = section.max(lines.len().saturating_sub(TAIL_LINES));
+        lines[start..].iter().map(|l| l.to_string()).collect()
+    }
+}
+
+/// Watch a boot until `watcher` sees the system up.
+fn boot(
+    vm: &SerialVm,
+    watcher: &BootWatcher,
+    timeout: u64,
+    log: &mut dyn Write,
+) -> Option<StepFailure> {
+    let outcome = watcher.watch(&vm.rx, Instant::now(), Duration::from_secs(timeout), log);
+    if let Some(reason) = outcome.failure {
+        return Some(StepFailure::Failed(reason));
+    }
+    (!outcome.tracker.shell_ready()).then_some(StepFailure::TimedOut(timeout))
+}
+
+/// Type the step's script into the live shell and wait for its exit.
+fn run_step(
+    vm: &mut SerialVm,
+    step: InstallStep,
+    deadline: Instant,
+    timeout: u64,
+    log: &mut dyn Write,
+) -> Result<Option<StepFailure>> {
+    let script = step.script()?.context("step has no script")?;
+    let input = verify_script_input(&script)?;
+    let outcome = run_verify_script(&mut vm.stdin, &vm.rx, &input, deadline, log)?;
+    Ok(StepFailure::classify(outcome.exit_code, timeout))
+}
+
+/// Install the testing ISO onto a blank disk over the serial console, then
+/// boot the disk and check the installed system (see the module docs).
+pub fn run(base_dir: &Path, options: &TestOptions) -> Result<()> {
+    let arch = options.arch;
+    if options.firmware == Firmware::Bios {
+        bail!("--install sets up an EFI GRUB and boots UEFI only");
+    }
+    let output_dir = crate::paths::output_dir(base_dir);
+    let iso_path = output_dir.join(ISO_FILENAME);
+    require_testing_iso(&iso_path, &output_dir)?;
+    let transcript_path = options
+        .log_path
+        .clone()
+        .unwrap_or_else(|| output_dir.join(INSTALL_TEST_LOG));
+    let disk = output_dir.join(INSTALL_TEST_DISK);
+
+    options.resources.validate()?;
+    let accel = select_accel(arch, options.resources.no_kvm)?;
+    let factor = if accel == Accel::Kvm {
+        1
+    } else {
+        TCG_BUDGET_FACTOR
+    };
+
+    let installed = BootWatcher::installed(Some(TEST_ROOT_PASSWORD));
+    let mut script = installed.checks.to_string();
+    let mut fields = Fields::new();
+    fields.row("ISO", iso_path.display().to_string());
+    fields.row(
+        "Disk",
+        format!("{} ({}, /dev/vda)", disk.display(), INSTALL_TEST_DISK_SIZE),
+    );
+    match accel {
+        Accel::Kvm => fields.row("Acceleration", "KVM"),
+        Accel::Tcg => fields.row(
+            "Acceleration",
+            format!("TCG (step timeouts x{})", TCG_BUDGET_FACTOR),
+        ),
+    };
+    if let Some(path) = &options.verify_script {
+        script += &std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read verify script {}", path.display()))?;
+        fields.row("Verify script", path.display().to_string());
+    }
+    let checks = verify_script_input(&script)?;
+    fields.row("Transcript", transcript_path.display().to_string());
+
+    println!("Testing installation onto a blank disk (headless)...");
+    fields.print(2);
+    if disk.exists() {
+        std::fs::remove_file(&disk)
+            .with_context(|| format!("Failed to remove {}", disk.display()))?;
+    }
+    prepare_disk(&disk, INSTALL_TEST_DISK_SIZE, false)?;
+    let firmware = find_firmware(arch)?;
+    let vm_disks = vec![VmDisk {
+        path: disk.clone(),
+        serial: None,
+    }];
+
+    let mut t = Transcript {
+        log: open_serial_log(&transcript_path)?,
+        path: transcript_path,
+        boot_timeout: options.timeout,
+        factor,
+        step_start: Instant::now(),
+    };
+    writeln!(
+        t.log,
+        "acornos install test: {} onto {} (accelerator: {})",
+        iso_path.display(),
+        disk.display(),
+        accel
+    )?;
+
+    // The blank disk has nothing to boot, so the firmware falls through to the CD
+    let cmd = VmConfig {
+        iso: Some(iso_path.clone()),
+        disks: vm_disks.clone(),
+        firmware: Some(firmware.clone()),
+        resources: options.resources.clone(),
+        ..VmConfig::new(arch, accel, SerialOutput::Stdio)
+    }
+    .command();
+    options.resources.print(&cmd);
+    let mut vm = SerialVm::spawn(cmd, arch)?;
+
+    let (_, timeout) = t.begin(InstallStep::LiveBoot)?;
+    let failure = boot(&vm, &BootWatcher::live(), timeout, &mut t.log);
+    t.end(InstallStep::LiveBoot, failure)?;
+
+    for step in [
+        InstallStep::Partition,
+        InstallStep::Extract,
+        InstallStep::Configure,
+        InstallStep::Bootloader,
+        InstallStep::Password,
+    ] {
+        let (deadline, timeout) = t.begin(step)?;
+        let failure = run_step(&mut vm, step, deadline, timeout, &mut t.log)?;
+        t.end(step, failure)?;
+    }
+
+    let (deadline, timeout) = t.begin(InstallStep::Shutdown)?;
+    let mut failure = run_step(
+        &mut vm,
+        InstallStep::Shutdown,
+        deadline,
+        timeout,
+        &mut t.log,
+    )?;
+    if failure.is_none() {
+        type_lines(&mut vm.stdin, &["poweroff".to_string()], Duration::ZERO)?;
+        if vm.wait_exit(deadline, &mut t.log)?.is_none() {
+            failure = Some(StepFailure::TimedOut(timeout));
+        }
+    }
+    t.end(InstallStep::Shutdown, failure)?;
+    drop(vm);
+
+    // The disk alone, as `acornos test --disk` boots it
+    let cmd = VmConfig {
+        disks: vm_disks,
+        firmware: Some(firmware),
+        snapshot: true,
+        resources: options.resources.clone(),
+        ..VmConfig::new(arch, accel, SerialOutput::Stdio)
+    }
+    .command();
+    let mut vm = SerialVm::spawn(cmd, arch)?;
+
+    let (deadline, timeout) = t.begin(InstallStep::InstalledBoot)?;
+    let start = Instant::now();
+    let mut failure = boot(&vm, &installed, timeout, &mut t.log);
+    if failure.is_none() {
+        failure = installed
+            .run_checks(&mut vm, &checks, start, deadline, timeout, &mut t.log)?
+            .map(|(reason, _)| StepFailure::Failed(reason));
+    }
+    t.end(InstallStep::InstalledBoot, failure)?;
+    drop(vm);
+
+    writeln!(t.log, "RESULT: PASS")?;
+    println!(
+        "\nInstall test passed (accelerator: {}, transcript: {}).",
+        accel,
+        t.path.display()
+    );
+    println!(
+        "Installed disk kept: acornos test --disk {} --root-password {}",
+        disk.display(),
+        TEST_ROOT_PASSWORD
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::artifact::init_template::check_shell_syntax;
+
+    #[test]
+    fn test_step_scripts_and_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        for step in InstallStep::ALL {
+            let Some(script) = step.script().unwrap() else {
+                assert!(matches!(
+                    step,
+                    InstallStep::LiveBoot | InstallStep::InstalledBoot
+                ));
+                continue;
+            };
+            assert!(script.starts_with("set -e\nDISK=/dev/vda\n"), "{}", step);
+            verify_script_input(&script).unwrap();
+            check_shell_syntax(step.name(), &script, None, &dir.path().join("check")).unwrap();
+        }
+        let partition = InstallStep::Partition.script().unwrap().unwrap();
+        assert!(partition.contains("sgdisk -n \"1:0:+512M\""));
+        let extract = InstallStep::Extract.script().unwrap().unwrap();
+        assert!(extract.contains("recstrap \"$TARGET\""));
+        assert!(extract.contains("erofs:"));
+        assert!(extract.contains(ROOTFS_IMAGE_FUNCTIONS));
+        assert!(extract.contains("\n    copy_rootfs_image\n"));
+        let configure = InstallStep::Configure.script().unwrap().unwrap();
+        assert!(configure.contains(&format!("echo \"{}\"", TEST_HOSTNAME)));
+        assert_ne!(TEST_HOSTNAME, distro_spec::acorn::OS_ID);
+        let password = InstallStep::Password.script().unwrap().unwrap();
+        assert!(password.contains("echo \"root:acorn\" | in_target chpasswd"));
+
+        assert_eq!(StepFailure::classify(Some(0), 600), None);
+        let hang = InstallFailure {
+            step: InstallStep::Extract,
+            kind: StepFailure::classify(None, 600).unwrap(),
+        };
+        assert_eq!(
+            hang.to_string(),
+            "extract: hung extracting the system (recstrap or the image copy) (nothing within 600s)"
+        );
+        let grub = InstallFailure {
+            step: InstallStep::Bootloader,
+            kind: StepFailure::classify(Some(127), 180).unwrap(),
+        };
+        assert_eq!(
+            grub.to_string(),
+            "bootloader: installing GRUB failed (exit 127)"
+        );
+        assert_eq!(InstallStep::Extract.timeout(120), 600);
+        assert_eq!(InstallStep::InstalledBoot.timeout(120), 120);
+    }
+}
@@ -0,0 +1,140 @@
+//! The kernel the builds consume (`acornos kernel build` / `kernel status`).
+//!
+//! Kernel compilation is centralized in the monorepo's xtask
+//! (`cargo xtask kernels build acorn`, nightly build-hours policy), which
+//! installs into `output/staging`. `kernel build` runs it from the monorepo
+//! root, so nobody has to switch directories for it, and `kernel status`
+//! reads the release it installed.
+
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::paths::OutputPaths;
+
+/// Arguments of `cargo` that build the AcornOS kernel.
+pub const XTASK_KERNEL_BUILD: &[&str] = &["xtask", "kernels", "build", "acorn"];
+
+/// The xtask invocation, for messages.
+pub fn xtask_command() -> String {
+    format!("cargo {}", XTASK_KERNEL_BUILD.join(" "))
+}
+
+/// The monorepo checkout around `base_dir`: the nearest ancestor with an
+/// `xtask/` crate (normally the parent).
+pub fn monorepo_root(base_dir: &Path) -> Result<PathBuf> {
+    let base = base_dir
+        .canonicalize()
+        .unwrap_or_else(|_| base_dir.to_path_buf());
+    match base
+        .ancestors()
+        .skip(1)
+        .find(|dir| dir.join("xtask").is_dir())
+    {
+        Some(root) => Ok(root.to_path_buf()),
+        None => bail!(
+            "No xtask/ above {}: kernels are built by the LevitateOS monorepo's xtask, \
+             so AcornOS must be checked out inside it",
+            base.display()
+        ),
+    }
+}
+
+/// Run `cargo xtask kernels build acorn` from the monorepo root with the
+/// console attached.
+pub fn build(base_dir: &Path) -> Result<()> {
+    let root = monorepo_root(base_dir)?;
+    log::info!("Running '{}' in {}", xtask_command(), root.display());
+    let status = Command::new("cargo")
+        .args(XTASK_KERNEL_BUILD)
+        .current_dir(&root)
+        .status()
+        .with_context(|| format!("Failed to run '{}'", xtask_command()))?;
+    if !status.success() {
+        bail!("'{}' failed ({})", xtask_command(), status);
+    }
+    Ok(())
+}
+
+/// Release of the installed kernel: the versioned dir of its modules.
+pub fn installed_release(staging: &Path) -> Option<String> {
+    ["lib/modules", "usr/lib/modules"].iter().find_map(|dir| {
+        std::fs::read_dir(staging.join(dir))
+            .ok()?
+            .flatten()
+            .filter(|entry| entry.path().is_dir())
+            .find_map(|entry| entry.file_name().to_str().map(str::to_string))
+    })
+}
+
+/// What is installed in the kernel staging dir, and whether it was built
+/// for AcornOS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KernelStatus {
+    /// `boot/vmlinuz` of the staging dir.
+    pub image: PathBuf,
+    /// Size of the image in bytes, if it exists.
+    pub size: Option<u64>,
+    /// Release from the modules dir (output/kernel-build may be gone even
+    /// when a kernel is installed).
+    pub release: Option<String>,
+    /// Local version suffix of kernels built for AcornOS.
+    pub expected_suffix: &'static str,
+}
+
+impl KernelStatus {
+    pub fn detect(output: &OutputPaths) -> Self {
+        let image = output.kernel();
+        Self {
+            size: std::fs::metadata(&image).ok().map(|m| m.len()),
+            image,
+            release: installed_release(&output.kernel_staging()),
+            expected_suffix: distro_spec::acorn::KERNEL_SOURCE.localversion,
+        }
+    }
+
+    pub fn installed(&self) -> bool {
+        self.size.is_some()
+    }
+
+    /// Whether the release carries the AcornOS local version.
+    pub fn built_for_distro(&self) -> bool {
+        self.release
+            .as_deref()
+            .is_some_and(|r| r.contains(self.expected_suffix))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_monorepo_root_and_release() {
+        let dir = tempdir().unwrap();
+        let base = dir.path().join("AcornOS");
+        std::fs::create_dir_all(base.join("xtask")).unwrap();
+        assert!(monorepo_root(&base).is_err());
+        std::fs::create_dir_all(dir.path().join("xtask")).unwrap();
+        assert_eq!(
+            monorepo_root(&base).unwrap(),
+            dir.path().canonicalize().unwrap()
+        );
+
+        let output = OutputPaths::new(&base.join("output"));
+        let mut status = KernelStatus::detect(&output);
+        assert!(!status.installed());
+        assert_eq!(status.release, None);
+        assert!(!status.built_for_distro());
+
+        let release = format!("6.12.1{}", status.expected_suffix);
+        std::fs::create_dir_all(output.kernel_modules().join(&release)).unwrap();
+        std::fs::create_dir_all(output.kernel().parent().unwrap()).unwrap();
+        std::fs::write(output.kernel(), b"bzImage").unwrap();
+        status = KernelStatus::detect(&output);
+        assert_eq!(status.size, Some(7));
+        assert_eq!(status.release.as_deref(), Some(release.as_str()));
+        assert!(status.built_for_distro());
+    }
+}
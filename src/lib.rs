@@ -16,10 +16,51 @@
 //! ```text
 //! AcornOS (this crate)
 //!     │
+//!     ├── alpine_signature.rs GPG check of the Alpine ISO (download --verify-gpg)
+//!     ├── alpine_source.rs Alpine mirror and branch (--alpine-mirror/--alpine-version)
+//!     ├── apk_install.rs Tiered apk installs with retries and error attribution
+//!     ├── arch.rs        Target architecture (x86_64, aarch64)
+//!     ├── boot_history.rs Boot-time history of `test` (boot-times.jsonl)
+//!     ├── build_env.rs   Build environment description (env export / env diff)
+//!     ├── build_info.rs  Version and build id (os-release, ISO application id)
+//!     ├── clean.rs       Scoped cleanup (output, downloads, cache)
+//!     ├── cmd_args.rs    Byte-exact path arguments for external commands
 //!     ├── config.rs      DistroConfig implementation
+//!     ├── fast_copy.rs   Reflink/hardlink copies into staging (--hardlink-copies)
+//!     ├── flash.rs       Writing the ISO to a USB stick (acornos flash)
 //!     ├── extract.rs     Path definitions (download logic in deps/alpine.rhai)
+//!     ├── gc.rs          Stale output entries (acornos gc)
+//!     ├── install_test.rs Install onto a blank disk in QEMU (test --install)
+//!     ├── kernel.rs      Kernel build via xtask and its provenance (acornos kernel)
+//!     ├── license_report.rs Licenses of the copied files (licenses-report.txt)
+//!     ├── logging.rs     Log levels (-v, -vv, --quiet)
 //!     ├── artifact/      Build artifacts (EROFS, initramfs, ISO)
+//!     ├── disks.rs       Virtual disk profiles for run/test (disks.toml)
+//!     ├── download.rs    Resumable downloads (Alpine ISO, busybox)
+//!     ├── download_cache.rs Download cache shared between checkouts
+//!     ├── download_check.rs Verify cached downloads in place (download check)
+//!     ├── manifest.rs    Build manifest (packages, kernel, artifact hashes)
+//!     ├── offline.rs     Air-gapped download validation
+//!     ├── output_lock.rs Output dir lock against concurrent runs (--no-wait)
+//!     ├── package_manifest.rs Package manifest/SBOM (JSON, SPDX)
+//!     ├── package_overlay.rs Package additions/removals (acorn-packages.toml)
+//!     ├── parallel.rs    Concurrent build steps with prefixed output
+//!     ├── paths.rs       Base and output dirs (--base-dir, --output-dir)
+//!     ├── pins.rs        Pinned Alpine ISO checksums (acorn-pins.toml)
+//!     ├── pipeline.rs    BuildPipeline: restore / rebuild check / build / store
+//!     ├── progress.rs    Progress of image tools (mkfs.erofs, mksquashfs, xorriso)
+//!     ├── privilege.rs   Unprivileged builds (root wrapper for apk)
+//!     ├── rootfs_cache.rs Alpine rootfs cache in the artifact store
 //!     ├── qemu.rs        QEMU runner
+//!     ├── reproducible.rs Reproducible builds (--reproducible, --verify-reproducible)
+//!     ├── shell.rs       Shell inside the staged rootfs (acornos shell)
+//!     ├── source_rootfs.rs Completion stamp of downloads/rootfs (.rootfs-complete)
+//!     ├── signing.rs     Release checksums and signatures
+//!     ├── store_meta.rs  Artifact store checksums (store verify)
+//!     ├── term.rs        Colored, aligned CLI output (--no-color)
+//!     ├── timing.rs      Build phase timings (build-timings.json)
+//!     ├── toml_subset.rs The TOML subset of the project-root config files
+//!     ├── tree_copy.rs   Copying overlays and trees into the image
 //!     └── component/     OpenRC-specific components
 //!
 //! Uses:
@@ -38,11 +79,53 @@
 //! println!("Init system: {}", config.init_system());
 //! ```
 
+pub mod alpine_signature;
+pub mod alpine_source;
+pub mod apk;
+pub mod apk_install;
+pub mod arch;
 pub mod artifact;
+pub mod boot_history;
+pub mod build_env;
+pub mod build_info;
+pub mod clean;
+pub mod cmd_args;
 pub mod component;
 pub mod config;
+pub mod disks;
+pub mod download;
+pub mod download_cache;
+pub mod download_check;
+pub mod fast_copy;
+pub mod flash;
+pub mod gc;
+pub mod install_test;
+pub mod kernel;
+pub mod license_report;
+pub mod logging;
+pub mod manifest;
+pub mod offline;
+pub mod output_lock;
+pub mod package_manifest;
+pub mod package_overlay;
+pub mod parallel;
+pub mod paths;
+pub mod pins;
+pub mod pipeline;
 pub mod preflight;
+pub mod privilege;
+pub mod progress;
 pub mod qemu;
 pub mod rebuild;
+pub mod reproducible;
+pub mod rootfs_cache;
+pub mod shell;
+pub mod signing;
+pub mod source_rootfs;
+pub mod store_meta;
+pub mod term;
+pub mod timing;
+pub mod toml_subset;
+pub mod tree_copy;
 
 pub use config::AcornConfig;
@@ -0,0 +1,188 @@
+//! License attribution of the files copied into the rootfs
+//! (`output/licenses-report.txt`).
+//!
+//! Every file a component copies from the Alpine rootfs (binaries, the
+//! libraries they load, trees, init scripts) is looked up in the source's
+//! `lib/apk/db/installed`, and the owning package is registered with the
+//! `LicenseTracker` so its license ships in the image. Files no package
+//! owns cannot be attributed; they are listed in the report and warned
+//! about at the end of the build.
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::apk::{installed_packages, ApkPackage, FileOwners};
+
+/// Report in the output dir.
+pub const LICENSES_REPORT_FILE: &str = "licenses-report.txt";
+
+/// Packages the copied files came from, and the files none owns.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LicenseReport {
+    /// Owning packages, by name, with the number of files copied from each.
+    pub packages: Vec<(ApkPackage, usize)>,
+    /// Copied files no installed package owns, sorted.
+    pub unattributed: Vec<String>,
+}
+
+impl LicenseReport {
+    /// Attribute `copied` (paths relative to the source rootfs) to the
+    /// packages of `installed`.
+    pub fn new(installed: &[ApkPackage], owners: &FileOwners, copied: &[PathBuf]) -> Self {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        let mut unattributed = Vec::new();
+        for path in copied {
+            let path = path.to_string_lossy();
+            match owners.owner(&path) {
+                Some(package) => *counts.entry(package).or_default() += 1,
+                None => unattributed.push(path.into_owned()),
+            }
+        }
+        unattributed.sort();
+        let packages = counts
+            .into_iter()
+            .map(|(name, files)| {
+                let package = installed
+                    .iter()
+                    .find(|p| p.name == name)
+                    .cloned()
+                    .unwrap_or_else(|| ApkPackage {
+                        name: name.to_string(),
+                        version: String::new(),
+                        arch: String::new(),
+                        origin: String::new(),
+                        license: String::new(),
+                    });
+                (package, files)
+            })
+            .collect();
+        Self {
+            packages,
+            unattributed,
+        }
+    }
+
+    /// Attribute `copied` with the installed database of `source`.
+    pub fn load(source: &Path, copied: &[PathBuf]) -> Result<Self> {
+        let installed = installed_packages(source)?;
+        let owners = FileOwners::load(source)?;
+        Ok(Self::new(&installed, &owners, copied))
+    }
+
+    /// Names of the packages whose licenses must ship.
+    pub fn package_names(&self) -> impl Iterator<Item = &str> {
+        self.packages.iter().map(|(p, _)| p.name.as_str())
+    }
+
+    /// The report: a table of packages, then the unattributed files.
+    pub fn render(&self) -> String {
+        let attributed: usize = self.packages.iter().map(|(_, n)| n).sum();
+        let mut out = format!(
+            "# Licenses of the files copied from the Alpine rootfs\n\
+             # {} files from {} packages; {} owned by no package\n\n",
+            attributed,
+            self.packages.len(),
+            self.unattributed.len()
+        );
+
+        let header = ["PACKAGE", "VERSION", "LICENSE", "FILES"];
+        let rows: Vec<[String; 4]> = self
+            .packages
+            .iter()
+            .map(|(p, files)| {
+                let license = if p.license.is_empty() {
+                    "(unknown)"
+                } else {
+                    &p.license
+                };
+                [
+                    p.name.clone(),
+                    p.version.clone(),
+                    license.to_string(),
+                    files.to_string(),
+                ]
+            })
+            .collect();
+        let width = |column: usize| {
+            rows.iter()
+                .map(|r| r[column].len())
+                .chain([header[column].len()])
+                .max()
+                .unwrap_or(0)
+        };
+        let (name, version, license) = (width(0), width(1), width(2));
+        let mut line = |cells: [&str; 4]| {
+            out += &format!(
+                "{:<name$}  {:<version$}  {:<license$}  {}\n",
+                cells[0], cells[1], cells[2], cells[3]
+            );
+        };
+        line(header);
+        for row in &rows {
+            line([&row[0], &row[1], &row[2], &row[3]]);
+        }
+
+        if !self.unattributed.is_empty() {
+            out += "\n# Owned by no package (license not shipped)\n";
+            for path in &self.unattributed {
+                out += &format!("{}\n", path);
+            }
+        }
+        out
+    }
+
+    /// Write the report into `output_dir`.
+    pub fn write(&self, output_dir: &Path) -> Result<PathBuf> {
+        let path = output_dir.join(LICENSES_REPORT_FILE);
+        fs::create_dir_all(output_dir)
+            .with_context(|| format!("Failed to create {}", output_dir.display()))?;
+        fs::write(&path, self.render())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apk::parse_installed;
+
+    const DB: &str = "P:busybox\nV:1.37.0-r0\nL:GPL-2.0-only\nF:bin\nR:busybox\n\n\
+                      P:zlib\nV:1.3.1-r2\nL:Zlib\nF:usr/lib\nR:libz.so.1\nR:libz.so.1.3.1\n\n";
+
+    #[test]
+    fn test_report_counts_files_and_lists_unattributed() {
+        let copied: Vec<PathBuf> = ["bin/busybox", "lib/libz.so.1", "usr/lib/libz.so.1.3.1"]
+            .iter()
+            .chain(&["etc/motd"])
+            .map(PathBuf::from)
+            .collect();
+        let report = LicenseReport::new(&parse_installed(DB), &FileOwners::parse(DB), &copied);
+        assert_eq!(
+            report.package_names().collect::<Vec<_>>(),
+            ["busybox", "zlib"]
+        );
+        assert_eq!(report.packages[1].1, 2);
+        assert_eq!(report.unattributed, ["etc/motd"]);
+
+        let text = report.render();
+        assert!(text.contains("# 3 files from 2 packages; 1 owned by no package"));
+        assert!(
+            text.contains("PACKAGE  VERSION    LICENSE       FILES\n"),
+            "{}",
+            text
+        );
+        assert!(
+            text.contains("zlib     1.3.1-r2   Zlib          2\n"),
+            "{}",
+            text
+        );
+        assert!(
+            text.ends_with("(license not shipped)\netc/motd\n"),
+            "{}",
+            text
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! Log levels for the build output (`-v`, `-vv`, `--quiet`).
+//!
+//! Build stages log through the `log` facade:
+//!
+//! - `--quiet`: warnings and errors only (final artifact paths are always
+//!   printed)
+//! - default: the high-level stage messages
+//! - `-v`: every component op the executor runs, with its staging paths
+//! - `-vv`: per-binary detail (source lookup and destination of each copy)
+//!
+//! The level is exported as `ACORNOS_VERBOSITY` so the parallel child builds
+//! log at the same level.
+
+use log::{Level, LevelFilter};
+use std::io::Write;
+
+use crate::term::{self, Status};
+
+/// Verbosity passed to child builds.
+pub const VERBOSITY_ENV: &str = "ACORNOS_VERBOSITY";
+
+/// How much build output to show.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,
+    #[default]
+    Normal,
+    /// `-v`
+    Verbose,
+    /// `-vv` (or more)
+    Debug,
+}
+
+impl Verbosity {
+    /// From the CLI flags, else `ACORNOS_VERBOSITY` (as set for child builds).
+    pub fn resolve(verbose: u8, quiet: bool) -> Self {
+        if quiet {
+            return Self::Quiet;
+        }
+        match verbose {
+            0 => std::env::var(VERBOSITY_ENV)
+                .ok()
+                .and_then(|v| Self::from_name(v.trim()))
+                .unwrap_or_default(),
+            1 => Self::Verbose,
+            _ => Self::Debug,
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "quiet" => Some(Self::Quiet),
+            "normal" => Some(Self::Normal),
+            "verbose" => Some(Self::Verbose),
+            "debug" => Some(Self::Debug),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Quiet => "quiet",
+            Self::Normal => "normal",
+            Self::Verbose => "verbose",
+            Self::Debug => "debug",
+        }
+    }
+
+    pub fn level(self) -> LevelFilter {
+        match self {
+            Self::Quiet => LevelFilter::Warn,
+            Self::Normal => LevelFilter::Info,
+            Self::Verbose => LevelFilter::Debug,
+            Self::Debug => LevelFilter::Trace,
+        }
+    }
+
+    /// Export as `ACORNOS_VERBOSITY` for child builds.
+    ///
+    /// Must be called before any threads are spawned.
+    pub fn export(self) {
+        std::env::set_var(VERBOSITY_ENV, self.name());
+    }
+}
+
+/// Whether `--quiet` is in effect.
+pub fn is_quiet() -> bool {
+    log::max_level() < LevelFilter::Info
+}
+
+/// Install the logger: everything goes to stdout as the stage messages
+/// always did, warnings and errors with their usual prefixes. Status
+/// markers are colored and aligned ([`crate::term`]).
+pub fn init(verbosity: Verbosity) {
+    let _ = env_logger::Builder::new()
+        .filter_level(verbosity.level())
+        .format(|buf, record| match record.level() {
+            Level::Error => writeln!(
+                buf,
+                "{} {}",
+                term::paint(Status::Fail, "[ERROR]"),
+                record.args()
+            ),
+            Level::Warn => writeln!(
+                buf,
+                "{} {}",
+                term::paint(Status::Warn, "[WARN]"),
+                record.args()
+            ),
+            _ => writeln!(buf, "{}", term::decorate(&record.args().to_string())),
+        })
+        .target(env_logger::Target::Stdout)
+        .try_init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verbosity_from_flags() {
+        assert_eq!(Verbosity::resolve(2, true), Verbosity::Quiet);
+        assert_eq!(Verbosity::resolve(1, false), Verbosity::Verbose);
+        assert_eq!(Verbosity::resolve(3, false), Verbosity::Debug);
+        assert_eq!(Verbosity::Quiet.level(), LevelFilter::Warn);
+        assert_eq!(Verbosity::Debug.level(), LevelFilter::Trace);
+        for v in [
+            Verbosity::Quiet,
+            Verbosity::Normal,
+            Verbosity::Verbose,
+            Verbosity::Debug,
+        ] {
+            assert_eq!(Verbosity::from_name(v.name()), Some(v));
+        }
+    }
+}
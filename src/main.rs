@@ -20,15 +20,33 @@
 //! # Build EROFS rootfs only
 //! acornos build rootfs
 //!
+//! # Build with a squashfs rootfs instead of EROFS
+//! acornos build --rootfs-format squashfs
+//!
+//! # Build the kernel (runs cargo xtask kernels build acorn from the monorepo root)
+//! acornos kernel build
+//!
+//! # Build kernel + initramfs + rootfs for PXE/HTTP netboot
+//! acornos build netboot --base-url http://10.0.0.1/acornos
+//!
 //! # Build complete ISO (rootfs + initramfs + ISO)
 //! acornos build
 //!
 //! # Rebuild only the initramfs
 //! acornos initramfs
 //!
+//! # Rebuild even if inputs are unchanged (skips artifact-store restore)
+//! acornos build --force
+//!
 //! # Rebuild only the ISO
 //! acornos iso
 //!
+//! # Remove output artifacts (or: clean downloads | cache | all)
+//! acornos clean
+//!
+//! # Check stored artifacts against their checksums
+//! acornos store verify
+//!
 //! # Run in QEMU
 //! acornos run
 //! ```
@@ -43,26 +61,60 @@
 //! | Coreutils | GNU | busybox |
 //! | Shell | bash | ash (busybox) |
 
+use acornos::paths::OutputPaths;
+use acornos::term::{self, Fields, Status};
 use anyhow::Result;
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
-
-fn open_artifact_store(
-    base_dir: &std::path::Path,
-) -> Option<distro_builder::artifact_store::ArtifactStore> {
-    match distro_builder::artifact_store::ArtifactStore::open_for_distro(base_dir) {
-        Ok(s) => Some(s),
-        Err(e) => {
-            eprintln!("[WARN] Artifact store disabled: {:#}", e);
-            None
-        }
-    }
-}
+use clap::{Args, Parser, Subcommand};
+use std::path::{Path, PathBuf};
 
 #[derive(Parser)]
 #[command(name = "acornos")]
 #[command(author, version, about = "AcornOS ISO builder", long_about = None)]
 struct Cli {
+    /// Target architecture: x86_64 or aarch64 (or ACORNOS_ARCH; default x86_64)
+    #[arg(long, global = true)]
+    arch: Option<acornos::arch::Arch>,
+
+    /// Alpine mirror for the ISO, apk-tools and package repos (or ALPINE_MIRROR; default: dl-cdn)
+    #[arg(long, global = true, value_name = "URL")]
+    alpine_mirror: Option<String>,
+
+    /// Fallback mirror for the ISO and apk-tools downloads, tried in order before dl-cdn and dl-2 (repeatable; or ALPINE_MIRRORS)
+    #[arg(long = "mirror", global = true, value_name = "URL")]
+    mirrors: Vec<String>,
+
+    /// Alpine stable branch to build from, e.g. 3.22 (or ALPINE_VERSION; default: the pinned release)
+    #[arg(long, global = true, value_name = "X.Y")]
+    alpine_version: Option<String>,
+
+    /// Never access the network; fail fast on anything not pre-seeded (or ACORNOS_OFFLINE=1)
+    #[arg(long, global = true)]
+    offline: bool,
+
+    /// Project dir with recipes, downloads/ and profile/ (or ACORNOS_BASE_DIR; default: source checkout)
+    #[arg(long, global = true)]
+    base_dir: Option<PathBuf>,
+
+    /// Build artifacts go here (or ACORNOS_OUTPUT_DIR; default: output/ in the base dir)
+    #[arg(long, global = true)]
+    output_dir: Option<PathBuf>,
+
+    /// More build output: -v shows each component op, -vv each binary copied (or ACORNOS_VERBOSITY)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Only warnings, errors and the final artifact paths
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Plain output without colors (or NO_COLOR; colors are only used on a terminal)
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// Fail instead of waiting when another acornos run holds the output dir
+    #[arg(long, global = true)]
+    no_wait: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -71,37 +123,520 @@ struct Cli {
 enum Commands {
     /// Download Alpine dependencies (ISO and packages)
     Download {
+        /// Always re-run apk instead of restoring the Alpine rootfs from the artifact store
+        #[arg(long, global = true)]
+        no_rootfs_cache: bool,
+
+        /// Re-download the ISO, apk-tools and busybox even if cached
+        #[arg(long, global = true)]
+        force: bool,
+
+        /// Check the Alpine ISO's release signature with gpg
+        #[arg(long, global = true)]
+        verify_gpg: bool,
+
         #[command(subcommand)]
         what: Option<DownloadTarget>,
     },
 
     /// Build artifacts (rootfs, or full build)
     Build {
+        /// Rootfs image format: erofs or squashfs
+        #[arg(long, visible_alias = "format", global = true, default_value = "erofs")]
+        rootfs_format: acornos::artifact::RootfsFormat,
+
+        /// Live session user on tty1: live (doas) or none (root autologin)
+        #[arg(long, global = true, default_value = "live")]
+        live_user: acornos::artifact::live_user::LiveUser,
+
+        /// Also build installed-system UKIs into the ISO's live system (needs ukify)
+        #[arg(long, global = true)]
+        with_ukis: bool,
+
+        /// Also make the ISO bootable on legacy BIOS (x86_64; needs GRUB i386-pc files)
+        #[arg(long, global = true)]
+        bios: bool,
+
+        /// Allow root SSH logins with your ~/.ssh/id_*.pub keys (live overlay only; see run --ssh)
+        #[arg(long, global = true)]
+        ssh: bool,
+
+        /// ISO volume label the initramfs boots from (default: $ISO_LABEL or ACORNOS)
+        #[arg(long, global = true, value_name = "LABEL")]
+        label: Option<acornos::artifact::IsoLabel>,
+
+        #[command(flatten)]
+        rootfs: RootfsArgs,
+
+        /// Leave a non-core component out of the rootfs (repeatable; see components.toml)
+        #[arg(long = "disable-component", value_name = "NAME", global = true)]
+        disable_components: Vec<String>,
+
+        /// Firmware preset for the rootfs: wifi, common or all (default: firmware.toml, else wifi)
+        #[arg(long, value_name = "PRESET", global = true)]
+        firmware: Option<acornos::component::firmware::FirmwarePreset>,
+
+        /// Hardlink files from downloads/rootfs into staging where they cannot be reflinked
+        #[arg(long, global = true)]
+        hardlink_copies: bool,
+
+        #[command(flatten)]
+        compression: CompressionArgs,
+
+        /// Fix every timestamp to $SOURCE_DATE_EPOCH (default: HEAD's commit time)
+        #[arg(long, global = true)]
+        reproducible: bool,
+
+        /// Build twice into output/reproducible/{a,b} and fail if the ISOs differ
+        #[arg(long, requires = "reproducible")]
+        verify_reproducible: bool,
+
+        /// Build a missing kernel with xtask without asking
+        #[arg(long, global = true)]
+        yes: bool,
+
         #[command(subcommand)]
         artifact: Option<BuildArtifact>,
     },
 
     /// Rebuild only the initramfs
-    Initramfs,
+    Initramfs {
+        /// Build the installed-system initramfs (fsck, root= resolution) instead
+        #[arg(long)]
+        installed: bool,
+
+        /// Include cryptsetup + dm-crypt for a LUKS root (with --installed)
+        #[arg(long, requires = "installed")]
+        luks: bool,
+
+        /// Rebuild even if inputs are unchanged, without restoring from the artifact store
+        #[arg(long)]
+        force: bool,
+
+        /// ISO volume label the live initramfs boots from (default: $ISO_LABEL or ACORNOS)
+        #[arg(long, value_name = "LABEL", conflicts_with = "installed")]
+        label: Option<acornos::artifact::IsoLabel>,
+
+        #[command(flatten)]
+        compression: CompressionArgs,
+    },
 
     /// Rebuild only the ISO (requires rootfs and initramfs)
-    Iso,
+    Iso {
+        /// Extra file/directory for the ISO root, as <src>[:dest] (repeatable)
+        #[arg(long = "iso-extra", value_name = "SRC[:DEST]")]
+        iso_extra: Vec<String>,
+
+        /// Live session user on tty1: live (doas) or none (root autologin)
+        #[arg(long, default_value = "live")]
+        live_user: acornos::artifact::live_user::LiveUser,
+
+        /// Also build installed-system UKIs into the live system (needs ukify)
+        #[arg(long)]
+        with_ukis: bool,
+
+        /// Also make the ISO bootable on legacy BIOS (x86_64; needs GRUB i386-pc files)
+        #[arg(long)]
+        bios: bool,
+
+        /// Allow root SSH logins with your ~/.ssh/id_*.pub keys (live overlay only; see run --ssh)
+        #[arg(long)]
+        ssh: bool,
+
+        /// ISO volume label; an initramfs built for another is rebuilt (default: $ISO_LABEL or ACORNOS)
+        #[arg(long, value_name = "LABEL")]
+        label: Option<acornos::artifact::IsoLabel>,
+
+        /// Append kernel options to every live boot entry (repeatable; also $ACORNOS_CMDLINE_APPEND)
+        #[arg(long, value_name = "ARGS", allow_hyphen_values = true)]
+        cmdline_append: Vec<String>,
+
+        /// Drop a default kernel option such as quiet from every live entry (repeatable)
+        #[arg(long, value_name = "TOKEN")]
+        cmdline_remove: Vec<String>,
+
+        /// live, or netinstall: no rootfs image, installs over the network (acornos-netinstall.iso)
+        #[arg(long, default_value = "live")]
+        variant: acornos::artifact::IsoVariant,
+
+        /// testing (serial root autologin, test markers; for 'acornos test') or release
+        #[arg(long, default_value = "testing")]
+        profile: acornos::artifact::live_profile::LiveProfile,
+
+        /// Rebuild even if components are unchanged, without restoring from the artifact store
+        #[arg(long)]
+        force: bool,
+    },
 
     /// Run the ISO in QEMU (GUI)
-    Run,
+    Run {
+        /// Forward a host TCP port to the guest, as HOST:GUEST (repeatable)
+        #[arg(long, value_name = "HOST:GUEST")]
+        publish: Vec<acornos::qemu::PortForward>,
+
+        /// Share a host directory with the guest over 9p, as DIR[:TAG] (repeatable)
+        #[arg(long, value_name = "DIR[:TAG]")]
+        share: Vec<acornos::qemu::VirtfsShare>,
+
+        /// Discard everything written to the virtual disk when QEMU exits
+        #[arg(long)]
+        snapshot: bool,
+
+        /// Delete and recreate the virtual disk before booting
+        #[arg(long)]
+        fresh_disk: bool,
 
-    /// Test the ISO boots correctly (headless, automated)
+        /// Size of a newly created virtual disk, e.g. 40G (default from distro-spec)
+        #[arg(long, value_name = "SIZE", conflicts_with = "profile")]
+        disk_size: Option<String>,
+
+        /// Boot with the disks of this disks.toml profile (see 'acornos disks list')
+        #[arg(long, value_name = "NAME")]
+        profile: Option<String>,
+
+        /// Forward a host port to the guest's sshd (ISO built with --ssh)
+        #[arg(long)]
+        ssh: bool,
+
+        /// Host port for --ssh; a free one is picked if it is taken
+        #[arg(long, default_value_t = acornos::qemu::DEFAULT_SSH_PORT, requires = "ssh")]
+        ssh_port: u16,
+
+        #[command(flatten)]
+        qemu: QemuArgs,
+    },
+
+    /// Test the ISO (or an installed system's disk) boots correctly (headless, automated)
     Test {
-        /// Timeout in seconds (default: 120)
+        /// Timeout in seconds (default: 120; x3 under TCG)
         #[arg(short, long, default_value = "120")]
         timeout: u64,
+
+        /// Fail if any boot stage exceeds its time budget
+        #[arg(long)]
+        enforce_boot_budget: bool,
+
+        /// Override stage budgets in KVM seconds, e.g. "kernel=20,total=90"
+        #[arg(long, value_name = "STAGE=SECS,...")]
+        boot_budget: Option<String>,
+
+        /// Write the full serial log here (default: output/test-serial.log)
+        #[arg(long, visible_alias = "keep-log", value_name = "PATH")]
+        log_path: Option<PathBuf>,
+
+        /// Run this shell script in the guest once booted; a nonzero exit fails the test
+        #[arg(long, value_name = "PATH")]
+        verify_script: Option<PathBuf>,
+
+        /// Boot firmware: uefi or bios (bios needs an ISO built with --bios)
+        #[arg(long, default_value = "uefi")]
+        firmware: acornos::qemu::Firmware,
+
+        /// Fail if shell-ready is slower than this many seconds (plus the tolerance)
+        #[arg(long, value_name = "SECS")]
+        baseline: Option<f64>,
+
+        /// Slowdown over --baseline still accepted, in percent
+        #[arg(long, value_name = "PERCENT", default_value_t = acornos::boot_history::DEFAULT_BASELINE_TOLERANCE)]
+        baseline_tolerance: f64,
+
+        /// Attach the disks of this disks.toml profile (see 'acornos disks list')
+        #[arg(long, value_name = "NAME")]
+        profile: Option<String>,
+
+        /// Boot the kernel directly with these options appended to the default entry (no ISO rebuild)
+        #[arg(long, value_name = "ARGS", allow_hyphen_values = true)]
+        cmdline_append: Vec<String>,
+
+        /// Boot this qcow2 of an installed system (UEFI, no CD-ROM) instead of the ISO
+        #[arg(
+            long,
+            visible_alias = "installed",
+            value_name = "PATH",
+            conflicts_with_all = ["cmdline_append", "baseline"]
+        )]
+        disk: Option<PathBuf>,
+
+        /// Root password of the installed system on --disk, if it has one
+        #[arg(long, value_name = "PASSWORD", requires = "disk")]
+        root_password: Option<String>,
+
+        /// Install the ISO onto a blank disk over serial, then boot and check it (transcript: output/install-test.log)
+        #[arg(
+            long,
+            conflicts_with_all = ["disk", "cmdline_append", "baseline", "profile", "history"]
+        )]
+        install: bool,
+
+        /// Print the last N runs from output/boot-times.jsonl instead of booting (default: 10)
+        #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "10")]
+        history: Option<usize>,
+
+        #[command(flatten)]
+        qemu: QemuArgs,
+    },
+
+    /// Verify a released ISO's SHA512 checksum and signature
+    VerifyRelease {
+        /// Path to the ISO (expects <iso>.sha512 and <iso>.sha512.sig alongside)
+        iso: PathBuf,
+
+        /// Public key (minisign .pub, or gpg keyring for gpgv)
+        #[arg(long)]
+        pubkey: PathBuf,
+
+        /// Signature tool: minisign or gpg
+        #[arg(long, default_value = "minisign")]
+        method: String,
+    },
+
+    /// Inspect a built ISO without booting it (label, boot files, checksum)
+    VerifyIso {
+        /// ISO to check (default: output/acornos.iso)
+        #[arg(long)]
+        iso: Option<PathBuf>,
+
+        /// Expected volume label (default: the one the last build used)
+        #[arg(long, value_name = "LABEL")]
+        label: Option<acornos::artifact::IsoLabel>,
+    },
+
+    /// Compare two built ISOs: files, rootfs contents, manifest, os-release, GRUB config
+    ///
+    /// Exits 1 if they differ, unless --report-only.
+    DiffIso {
+        /// Older ISO
+        old: PathBuf,
+
+        /// Newer ISO
+        new: PathBuf,
+
+        /// Also write the full diff as JSON to PATH
+        #[arg(long, value_name = "PATH")]
+        output: Option<PathBuf>,
+
+        /// Exit 0 even if the ISOs differ
+        #[arg(long)]
+        report_only: bool,
+    },
+
+    /// Write the ISO to a USB stick (removable devices only unless --force)
+    Flash {
+        /// Whole-disk block device, e.g. /dev/sdb
+        device: PathBuf,
+
+        /// ISO to write (default: output/acornos.iso)
+        #[arg(long)]
+        iso: Option<PathBuf>,
+
+        /// Write even if the device is not marked removable
+        #[arg(long)]
+        force: bool,
+
+        /// Do not ask for confirmation
+        #[arg(long)]
+        yes: bool,
+    },
+
+    /// Print what the rootfs components would do, without building (dry run)
+    Plan {
+        /// Write the plan as JSON to PATH instead ("-" or no PATH for stdout)
+        #[arg(long, value_name = "PATH", num_args = 0..=1, default_missing_value = "-")]
+        json: Option<PathBuf>,
+
+        /// Leave a non-core component out, as for build (repeatable)
+        #[arg(long = "disable-component", value_name = "NAME")]
+        disable_components: Vec<String>,
+    },
+
+    /// Graph what the rootfs would do at boot, without booting it
+    Graph {
+        #[command(subcommand)]
+        what: GraphTarget,
+    },
+
+    /// Open a shell inside the staged rootfs (chroot with /proc, /sys and /dev)
+    Shell {
+        /// Enter output/rootfs-staging (default)
+        #[arg(long, conflicts_with = "rootfs")]
+        staging: bool,
+
+        /// Enter downloads/rootfs, the Alpine rootfs components copy from
+        #[arg(long)]
+        rootfs: bool,
+    },
+
+    /// Record or compare the build environment (tool versions, inputs, overrides)
+    Env {
+        #[command(subcommand)]
+        action: EnvAction,
     },
 
     /// Validate host tools and prerequisites (xorriso, mkfs.erofs, etc.)
     Preflight,
 
+    /// Build the kernel with the monorepo's xtask, or show the installed one
+    Kernel {
+        #[command(subcommand)]
+        action: KernelAction,
+    },
+
     /// Show build status and next steps
-    Status,
+    Status {
+        /// Show the phase timings of the last full build
+        #[arg(long)]
+        timings: bool,
+    },
+
+    /// Remove build state (default: output artifacts, keeping downloads)
+    Clean {
+        #[command(subcommand)]
+        what: Option<CleanScope>,
+    },
+
+    /// List stale output entries (unknown artifacts, crashed-build leftovers)
+    Gc {
+        /// Delete them instead of only listing them
+        #[arg(long)]
+        yes: bool,
+
+        /// Age in hours after which *.tmp and *.work entries count as crashed
+        #[arg(long, value_name = "HOURS", default_value_t = acornos::gc::DEFAULT_MAX_AGE_HOURS)]
+        older_than: u64,
+    },
+
+    /// Manage the virtual disk profiles of disks.toml
+    Disks {
+        #[command(subcommand)]
+        action: DisksAction,
+    },
+
+    /// Inspect artifact store entries
+    Store {
+        #[command(subcommand)]
+        action: StoreAction,
+    },
+
+    /// Print the build manifest (packages, kernel, artifact hashes)
+    Manifest {
+        /// Regenerate from existing artifacts instead of printing the saved one
+        #[arg(long)]
+        regenerate: bool,
+
+        /// Print the package manifest (name, version, license, origin) instead
+        #[arg(long)]
+        packages: bool,
+
+        /// Package manifest format: json or spdx (SPDX 2.3 JSON)
+        #[arg(long, default_value = "json", requires = "packages")]
+        format: acornos::package_manifest::ManifestFormat,
+    },
+}
+
+/// Rootfs build flags of `build`, passed on to the child `build rootfs`.
+#[derive(Args)]
+struct RootfsArgs {
+    /// Treat dangling (non-runlevel) symlinks in the staging tree as errors
+    #[arg(long, global = true)]
+    strict_links: bool,
+
+    /// Skip post-build verification of the rootfs image
+    #[arg(long, global = true)]
+    skip_verify: bool,
+
+    /// Rebuild even if inputs are unchanged, without restoring from the artifact store
+    #[arg(long, global = true)]
+    force: bool,
+
+    /// Wipe the rootfs staging tree instead of re-running only changed components
+    #[arg(long, global = true)]
+    clean_staging: bool,
+
+    /// Continue a failed rootfs build from the component that failed
+    #[arg(long, global = true, conflicts_with = "clean_staging")]
+    resume: bool,
+
+    /// Build even if the source rootfs lacks binaries or files components copy
+    #[arg(long, global = true)]
+    keep_going: bool,
+
+    /// Compression threads for mkfs.erofs/mksquashfs (default: physical cores)
+    #[arg(long, global = true, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    jobs: Option<u64>,
+}
+
+impl RootfsArgs {
+    /// Pipeline building the rootfs in `format` with these flags.
+    fn pipeline(
+        &self,
+        base_dir: &std::path::Path,
+        format: acornos::artifact::RootfsFormat,
+    ) -> acornos::pipeline::BuildPipeline {
+        let mut pipeline = acornos::pipeline::BuildPipeline::new(base_dir);
+        pipeline.format = format;
+        pipeline.strict_links = self.strict_links;
+        pipeline.skip_verify = self.skip_verify;
+        pipeline.force = self.force;
+        pipeline.clean_staging = self.clean_staging;
+        pipeline.resume = self.resume;
+        pipeline.keep_going = self.keep_going;
+        pipeline.jobs = self.jobs.map(|jobs| jobs as usize);
+        pipeline
+    }
+}
+
+/// Live initramfs compression, shared by `build` and `initramfs`.
+#[derive(Args)]
+struct CompressionArgs {
+    /// Live initramfs compression: gzip, xz or zstd (zstd unpacks fastest on slow media)
+    #[arg(long, global = true, default_value = "gzip")]
+    initramfs_compression: acornos::artifact::Compressor,
+
+    /// Compression level (default: gzip 6, xz 6, zstd 19)
+    #[arg(long, global = true, value_name = "LEVEL")]
+    initramfs_compression_level: Option<u32>,
+}
+
+impl CompressionArgs {
+    fn resolve(&self) -> Result<acornos::artifact::InitramfsCompression> {
+        acornos::artifact::InitramfsCompression::new(
+            self.initramfs_compression,
+            self.initramfs_compression_level,
+        )
+    }
+}
+
+/// Guest resources shared by `run` and `test`.
+#[derive(Args)]
+struct QemuArgs {
+    /// Guest memory, e.g. 512M or 2G (at least 256M; default from distro-spec)
+    #[arg(long, value_name = "SIZE")]
+    memory: Option<acornos::qemu::MemorySize>,
+
+    /// Number of virtual CPUs
+    #[arg(long)]
+    cpus: Option<u32>,
+
+    /// Extra argument appended to the QEMU command line (repeatable), e.g. --qemu-arg=-device --qemu-arg=usb-tablet
+    #[arg(long = "qemu-arg", value_name = "ARG", allow_hyphen_values = true)]
+    qemu_args: Vec<String>,
+
+    /// Run without KVM (TCG software emulation), e.g. when /dev/kvm is not accessible
+    #[arg(long)]
+    no_kvm: bool,
+}
+
+impl QemuArgs {
+    /// `verbose` (the global `-v`) prints the final QEMU command line.
+    fn into_resources(self, verbose: bool) -> acornos::qemu::QemuResources {
+        acornos::qemu::QemuResources {
+            memory: self.memory,
+            cpus: self.cpus,
+            extra_args: self.qemu_args,
+            no_kvm: self.no_kvm,
+            verbose,
+        }
+    }
 }
 
 #[derive(Subcommand)]
@@ -112,427 +647,1215 @@ enum DownloadTarget {
     Tools,
     /// Download everything
     All,
+    /// Verify cached downloads against their checksums without fetching
+    Check,
+}
+
+#[derive(Subcommand)]
+enum GraphTarget {
+    /// OpenRC service start order per runlevel, with anomalies flagged
+    Services {
+        /// Rootfs to read (default: the rootfs staging tree)
+        #[arg(long)]
+        rootfs: Option<PathBuf>,
+
+        /// Write the Graphviz DOT graph to PATH ("-" for stdout; default:
+        /// output/openrc-services.dot)
+        #[arg(long, value_name = "PATH")]
+        output: Option<PathBuf>,
+
+        /// Also print the start order as an ASCII tree
+        #[arg(long)]
+        ascii: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum CleanScope {
+    /// Remove the downloads directory
+    Downloads,
+    /// Clear rebuild hashes and recipe state markers
+    Cache,
+    /// Remove output, downloads and cache
+    All,
+}
+
+#[derive(Subcommand)]
+enum DisksAction {
+    /// List the profiles, their disks and which exist
+    List,
+    /// Delete a profile's disks (recreated empty on the next boot)
+    Reset {
+        /// Profile name
+        profile: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum EnvAction {
+    /// Write output/build-env.json (also embedded in every ISO)
+    Export,
+    /// Compare this host with an exported build-env.json
+    Diff {
+        /// build-env.json from the other build (e.g. /usr/share/acornos/build-env.json)
+        file: PathBuf,
+    },
 }
 
-#[derive(Subcommand)]
-enum BuildArtifact {
-    /// Build only the EROFS rootfs image
-    Rootfs,
+#[derive(Subcommand)]
+enum KernelAction {
+    /// Run 'cargo xtask kernels build acorn' from the monorepo root and install the kernel
+    Build,
+    /// Show the installed kernel's release and whether it was built for AcornOS
+    Status,
+}
+
+#[derive(Subcommand)]
+enum StoreAction {
+    /// Check every stored artifact against its recorded checksum
+    Verify,
+}
+
+#[derive(Subcommand)]
+enum BuildArtifact {
+    /// Build only the rootfs image
+    Rootfs,
+    /// Build the kernel (same as 'acornos kernel build')
+    Kernel,
+    /// Build output/netboot/ (kernel, netboot initramfs, rootfs, iPXE/GRUB stanzas)
+    Netboot {
+        /// URL output/netboot/ will be served from (default: http://${next-server}/acornos)
+        #[arg(long, value_name = "URL")]
+        base_url: Option<String>,
+    },
+}
+
+fn main() {
+    if std::env::var_os("ACORNOS_ALLOW_LEGACY_ENTRYPOINT").is_none() {
+        eprintln!(
+            "Deprecated entrypoint blocked: 'acornos'.\n\
+             Use the new Stage 00 endpoint instead:\n\
+               just build acorn\n\
+             or:\n\
+               cargo run -p distro-builder --bin distro-builder -- iso build acorn"
+        );
+        std::process::exit(2);
+    }
+
+    let cli = Cli::parse();
+
+    // Exported so child builds print plain output too
+    acornos::term::export_no_color(cli.no_color);
+
+    // Exported so the recipes and parallel child builds target the same arch
+    let arch = match acornos::arch::Arch::resolve(cli.arch) {
+        Ok(arch) => arch,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    arch.export();
+
+    // Exported for the same reason: the recipes build the rootfs from it
+    let source = match acornos::alpine_source::AlpineSource::resolve(
+        cli.alpine_mirror.as_deref(),
+        cli.alpine_version.as_deref(),
+        &cli.mirrors,
+    ) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    source.export();
+
+    // Exported for the same reason: child builds log at the same level
+    let verbosity = acornos::logging::Verbosity::resolve(cli.verbose, cli.quiet);
+    verbosity.export();
+    acornos::logging::init(verbosity);
+    let verbose = verbosity >= acornos::logging::Verbosity::Verbose;
+
+    // Exported for the same reason: child builds must write to the same dirs
+    acornos::paths::export(&acornos::paths::base_dir(cli.base_dir), cli.output_dir);
+
+    // Held until exit, and exported for the same reason: child builds run
+    // under their parent's lock
+    let output_dir = acornos::paths::output_dir(&acornos::paths::base_dir(None));
+    let lock = match lock_command(&cli.command) {
+        Some(command) if !acornos::output_lock::held_by_parent(&output_dir) => {
+            match acornos::output_lock::OutputLock::acquire(&output_dir, command, !cli.no_wait) {
+                Ok(lock) => {
+                    acornos::output_lock::export();
+                    Some(lock)
+                }
+                Err(e) => {
+                    eprintln!("Error: {:#}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(_) => None,
+        None => {
+            if matches!(
+                cli.command,
+                Commands::Status { .. }
+                    | Commands::Preflight
+                    | Commands::Run { .. }
+                    | Commands::Test { .. }
+            ) {
+                if let Some(holder) = acornos::output_lock::holder(&output_dir) {
+                    log::warn!(
+                        "{}; its artifacts may change under this command",
+                        holder.describe()
+                    );
+                }
+            }
+            None
+        }
+    };
+
+    // Exported for the same reason: the alpine recipe verifies the ISO against the pin
+    match acornos::download::alpine_iso(&acornos::paths::base_dir(None), arch, &source) {
+        Ok(iso) => acornos::pins::export(iso.sha256.as_deref()),
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+
+    // Exported for the same reason: child builds must not download either
+    let offline = acornos::offline::is_offline(cli.offline);
+    if offline {
+        acornos::offline::export();
+    }
+
+    // Exported for the same reason: the rootfs is built by a child process
+    if let Commands::Build {
+        disable_components, ..
+    }
+    | Commands::Plan {
+        disable_components, ..
+    } = &cli.command
+    {
+        acornos::component::selection::export(disable_components);
+    }
+    if let Commands::Build {
+        firmware,
+        hardlink_copies,
+        ..
+    } = &cli.command
+    {
+        acornos::component::firmware::export(*firmware);
+        acornos::fast_copy::export(*hardlink_copies);
+    }
+    if let Commands::Build {
+        reproducible: true, ..
+    } = &cli.command
+    {
+        let base_dir = acornos::paths::base_dir(None);
+        match acornos::reproducible::source_date_epoch(&base_dir) {
+            Ok(epoch) => acornos::reproducible::export(epoch),
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let result = match cli.command {
+        Commands::Download {
+            what: Some(DownloadTarget::Check),
+            ..
+        } => cmd_download_check(arch, offline),
+        Commands::Download { force: true, .. } if offline => Err(anyhow::anyhow!(
+            "--force re-downloads, which needs network access (drop --offline)"
+        )),
+        // Offline, downloads are verified instead of resolved through the recipes
+        Commands::Download {
+            verify_gpg: true, ..
+        } if offline => Err(anyhow::anyhow!(
+            "--verify-gpg fetches the release signature, which needs network access (drop --offline)"
+        )),
+        Commands::Download { what, .. } if offline => cmd_download_offline(what),
+        Commands::Download {
+            what,
+            no_rootfs_cache,
+            force,
+            verify_gpg,
+        } => match what {
+            Some(DownloadTarget::Alpine) => {
+                cmd_download_alpine(no_rootfs_cache, force, verify_gpg, arch)
+            }
+            Some(DownloadTarget::Tools) => cmd_download_tools(),
+            Some(DownloadTarget::All) | None => cmd_download_all(force, verify_gpg, arch),
+            Some(DownloadTarget::Check) => unreachable!("handled above"),
+        },
+        Commands::Build {
+            rootfs_format,
+            live_user,
+            with_ukis,
+            bios,
+            ssh,
+            label,
+            rootfs,
+            compression,
+            verify_reproducible,
+            yes,
+            artifact,
+            ..
+        } => match artifact {
+            Some(_) if verify_reproducible => Err(anyhow::anyhow!(
+                "--verify-reproducible compares full builds (drop the artifact)"
+            )),
+            Some(BuildArtifact::Rootfs) => cmd_build_rootfs(rootfs_format, &rootfs),
+            Some(BuildArtifact::Kernel) => cmd_kernel_build(),
+            Some(BuildArtifact::Netboot { base_url }) => {
+                cmd_build_netboot(rootfs_format, live_user, base_url, &rootfs, yes)
+            }
+            None => compression.resolve().and_then(|compression| {
+                let iso_options = acornos::artifact::IsoOptions {
+                    live_user,
+                    arch,
+                    with_ukis,
+                    bios,
+                    ssh,
+                    label: acornos::artifact::IsoLabel::resolve(label)?,
+                    cmdline: acornos::artifact::CmdlineEdit::with_env(&[], &[])?,
+                    ..Default::default()
+                };
+                if verify_reproducible {
+                    cmd_verify_reproducible(rootfs_format, compression, iso_options, &rootfs)
+                } else {
+                    cmd_build(rootfs_format, compression, iso_options, &rootfs, yes)
+                }
+            }),
+        },
+        Commands::Initramfs {
+            installed,
+            luks,
+            force,
+            label,
+            compression,
+        } => {
+            if installed {
+                cmd_initramfs_installed(luks, force)
+            } else {
+                compression.resolve().and_then(|compression| {
+                    cmd_initramfs(
+                        compression,
+                        acornos::artifact::IsoLabel::resolve(label)?,
+                        force,
+                    )
+                })
+            }
+        }
+        Commands::Iso {
+            iso_extra,
+            live_user,
+            with_ukis,
+            bios,
+            ssh,
+            label,
+            cmdline_append,
+            cmdline_remove,
+            variant,
+            profile,
+            force,
+        } => acornos::artifact::IsoLabel::resolve(label).and_then(|label| {
+            cmd_iso(
+                &iso_extra,
+                acornos::artifact::IsoOptions {
+                    live_user,
+                    with_ukis,
+                    bios,
+                    ssh,
+                    arch,
+                    label,
+                    cmdline: acornos::artifact::CmdlineEdit::with_env(
+                        &cmdline_append,
+                        &cmdline_remove,
+                    )?,
+                    variant,
+                    profile,
+                    ..Default::default()
+                },
+                force,
+            )
+        }),
+        Commands::Run {
+            publish,
+            share,
+            snapshot,
+            fresh_disk,
+            disk_size,
+            profile,
+            ssh,
+            ssh_port,
+            qemu,
+        } => cmd_run(acornos::qemu::RunOptions {
+            disk_size,
+            snapshot,
+            fresh_disk,
+            profile,
+            forwards: publish,
+            shares: share,
+            arch,
+            resources: qemu.into_resources(verbose),
+            ssh_port: ssh.then_some(ssh_port),
+        }),
+        Commands::Test {
+            timeout,
+            enforce_boot_budget,
+            boot_budget,
+            log_path,
+            verify_script,
+            firmware,
+            baseline,
+            baseline_tolerance,
+            profile,
+            cmdline_append,
+            disk,
+            root_password,
+            install,
+            history,
+            qemu,
+        } => match history {
+            Some(count) => cmd_test_history(count),
+            None => baseline
+                .map(|secs| acornos::boot_history::Baseline::new(secs, baseline_tolerance))
+                .transpose()
+                .and_then(|baseline| {
+                    cmd_test(
+                        acornos::qemu::TestOptions {
+                            cmdline: acornos::artifact::CmdlineEdit::new(&cmdline_append, &[])?,
+                            timeout,
+                            enforce_budget: enforce_boot_budget,
+                            arch,
+                            log_path,
+                            verify_script,
+                            firmware,
+                            resources: qemu.into_resources(verbose),
+                            baseline,
+                            profile,
+                            disk,
+                            root_password,
+                            ..Default::default()
+                        },
+                        boot_budget.as_deref(),
+                        install,
+                    )
+                }),
+        },
+        Commands::VerifyRelease {
+            iso,
+            pubkey,
+            method,
+        } => cmd_verify_release(&iso, &pubkey, &method),
+        Commands::VerifyIso { iso, label } => cmd_verify_iso(iso, label, arch),
+        Commands::DiffIso {
+            old,
+            new,
+            output,
+            report_only,
+        } => cmd_diff_iso(&old, &new, output, report_only),
+        Commands::Flash {
+            device,
+            iso,
+            force,
+            yes,
+        } => cmd_flash(&device, iso, acornos::flash::FlashOptions { force, yes }),
+        Commands::Plan { json, .. } => cmd_plan(json),
+        Commands::Graph {
+            what:
+                GraphTarget::Services {
+                    rootfs,
+                    output,
+                    ascii,
+                },
+        } => cmd_graph_services(rootfs, output, ascii),
+        Commands::Shell { rootfs, .. } => cmd_shell(rootfs),
+        Commands::Env { action } => cmd_env(action),
+        Commands::Preflight => cmd_preflight(),
+        Commands::Kernel {
+            action: KernelAction::Build,
+        } => cmd_kernel_build(),
+        Commands::Kernel {
+            action: KernelAction::Status,
+        } => cmd_kernel_status(),
+        Commands::Status { timings } => cmd_status(timings),
+        Commands::Clean { what } => cmd_clean(what),
+        Commands::Gc { yes, older_than } => cmd_gc(yes, older_than),
+        Commands::Disks { action } => cmd_disks(action),
+        Commands::Store {
+            action: StoreAction::Verify,
+        } => cmd_store_verify(),
+        Commands::Manifest {
+            regenerate,
+            packages,
+            format,
+        } => {
+            if packages {
+                cmd_package_manifest(regenerate, format)
+            } else {
+                cmd_manifest(regenerate)
+            }
+        }
+    };
+
+    // exit() skips destructors
+    drop(lock);
+    if let Err(e) = result {
+        eprintln!("Error: {:#}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Name of a command that changes the output dir and takes its lock.
+fn lock_command(command: &Commands) -> Option<&'static str> {
+    match command {
+        Commands::Build { .. } => Some("build"),
+        Commands::Initramfs { .. } => Some("initramfs"),
+        Commands::Iso { .. } => Some("iso"),
+        Commands::Kernel {
+            action: KernelAction::Build,
+        } => Some("kernel build"),
+        Commands::Clean { .. } => Some("clean"),
+        Commands::Gc { yes: true, .. } => Some("gc"),
+        _ => None,
+    }
+}
+
+fn require_conformance_contract() -> Result<()> {
+    distro_spec::conformance::require_valid_contract_for_distro("acorn")
+        .map_err(|e| anyhow::anyhow!(e))
+}
+
+fn cmd_build(
+    format: acornos::artifact::RootfsFormat,
+    compression: acornos::artifact::InitramfsCompression,
+    iso_options: acornos::artifact::IsoOptions,
+    rootfs: &RootfsArgs,
+    yes: bool,
+) -> Result<()> {
+    use acornos::timing;
+    use std::time::Instant;
+
+    let base_dir = acornos::paths::base_dir(None);
+    let output_dir = acornos::paths::output_dir(&base_dir);
+    let build_start = Instant::now();
+    let build_start_ms = timing::now_ms();
+    // Child builds append their phases here
+    let timings_part = output_dir.join(timing::TIMINGS_PART_FILENAME);
+    let _ = std::fs::remove_file(&timings_part);
+
+    require_conformance_contract()?;
+
+    log::info!("=== Full AcornOS Build ===\n");
+
+    // Rootfs and initramfs have no data dependency: they are built
+    // concurrently as child `acornos` runs so their output can be prefixed
+    let mut pipeline = rootfs.pipeline(&base_dir, format);
+    pipeline.initramfs_compression = compression;
+    pipeline.iso = iso_options;
+    pipeline.parallel_exe = Some(std::env::current_exe()?);
+    ensure_kernel(&pipeline, yes)?;
+    pipeline.build_all()?;
+
+    let collector = timing::collector();
+    collector.merge_file(&timings_part);
+    let _ = std::fs::remove_file(&timings_part);
+    let timings_path = output_dir.join(timing::TIMINGS_FILENAME);
+    collector.write_json(
+        &timings_path,
+        build_start_ms,
+        build_start.elapsed().as_millis() as u64,
+    )?;
+    let phases = collector.phases();
+    let slowest = timing::slowest(&phases, timing::SLOWEST_SHOWN);
+    if !slowest.is_empty() {
+        log::info!("\nSlowest phases:");
+        for phase in slowest {
+            log::info!(
+                "  {:>7}  {}",
+                timing::format_duration(phase.duration_ms),
+                phase.name
+            );
+        }
+    }
+
+    let total = build_start.elapsed().as_secs_f64();
+    if total >= 60.0 {
+        log::info!("\n=== Build Complete ({:.1}m) ===", total / 60.0);
+    } else {
+        log::info!("\n=== Build Complete ({:.1}s) ===", total);
+    }
+    println!("  ISO: {}", OutputPaths::new(&output_dir).iso().display());
+    let manifest = acornos::manifest::write_manifest(&base_dir, true)?;
+    println!("  Manifest: {}", manifest.display());
+    println!("  Timings: {}", timings_path.display());
+    log::info!("\nNext: acornos run");
+
+    Ok(())
+}
+
+/// Full build twice, into `reproducible/a` and `reproducible/b` of the
+/// output dir, comparing the artifacts.
+fn cmd_verify_reproducible(
+    format: acornos::artifact::RootfsFormat,
+    compression: acornos::artifact::InitramfsCompression,
+    iso_options: acornos::artifact::IsoOptions,
+    rootfs: &RootfsArgs,
+) -> Result<()> {
+    use acornos::reproducible;
+
+    let base_dir = acornos::paths::base_dir(None);
+    let output_dir = acornos::paths::output_dir(&base_dir);
+    let main_paths = OutputPaths::new(&output_dir);
+    require_conformance_contract()?;
+
+    let mut builds = Vec::new();
+    for name in ["a", "b"] {
+        let dir = output_dir.join(reproducible::VERIFY_DIR).join(name);
+        log::info!(
+            "=== Reproducibility build {} ({}) ===\n",
+            name,
+            dir.display()
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir)?;
+        let paths = OutputPaths::new(&dir);
+        // The kernel is an input, not something being checked
+        if main_paths.kernel_staging().is_dir() {
+            acornos::tree_copy::copy_tree(
+                &main_paths.kernel_staging(),
+                &paths.kernel_staging(),
+                &paths.kernel_staging(),
+            )?;
+        }
+        acornos::paths::export(&base_dir, Some(dir));
+
+        // Forced: a restore from the artifact store would compare nothing
+        let mut pipeline = rootfs.pipeline(&base_dir, format);
+        pipeline.force = true;
+        pipeline.initramfs_compression = compression;
+        pipeline.iso = iso_options.clone();
+        pipeline.parallel_exe = Some(std::env::current_exe()?);
+        pipeline.build_all()?;
+        builds.push(paths);
+    }
+
+    log::info!("\n=== Comparing builds (SHA512) ===");
+    reproducible::check_comparison(&reproducible::compare_builds(&builds[0], &builds[1])?)?;
+    println!("Reproducible: both builds produced the same ISO");
+    Ok(())
+}
+
+/// Hint printed when a single-artifact command had nothing to do; with
+/// `--quiet` the artifact path is printed instead.
+fn report_outcome(outcome: &acornos::pipeline::BuildOutcome) {
+    if acornos::logging::is_quiet() {
+        println!("{}", outcome.artifact.display());
+    } else if !outcome.rebuilt && !outcome.restored_from_store {
+        println!("  Use --force to rebuild");
+    }
+}
+
+fn cmd_build_rootfs(format: acornos::artifact::RootfsFormat, rootfs: &RootfsArgs) -> Result<()> {
+    let base_dir = acornos::paths::base_dir(None);
+
+    require_conformance_contract()?;
+
+    report_outcome(&rootfs.pipeline(&base_dir, format).build_rootfs()?);
+    Ok(())
+}
+
+fn cmd_build_netboot(
+    format: acornos::artifact::RootfsFormat,
+    live_user: acornos::artifact::live_user::LiveUser,
+    base_url: Option<String>,
+    rootfs: &RootfsArgs,
+    yes: bool,
+) -> Result<()> {
+    let base_dir = acornos::paths::base_dir(None);
+    let options = acornos::artifact::NetbootOptions {
+        base_url,
+        live_user,
+    };
+
+    require_conformance_contract()?;
+
+    // --force applies to the netboot set, not the rootfs it packages
+    let pipeline = rootfs.pipeline(&base_dir, format);
+    ensure_kernel(&pipeline, yes)?;
+    report_outcome(&pipeline.build_netboot(&options)?);
+
+    let note = acornos::preflight::netboot_notice();
+    println!("\nNote: {}", note.message);
+    Ok(())
+}
+
+/// Make sure the kernel is installed, offering to build a missing one
+/// (interactive only; `--yes` builds without asking).
+fn ensure_kernel(pipeline: &acornos::pipeline::BuildPipeline, yes: bool) -> Result<()> {
+    use std::io::IsTerminal;
+
+    let missing = match pipeline.resolve_kernel() {
+        Ok(()) => return Ok(()),
+        Err(e) => e,
+    };
+    let question = format!(
+        "No kernel available. Build it now with '{}'?",
+        acornos::kernel::xtask_command()
+    );
+    let build = yes || (std::io::stdin().is_terminal() && term::confirm(&question)?);
+    if !build {
+        return Err(missing);
+    }
+    install_kernel(pipeline)
+}
+
+/// Build the kernel with xtask and install it into staging.
+fn install_kernel(pipeline: &acornos::pipeline::BuildPipeline) -> Result<()> {
+    acornos::kernel::build(pipeline.base_dir())?;
+    pipeline.resolve_kernel()?;
+    let status = acornos::kernel::KernelStatus::detect(&pipeline.paths());
+    println!(
+        "  Kernel: {} ({})",
+        status.release.as_deref().unwrap_or("unknown release"),
+        status.image.display()
+    );
+    Ok(())
+}
+
+fn cmd_kernel_build() -> Result<()> {
+    let base_dir = acornos::paths::base_dir(None);
+    install_kernel(&acornos::pipeline::BuildPipeline::new(&base_dir))
+}
+
+fn cmd_kernel_status() -> Result<()> {
+    let base_dir = acornos::paths::base_dir(None);
+    let output = OutputPaths::new(&acornos::paths::output_dir(&base_dir));
+    let status = acornos::kernel::KernelStatus::detect(&output);
+
+    println!("Kernel:");
+    let mut fields = Fields::new();
+    match status.size {
+        Some(size) => fields.row(
+            "Image",
+            format!(
+                "{} ({} MB) at {}",
+                term::paint(Status::Ok, "PRESENT"),
+                size / 1024 / 1024,
+                status.image.display()
+            ),
+        ),
+        None => fields.row(
+            "Image",
+            format!(
+                "{} (run 'acornos kernel build')",
+                term::paint(Status::Warn, "NOT BUILT")
+            ),
+        ),
+    };
+    fields
+        .row(
+            "Release",
+            status
+                .release
+                .as_deref()
+                .unwrap_or("unknown (no modules dir)"),
+        )
+        .row("Expected suffix", status.expected_suffix)
+        .row(
+            "Built for AcornOS",
+            if status.built_for_distro() {
+                term::paint(Status::Ok, "yes")
+            } else {
+                term::paint(Status::Warn, "no (run 'acornos kernel build')")
+            },
+        )
+        .print(2);
+    Ok(())
+}
+
+fn cmd_initramfs(
+    compression: acornos::artifact::InitramfsCompression,
+    label: acornos::artifact::IsoLabel,
+    force: bool,
+) -> Result<()> {
+    let base_dir = acornos::paths::base_dir(None);
+
+    require_conformance_contract()?;
+
+    let mut pipeline = acornos::pipeline::BuildPipeline::new(&base_dir);
+    pipeline.force = force;
+    pipeline.initramfs_compression = compression;
+    pipeline.iso.label = label;
+    report_outcome(&pipeline.build_initramfs()?);
+    Ok(())
+}
+
+fn cmd_initramfs_installed(luks: bool, force: bool) -> Result<()> {
+    let base_dir = acornos::paths::base_dir(None);
+
+    require_conformance_contract()?;
+
+    let mut pipeline = acornos::pipeline::BuildPipeline::new(&base_dir);
+    pipeline.force = force;
+    report_outcome(&pipeline.build_installed_initramfs(luks)?);
+    Ok(())
+}
+
+fn cmd_iso(
+    iso_extra: &[String],
+    options: acornos::artifact::IsoOptions,
+    force: bool,
+) -> Result<()> {
+    let base_dir = acornos::paths::base_dir(None);
+
+    require_conformance_contract()?;
+
+    let extras = iso_extra
+        .iter()
+        .map(|spec| acornos::artifact::iso_extra::parse_iso_extra(spec))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut pipeline = acornos::pipeline::BuildPipeline::new(&base_dir);
+    pipeline.force = force;
+    pipeline.iso = acornos::artifact::IsoOptions { extras, ..options };
+    let outcome = pipeline.build_iso()?;
+    if outcome.rebuilt {
+        acornos::manifest::write_manifest(&base_dir, true)?;
+    }
+    report_outcome(&outcome);
+    Ok(())
+}
+
+fn cmd_run(options: acornos::qemu::RunOptions) -> Result<()> {
+    let base_dir = acornos::paths::base_dir(None);
+    acornos::qemu::run_iso_with(&base_dir, &options)
+}
+
+fn cmd_test(
+    mut options: acornos::qemu::TestOptions,
+    boot_budget: Option<&str>,
+    install: bool,
+) -> Result<()> {
+    let base_dir = acornos::paths::base_dir(None);
+    if let Some(spec) = boot_budget {
+        options.budget = options.budget.with_overrides(spec)?;
+    }
+    if install {
+        return acornos::install_test::run(&base_dir, &options);
+    }
+    acornos::qemu::test_iso(&base_dir, &options)
+}
+
+fn cmd_test_history(count: usize) -> Result<()> {
+    use acornos::boot_history::{load, print_history, BOOT_HISTORY_FILENAME};
+
+    let base_dir = acornos::paths::base_dir(None);
+    let output_dir = acornos::paths::output_dir(&base_dir);
+    let records = load(&output_dir, count);
+    if records.is_empty() {
+        println!("No boot test runs recorded yet (run 'acornos test' first).");
+        return Ok(());
+    }
+    println!(
+        "Last {} boot test runs ({}):\n",
+        records.len(),
+        output_dir.join(BOOT_HISTORY_FILENAME).display()
+    );
+    print_history(&records);
+    Ok(())
+}
+
+fn cmd_verify_release(iso: &std::path::Path, pubkey: &std::path::Path, method: &str) -> Result<()> {
+    use acornos::signing::{verify_release, SystemRunner};
+
+    println!("Verifying release {}...", iso.display());
+    verify_release(&SystemRunner, iso, method.parse()?, pubkey)?;
+    println!("  SHA512:    OK");
+    println!("  Signature: OK ({})", pubkey.display());
+    Ok(())
 }
 
-fn main() {
-    if std::env::var_os("ACORNOS_ALLOW_LEGACY_ENTRYPOINT").is_none() {
-        eprintln!(
-            "Deprecated entrypoint blocked: 'acornos'.\n\
-             Use the new Stage 00 endpoint instead:\n\
-               just build acorn\n\
-             or:\n\
-               cargo run -p distro-builder --bin distro-builder -- iso build acorn"
+fn cmd_verify_iso(
+    iso: Option<PathBuf>,
+    label: Option<acornos::artifact::IsoLabel>,
+    arch: acornos::arch::Arch,
+) -> Result<()> {
+    use acornos::artifact::iso_verify::verify_iso_file;
+
+    let base_dir = acornos::paths::base_dir(None);
+    let output_dir = acornos::paths::output_dir(&base_dir);
+    let iso = iso.unwrap_or_else(|| OutputPaths::new(&output_dir).iso());
+    if !iso.exists() {
+        anyhow::bail!(
+            "ISO not found at {}\nRun 'cargo run -- build' first, or pass --iso <path>.",
+            iso.display()
         );
-        std::process::exit(2);
     }
-
-    let cli = Cli::parse();
-
-    let result = match cli.command {
-        Commands::Download { what } => match what {
-            Some(DownloadTarget::Alpine) => cmd_download_alpine(),
-            Some(DownloadTarget::Tools) => cmd_download_tools(),
-            Some(DownloadTarget::All) | None => cmd_download_all(),
-        },
-        Commands::Build { artifact } => match artifact {
-            Some(BuildArtifact::Rootfs) => cmd_build_rootfs(),
-            None => cmd_build(),
-        },
-        Commands::Initramfs => cmd_initramfs(),
-        Commands::Iso => cmd_iso(),
-        Commands::Run => cmd_run(),
-        Commands::Test { timeout } => cmd_test(timeout),
-        Commands::Preflight => cmd_preflight(),
-        Commands::Status => cmd_status(),
+    let label = match label {
+        Some(label) => label,
+        None => acornos::artifact::IsoLabel::load(&output_dir),
     };
 
-    if let Err(e) = result {
-        eprintln!("Error: {:#}", e);
+    println!("=== Verifying {} ===\n", iso.display());
+    let checks = verify_iso_file(&iso, arch, label.as_str(), &output_dir.join("verify-iso"));
+    print!("{}", acornos::preflight::render_checks(&checks));
+
+    let passed = checks.iter().filter(|c| c.passed).count();
+    println!();
+    if passed == checks.len() {
+        println!("All ISO checks passed ({}/{})", passed, checks.len());
+        Ok(())
+    } else {
+        println!("ISO checks failed: {} of {} passed", passed, checks.len());
         std::process::exit(1);
     }
 }
 
-/// Resolve kernel from existing artifacts or the centralized artifact store.
-///
-/// Kernel compilation is centralized in `cargo xtask kernels build acorn` (nightly policy).
-/// This distro builder should never compile kernels implicitly.
-fn resolve_kernel(base_dir: &std::path::Path) -> Result<()> {
-    let store = open_artifact_store(base_dir);
-    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
-    let staging = output_dir.join("staging");
-    let vmlinuz = staging.join("boot/vmlinuz");
-    if vmlinuz.exists() {
-        println!("[SKIP] Kernel already built and installed");
-        return Ok(());
+fn cmd_diff_iso(
+    old: &std::path::Path,
+    new: &std::path::Path,
+    output: Option<PathBuf>,
+    report_only: bool,
+) -> Result<()> {
+    use acornos::artifact::iso_diff::IsoDiff;
+
+    let base_dir = acornos::paths::base_dir(None);
+    let output_dir = acornos::paths::output_dir(&base_dir);
+
+    println!("=== Comparing {} -> {} ===\n", old.display(), new.display());
+    let diff = IsoDiff::compare(old, new, &output_dir.join("diff-iso"))?;
+    print!("{}", diff.to_text());
+    if let Some(path) = output {
+        std::fs::write(&path, diff.to_json(old, new))?;
+        println!("\nWrote {}", path.display());
     }
 
-    // Try to restore from the centralized artifact store first (no compilation).
-    if let Some(store) = &store {
-        let key = output_dir.join(".kernel-inputs.hash");
-        match distro_builder::artifact_store::try_restore_kernel_payload_from_key(
-            store, &key, &staging,
-        ) {
-            Ok(true) => {
-                println!("[RESTORE] Kernel payload restored from artifact store");
-                return Ok(());
-            }
-            Ok(false) => {}
-            Err(e) => eprintln!(
-                "[WARN] Failed to restore kernel payload from artifact store: {:#}",
-                e
-            ),
-        }
+    if !diff.is_empty() && !report_only {
+        std::process::exit(1);
     }
-
-    anyhow::bail!(
-        "No kernel available.\n\n\
-         Kernel compilation is centralized in xtask (nightly build-hours policy).\n\
-         Build the kernels first, then re-run this command:\n\
-           cargo xtask kernels build acorn"
-    )
-}
-
-fn require_conformance_contract() -> Result<()> {
-    distro_spec::conformance::require_valid_contract_for_distro("acorn")
-        .map_err(|e| anyhow::anyhow!(e))
+    Ok(())
 }
 
-fn cmd_build() -> Result<()> {
-    use distro_builder::timing::Timer;
-    use std::time::Instant;
-
-    let base_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    let store = open_artifact_store(&base_dir);
-    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(&base_dir);
-    let build_start = Instant::now();
-
-    require_conformance_contract()?;
+fn cmd_clean(what: Option<CleanScope>) -> Result<()> {
+    use acornos::clean::{clean, human_bytes, CleanTarget};
 
-    println!("=== Full AcornOS Build ===\n");
+    let base_dir = acornos::paths::base_dir(None);
+    let output_dir = acornos::paths::output_dir(&base_dir);
+    let target = match what {
+        None => CleanTarget::Output,
+        Some(CleanScope::Downloads) => CleanTarget::Downloads,
+        Some(CleanScope::Cache) => CleanTarget::Cache,
+        Some(CleanScope::All) => CleanTarget::All,
+    };
 
-    // 1. Resolve kernel (must already be built via xtask)
-    resolve_kernel(&base_dir)?;
+    let summaries = clean(&base_dir, &output_dir, target)?;
+    println!("Cleaned:");
+    for summary in &summaries {
+        println!("  {}", summary);
+    }
+    let total: u64 = summaries.iter().map(|s| s.bytes).sum();
+    println!("  Total: {}", human_bytes(total));
+    Ok(())
+}
 
-    // Try to restore build outputs from the centralized artifact store if the
-    // output files are missing but input hashes are known.
-    if let Some(store) = &store {
-        let rootfs_key = output_dir.join(".rootfs-inputs.hash");
-        let rootfs_out = output_dir.join(distro_spec::acorn::ROOTFS_NAME);
-        match distro_builder::artifact_store::try_restore_file_from_key(
-            store,
-            "rootfs_erofs",
-            &rootfs_key,
-            &rootfs_out,
-        ) {
-            Ok(true) => println!("\n[RESTORE] Rootfs restored from artifact store"),
-            Ok(false) => {}
-            Err(e) => eprintln!(
-                "[WARN] Failed to restore rootfs from artifact store: {:#}",
-                e
-            ),
-        }
+fn cmd_gc(yes: bool, older_than: u64) -> Result<()> {
+    use acornos::clean::human_bytes;
+    use acornos::gc::{find_stale, remove_stale};
+    use std::time::{Duration, SystemTime};
+
+    let base_dir = acornos::paths::base_dir(None);
+    let output_dir = acornos::paths::output_dir(&base_dir);
+    let max_age = Duration::from_secs(older_than * 3600);
+    let stale = find_stale(&output_dir, max_age, SystemTime::now())?;
+    if stale.is_empty() {
+        println!("No stale entries in {}", output_dir.display());
+        return Ok(());
+    }
 
-        let initramfs_key = output_dir.join(".initramfs-inputs.hash");
-        let initramfs_out = output_dir.join(distro_spec::acorn::INITRAMFS_LIVE_OUTPUT);
-        match distro_builder::artifact_store::try_restore_file_from_key(
-            store,
-            "initramfs",
-            &initramfs_key,
-            &initramfs_out,
-        ) {
-            Ok(true) => println!("\n[RESTORE] Initramfs restored from artifact store"),
-            Ok(false) => {}
-            Err(e) => eprintln!(
-                "[WARN] Failed to restore initramfs from artifact store: {:#}",
-                e
-            ),
-        }
+    println!("Stale entries in {}:", output_dir.display());
+    for entry in &stale {
+        println!(
+            "  {:>10}  {}  ({})",
+            human_bytes(entry.bytes),
+            entry.path.file_name().unwrap_or_default().to_string_lossy(),
+            entry.reason.label()
+        );
+    }
+    let total: u64 = stale.iter().map(|e| e.bytes).sum();
+    if yes {
+        let freed = remove_stale(&output_dir, &stale)?;
+        println!(
+            "Removed {} entries, {} freed",
+            stale.len(),
+            human_bytes(freed)
+        );
+    } else {
+        println!(
+            "Total: {} in {} entries. Run 'acornos gc --yes' to delete them.",
+            human_bytes(total),
+            stale.len()
+        );
     }
+    Ok(())
+}
 
-    // 2. Build EROFS rootfs (skip if inputs unchanged)
-    if acornos::rebuild::rootfs_needs_rebuild(&base_dir) {
-        println!("\nBuilding EROFS system image...");
-        let t = Timer::start("EROFS");
-        acornos::artifact::build_rootfs(&base_dir)?;
-        acornos::rebuild::cache_rootfs_hash(&base_dir);
-        if let Some(store) = &store {
-            let key = output_dir.join(".rootfs-inputs.hash");
-            let out = output_dir.join(distro_spec::acorn::ROOTFS_NAME);
-            if let Err(e) = distro_builder::artifact_store::try_store_file_from_key(
-                store,
-                "rootfs_erofs",
-                &key,
-                &out,
-                std::collections::BTreeMap::new(),
-            ) {
-                eprintln!("[WARN] Failed to store rootfs in artifact store: {:#}", e);
+fn cmd_disks(action: DisksAction) -> Result<()> {
+    use acornos::clean::human_bytes;
+    use acornos::disks::{reset, DiskProfiles, DISKS_FILE};
+
+    let base_dir = acornos::paths::base_dir(None);
+    let output_dir = acornos::paths::output_dir(&base_dir);
+    let profiles = DiskProfiles::load(&base_dir)?;
+    match action {
+        DisksAction::List => {
+            if profiles.profiles.is_empty() {
+                println!(
+                    "No disk profiles ({} not found in {})",
+                    DISKS_FILE,
+                    base_dir.display()
+                );
+            }
+            for profile in &profiles.profiles {
+                println!("{}:", profile.name);
+                for (index, disk) in profile.disks.iter().enumerate() {
+                    let path = profile.disk_path(&output_dir, index);
+                    let state = match std::fs::metadata(&path) {
+                        Ok(meta) => format!("{} used", human_bytes(meta.len())),
+                        Err(_) => "not created".to_string(),
+                    };
+                    println!(
+                        "  disk{}  {:>6}  {:<20}  {}",
+                        index,
+                        disk.size,
+                        disk.serial.as_deref().unwrap_or("-"),
+                        state
+                    );
+                }
             }
         }
-        t.finish();
-    } else {
-        println!("\n[SKIP] EROFS rootfs already built (inputs unchanged)");
-    }
-
-    // 3. Build initramfs (skip if inputs unchanged)
-    if acornos::rebuild::initramfs_needs_rebuild(&base_dir) {
-        println!("\nBuilding tiny initramfs...");
-        let t = Timer::start("Initramfs");
-        acornos::artifact::build_tiny_initramfs(&base_dir)?;
-        acornos::rebuild::cache_initramfs_hash(&base_dir);
-        if let Some(store) = &store {
-            let key = output_dir.join(".initramfs-inputs.hash");
-            let out = output_dir.join(distro_spec::acorn::INITRAMFS_LIVE_OUTPUT);
-            if let Err(e) = distro_builder::artifact_store::try_store_file_from_key(
-                store,
-                "initramfs",
-                &key,
-                &out,
-                std::collections::BTreeMap::new(),
-            ) {
-                eprintln!(
-                    "[WARN] Failed to store initramfs in artifact store: {:#}",
-                    e
-                );
+        DisksAction::Reset { profile } => {
+            let profile = profiles.get(&profile)?;
+            if reset(&output_dir, profile)? {
+                println!("Removed the disks of '{}'", profile.name);
+            } else {
+                println!("Profile '{}' has no disks yet", profile.name);
             }
         }
-        t.finish();
-    } else {
-        println!("\n[SKIP] Initramfs already built (inputs unchanged)");
     }
+    Ok(())
+}
 
-    // 4. Build ISO (skip if components unchanged)
-    if acornos::rebuild::iso_needs_rebuild(&base_dir) {
-        println!("\nBuilding ISO...");
-        let t = Timer::start("ISO");
-        acornos::artifact::create_iso(&base_dir)?;
-        t.finish();
-    } else {
-        println!("\n[SKIP] ISO already built (components unchanged)");
-    }
+fn cmd_store_verify() -> Result<()> {
+    use acornos::pipeline::open_artifact_store;
+    use acornos::store_meta::{verify_entries, EntryStatus};
 
-    let total = build_start.elapsed().as_secs_f64();
-    if total >= 60.0 {
-        println!("\n=== Build Complete ({:.1}m) ===", total / 60.0);
-    } else {
-        println!("\n=== Build Complete ({:.1}s) ===", total);
+    let base_dir = acornos::paths::base_dir(None);
+    let output_dir = acornos::paths::output_dir(&base_dir);
+    let Some(store) = open_artifact_store(&base_dir) else {
+        anyhow::bail!("Artifact store unavailable");
+    };
+
+    let reports = verify_entries(&store, &output_dir)?;
+    if reports.is_empty() {
+        println!("No checksummed artifact store entries (build first).");
+        return Ok(());
+    }
+    for report in &reports {
+        let entry = &report.sidecar;
+        println!("[{}] {} {}", report.status.label(), entry.kind, entry.key);
+        if let EntryStatus::Corrupt(actual) = &report.status {
+            println!("    expected {}", entry.meta);
+            println!("    restored {}", actual);
+        }
     }
-    println!(
-        "  ISO: {}",
-        output_dir.join(distro_spec::acorn::ISO_FILENAME).display()
-    );
-    println!("\nNext: acornos run");
 
+    let corrupt = reports
+        .iter()
+        .filter(|r| matches!(r.status, EntryStatus::Corrupt(_)))
+        .count();
+    if corrupt > 0 {
+        anyhow::bail!("{} of {} store entries are corrupt", corrupt, reports.len());
+    }
     Ok(())
 }
 
-fn cmd_build_rootfs() -> Result<()> {
-    let base_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    let store = open_artifact_store(&base_dir);
-    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(&base_dir);
+fn cmd_manifest(regenerate: bool) -> Result<()> {
+    use acornos::manifest::{write_manifest, MANIFEST_FILENAME};
 
-    require_conformance_contract()?;
+    let base_dir = acornos::paths::base_dir(None);
+    let output_dir = acornos::paths::output_dir(&base_dir);
+    let mut path = output_dir.join(MANIFEST_FILENAME);
 
-    if let Some(store) = &store {
-        let key = output_dir.join(".rootfs-inputs.hash");
-        let out = output_dir.join(distro_spec::acorn::ROOTFS_NAME);
-        match distro_builder::artifact_store::try_restore_file_from_key(
-            store,
-            "rootfs_erofs",
-            &key,
-            &out,
-        ) {
-            Ok(true) => println!("[RESTORE] Rootfs restored from artifact store"),
-            Ok(false) => {}
-            Err(e) => eprintln!(
-                "[WARN] Failed to restore rootfs from artifact store: {:#}",
-                e
-            ),
-        }
+    if regenerate || !path.exists() {
+        path = write_manifest(&base_dir, true)?;
+        eprintln!("Wrote {}", path.display());
     }
+    print!("{}", std::fs::read_to_string(&path)?);
+    Ok(())
+}
 
-    if acornos::rebuild::rootfs_needs_rebuild(&base_dir) {
-        acornos::artifact::build_rootfs(&base_dir)?;
-        acornos::rebuild::cache_rootfs_hash(&base_dir);
-        if let Some(store) = &store {
-            let key = output_dir.join(".rootfs-inputs.hash");
-            let out = output_dir.join(distro_spec::acorn::ROOTFS_NAME);
-            if let Err(e) = distro_builder::artifact_store::try_store_file_from_key(
-                store,
-                "rootfs_erofs",
-                &key,
-                &out,
-                std::collections::BTreeMap::new(),
-            ) {
-                eprintln!("[WARN] Failed to store rootfs in artifact store: {:#}", e);
+fn cmd_package_manifest(
+    regenerate: bool,
+    format: acornos::package_manifest::ManifestFormat,
+) -> Result<()> {
+    use acornos::package_manifest::{
+        write_package_manifest, ManifestFormat, PackageManifest, PACKAGE_MANIFEST_FILENAME,
+        PACKAGE_MANIFEST_SPDX_FILENAME,
+    };
+    use distro_builder::alpine::extract::ExtractPaths;
+
+    let base_dir = acornos::paths::base_dir(None);
+    let output_dir = acornos::paths::output_dir(&base_dir);
+
+    match format {
+        ManifestFormat::Json => {
+            let mut path = output_dir.join(PACKAGE_MANIFEST_FILENAME);
+            if regenerate || !path.exists() {
+                path = write_package_manifest(&base_dir, &output_dir)?;
+                eprintln!("Wrote {}", path.display());
             }
+            print!("{}", std::fs::read_to_string(&path)?);
+        }
+        ManifestFormat::Spdx => {
+            let staging = OutputPaths::new(&output_dir).rootfs_staging();
+            if !staging.exists() {
+                anyhow::bail!("No rootfs staging found. Run 'acornos build rootfs' first.");
+            }
+            let paths = ExtractPaths::new(&base_dir);
+            let manifest =
+                PackageManifest::collect(&paths.rootfs, &staging, &base_dir.join("../tools"))?;
+            let spdx = manifest.to_spdx();
+            let path = output_dir.join(PACKAGE_MANIFEST_SPDX_FILENAME);
+            std::fs::write(&path, &spdx)?;
+            eprintln!("Wrote {}", path.display());
+            print!("{}", spdx);
         }
-    } else {
-        println!("[SKIP] EROFS rootfs already built (inputs unchanged)");
-        println!(
-            "  Delete {} to force rebuild",
-            output_dir.join(distro_spec::acorn::ROOTFS_NAME).display()
-        );
     }
     Ok(())
 }
 
-fn cmd_initramfs() -> Result<()> {
-    let base_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    let store = open_artifact_store(&base_dir);
-    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(&base_dir);
+fn cmd_plan(json: Option<PathBuf>) -> Result<()> {
+    use acornos::component::plan::Plan;
+    use acornos::component::{selection, BuildContext};
 
-    require_conformance_contract()?;
+    let base_dir = acornos::paths::base_dir(None);
+    let staging = OutputPaths::for_base(&base_dir).rootfs_staging();
+    let ctx = BuildContext::new(&base_dir, &staging, "acornos extract")?;
+    let components = selection::selected_components(&base_dir)?;
+    let plan = Plan::build(&ctx.source, &ctx.staging, &components);
 
-    if let Some(store) = &store {
-        let key = output_dir.join(".initramfs-inputs.hash");
-        let out = output_dir.join(distro_spec::acorn::INITRAMFS_LIVE_OUTPUT);
-        match distro_builder::artifact_store::try_restore_file_from_key(
-            store,
-            "initramfs",
-            &key,
-            &out,
-        ) {
-            Ok(true) => println!("[RESTORE] Initramfs restored from artifact store"),
-            Ok(false) => {}
-            Err(e) => eprintln!(
-                "[WARN] Failed to restore initramfs from artifact store: {:#}",
-                e
-            ),
+    match json {
+        Some(path) if path.as_os_str() != "-" => {
+            std::fs::write(&path, plan.to_json())?;
+            eprintln!("Wrote {}", path.display());
         }
+        Some(_) => print!("{}", plan.to_json()),
+        None => print!("{}", plan.to_text()),
     }
 
-    if acornos::rebuild::initramfs_needs_rebuild(&base_dir) {
-        acornos::artifact::build_tiny_initramfs(&base_dir)?;
-        acornos::rebuild::cache_initramfs_hash(&base_dir);
-        if let Some(store) = &store {
-            let key = output_dir.join(".initramfs-inputs.hash");
-            let out = output_dir.join(distro_spec::acorn::INITRAMFS_LIVE_OUTPUT);
-            if let Err(e) = distro_builder::artifact_store::try_store_file_from_key(
-                store,
-                "initramfs",
-                &key,
-                &out,
-                std::collections::BTreeMap::new(),
-            ) {
-                eprintln!(
-                    "[WARN] Failed to store initramfs in artifact store: {:#}",
-                    e
-                );
-            }
-        }
-    } else {
-        println!("[SKIP] Initramfs already built (inputs unchanged)");
-        println!(
-            "  Delete {} to force rebuild",
-            output_dir
-                .join(distro_spec::acorn::INITRAMFS_LIVE_OUTPUT)
-                .display()
+    let failures = plan.failures().count();
+    if failures > 0 {
+        anyhow::bail!(
+            "{} of {} planned operations would fail",
+            failures,
+            plan.entries.len()
         );
     }
     Ok(())
 }
 
-fn cmd_iso() -> Result<()> {
-    let base_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    let store = open_artifact_store(&base_dir);
-    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(&base_dir);
+fn cmd_flash(
+    device: &std::path::Path,
+    iso: Option<PathBuf>,
+    options: acornos::flash::FlashOptions,
+) -> Result<()> {
+    let base_dir = acornos::paths::base_dir(None);
+    let output_dir = acornos::paths::output_dir(&base_dir);
+    let iso = iso.unwrap_or_else(|| OutputPaths::new(&output_dir).iso());
+    acornos::flash::flash(&iso, device, options)
+}
 
-    require_conformance_contract()?;
+fn cmd_shell(rootfs: bool) -> Result<()> {
+    use acornos::shell::ShellTarget;
 
-    // Ensure dependencies exist first
-    let rootfs = output_dir.join(distro_spec::acorn::ROOTFS_NAME);
-    let initramfs = output_dir.join(distro_spec::acorn::INITRAMFS_LIVE_OUTPUT);
-
-    if !rootfs.exists() {
-        if let Some(store) = &store {
-            let key = output_dir.join(".rootfs-inputs.hash");
-            let out = output_dir.join(distro_spec::acorn::ROOTFS_NAME);
-            match distro_builder::artifact_store::try_restore_file_from_key(
-                store,
-                "rootfs_erofs",
-                &key,
-                &out,
-            ) {
-                Ok(true) => println!("EROFS rootfs restored from artifact store."),
-                Ok(false) => {}
-                Err(e) => eprintln!(
-                    "[WARN] Failed to restore rootfs from artifact store: {:#}",
-                    e
-                ),
-            }
-        }
-        if !rootfs.exists() {
-            println!("EROFS rootfs not found, building...");
-            acornos::artifact::build_rootfs(&base_dir)?;
-            acornos::rebuild::cache_rootfs_hash(&base_dir);
-        }
-    }
-    if !initramfs.exists() {
-        if let Some(store) = &store {
-            let key = output_dir.join(".initramfs-inputs.hash");
-            let out = output_dir.join(distro_spec::acorn::INITRAMFS_LIVE_OUTPUT);
-            match distro_builder::artifact_store::try_restore_file_from_key(
-                store,
-                "initramfs",
-                &key,
-                &out,
-            ) {
-                Ok(true) => println!("Initramfs restored from artifact store."),
-                Ok(false) => {}
-                Err(e) => eprintln!(
-                    "[WARN] Failed to restore initramfs from artifact store: {:#}",
-                    e
-                ),
-            }
-        }
-        if !initramfs.exists() {
-            println!("Initramfs not found, building...");
-            acornos::artifact::build_tiny_initramfs(&base_dir)?;
-            acornos::rebuild::cache_initramfs_hash(&base_dir);
+    let base_dir = acornos::paths::base_dir(None);
+    let output_dir = acornos::paths::output_dir(&base_dir);
+    let target = if rootfs {
+        ShellTarget::Rootfs
+    } else {
+        ShellTarget::Staging
+    };
+    acornos::shell::run(&base_dir, &output_dir, target)
+}
+
+fn cmd_env(action: EnvAction) -> Result<()> {
+    let base_dir = acornos::paths::base_dir(None);
+    match action {
+        EnvAction::Export => {
+            let output_dir = acornos::paths::output_dir(&base_dir);
+            std::fs::create_dir_all(&output_dir)?;
+            let path = acornos::build_env::write_build_env(&base_dir, &output_dir)?;
+            println!("Build environment: {}", path.display());
         }
+        EnvAction::Diff { file } => print!("{}", acornos::build_env::diff_with(&base_dir, &file)?),
     }
+    Ok(())
+}
 
-    if acornos::rebuild::iso_needs_rebuild(&base_dir) {
-        acornos::artifact::create_iso(&base_dir)?;
-    } else {
-        println!("[SKIP] ISO already built (components unchanged)");
-        println!(
-            "  Delete {} to force rebuild",
-            output_dir.join(distro_spec::acorn::ISO_FILENAME).display()
+fn cmd_graph_services(rootfs: Option<PathBuf>, output: Option<PathBuf>, ascii: bool) -> Result<()> {
+    use acornos::component::selection;
+    use acornos::component::service_graph::ServiceGraph;
+
+    let base_dir = acornos::paths::base_dir(None);
+    let output_dir = acornos::paths::output_dir(&base_dir);
+    let rootfs = rootfs.unwrap_or_else(|| OutputPaths::new(&output_dir).rootfs_staging());
+    if !rootfs.join("etc/init.d").is_dir() {
+        anyhow::bail!(
+            "No etc/init.d in {}. Run 'acornos build rootfs' first.",
+            rootfs.display()
         );
     }
-    Ok(())
-}
 
-fn cmd_run() -> Result<()> {
-    let base_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    acornos::qemu::run_iso(&base_dir, None)
-}
+    let components = selection::selected_components(&base_dir)?;
+    let graph = ServiceGraph::load(&rootfs, &components)?;
+
+    match output {
+        Some(path) if path.as_os_str() == "-" => print!("{}", graph.to_dot()),
+        output => {
+            let path = output.unwrap_or_else(|| output_dir.join("openrc-services.dot"));
+            std::fs::write(&path, graph.to_dot())?;
+            eprintln!(
+                "Wrote {} (render: dot -Tsvg {} -o services.svg)",
+                path.display(),
+                path.display()
+            );
+        }
+    }
+    if ascii {
+        print!("{}", graph.to_ascii());
+    }
 
-fn cmd_test(timeout: u64) -> Result<()> {
-    let base_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(&base_dir);
-    let iso_path = output_dir.join(distro_spec::acorn::ISO_FILENAME);
-    distro_builder::qemu::test_iso_boot(
-        &iso_path,
-        timeout,
-        "acorn",
-        "00-acorn-test.sh",
-        distro_spec::acorn::QEMU_CPU_MODE,
-        distro_spec::acorn::QEMU_MEMORY_GB,
-    )
+    let anomalies = graph.anomalies();
+    for anomaly in &anomalies {
+        log::warn!("{}", anomaly);
+    }
+    if !anomalies.is_empty() {
+        eprintln!("{} anomalies in the service graph", anomalies.len());
+    }
+    Ok(())
 }
 
 fn cmd_preflight() -> Result<()> {
     use acornos::preflight::PreflightChecker;
 
-    let base_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let base_dir = acornos::paths::base_dir(None);
     let checker = PreflightChecker::new(&base_dir);
 
     // Run preflight checks (this is async)
@@ -548,10 +1871,83 @@ fn cmd_preflight() -> Result<()> {
     Ok(())
 }
 
-fn cmd_download_all() -> Result<()> {
-    let base_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+fn cmd_download_offline(what: Option<DownloadTarget>) -> Result<()> {
+    use acornos::offline::OfflineCache;
+
+    let base_dir = acornos::paths::base_dir(None);
+
+    println!("Offline mode: verifying pre-seeded downloads (no network access)...\n");
+
+    if matches!(what, Some(DownloadTarget::Tools)) {
+        println!("  [SKIP] Installation tools are resolved at build time from local checkouts");
+        return Ok(());
+    }
+
+    let cache = OfflineCache::new(&base_dir);
+    cache.verify()?;
+
+    println!("  ISO:         {} [OK]", cache.iso.display());
+    println!("  apk-tools:   {} [OK]", cache.apk_static.display());
+    println!("  busybox:     {} [OK]", cache.busybox.display());
+    println!("\nAll offline dependencies verified.");
+    Ok(())
+}
+
+fn cmd_download_check(arch: acornos::arch::Arch, offline: bool) -> Result<()> {
+    use acornos::alpine_source::AlpineSource;
+    use acornos::download_check::{cached_files, check_file, print_reports};
+
+    let base_dir = acornos::paths::base_dir(None);
+
+    println!("Verifying cached downloads (nothing is fetched)...\n");
+    let reports = cached_files(&base_dir, arch, &AlpineSource::from_env()?, offline)?
+        .into_iter()
+        .map(check_file)
+        .collect::<Result<Vec<_>>>()?;
+    print_reports(&reports);
+
+    let failed = reports.iter().filter(|r| r.failed()).count();
+    if failed > 0 {
+        anyhow::bail!("{} cached download(s) missing or corrupt", failed);
+    }
+    println!("\nAll cached downloads verified.");
+    Ok(())
+}
+
+/// `--force`: drop cached downloads so they are fetched again.
+fn discard_downloads(base_dir: &Path, arch: acornos::arch::Arch) -> Result<()> {
+    use acornos::alpine_source::AlpineSource;
+
+    for path in
+        acornos::download_check::discard_downloads(base_dir, arch, &AlpineSource::from_env()?)?
+    {
+        println!("[FORCE] Removed {}", path.display());
+    }
+    Ok(())
+}
+
+fn cmd_download_all(force: bool, verify_gpg: bool, arch: acornos::arch::Arch) -> Result<()> {
+    let base_dir = acornos::paths::base_dir(None);
+    arch.check_build_host()?;
 
     println!("Resolving all dependencies...\n");
+    if force {
+        discard_downloads(&base_dir, arch)?;
+    }
+
+    // Pre-fetch the large downloads in parallel (resumable) so the recipes
+    // find them already cached and verified
+    use acornos::alpine_source::AlpineSource;
+    use acornos::download::{alpine_downloads, busybox, fetch_all, CurlFetcher, RetryPolicy};
+    let mut downloads = alpine_downloads(&base_dir, arch, &AlpineSource::from_env()?)?;
+    // busybox.net only has x86_64; other arches use busybox-static from Alpine
+    if arch == acornos::arch::Arch::X86_64 {
+        downloads.push(busybox(&base_dir)?);
+    }
+    fetch_all(&downloads, &CurlFetcher, RetryPolicy::from_env())?;
+    if verify_gpg {
+        verify_iso_signature(&downloads[0])?;
+    }
 
     // Alpine ISO and packages
     let alpine = distro_builder::recipe::alpine::alpine(&base_dir)?;
@@ -568,8 +1964,66 @@ fn cmd_download_all() -> Result<()> {
     Ok(())
 }
 
-fn cmd_download_alpine() -> Result<()> {
-    let base_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+/// `--verify-gpg`: check the fetched ISO against Alpine's release signature.
+fn verify_iso_signature(iso: &acornos::download::Download) -> Result<()> {
+    use acornos::download::CurlFetcher;
+    use acornos::signing::SystemRunner;
+
+    println!("Verifying the Alpine release signature...");
+    acornos::alpine_signature::verify_iso(&SystemRunner, &CurlFetcher, iso)?;
+    println!(
+        "  {} [OK] signed by {}",
+        iso.dest.display(),
+        acornos::alpine_signature::ALPINE_RELEASE_KEY_FINGERPRINT
+    );
+    Ok(())
+}
+
+fn cmd_download_alpine(
+    no_rootfs_cache: bool,
+    force: bool,
+    verify_gpg: bool,
+    arch: acornos::arch::Arch,
+) -> Result<()> {
+    use acornos::alpine_source::AlpineSource;
+    use acornos::download::{alpine_downloads, fetch, CurlFetcher, RetryPolicy};
+
+    let base_dir = acornos::paths::base_dir(None);
+    arch.check_build_host()?;
+    let store = if no_rootfs_cache {
+        println!("[SKIP] Rootfs cache disabled (--no-rootfs-cache)");
+        None
+    } else {
+        acornos::pipeline::open_artifact_store(&base_dir)
+    };
+
+    if force {
+        discard_downloads(&base_dir, arch)?;
+    }
+
+    // Resumable ISO and apk-tools downloads with mirror fallback; the
+    // recipe then finds them verified in place
+    println!("Downloading Alpine Extended ISO and apk-tools-static...");
+    let downloads = alpine_downloads(&base_dir, arch, &AlpineSource::from_env()?)?;
+    for download in &downloads {
+        fetch(download, &CurlFetcher, RetryPolicy::from_env(), false)?;
+    }
+    if verify_gpg {
+        verify_iso_signature(&downloads[0])?;
+    }
+
+    // Clean checkout: restore the installed rootfs instead of re-running apk
+    if let Some(store) = &store {
+        match acornos::rootfs_cache::restore_rootfs(store, &base_dir) {
+            Ok(true) => println!("[RESTORE] Alpine rootfs restored from artifact store"),
+            Ok(false) => {}
+            Err(e) => log::warn!(
+                "Failed to restore Alpine rootfs from artifact store: {:#}",
+                e
+            ),
+        }
+    }
+
     let alpine = distro_builder::recipe::alpine::alpine(&base_dir)?;
     distro_builder::alpine::keys::install_keys(
         &alpine.rootfs,
@@ -580,25 +2034,37 @@ fn cmd_download_alpine() -> Result<()> {
     println!("  ISO:         {}", alpine.iso.display());
     println!("  rootfs:      {}", alpine.rootfs.display());
 
-    // Install Tier 0-2 packages (dependencies for rootfs build)
-    println!("\nInstalling Tier 0-2 packages...");
+    // Install Tiers 1-3 one apk run at a time (signature-checked, retried,
+    // resumable); the packages recipe then only runs its install checks
+    println!("\nInstalling supplementary packages...");
+    acornos::apk_install::install_supplementary(&base_dir)?;
     distro_builder::recipe::packages(&base_dir)?;
     println!("✓ Packages installed");
 
+    if let Some(store) = &store {
+        match acornos::rootfs_cache::store_rootfs(store, &base_dir) {
+            Ok(true) => println!("[STORE] Alpine rootfs stored in artifact store"),
+            Ok(false) => {}
+            Err(e) => log::warn!("Failed to store Alpine rootfs in artifact store: {:#}", e),
+        }
+    }
+
     Ok(())
 }
 
 fn cmd_download_tools() -> Result<()> {
     use distro_spec::shared::LEVITATE_CARGO_TOOLS;
 
-    let base_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(&base_dir);
+    let base_dir = acornos::paths::base_dir(None);
+    let output_dir = acornos::paths::output_dir(&base_dir);
 
     println!("Installing tools via recipes...\n");
     distro_builder::recipe::install_tools(&base_dir)?;
 
     // Show what was installed
-    let staging_bin = output_dir.join("staging/usr/bin");
+    let staging_bin = OutputPaths::new(&output_dir)
+        .kernel_staging()
+        .join("usr/bin");
     println!("\nTools installed:");
     for tool in LEVITATE_CARGO_TOOLS {
         let path = staging_bin.join(tool);
@@ -614,46 +2080,147 @@ fn cmd_download_tools() -> Result<()> {
     Ok(())
 }
 
-fn cmd_status() -> Result<()> {
+/// Print `build-timings.json` from the last full build.
+fn print_timings(output_dir: &std::path::Path) -> Result<()> {
+    use acornos::timing::{format_duration, parse_timings_json, TIMINGS_FILENAME};
+    use anyhow::Context;
+
+    let path = output_dir.join(TIMINGS_FILENAME);
+    let json = std::fs::read_to_string(&path).with_context(|| {
+        format!(
+            "No build timings at {} (run 'acornos build' first)",
+            path.display()
+        )
+    })?;
+    let (total, phases) = parse_timings_json(&json)
+        .with_context(|| format!("Unrecognized timings file {}", path.display()))?;
+
+    println!(
+        "Last build: {} ({})",
+        format_duration(total),
+        path.display()
+    );
+    println!();
+    println!("  {:>8}  {:>8}  Phase", "Start", "Duration");
+    for phase in &phases {
+        let duration = if phase.skipped {
+            "skipped".to_string()
+        } else {
+            format_duration(phase.duration_ms)
+        };
+        println!(
+            "  {:>8}  {:>8}  {}",
+            format!("+{}", format_duration(phase.start_ms)),
+            duration,
+            phase.name
+        );
+    }
+    Ok(())
+}
+
+fn cmd_status(timings: bool) -> Result<()> {
     use acornos::config::AcornConfig;
+    use acornos::package_overlay::PackageOverlay;
     use distro_builder::alpine::extract::ExtractPaths;
     use distro_builder::DistroConfig;
 
     let config = AcornConfig;
-    let base_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(&base_dir);
+    let base_dir = acornos::paths::base_dir(None);
+    let output_dir = acornos::paths::output_dir(&base_dir);
+    if timings {
+        return print_timings(&output_dir);
+    }
     let paths = ExtractPaths::new(&base_dir);
+    let build_info = acornos::build_info::BuildInfo::detect(&base_dir);
 
-    println!("AcornOS Builder Status");
-    println!("======================");
+    let found = |state: &str| term::paint(Status::Ok, state);
+    let missing = |state: &str| term::paint(Status::Warn, state);
+
+    println!("{}", term::heading("AcornOS Builder Status"));
     println!();
     println!("Configuration:");
-    println!("  OS Name:     {}", config.os_name());
-    println!("  OS ID:       {}", config.os_id());
-    println!("  ISO Label:   {}", config.iso_label());
-    println!("  Init System: {}", config.init_system());
-    println!("  Shell:       {}", config.default_shell());
+    Fields::new()
+        .row("OS Name", config.os_name())
+        .row("OS ID", config.os_id())
+        .row(
+            "ISO Label",
+            acornos::artifact::IsoLabel::load(&output_dir).to_string(),
+        )
+        .row("Init System", config.init_system().to_string())
+        .row("Shell", config.default_shell())
+        .row("Version", build_info.version_id.clone())
+        .row(
+            "Build ID",
+            match &build_info.build_id {
+                Some(id) if build_info.dirty() => {
+                    format!("{} (uncommitted changes)", missing(id))
+                }
+                Some(id) => id.clone(),
+                None => missing("unknown (not a git checkout)"),
+            },
+        )
+        .print(2);
     println!();
 
     println!("Dependencies (managed by recipe):");
+    let source = acornos::alpine_source::AlpineSource::from_env()?;
+    let mut fields = Fields::new();
+    fields
+        .row(
+            "Alpine version",
+            format!("{} (branch v{})", source.release(), source.branch()),
+        )
+        .row("Alpine mirror", source.mirror.clone())
+        .row("Fallbacks", source.mirrors()[1..].join(" "));
     if paths.iso.exists() {
-        println!("  Alpine ISO:      FOUND at {}", paths.iso.display());
+        fields.row(
+            "Alpine ISO",
+            format!("{} at {}", found("FOUND"), paths.iso.display()),
+        );
     } else {
-        println!("  Alpine ISO:      NOT FOUND (run 'acornos download alpine')");
+        fields.row(
+            "Alpine ISO",
+            format!("{} (run 'acornos download alpine')", missing("NOT FOUND")),
+        );
     }
 
     let apk_static = paths.apk_tools.join("sbin").join("apk.static");
     if apk_static.exists() {
-        println!("  apk-tools:       FOUND at {}", apk_static.display());
+        fields.row(
+            "apk-tools",
+            format!("{} at {}", found("FOUND"), apk_static.display()),
+        );
     } else {
-        println!("  apk-tools:       NOT FOUND (run 'acornos download alpine')");
+        fields.row(
+            "apk-tools",
+            format!("{} (run 'acornos download alpine')", missing("NOT FOUND")),
+        );
     }
 
-    if paths.rootfs.exists() && paths.rootfs.join("bin").exists() {
-        println!("  Rootfs:          CREATED at {}", paths.rootfs.display());
+    if acornos::source_rootfs::is_complete(&base_dir) {
+        fields.row(
+            "Rootfs",
+            format!("{} at {}", found("CREATED"), paths.rootfs.display()),
+        );
+    } else if paths.rootfs.exists() {
+        fields.row(
+            "Rootfs",
+            format!(
+                "{} at {} (checked by the next build)",
+                missing("UNVERIFIED"),
+                paths.rootfs.display()
+            ),
+        );
     } else {
-        println!("  Rootfs:          NOT CREATED (run 'acornos download alpine')");
+        fields.row(
+            "Rootfs",
+            format!("{} (run 'acornos download alpine')", missing("NOT CREATED")),
+        );
     }
+    if let Some(overlay) = PackageOverlay::load(&base_dir)? {
+        fields.row("Package overlay", overlay.summary());
+    }
+    fields.print(2);
     println!();
 
     // Check Linux kernel source
@@ -662,109 +2229,126 @@ fn cmd_status() -> Result<()> {
         .join("downloads")
         .join(kernel_spec.source_dir_name());
     println!("Kernel Source (v{}):", kernel_spec.version);
+    let mut fields = Fields::new();
     if tarball_source.join("Makefile").exists() {
-        println!("  Linux source:    FOUND at {}", tarball_source.display());
+        fields.row(
+            "Linux source",
+            format!("{} at {}", found("FOUND"), tarball_source.display()),
+        );
     } else {
-        println!("  Linux source:    NOT DOWNLOADED (will fetch from cdn.kernel.org)");
+        fields.row(
+            "Linux source",
+            format!(
+                "{} (will fetch from cdn.kernel.org)",
+                missing("NOT DOWNLOADED")
+            ),
+        );
     }
     let kconfig = base_dir.join("kconfig");
     if kconfig.exists() {
-        println!("  kconfig:         FOUND at {}", kconfig.display());
+        fields.row(
+            "kconfig",
+            format!("{} at {}", found("FOUND"), kconfig.display()),
+        );
     } else {
-        println!("  kconfig:         NOT FOUND");
+        fields.row("kconfig", missing("NOT FOUND"));
     }
+    fields.print(2);
+    println!();
 
     // Check build artifacts
-    let kernel = output_dir.join("staging/boot/vmlinuz");
-    let rootfs = output_dir.join("filesystem.erofs");
-    let initramfs = output_dir.join("initramfs-live.cpio.gz");
-    let iso = output_dir.join(distro_spec::acorn::ISO_FILENAME);
+    let output = OutputPaths::new(&output_dir);
+    let kernel_status = acornos::kernel::KernelStatus::detect(&output);
+    let format = acornos::artifact::RootfsFormat::load(&output_dir);
+    let rootfs = output.rootfs_image(format);
+    let initramfs =
+        output.live_initramfs(acornos::artifact::InitramfsCompression::load(&output_dir));
+    let iso = output.iso();
+    let netinstall_iso = output.iso_variant(acornos::artifact::IsoVariant::Netinstall);
 
     println!("Build Artifacts:");
-    if kernel.exists() {
-        let size = std::fs::metadata(&kernel)
-            .map(|m| m.len() / 1024 / 1024)
-            .unwrap_or(0);
-
-        // Prefer provenance from the kernel release (modules dir name), since
-        // output/kernel-build may be missing even when a kernel is present.
-        let kernel_release = {
-            let staging = output_dir.join("staging");
-            let candidates = [staging.join("lib/modules"), staging.join("usr/lib/modules")];
-            let mut found = None;
-            for dir in candidates {
-                if let Ok(entries) = std::fs::read_dir(&dir) {
-                    for entry in entries.flatten() {
-                        let path = entry.path();
-                        if path.is_dir() {
-                            if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-                                found = Some(name.to_string());
-                                break;
-                            }
-                        }
-                    }
-                }
-                if found.is_some() {
-                    break;
-                }
-            }
-            found
-        };
-
-        let expected_suffix = kernel_spec.localversion;
-        let built_for_distro = kernel_release
-            .as_deref()
-            .map(|r| r.contains(expected_suffix))
-            .unwrap_or(false);
-
-        let release_suffix = kernel_release
+    let mut fields = Fields::new();
+    if let Some(size) = kernel_status.size {
+        let release_suffix = kernel_status
+            .release
             .as_deref()
             .map(|r| format!(" ({})", r))
             .unwrap_or_default();
 
-        if built_for_distro {
-            println!("  Kernel:          PRESENT ({} MB){}", size, release_suffix);
-        } else {
-            println!("  Kernel:          PRESENT ({} MB){}", size, release_suffix);
-            println!(
-                "                  WARNING: expected suffix '{}' (build via: cargo xtask kernels build acorn)",
-                expected_suffix
+        fields.row(
+            "Kernel",
+            format!(
+                "{} ({} MB){}",
+                found("PRESENT"),
+                size / 1024 / 1024,
+                release_suffix
+            ),
+        );
+        if !kernel_status.built_for_distro() {
+            fields.row(
+                "",
+                format!(
+                    "{}: expected suffix '{}' (build via: acornos kernel build)",
+                    missing("WARNING"),
+                    kernel_status.expected_suffix
+                ),
             );
         }
     } else {
-        println!("  Kernel:          NOT BUILT");
+        fields.row("Kernel", missing("NOT BUILT"));
     }
     if rootfs.exists() {
         let size = std::fs::metadata(&rootfs)
             .map(|m| m.len() / 1024 / 1024)
             .unwrap_or(0);
-        println!("  EROFS:           BUILT ({} MB)", size);
+        fields.row(
+            "Rootfs",
+            format!("{} ({}, {} MB)", found("BUILT"), format, size),
+        );
     } else {
-        println!("  EROFS:           NOT BUILT");
+        fields.row("Rootfs", missing("NOT BUILT"));
     }
     if initramfs.exists() {
         let size = std::fs::metadata(&initramfs)
             .map(|m| m.len() / 1024)
             .unwrap_or(0);
-        println!("  Initramfs:       BUILT ({} KB)", size);
+        fields.row("Initramfs", format!("{} ({} KB)", found("BUILT"), size));
     } else {
-        println!("  Initramfs:       NOT BUILT");
+        fields.row("Initramfs", missing("NOT BUILT"));
     }
     if iso.exists() {
         let size = std::fs::metadata(&iso)
             .map(|m| m.len() / 1024 / 1024)
             .unwrap_or(0);
-        println!("  ISO:             BUILT ({} MB)", size);
+        fields.row("ISO", format!("{} ({} MB)", found("BUILT"), size));
+    } else {
+        fields.row("ISO", missing("NOT BUILT"));
+    }
+    if netinstall_iso.exists() {
+        let size = std::fs::metadata(&netinstall_iso)
+            .map(|m| m.len() / 1024 / 1024)
+            .unwrap_or(0);
+        fields.row(
+            "Netinstall ISO",
+            format!("{} ({} MB)", found("BUILT"), size),
+        );
     } else {
-        println!("  ISO:             NOT BUILT");
+        fields.row(
+            "Netinstall ISO",
+            format!(
+                "{} (acornos iso --variant netinstall)",
+                missing("NOT BUILT")
+            ),
+        );
     }
+    fields.print(2);
     println!();
 
     println!("Next steps:");
     if !paths.rootfs.exists() {
         println!("  1. Run 'acornos download alpine' to download and create rootfs");
-    } else if !kernel.exists() {
-        println!("  1. Run 'cargo xtask kernels build acorn' to build the kernel");
+    } else if !kernel_status.installed() {
+        println!("  1. Run 'acornos kernel build' to build the kernel");
     } else if !rootfs.exists() {
         println!("  1. Run 'acornos build rootfs' to create filesystem.erofs");
     } else if !initramfs.exists() {
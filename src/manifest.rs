@@ -0,0 +1,563 @@
+//! Machine-readable build manifest (SBOM-lite).
+//!
+//! `output/build-manifest.json` answers "what exactly is in this build":
+//! distro identity, the Alpine ISO it was built from, every installed APK
+//! with its version, the kernel release, busybox versions, the component
+//! list, artifact hashes and sizes, and the repo's `git describe`.
+//!
+//! A copy is placed on the ISO under `/.acorn/` so a booted system can
+//! report its own provenance. That copy is written before the ISO exists,
+//! so it lists every artifact except the ISO itself.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use distro_builder::process::Cmd;
+use distro_builder::DistroConfig;
+use distro_spec::acorn::{ISO_FILENAME, OS_VERSION};
+
+use crate::apk::{installed_packages, ApkPackage};
+use crate::artifact::full_initramfs::INITRAMFS_INSTALLED_OUTPUT;
+use crate::artifact::{InitramfsCompression, IsoLabel, RootfsFormat};
+use crate::component::ALL_COMPONENTS;
+use crate::config::AcornConfig;
+use crate::download::BUSYBOX_URL;
+use crate::offline::sha256_file;
+use crate::paths::OutputPaths;
+
+/// Manifest filename in the output directory.
+pub const MANIFEST_FILENAME: &str = "build-manifest.json";
+
+/// Where the manifest lands on the ISO.
+pub const MANIFEST_ISO_PATH: &str = ".acorn/build-manifest.json";
+
+/// Bump when fields are removed or change meaning.
+pub const MANIFEST_SCHEMA_VERSION: u32 = 1;
+
+/// A built artifact with its hash and size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArtifactInfo {
+    pub name: &'static str,
+    pub file: String,
+    pub sha256: String,
+    pub size: u64,
+}
+
+/// Everything recorded in the manifest.
+#[derive(Debug, Clone, Default)]
+pub struct BuildManifest {
+    pub generated_at: u64,
+    pub git_describe: Option<String>,
+    /// The ISO this build resolved (arch, `--alpine-version`) and its pin;
+    /// `None` for an unpinned ISO checked against the mirror's `.sha256`.
+    pub alpine_iso: String,
+    pub alpine_iso_sha256: Option<String>,
+    pub kernel_release: Option<String>,
+    pub busybox_rootfs: Option<String>,
+    pub busybox_initramfs: Option<String>,
+    pub rootfs_format: String,
+    /// Volume label the live initramfs and ISO were built for.
+    pub iso_label: String,
+    pub components: Vec<String>,
+    pub packages: Vec<ApkPackage>,
+    pub artifacts: Vec<ArtifactInfo>,
+}
+
+impl BuildManifest {
+    /// Collect the manifest from existing build outputs.
+    ///
+    /// Missing artifacts are left out rather than failing, so the manifest
+    /// can be produced at any point after the rootfs exists.
+    pub fn collect(base_dir: &Path, include_iso: bool) -> Result<Self> {
+        use distro_builder::alpine::extract::ExtractPaths;
+
+        let output_dir = crate::paths::output_dir(base_dir);
+        let paths = ExtractPaths::new(base_dir);
+        let packages = installed_packages(&paths.rootfs)?;
+        let format = RootfsFormat::load(&output_dir);
+        let initramfs = InitramfsCompression::load(&output_dir).output_name();
+
+        let mut candidates = vec![
+            ("rootfs", format.image_name()),
+            ("initramfs_live", initramfs.as_str()),
+            ("initramfs_installed", INITRAMFS_INSTALLED_OUTPUT),
+        ];
+        if include_iso {
+            candidates.push(("iso", ISO_FILENAME));
+        }
+        let mut artifacts = Vec::new();
+        for (name, file) in candidates {
+            let path = output_dir.join(file);
+            if !path.exists() {
+                continue;
+            }
+            artifacts.push(ArtifactInfo {
+                name,
+                file: file.to_string(),
+                sha256: sha256_file(&path)?,
+                size: fs::metadata(&path)?.len(),
+            });
+        }
+
+        let iso = crate::download::alpine_iso(
+            base_dir,
+            crate::arch::Arch::from_env()?,
+            &crate::alpine_source::AlpineSource::from_env()?,
+        )?;
+
+        Ok(Self {
+            generated_at: build_timestamp(),
+            git_describe: git_describe(base_dir),
+            alpine_iso: iso
+                .dest
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            alpine_iso_sha256: iso.sha256,
+            kernel_release: kernel_release(&OutputPaths::new(&output_dir).kernel_modules()),
+            busybox_rootfs: packages
+                .iter()
+                .find(|p| p.name == "busybox")
+                .map(|p| p.version.clone()),
+            busybox_initramfs: busybox_url_version(BUSYBOX_URL),
+            rootfs_format: format.to_string(),
+            iso_label: IsoLabel::load(&output_dir).to_string(),
+            components: ALL_COMPONENTS.iter().map(|c| c.name.to_string()).collect(),
+            packages,
+            artifacts,
+        })
+    }
+
+    /// Render as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        let config = AcornConfig;
+        let opt = |v: &Option<String>| v.as_deref().map(json_str).unwrap_or("null".into());
+
+        let components: Vec<String> = self.components.iter().map(|c| json_str(c)).collect();
+        let packages: Vec<String> = self
+            .packages
+            .iter()
+            .map(|p| {
+                format!(
+                    "    {{ \"name\": {}, \"version\": {}, \"arch\": {}, \"origin\": {} }}",
+                    json_str(&p.name),
+                    json_str(&p.version),
+                    json_str(&p.arch),
+                    json_str(&p.origin)
+                )
+            })
+            .collect();
+        let artifacts: Vec<String> = self
+            .artifacts
+            .iter()
+            .map(|a| {
+                format!(
+                    "    {}: {{ \"file\": {}, \"sha256\": {}, \"size\": {} }}",
+                    json_str(a.name),
+                    json_str(&a.file),
+                    json_str(&a.sha256),
+                    a.size
+                )
+            })
+            .collect();
+
+        let mut out = String::from("{\n");
+        out.push_str(&format!(
+            "  \"schema_version\": {},\n",
+            MANIFEST_SCHEMA_VERSION
+        ));
+        out.push_str(&format!(
+            "  \"distro\": {{ \"name\": {}, \"id\": {}, \"version\": {}, \"iso_label\": {}, \"init_system\": \"openrc\", \"default_shell\": {} }},\n",
+            json_str(config.os_name()),
+            json_str(config.os_id()),
+            json_str(OS_VERSION),
+            json_str(&self.iso_label),
+            json_str(config.default_shell())
+        ));
+        out.push_str(&format!("  \"generated_at\": {},\n", self.generated_at));
+        out.push_str(&format!(
+            "  \"git_describe\": {},\n",
+            opt(&self.git_describe)
+        ));
+        out.push_str(&format!(
+            "  \"alpine\": {{ \"iso\": {}, \"sha256\": {} }},\n",
+            json_str(&self.alpine_iso),
+            opt(&self.alpine_iso_sha256)
+        ));
+        out.push_str(&format!(
+            "  \"kernel_release\": {},\n",
+            opt(&self.kernel_release)
+        ));
+        out.push_str(&format!(
+            "  \"busybox\": {{ \"rootfs\": {}, \"initramfs\": {} }},\n",
+            opt(&self.busybox_rootfs),
+            opt(&self.busybox_initramfs)
+        ));
+        out.push_str(&format!(
+            "  \"rootfs_format\": {},\n",
+            json_str(&self.rootfs_format)
+        ));
+        out.push_str(&format!("  \"components\": [{}],\n", components.join(", ")));
+        out.push_str(&format!(
+            "  \"packages\": [\n{}\n  ],\n",
+            packages.join(",\n")
+        ));
+        out.push_str(&format!(
+            "  \"artifacts\": {{\n{}\n  }}\n",
+            artifacts.join(",\n")
+        ));
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// Collect and write `output/build-manifest.json`.
+pub fn write_manifest(base_dir: &Path, include_iso: bool) -> Result<PathBuf> {
+    let output_dir = crate::paths::output_dir(base_dir);
+    let manifest = BuildManifest::collect(base_dir, include_iso)?;
+    let path = output_dir.join(MANIFEST_FILENAME);
+    fs::write(&path, manifest.to_json())
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Quote and escape a JSON string.
+pub(crate) fn json_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A JSON value read back by [`parse_json`]; numbers keep their text.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<JsonValue>),
+    /// Fields in file order.
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    /// Field `key` of an object.
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            Self::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Self::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[JsonValue]> {
+        match self {
+            Self::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, JsonValue)]> {
+        match self {
+            Self::Object(fields) => Some(fields),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a JSON document such as those written with [`json_str`]; `None`
+/// if it is malformed or has trailing content.
+pub(crate) fn parse_json(text: &str) -> Option<JsonValue> {
+    let mut reader = JsonReader {
+        chars: text.chars().peekable(),
+    };
+    let value = reader.value()?;
+    reader.skip_ws();
+    reader.chars.peek().is_none().then_some(value)
+}
+
+struct JsonReader<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl JsonReader<'_> {
+    fn skip_ws(&mut self) {
+        while self.chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+    }
+
+    fn eat(&mut self, expected: char) -> Option<()> {
+        self.skip_ws();
+        self.chars.next_if_eq(&expected).map(|_| ())
+    }
+
+    fn value(&mut self) -> Option<JsonValue> {
+        self.skip_ws();
+        match *self.chars.peek()? {
+            '{' => self.object(),
+            '[' => self.array(),
+            '"' => self.string().map(JsonValue::String),
+            'n' => self.word("null", JsonValue::Null),
+            't' => self.word("true", JsonValue::Bool(true)),
+            'f' => self.word("false", JsonValue::Bool(false)),
+            _ => {
+                let mut number = String::new();
+                while let Some(c) = self
+                    .chars
+                    .next_if(|c| c.is_ascii_digit() || "+-.eE".contains(*c))
+                {
+                    number.push(c);
+                }
+                number
+                    .parse::<f64>()
+                    .is_ok()
+                    .then_some(JsonValue::Number(number))
+            }
+        }
+    }
+
+    fn word(&mut self, word: &str, value: JsonValue) -> Option<JsonValue> {
+        for expected in word.chars() {
+            self.chars.next_if_eq(&expected)?;
+        }
+        Some(value)
+    }
+
+    fn object(&mut self) -> Option<JsonValue> {
+        self.eat('{')?;
+        let mut fields = Vec::new();
+        if self.eat('}').is_some() {
+            return Some(JsonValue::Object(fields));
+        }
+        loop {
+            self.skip_ws();
+            let key = self.string()?;
+            self.eat(':')?;
+            fields.push((key, self.value()?));
+            if self.eat(',').is_none() {
+                self.eat('}')?;
+                return Some(JsonValue::Object(fields));
+            }
+        }
+    }
+
+    fn array(&mut self) -> Option<JsonValue> {
+        self.eat('[')?;
+        let mut items = Vec::new();
+        if self.eat(']').is_some() {
+            return Some(JsonValue::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            if self.eat(',').is_none() {
+                self.eat(']')?;
+                return Some(JsonValue::Array(items));
+            }
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.chars.next_if_eq(&'"')?;
+        let mut out = String::new();
+        loop {
+            match self.chars.next()? {
+                '"' => return Some(out),
+                '\\' => out.push(match self.chars.next()? {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'u' => self.unicode_escape()?,
+                    c @ ('"' | '\\' | '/') => c,
+                    _ => return None,
+                }),
+                c => out.push(c),
+            }
+        }
+    }
+
+    /// The character of a `\uXXXX` escape, joining surrogate pairs.
+    fn unicode_escape(&mut self) -> Option<char> {
+        let high = self.hex4()?;
+        if !(0xd800..0xdc00).contains(&high) {
+            return char::from_u32(high);
+        }
+        self.chars.next_if_eq(&'\\')?;
+        self.chars.next_if_eq(&'u')?;
+        let low = self.hex4()?;
+        if !(0xdc00..0xe000).contains(&low) {
+            return None;
+        }
+        char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00))
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let hex: String = (0..4).map_while(|_| self.chars.next()).collect();
+        if hex.len() != 4 {
+            return None;
+        }
+        u32::from_str_radix(&hex, 16).ok()
+    }
+}
+
+/// Seconds since the epoch, honoring SOURCE_DATE_EPOCH for reproducible builds.
+pub(crate) fn build_timestamp() -> u64 {
+    std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0)
+        })
+}
+
+/// `git describe` of the repo, if it is a git checkout.
+pub(crate) fn git_describe(base_dir: &Path) -> Option<String> {
+    let result = Cmd::new("git")
+        .args(["describe", "--always", "--dirty", "--tags"])
+        .dir(base_dir)
+        .allow_fail()
+        .run()
+        .ok()?;
+    let out = result.stdout.trim();
+    (result.success() && !out.is_empty()).then(|| out.to_string())
+}
+
+/// Kernel release from the single versioned dir under the staged modules.
+fn kernel_release(modules_base: &Path) -> Option<String> {
+    recinit::find_kernel_modules_dir(modules_base)
+        .ok()?
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+}
+
+/// Version component of the static busybox download URL.
+fn busybox_url_version(url: &str) -> Option<String> {
+    url.split('/')
+        .find(|seg| seg.starts_with(|c: char| c.is_ascii_digit()))
+        .and_then(|seg| seg.split('-').next())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offline::ALPINE_ISO_SHA256;
+
+    #[test]
+    fn test_manifest_json() {
+        let manifest = BuildManifest {
+            generated_at: 1700000000,
+            git_describe: None,
+            alpine_iso: "alpine-extended-3.23.2-x86_64.iso".to_string(),
+            alpine_iso_sha256: Some(ALPINE_ISO_SHA256.to_string()),
+            kernel_release: Some("6.12.0-acorn".to_string()),
+            busybox_rootfs: Some("1.37.0-r12".to_string()),
+            busybox_initramfs: busybox_url_version(BUSYBOX_URL),
+            rootfs_format: "erofs".to_string(),
+            iso_label: "ACORN_DEV".to_string(),
+            components: vec!["filesystem".to_string()],
+            packages: crate::apk::parse_installed("P:musl\nV:1.2.5-r8\nA:x86_64\n"),
+            artifacts: vec![ArtifactInfo {
+                name: "rootfs",
+                file: "filesystem.erofs".to_string(),
+                sha256: "ab".repeat(32),
+                size: 42,
+            }],
+        };
+        let json = manifest.to_json();
+        assert!(json.contains("\"name\": \"AcornOS\""));
+        assert!(json.contains("\"git_describe\": null"));
+        assert!(json.contains("\"iso_label\": \"ACORN_DEV\""));
+        assert!(json.contains("\"kernel_release\": \"6.12.0-acorn\""));
+        assert!(json.contains("\"initramfs\": \"1.35.0\""));
+        assert!(json.contains(
+            "{ \"name\": \"musl\", \"version\": \"1.2.5-r8\", \"arch\": \"x86_64\", \"origin\": \"\" }"
+        ));
+        assert!(json.contains("\"rootfs\": { \"file\": \"filesystem.erofs\""));
+        // Balanced braces/brackets as a cheap well-formedness check
+        assert_eq!(json.matches('{').count(), json.matches('}').count());
+        assert_eq!(json.matches('[').count(), json.matches(']').count());
+    }
+
+    #[test]
+    fn test_json_str_escapes() {
+        assert_eq!(json_str("a\"b\\c\nd\u{1}"), "\"a\\\"b\\\\c\\nd\\u0001\"");
+    }
+
+    #[test]
+    fn test_parse_json_round_trips_json_str() {
+        let tricky = "a \"quoted\", {braced} \\ path\n\ttab\r\u{1}é 🌰";
+        let json = format!(
+            "{{\"s\": {}, \"n\": 12, \"f\": -1.5e2, \"list\": [true, null, {{}}], \"o\": {{\"x\": []}}}}",
+            json_str(tricky)
+        );
+        let value = parse_json(&json).unwrap();
+        assert_eq!(value.get("s").and_then(JsonValue::as_str), Some(tricky));
+        assert_eq!(value.get("n").and_then(JsonValue::as_u64), Some(12));
+        assert_eq!(value.get("f").and_then(JsonValue::as_f64), Some(-150.0));
+        let list = value.get("list").and_then(JsonValue::as_array).unwrap();
+        assert_eq!(list[0].as_bool(), Some(true));
+        assert_eq!(list[1], JsonValue::Null);
+        assert_eq!(list[2].as_object(), Some(&[][..]));
+        assert!(value.get("o").unwrap().get("x").is_some());
+
+        assert_eq!(
+            parse_json("\"\\ud83c\\udf30\\u00e9\\/\"").unwrap(),
+            JsonValue::String("🌰é/".to_string())
+        );
+        for bad in [
+            "{\"a\": 1",
+            "{\"a\" 1}",
+            "[1,]",
+            "\"\\q\"",
+            "\"\\ud83c\"",
+            "1 2",
+            "nul",
+        ] {
+            assert_eq!(parse_json(bad), None, "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,355 @@
+//! Offline (air-gapped) dependency validation for AcornOS.
+//!
+//! In offline mode the build never touches the network. Everything that
+//! `acornos download` would normally fetch must already be present under
+//! `downloads/` (e.g. pre-seeded from a USB drive), and is verified locally
+//! instead of being resolved through the recipes.
+//!
+//! Offline mode is enabled with the global `--offline` flag or by setting
+//! `ACORNOS_OFFLINE=1` in the environment. Outside of
+//! `acornos download`, every download site fails fast with the missing file
+//! and where to pre-place it instead of reaching for the network.
+//!
+//! Pre-seeded files are held to their pins (see [`crate::pins`]): the ISO,
+//! the apk-tools-static package and, on x86_64, busybox from busybox.net.
+//! A `downloads/alpine-iso.sha256` next to the ISO is only compared as a
+//! hint, like the mirror's `.sha256` online.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use distro_builder::process::Cmd;
+
+use crate::arch::Arch;
+use crate::download_check::{apk_tools_dir, APK_TOOLS_STATIC_APK, APK_TOOLS_STATIC_SHA256};
+use crate::pins::{Pins, BUSYBOX_KEY, PINS_FILE};
+
+/// Environment variable that enables offline mode.
+pub const OFFLINE_ENV: &str = "ACORNOS_OFFLINE";
+
+/// Pinned SHA256 of the Alpine Extended ISO.
+///
+/// Canonical source: deps/alpine.rhai (`sha256`).
+pub const ALPINE_ISO_SHA256: &str =
+    "8d50854936dba58e7616ac3bfa07ad67dac0347305fec1cba6d288cf5df1577d";
+
+/// Locally cached copy of the mirror's `.sha256`, compared as a hint only.
+pub const CACHED_ISO_CHECKSUM: &str = "alpine-iso.sha256";
+
+/// Check whether offline mode is enabled via the environment.
+pub fn offline_from_env() -> bool {
+    std::env::var(OFFLINE_ENV)
+        .map(|v| matches!(v.trim(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+/// Check whether offline mode is enabled (CLI flag or environment).
+pub fn is_offline(flag: bool) -> bool {
+    flag || offline_from_env()
+}
+
+/// Export offline mode so the recipes and parallel child builds see it.
+///
+/// Must be called before any threads are spawned.
+pub fn export() {
+    std::env::set_var(OFFLINE_ENV, "1");
+}
+
+/// Error for a download refused in offline mode.
+pub fn download_refused(what: &str, url: &str, dest: &Path) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Offline mode: {} is missing and will not be downloaded from {}.\n\
+         Pre-place it at {} (e.g. copied from a connected machine), or unset {} to allow network access.",
+        what,
+        url,
+        dest.display(),
+        OFFLINE_ENV
+    )
+}
+
+/// Locally cached dependencies required for an offline build.
+#[derive(Debug, Clone)]
+pub struct OfflineCache {
+    /// Alpine Extended ISO.
+    pub iso: PathBuf,
+    /// Extracted apk.static binary.
+    pub apk_static: PathBuf,
+    /// apk-tools-static package it was extracted from.
+    pub apk_tools_package: PathBuf,
+    /// Pinned SHA256 of that package.
+    pub apk_tools_sha256: String,
+    /// Static busybox used by the initramfs.
+    pub busybox: PathBuf,
+    /// Optional locally cached ISO checksum (`sha256  filename` format).
+    pub iso_checksum: PathBuf,
+    /// Project dir that may hold `acorn-pins.toml`.
+    pub pins_dir: PathBuf,
+    /// Target arch; only the x86_64 busybox comes from busybox.net.
+    pub arch: Arch,
+}
+
+impl OfflineCache {
+    /// Resolve the cache locations for a build directory.
+    pub fn new(base_dir: &Path) -> Self {
+        use distro_builder::alpine::extract::ExtractPaths;
+
+        let paths = ExtractPaths::new(base_dir);
+        let downloads = base_dir.join("downloads");
+
+        Self {
+            iso: paths.iso,
+            apk_static: paths.apk_tools.join("sbin").join("apk.static"),
+            apk_tools_package: apk_tools_dir(base_dir).join(APK_TOOLS_STATIC_APK),
+            apk_tools_sha256: APK_TOOLS_STATIC_SHA256.to_string(),
+            busybox: downloads.join(BUSYBOX_KEY),
+            iso_checksum: downloads.join(CACHED_ISO_CHECKSUM),
+            pins_dir: base_dir.to_path_buf(),
+            arch: Arch::from_env().unwrap_or_default(),
+        }
+    }
+
+    /// Expected ISO checksum: its pin (see [`crate::pins`]). The locally
+    /// cached `.sha256` never overrides it; a disagreeing one is reported.
+    pub fn expected_iso_sha256(&self) -> Result<String> {
+        let name = self
+            .iso
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let pin = Pins::load(&self.pins_dir)?.iso(&name).with_context(|| {
+            format!(
+                "{} has no pin: add its SHA256 to {} ([alpine-iso])",
+                name, PINS_FILE
+            )
+        })?;
+
+        let cached = fs::read_to_string(&self.iso_checksum)
+            .ok()
+            .and_then(|text| crate::download_check::parse_sha256(&text));
+        if let Some(cached) = cached.filter(|c| !c.eq_ignore_ascii_case(&pin.sha256)) {
+            log::warn!(
+                "{} says {}, but {} is pinned to {} ({}); using the pin",
+                self.iso_checksum.display(),
+                cached,
+                name,
+                pin.sha256,
+                pin.source
+            );
+        }
+        Ok(pin.sha256)
+    }
+
+    /// Problem with the pinned file `path`, if it is missing or differs.
+    fn check_pinned(what: &str, path: &Path, expected: &str) -> Option<String> {
+        if !path.exists() {
+            return Some(format!("{} missing at {}", what, path.display()));
+        }
+        match sha256_file(path) {
+            Ok(actual) if actual.eq_ignore_ascii_case(expected) => None,
+            Ok(actual) => Some(format!(
+                "{} checksum mismatch at {} (expected {}, got {})",
+                what,
+                path.display(),
+                expected,
+                actual
+            )),
+            Err(e) => Some(format!("{} could not be hashed: {:#}", what, e)),
+        }
+    }
+
+    /// Verify every cached dependency, collecting all problems into one report.
+    ///
+    /// Never performs network access.
+    pub fn verify(&self) -> Result<()> {
+        let mut problems = Vec::new();
+
+        if !self.iso.exists() {
+            problems.push(format!("Alpine ISO missing at {}", self.iso.display()));
+        } else {
+            match self.expected_iso_sha256() {
+                Ok(expected) => {
+                    problems.extend(Self::check_pinned("Alpine ISO", &self.iso, &expected))
+                }
+                Err(e) => problems.push(format!("{:#}", e)),
+            }
+        }
+
+        problems.extend(Self::check_pinned(
+            "apk-tools-static package",
+            &self.apk_tools_package,
+            &self.apk_tools_sha256,
+        ));
+        if !self.apk_static.exists() {
+            problems.push(format!(
+                "apk-tools-static missing at {}",
+                self.apk_static.display()
+            ));
+        }
+
+        // Other arches copy it from the signature-checked Alpine package
+        let busybox_pin = match self.arch {
+            Arch::X86_64 => Pins::load(&self.pins_dir)
+                .map(|pins| pins.busybox())
+                .unwrap_or_default(),
+            _ => None,
+        };
+        match busybox_pin {
+            Some(pin) => problems.extend(Self::check_pinned(
+                "busybox-static",
+                &self.busybox,
+                &pin.sha256,
+            )),
+            None if !self.busybox.exists() => problems.push(format!(
+                "busybox-static missing at {}",
+                self.busybox.display()
+            )),
+            None if self.arch == Arch::X86_64 => problems.push(format!(
+                "busybox-static has no pin: add its SHA256 to {} ([busybox] \"{}\")",
+                PINS_FILE, BUSYBOX_KEY
+            )),
+            None => {}
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            bail!(
+                "Offline mode: {} required download(s) unavailable:\n  - {}\n\n\
+                 Pre-seed downloads/ from a connected machine, or unset {} to allow network access.",
+                problems.len(),
+                problems.join("\n  - "),
+                OFFLINE_ENV
+            );
+        }
+    }
+}
+
+/// Compute the SHA256 of a file using `sha256sum`.
+pub(crate) fn sha256_file(path: &Path) -> Result<String> {
+    let result = Cmd::new("sha256sum")
+        .arg_path(path)
+        .error_msg("sha256sum failed. Install coreutils.")
+        .run()?;
+
+    match result.stdout.split_whitespace().next() {
+        Some(hash) => Ok(hash.to_lowercase()),
+        None => bail!("sha256sum produced no output for {}", path.display()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    // sha256("acorn")
+    const FAKE_ISO_SHA256: &str =
+        "84f0ceca5ebebf54c45888a573b1c2380ec7e8b35289290af603644f04fb1e21";
+
+    fn fake_cache(dir: &Path) -> OfflineCache {
+        OfflineCache {
+            iso: dir.join("alpine.iso"),
+            apk_static: dir.join("apk.static"),
+            apk_tools_package: dir.join("apk-tools-static.apk"),
+            apk_tools_sha256: FAKE_ISO_SHA256.to_string(),
+            busybox: dir.join("busybox-static"),
+            iso_checksum: dir.join(CACHED_ISO_CHECKSUM),
+            pins_dir: dir.to_path_buf(),
+            arch: Arch::X86_64,
+        }
+    }
+
+    fn pin_all(dir: &Path) {
+        fs::write(
+            dir.join(PINS_FILE),
+            format!(
+                "[alpine-iso]\n\"alpine.iso\" = \"{0}\"\n[busybox]\n\"busybox-static\" = \"{0}\"\n",
+                FAKE_ISO_SHA256
+            ),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_offline_complete_cache() {
+        let dir = tempdir().unwrap();
+        let cache = fake_cache(dir.path());
+        pin_all(dir.path());
+
+        for path in [&cache.iso, &cache.apk_tools_package, &cache.busybox] {
+            fs::write(path, "acorn").unwrap();
+        }
+        fs::write(&cache.apk_static, "").unwrap();
+
+        cache.verify().unwrap();
+
+        // Each pinned file is checked, not just present
+        fs::write(&cache.busybox, "tampered").unwrap();
+        fs::write(&cache.apk_tools_package, "tampered").unwrap();
+        let err = cache.verify().unwrap_err().to_string();
+        assert!(err.contains("2 required download(s)"));
+        assert!(err.contains("busybox-static checksum mismatch"));
+        assert!(err.contains("apk-tools-static package checksum mismatch"));
+
+        // An unpinned busybox.net binary is refused
+        fs::write(&cache.busybox, "acorn").unwrap();
+        fs::write(&cache.apk_tools_package, "acorn").unwrap();
+        fs::write(
+            dir.path().join(PINS_FILE),
+            format!("[alpine-iso]\n\"alpine.iso\" = \"{}\"\n", FAKE_ISO_SHA256),
+        )
+        .unwrap();
+        let err = cache.verify().unwrap_err().to_string();
+        assert!(err.contains("busybox-static has no pin"));
+    }
+
+    #[test]
+    fn test_offline_missing_iso_aggregates_errors() {
+        let dir = tempdir().unwrap();
+        let cache = fake_cache(dir.path());
+
+        fs::write(&cache.apk_static, "").unwrap();
+        fs::write(&cache.apk_tools_package, "acorn").unwrap();
+
+        let err = cache.verify().unwrap_err().to_string();
+        assert!(err.contains("2 required download(s)"));
+        assert!(err.contains("Alpine ISO missing"));
+        assert!(err.contains("busybox-static missing"));
+        assert!(!err.contains("apk-tools-static"));
+    }
+
+    #[test]
+    fn test_download_refused_names_file_and_location() {
+        let err = download_refused(
+            "busybox-static",
+            "https://busybox.net/busybox",
+            Path::new("/tmp/downloads/busybox-static"),
+        )
+        .to_string();
+        assert!(err.starts_with("Offline mode: busybox-static is missing"));
+        assert!(err.contains("https://busybox.net/busybox"));
+        assert!(err.contains("Pre-place it at /tmp/downloads/busybox-static"));
+    }
+
+    #[test]
+    fn test_expected_sha256_is_always_the_pin() {
+        let dir = tempdir().unwrap();
+        let cache = fake_cache(dir.path());
+        assert!(format!("{:#}", cache.expected_iso_sha256().unwrap_err())
+            .contains("alpine.iso has no pin"));
+
+        // The cached .sha256 never stands in for the pin
+        fs::write(
+            &cache.iso_checksum,
+            format!("{}  alpine.iso\n", "0".repeat(64)),
+        )
+        .unwrap();
+        assert!(cache.expected_iso_sha256().is_err());
+        pin_all(dir.path());
+        assert_eq!(cache.expected_iso_sha256().unwrap(), FAKE_ISO_SHA256);
+
+        let built_in = OfflineCache::new(dir.path());
+        assert_eq!(built_in.expected_iso_sha256().unwrap(), ALPINE_ISO_SHA256);
+    }
+}
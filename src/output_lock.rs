@@ -0,0 +1,359 @@
+//! Advisory lock on the output dir (`.acornos.lock`).
+//!
+//! Two mutating runs against one output dir (`build` in one terminal, `iso`
+//! in another) write the same `.tmp`/`.work` files and hash caches, and
+//! one xorriso run reads the other's half-written tree. `build`,
+//! `initramfs`, `iso`, `clean` and `gc --yes` therefore take an exclusive
+//! `flock` on `.acornos.lock` first; a second run waits for it (or fails
+//! with `--no-wait`), naming the holder from the pid, start time and
+//! command recorded in the file. The kernel drops the lock when the holder
+//! exits, however it exits.
+//!
+//! The holder exports its pid in [`LOCK_HELD_ENV`], so its child builds
+//! (`build rootfs` and `initramfs` of a parallel build) run under its lock
+//! instead of waiting for it forever. A child only does so if that pid is
+//! one of its ancestors and still holds the lock on the same output dir; a
+//! value leaked into an unrelated shell is ignored. Read-only commands (`status`, `preflight`,
+//! `run`, `test`) take no lock but warn while one is held.
+//!
+//! A holder that crashed leaves its record behind. Whoever finds a record
+//! whose pid is no longer running treats it as stale: read-only commands do
+//! not warn, and the next mutating run takes the lock over and says so.
+
+use anyhow::{bail, Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Lock file in the output dir.
+pub const LOCK_FILE: &str = ".acornos.lock";
+
+/// Set by the lock holder for its child builds.
+pub const LOCK_HELD_ENV: &str = "ACORNOS_OUTPUT_LOCKED";
+
+/// Who holds (or held) the lock, as recorded in the lock file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockHolder {
+    pub pid: u32,
+    /// Seconds since the epoch.
+    pub started: u64,
+    /// The acornos subcommand, e.g. `build`.
+    pub command: String,
+}
+
+impl LockHolder {
+    fn current(command: &str) -> Self {
+        Self {
+            pid: std::process::id(),
+            started: now(),
+            command: command.to_string(),
+        }
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        let mut pid = None;
+        let mut started = None;
+        let mut command = None;
+        for line in text.lines() {
+            match line.split_once('=') {
+                Some(("pid", value)) => pid = value.parse().ok(),
+                Some(("started", value)) => started = value.parse().ok(),
+                Some(("command", value)) => command = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        Some(Self {
+            pid: pid?,
+            started: started?,
+            command: command?,
+        })
+    }
+
+    fn render(&self) -> String {
+        format!(
+            "pid={}\nstarted={}\ncommand={}\n",
+            self.pid, self.started, self.command
+        )
+    }
+
+    /// Whether the recorded process is still running.
+    pub fn alive(&self) -> bool {
+        let Ok(pid) = libc::pid_t::try_from(self.pid) else {
+            return false;
+        };
+        if pid <= 0 {
+            return false;
+        }
+        // Signal 0 only checks; EPERM means it exists under another user
+        let exists = unsafe { libc::kill(pid, 0) } == 0;
+        exists || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+
+    /// `another acornos build is running (pid N, started Xs ago)`
+    pub fn describe(&self) -> String {
+        format!(
+            "another acornos {} is running (pid {}, started {}s ago)",
+            self.command,
+            self.pid,
+            now().saturating_sub(self.started)
+        )
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn flock(file: &File, operation: libc::c_int) -> std::io::Result<()> {
+    if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+fn read_holder(file: &mut File) -> Option<LockHolder> {
+    let mut text = String::new();
+    file.rewind().ok()?;
+    file.read_to_string(&mut text).ok()?;
+    LockHolder::parse(&text)
+}
+
+/// The exclusive lock on an output dir, released on drop.
+#[derive(Debug)]
+pub struct OutputLock {
+    file: File,
+    path: PathBuf,
+    /// Record of a crashed holder this lock took over.
+    pub stale: Option<LockHolder>,
+}
+
+impl OutputLock {
+    /// Lock `output_dir` for `command`. A held lock is waited for, with a
+    /// message naming the holder, or with `wait` false an error.
+    pub fn acquire(output_dir: &Path, command: &str, wait: bool) -> Result<Self> {
+        std::fs::create_dir_all(output_dir)
+            .with_context(|| format!("Failed to create {}", output_dir.display()))?;
+        let path = output_dir.join(LOCK_FILE);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+
+        if let Err(e) = flock(&file, libc::LOCK_EX | libc::LOCK_NB) {
+            if e.raw_os_error() != Some(libc::EWOULDBLOCK) {
+                return Err(e).with_context(|| format!("Failed to lock {}", path.display()));
+            }
+            let holder = read_holder(&mut file)
+                .map(|h| h.describe())
+                .unwrap_or_else(|| "another acornos run holds the output dir".to_string());
+            if !wait {
+                bail!(
+                    "{} ({}); wait for it or drop --no-wait",
+                    holder,
+                    path.display()
+                );
+            }
+            log::warn!("{}; waiting for it to finish...", holder);
+            flock(&file, libc::LOCK_EX)
+                .with_context(|| format!("Failed to lock {}", path.display()))?;
+        }
+
+        // Ours now: anything recorded is left by a holder that did not
+        // clean up, i.e. crashed
+        let stale = read_holder(&mut file).filter(|h| !h.alive());
+        if let Some(holder) = &stale {
+            log::warn!(
+                "Took over the stale lock of acornos {} (pid {}, no longer running)",
+                holder.command,
+                holder.pid
+            );
+        }
+        file.set_len(0)?;
+        file.rewind()?;
+        file.write_all(LockHolder::current(command).render().as_bytes())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(Self { file, path, stale })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for OutputLock {
+    fn drop(&mut self) {
+        // An empty file is a released lock; the flock goes with the fd
+        let _ = self.file.set_len(0);
+    }
+}
+
+/// The live holder of the lock on `output_dir`, if any (for read-only
+/// commands, which only warn).
+pub fn holder(output_dir: &Path) -> Option<LockHolder> {
+    let mut file = File::open(output_dir.join(LOCK_FILE)).ok()?;
+    let holder = read_holder(&mut file)?;
+    // A shared lock is only refused while the exclusive one is held
+    let held = match flock(&file, libc::LOCK_SH | libc::LOCK_NB) {
+        Ok(()) => {
+            let _ = flock(&file, libc::LOCK_UN);
+            false
+        }
+        Err(_) => true,
+    };
+    (held && holder.alive() && holder.pid != std::process::id()).then_some(holder)
+}
+
+/// Whether a parent acornos holds the lock on `output_dir` for this run.
+pub fn held_by_parent(output_dir: &Path) -> bool {
+    let exported = std::env::var(LOCK_HELD_ENV).ok();
+    let held = parent_holds(output_dir, exported.as_deref());
+    if exported.is_some() && !held {
+        log::debug!(
+            "Ignoring {}: not exported by an ancestor holding {}",
+            LOCK_HELD_ENV,
+            output_dir.join(LOCK_FILE).display()
+        );
+    }
+    held
+}
+
+/// Whether the pid in `exported` is an ancestor of this process and the
+/// live holder of the lock on `output_dir`.
+fn parent_holds(output_dir: &Path, exported: Option<&str>) -> bool {
+    let Some(pid) = exported.and_then(|v| v.parse::<u32>().ok()) else {
+        return false;
+    };
+    is_ancestor(pid) && holder(output_dir).is_some_and(|h| h.pid == pid)
+}
+
+/// Whether `pid` is this process's parent, grandparent, and so on.
+fn is_ancestor(pid: u32) -> bool {
+    let mut current = std::os::unix::process::parent_id();
+    // Bounded in case /proc reports a cycle
+    for _ in 0..64 {
+        if current == pid {
+            return true;
+        }
+        match parent_of(current) {
+            Some(parent) if current > 1 => current = parent,
+            _ => return false,
+        }
+    }
+    false
+}
+
+/// Parent pid of `pid`, from `/proc/<pid>/stat`.
+fn parent_of(pid: u32) -> Option<u32> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // `pid (comm) state ppid ...`; comm may hold spaces and parentheses
+    stat.rsplit_once(')')?
+        .1
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+}
+
+/// Export the held lock for child builds.
+///
+/// Must be called before any threads are spawned.
+pub fn export() {
+    std::env::set_var(LOCK_HELD_ENV, std::process::id().to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_second_lock_fails_fast_and_names_holder() {
+        let dir = tempdir().unwrap();
+        let lock = OutputLock::acquire(dir.path(), "build", false).unwrap();
+        assert_eq!(lock.stale, None);
+        let text = std::fs::read_to_string(lock.path()).unwrap();
+        assert!(text.contains(&format!("pid={}\n", std::process::id())));
+        assert!(text.ends_with("command=build\n"));
+
+        // flock is per open file, so a second open conflicts in-process too
+        let err = OutputLock::acquire(dir.path(), "iso", false)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.starts_with(&format!(
+                "another acornos build is running (pid {}, started ",
+                std::process::id()
+            )),
+            "{}",
+            err
+        );
+        assert!(err.ends_with("wait for it or drop --no-wait"), "{}", err);
+
+        drop(lock);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(LOCK_FILE)).unwrap(),
+            ""
+        );
+        OutputLock::acquire(dir.path(), "iso", false).unwrap();
+    }
+
+    #[test]
+    fn test_stale_lock_detected() {
+        let dir = tempdir().unwrap();
+        // The pid of a process that has exited
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        let crashed = LockHolder {
+            pid,
+            started: now() - 60,
+            command: "build".to_string(),
+        };
+        assert!(!crashed.alive());
+        assert!(LockHolder::current("status").alive());
+        assert_eq!(LockHolder::parse(&crashed.render()), Some(crashed.clone()));
+
+        std::fs::write(dir.path().join(LOCK_FILE), crashed.render()).unwrap();
+        assert_eq!(holder(dir.path()), None);
+        let lock = OutputLock::acquire(dir.path(), "iso", false).unwrap();
+        assert_eq!(lock.stale, Some(crashed));
+    }
+
+    #[test]
+    fn test_held_by_parent_checks_the_holder() {
+        let dir = tempdir().unwrap();
+        let parent = std::os::unix::process::parent_id();
+        let own = std::process::id().to_string();
+        assert!(is_ancestor(parent));
+        assert!(!is_ancestor(std::process::id()));
+
+        // Nothing holds the lock yet
+        assert!(!parent_holds(dir.path(), Some(&parent.to_string())));
+
+        // Held, recorded as the parent's: as a child build sees it
+        let lock = OutputLock::acquire(dir.path(), "build", false).unwrap();
+        let record = LockHolder {
+            pid: parent,
+            started: now(),
+            command: "build".to_string(),
+        };
+        std::fs::write(lock.path(), record.render()).unwrap();
+        assert!(parent_holds(dir.path(), Some(&parent.to_string())));
+
+        // Not an ancestor, not a pid, not exported, or another output dir
+        assert!(!parent_holds(dir.path(), Some(&own)));
+        assert!(!parent_holds(dir.path(), Some("build")));
+        assert!(!parent_holds(dir.path(), None));
+        let other = tempdir().unwrap();
+        assert!(!parent_holds(other.path(), Some(&parent.to_string())));
+    }
+}
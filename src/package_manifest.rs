@@ -0,0 +1,448 @@
+//! Package manifest for compliance (`acornos-manifest.json`).
+//!
+//! Lists every Alpine package installed in the source rootfs with its
+//! version and license, marks which of them the component system actually
+//! copied into the image, and adds the locally built tools (recstrap and
+//! friends) that don't come from apk.
+//!
+//! "In the image" is decided from the staged rootfs: a package counts if
+//! any file it owns (per `lib/apk/db/installed`) was staged. That is the
+//! set the `LicenseTracker` registers licenses for.
+//!
+//! The manifest is written to the output dir and into the live overlay at
+//! `/usr/share/acornos/manifest.json`. `acornos manifest --packages
+//! --format spdx` renders the in-image packages as SPDX 2.3 JSON.
+
+use anyhow::{bail, Context, Result};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use distro_spec::acorn::{OS_NAME, OS_VERSION};
+
+use crate::apk::{installed_files, installed_packages};
+use crate::arch::Arch;
+use crate::manifest::{build_timestamp, json_str};
+use crate::offline::sha256_file;
+use crate::paths::OutputPaths;
+
+/// Package manifest filename in the output directory.
+pub const PACKAGE_MANIFEST_FILENAME: &str = "acornos-manifest.json";
+
+/// SPDX rendering of the package manifest in the output directory.
+pub const PACKAGE_MANIFEST_SPDX_FILENAME: &str = "acornos-manifest.spdx.json";
+
+/// Where the manifest lands in the live system.
+pub const PACKAGE_MANIFEST_LIVE_PATH: &str = "usr/share/acornos/manifest.json";
+
+/// Locally built tools copied into `/usr/bin` by the live components.
+pub const LOCAL_TOOLS: &[&str] = &["recstrap", "recfstab", "recchroot"];
+
+/// Where a package came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageSource {
+    /// Alpine package installed with apk.
+    Apk,
+    /// Tool built from this repo's `tools/` checkout.
+    Local,
+}
+
+impl PackageSource {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Apk => "apk",
+            Self::Local => "local",
+        }
+    }
+}
+
+/// One entry of the package manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestPackage {
+    pub name: String,
+    pub version: String,
+    /// License expression (empty if unknown).
+    pub license: String,
+    pub source: PackageSource,
+    /// Whether any of the package's files are in the image.
+    pub in_image: bool,
+    /// Binary hash, for local tools.
+    pub sha256: Option<String>,
+}
+
+/// Output format of `acornos manifest --packages`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ManifestFormat {
+    #[default]
+    Json,
+    Spdx,
+}
+
+impl FromStr for ManifestFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "spdx" => Ok(Self::Spdx),
+            other => bail!(
+                "Unknown manifest format '{}' (expected json or spdx)",
+                other
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ManifestFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Json => "json",
+            Self::Spdx => "spdx",
+        })
+    }
+}
+
+/// Packages and local tools in a build.
+#[derive(Debug, Clone, Default)]
+pub struct PackageManifest {
+    pub generated_at: u64,
+    pub packages: Vec<ManifestPackage>,
+}
+
+impl PackageManifest {
+    /// Collect from the source `rootfs` and the `staging` tree it was
+    /// built into; `tools_dir` holds the local tool checkouts.
+    pub fn collect(rootfs: &Path, staging: &Path, tools_dir: &Path) -> Result<Self> {
+        let files = installed_files(rootfs)?;
+        let mut packages: Vec<ManifestPackage> = installed_packages(rootfs)?
+            .into_iter()
+            .map(|p| {
+                let in_image = files.get(&p.name).is_some_and(|owned| {
+                    owned
+                        .iter()
+                        .any(|f| staging.join(f).symlink_metadata().is_ok())
+                });
+                ManifestPackage {
+                    name: p.name,
+                    version: p.version,
+                    license: p.license,
+                    source: PackageSource::Apk,
+                    in_image,
+                    sha256: None,
+                }
+            })
+            .collect();
+
+        for tool in LOCAL_TOOLS {
+            let binary = staging.join("usr/bin").join(tool);
+            // The recstrap placeholder script is not a package
+            if Arch::of_elf(&binary).is_none() {
+                continue;
+            }
+            let (version, license) = cargo_package_info(&tools_dir.join(tool).join("Cargo.toml"));
+            packages.push(ManifestPackage {
+                name: tool.to_string(),
+                version: version.unwrap_or_else(|| "unknown".to_string()),
+                license: license.unwrap_or_default(),
+                source: PackageSource::Local,
+                in_image: true,
+                sha256: Some(sha256_file(&binary)?),
+            });
+        }
+
+        Ok(Self {
+            generated_at: build_timestamp(),
+            packages,
+        })
+    }
+
+    /// Render as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        let packages: Vec<String> = self
+            .packages
+            .iter()
+            .map(|p| {
+                let sha = p.sha256.as_deref().map(json_str).unwrap_or("null".into());
+                format!(
+                    "    {{ \"name\": {}, \"version\": {}, \"license\": {}, \"origin\": {}, \"in_image\": {}, \"sha256\": {} }}",
+                    json_str(&p.name),
+                    json_str(&p.version),
+                    json_str(&p.license),
+                    json_str(p.source.name()),
+                    p.in_image,
+                    sha
+                )
+            })
+            .collect();
+
+        let mut out = String::from("{\n");
+        out.push_str(&format!(
+            "  \"distro\": {{ \"name\": {}, \"version\": {} }},\n",
+            json_str(OS_NAME),
+            json_str(OS_VERSION)
+        ));
+        out.push_str(&format!("  \"generated_at\": {},\n", self.generated_at));
+        out.push_str(&format!(
+            "  \"packages\": [\n{}\n  ]\n",
+            packages.join(",\n")
+        ));
+        out.push_str("}\n");
+        out
+    }
+
+    /// Render the in-image packages as an SPDX 2.3 JSON document.
+    pub fn to_spdx(&self) -> String {
+        let packages: Vec<String> = self
+            .packages
+            .iter()
+            .filter(|p| p.in_image)
+            .map(|p| {
+                let supplier = match p.source {
+                    PackageSource::Apk => "Organization: Alpine Linux",
+                    PackageSource::Local => "Organization: LevitateOS",
+                };
+                let mut fields = vec![
+                    format!("\"name\": {}", json_str(&p.name)),
+                    format!(
+                        "\"SPDXID\": {}",
+                        json_str(&format!("SPDXRef-Package-{}", spdx_id(&p.name)))
+                    ),
+                    format!("\"versionInfo\": {}", json_str(&p.version)),
+                    format!("\"supplier\": {}", json_str(supplier)),
+                    "\"downloadLocation\": \"NOASSERTION\"".to_string(),
+                    "\"filesAnalyzed\": false".to_string(),
+                    "\"licenseConcluded\": \"NOASSERTION\"".to_string(),
+                    format!(
+                        "\"licenseDeclared\": {}",
+                        json_str(spdx_license(&p.license))
+                    ),
+                ];
+                if let Some(sha) = &p.sha256 {
+                    fields.push(format!(
+                        "\"checksums\": [{{ \"algorithm\": \"SHA256\", \"checksumValue\": {} }}]",
+                        json_str(sha)
+                    ));
+                }
+                format!("    {{ {} }}", fields.join(", "))
+            })
+            .collect();
+
+        let mut out = String::from("{\n");
+        out.push_str("  \"spdxVersion\": \"SPDX-2.3\",\n");
+        out.push_str("  \"dataLicense\": \"CC0-1.0\",\n");
+        out.push_str("  \"SPDXID\": \"SPDXRef-DOCUMENT\",\n");
+        out.push_str(&format!(
+            "  \"name\": {},\n",
+            json_str(&format!("{}-{}", OS_NAME, OS_VERSION))
+        ));
+        out.push_str(&format!(
+            "  \"documentNamespace\": {},\n",
+            json_str(&format!(
+                "https://levitateos.org/spdx/{}-{}-{}",
+                OS_NAME.to_ascii_lowercase(),
+                OS_VERSION,
+                self.generated_at
+            ))
+        ));
+        out.push_str(&format!(
+            "  \"creationInfo\": {{ \"created\": {}, \"creators\": [\"Tool: acornos\"] }},\n",
+            json_str(&iso8601(self.generated_at))
+        ));
+        out.push_str(&format!(
+            "  \"packages\": [\n{}\n  ]\n",
+            packages.join(",\n")
+        ));
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// Collect the manifest and write it to the output dir and the live overlay.
+pub fn write_package_manifest(base_dir: &Path, output_dir: &Path) -> Result<PathBuf> {
+    use distro_builder::alpine::extract::ExtractPaths;
+
+    let paths = ExtractPaths::new(base_dir);
+    let output = OutputPaths::new(output_dir);
+    let manifest = PackageManifest::collect(
+        &paths.rootfs,
+        &output.rootfs_staging(),
+        &base_dir.join("../tools"),
+    )?;
+    let json = manifest.to_json();
+
+    let path = output_dir.join(PACKAGE_MANIFEST_FILENAME);
+    fs::write(&path, &json).with_context(|| format!("Failed to write {}", path.display()))?;
+    let live = output.live_overlay().join(PACKAGE_MANIFEST_LIVE_PATH);
+    if let Some(parent) = live.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&live, &json).with_context(|| format!("Failed to write {}", live.display()))?;
+    Ok(path)
+}
+
+/// `version` and `license` from a Cargo manifest's `[package]` table.
+fn cargo_package_info(cargo_toml: &Path) -> (Option<String>, Option<String>) {
+    let Ok(content) = fs::read_to_string(cargo_toml) else {
+        return (None, None);
+    };
+    let mut in_package = false;
+    let (mut version, mut license) = (None, None);
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_package = line == "[package]";
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=').filter(|_| in_package) else {
+            continue;
+        };
+        let value = value.trim();
+        // `version.workspace = true` and the like are not plain strings
+        if !value.starts_with('"') {
+            continue;
+        }
+        let value = Some(value.trim_matches('"').to_string());
+        match key.trim() {
+            "version" => version = value,
+            "license" => license = value,
+            _ => {}
+        }
+    }
+    (version, license)
+}
+
+/// SPDX identifiers allow only letters, digits, `.` and `-`.
+fn spdx_id(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+/// Declared license, or NOASSERTION for Alpine's non-SPDX placeholders.
+fn spdx_license(license: &str) -> &str {
+    match license.trim() {
+        "" | "custom" | "custom:multiple" => "NOASSERTION",
+        other => other,
+    }
+}
+
+/// `YYYY-MM-DDTHH:MM:SSZ` for a Unix timestamp.
+pub(crate) fn iso8601(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    // Civil-from-days (Howard Hinnant)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_collect_package_manifest() {
+        let rootfs = tempdir().unwrap();
+        let staging = tempdir().unwrap();
+        let tools = tempdir().unwrap();
+        fs::create_dir_all(rootfs.path().join("lib/apk/db")).unwrap();
+        fs::write(
+            rootfs.path().join("lib/apk/db/installed"),
+            "P:musl\nV:1.2.5-r8\nL:MIT\nF:lib\nR:libc.musl-x86_64.so.1\n\n\
+             P:nano\nV:8.2-r0\nL:GPL-3.0-or-later\nF:usr/bin\nR:nano\n",
+        )
+        .unwrap();
+        fs::create_dir_all(staging.path().join("lib")).unwrap();
+        fs::write(staging.path().join("lib/libc.musl-x86_64.so.1"), "").unwrap();
+
+        // A real recstrap binary is listed, the placeholder script is not
+        fs::create_dir_all(staging.path().join("usr/bin")).unwrap();
+        let mut elf = vec![0u8; 64];
+        elf[..4].copy_from_slice(b"\x7fELF");
+        elf[18] = 0x3e;
+        fs::write(staging.path().join("usr/bin/recstrap"), elf).unwrap();
+        fs::write(staging.path().join("usr/bin/recfstab"), "#!/bin/sh\n").unwrap();
+        fs::create_dir_all(tools.path().join("recstrap")).unwrap();
+        fs::write(
+            tools.path().join("recstrap/Cargo.toml"),
+            "[package]\nname = \"recstrap\"\nversion = \"0.3.1\"\nlicense = \"MIT\"\n\n[dependencies]\nversion = \"9\"\n",
+        )
+        .unwrap();
+
+        let manifest =
+            PackageManifest::collect(rootfs.path(), staging.path(), tools.path()).unwrap();
+        let names: Vec<(&str, bool)> = manifest
+            .packages
+            .iter()
+            .map(|p| (p.name.as_str(), p.in_image))
+            .collect();
+        assert_eq!(names, [("musl", true), ("nano", false), ("recstrap", true)]);
+        assert_eq!(manifest.packages[2].version, "0.3.1");
+        assert_eq!(manifest.packages[2].source, PackageSource::Local);
+
+        let json = manifest.to_json();
+        assert!(json.contains(
+            "{ \"name\": \"musl\", \"version\": \"1.2.5-r8\", \"license\": \"MIT\", \"origin\": \"apk\", \"in_image\": true, \"sha256\": null }"
+        ));
+        assert!(json.contains("\"origin\": \"local\""));
+        assert_eq!(json.matches('{').count(), json.matches('}').count());
+    }
+
+    #[test]
+    fn test_spdx_output() {
+        let manifest = PackageManifest {
+            generated_at: 1700000000,
+            packages: vec![
+                ManifestPackage {
+                    name: "py3-foo_bar".to_string(),
+                    version: "1.0-r0".to_string(),
+                    license: "custom".to_string(),
+                    source: PackageSource::Apk,
+                    in_image: true,
+                    sha256: None,
+                },
+                ManifestPackage {
+                    name: "nano".to_string(),
+                    version: "8.2-r0".to_string(),
+                    license: "GPL-3.0-or-later".to_string(),
+                    source: PackageSource::Apk,
+                    in_image: false,
+                    sha256: None,
+                },
+            ],
+        };
+        let spdx = manifest.to_spdx();
+        assert!(spdx.contains("\"spdxVersion\": \"SPDX-2.3\""));
+        assert!(spdx.contains("\"created\": \"2023-11-14T22:13:20Z\""));
+        assert!(spdx.contains("\"SPDXID\": \"SPDXRef-Package-py3-foo-bar\""));
+        assert!(spdx.contains("\"licenseDeclared\": \"NOASSERTION\""));
+        assert!(!spdx.contains("nano"));
+        assert_eq!(
+            "SPDX".parse::<ManifestFormat>().unwrap(),
+            ManifestFormat::Spdx
+        );
+    }
+}
@@ -0,0 +1,307 @@
+//! Custom package overlay (`acorn-packages.toml`).
+//!
+//! Adds packages to, or removes them from, the tier lists in
+//! `deps/packages.rhai` without editing the recipe. The file lives in the
+//! project root:
+//!
+//! ```toml
+//! [add]
+//! packages = ["htop", "strace"]
+//! vim = "9.1.0-r0"   # pinned version
+//!
+//! [remove]
+//! packages = ["nano"]
+//! ```
+//!
+//! Removals are dropped from the tiers (and `apk del`ed if an earlier run
+//! installed them); additions are installed as one extra `OVERLAY` tier.
+//! Only this subset of TOML is accepted, and anything else fails with the
+//! line and key at fault.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::apk_install::{is_valid_package_name, PackageTier};
+use crate::toml_subset::{self, Item, Line};
+
+/// Overlay file in the project root.
+pub const OVERLAY_FILE: &str = "acorn-packages.toml";
+
+/// Overlay applied by the last install, under `downloads/`.
+pub const OVERLAY_STATE: &str = ".packages-overlay";
+
+/// Name of the tier holding the additions.
+pub const OVERLAY_TIER: &str = "OVERLAY";
+
+/// A package added by the overlay.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverlayPackage {
+    pub name: String,
+    /// Exact version (`9.1.0-r0`), if pinned.
+    pub version: Option<String>,
+}
+
+impl OverlayPackage {
+    /// Argument for `apk add` (`name` or `name=version`).
+    pub fn apk_spec(&self) -> String {
+        match &self.version {
+            Some(version) => format!("{}={}", self.name, version),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// Parsed `acorn-packages.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackageOverlay {
+    pub add: Vec<OverlayPackage>,
+    pub remove: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+    None,
+    Add,
+    Remove,
+}
+
+impl PackageOverlay {
+    /// Load the overlay from `base_dir`, if the file exists.
+    pub fn load(base_dir: &Path) -> Result<Option<Self>> {
+        let path = base_dir.join(OVERLAY_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let overlay = Self::parse(&text).with_context(|| format!("Invalid {}", OVERLAY_FILE))?;
+        Ok(Some(overlay))
+    }
+
+    /// Parse the overlay; errors name the line and key.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut overlay = Self::default();
+        let mut section = Section::None;
+
+        for Line { lineno, item } in toml_subset::parse(text)? {
+            let (key, value) = match item {
+                Item::Table(name) if name == "add" => {
+                    section = Section::Add;
+                    continue;
+                }
+                Item::Table(name) if name == "remove" => {
+                    section = Section::Remove;
+                    continue;
+                }
+                Item::Table(name) | Item::ArrayTable(name) => bail!(
+                    "line {}: unknown section [{}] (expected [add] or [remove])",
+                    lineno,
+                    name
+                ),
+                Item::Entry { key, value } => (key, value),
+            };
+            let key = key.as_str();
+            if section == Section::None {
+                bail!(
+                    "line {}: key `{}` outside of an [add] or [remove] section",
+                    lineno,
+                    key
+                );
+            }
+
+            if key == "packages" {
+                let names = value.strings().with_context(|| {
+                    format!(
+                        "line {}: key `packages`: expected an array of quoted package names, got {}",
+                        lineno,
+                        value.raw()
+                    )
+                })?;
+                for name in names {
+                    check_name(&name, lineno, key)?;
+                    match section {
+                        Section::Add => overlay.push_add(name, None, lineno)?,
+                        _ => overlay.push_remove(name, lineno)?,
+                    }
+                }
+                continue;
+            }
+
+            if section == Section::Remove {
+                bail!(
+                    "line {}: key `{}`: [remove] only takes `packages = [...]`",
+                    lineno,
+                    key
+                );
+            }
+            check_name(key, lineno, key)?;
+            let version = value.string().with_context(|| {
+                format!(
+                    "line {}: key `{}`: expected a quoted version like \"9.1.0-r0\"",
+                    lineno, key
+                )
+            })?;
+            if version.is_empty() || version.contains(char::is_whitespace) {
+                bail!(
+                    "line {}: key `{}`: invalid version '{}'",
+                    lineno,
+                    key,
+                    version
+                );
+            }
+            overlay.push_add(key.to_string(), Some(version), lineno)?;
+        }
+
+        if let Some(pkg) = overlay
+            .add
+            .iter()
+            .find(|p| overlay.remove.contains(&p.name))
+        {
+            bail!("package '{}' is both added and removed", pkg.name);
+        }
+        Ok(overlay)
+    }
+
+    fn push_add(&mut self, name: String, version: Option<String>, lineno: usize) -> Result<()> {
+        if self.add.iter().any(|p| p.name == name) {
+            bail!("line {}: key `{}`: package added twice", lineno, name);
+        }
+        self.add.push(OverlayPackage { name, version });
+        Ok(())
+    }
+
+    fn push_remove(&mut self, name: String, lineno: usize) -> Result<()> {
+        if self.remove.contains(&name) {
+            bail!("line {}: key `packages`: '{}' removed twice", lineno, name);
+        }
+        self.remove.push(name);
+        Ok(())
+    }
+
+    /// Drop removed packages from `tiers` and append the additions as the
+    /// `OVERLAY` tier.
+    ///
+    /// Unpinned additions already in a tier are left there; pinned ones are
+    /// moved to the overlay tier so the pin applies. Tiers left empty are
+    /// dropped.
+    pub fn apply(&self, tiers: &mut Vec<PackageTier>) {
+        for tier in tiers.iter_mut() {
+            tier.packages.retain(|pkg| {
+                !self.remove.contains(pkg)
+                    && !self
+                        .add
+                        .iter()
+                        .any(|p| &p.name == pkg && p.version.is_some())
+            });
+        }
+        tiers.retain(|t| !t.packages.is_empty());
+
+        let packages: Vec<String> = self
+            .add
+            .iter()
+            .filter(|p| p.version.is_some() || !tiers.iter().any(|t| t.packages.contains(&p.name)))
+            .map(OverlayPackage::apk_spec)
+            .collect();
+        if !packages.is_empty() {
+            tiers.push(PackageTier {
+                name: OVERLAY_TIER.to_string(),
+                packages,
+            });
+        }
+    }
+
+    /// Canonical form, recorded in [`OVERLAY_STATE`] to detect edits.
+    ///
+    /// Empty when there is nothing to apply.
+    pub fn fingerprint(&self) -> String {
+        if self.add.is_empty() && self.remove.is_empty() {
+            return String::new();
+        }
+        let add: Vec<String> = self.add.iter().map(OverlayPackage::apk_spec).collect();
+        format!("add={};remove={}", add.join(","), self.remove.join(","))
+    }
+
+    /// `N additions, M removals`.
+    pub fn summary(&self) -> String {
+        format!(
+            "{} addition{}, {} removal{}",
+            self.add.len(),
+            if self.add.len() == 1 { "" } else { "s" },
+            self.remove.len(),
+            if self.remove.len() == 1 { "" } else { "s" }
+        )
+    }
+}
+
+fn check_name(name: &str, lineno: usize, key: &str) -> Result<()> {
+    if !is_valid_package_name(name) {
+        bail!(
+            "line {}: key `{}`: invalid package name '{}'",
+            lineno,
+            key,
+            name
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OVERLAY: &str = r#"
+# local additions
+[add]
+packages = [
+    "htop",   # process viewer
+    "strace",
+]
+vim = "9.1.0-r0"
+
+[remove]
+packages = ["nano"]
+"#;
+
+    #[test]
+    fn test_parse_overlay() {
+        let overlay = PackageOverlay::parse(OVERLAY).unwrap();
+        let specs: Vec<String> = overlay.add.iter().map(|p| p.apk_spec()).collect();
+        assert_eq!(specs, ["htop", "strace", "vim=9.1.0-r0"]);
+        assert_eq!(overlay.remove, ["nano"]);
+        assert_eq!(overlay.summary(), "3 additions, 1 removal");
+
+        let err = |text: &str| format!("{:#}", PackageOverlay::parse(text).unwrap_err());
+        assert!(err("[add]\nvim = 9.1\n").contains("line 2: key `vim`"));
+        assert!(err("[remove]\nvim = \"1.0\"\n").contains("key `vim`"));
+        assert!(err("[add]\npackages = [\"Bad Name\"]\n").contains("key `packages`"));
+        assert!(err("[add]\npackages = [\"a\",\n").contains("unterminated array"));
+        assert!(err("vim = \"1.0\"\n").contains("outside of an [add]"));
+        assert!(err("[extra]\n").contains("unknown section [extra]"));
+        assert!(
+            err("[add]\npackages = [\"nano\"]\n[remove]\npackages = [\"nano\"]\n")
+                .contains("both added and removed")
+        );
+    }
+
+    #[test]
+    fn test_apply_overlay() {
+        let mut tiers = vec![
+            PackageTier {
+                name: "TIER1_CORE".to_string(),
+                packages: vec!["nano".to_string(), "htop".to_string()],
+            },
+            PackageTier {
+                name: "TIER2_EDIT".to_string(),
+                packages: vec!["vim".to_string()],
+            },
+        ];
+        PackageOverlay::parse(OVERLAY).unwrap().apply(&mut tiers);
+
+        // nano removed, htop stays in its tier, the pinned vim moves
+        assert_eq!(tiers.len(), 2);
+        assert_eq!(tiers[0].packages, ["htop"]);
+        assert_eq!(tiers[1].name, OVERLAY_TIER);
+        assert_eq!(tiers[1].packages, ["strace", "vim=9.1.0-r0"]);
+    }
+}
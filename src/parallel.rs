@@ -0,0 +1,158 @@
+//! Concurrent build steps with prefixed output.
+//!
+//! The artifact builders print straight to stdout (as do recinit and the
+//! external tools they run), so in-process threads would interleave
+//! unreadably. Each job instead runs as a child process whose stdout and
+//! stderr lines are prefixed with the job label (`[rootfs] ...`).
+//!
+//! Every job runs to completion; a failure in one does not kill the other,
+//! since both builders write to `.work`/temp files and only swap results
+//! into place on success. All failures are reported together.
+
+use anyhow::{anyhow, Context, Result};
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use crate::timing::PhaseTimer;
+
+/// A labelled child process.
+pub struct Job {
+    pub label: String,
+    pub command: Command,
+}
+
+impl Job {
+    pub fn new(label: impl Into<String>, command: Command) -> Self {
+        Self {
+            label: label.into(),
+            command,
+        }
+    }
+}
+
+/// `[label] line`.
+pub fn prefix_line(label: &str, line: &str) -> String {
+    format!("[{}] {}", label, line)
+}
+
+/// Run one job, forwarding its output line by line with the label prefix.
+///
+/// The job is timed with [`PhaseTimer`] under its label.
+pub fn run_prefixed(job: Job) -> Result<()> {
+    let Job { label, mut command } = job;
+    let timer = PhaseTimer::start(&label);
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to start {} build", label))?;
+
+    let forward = |stream: Box<dyn Read + Send>, to_stderr: bool, label: String| {
+        thread::spawn(move || {
+            for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                if to_stderr {
+                    eprintln!("{}", prefix_line(&label, &line));
+                } else {
+                    println!("{}", prefix_line(&label, &line));
+                }
+            }
+        })
+    };
+    let mut readers = Vec::new();
+    if let Some(out) = child.stdout.take() {
+        readers.push(forward(Box::new(out), false, label.clone()));
+    }
+    if let Some(err) = child.stderr.take() {
+        readers.push(forward(Box::new(err), true, label.clone()));
+    }
+
+    let status = child.wait()?;
+    for reader in readers {
+        let _ = reader.join();
+    }
+    if !status.success() {
+        return Err(anyhow!("{} build failed ({})", label, status));
+    }
+    timer.finish();
+    Ok(())
+}
+
+/// Run all jobs concurrently and wait for every one of them.
+///
+/// Returns one error listing every failed job.
+pub fn run_all(jobs: Vec<Job>) -> Result<()> {
+    let results: Vec<(String, Result<()>)> = thread::scope(|scope| {
+        let handles: Vec<_> = jobs
+            .into_iter()
+            .map(|job| {
+                let label = job.label.clone();
+                (label, scope.spawn(move || run_prefixed(job)))
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|(label, handle)| {
+                let result = handle
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow!("{} build thread panicked", label)));
+                (label, result)
+            })
+            .collect()
+    });
+
+    let failures: Vec<String> = results
+        .into_iter()
+        .filter_map(|(_, result)| result.err())
+        .map(|e| format!("{:#}", e))
+        .collect();
+    if !failures.is_empty() {
+        return Err(anyhow!(
+            "{} parallel build step(s) failed:\n  {}",
+            failures.len(),
+            failures.join("\n  ")
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> Command {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", script]);
+        cmd
+    }
+
+    #[test]
+    fn test_run_all_waits_for_every_job() {
+        assert_eq!(prefix_line("rootfs", "done"), "[rootfs] done");
+
+        run_all(vec![
+            Job::new("a", sh("echo one; echo two >&2")),
+            Job::new("b", sh("true")),
+        ])
+        .unwrap();
+
+        // The slow job still finishes when the fast one fails
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("finished");
+        let err = run_all(vec![
+            Job::new("fails", sh("exit 3")),
+            Job::new(
+                "slow",
+                sh(&format!("sleep 0.2; touch {}", marker.display())),
+            ),
+            Job::new("also-fails", sh("exit 1")),
+        ])
+        .unwrap_err()
+        .to_string();
+        assert!(marker.exists());
+        assert!(err.starts_with("2 parallel build step(s) failed"));
+        assert!(err.contains("fails build failed"));
+        assert!(err.contains("also-fails build failed"));
+    }
+}
@@ -0,0 +1,173 @@
+//! Project and output directories (`--base-dir`, `--output-dir`).
+//!
+//! The base dir holds the recipes, `downloads/` and the profile overlays;
+//! it defaults to the source checkout this binary was built from. Build
+//! artifacts go to the distro output dir under it unless `--output-dir` (or
+//! `ACORNOS_OUTPUT_DIR`) puts them elsewhere, e.g. on a larger disk.
+//!
+//! Every module resolves the output dir through [`output_dir`], and `main`
+//! exports both overrides so the recipes and parallel child builds use the
+//! same directories. The artifacts inside it are named by [`OutputPaths`],
+//! used alike by the steps writing them and the steps reading them; the
+//! Alpine inputs under `downloads/` come from distro-builder's
+//! `ExtractPaths`.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use distro_spec::acorn::{ISO_FILENAME, KERNEL_FILENAME};
+
+use crate::artifact::full_initramfs::INITRAMFS_INSTALLED_OUTPUT;
+use crate::artifact::netinstall::{IsoVariant, INITRAMFS_NETINSTALL_OUTPUT};
+use crate::artifact::{InitramfsCompression, RootfsFormat};
+
+/// Environment override for the base dir.
+pub const BASE_DIR_ENV: &str = "ACORNOS_BASE_DIR";
+
+/// Environment override for the output dir.
+pub const OUTPUT_DIR_ENV: &str = "ACORNOS_OUTPUT_DIR";
+
+/// Base dir for this run: the CLI flag, else `ACORNOS_BASE_DIR`, else the
+/// source checkout.
+pub fn base_dir(flag: Option<PathBuf>) -> PathBuf {
+    resolve(flag, std::env::var_os(BASE_DIR_ENV))
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")))
+}
+
+/// Output dir for `base_dir`: `ACORNOS_OUTPUT_DIR` if set, else the
+/// distro output dir.
+pub fn output_dir(base_dir: &Path) -> PathBuf {
+    resolve(None, std::env::var_os(OUTPUT_DIR_ENV))
+        .unwrap_or_else(|| distro_builder::artifact_store::central_output_dir_for_distro(base_dir))
+}
+
+/// Artifact locations in an output dir.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputPaths {
+    pub dir: PathBuf,
+}
+
+impl OutputPaths {
+    pub fn new(output_dir: &Path) -> Self {
+        Self {
+            dir: output_dir.to_path_buf(),
+        }
+    }
+
+    /// Paths in the output dir of `base_dir`.
+    pub fn for_base(base_dir: &Path) -> Self {
+        Self::new(&output_dir(base_dir))
+    }
+
+    /// Kernel build tree: the kernel image and its modules.
+    pub fn kernel_staging(&self) -> PathBuf {
+        self.dir.join("staging")
+    }
+
+    pub fn kernel(&self) -> PathBuf {
+        self.kernel_staging().join("boot").join(KERNEL_FILENAME)
+    }
+
+    /// `lib/modules` of the kernel build, one directory per kernel version.
+    pub fn kernel_modules(&self) -> PathBuf {
+        self.kernel_staging().join("usr/lib/modules")
+    }
+
+    /// Tree the rootfs image is made from.
+    pub fn rootfs_staging(&self) -> PathBuf {
+        self.dir.join("rootfs-staging")
+    }
+
+    pub fn rootfs_image(&self, format: RootfsFormat) -> PathBuf {
+        self.dir.join(format.image_name())
+    }
+
+    pub fn live_initramfs(&self, compression: InitramfsCompression) -> PathBuf {
+        self.dir.join(compression.output_name())
+    }
+
+    pub fn installed_initramfs(&self) -> PathBuf {
+        self.dir.join(INITRAMFS_INSTALLED_OUTPUT)
+    }
+
+    /// Files layered over the rootfs in the live session.
+    pub fn live_overlay(&self) -> PathBuf {
+        self.dir.join("live-overlay")
+    }
+
+    pub fn iso(&self) -> PathBuf {
+        self.dir.join(ISO_FILENAME)
+    }
+
+    /// ISO of `variant` (`iso()` for the live one).
+    pub fn iso_variant(&self, variant: IsoVariant) -> PathBuf {
+        self.dir.join(variant.iso_filename())
+    }
+
+    /// Live initramfs plus the network installer (see `netinstall`).
+    pub fn netinstall_initramfs(&self) -> PathBuf {
+        self.dir.join(INITRAMFS_NETINSTALL_OUTPUT)
+    }
+}
+
+/// The flag, else a non-empty environment value, made absolute so child
+/// processes in another working directory agree.
+fn resolve(flag: Option<PathBuf>, env: Option<OsString>) -> Option<PathBuf> {
+    let path = flag.or_else(|| env.filter(|v| !v.is_empty()).map(PathBuf::from))?;
+    Some(std::path::absolute(&path).unwrap_or(path))
+}
+
+/// Export the directories for recipes and child builds; an output dir is
+/// only exported when overridden.
+///
+/// Must be called before any threads are spawned.
+pub fn export(base_dir: &Path, output_dir: Option<PathBuf>) {
+    std::env::set_var(BASE_DIR_ENV, base_dir);
+    if let Some(dir) = resolve(output_dir, None) {
+        std::env::set_var(OUTPUT_DIR_ENV, dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_prefers_flag_then_env() {
+        let flag = PathBuf::from("/mnt/big/out");
+        assert_eq!(
+            resolve(Some(flag.clone()), Some("/elsewhere".into())),
+            Some(flag)
+        );
+        assert_eq!(
+            resolve(None, Some("/elsewhere".into())),
+            Some(PathBuf::from("/elsewhere"))
+        );
+        assert_eq!(resolve(None, Some(OsString::new())), None);
+        assert_eq!(resolve(None, None), None);
+
+        let relative = resolve(Some(PathBuf::from("out")), None).unwrap();
+        assert!(relative.is_absolute());
+        assert!(relative.ends_with("out"));
+    }
+
+    #[test]
+    fn test_output_layout() {
+        let paths = OutputPaths::new(Path::new("/out"));
+        assert_eq!(paths.kernel_staging(), Path::new("/out/staging"));
+        assert_eq!(
+            paths.kernel_modules(),
+            Path::new("/out/staging/usr/lib/modules")
+        );
+        assert_eq!(paths.rootfs_staging(), Path::new("/out/rootfs-staging"));
+        assert_eq!(
+            paths.rootfs_image(RootfsFormat::Erofs),
+            Path::new("/out").join(RootfsFormat::Erofs.image_name())
+        );
+        assert_eq!(
+            paths.installed_initramfs(),
+            Path::new("/out").join(INITRAMFS_INSTALLED_OUTPUT)
+        );
+        assert_eq!(paths.iso(), Path::new("/out").join(ISO_FILENAME));
+    }
+}
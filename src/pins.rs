@@ -0,0 +1,275 @@
+//! Pinned checksums of the Alpine Extended ISO and static busybox
+//! (`acorn-pins.toml`).
+//!
+//! The `.sha256` next to the ISO is served by the same CDN as the ISO, so
+//! on its own it only catches corruption, and an unpinned "latest" ISO
+//! makes the build unpinned too. With a pin, downloads are verified
+//! against it and the mirror's `.sha256` is only compared as a hint; a
+//! mismatch fails and says how to update the pin.
+//!
+//! The x86_64 ISO of the default release is pinned in the tree
+//! ([`ALPINE_ISO_SHA256`]). Other ISOs (another arch or `--alpine-version`)
+//! are pinned in the project root, keyed by ISO file name:
+//!
+//! ```toml
+//! [alpine-iso]
+//! "alpine-extended-3.22.0-aarch64.iso" = "<sha256>"
+//! ```
+//!
+//! A pin in the file overrides the built-in one. The x86_64 static busybox
+//! from busybox.net has no built-in pin; it is pinned the same way, and an
+//! offline build refuses it unpinned:
+//!
+//! ```toml
+//! [busybox]
+//! "busybox-static" = "<sha256>"
+//! ```
+//!
+//! Only this subset of TOML is accepted, and anything else fails with the
+//! line and key at fault.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::download::ALPINE_ISO_URL;
+use crate::offline::ALPINE_ISO_SHA256;
+use crate::toml_subset::{self, Item, Line};
+
+/// Pins file in the project root.
+pub const PINS_FILE: &str = "acorn-pins.toml";
+
+/// Environment variable holding the ISO pin for the alpine recipe.
+pub const ISO_PIN_ENV: &str = "ACORNOS_ISO_SHA256";
+
+/// Key of the busybox pin (the cached file's name).
+pub const BUSYBOX_KEY: &str = "busybox-static";
+
+/// Where the built-in pin comes from.
+const BUILT_IN: &str = "built-in pin";
+
+/// An expected SHA256 and where it was pinned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pin {
+    pub sha256: String,
+    pub source: String,
+}
+
+/// Parsed `acorn-pins.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pins {
+    /// ISO file name and SHA256, in file order.
+    iso: Vec<(String, String)>,
+    /// SHA256 of the x86_64 static busybox.
+    busybox: Option<String>,
+}
+
+impl Pins {
+    /// Load the pins from `base_dir` (none if the file does not exist).
+    pub fn load(base_dir: &Path) -> Result<Self> {
+        let path = base_dir.join(PINS_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid {}", PINS_FILE))
+    }
+
+    /// Parse the pins; errors name the line and key.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut pins = Self::default();
+        let mut section = None;
+
+        for Line { lineno, item } in toml_subset::parse(text)? {
+            let (key, value) = match item {
+                Item::Table(name) if name == "alpine-iso" || name == "busybox" => {
+                    section = Some(name);
+                    continue;
+                }
+                Item::Table(name) | Item::ArrayTable(name) => bail!(
+                    "line {}: unknown section [{}] (expected [alpine-iso] or [busybox])",
+                    lineno,
+                    name
+                ),
+                Item::Entry { key, value } => (key, value),
+            };
+            let key = key.as_str();
+            let Some(section) = section.as_deref() else {
+                bail!(
+                    "line {}: key `{}` outside of an [alpine-iso] or [busybox] section",
+                    lineno,
+                    key
+                );
+            };
+            if section == "busybox" && key != BUSYBOX_KEY {
+                bail!(
+                    "line {}: key `{}`: expected \"{}\"",
+                    lineno,
+                    key,
+                    BUSYBOX_KEY
+                );
+            }
+            if section == "alpine-iso" && (!key.ends_with(".iso") || key.contains('/')) {
+                bail!(
+                    "line {}: key `{}`: expected an ISO file name like \"{}\"",
+                    lineno,
+                    key,
+                    default_iso_name()
+                );
+            }
+            let sha256 = value
+                .string()
+                .filter(|v| v.len() == 64 && v.chars().all(|c| c.is_ascii_hexdigit()))
+                .with_context(|| {
+                    format!(
+                        "line {}: key `{}`: expected a quoted 64-character SHA256",
+                        lineno, key
+                    )
+                })?;
+            let twice = match section {
+                "busybox" => pins.busybox.replace(sha256.to_lowercase()).is_some(),
+                _ => {
+                    let twice = pins.iso.iter().any(|(name, _)| name == key);
+                    pins.iso.push((key.to_string(), sha256.to_lowercase()));
+                    twice
+                }
+            };
+            if twice {
+                bail!("line {}: key `{}`: pinned twice", lineno, key);
+            }
+        }
+        Ok(pins)
+    }
+
+    /// The pin for the ISO named `name`: from the file, else built in.
+    pub fn iso(&self, name: &str) -> Option<Pin> {
+        if let Some((_, sha256)) = self.iso.iter().find(|(n, _)| n == name) {
+            return Some(Pin {
+                sha256: sha256.clone(),
+                source: PINS_FILE.to_string(),
+            });
+        }
+        (name == default_iso_name()).then(|| Pin {
+            sha256: ALPINE_ISO_SHA256.to_string(),
+            source: BUILT_IN.to_string(),
+        })
+    }
+
+    /// The pin for the x86_64 static busybox, if any.
+    pub fn busybox(&self) -> Option<Pin> {
+        self.busybox.as_ref().map(|sha256| Pin {
+            sha256: sha256.clone(),
+            source: PINS_FILE.to_string(),
+        })
+    }
+}
+
+/// File name of the ISO with the built-in pin.
+fn default_iso_name() -> &'static str {
+    ALPINE_ISO_URL.rsplit('/').next().unwrap_or(ALPINE_ISO_URL)
+}
+
+/// Error for `name` not matching its pin, with how to update the pin on
+/// purpose.
+pub fn mismatch_error(name: &str, pin: &Pin, actual: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Checksum mismatch for {}: expected {} ({}), got {}.\n\
+         Do not update the pin unless the ISO changed on purpose (check the \
+         release announcement and signature, see `acornos download --verify-gpg`).\n\
+         To pin the new ISO, add to {} in the project root:\n\
+         \n  [alpine-iso]\n  \"{}\" = \"{}\"",
+        name,
+        pin.sha256,
+        pin.source,
+        actual,
+        PINS_FILE,
+        name,
+        actual
+    )
+}
+
+/// Export the ISO pin so the alpine recipe verifies against it.
+///
+/// Must be called before any threads are spawned.
+pub fn export(sha256: Option<&str>) {
+    match sha256 {
+        Some(sha256) => std::env::set_var(ISO_PIN_ENV, sha256),
+        None => std::env::remove_var(ISO_PIN_ENV),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_pin_overrides_built_in() {
+        let pins = Pins::parse(&format!(
+            "# pinned on purpose\n[alpine-iso]\n\"{}\" = \"{}\"\n\
+             \"alpine-extended-3.22.0-aarch64.iso\" = \"{}\"  # arm\n",
+            default_iso_name(),
+            "A".repeat(64),
+            "b".repeat(64)
+        ))
+        .unwrap();
+        assert_eq!(
+            pins.iso(default_iso_name()),
+            Some(Pin {
+                sha256: "a".repeat(64),
+                source: PINS_FILE.to_string()
+            })
+        );
+        assert_eq!(
+            pins.iso("alpine-extended-3.22.0-aarch64.iso")
+                .unwrap()
+                .sha256,
+            "b".repeat(64)
+        );
+        assert_eq!(pins.iso("alpine-extended-3.22.0-x86_64.iso"), None);
+
+        assert_eq!(pins.busybox(), None);
+        let busybox = Pins::parse(&format!(
+            "[busybox]\n\"busybox-static\" = \"{}\"\n",
+            "C".repeat(64)
+        ))
+        .unwrap()
+        .busybox()
+        .unwrap();
+        assert_eq!(busybox.sha256, "c".repeat(64));
+
+        let built_in = Pins::default().iso(default_iso_name()).unwrap();
+        assert_eq!(built_in.sha256, ALPINE_ISO_SHA256);
+        assert_eq!(built_in.source, BUILT_IN);
+    }
+
+    #[test]
+    fn test_parse_errors_name_line_and_key() {
+        let err = |text: &str| format!("{:#}", Pins::parse(text).unwrap_err());
+        assert!(err("\"a.iso\" = \"x\"").contains("line 1: key `a.iso` outside"));
+        assert!(err("[alpine-iso]\n\"a.iso\" = \"abc\"")
+            .contains("line 2: key `a.iso`: expected a quoted"));
+        assert!(err("[alpine-iso]\nsha256 = \"abc\"").contains("expected an ISO file name"));
+        assert!(err("[packages]").contains("unknown section [packages]"));
+        assert!(err("[busybox]\nbusybox = \"abc\"").contains("line 2: key `busybox`: expected"));
+        let sha = "0".repeat(64);
+        assert!(err(&format!(
+            "[alpine-iso]\na.iso = \"{0}\"\na.iso = \"{0}\"",
+            sha
+        ))
+        .contains("line 3: key `a.iso`: pinned twice"));
+    }
+
+    #[test]
+    fn test_mismatch_error_shows_the_new_pin() {
+        let pin = Pins::default().iso(default_iso_name()).unwrap();
+        let err = mismatch_error(default_iso_name(), &pin, &"f".repeat(64)).to_string();
+        assert!(err.contains("built-in pin"));
+        assert!(err.contains(&format!(
+            "\"{}\" = \"{}\"",
+            default_iso_name(),
+            "f".repeat(64)
+        )));
+        assert!(err.contains(PINS_FILE));
+    }
+}
@@ -0,0 +1,872 @@
+//! Build orchestration as a library API.
+//!
+//! Every cached artifact goes through the same sequence: restore it from
+//! the artifact store (unless forced), check whether its inputs changed,
+//! build it, record the input hash, and store the result. [`BuildPipeline`]
+//! runs that sequence for the rootfs, the initramfs images, the netboot set
+//! and the ISO, so the CLI and other tools drive builds the same way:
+//!
+//! ```rust,ignore
+//! use acornos::pipeline::BuildPipeline;
+//!
+//! let mut pipeline = BuildPipeline::new(&base_dir);
+//! pipeline.force = true;
+//! let iso = pipeline.build_iso()?;
+//! log::info!("{} (rebuilt: {})", iso.artifact.display(), iso.rebuilt);
+//! ```
+//!
+//! The store is reached through [`ArtifactCache`] so the decisions can be
+//! tested without one. Progress of the long image steps (mkfs.erofs,
+//! mksquashfs, xorriso) can be followed with
+//! [`BuildPipeline::subscribe_progress`].
+
+use anyhow::{bail, Result};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use distro_builder::artifact_store::{
+    try_restore_file_from_key, try_restore_kernel_payload_from_key, try_store_file_from_key,
+    ArtifactStore,
+};
+
+use crate::artifact::netboot::NETBOOT_DIR;
+use crate::artifact::{
+    InitramfsCompression, IsoLabel, IsoOptions, IsoVariant, NetbootOptions, RootfsFormat,
+};
+use crate::parallel::Job;
+use crate::paths::OutputPaths;
+use crate::progress::ProgressSink;
+use crate::store_meta;
+use crate::{artifact, rebuild, timing};
+
+/// Artifact store operations the pipeline uses.
+pub trait ArtifactCache {
+    /// Restore `out` for the input hash in `key`; `Ok(false)` on a miss.
+    fn restore(&self, kind: &str, key: &Path, out: &Path) -> Result<bool>;
+    /// Store `out` under the input hash in `key`, with `meta` alongside.
+    fn store(
+        &self,
+        kind: &str,
+        key: &Path,
+        out: &Path,
+        meta: BTreeMap<String, String>,
+    ) -> Result<()>;
+    /// Restore the kernel payload into the staging tree.
+    fn restore_kernel(&self, key: &Path, staging: &Path) -> Result<bool>;
+}
+
+impl ArtifactCache for ArtifactStore {
+    fn restore(&self, kind: &str, key: &Path, out: &Path) -> Result<bool> {
+        try_restore_file_from_key(self, kind, key, out)
+    }
+
+    fn store(
+        &self,
+        kind: &str,
+        key: &Path,
+        out: &Path,
+        meta: BTreeMap<String, String>,
+    ) -> Result<()> {
+        try_store_file_from_key(self, kind, key, out, meta)
+    }
+
+    fn restore_kernel(&self, key: &Path, staging: &Path) -> Result<bool> {
+        try_restore_kernel_payload_from_key(self, key, staging)
+    }
+}
+
+/// The distro's artifact store, or `None` (with a warning) if it cannot be
+/// opened.
+pub fn open_artifact_store(base_dir: &Path) -> Option<ArtifactStore> {
+    match ArtifactStore::open_for_distro(base_dir) {
+        Ok(s) => Some(s),
+        Err(e) => {
+            log::warn!("Artifact store disabled: {:#}", e);
+            None
+        }
+    }
+}
+
+/// What happened to one artifact.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildOutcome {
+    pub artifact: PathBuf,
+    /// Built in this run (or by a child build started by it).
+    pub rebuilt: bool,
+    /// Restored from the artifact store and used as is.
+    pub restored_from_store: bool,
+    pub duration: Duration,
+}
+
+/// Outcomes of [`BuildPipeline::build_all`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FullBuild {
+    pub rootfs: BuildOutcome,
+    pub initramfs: BuildOutcome,
+    pub installed_initramfs: BuildOutcome,
+    pub iso: BuildOutcome,
+}
+
+/// When an artifact is (re)built.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// Whenever its inputs changed.
+    InputsChanged,
+    /// Only when the output is missing (inputs of `acornos iso`).
+    Missing,
+}
+
+/// One artifact: where it goes, how to check it, and how to build it.
+pub trait Step {
+    /// Name in messages and timings.
+    fn name(&self) -> String;
+    /// Artifact store kind, or `None` for artifacts that are never stored.
+    fn kind(&self) -> Option<&str>;
+    fn output(&self) -> PathBuf;
+    /// Input hash file used as the store key.
+    fn key(&self) -> PathBuf;
+    fn needs_rebuild(&self) -> bool;
+    /// Build the artifact and record its input hash.
+    fn build(&self) -> Result<()>;
+    /// Called after a successful restore.
+    fn restored(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Restore `step` from the store if allowed; whether it was restored.
+fn restore_step(
+    store: Option<&dyn ArtifactCache>,
+    step: &dyn Step,
+    force: bool,
+    policy: Policy,
+) -> Result<bool> {
+    let (Some(store), Some(kind)) = (store, step.kind()) else {
+        return Ok(false);
+    };
+    let output = step.output();
+    if force || (policy == Policy::Missing && output.exists()) {
+        return Ok(false);
+    }
+    let key = step.key();
+    match store_meta::restore_checked(store, kind, &key, &output) {
+        Ok(true) => {
+            step.restored()?;
+            log::info!("[RESTORE] {} restored from artifact store", step.name());
+            Ok(true)
+        }
+        Ok(false) => Ok(false),
+        Err(e) => {
+            log::warn!(
+                "Failed to restore {} from artifact store: {:#}",
+                step.name(),
+                e
+            );
+            Ok(false)
+        }
+    }
+}
+
+/// Whether `step` has to be built now.
+fn step_needed(step: &dyn Step, force: bool, policy: Policy) -> bool {
+    force
+        || match policy {
+            Policy::InputsChanged => step.needs_rebuild(),
+            Policy::Missing => !step.output().exists(),
+        }
+}
+
+/// Store a freshly built artifact with its checksum; failures only warn.
+fn store_step(store: Option<&dyn ArtifactCache>, step: &dyn Step) {
+    if let (Some(store), Some(kind)) = (store, step.kind()) {
+        let (key, output) = (step.key(), step.output());
+        if let Err(e) = store_meta::store_checked(store, kind, &key, &output, BTreeMap::new()) {
+            log::warn!("Failed to store {} in artifact store: {:#}", step.name(), e);
+        }
+    }
+}
+
+/// Restore, check, build and store one artifact.
+pub fn run_step(
+    store: Option<&dyn ArtifactCache>,
+    step: &dyn Step,
+    force: bool,
+    policy: Policy,
+) -> Result<BuildOutcome> {
+    let started = Instant::now();
+    let restored = restore_step(store, step, force, policy)?;
+    let rebuilt = step_needed(step, force, policy);
+    if rebuilt {
+        step.build()?;
+        store_step(store, step);
+    } else if policy == Policy::InputsChanged {
+        log::info!("[SKIP] {} already built (inputs unchanged)", step.name());
+    }
+    Ok(BuildOutcome {
+        artifact: step.output(),
+        rebuilt,
+        restored_from_store: restored && !rebuilt,
+        duration: started.elapsed(),
+    })
+}
+
+/// The artifacts this crate builds.
+enum Target<'a> {
+    Rootfs(&'a BuildPipeline, RootfsFormat),
+    Initramfs(&'a BuildPipeline, InitramfsCompression),
+    InstalledInitramfs(&'a BuildPipeline, bool),
+    Netboot(&'a BuildPipeline, &'a NetbootOptions),
+    Iso(&'a BuildPipeline),
+}
+
+impl Step for Target<'_> {
+    fn name(&self) -> String {
+        match self {
+            Self::Rootfs(_, format) => format!("{} rootfs", format),
+            Self::Initramfs(..) => "Initramfs".to_string(),
+            Self::InstalledInitramfs(..) => "Installed initramfs".to_string(),
+            Self::Netboot(..) => "Netboot artifacts".to_string(),
+            Self::Iso(p) => match p.iso.variant {
+                IsoVariant::Live => "ISO".to_string(),
+                IsoVariant::Netinstall => "Netinstall ISO".to_string(),
+            },
+        }
+    }
+
+    fn kind(&self) -> Option<&str> {
+        match self {
+            Self::Rootfs(_, format) => Some(format.artifact_kind()),
+            Self::Initramfs(_, compression) => Some(compression.artifact_kind()),
+            _ => None,
+        }
+    }
+
+    fn output(&self) -> PathBuf {
+        match self {
+            Self::Rootfs(p, format) => p.paths().rootfs_image(*format),
+            Self::Initramfs(p, compression) => p.paths().live_initramfs(*compression),
+            Self::InstalledInitramfs(p, _) => p.paths().installed_initramfs(),
+            Self::Netboot(p, _) => p.output_dir.join(NETBOOT_DIR),
+            Self::Iso(p) => p.paths().iso_variant(p.iso.variant),
+        }
+    }
+
+    fn key(&self) -> PathBuf {
+        let (p, name) = match self {
+            Self::Rootfs(p, _) => (p, ".rootfs-inputs.hash"),
+            Self::Initramfs(p, _) => (p, ".initramfs-inputs.hash"),
+            Self::InstalledInitramfs(p, _) => (p, ".initramfs-installed-inputs.hash"),
+            Self::Netboot(p, _) => (p, ".netboot-inputs.hash"),
+            Self::Iso(p) => (p, rebuild::iso_inputs_hash(p.iso.variant)),
+        };
+        p.output_dir.join(name)
+    }
+
+    fn needs_rebuild(&self) -> bool {
+        match self {
+            Self::Rootfs(p, format) => rebuild::rootfs_needs_rebuild(&p.base_dir, *format),
+            Self::Initramfs(p, compression) => {
+                rebuild::initramfs_needs_rebuild(&p.base_dir, *compression, &p.iso.label)
+            }
+            Self::InstalledInitramfs(p, luks) => {
+                rebuild::full_initramfs_needs_rebuild(&p.base_dir, *luks)
+            }
+            Self::Netboot(p, options) => rebuild::netboot_needs_rebuild(&p.base_dir, options),
+            Self::Iso(p) => rebuild::iso_needs_rebuild_with(&p.base_dir, &p.iso),
+        }
+    }
+
+    fn build(&self) -> Result<()> {
+        match self {
+            Self::Rootfs(p, format) => {
+                let options = artifact::RootfsOptions {
+                    strict_links: p.strict_links,
+                    skip_verify: p.skip_verify,
+                    clean_staging: p.clean_staging,
+                    resume: p.resume,
+                    keep_going: p.keep_going,
+                    jobs: p.jobs,
+                };
+                artifact::build_rootfs_image(&p.base_dir, *format, &options)?;
+                rebuild::cache_rootfs_hash(&p.base_dir, *format);
+            }
+            Self::Initramfs(p, compression) => {
+                artifact::build_tiny_initramfs(&p.base_dir, *compression, &p.iso.label)?;
+                rebuild::cache_initramfs_hash(&p.base_dir, *compression, &p.iso.label);
+            }
+            Self::InstalledInitramfs(p, luks) => {
+                artifact::build_full_initramfs(&p.base_dir, *luks)?;
+                rebuild::cache_full_initramfs_hash(&p.base_dir, *luks);
+            }
+            Self::Netboot(p, options) => {
+                artifact::build_netboot(&p.base_dir, options)?;
+                rebuild::cache_netboot_hash(&p.base_dir, options);
+            }
+            Self::Iso(p) => {
+                artifact::create_iso_with(&p.base_dir, &p.iso)?;
+                rebuild::cache_iso_hash(&p.base_dir, &p.iso);
+            }
+        }
+        Ok(())
+    }
+
+    fn restored(&self) -> Result<()> {
+        match self {
+            Self::Rootfs(p, format) => format.save(&p.output_dir)?,
+            Self::Initramfs(p, compression) => {
+                compression.save(&p.output_dir)?;
+                p.iso.label.save(&p.output_dir)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Builds AcornOS artifacts under a base dir.
+pub struct BuildPipeline {
+    base_dir: PathBuf,
+    output_dir: PathBuf,
+    store: Option<Box<dyn ArtifactCache>>,
+    /// Rootfs image format.
+    pub format: RootfsFormat,
+    /// Live initramfs compressor and level.
+    pub initramfs_compression: InitramfsCompression,
+    pub iso: IsoOptions,
+    /// Fail on any dangling symlink in the staging tree.
+    pub strict_links: bool,
+    /// Skip the post-build rootfs image check.
+    pub skip_verify: bool,
+    /// Rebuild even if inputs are unchanged; never restore from the store.
+    pub force: bool,
+    /// Wipe the rootfs staging tree instead of reusing it.
+    pub clean_staging: bool,
+    /// Continue the staging tree of a rootfs build that failed.
+    pub resume: bool,
+    /// Build despite sources missing from the rootfs, failing per component.
+    pub keep_going: bool,
+    /// Compression threads of the rootfs image tool (default: physical cores).
+    pub jobs: Option<usize>,
+    /// Build the rootfs and initramfs concurrently in [`BuildPipeline::build_all`],
+    /// as child `build rootfs` / `initramfs` runs of this `acornos` binary.
+    pub parallel_exe: Option<PathBuf>,
+}
+
+impl BuildPipeline {
+    /// Pipeline for `base_dir` using the distro's artifact store.
+    pub fn new(base_dir: &Path) -> Self {
+        let store = open_artifact_store(base_dir).map(|s| Box::new(s) as Box<dyn ArtifactCache>);
+        Self::with_store(base_dir, store)
+    }
+
+    /// Pipeline for `base_dir` with the given store (or none).
+    pub fn with_store(base_dir: &Path, store: Option<Box<dyn ArtifactCache>>) -> Self {
+        Self {
+            base_dir: base_dir.to_path_buf(),
+            output_dir: crate::paths::output_dir(base_dir),
+            store,
+            format: RootfsFormat::default(),
+            initramfs_compression: InitramfsCompression::default(),
+            iso: IsoOptions::default(),
+            strict_links: false,
+            skip_verify: false,
+            force: false,
+            clean_staging: false,
+            resume: false,
+            keep_going: false,
+            jobs: None,
+            parallel_exe: None,
+        }
+    }
+
+    pub fn base_dir(&self) -> &Path {
+        &self.base_dir
+    }
+
+    pub fn output_dir(&self) -> &Path {
+        &self.output_dir
+    }
+
+    /// Artifact locations in the output dir.
+    pub fn paths(&self) -> OutputPaths {
+        OutputPaths::new(&self.output_dir)
+    }
+
+    /// Send the progress of the image tools to `sink` instead of the
+    /// console (process-wide, like the timing collector).
+    pub fn subscribe_progress(&self, sink: Box<dyn ProgressSink>) {
+        crate::progress::subscribe(sink);
+    }
+
+    fn store(&self) -> Option<&dyn ArtifactCache> {
+        self.store.as_deref()
+    }
+
+    /// Make sure the kernel is installed in staging, restoring it from the
+    /// artifact store if needed.
+    ///
+    /// Kernel compilation is centralized in `cargo xtask kernels build acorn`
+    /// (nightly policy); this builder never compiles kernels implicitly.
+    pub fn resolve_kernel(&self) -> Result<()> {
+        let paths = self.paths();
+        let staging = paths.kernel_staging();
+        if paths.kernel().exists() {
+            log::info!("[SKIP] Kernel already built and installed");
+            return Ok(());
+        }
+
+        if let Some(store) = self.store() {
+            let key = self.output_dir.join(".kernel-inputs.hash");
+            match store.restore_kernel(&key, &staging) {
+                Ok(true) => {
+                    log::info!("[RESTORE] Kernel payload restored from artifact store");
+                    return Ok(());
+                }
+                Ok(false) => {}
+                Err(e) => log::warn!(
+                    "Failed to restore kernel payload from artifact store: {:#}",
+                    e
+                ),
+            }
+        }
+
+        bail!(
+            "No kernel available.\n\n\
+             Kernel compilation is centralized in xtask (nightly build-hours policy).\n\
+             Build the kernels first, then re-run this command:\n\
+               cargo xtask kernels build acorn   (or: acornos kernel build)\n\
+             'acornos build --yes' builds a missing kernel without asking."
+        )
+    }
+
+    /// Build the rootfs image if its inputs changed.
+    pub fn build_rootfs(&self) -> Result<BuildOutcome> {
+        let step = Target::Rootfs(self, self.format);
+        run_step(self.store(), &step, self.force, Policy::InputsChanged)
+    }
+
+    /// Build the live initramfs if its inputs (or the compression) changed,
+    /// and make it the one the ISO uses.
+    pub fn build_initramfs(&self) -> Result<BuildOutcome> {
+        let step = Target::Initramfs(self, self.initramfs_compression);
+        let outcome = run_step(self.store(), &step, self.force, Policy::InputsChanged)?;
+        self.initramfs_compression.save(&self.output_dir)?;
+        self.iso.label.save(&self.output_dir)?;
+        Ok(outcome)
+    }
+
+    /// Build the installed-system initramfs if its inputs changed.
+    pub fn build_installed_initramfs(&self, luks: bool) -> Result<BuildOutcome> {
+        let step = Target::InstalledInitramfs(self, luks);
+        run_step(self.store(), &step, self.force, Policy::InputsChanged)
+    }
+
+    /// Build the netboot set (and the rootfs it packages, never forced).
+    pub fn build_netboot(&self, options: &NetbootOptions) -> Result<BuildOutcome> {
+        self.resolve_kernel()?;
+        run_step(
+            self.store(),
+            &Target::Rootfs(self, self.format),
+            false,
+            Policy::InputsChanged,
+        )?;
+        let step = Target::Netboot(self, options);
+        run_step(self.store(), &step, self.force, Policy::InputsChanged)
+    }
+
+    /// Build the ISO, first restoring or building a missing rootfs (in the
+    /// last built format; not for the netinstall ISO) or initramfs (with
+    /// the last used compression). An initramfs built for another label is
+    /// rebuilt for this one.
+    pub fn build_iso(&self) -> Result<BuildOutcome> {
+        let format = RootfsFormat::load(&self.output_dir);
+        let compression = InitramfsCompression::load(&self.output_dir);
+        if self.iso.variant == IsoVariant::Live {
+            run_step(
+                self.store(),
+                &Target::Rootfs(self, format),
+                self.force,
+                Policy::Missing,
+            )?;
+        }
+        let initramfs_policy = if IsoLabel::load(&self.output_dir) == self.iso.label {
+            Policy::Missing
+        } else {
+            Policy::InputsChanged
+        };
+        run_step(
+            self.store(),
+            &Target::Initramfs(self, compression),
+            self.force,
+            initramfs_policy,
+        )?;
+        run_step(None, &Target::Iso(self), self.force, Policy::InputsChanged)
+    }
+
+    /// Full build: kernel check, rootfs and initramfs, installed initramfs,
+    /// ISO. Phases are recorded in [`timing::collector`].
+    pub fn build_all(&self) -> Result<FullBuild> {
+        self.resolve_kernel()?;
+
+        let (rootfs, initramfs) = match &self.parallel_exe {
+            Some(exe) => self.build_images_parallel(exe)?,
+            None => (self.build_rootfs()?, self.build_initramfs()?),
+        };
+
+        let timed = |step: Target| -> Result<BuildOutcome> {
+            let outcome = run_step(self.store(), &step, self.force, Policy::InputsChanged)?;
+            let name = step.name();
+            if outcome.rebuilt {
+                timing::record_since(&name, Instant::now() - outcome.duration);
+            } else {
+                timing::record_skipped(&name);
+            }
+            Ok(outcome)
+        };
+        let installed_initramfs = timed(Target::InstalledInitramfs(self, false))?;
+        let iso = timed(Target::Iso(self))?;
+
+        Ok(FullBuild {
+            rootfs,
+            initramfs,
+            installed_initramfs,
+            iso,
+        })
+    }
+
+    /// Restore the rootfs and initramfs, then build whichever is still
+    /// needed as concurrent child runs of `exe` (their output prefixed).
+    fn build_images_parallel(&self, exe: &Path) -> Result<(BuildOutcome, BuildOutcome)> {
+        let started = Instant::now();
+        let rootfs = Target::Rootfs(self, self.format);
+        let initramfs = Target::Initramfs(self, self.initramfs_compression);
+        let rootfs_restored =
+            restore_step(self.store(), &rootfs, self.force, Policy::InputsChanged)?;
+        let initramfs_restored =
+            restore_step(self.store(), &initramfs, self.force, Policy::InputsChanged)?;
+
+        let rootfs_needed = step_needed(&rootfs, self.force, Policy::InputsChanged);
+        if rootfs_needed {
+            // The initramfs template is rendered for this format; record it
+            // first so the concurrent initramfs build does not see the old one
+            self.format.save(&self.output_dir)?;
+        }
+        let initramfs_needed = step_needed(&initramfs, self.force, Policy::InputsChanged);
+
+        let timings_part = self.output_dir.join(timing::TIMINGS_PART_FILENAME);
+        let mut jobs = Vec::new();
+        if rootfs_needed {
+            let mut cmd = Command::new(exe);
+            cmd.args(["build", "--rootfs-format", self.format.fs_type()]);
+            for (set, flag) in [
+                (self.strict_links, "--strict-links"),
+                (self.skip_verify, "--skip-verify"),
+                (self.force, "--force"),
+                (self.clean_staging, "--clean-staging"),
+                (self.resume, "--resume"),
+                (self.keep_going, "--keep-going"),
+            ] {
+                if set {
+                    cmd.arg(flag);
+                }
+            }
+            if let Some(jobs) = self.jobs {
+                cmd.arg("--jobs").arg(jobs.to_string());
+            }
+            cmd.arg("rootfs");
+            cmd.env(timing::TIMINGS_ENV, &timings_part);
+            jobs.push(Job::new("rootfs", cmd));
+        } else {
+            log::info!(
+                "\n[SKIP] {} already built (inputs unchanged)",
+                rootfs.name()
+            );
+            timing::record_skipped("rootfs");
+        }
+        if initramfs_needed {
+            let compression = self.initramfs_compression;
+            let mut cmd = Command::new(exe);
+            cmd.args(["initramfs", "--initramfs-compression"])
+                .arg(compression.compressor.name())
+                .arg("--initramfs-compression-level")
+                .arg(compression.level.to_string())
+                .arg("--label")
+                .arg(self.iso.label.as_str());
+            if self.force {
+                cmd.arg("--force");
+            }
+            cmd.env(timing::TIMINGS_ENV, &timings_part);
+            jobs.push(Job::new("initramfs", cmd));
+        } else {
+            log::info!("\n[SKIP] Initramfs already built (inputs unchanged)");
+            timing::record_skipped("initramfs");
+        }
+        if !jobs.is_empty() {
+            let labels: Vec<&str> = jobs.iter().map(|j| j.label.as_str()).collect();
+            log::info!("\nBuilding {} in parallel...", labels.join(" + "));
+            crate::parallel::run_all(jobs)?;
+        }
+        self.initramfs_compression.save(&self.output_dir)?;
+        self.iso.label.save(&self.output_dir)?;
+
+        let outcome = |step: &Target, rebuilt: bool, restored: bool| BuildOutcome {
+            artifact: step.output(),
+            rebuilt,
+            restored_from_store: restored && !rebuilt,
+            duration: started.elapsed(),
+        };
+        Ok((
+            outcome(&rootfs, rootfs_needed, rootfs_restored),
+            outcome(&initramfs, initramfs_needed, initramfs_restored),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+    use std::fs;
+    use tempfile::tempdir;
+
+    /// Store holding one entry per kind (or failing), recording every call.
+    #[derive(Default)]
+    struct MockStore {
+        entries: RefCell<BTreeMap<String, String>>,
+        broken: bool,
+        calls: RefCell<Vec<String>>,
+    }
+
+    impl MockStore {
+        /// Holding `content` as a checksummed `mock` entry.
+        fn holding(dir: &Path, content: &str) -> Self {
+            let store = Self::default();
+            let (key, out) = (dir.join("seed.hash"), dir.join("seed.img"));
+            fs::write(&key, "k1").unwrap();
+            fs::write(&out, content).unwrap();
+            store_meta::store_checked(&store, "mock", &key, &out, BTreeMap::new()).unwrap();
+            store.calls.borrow_mut().clear();
+            store
+        }
+    }
+
+    impl ArtifactCache for MockStore {
+        fn restore(&self, kind: &str, _key: &Path, out: &Path) -> Result<bool> {
+            self.calls.borrow_mut().push(format!("restore {}", kind));
+            if self.broken {
+                bail!("store offline");
+            }
+            match self.entries.borrow().get(kind) {
+                Some(content) => fs::write(out, content)?,
+                None => return Ok(false),
+            }
+            Ok(true)
+        }
+
+        fn store(
+            &self,
+            kind: &str,
+            _key: &Path,
+            out: &Path,
+            _meta: BTreeMap<String, String>,
+        ) -> Result<()> {
+            self.calls.borrow_mut().push(format!("store {}", kind));
+            if self.broken {
+                bail!("store offline");
+            }
+            self.entries
+                .borrow_mut()
+                .insert(kind.to_string(), fs::read_to_string(out)?);
+            Ok(())
+        }
+
+        fn restore_kernel(&self, _key: &Path, _staging: &Path) -> Result<bool> {
+            Ok(false)
+        }
+    }
+
+    /// Artifact whose inputs changed unless its output matches `current`.
+    struct MockStep {
+        output: PathBuf,
+        current: &'static str,
+        builds: Cell<usize>,
+    }
+
+    impl Step for MockStep {
+        fn name(&self) -> String {
+            "Mock".to_string()
+        }
+        fn kind(&self) -> Option<&str> {
+            Some("mock")
+        }
+        fn output(&self) -> PathBuf {
+            self.output.clone()
+        }
+        fn key(&self) -> PathBuf {
+            self.output.with_extension("hash")
+        }
+        fn needs_rebuild(&self) -> bool {
+            fs::read_to_string(&self.output).map_or(true, |s| s != self.current)
+        }
+        fn build(&self) -> Result<()> {
+            self.builds.set(self.builds.get() + 1);
+            fs::write(&self.output, self.current)?;
+            Ok(())
+        }
+    }
+
+    fn step(dir: &Path, current: &'static str) -> MockStep {
+        MockStep {
+            output: dir.join("artifact.img"),
+            current,
+            builds: Cell::new(0),
+        }
+    }
+
+    #[test]
+    fn test_restore_skip_rebuild_matrix() {
+        let dir = tempdir().unwrap();
+        let run = |store: &MockStore, step: &MockStep, force: bool, policy: Policy| {
+            let outcome = run_step(Some(store), step, force, policy).unwrap();
+            (outcome.rebuilt, outcome.restored_from_store)
+        };
+
+        // Store hit with matching inputs: restored and used
+        let hit = MockStore::holding(dir.path(), "restored");
+        let restored = step(dir.path(), "restored");
+        fs::write(restored.key(), "k1").unwrap();
+        assert_eq!(
+            run(&hit, &restored, false, Policy::InputsChanged),
+            (false, true)
+        );
+        assert_eq!(restored.builds.get(), 0);
+
+        // Restored but stale: rebuilt and stored
+        let stale = step(dir.path(), "v2");
+        assert_eq!(
+            run(&hit, &stale, false, Policy::InputsChanged),
+            (true, false)
+        );
+        assert_eq!(stale.builds.get(), 1);
+        assert_eq!(
+            *hit.calls.borrow(),
+            [
+                "restore mock_checksum",
+                "restore mock",
+                "restore mock_checksum",
+                "restore mock",
+                "store mock",
+                "store mock_checksum",
+            ]
+        );
+
+        // Up to date, store miss: skipped
+        let miss = MockStore::default();
+        assert_eq!(
+            run(&miss, &stale, false, Policy::InputsChanged),
+            (false, false)
+        );
+        assert_eq!(stale.builds.get(), 1);
+
+        // Forced: no restore attempt, always rebuilt
+        let forced = MockStore::holding(dir.path(), "restored");
+        assert_eq!(
+            run(&forced, &stale, true, Policy::InputsChanged),
+            (true, false)
+        );
+        assert_eq!(
+            *forced.calls.borrow(),
+            ["store mock", "store mock_checksum"]
+        );
+        assert_eq!(fs::read_to_string(&stale.output).unwrap(), "v2");
+
+        // A broken store only warns
+        let broken = MockStore {
+            broken: true,
+            ..Default::default()
+        };
+        let fresh = step(dir.path(), "v3");
+        assert_eq!(
+            run(&broken, &fresh, false, Policy::InputsChanged),
+            (true, false)
+        );
+        assert_eq!(fresh.builds.get(), 1);
+    }
+
+    #[test]
+    fn test_missing_policy_only_fills_gaps() {
+        let dir = tempdir().unwrap();
+        let store = MockStore::default();
+        let step = step(dir.path(), "v1");
+        fs::write(step.key(), "k1").unwrap();
+        fs::write(&step.output, "old").unwrap();
+
+        // Present, even if stale: neither restored nor rebuilt
+        let outcome = run_step(Some(&store), &step, false, Policy::Missing).unwrap();
+        assert!(!outcome.rebuilt && !outcome.restored_from_store);
+        assert!(store.calls.borrow().is_empty());
+
+        fs::remove_file(&step.output).unwrap();
+        let outcome = run_step(Some(&store), &step, false, Policy::Missing).unwrap();
+        assert!(outcome.rebuilt);
+        assert_eq!(
+            *store.calls.borrow(),
+            ["restore mock_checksum", "store mock", "store mock_checksum"]
+        );
+        assert_eq!(outcome.artifact, step.output);
+    }
+
+    #[test]
+    fn test_corrupt_restore_is_rebuilt() {
+        let dir = tempdir().unwrap();
+        let store = MockStore::holding(dir.path(), "restored, but longer");
+        store
+            .entries
+            .borrow_mut()
+            .insert("mock".to_string(), "restored".to_string());
+        let step = step(dir.path(), "restored");
+        fs::write(step.key(), "k1").unwrap();
+
+        // The store hands back something else: rebuilt, and the new
+        // checksum stored with it
+        let outcome = run_step(Some(&store), &step, false, Policy::InputsChanged).unwrap();
+        assert!(outcome.rebuilt && !outcome.restored_from_store);
+        assert_eq!(step.builds.get(), 1);
+        let checksum = store.entries.borrow()["mock_checksum"].clone();
+        assert!(checksum.contains(&format!("size={}\n", "restored".len())));
+
+        // Now it matches
+        fs::remove_file(&step.output).unwrap();
+        let outcome = run_step(Some(&store), &step, false, Policy::InputsChanged).unwrap();
+        assert!(outcome.restored_from_store);
+        assert_eq!(step.builds.get(), 1);
+
+        // An entry stored without its checksum is a miss: rebuilt
+        store.entries.borrow_mut().remove("mock_checksum");
+        fs::remove_file(&step.output).unwrap();
+        let outcome = run_step(Some(&store), &step, false, Policy::InputsChanged).unwrap();
+        assert!(outcome.rebuilt && !outcome.restored_from_store);
+        assert_eq!(step.builds.get(), 2);
+    }
+
+    #[test]
+    fn test_pipeline_paths_and_kernel() {
+        let dir = tempdir().unwrap();
+        let store: Box<dyn ArtifactCache> = Box::new(MockStore::default());
+        let pipeline = BuildPipeline::with_store(dir.path(), Some(store));
+        assert_eq!(pipeline.output_dir(), dir.path().join("output"));
+
+        let rootfs = Target::Rootfs(&pipeline, RootfsFormat::Squashfs);
+        assert_eq!(rootfs.kind(), Some("rootfs_squashfs"));
+        assert_eq!(rootfs.key(), dir.path().join("output/.rootfs-inputs.hash"));
+        assert_eq!(Target::Iso(&pipeline).kind(), None);
+
+        let err = pipeline.resolve_kernel().unwrap_err().to_string();
+        assert!(err.contains("cargo xtask kernels build acorn"));
+        fs::create_dir_all(dir.path().join("output/staging/boot")).unwrap();
+        fs::write(dir.path().join("output/staging/boot/vmlinuz"), "kernel").unwrap();
+        pipeline.resolve_kernel().unwrap();
+    }
+}
@@ -1,9 +1,13 @@
 //! Host tool validation for AcornOS build.
 //!
-//! Checks that required external tools are installed and executable.
+//! Checks that required external tools are installed and executable, and
+//! that the image tools are new enough for the features the build uses.
 
 use super::CheckResult;
-use distro_builder::process::{exists, which};
+use crate::arch::Arch;
+use crate::artifact::{Compressor, InitramfsCompression, RootfsFormat};
+use distro_builder::process::{exists, which, Cmd};
+use distro_spec::acorn::EROFS_COMPRESSION;
 
 /// Required host tools with their install suggestions.
 const REQUIRED_TOOLS: &[(&str, &str, &str)] = &[
@@ -31,6 +35,348 @@ pub fn check_host_tools() -> Vec<CheckResult> {
         .collect()
 }
 
+/// Extra tools for building a non-x86_64 ISO (standalone GRUB EFI image).
+const CROSS_ISO_TOOLS: &[(&str, &str, &str)] = &[
+    (
+        "mkfs.fat",
+        "Build EFI boot image",
+        "sudo dnf install dosfstools",
+    ),
+    (
+        "mcopy",
+        "Populate EFI boot image",
+        "sudo dnf install mtools",
+    ),
+];
+
+/// Check the QEMU emulator and UEFI firmware for `arch`, plus the tools
+/// its ISO needs beyond the x86_64 set.
+pub fn check_arch_tools(arch: Arch) -> Vec<CheckResult> {
+    let mut results = vec![check_tool(
+        arch.qemu_binary(),
+        &format!("Run and test the {} ISO", arch),
+        arch.qemu_hint(),
+    )];
+
+    let firmware = format!("{} UEFI firmware", arch);
+    results.push(match arch.find_firmware() {
+        Some(path) => CheckResult::pass(firmware, format!("Found at {}", path.display())),
+        None => CheckResult::fail(
+            firmware,
+            "Not found (needed to boot the ISO in QEMU)",
+            arch.firmware_hint(),
+        ),
+    });
+
+    if arch != Arch::X86_64 {
+        let mkstandalone = ["grub2-mkstandalone", "grub-mkstandalone"]
+            .iter()
+            .find_map(|t| which(t));
+        results.push(match mkstandalone {
+            Some(path) => CheckResult::pass(
+                "grub-mkstandalone tool",
+                format!(
+                    "Found at {} (Build {} EFI loader)",
+                    path,
+                    arch.grub_format()
+                ),
+            ),
+            None => CheckResult::fail(
+                "grub-mkstandalone tool",
+                format!("Not found (needed for: {} EFI loader)", arch.grub_format()),
+                "sudo dnf install grub2-tools-extra",
+            ),
+        });
+        results.extend(
+            CROSS_ISO_TOOLS
+                .iter()
+                .map(|(tool, purpose, install)| check_tool(tool, purpose, install)),
+        );
+    }
+    results
+}
+
+/// Check `ukify` and the systemd EFI stub, needed only for `--with-ukis`.
+///
+/// Missing pieces are warnings: UKIs are optional.
+pub fn check_uki_tools() -> Vec<CheckResult> {
+    use crate::artifact::uki::{find_ukify, UKI_EFI_STUB};
+
+    let ukify = match find_ukify() {
+        Some(path) => CheckResult::pass(
+            "ukify tool",
+            format!("Found at {} (Build UKIs with --with-ukis)", path.display()),
+        ),
+        None => CheckResult::warn(
+            "ukify tool",
+            "Not found (only needed for --with-ukis): sudo dnf install systemd-ukify",
+        ),
+    };
+    let stub = if std::path::Path::new(UKI_EFI_STUB).exists() {
+        CheckResult::pass("systemd EFI stub", format!("Found at {}", UKI_EFI_STUB))
+    } else {
+        CheckResult::warn(
+            "systemd EFI stub",
+            "Not found (only needed for --with-ukis): sudo dnf install systemd-boot-unsigned",
+        )
+    };
+    vec![ukify, stub]
+}
+
+/// GRUB i386-pc files for `--bios`; warnings only, since BIOS boot is optional.
+pub fn check_bios_tools() -> Vec<CheckResult> {
+    use crate::artifact::grub_bios::{check_bios_prerequisites, GRUB_PC_DIR};
+
+    let result = match check_bios_prerequisites() {
+        Ok(()) => CheckResult::pass(
+            "GRUB BIOS files",
+            format!("Found at {} (BIOS boot with --bios)", GRUB_PC_DIR),
+        ),
+        Err(e) => CheckResult::warn(
+            "GRUB BIOS files",
+            format!(
+                "Not usable (only needed for --bios): {}",
+                e.to_string().replace('\n', " ")
+            ),
+        ),
+    };
+    vec![result]
+}
+
+/// erofs-utils release that handles the configured compression.
+const MIN_EROFS_UTILS: &[u32] = &[1, 5];
+
+/// xorriso release with `-partition_offset`.
+const MIN_XORRISO: &[u32] = &[1, 4];
+
+/// squashfs-tools release with zstd compression.
+const MIN_SQUASHFS_TOOLS: &[u32] = &[4, 4];
+
+/// Check image tool versions for a build producing a `format` rootfs.
+///
+/// Missing tools are left to [`check_host_tools`], except mksquashfs, which
+/// is only required for squashfs builds.
+pub fn check_tool_versions(format: RootfsFormat) -> Vec<CheckResult> {
+    let mut results = Vec::new();
+    if format == RootfsFormat::Erofs && exists("mkfs.erofs") {
+        results.push(erofs_check(
+            &tool_output("mkfs.erofs", "--version"),
+            &tool_output("mkfs.erofs", "--help"),
+            EROFS_COMPRESSION,
+        ));
+    }
+    if exists("xorriso") {
+        results.push(version_check(
+            "xorriso",
+            &tool_output("xorriso", "-version"),
+            MIN_XORRISO,
+            "-partition_offset",
+            "sudo dnf install xorriso",
+        ));
+    }
+    if format == RootfsFormat::Squashfs {
+        results.push(if exists("mksquashfs") {
+            version_check(
+                "mksquashfs",
+                &tool_output("mksquashfs", "-version"),
+                MIN_SQUASHFS_TOOLS,
+                "zstd compression",
+                "sudo dnf install squashfs-tools",
+            )
+        } else {
+            check_tool(
+                "mksquashfs",
+                "Build squashfs image",
+                "sudo dnf install squashfs-tools",
+            )
+        });
+    }
+    results
+}
+
+/// Combined stdout and stderr of `tool flag` (tools differ in which they use).
+fn tool_output(tool: &str, flag: &str) -> String {
+    Cmd::new(tool)
+        .arg(flag)
+        .allow_fail()
+        .run()
+        .map(|r| format!("{}\n{}", r.stdout, r.stderr))
+        .unwrap_or_default()
+}
+
+/// Version `tool flag` reports, if the tool is installed and prints one.
+pub fn tool_version(tool: &str, flag: &str) -> Option<String> {
+    if !exists(tool) {
+        return None;
+    }
+    parse_version(&tool_output(tool, flag)).map(|v| version_string(&v))
+}
+
+/// First dotted version number in tool output (`mkfs.erofs (erofs-utils) 1.7.1`).
+pub fn parse_version(output: &str) -> Option<Vec<u32>> {
+    output.split_whitespace().find_map(|token| {
+        let token = token.trim_start_matches('v');
+        let end = token
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(token.len());
+        let parts: Vec<u32> = token[..end]
+            .trim_end_matches('.')
+            .split('.')
+            .map(|p| p.parse().ok())
+            .collect::<Option<_>>()?;
+        (parts.len() >= 2).then_some(parts)
+    })
+}
+
+fn version_string(version: &[u32]) -> String {
+    version
+        .iter()
+        .map(|p| p.to_string())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Compressors listed by `mkfs.erofs --help`, if it lists them.
+///
+/// Newer releases print a "Supported algorithms are:" block, older ones an
+/// "Available compressors are:" line.
+pub fn erofs_compressors(help: &str) -> Vec<String> {
+    let lower = help.to_ascii_lowercase();
+    let start = ["available compressors are", "supported algorithms are"]
+        .iter()
+        .find_map(|marker| lower.find(marker).map(|i| i + marker.len()));
+    let Some(start) = start else {
+        return Vec::new();
+    };
+    let mut names = Vec::new();
+    for line in lower[start..].lines() {
+        let line = line.trim().trim_start_matches(':').trim();
+        // The block ends at the first option line or blank line after it
+        if line.starts_with('-') || (line.is_empty() && !names.is_empty()) {
+            break;
+        }
+        for token in line.split([',', ' ', '\t']) {
+            let token = token.trim();
+            if !token.is_empty()
+                && token.chars().all(|c| c.is_ascii_alphanumeric())
+                && !token.chars().all(|c| c.is_ascii_digit())
+                && !["level", "default", "dictsize"].contains(&token)
+            {
+                names.push(token.to_string());
+            }
+        }
+    }
+    names
+}
+
+/// Result for a tool that needs at least `minimum` for `feature`.
+fn version_check(
+    tool: &str,
+    output: &str,
+    minimum: &[u32],
+    feature: &str,
+    install: &str,
+) -> CheckResult {
+    let name = format!("{} version", tool);
+    let Some(version) = parse_version(output) else {
+        return CheckResult::warn(
+            name,
+            format!(
+                "Could not parse version (needs >= {})",
+                version_string(minimum)
+            ),
+        );
+    };
+    if version.as_slice() < minimum {
+        return CheckResult::fail(
+            name,
+            format!(
+                "{} {} is older than {} (needed for {})",
+                tool,
+                version_string(&version),
+                version_string(minimum),
+                feature
+            ),
+            format!(
+                "Upgrade {} to >= {}: {}",
+                tool,
+                version_string(minimum),
+                install
+            ),
+        );
+    }
+    CheckResult::pass(
+        name,
+        format!(
+            "{} {} (>= {})",
+            tool,
+            version_string(&version),
+            version_string(minimum)
+        ),
+    )
+}
+
+/// mkfs.erofs version plus support for the `compression` algorithm.
+fn erofs_check(version_output: &str, help: &str, compression: &str) -> CheckResult {
+    let feature = format!("{} compression", compression);
+    let result = version_check(
+        "mkfs.erofs",
+        version_output,
+        MIN_EROFS_UTILS,
+        &feature,
+        "sudo dnf install erofs-utils (or build with --rootfs-format squashfs)",
+    );
+    if !result.passed {
+        return result;
+    }
+
+    let compressors = erofs_compressors(help);
+    if compressors.is_empty() {
+        return CheckResult::warn(
+            result.name,
+            format!("{}; compressor list not found in --help", result.message),
+        );
+    }
+    if !compressors.iter().any(|c| c == compression) {
+        return CheckResult::fail(
+            result.name,
+            format!(
+                "{} does not support {} (supports: {})",
+                result.message,
+                compression,
+                compressors.join(", ")
+            ),
+            format!(
+                "Install erofs-utils built with {} support, or build with --rootfs-format squashfs",
+                compression
+            ),
+        );
+    }
+    CheckResult::pass(
+        result.name,
+        format!("{}, {} supported", result.message, compression),
+    )
+}
+
+/// Check the compressors the live initramfs needs: gzip (recinit's archive
+/// is always gzip) and, for xz/zstd, the tool that recompresses it.
+pub fn check_initramfs_compression(compression: InitramfsCompression) -> Vec<CheckResult> {
+    let mut results = vec![check_tool(
+        Compressor::Gzip.tool(),
+        "Compress initramfs",
+        Compressor::Gzip.install_cmd(),
+    )];
+    let compressor = compression.compressor;
+    if compressor != Compressor::Gzip {
+        results.push(check_tool(
+            compressor.tool(),
+            &format!("Compress initramfs ({} -{})", compressor, compression.level),
+            compressor.install_cmd(),
+        ));
+    }
+    results
+}
+
 /// Check a single tool (using shared infrastructure from distro-builder).
 fn check_tool(tool: &str, purpose: &str, install_cmd: &str) -> CheckResult {
     match which(tool) {
@@ -72,4 +418,67 @@ mod tests {
         let results = check_host_tools();
         assert_eq!(results.len(), REQUIRED_TOOLS.len());
     }
+
+    #[test]
+    fn test_tool_version_checks() {
+        assert_eq!(
+            parse_version("mkfs.erofs (erofs-utils) 1.7.1-g1a2b\n"),
+            Some(vec![1, 7, 1])
+        );
+        assert_eq!(
+            parse_version("mksquashfs version 4.6.1 (2023/03/25)"),
+            Some(vec![4, 6, 1])
+        );
+        assert_eq!(
+            parse_version("xorriso 1.5.6 : RockRidge"),
+            Some(vec![1, 5, 6])
+        );
+        assert_eq!(parse_version("no version here 3"), None);
+
+        let old = version_check(
+            "xorriso",
+            "xorriso 1.3.8",
+            MIN_XORRISO,
+            "-partition_offset",
+            "x",
+        );
+        assert!(!old.passed);
+        assert!(old.message.contains("1.3.8 is older than 1.4"));
+        assert!(version_check("xorriso", "xorriso 1.4", MIN_XORRISO, "f", "x").passed);
+
+        let new_help = "Usage: mkfs.erofs [OPTIONS] FILE SOURCE\n\
+                        Supported algorithms are:\n\
+                        \x20 lz4\n\
+                        \x20 lz4hc, level 0-12 [9]\n\
+                        \x20 zstd, level 0-22 [3]\n\n\
+                        \x20-b# set block size\n";
+        assert_eq!(erofs_compressors(new_help), ["lz4", "lz4hc", "zstd"]);
+        let old_help = "Available compressors are: lz4 lz4hc\n -x# set xattr tolerance\n";
+        assert_eq!(erofs_compressors(old_help), ["lz4", "lz4hc"]);
+
+        let ok = erofs_check("mkfs.erofs (erofs-utils) 1.7.1", new_help, "lz4hc");
+        assert!(ok.passed);
+        assert_eq!(ok.message, "mkfs.erofs 1.7.1 (>= 1.5), lz4hc supported");
+        let too_old = erofs_check("mkfs.erofs 1.3", old_help, "lz4hc");
+        assert!(!too_old.passed);
+        assert!(too_old.message.contains("needed for lz4hc compression"));
+        let no_lz4hc = erofs_check(
+            "mkfs.erofs 1.6",
+            "Available compressors are: lz4\n",
+            "lz4hc",
+        );
+        assert!(!no_lz4hc.passed);
+        assert!(no_lz4hc
+            .message
+            .contains("does not support lz4hc (supports: lz4)"));
+    }
+
+    #[test]
+    fn test_check_arch_tools() {
+        let results = check_arch_tools(Arch::Aarch64);
+        assert_eq!(results.len(), 3 + CROSS_ISO_TOOLS.len());
+        assert_eq!(results[0].name, "qemu-system-aarch64 tool");
+        assert_eq!(results[1].name, "aarch64 UEFI firmware");
+        assert_eq!(check_arch_tools(Arch::X86_64).len(), 2);
+    }
 }
@@ -6,9 +6,19 @@
 //! # Checks Performed
 //!
 //! - **Host tools**: 7z, tar, mkfs.erofs, xorriso are installed
-//! - **Network**: Alpine mirror is reachable
+//! - **Tool versions**: erofs-utils >= 1.5 with the configured compressor,
+//!   xorriso >= 1.4, squashfs-tools >= 4.4 for squashfs builds
+//! - **Target arch**: QEMU binary, UEFI firmware and GRUB tools for `--arch`
+//!   (non-x86_64 only)
+//! - **UKI tools**: `ukify` and the systemd EFI stub (warning only, needed
+//!   for `--with-ukis`)
+//! - **Network**: Alpine mirror is reachable (skipped when `ACORNOS_OFFLINE=1`)
 //! - **Disk space**: Sufficient space for downloads and build artifacts
-//! - **Cache status**: Reports what's already downloaded
+//! - **Privileges**: What an unprivileged build skips or works around
+//!   (warnings only)
+//! - **Netboot**: Reminder that served rootfs must be reachable by targets
+//! - **Cache status**: Reports what's already downloaded, and whether from the
+//!   shared download cache
 //!
 //! # Usage
 //!
@@ -30,13 +40,21 @@
 mod disk_space;
 mod host_tools;
 mod network;
+mod privileges;
 
 pub use disk_space::check_disk_space;
-pub use host_tools::check_host_tools;
-pub use network::check_network;
+pub use host_tools::{
+    check_arch_tools, check_bios_tools, check_host_tools, check_initramfs_compression,
+    check_tool_versions, check_uki_tools, tool_version,
+};
+pub use network::{check_network, netboot_notice, offline_notice};
+pub use privileges::check_privileges;
 
 use std::path::{Path, PathBuf};
 
+use crate::download_cache::{Origin, SharedCache};
+use crate::term::{self, Status};
+
 /// Result of a single preflight check.
 #[derive(Debug, Clone)]
 pub struct CheckResult {
@@ -48,6 +66,8 @@ pub struct CheckResult {
     pub message: String,
     /// Optional suggestion for fixing the issue
     pub suggestion: Option<String>,
+    /// Passed, but with a note worth reading
+    pub warning: bool,
 }
 
 impl CheckResult {
@@ -58,6 +78,7 @@ impl CheckResult {
             passed: true,
             message: message.into(),
             suggestion: None,
+            warning: false,
         }
     }
 
@@ -72,6 +93,7 @@ impl CheckResult {
             passed: false,
             message: message.into(),
             suggestion: Some(suggestion.into()),
+            warning: false,
         }
     }
 
@@ -82,10 +104,43 @@ impl CheckResult {
             passed: true,
             message: message.into(),
             suggestion: None,
+            warning: true,
+        }
+    }
+
+    /// Status shown for the check.
+    pub fn status(&self) -> Status {
+        if !self.passed {
+            Status::Fail
+        } else if self.warning {
+            Status::Warn
+        } else {
+            Status::Ok
         }
     }
 }
 
+/// Checks as a status column, then the names and messages in columns, with
+/// suggestions under their message.
+pub fn render_checks(checks: &[CheckResult]) -> String {
+    let name_width = checks
+        .iter()
+        .map(|c| term::width(&c.name) + 1)
+        .max()
+        .unwrap_or(0);
+    let mut out = String::new();
+    for check in checks {
+        let marker = term::marker(check.status());
+        let label = term::pad(&format!("{}:", check.name), name_width);
+        out += &format!("{} {} {}\n", marker, label, check.message);
+        if let Some(suggestion) = &check.suggestion {
+            let indent = term::width(&marker) + name_width + 2;
+            out += &format!("{}Suggestion: {}\n", " ".repeat(indent), suggestion);
+        }
+    }
+    out
+}
+
 /// Comprehensive preflight report.
 #[derive(Debug, Default)]
 pub struct PreflightReport {
@@ -120,13 +175,7 @@ impl PreflightReport {
     pub fn print_summary(&self) {
         println!("=== Preflight Check Results ===\n");
 
-        for check in &self.checks {
-            let status = if check.passed { "[OK]" } else { "[FAIL]" };
-            println!("{} {}: {}", status, check.name, check.message);
-            if let Some(suggestion) = &check.suggestion {
-                println!("     Suggestion: {}", suggestion);
-            }
-        }
+        print!("{}", render_checks(&self.checks));
 
         println!();
         println!("=== Cache Status ===\n");
@@ -135,13 +184,15 @@ impl PreflightReport {
         println!();
         if self.is_ok() {
             println!(
-                "All preflight checks passed ({}/{})",
+                "{} ({}/{})",
+                term::paint(Status::Ok, "All preflight checks passed"),
                 self.passed_count(),
                 self.total_count()
             );
         } else {
             println!(
-                "Preflight checks failed: {} of {} passed",
+                "{}: {} of {} passed",
+                term::paint(Status::Fail, "Preflight checks failed"),
                 self.passed_count(),
                 self.total_count()
             );
@@ -162,18 +213,47 @@ pub struct CacheStatus {
     pub has_rootfs: bool,
     /// Busybox static binary is cached
     pub has_busybox: bool,
+    /// Shared download cache, if configured
+    pub shared_cache: Option<PathBuf>,
+    /// Where the ISO and busybox came from, with a shared cache configured
+    pub alpine_iso_origin: Option<Origin>,
+    pub busybox_origin: Option<Origin>,
 }
 
 impl CacheStatus {
+    /// Everything the build downloads is present, so it can run offline.
+    pub fn all_downloads_cached(&self) -> bool {
+        self.has_alpine_iso && self.has_apk_tools && self.has_rootfs && self.has_busybox
+    }
+
     /// Print cache status.
     pub fn print(&self) {
-        let status = |b: bool| if b { "[cached]" } else { "[missing]" };
-
-        println!("{}  Alpine Extended ISO", status(self.has_alpine_iso));
+        let status = |b: bool| {
+            let marker = if b {
+                term::paint(Status::Ok, "[cached]")
+            } else {
+                term::paint(Status::Warn, "[missing]")
+            };
+            term::pad(&marker, "[missing]".len())
+        };
+        let origin = |o: Option<Origin>| o.map_or(String::new(), |o| format!(" ({})", o.label()));
+
+        if let Some(dir) = &self.shared_cache {
+            println!("Shared download cache: {}", dir.display());
+        }
+        println!(
+            "{}  Alpine Extended ISO{}",
+            status(self.has_alpine_iso),
+            origin(self.alpine_iso_origin)
+        );
         println!("{}  ISO contents extracted", status(self.has_iso_contents));
         println!("{}  apk-tools-static", status(self.has_apk_tools));
         println!("{}  Rootfs", status(self.has_rootfs));
-        println!("{}  Busybox static", status(self.has_busybox));
+        println!(
+            "{}  Busybox static{}",
+            status(self.has_busybox),
+            origin(self.busybox_origin)
+        );
     }
 }
 
@@ -197,15 +277,60 @@ impl PreflightChecker {
         // Check host tools
         report.checks.extend(check_host_tools());
 
+        // Image tool versions for the rootfs format of the current build
+        let output_dir = crate::paths::output_dir(&self.base_dir);
+        let format = crate::artifact::RootfsFormat::load(&output_dir);
+        report.checks.extend(check_tool_versions(format));
+
+        // Compressors for the initramfs compression of the current build
+        let compression = crate::artifact::InitramfsCompression::load(&output_dir);
+        report
+            .checks
+            .extend(check_initramfs_compression(compression));
+
+        // Non-default target: its QEMU, firmware and ISO tools
+        match crate::arch::Arch::from_env() {
+            Ok(crate::arch::Arch::X86_64) => {}
+            Ok(arch) => report.checks.extend(check_arch_tools(arch)),
+            Err(e) => report.checks.push(CheckResult::fail(
+                "Target architecture",
+                e.to_string(),
+                "Use --arch x86_64 or --arch aarch64",
+            )),
+        }
+
+        // Optional installed-system UKIs
+        report.checks.extend(check_uki_tools());
+
+        // Optional legacy BIOS boot
+        report.checks.extend(check_bios_tools());
+
+        // Unprivileged builds: root emulation for apk, image verification
+        report.checks.extend(check_privileges(format));
+
         // Check disk space
         report.checks.push(check_disk_space(&self.base_dir));
 
-        // Check network (async)
-        report.checks.push(check_network().await);
-
         // Check cache status
         report.cache_status = self.check_cache_status();
 
+        // Check network (async), unless building air-gapped
+        if crate::offline::offline_from_env() {
+            report.checks.push(offline_notice());
+        } else {
+            report
+                .checks
+                .push(check_network(&report.cache_status).await);
+        }
+
+        // Netboot targets download the rootfs themselves
+        if output_dir
+            .join(crate::artifact::netboot::NETBOOT_DIR)
+            .exists()
+        {
+            report.checks.push(netboot_notice());
+        }
+
         report
     }
 
@@ -214,14 +339,18 @@ impl PreflightChecker {
         use distro_builder::alpine::extract::ExtractPaths;
 
         let paths = ExtractPaths::new(&self.base_dir);
-        let downloads = self.base_dir.join("downloads");
+        let busybox = self.base_dir.join("downloads").join("busybox-static");
+        let shared = SharedCache::from_env();
 
         CacheStatus {
             has_alpine_iso: paths.iso.exists(),
             has_iso_contents: paths.iso_contents.join("apks").exists(),
             has_apk_tools: paths.apk_tools.join("sbin").join("apk.static").exists(),
             has_rootfs: paths.rootfs.join("bin").exists(),
-            has_busybox: downloads.join("busybox-static").exists(),
+            has_busybox: busybox.exists(),
+            alpine_iso_origin: shared.as_ref().and_then(|c| c.origin(&paths.iso)),
+            busybox_origin: shared.as_ref().and_then(|c| c.origin(&busybox)),
+            shared_cache: shared.map(|c| c.dir),
         }
     }
 
@@ -249,6 +378,32 @@ mod tests {
         assert!(result.suggestion.is_some());
     }
 
+    #[test]
+    fn test_render_checks_aligns_columns() {
+        let checks = [
+            CheckResult::pass("7z", "found"),
+            CheckResult::warn("UKI tools", "ukify not found"),
+            CheckResult::fail("xorriso", "not found", "sudo dnf install xorriso"),
+        ];
+        let rendered = render_checks(&checks);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[1],
+            format!("{} UKI tools: ukify not found", term::marker(Status::Warn))
+        );
+        assert_eq!(
+            lines[0],
+            format!("{} 7z:        found", term::marker(Status::Ok))
+        );
+        // The suggestion starts in the message column
+        let column = |line: &str, text: &str| term::width(&line[..line.find(text).unwrap()]);
+        assert_eq!(
+            column(lines[3], "Suggestion"),
+            column(lines[2], "not found")
+        );
+    }
+
     #[test]
     fn test_preflight_report_is_ok() {
         let mut report = PreflightReport::default();
@@ -1,62 +1,438 @@
 //! Network connectivity check for AcornOS build.
 //!
 //! Verifies that Alpine mirrors are reachable before starting downloads.
+//! The mirror root and the release URL are probed separately, so "no
+//! connectivity" is told apart from "mirror path missing" (a bad
+//! `ALPINE_VERSION`). Probes go through `https_proxy`/`http_proxy` (either
+//! case) and are retried with backoff on timeouts, connection errors and
+//! 5xx answers, all within [`NETWORK_BUDGET`]. A failure is only a warning
+//! when every download is already cached, since the build can then run
+//! without the network.
 
-use super::CheckResult;
+use std::time::{Duration, Instant};
 
-// Canonical source: deps/alpine.rhai
-const ALPINE_EXTENDED_ISO_URL: &str =
-    "https://dl-cdn.alpinelinux.org/alpine/v3.23/releases/x86_64/alpine-extended-3.23.2-x86_64.iso";
+use super::{CacheStatus, CheckResult};
+use crate::alpine_source::AlpineSource;
+use crate::arch::Arch;
+use crate::download::alpine_iso_url;
+
+/// Total time the network check may take, retries included.
+pub const NETWORK_BUDGET: Duration = Duration::from_secs(10);
+
+/// Retries after a failed probe.
+const PROBE_RETRIES: u32 = 3;
+
+/// Delay before the first retry, doubled for each further one.
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Outcome of probing one URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Probe {
+    /// The server answered with this HTTP status.
+    Status(u16),
+    /// No answer (DNS, connection, proxy, TLS or timeout), with the reason.
+    Unreachable(String),
+}
+
+impl Probe {
+    fn retryable(&self) -> bool {
+        match self {
+            Self::Status(code) => *code >= 500,
+            Self::Unreachable(_) => true,
+        }
+    }
+}
+
+/// What the mirror probes found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum MirrorStatus {
+    Reachable,
+    /// The mirror answers, but not for the release URL.
+    PathMissing(u16),
+    /// No connectivity to the mirror (or through the proxy).
+    Unreachable(String),
+}
 
 /// Check network connectivity to Alpine mirrors.
 ///
-/// Performs a HEAD request to verify the Alpine CDN is reachable.
-pub async fn check_network() -> CheckResult {
-    // Use a simple HEAD request via curl to check connectivity
-    // This avoids adding reqwest as a dependency
+/// Performs HEAD requests against the mirror root and the build's Extended
+/// ISO on its mirror (`--alpine-mirror`, `--alpine-version`).
+pub async fn check_network(cache: &CacheStatus) -> CheckResult {
+    let source = match AlpineSource::from_env() {
+        Ok(source) => source,
+        Err(e) => {
+            return CheckResult::fail(
+                "Network",
+                e.to_string(),
+                "Fix ALPINE_MIRROR / ALPINE_VERSION",
+            )
+        }
+    };
+    let url = alpine_iso_url(Arch::from_env().unwrap_or_default(), &source);
+    let proxy = proxy_for(&url, |var| std::env::var(var).ok());
+    let status = check_mirror(
+        &mirror_root(&source.mirror),
+        &url,
+        proxy.as_deref(),
+        NETWORK_BUDGET,
+        RETRY_BACKOFF,
+    )
+    .await;
+    mirror_result(
+        &status,
+        mirror_host(&source.mirror),
+        &url,
+        proxy.as_deref(),
+        cache.all_downloads_cached(),
+    )
+}
+
+/// Probe the mirror root, then the release URL, within `budget`.
+async fn check_mirror(
+    root: &str,
+    release_url: &str,
+    proxy: Option<&str>,
+    budget: Duration,
+    backoff: Duration,
+) -> MirrorStatus {
+    let deadline = Instant::now() + budget;
+    // Any answer from the root means the host (or proxy) is reachable
+    if let Probe::Unreachable(why) = probe(root, proxy, deadline, backoff).await {
+        return MirrorStatus::Unreachable(why);
+    }
+    match probe(release_url, proxy, deadline, backoff).await {
+        Probe::Status(code) if (200..400).contains(&code) => MirrorStatus::Reachable,
+        Probe::Status(code) => MirrorStatus::PathMissing(code),
+        Probe::Unreachable(why) => MirrorStatus::Unreachable(why),
+    }
+}
+
+/// Probe `url`, retrying with backoff until `deadline`.
+async fn probe(url: &str, proxy: Option<&str>, deadline: Instant, backoff: Duration) -> Probe {
+    let mut delay = backoff;
+    let mut result = Probe::Unreachable("no time left for the request".to_string());
+    for attempt in 0..=PROBE_RETRIES {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        result = probe_once(url, proxy, left).await;
+        if !result.retryable() || attempt == PROBE_RETRIES || Instant::now() + delay >= deadline {
+            break;
+        }
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+    }
+    result
+}
+
+/// One HEAD request via curl (avoids adding an HTTP client dependency).
+async fn probe_once(url: &str, proxy: Option<&str>, timeout: Duration) -> Probe {
+    // An empty --proxy makes curl ignore the proxy variables: they were
+    // already resolved by proxy_for
     let result = tokio::process::Command::new("curl")
-        .args([
-            "--head",   // HEAD request only
-            "--silent", // No progress output
-            "--fail",   // Fail on HTTP errors
-            "--max-time",
-            "10", // 10 second timeout
-            "--output",
-            "/dev/null",
-            ALPINE_EXTENDED_ISO_URL,
-        ])
+        .args(["--head", "--silent", "--output", "/dev/null"])
+        .args(["--write-out", "%{http_code}"])
+        .args(["--max-time", &format!("{:.1}", timeout.as_secs_f64())])
+        .args(["--proxy", proxy.unwrap_or("")])
+        .arg(url)
         .output()
         .await;
-
     match result {
-        Ok(output) if output.status.success() => CheckResult::pass(
-            "Network",
-            format!("Alpine mirror reachable ({})", mirror_host()),
+        Ok(output) if output.status.success() => {
+            match String::from_utf8_lossy(&output.stdout).trim().parse() {
+                Ok(code) if code > 0 => Probe::Status(code),
+                _ => Probe::Unreachable("no HTTP response".to_string()),
+            }
+        }
+        Ok(output) => Probe::Unreachable(curl_error(output.status.code())),
+        Err(e) => Probe::Unreachable(format!("failed to run curl: {}", e)),
+    }
+}
+
+/// Reason for a curl exit code.
+fn curl_error(code: Option<i32>) -> String {
+    match code {
+        Some(5) => "could not resolve the proxy".to_string(),
+        Some(6) => "could not resolve the host".to_string(),
+        Some(7) => "connection refused".to_string(),
+        Some(28) => "timed out".to_string(),
+        Some(35) | Some(60) => "TLS handshake failed".to_string(),
+        Some(code) => format!("curl exited with {}", code),
+        None => "curl was killed".to_string(),
+    }
+}
+
+/// Proxy for `url` from `https_proxy`/`http_proxy` (lowercase first, as curl
+/// reads them; curl alone ignores an uppercase `HTTP_PROXY`).
+fn proxy_for(url: &str, var: impl Fn(&str) -> Option<String>) -> Option<String> {
+    let names: &[&str] = if url.starts_with("https://") {
+        &["https_proxy", "HTTPS_PROXY"]
+    } else {
+        &["http_proxy", "HTTP_PROXY"]
+    };
+    names
+        .iter()
+        .find_map(|name| var(name).filter(|v| !v.trim().is_empty()))
+}
+
+/// Check result for a mirror probe; failures only warn if nothing needs
+/// downloading.
+fn mirror_result(
+    status: &MirrorStatus,
+    host: &str,
+    url: &str,
+    proxy: Option<&str>,
+    cached: bool,
+) -> CheckResult {
+    let via = proxy.map_or(String::new(), |p| format!(" via proxy {}", p));
+    let (message, suggestion) = match status {
+        MirrorStatus::Reachable => {
+            return CheckResult::pass("Network", format!("Alpine mirror reachable ({}{})", host, via))
+        }
+        MirrorStatus::PathMissing(code) => (
+            format!(
+                "Alpine mirror {} answers, but {} returned HTTP {}: mirror path missing (bad ALPINE_VERSION?)",
+                host, url, code
+            ),
+            "Check --alpine-version / ALPINE_VERSION and that the mirror carries this release",
         ),
-        Ok(_) => CheckResult::fail(
-            "Network",
-            format!("Alpine mirror unreachable ({})", mirror_host()),
-            "Check your internet connection or try again later",
+        MirrorStatus::Unreachable(why) => (
+            format!("No connectivity to Alpine mirror {}{}: {}", host, via, why),
+            "Check your internet connection, or set https_proxy/http_proxy for an HTTP proxy",
         ),
-        Err(e) => CheckResult::fail(
+    };
+    if cached {
+        CheckResult::warn(
             "Network",
-            format!("Failed to check network: {}", e),
-            "Ensure curl is installed and you have network access",
-        ),
+            format!(
+                "{}; all downloads are cached, so the build can proceed",
+                message
+            ),
+        )
+    } else {
+        CheckResult::fail("Network", message, suggestion)
     }
 }
 
-/// Extract just the host from the Alpine URL for display.
-fn mirror_host() -> &'static str {
-    "dl-cdn.alpinelinux.org"
+/// Informational result used instead of [`check_network`] in offline mode.
+pub fn offline_notice() -> CheckResult {
+    CheckResult::warn(
+        "Network",
+        format!(
+            "skipped: offline mode ({}=1), using pre-seeded downloads",
+            crate::offline::OFFLINE_ENV
+        ),
+    )
+}
+
+/// Reminder shown once netboot artifacts exist: the target fetches the
+/// rootfs itself at boot, so the build host's connectivity says nothing.
+pub fn netboot_notice() -> CheckResult {
+    CheckResult::warn(
+        "Netboot",
+        "output/netboot/ must be served over HTTP where the target network can reach it \
+         (acorn.rootfs_url= is fetched by the booting machine, not this host)",
+    )
+}
+
+/// `scheme://host/` of the mirror URL.
+fn mirror_root(mirror: &str) -> String {
+    let scheme = mirror
+        .split_once("://")
+        .map_or("https", |(scheme, _)| scheme);
+    format!("{}://{}/", scheme, mirror_host(mirror))
+}
+
+/// Extract just the host from the Alpine mirror URL for display.
+fn mirror_host(mirror: &str) -> &str {
+    let rest = mirror.split_once("://").map_or(mirror, |(_, rest)| rest);
+    rest.split('/').next().unwrap_or(rest)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    /// HTTP server (or proxy) answering with `statuses` in turn, the last
+    /// one repeating. Returns its URL and the request lines it got.
+    fn mock_server(statuses: &[u16]) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        let statuses = statuses.to_vec();
+        std::thread::spawn(move || {
+            for (i, stream) in listener.incoming().enumerate() {
+                let Ok(mut stream) = stream else { break };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap_or(0) > 2 {
+                    header.clear();
+                }
+                seen.lock().unwrap().push(request.trim().to_string());
+                let status = statuses[i.min(statuses.len() - 1)];
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {} Mock\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                );
+            }
+        });
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn test_probe_retries_transient_failures() {
+        let (url, requests) = mock_server(&[503, 503, 200]);
+        let release = format!("{}/v3.23/releases/x86_64/alpine.iso", url);
+        let status = check_mirror(
+            &format!("{}/", url),
+            &release,
+            None,
+            Duration::from_secs(5),
+            Duration::from_millis(10),
+        )
+        .await;
+        assert_eq!(status, MirrorStatus::Reachable);
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                "HEAD / HTTP/1.1",
+                "HEAD / HTTP/1.1",
+                "HEAD / HTTP/1.1",
+                "HEAD /v3.23/releases/x86_64/alpine.iso HTTP/1.1"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_probe_through_proxy() {
+        let (proxy, requests) = mock_server(&[200, 404]);
+        let release = "http://mirror.invalid/alpine/v9.99/releases/alpine.iso";
+        let status = check_mirror(
+            "http://mirror.invalid/",
+            release,
+            Some(&proxy),
+            Duration::from_secs(5),
+            Duration::from_millis(10),
+        )
+        .await;
+        assert_eq!(status, MirrorStatus::PathMissing(404));
+        // Absolute URLs: the requests went to the proxy
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                "HEAD http://mirror.invalid/ HTTP/1.1".to_string(),
+                format!("HEAD {} HTTP/1.1", release)
+            ]
+        );
+
+        let result = mirror_result(&status, "mirror.invalid", release, Some(&proxy), false);
+        assert!(!result.passed);
+        assert!(
+            result.message.contains("bad ALPINE_VERSION?"),
+            "{}",
+            result.message
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_mirror_warns_when_cached() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let root = format!("http://127.0.0.1:{}/", port);
+        let status = check_mirror(
+            &root,
+            &format!("{}alpine.iso", root),
+            None,
+            Duration::from_secs(3),
+            Duration::from_millis(10),
+        )
+        .await;
+        assert_eq!(
+            status,
+            MirrorStatus::Unreachable("connection refused".to_string())
+        );
+
+        let failed = mirror_result(&status, "127.0.0.1", &root, None, false);
+        assert!(!failed.passed);
+        assert!(failed
+            .message
+            .starts_with("No connectivity to Alpine mirror"));
+        let cached = mirror_result(&status, "127.0.0.1", &root, None, true);
+        assert!(cached.passed);
+        assert!(cached.message.ends_with("the build can proceed"));
+    }
+
+    #[test]
+    fn test_proxy_for() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(k, _)| *k == name)
+                    .map(|(_, v)| v.to_string())
+            }
+        };
+        let https = "https://dl-cdn.alpinelinux.org/alpine/";
+        assert_eq!(
+            proxy_for(https, env(&[("HTTPS_PROXY", "http://proxy:3128")])).as_deref(),
+            Some("http://proxy:3128")
+        );
+        assert_eq!(
+            proxy_for(
+                https,
+                env(&[("https_proxy", "http://a:1"), ("HTTPS_PROXY", "http://b:2")])
+            )
+            .as_deref(),
+            Some("http://a:1")
+        );
+        assert_eq!(proxy_for(https, env(&[("http_proxy", "http://a:1")])), None);
+        assert_eq!(
+            proxy_for(
+                "http://mirror.corp/alpine",
+                env(&[("HTTP_PROXY", "http://b:2")])
+            )
+            .as_deref(),
+            Some("http://b:2")
+        );
+        assert_eq!(
+            mirror_root("http://mirror.corp:8080/alpine"),
+            "http://mirror.corp:8080/"
+        );
+    }
 
     #[test]
     fn test_mirror_host() {
-        assert!(!mirror_host().is_empty());
+        assert_eq!(
+            mirror_host(crate::alpine_source::DEFAULT_MIRROR),
+            "dl-cdn.alpinelinux.org"
+        );
+        assert_eq!(
+            mirror_host("http://mirror.corp:8080/alpine"),
+            "mirror.corp:8080"
+        );
+    }
+
+    #[test]
+    fn test_offline_notice_passes() {
+        let result = offline_notice();
+        assert!(result.passed);
+        assert!(result.message.starts_with("skipped: offline mode"));
+    }
+
+    #[test]
+    fn test_netboot_notice_passes() {
+        let result = netboot_notice();
+        assert!(result.passed);
+        assert!(result.message.contains("acorn.rootfs_url="));
     }
 }
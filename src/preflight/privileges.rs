@@ -0,0 +1,91 @@
+//! Root vs. unprivileged builds.
+//!
+//! A build without root works, but some steps change: apk runs under a user
+//! namespace or fakeroot, and the rootfs image can only be verified with an
+//! unprivileged checker. Each such change is reported as a warning so it is
+//! visible before the build rather than buried in its log.
+
+use super::CheckResult;
+use crate::artifact::rootfs::{image_check, ImageCheck};
+use crate::artifact::RootfsFormat;
+use crate::privilege::RootWrapper;
+use distro_builder::process::exists;
+
+/// Check what an unprivileged build of `format` can and cannot do here.
+pub fn check_privileges(format: RootfsFormat) -> Vec<CheckResult> {
+    let wrapper = RootWrapper::detect();
+    privilege_checks(wrapper, image_check(format, exists, false), format)
+}
+
+fn privilege_checks(
+    wrapper: RootWrapper,
+    unprivileged_check: Option<ImageCheck>,
+    format: RootfsFormat,
+) -> Vec<CheckResult> {
+    if wrapper == RootWrapper::Root {
+        return vec![CheckResult::pass("Build privileges", "Running as root")];
+    }
+
+    let mut results = vec![CheckResult::warn(
+        "Build privileges",
+        "Not root: image ownership comes from mkfs --all-root and the initramfs cpio headers",
+    )];
+    results.push(match wrapper {
+        RootWrapper::Unshare => CheckResult::pass(
+            "apk ownership",
+            "apk runs in a user namespace (unshare --map-root-user)",
+        ),
+        RootWrapper::Fakeroot => CheckResult::warn(
+            "apk ownership",
+            "User namespaces unavailable; apk runs under fakeroot",
+        ),
+        _ => CheckResult::warn(
+            "apk ownership",
+            "Neither user namespaces nor fakeroot available; apk may fail to chown \
+             (sudo dnf install fakeroot)",
+        ),
+    });
+    results.push(match unprivileged_check {
+        Some(check) => CheckResult::pass(
+            "Image verification",
+            format!("{:?} (no root needed)", check),
+        ),
+        None => CheckResult::warn(
+            "Image verification",
+            format!(
+                "Skipped: the loop-mount check needs root (install {} to verify {} images)",
+                match format {
+                    RootfsFormat::Erofs => "fsck.erofs or erofsfuse",
+                    RootfsFormat::Squashfs => "unsquashfs",
+                },
+                format
+            ),
+        ),
+    });
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unprivileged_degradations_are_warned() {
+        let root = privilege_checks(RootWrapper::Root, None, RootfsFormat::Erofs);
+        assert_eq!(root.len(), 1);
+        assert_eq!(root[0].message, "Running as root");
+
+        let bare = privilege_checks(RootWrapper::Unavailable, None, RootfsFormat::Erofs);
+        let messages: Vec<&str> = bare.iter().map(|c| c.message.as_str()).collect();
+        assert!(bare.iter().all(|c| c.passed));
+        assert!(messages[1].contains("fakeroot"));
+        assert!(messages[2].starts_with("Skipped: the loop-mount check needs root"));
+
+        let tooled = privilege_checks(
+            RootWrapper::Unshare,
+            Some(ImageCheck::FsckErofs),
+            RootfsFormat::Erofs,
+        );
+        assert_eq!(tooled[2].message, "FsckErofs (no root needed)");
+    }
+}
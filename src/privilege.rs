@@ -0,0 +1,115 @@
+//! Unprivileged builds.
+//!
+//! `acornos build` does not need root: file ownership is never set on disk.
+//! The rootfs image tools record every file as root (`mkfs.erofs
+//! --all-root`, `mksquashfs -all-root`) and the initramfs cpio headers are
+//! written with uid/gid 0. Only apk insists on chowning what it installs,
+//! so it runs under a [`RootWrapper`] when the build is not root. The one
+//! step left out is the loop-mount image check, used only when no
+//! unprivileged checker is installed (see `acornos preflight`).
+
+use std::ffi::OsString;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use distro_builder::process::exists;
+
+/// Whether this process runs as root.
+pub fn is_root() -> bool {
+    fs::metadata("/proc/self").is_ok_and(|m| m.uid() == 0)
+}
+
+/// How commands that chown are run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RootWrapper {
+    /// Already root.
+    Root,
+    /// A user namespace mapping the build user to root.
+    Unshare,
+    /// LD_PRELOAD-faked root.
+    Fakeroot,
+    /// Nothing available; commands run as the build user.
+    Unavailable,
+}
+
+impl RootWrapper {
+    /// Best wrapper on this host.
+    pub fn detect() -> Self {
+        if is_root() {
+            Self::Root
+        } else if user_namespaces_available() {
+            Self::Unshare
+        } else if exists("fakeroot") {
+            Self::Fakeroot
+        } else {
+            Self::Unavailable
+        }
+    }
+
+    /// `program` with `args`, wrapped.
+    pub fn command(&self, program: &Path, args: &[OsString]) -> Command {
+        let (wrapper, prefix): (&str, &[&str]) = match self {
+            Self::Root | Self::Unavailable => {
+                let mut cmd = Command::new(program);
+                cmd.args(args);
+                return cmd;
+            }
+            Self::Unshare => ("unshare", &["--map-root-user", "--"]),
+            Self::Fakeroot => ("fakeroot", &["--"]),
+        };
+        let mut cmd = Command::new(wrapper);
+        cmd.args(prefix).arg(program).args(args);
+        cmd
+    }
+}
+
+/// Whether an unprivileged user namespace can actually be created (sysctls
+/// and container seccomp profiles can both forbid it).
+pub fn user_namespaces_available() -> bool {
+    exists("unshare")
+        && Command::new("unshare")
+            .args(["--map-root-user", "true"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|s| s.success())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrapped_commands() {
+        let args = ["--root".into(), "/tmp/rootfs".into()];
+        let argv = |wrapper: RootWrapper| {
+            let cmd = wrapper.command(Path::new("apk.static"), &args);
+            std::iter::once(cmd.get_program())
+                .chain(cmd.get_args())
+                .map(|a| a.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            argv(RootWrapper::Unshare),
+            [
+                "unshare",
+                "--map-root-user",
+                "--",
+                "apk.static",
+                "--root",
+                "/tmp/rootfs"
+            ]
+        );
+        assert_eq!(
+            argv(RootWrapper::Fakeroot),
+            ["fakeroot", "--", "apk.static", "--root", "/tmp/rootfs"]
+        );
+        assert_eq!(
+            argv(RootWrapper::Root),
+            ["apk.static", "--root", "/tmp/rootfs"]
+        );
+    }
+}
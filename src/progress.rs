@@ -0,0 +1,296 @@
+//! Progress of long-running image tools (mkfs.erofs, mksquashfs, xorriso).
+//!
+//! Building the rootfs image and rewriting the ISO take minutes, and the
+//! tools only report how far they are on their own output. [`run`] runs a
+//! tool with its stdout and stderr captured, picks the percentages out of
+//! it (`[====   ] 45%`, `xorriso : UPDATE :  45.67% done`) and emits them
+//! as [`ProgressEvent`]s; the other lines are kept for the error message.
+//!
+//! Events go to every subscriber ([`subscribe`], or
+//! `BuildPipeline::subscribe_progress`), so a GUI can follow a build.
+//! Without one they are printed: a single updating line on a terminal,
+//! otherwise (CI, parallel child builds) a line every 10%.
+
+use anyhow::{bail, Context, Result};
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{IsTerminal, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, OnceLock};
+
+/// Output lines kept for the error message of a failed tool.
+const TAIL_LINES: usize = 20;
+
+/// Step between progress lines when stderr is not a terminal.
+const PLAIN_STEP: u8 = 10;
+
+/// What a long-running tool is doing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgressEvent {
+    Started {
+        task: String,
+    },
+    /// Whole percent, only sent when it changes.
+    Progress {
+        task: String,
+        percent: u8,
+    },
+    Finished {
+        task: String,
+        success: bool,
+    },
+}
+
+/// Receives progress events (a GUI, the console).
+pub trait ProgressSink: Send + Sync {
+    fn event(&self, event: &ProgressEvent);
+}
+
+fn subscribers() -> &'static Mutex<Vec<Box<dyn ProgressSink>>> {
+    static SUBSCRIBERS: OnceLock<Mutex<Vec<Box<dyn ProgressSink>>>> = OnceLock::new();
+    SUBSCRIBERS.get_or_init(Default::default)
+}
+
+/// Send every later event to `sink` instead of the console.
+pub fn subscribe(sink: Box<dyn ProgressSink>) {
+    subscribers()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(sink);
+}
+
+/// Send `event` to the subscribers, or the console if there are none; for
+/// work that is not an external tool (writing an ISO to a device).
+pub fn emit(event: &ProgressEvent) {
+    Dispatch.event(event);
+}
+
+/// Sink dispatching to the subscribers, or the console if there are none.
+struct Dispatch;
+
+impl ProgressSink for Dispatch {
+    fn event(&self, event: &ProgressEvent) {
+        static CONSOLE: OnceLock<Console> = OnceLock::new();
+        let subscribers = subscribers().lock().unwrap_or_else(|e| e.into_inner());
+        if subscribers.is_empty() {
+            CONSOLE.get_or_init(Console::new).event(event);
+        }
+        for sink in subscribers.iter() {
+            sink.event(event);
+        }
+    }
+}
+
+/// Prints progress to stderr.
+struct Console {
+    terminal: bool,
+    /// Last printed step per task, when not on a terminal.
+    printed: Mutex<BTreeMap<String, u8>>,
+}
+
+impl Console {
+    fn new() -> Self {
+        Self {
+            terminal: std::io::stderr().is_terminal(),
+            printed: Mutex::default(),
+        }
+    }
+}
+
+impl ProgressSink for Console {
+    fn event(&self, event: &ProgressEvent) {
+        match event {
+            ProgressEvent::Progress { task, percent } if self.terminal => {
+                eprint!("\r  {}: {:>3}%", task, percent);
+                let _ = std::io::stderr().flush();
+            }
+            ProgressEvent::Progress { task, percent } => {
+                let step = percent / PLAIN_STEP * PLAIN_STEP;
+                let mut printed = self.printed.lock().unwrap_or_else(|e| e.into_inner());
+                if printed.get(task).is_none_or(|&last| step > last) {
+                    printed.insert(task.clone(), step);
+                    log::info!("  {}: {}%", task, step);
+                }
+            }
+            ProgressEvent::Finished { task, .. } => {
+                if self.terminal {
+                    // Clear the progress line
+                    eprint!("\r\x1b[K");
+                }
+                self.printed
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .remove(task);
+            }
+            ProgressEvent::Started { .. } => {}
+        }
+    }
+}
+
+/// Last percentage in a line of tool output.
+pub fn parse_percent(line: &str) -> Option<u8> {
+    let end = line.rfind('%')?;
+    let number = line[..end].trim_end();
+    let start = number
+        .rfind(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .map_or(0, |i| i + 1);
+    let value: f32 = number[start..].parse().ok()?;
+    (0.0..=100.0).contains(&value).then_some(value as u8)
+}
+
+/// Run `cmd` as `task`, reporting its progress to the subscribers.
+pub fn run(task: &str, cmd: &mut Command, error_msg: &str) -> Result<()> {
+    run_reporting(task, cmd, error_msg, &Dispatch)
+}
+
+fn run_reporting(
+    task: &str,
+    cmd: &mut Command,
+    error_msg: &str,
+    sink: &dyn ProgressSink,
+) -> Result<()> {
+    let program = cmd.get_program().to_string_lossy().into_owned();
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("{}: failed to run {}", error_msg, program))?;
+
+    sink.event(&ProgressEvent::Started {
+        task: task.to_string(),
+    });
+    let stdout = child.stdout.take().context("stdout not captured")?;
+    let stderr = child.stderr.take().context("stderr not captured")?;
+    let state = Mutex::new((None::<u8>, VecDeque::new()));
+    let on_line = |line: &str| {
+        let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+        match parse_percent(line) {
+            Some(percent) if state.0 != Some(percent) => {
+                state.0 = Some(percent);
+                sink.event(&ProgressEvent::Progress {
+                    task: task.to_string(),
+                    percent,
+                });
+            }
+            Some(_) => {}
+            None if !line.trim().is_empty() => {
+                if state.1.len() == TAIL_LINES {
+                    state.1.pop_front();
+                }
+                state.1.push_back(line.to_string());
+            }
+            None => {}
+        }
+    };
+    std::thread::scope(|scope| {
+        scope.spawn(|| read_lines(stderr, &on_line));
+        read_lines(stdout, &on_line);
+    });
+    let status = child.wait()?;
+
+    sink.event(&ProgressEvent::Finished {
+        task: task.to_string(),
+        success: status.success(),
+    });
+    if !status.success() {
+        let (_, tail) = state.into_inner().unwrap_or_else(|e| e.into_inner());
+        bail!(
+            "{} ({} exited with {})\n{}",
+            error_msg,
+            program,
+            status,
+            Vec::from(tail).join("\n")
+        );
+    }
+    Ok(())
+}
+
+/// Feed `reader` to `on_line` line by line; progress bars redraw with
+/// `\r`, so that ends a line too.
+fn read_lines(mut reader: impl Read, on_line: &dyn Fn(&str)) {
+    let mut pending = Vec::new();
+    let mut buf = [0u8; 4096];
+    while let Ok(n) = reader.read(&mut buf) {
+        if n == 0 {
+            break;
+        }
+        for &b in &buf[..n] {
+            if b == b'\n' || b == b'\r' {
+                on_line(&String::from_utf8_lossy(&pending));
+                pending.clear();
+            } else {
+                pending.push(b);
+            }
+        }
+    }
+    if !pending.is_empty() {
+        on_line(&String::from_utf8_lossy(&pending));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Collect(Mutex<Vec<ProgressEvent>>);
+
+    impl ProgressSink for Collect {
+        fn event(&self, event: &ProgressEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn test_progress_is_parsed_from_tool_output() {
+        assert_eq!(parse_percent("[=========     ] 1234/5678  45%"), Some(45));
+        assert_eq!(
+            parse_percent("xorriso : UPDATE :  67.89% done, estimate finish"),
+            Some(67)
+        );
+        assert_eq!(parse_percent("Creating 4.0 filesystem"), None);
+        assert_eq!(parse_percent("250%"), None);
+
+        let sink = Collect::default();
+        let mut ok = Command::new("sh");
+        ok.args([
+            "-c",
+            "printf '[=  ] 10%%\\r[== ] 10%%\\r[===] 50%%\\n'; echo '100% done' >&2",
+        ]);
+        run_reporting("EROFS", &mut ok, "mkfs failed", &sink).unwrap();
+        let progress = |percent| ProgressEvent::Progress {
+            task: "EROFS".into(),
+            percent,
+        };
+        let events = sink.0.into_inner().unwrap();
+        assert_eq!(
+            events[0],
+            ProgressEvent::Started {
+                task: "EROFS".into()
+            }
+        );
+        assert_eq!(
+            events[1..events.len() - 1]
+                .iter()
+                .filter(|e| **e == progress(10))
+                .count(),
+            1
+        );
+        assert!(events.contains(&progress(50)) && events.contains(&progress(100)));
+        assert_eq!(
+            events.last(),
+            Some(&ProgressEvent::Finished {
+                task: "EROFS".into(),
+                success: true
+            })
+        );
+
+        let mut failing = Command::new("sh");
+        failing.args(["-c", "echo 'disk full' >&2; exit 3"]);
+        let err = run_reporting("ISO", &mut failing, "xorriso failed", &Collect::default())
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("xorriso failed (sh exited with"), "{}", err);
+        assert!(err.ends_with("disk full"), "{}", err);
+    }
+}
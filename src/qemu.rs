@@ -1,19 +1,672 @@
 //! QEMU runner for AcornOS.
 //!
-//! Thin wrapper over `distro_builder::qemu` with AcornOS-specific configuration.
+//! Renders QEMU command lines from a [`VmConfig`] with AcornOS-specific
+//! defaults, debugging extras for `acornos run` (port forwards, virtfs
+//! shares), plus the headless boot test with per-stage boot-time budgets, of the live ISO or
+//! of an installed system's disk image.
 
 use anyhow::{bail, Context, Result};
-use std::path::Path;
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::File;
+use std::io::{LineWriter, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
 
+use crate::arch::Arch;
+use crate::artifact::live_profile::{read_iso_profile, LiveProfile, PROFILE_MARKER};
+use crate::artifact::uki::live_entries_with;
+use crate::artifact::{CmdlineEdit, InitramfsCompression, IsoLabel};
+use crate::boot_history::{self, iso_sha512, Baseline, BootRecord, BOOT_HISTORY_FILENAME};
+use crate::cmd_args::qemu_opts;
+use crate::paths::OutputPaths;
+use crate::term::{self, Fields, Status};
 use distro_builder::process::Cmd;
-use distro_builder::qemu::{find_ovmf, QemuBuilder, SerialOutput};
+use distro_builder::qemu::SerialOutput;
 use distro_spec::acorn::{
     ISO_FILENAME, QEMU_CPU_MODE, QEMU_DISK_FILENAME, QEMU_DISK_GB, QEMU_MEMORY_GB, QEMU_SERIAL_LOG,
 };
 
+/// Netdev id of the guest's user-mode network.
+pub const USER_NETDEV_ID: &str = "net0";
+
+/// Virtual CPUs when `--cpus` is not given.
+const DEFAULT_CPUS: u32 = 4;
+
+/// Default host port for `acornos run --ssh`.
+pub const DEFAULT_SSH_PORT: u16 = 2222;
+
+/// Default 9p mount tag for `--share` without an explicit tag.
+pub const DEFAULT_SHARE_TAG: &str = "hostshare";
+
+/// A host TCP port forwarded to the guest (`--publish host:guest`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortForward {
+    pub host: u16,
+    pub guest: u16,
+}
+
+impl PortForward {
+    /// `hostfwd` option for a user-mode netdev.
+    pub fn hostfwd(&self) -> String {
+        format!("hostfwd=tcp::{}-:{}", self.host, self.guest)
+    }
+}
+
+impl fmt::Display for PortForward {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.host, self.guest)
+    }
+}
+
+impl FromStr for PortForward {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (host, guest) = s
+            .split_once(':')
+            .with_context(|| format!("Invalid port mapping '{}' (expected HOST:GUEST)", s))?;
+        let port = |p: &str| -> Result<u16> {
+            match p.trim().parse::<u16>() {
+                Ok(0) | Err(_) => bail!("Invalid port '{}' in '{}' (expected 1-65535)", p, s),
+                Ok(port) => Ok(port),
+            }
+        };
+        Ok(Self {
+            host: port(host)?,
+            guest: port(guest)?,
+        })
+    }
+}
+
+/// A host directory exported to the guest over 9p (`--share dir[:tag]`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VirtfsShare {
+    pub path: PathBuf,
+    pub tag: String,
+}
+
+impl VirtfsShare {
+    /// `-virtfs` option value.
+    pub fn virtfs(&self) -> OsString {
+        let mut value = qemu_opts("local,path=", &self.path);
+        value.push(format!(
+            ",mount_tag={},security_model=mapped-xattr",
+            self.tag
+        ));
+        value
+    }
+
+    /// Command to mount the share inside the guest.
+    pub fn guest_mount_command(&self) -> String {
+        format!(
+            "mkdir -p /mnt/{tag} && mount -t 9p -o trans=virtio,version=9p2000.L {tag} /mnt/{tag}",
+            tag = self.tag
+        )
+    }
+}
+
+impl FromStr for VirtfsShare {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (path, tag) = match s.rsplit_once(':') {
+            Some((path, tag)) if !path.is_empty() => (path, tag),
+            _ => (s, DEFAULT_SHARE_TAG),
+        };
+        // 9p mount tags are limited to 31 bytes; commas would break -virtfs parsing
+        if tag.is_empty()
+            || tag.len() > 31
+            || !tag
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            bail!("Invalid share tag '{}' (1-31 chars of [A-Za-z0-9_-])", tag);
+        }
+        let path = PathBuf::from(path);
+        if !path.is_dir() {
+            bail!("Share directory not found: {}", path.display());
+        }
+        Ok(Self {
+            path: path.canonicalize()?,
+            tag: tag.to_string(),
+        })
+    }
+}
+
+/// Smallest guest memory AcornOS boots with.
+pub const MIN_MEMORY_MB: u64 = 256;
+
+/// Guest memory (`--memory 512M`, `--memory 2G`; a bare number is MiB).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemorySize {
+    pub mb: u64,
+}
+
+impl fmt::Display for MemorySize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}M", self.mb)
+    }
+}
+
+impl FromStr for MemorySize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let text = s.trim();
+        let (digits, factor) = match text.char_indices().last() {
+            Some((i, 'G' | 'g')) => (&text[..i], 1024),
+            Some((i, 'M' | 'm')) => (&text[..i], 1),
+            _ => (text, 1),
+        };
+        let mb = digits
+            .parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(factor))
+            .with_context(|| format!("Invalid memory size '{}' (expected e.g. 512M or 2G)", s))?;
+        if mb < MIN_MEMORY_MB {
+            bail!(
+                "Memory size {} is below the {}M minimum",
+                s.trim(),
+                MIN_MEMORY_MB
+            );
+        }
+        Ok(Self { mb })
+    }
+}
+
+/// Guest resources and extra QEMU arguments for `run` and `test`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QemuResources {
+    /// Guest memory (default from distro-spec).
+    pub memory: Option<MemorySize>,
+    /// Virtual CPUs (default 4).
+    pub cpus: Option<u32>,
+    /// Appended to the QEMU command line as-is (`--qemu-arg`, repeatable).
+    pub extra_args: Vec<String>,
+    /// Run under TCG even when KVM is usable (`--no-kvm`).
+    pub no_kvm: bool,
+    /// Print the final QEMU command line.
+    pub verbose: bool,
+}
+
+impl QemuResources {
+    pub fn validate(&self) -> Result<()> {
+        if self.cpus == Some(0) {
+            bail!("--cpus must be at least 1");
+        }
+        Ok(())
+    }
+
+    /// With `verbose`, print `cmd` as a shell command line.
+    pub(crate) fn print(&self, cmd: &Command) {
+        if self.verbose {
+            println!("  QEMU command: {}", command_line(cmd));
+        }
+    }
+}
+
+/// `cmd` as a copy-pasteable shell command line.
+pub fn command_line(cmd: &Command) -> String {
+    let quote = |arg: &std::ffi::OsStr| {
+        let arg = arg.to_string_lossy();
+        let safe = !arg.is_empty()
+            && arg
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+        if safe {
+            arg.into_owned()
+        } else {
+            format!("'{}'", arg.replace('\'', "'\\''"))
+        }
+    };
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(quote)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Options for `acornos run`.
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Disk image size for a newly created virtual disk (default from distro-spec).
+    pub disk_size: Option<String>,
+    /// Discard all disk writes when QEMU exits (`-snapshot`).
+    pub snapshot: bool,
+    /// Delete and recreate the virtual disk before booting.
+    pub fresh_disk: bool,
+    /// Boot with the disks of this `disks.toml` profile instead.
+    pub profile: Option<String>,
+    pub forwards: Vec<PortForward>,
+    pub shares: Vec<VirtfsShare>,
+    /// Guest architecture (and so QEMU binary and firmware).
+    pub arch: Arch,
+    /// Memory, CPUs and extra QEMU arguments.
+    pub resources: QemuResources,
+    /// Forward this host port (or a free one, if taken) to the guest's sshd.
+    pub ssh_port: Option<u16>,
+}
+
+impl RunOptions {
+    /// Reject conflicting forwards/shares before QEMU launches.
+    pub fn validate(&self) -> Result<()> {
+        self.resources.validate()?;
+        if let Some(size) = &self.disk_size {
+            validate_disk_size(size)?;
+        }
+        for (i, fwd) in self.forwards.iter().enumerate() {
+            if self.forwards[..i].iter().any(|f| f.host == fwd.host) {
+                bail!("Host port {} is published more than once", fwd.host);
+            }
+        }
+        for (i, share) in self.shares.iter().enumerate() {
+            if self.shares[..i].iter().any(|s| s.tag == share.tag) {
+                bail!("Share tag '{}' is used more than once", share.tag);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `preferred` if it is free on the host, else a free port picked by the OS.
+///
+/// QEMU's hostfwd listens on all interfaces, so that is what is tested.
+pub fn pick_host_port(preferred: u16) -> Result<u16> {
+    if TcpListener::bind(("0.0.0.0", preferred)).is_ok() {
+        return Ok(preferred);
+    }
+    let listener = TcpListener::bind(("0.0.0.0", 0)).context("Failed to find a free host port")?;
+    Ok(listener.local_addr()?.port())
+}
+
+/// Reject disk sizes `qemu-img create` would not accept (`20G`, `512M`, bytes).
+pub fn validate_disk_size(size: &str) -> Result<()> {
+    let digits = size.trim_end_matches(['K', 'M', 'G', 'T', 'k', 'm', 'g', 't']);
+    let valid = size.len() - digits.len() <= 1
+        && !digits.is_empty()
+        && digits.chars().all(|c| c.is_ascii_digit())
+        && digits.parse::<u64>().is_ok_and(|n| n > 0);
+    if !valid {
+        bail!(
+            "Invalid disk size '{}' (expected a number with an optional K/M/G/T suffix, e.g. 40G)",
+            size
+        );
+    }
+    Ok(())
+}
+
+/// Create the virtual disk if missing; with `fresh`, recreate it first.
+///
+/// Returns whether a new disk was created.
+pub fn prepare_disk(disk_path: &Path, size: &str, fresh: bool) -> Result<bool> {
+    if fresh && disk_path.exists() {
+        println!("  Removing previous virtual disk (--fresh-disk)");
+        std::fs::remove_file(disk_path)
+            .with_context(|| format!("Failed to remove {}", disk_path.display()))?;
+    }
+    if disk_path.exists() {
+        return Ok(false);
+    }
+    println!("  Creating {} virtual disk...", size);
+    Cmd::new("qemu-img")
+        .args(["create", "-f", "qcow2"])
+        .arg_path(disk_path)
+        .arg(size)
+        .error_msg("qemu-img create failed. Install: sudo dnf install qemu-img")
+        .run()?;
+    Ok(true)
+}
+
+/// Boot firmware for `acornos test --firmware`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Firmware {
+    #[default]
+    Uefi,
+    /// QEMU's SeaBIOS; needs an ISO built with `--bios` (x86_64 only).
+    Bios,
+}
+
+impl fmt::Display for Firmware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Uefi => "uefi",
+            Self::Bios => "bios",
+        })
+    }
+}
+
+impl FromStr for Firmware {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "uefi" => Ok(Self::Uefi),
+            "bios" => Ok(Self::Bios),
+            other => bail!("Unknown firmware '{}' (expected uefi or bios)", other),
+        }
+    }
+}
+
+/// Size of a pflash-padded UEFI image (AAVMF_CODE.fd); smaller ones use `-bios`.
+const PFLASH_SIZE: u64 = 64 * 1024 * 1024;
+
+/// KVM device QEMU accelerates with.
+const KVM_DEVICE: &str = "/dev/kvm";
+
+/// Accelerator QEMU runs the guest with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Accel {
+    /// Hardware virtualization through /dev/kvm.
+    Kvm,
+    /// Software emulation.
+    Tcg,
+}
+
+impl fmt::Display for Accel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Kvm => "kvm",
+            Self::Tcg => "tcg",
+        })
+    }
+}
+
+/// Whether this user can use KVM for a guest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KvmAccess {
+    /// /dev/kvm opens read-write.
+    Usable,
+    /// The guest is not the host's arch.
+    ForeignArch,
+    /// No /dev/kvm (no virtualization, or the module is not loaded).
+    Missing,
+    /// /dev/kvm exists but this user may not open it (not in the kvm group).
+    Denied,
+    /// /dev/kvm failed to open for another reason.
+    Failed(String),
+}
+
+impl KvmAccess {
+    /// Classify the result of opening /dev/kvm read-write.
+    pub fn from_open(result: std::io::Result<File>) -> Self {
+        match result {
+            Ok(_) => Self::Usable,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::Missing,
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => Self::Denied,
+            Err(e) => Self::Failed(e.to_string()),
+        }
+    }
+}
+
+/// Whether KVM is usable for `arch` here. Opens /dev/kvm as QEMU would:
+/// it existing is not enough.
+pub fn kvm_access(arch: Arch) -> KvmAccess {
+    if !arch.is_host() {
+        return KvmAccess::ForeignArch;
+    }
+    KvmAccess::from_open(
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(KVM_DEVICE),
+    )
+}
+
+/// Accelerator for `arch`: KVM when usable, TCG without it or with
+/// `no_kvm`. A /dev/kvm this user cannot open is an error naming the fix,
+/// where QEMU would only fail with a bare permission error.
+pub fn select_accel(arch: Arch, no_kvm: bool) -> Result<Accel> {
+    if no_kvm {
+        return Ok(Accel::Tcg);
+    }
+    match kvm_access(arch) {
+        KvmAccess::Usable => Ok(Accel::Kvm),
+        KvmAccess::ForeignArch | KvmAccess::Missing => Ok(Accel::Tcg),
+        KvmAccess::Denied => bail!(
+            "{} exists but you may not open it.\n\
+             Add yourself to the kvm group and log in again:\n  \
+             sudo usermod -aG kvm $USER\n\
+             or pass --no-kvm to run without acceleration (much slower).",
+            KVM_DEVICE
+        ),
+        KvmAccess::Failed(e) => bail!(
+            "Cannot open {}: {}\nPass --no-kvm to run without acceleration (much slower).",
+            KVM_DEVICE,
+            e
+        ),
+    }
+}
+
+/// UEFI firmware for `arch`, or an error with install instructions.
+pub fn find_firmware(arch: Arch) -> Result<PathBuf> {
+    arch.find_firmware().with_context(|| {
+        format!(
+            "{} UEFI firmware not found. AcornOS requires UEFI boot.\n{}",
+            arch,
+            arch.firmware_hint()
+        )
+    })
+}
+
+/// A qcow2 disk attached to the VM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VmDisk {
+    pub path: PathBuf,
+    /// Shown to the guest as the virtio serial (`/dev/disk/by-id/virtio-<serial>`).
+    pub serial: Option<String>,
+}
+
+/// `-drive`/`-device` arguments attaching `disks` as virtio disks, in order
+/// (`disk0` is the guest's first virtio disk).
+pub fn disk_args(disks: &[VmDisk]) -> Vec<OsString> {
+    let mut args = Vec::new();
+    for (index, disk) in disks.iter().enumerate() {
+        let mut device = format!("virtio-blk-pci,drive=disk{}", index);
+        if let Some(serial) = &disk.serial {
+            device.push_str(&format!(",serial={}", serial));
+        }
+        args.push("-drive".into());
+        args.push(qemu_opts(
+            &format!("if=none,id=disk{},format=qcow2,file=", index),
+            &disk.path,
+        ));
+        args.push("-device".into());
+        args.push(device.into());
+    }
+    args
+}
+
+/// One QEMU invocation. [`VmConfig::command`] renders the whole command
+/// line from these fields, so forwards, shares and resources land where
+/// they belong instead of being patched into a built argv.
+///
+/// aarch64 needs `-machine virt` and a virtio-scsi CD-ROM; disks, network,
+/// serial and everything after are attached the same way on both arches.
+pub(crate) struct VmConfig {
+    pub arch: Arch,
+    pub accel: Accel,
+    /// Boot CD-ROM; without one the firmware boots from `disks`.
+    pub iso: Option<PathBuf>,
+    pub disks: Vec<VmDisk>,
+    pub serial: SerialOutput,
+    /// UEFI firmware; SeaBIOS when `None` (x86_64 only).
+    pub firmware: Option<PathBuf>,
+    /// Show a display; without one QEMU runs headless and exits on reboot.
+    pub gui: bool,
+    /// Host ports forwarded on the user-mode netdev.
+    pub forwards: Vec<PortForward>,
+    pub shares: Vec<VirtfsShare>,
+    /// Discard all disk writes when QEMU exits.
+    pub snapshot: bool,
+    /// `-kernel`/`-initrd`/`-append` for a direct kernel boot.
+    pub boot_args: Vec<OsString>,
+    /// Memory and CPUs; the extra arguments go last.
+    pub resources: QemuResources,
+}
+
+impl VmConfig {
+    pub(crate) fn new(arch: Arch, accel: Accel, serial: SerialOutput) -> Self {
+        Self {
+            arch,
+            accel,
+            iso: None,
+            disks: Vec::new(),
+            serial,
+            firmware: None,
+            gui: false,
+            forwards: Vec::new(),
+            shares: Vec::new(),
+            snapshot: false,
+            boot_args: Vec::new(),
+            resources: QemuResources::default(),
+        }
+    }
+
+    /// QEMU arguments, without the binary.
+    pub(crate) fn args(&self) -> Vec<OsString> {
+        let mut args: Vec<OsString> = Vec::new();
+        let push = |args: &mut Vec<OsString>, items: &[&str]| {
+            args.extend(items.iter().map(OsString::from))
+        };
+
+        if self.arch == Arch::Aarch64 {
+            push(&mut args, &["-machine", "virt"]);
+        }
+        match self.accel {
+            Accel::Kvm => push(&mut args, &["-accel", "kvm", "-cpu", QEMU_CPU_MODE]),
+            Accel::Tcg => push(&mut args, &["-cpu", "max"]),
+        }
+        let memory = self
+            .resources
+            .memory
+            .map_or_else(|| format!("{}G", QEMU_MEMORY_GB), |m| m.to_string());
+        let cpus = self.resources.cpus.unwrap_or(DEFAULT_CPUS).to_string();
+        push(&mut args, &["-m", &memory, "-smp", &cpus]);
+
+        if let Some(firmware) = &self.firmware {
+            // OVMF's code image always maps as pflash; AAVMF only when padded
+            let pflash = self.arch == Arch::X86_64
+                || std::fs::metadata(firmware).is_ok_and(|m| m.len() == PFLASH_SIZE);
+            if pflash {
+                args.push("-drive".into());
+                args.push(qemu_opts(
+                    "if=pflash,format=raw,readonly=on,file=",
+                    firmware,
+                ));
+            } else {
+                args.push("-bios".into());
+                args.push(firmware.into());
+            }
+        }
+
+        if let Some(iso) = &self.iso {
+            if self.arch == Arch::X86_64 {
+                args.push("-cdrom".into());
+                args.push(iso.into());
+            } else {
+                push(
+                    &mut args,
+                    &["-device", "virtio-scsi-pci,id=scsi0", "-drive"],
+                );
+                args.push(qemu_opts(
+                    "if=none,id=cd0,media=cdrom,readonly=on,file=",
+                    iso,
+                ));
+                push(&mut args, &["-device", "scsi-cd,drive=cd0,bootindex=0"]);
+            }
+        }
+        args.extend(disk_args(&self.disks));
+
+        let mut netdev = format!("user,id={}", USER_NETDEV_ID);
+        for fwd in &self.forwards {
+            netdev.push(',');
+            netdev.push_str(&fwd.hostfwd());
+        }
+        let device = format!("virtio-net-pci,netdev={}", USER_NETDEV_ID);
+        push(&mut args, &["-netdev", &netdev, "-device", &device]);
+
+        match (self.gui, self.arch) {
+            (true, Arch::X86_64) => push(&mut args, &["-vga", "virtio"]),
+            (true, Arch::Aarch64) => push(
+                &mut args,
+                &[
+                    "-device",
+                    "virtio-gpu-pci",
+                    "-device",
+                    "qemu-xhci",
+                    "-device",
+                    "usb-kbd",
+                    "-device",
+                    "usb-tablet",
+                ],
+            ),
+            (false, _) => push(&mut args, &["-display", "none", "-no-reboot"]),
+        }
+        match &self.serial {
+            SerialOutput::Stdio => push(&mut args, &["-serial", "stdio"]),
+            SerialOutput::File(path) => push(&mut args, &["-serial", &format!("file:{}", path)]),
+        }
+        for share in &self.shares {
+            args.push("-virtfs".into());
+            args.push(share.virtfs());
+        }
+        if self.snapshot {
+            args.push("-snapshot".into());
+        }
+        args.extend(self.boot_args.iter().cloned());
+        args.extend(self.resources.extra_args.iter().map(OsString::from));
+        args
+    }
+
+    /// The QEMU command for this VM.
+    pub(crate) fn command(&self) -> Command {
+        let mut cmd = Command::new(self.arch.qemu_binary());
+        cmd.args(self.args());
+        cmd
+    }
+}
+
 /// Run the ISO in QEMU GUI.
 pub fn run_iso(base_dir: &Path, disk_size: Option<String>) -> Result<()> {
-    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
+    run_iso_with(
+        base_dir,
+        &RunOptions {
+            disk_size,
+            ..Default::default()
+        },
+    )
+}
+
+/// Run the ISO in QEMU GUI with debugging options.
+pub fn run_iso_with(base_dir: &Path, options: &RunOptions) -> Result<()> {
+    let mut options = options.clone();
+    let ssh_port = match options.ssh_port {
+        Some(preferred) => {
+            let port = pick_host_port(preferred)?;
+            if port != preferred {
+                log::warn!(
+                    "Host port {} is in use; SSH uses port {} instead",
+                    preferred,
+                    port
+                );
+            }
+            options.forwards.push(PortForward {
+                host: port,
+                guest: 22,
+            });
+            Some(port)
+        }
+        None => None,
+    };
+    options.validate()?;
+
+    let output_dir = crate::paths::output_dir(base_dir);
     let iso_path = output_dir.join(ISO_FILENAME);
 
     if !iso_path.exists() {
@@ -24,58 +677,2014 @@ pub fn run_iso(base_dir: &Path, disk_size: Option<String>) -> Result<()> {
     }
 
     println!("Running ISO in QEMU GUI...");
-    println!("  ISO: {}", iso_path.display());
+    // Printed once the disks are prepared, so the values line up
+    let mut fields = Fields::new();
+    fields.row("ISO", iso_path.display().to_string());
+    if options.arch != Arch::X86_64 {
+        fields.row(
+            "Arch",
+            format!("{} ({})", options.arch, options.arch.qemu_binary()),
+        );
+    }
+
+    let accel = select_accel(options.arch, options.resources.no_kvm)?;
+    match accel {
+        Accel::Kvm => fields.row("Acceleration", "KVM (hardware virtualization)"),
+        Accel::Tcg => fields.row("Acceleration", "TCG (software emulation - slower)"),
+    };
+
+    // Always include a virtual disk, or the profile's disks
+    let disks = match &options.profile {
+        Some(name) => {
+            let profiles = crate::disks::DiskProfiles::load(base_dir)?;
+            profiles
+                .get(name)?
+                .prepare(&output_dir, options.fresh_disk)?
+        }
+        None => {
+            let size = options
+                .disk_size
+                .clone()
+                .unwrap_or_else(|| format!("{}G", QEMU_DISK_GB));
+            let disk_path = output_dir.join(QEMU_DISK_FILENAME);
+            let created = prepare_disk(&disk_path, &size, options.fresh_disk)?;
+            if !created && options.disk_size.is_some() {
+                log::warn!(
+                    "Keeping the existing disk at its size; use --fresh-disk to recreate it"
+                );
+            }
+            vec![VmDisk {
+                path: disk_path,
+                serial: None,
+            }]
+        }
+    };
+
+    for disk in &disks {
+        let serial = disk
+            .serial
+            .as_ref()
+            .map(|s| format!(", serial {}", s))
+            .unwrap_or_default();
+        if options.snapshot {
+            fields.row(
+                "Disk",
+                format!(
+                    "{}{} (snapshot: writes are discarded on exit)",
+                    disk.path.display(),
+                    serial
+                ),
+            );
+        } else {
+            fields.row("Disk", format!("{}{}", disk.path.display(), serial));
+        }
+    }
+
+    let firmware = find_firmware(options.arch)?;
+    fields.row("Boot", format!("UEFI ({})", firmware.display()));
+
+    for fwd in &options.forwards {
+        fields.row(
+            "Forward",
+            format!("host tcp/{} -> guest tcp/{}", fwd.host, fwd.guest),
+        );
+    }
+    for share in &options.shares {
+        fields.row(
+            "Share",
+            format!("{} (tag {})", share.path.display(), share.tag),
+        );
+        fields.row("", format!("in the guest: {}", share.guest_mount_command()));
+    }
+    fields.print(2);
+
+    let serial = SerialOutput::File(output_dir.join(QEMU_SERIAL_LOG).display().to_string());
+    let mut cmd = VmConfig {
+        iso: Some(iso_path),
+        disks,
+        firmware: Some(firmware),
+        gui: true,
+        forwards: options.forwards.clone(),
+        shares: options.shares.clone(),
+        snapshot: options.snapshot,
+        resources: options.resources.clone(),
+        ..VmConfig::new(options.arch, accel, serial)
+    }
+    .command();
+    options.resources.print(&cmd);
+    let mut child = cmd.spawn().with_context(|| {
+        format!(
+            "Failed to run {}. Is QEMU installed?",
+            options.arch.qemu_binary()
+        )
+    })?;
+    if let Some(port) = ssh_port {
+        let login = format!("ssh -p {} root@localhost", port);
+        println!("\n{}", term::boxed(&["SSH available:", &login]));
+        if !crate::artifact::live_ssh::overlay_has_ssh(
+            &OutputPaths::new(&output_dir).live_overlay(),
+        ) {
+            log::warn!(
+                "The ISO was built without --ssh, so root has no authorized_keys.\n  \
+                 Rebuild it with 'acornos iso --ssh'."
+            );
+        }
+    }
+    let status = child.wait()?;
+
+    if !status.success() {
+        bail!("QEMU exited with status: {}", status);
+    }
+
+    Ok(())
+}
+
+/// Serial marker emitted by `00-acorn-test.sh` once the test shell is usable.
+pub const SHELL_READY_MARKER: &str = "___SHELL_READY___";
+
+/// Serial output that means the firmware reached the boot manager.
+const UEFI_PATTERNS: &[&str] = &["BdsDxe", "UEFI firmware", "systemd-boot", "GNU GRUB"];
+
+/// Serial output that means the kernel started.
+const KERNEL_PATTERNS: &[&str] = &["Linux version"];
+
+/// Serial output that means userspace init started.
+const INIT_PATTERNS: &[&str] = &["Run /init as init process", "OpenRC"];
+
+/// Serial output that means OpenRC started (after any initramfs init).
+const OPENRC_PATTERN: &str = "OpenRC";
+
+/// First line of the diagnostics the live initramfs prints before its
+/// emergency shell (also in `/run/initramfs-failure.log`).
+pub const INITRAMFS_EMERGENCY_MARKER: &str = "___INITRAMFS_EMERGENCY___";
+
+/// Last line of the initramfs diagnostics.
+pub const INITRAMFS_DIAGNOSTICS_END: &str = "___INITRAMFS_DIAGNOSTICS_END___";
+
+/// How long to keep reading initramfs diagnostics without their end line.
+const DIAGNOSTICS_GRACE: Duration = Duration::from_secs(5);
+
+/// Serial output that means the boot is not going to recover.
+const FAILURE_PATTERNS: &[&str] = &["Kernel panic", "not syncing", "emergency shell"];
+
+/// Prompt of the installed system's serial getty (`agetty -L ... ttyS0`).
+const LOGIN_PROMPT: &str = "login:";
+
+/// Serial output that means an installed system is not going to come up:
+/// the live failures plus GRUB's rescue shell.
+const INSTALLED_FAILURE_PATTERNS: &[&str] = &[
+    "Kernel panic",
+    "not syncing",
+    "emergency shell",
+    "grub rescue>",
+];
+
+/// Prompts printed without a newline, passed on as lines when the serial
+/// output stops at one (getty's prompt would otherwise never arrive).
+const PROMPT_ENDINGS: &[&str] = &[LOGIN_PROMPT, "Password:", "grub rescue>"];
+
+/// Serial marker echoed by the installed system's shell once logged in.
+pub const LOGGED_IN_MARKER: &str = "___LOGGED_IN___";
+
+/// Pause after each typed login line, for getty and login to read it.
+const LOGIN_LINE_DELAY: Duration = Duration::from_secs(1);
+
+/// How long to wait for [`LOGGED_IN_MARKER`] before typing its echo again
+/// (login may flush what was typed while it started the shell).
+const LOGIN_RETRY: Duration = Duration::from_secs(5);
+
+/// OpenRC's report of a service that did not start
+/// (` * ERROR: chronyd failed to start`).
+const SERVICE_FAILURE: (&str, &str) = ("ERROR: ", " failed to start");
+
+/// Budget multiplier when /dev/kvm is absent (TCG software emulation).
+pub const TCG_BUDGET_FACTOR: u32 = 3;
+
+/// Serial lines kept for failure context.
+const RECENT_LINES: usize = 20;
+
+/// File in the output directory receiving the JSON test summary.
+pub const BOOT_TEST_SUMMARY: &str = "boot-test-summary.json";
+
+/// Serial marker printed in the guest right before the verify script runs.
+pub const VERIFY_START_MARKER: &str = "___VERIFY_START___";
+
+/// Serial marker carrying the verify script's exit code (`___VERIFY_EXIT:<code>___`).
+pub const VERIFY_EXIT_PREFIX: &str = "___VERIFY_EXIT:";
+
+/// Checks run in the guest before any `--verify-script`. The live user,
+/// when the ISO has one, must get root through doas without a password.
+const BUILTIN_CHECKS: &str = r#"if id live >/dev/null 2>&1; then
+    uid=$(su -s /bin/sh -c 'doas -n id -u' live)
+    if [ "$uid" != 0 ]; then
+        echo "FAIL: 'doas id -u' as live printed '$uid', expected 0"
+        exit 1
+    fi
+    echo "OK: doas gives live root"
+fi
+"#;
+
+/// Checks run in an installed system before any `--verify-script`: it is
+/// the installed system that booted, with its root writable and everything
+/// in its fstab mounted. `acornos` is the live hostname (OS_ID), which
+/// acorn-install also offers as the default, so a tested install must pick
+/// another.
+const INSTALLED_CHECKS: &str = r#"if ! touch /.acorn-test-rw 2>/dev/null; then
+    echo "FAIL: root filesystem is read-only"
+    exit 1
+fi
+rm -f /.acorn-test-rw
+echo "OK: root filesystem is writable"
+host=$(hostname)
+if [ "$host" = acornos ]; then
+    echo "FAIL: hostname is the live default '$host' (also the installer's; install with another)"
+    exit 1
+fi
+echo "OK: hostname $host"
+awk '$1 !~ /^#/ && $2 ~ /^\// && $3 != "swap" && $4 !~ /noauto/ { print $2 }' /etc/fstab |
+while read -r mnt; do
+    if ! mountpoint -q "$mnt"; then
+        echo "FAIL: $mnt from /etc/fstab is not mounted"
+        exit 1
+    fi
+    echo "OK: $mnt mounted"
+done || exit 1
+"#;
+
+/// Where the verify script is written in the guest.
+const VERIFY_SCRIPT_GUEST_PATH: &str = "/tmp/acorn-verify.sh";
+
+/// Heredoc terminator used to stream the verify script.
+const VERIFY_SCRIPT_EOF: &str = "___VERIFY_EOF___";
+
+/// Pause between lines typed into the guest, so the tty never overflows.
+const VERIFY_LINE_DELAY: Duration = Duration::from_millis(10);
+
+/// Default file in the output directory receiving the full test serial log.
+pub const TEST_SERIAL_LOG: &str = "test-serial.log";
+
+/// Boot stages observed on the serial console, in boot order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootStage {
+    Uefi,
+    Kernel,
+    Init,
+    ShellReady,
+}
+
+impl BootStage {
+    /// Classify a serial line, if it marks a stage. `ready` are the lines
+    /// that mean the system is up.
+    fn detect(line: &str, ready: &[&str]) -> Option<Self> {
+        let matches = |patterns: &[&str]| patterns.iter().any(|p| line.contains(p));
+        if matches(ready) {
+            Some(Self::ShellReady)
+        } else if matches(INIT_PATTERNS) {
+            Some(Self::Init)
+        } else if matches(KERNEL_PATTERNS) {
+            Some(Self::Kernel)
+        } else if matches(UEFI_PATTERNS) {
+            Some(Self::Uefi)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Uefi => "UEFI",
+            Self::Kernel => "kernel",
+            Self::Init => "init",
+            Self::ShellReady => "shell ready",
+        }
+    }
+}
+
+/// Per-stage boot time budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BootBudget {
+    /// Power-on to boot manager.
+    pub uefi: Duration,
+    /// Boot manager to kernel start.
+    pub kernel: Duration,
+    /// Init start to shell ready.
+    pub init_to_shell: Duration,
+    /// Power-on to shell ready.
+    pub total: Duration,
+}
+
+impl Default for BootBudget {
+    /// Budget for a KVM-accelerated boot.
+    fn default() -> Self {
+        Self {
+            uefi: Duration::from_secs(10),
+            kernel: Duration::from_secs(15),
+            init_to_shell: Duration::from_secs(20),
+            total: Duration::from_secs(60),
+        }
+    }
+}
+
+impl BootBudget {
+    /// Apply `stage=secs` overrides, e.g. `kernel=20,total=90`.
+    pub fn with_overrides(mut self, spec: &str) -> Result<Self> {
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (stage, secs) = entry
+                .split_once('=')
+                .with_context(|| format!("Invalid boot budget '{}': expected stage=secs", entry))?;
+            let secs: u64 = secs
+                .trim()
+                .parse()
+                .with_context(|| format!("Invalid boot budget '{}': not a number", entry))?;
+            let slot = match stage.trim() {
+                "uefi" => &mut self.uefi,
+                "kernel" => &mut self.kernel,
+                "init" | "init_to_shell" => &mut self.init_to_shell,
+                "total" => &mut self.total,
+                other => bail!(
+                    "Unknown boot stage '{}' (expected uefi, kernel, init_to_shell, total)",
+                    other
+                ),
+            };
+            *slot = Duration::from_secs(secs);
+        }
+        Ok(self)
+    }
+
+    /// Scale the budget for the host: unchanged on KVM, multiplied on TCG.
+    pub fn for_host(self, kvm: bool) -> Self {
+        if kvm {
+            return self;
+        }
+        Self {
+            uefi: self.uefi * TCG_BUDGET_FACTOR,
+            kernel: self.kernel * TCG_BUDGET_FACTOR,
+            init_to_shell: self.init_to_shell * TCG_BUDGET_FACTOR,
+            total: self.total * TCG_BUDGET_FACTOR,
+        }
+    }
+}
+
+/// Measured duration of one budgeted stage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageTiming {
+    pub name: &'static str,
+    pub measured: Option<Duration>,
+    pub budget: Duration,
+}
+
+impl StageTiming {
+    /// Stage completed, but slower than its budget.
+    pub fn exceeded(&self) -> bool {
+        self.measured.is_some_and(|m| m > self.budget)
+    }
+}
+
+/// Records when each boot stage was first seen on the serial console.
+#[derive(Debug, Clone)]
+pub struct BootStageTracker {
+    /// Serial output that means the system is up.
+    ready: &'static [&'static str],
+    uefi: Option<Duration>,
+    kernel: Option<Duration>,
+    init: Option<Duration>,
+    openrc: Option<Duration>,
+    shell: Option<Duration>,
+}
+
+impl Default for BootStageTracker {
+    fn default() -> Self {
+        Self::with_ready(&[SHELL_READY_MARKER])
+    }
+}
+
+impl BootStageTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tracker for a system that is up once a line contains one of `ready`.
+    pub fn with_ready(ready: &'static [&'static str]) -> Self {
+        Self {
+            ready,
+            uefi: None,
+            kernel: None,
+            init: None,
+            openrc: None,
+            shell: None,
+        }
+    }
+
+    /// Feed a serial line seen `elapsed` after power-on.
+    ///
+    /// Returns the stage if this line reached it for the first time.
+    pub fn observe(&mut self, line: &str, elapsed: Duration) -> Option<BootStage> {
+        if self.openrc.is_none() && line.contains(OPENRC_PATTERN) {
+            self.openrc = Some(elapsed);
+        }
+        let stage = BootStage::detect(line, self.ready)?;
+        let slot = match stage {
+            BootStage::Uefi => &mut self.uefi,
+            BootStage::Kernel => &mut self.kernel,
+            BootStage::Init => &mut self.init,
+            BootStage::ShellReady => &mut self.shell,
+        };
+        if slot.is_some() {
+            return None;
+        }
+        *slot = Some(elapsed);
+        Some(stage)
+    }
+
+    pub fn shell_ready(&self) -> bool {
+        self.shell.is_some()
+    }
+
+    /// Power-on to the kernel, OpenRC and the ready shell, for the boot
+    /// history.
+    pub fn milestones(&self) -> [Option<Duration>; 3] {
+        [self.kernel, self.openrc, self.shell]
+    }
+
+    /// Last stage reached, if any.
+    pub fn last_stage(&self) -> Option<BootStage> {
+        [
+            (BootStage::ShellReady, self.shell),
+            (BootStage::Init, self.init),
+            (BootStage::Kernel, self.kernel),
+            (BootStage::Uefi, self.uefi),
+        ]
+        .into_iter()
+        .find(|(_, t)| t.is_some())
+        .map(|(stage, _)| stage)
+    }
+
+    /// Stage durations measured against `budget`.
+    pub fn timings(&self, budget: &BootBudget) -> Vec<StageTiming> {
+        let between = |from: Option<Duration>, to: Option<Duration>| match (from, to) {
+            (Some(from), Some(to)) => Some(to.saturating_sub(from)),
+            _ => None,
+        };
+        vec![
+            StageTiming {
+                name: "uefi",
+                measured: self.uefi,
+                budget: budget.uefi,
+            },
+            StageTiming {
+                name: "kernel",
+                measured: between(self.uefi, self.kernel),
+                budget: budget.kernel,
+            },
+            StageTiming {
+                name: "init_to_shell",
+                measured: between(self.init, self.shell),
+                budget: budget.init_to_shell,
+            },
+            StageTiming {
+                name: "total",
+                measured: self.shell,
+                budget: budget.total,
+            },
+        ]
+    }
+}
+
+/// Print the stage-timing table.
+pub fn print_stage_table(timings: &[StageTiming]) {
+    println!(
+        "\n  {:<14} {:>9} {:>9}  Status",
+        "Stage", "Measured", "Budget"
+    );
+    for t in timings {
+        let measured = t
+            .measured
+            .map(|m| format!("{:.1}s", m.as_secs_f64()))
+            .unwrap_or_else(|| "-".to_string());
+        let status = match t.measured {
+            None => term::paint(Status::Skip, "not reached"),
+            Some(_) if t.exceeded() => term::paint(Status::Fail, "OVER"),
+            Some(_) => term::paint(Status::Ok, "ok"),
+        };
+        println!(
+            "  {:<14} {:>9} {:>8}s  {}",
+            t.name,
+            measured,
+            t.budget.as_secs(),
+            status
+        );
+    }
+}
+
+/// Render the JSON test summary consumed by CI.
+pub fn summary_json(passed: bool, accel: Accel, enforced: bool, timings: &[StageTiming]) -> String {
+    let stages: Vec<String> = timings
+        .iter()
+        .map(|t| {
+            let measured = t
+                .measured
+                .map(|m| format!("{:.3}", m.as_secs_f64()))
+                .unwrap_or_else(|| "null".to_string());
+            format!(
+                "    \"{}\": {{ \"seconds\": {}, \"budget_seconds\": {}, \"within_budget\": {} }}",
+                t.name,
+                measured,
+                t.budget.as_secs(),
+                t.measured.is_some() && !t.exceeded()
+            )
+        })
+        .collect();
+    format!(
+        "{{\n  \"passed\": {},\n  \"accelerator\": \"{}\",\n  \"kvm\": {},\n  \"budget_enforced\": {},\n  \"stages\": {{\n{}\n  }}\n}}\n",
+        passed,
+        accel,
+        accel == Accel::Kvm,
+        enforced,
+        stages.join(",\n")
+    )
+}
+
+/// Options for `acornos test`.
+#[derive(Debug, Clone)]
+pub struct TestOptions {
+    /// Overall timeout in seconds.
+    pub timeout: u64,
+    /// Per-stage budgets (KVM seconds).
+    pub budget: BootBudget,
+    /// Fail when a stage exceeds its budget.
+    pub enforce_budget: bool,
+    /// Guest architecture.
+    pub arch: Arch,
+    /// Full serial log destination (default: [`TEST_SERIAL_LOG`] in the output dir).
+    pub log_path: Option<PathBuf>,
+    /// Shell script run in the guest once the shell is ready.
+    pub verify_script: Option<PathBuf>,
+    /// Boot through UEFI (default) or legacy BIOS.
+    pub firmware: Firmware,
+    /// Memory, CPUs and extra QEMU arguments.
+    pub resources: QemuResources,
+    /// Fail when shell-ready regresses past this baseline.
+    pub baseline: Option<Baseline>,
+    /// Attach the disks of this `disks.toml` profile (none by default).
+    pub profile: Option<String>,
+    /// Kernel cmdline edits. Non-empty, the built kernel and live initramfs
+    /// are booted directly (`-kernel`/`-append`) with the default entry's
+    /// cmdline edited, instead of through the ISO's boot menu, so the ISO
+    /// need not be rebuilt. Options of the other entries (e.g. `emergency`,
+    /// `loglevel=7` of the debug entry) must be appended explicitly.
+    pub cmdline: CmdlineEdit,
+    /// Boot this qcow2 of an installed system instead of the ISO: UEFI, no
+    /// CD-ROM, ready at the serial login prompt. Booted with `-snapshot`,
+    /// so the test leaves the image as it was.
+    pub disk: Option<PathBuf>,
+    /// Root password of the installed system on `disk`, if it has one.
+    pub root_password: Option<String>,
+}
+
+impl Default for TestOptions {
+    fn default() -> Self {
+        Self {
+            timeout: 120,
+            budget: BootBudget::default(),
+            enforce_budget: false,
+            arch: Arch::default(),
+            log_path: None,
+            verify_script: None,
+            firmware: Firmware::Uefi,
+            resources: QemuResources::default(),
+            baseline: None,
+            profile: None,
+            cmdline: CmdlineEdit::default(),
+            disk: None,
+            root_password: None,
+        }
+    }
+}
+
+/// Cmdline of the default live entry for a direct kernel boot, with the
+/// consoles of the GRUB menus and `edit` applied.
+pub fn direct_boot_cmdline(arch: Arch, label: &str, edit: &CmdlineEdit) -> String {
+    let consoles = [
+        "tty0".to_string(),
+        format!("{},115200", arch.serial_console()),
+    ];
+    live_entries_with(edit)
+        .first()
+        .map(|entry| entry.cmdline(label, &consoles))
+        .unwrap_or_default()
+}
+
+/// QEMU arguments booting the built kernel and live initramfs directly, for
+/// testing cmdline edits without rebuilding the ISO (which stays attached
+/// for the live initramfs to find by label).
+fn direct_boot_args(output_dir: &Path, arch: Arch, edit: &CmdlineEdit) -> Result<Vec<OsString>> {
+    let paths = OutputPaths::new(output_dir);
+    let kernel = paths.kernel();
+    let initramfs = paths.live_initramfs(InitramfsCompression::load(output_dir));
+    for (what, path) in [("Kernel", &kernel), ("Live initramfs", &initramfs)] {
+        if !path.exists() {
+            bail!(
+                "{} not found at {} (needed to boot with --cmdline-append).
+                 Run 'acornos build' first.",
+                what,
+                path.display()
+            );
+        }
+    }
+    let label = IsoLabel::load(output_dir);
+    Ok(vec![
+        "-kernel".into(),
+        kernel.into(),
+        "-initrd".into(),
+        initramfs.into(),
+        "-append".into(),
+        direct_boot_cmdline(arch, label.as_str(), edit).into(),
+    ])
+}
+
+/// Create (truncating) the serial log, with its parent directory.
+///
+/// Line-buffered, so every complete line is on disk even if QEMU or the
+/// test is killed.
+pub fn open_serial_log(path: &Path) -> Result<LineWriter<File>> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    Ok(LineWriter::new(file))
+}
+
+/// Shell input that runs `script` in the guest between the verify markers.
+///
+/// The script is written to a file through a heredoc, one line at a time,
+/// then run with `sh`. The markers are split with `""` so the tty's echo
+/// of the typed commands never matches them.
+pub fn verify_script_input(script: &str) -> Result<Vec<String>> {
+    if script.lines().any(|l| l.trim() == VERIFY_SCRIPT_EOF) {
+        bail!(
+            "Verify script must not contain a '{}' line",
+            VERIFY_SCRIPT_EOF
+        );
+    }
+    let (start_head, start_tail) = VERIFY_START_MARKER.split_at(9);
+    let (exit_head, exit_tail) = VERIFY_EXIT_PREFIX.split_at(9);
+
+    let mut lines = vec![format!(
+        "cat > {} <<'{}'",
+        VERIFY_SCRIPT_GUEST_PATH, VERIFY_SCRIPT_EOF
+    )];
+    lines.extend(script.lines().map(str::to_string));
+    lines.push(VERIFY_SCRIPT_EOF.to_string());
+    lines.push(format!(
+        "echo '{}''{}'; sh {} 2>&1; echo \"{}\"\"{}$?___\"",
+        start_head, start_tail, VERIFY_SCRIPT_GUEST_PATH, exit_head, exit_tail
+    ));
+    Ok(lines)
+}
+
+/// Exit code from a `___VERIFY_EXIT:<code>___` line.
+pub fn parse_verify_exit(line: &str) -> Option<i32> {
+    let rest = &line[line.find(VERIFY_EXIT_PREFIX)? + VERIFY_EXIT_PREFIX.len()..];
+    rest.split_once("___")?.0.parse().ok()
+}
+
+/// Service named in an OpenRC "failed to start" line.
+pub fn failed_service(line: &str) -> Option<&str> {
+    let (prefix, suffix) = SERVICE_FAILURE;
+    let rest = &line[line.find(prefix)? + prefix.len()..];
+    let service = rest.strip_suffix(suffix)?.trim();
+    (!service.is_empty() && !service.contains(' ')).then_some(service)
+}
+
+/// Failure reason for initramfs diagnostics: the `reason:` line.
+fn initramfs_failure(diagnostics: &[String]) -> String {
+    let reason = diagnostics
+        .iter()
+        .find_map(|l| l.strip_prefix("reason: "))
+        .unwrap_or("no reason given");
+    format!("initramfs dropped to its emergency shell: {}", reason)
+}
+
+/// Likely causes read from initramfs diagnostics: a label no device has,
+/// no block devices at all, modules missing from the initramfs.
+pub fn diagnostics_hints(diagnostics: &[String]) -> Vec<String> {
+    let mut hints = Vec::new();
+    let mut section = "";
+    let mut label = None;
+    let mut partitions = 0;
+    let mut blkid = Vec::new();
+    for line in diagnostics {
+        if let Some(name) = line.strip_prefix("--- ") {
+            section = name;
+            continue;
+        }
+        match section {
+            "" => {
+                if let Some(l) = line.strip_prefix("searched label: ") {
+                    label = Some(l.trim());
+                }
+            }
+            "modules" => {
+                if let Some(module) = line.trim().strip_suffix(": not-found") {
+                    hints.push(format!("module '{}' is not in the initramfs", module));
+                }
+            }
+            "/proc/partitions" => {
+                // major minor #blocks name
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                if fields.len() == 4 && fields[0].parse::<u32>().is_ok() {
+                    partitions += 1;
+                }
+            }
+            "blkid" => blkid.push(line.as_str()),
+            _ => {}
+        }
+    }
+    if partitions == 0 {
+        hints.push(
+            "the kernel sees no block devices (storage driver missing from the initramfs?)"
+                .to_string(),
+        );
+    } else if let Some(label) = label.filter(|l| !l.is_empty()) {
+        let quoted = format!("LABEL=\"{}\"", label);
+        if !blkid.iter().any(|l| l.contains(&quoted)) {
+            hints.push(format!(
+                "no device is labeled '{}' (check the ISO volume label and root=LABEL= on the cmdline)",
+                label
+            ));
+        }
+    }
+    hints
+}
+
+/// Result of a verify script run.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct VerifyOutcome {
+    /// Exit code, or `None` if no exit marker arrived before the deadline.
+    pub exit_code: Option<i32>,
+    /// Serial lines between the start and exit markers.
+    pub output: Vec<String>,
+}
+
+/// Type `lines` into the guest's serial console, pausing `delay` after each.
+pub fn type_lines(stdin: &mut dyn Write, lines: &[String], delay: Duration) -> Result<()> {
+    for line in lines {
+        writeln!(stdin, "{}", line).context("Failed to write to the guest serial console")?;
+        stdin.flush()?;
+        std::thread::sleep(delay);
+    }
+    Ok(())
+}
+
+/// Type `input` into the guest and collect serial lines from `rx` until the
+/// exit marker or `deadline`. Every line is also written to `log`.
+pub fn run_verify_script(
+    stdin: &mut dyn Write,
+    rx: &mpsc::Receiver<String>,
+    input: &[String],
+    deadline: Instant,
+    log: &mut dyn Write,
+) -> Result<VerifyOutcome> {
+    type_lines(stdin, input, VERIFY_LINE_DELAY)?;
+
+    let mut outcome = VerifyOutcome::default();
+    let mut started = false;
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        let Ok(line) = rx.recv_timeout(remaining) else {
+            break;
+        };
+        let _ = writeln!(log, "{}", line);
+
+        if let Some(code) = parse_verify_exit(&line) {
+            outcome.exit_code = Some(code);
+            break;
+        }
+        if line.contains(VERIFY_START_MARKER) {
+            started = true;
+        } else if started {
+            outcome.output.push(line);
+        }
+    }
+    Ok(outcome)
+}
+
+/// Splits the guest's serial output into lines.
+#[derive(Debug, Default)]
+pub struct SerialLines {
+    pending: Vec<u8>,
+}
+
+impl SerialLines {
+    /// Lines completed by `chunk`, then the rest if it ends in a prompt.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        let mut lines = Vec::new();
+        for &byte in chunk {
+            if byte == b'\n' {
+                lines.push(self.take());
+            } else {
+                self.pending.push(byte);
+            }
+        }
+        let pending = String::from_utf8_lossy(&self.pending);
+        if PROMPT_ENDINGS
+            .iter()
+            .any(|p| pending.trim_end().ends_with(p))
+        {
+            lines.push(self.take());
+        }
+        lines
+    }
+
+    fn take(&mut self) -> String {
+        let line = String::from_utf8_lossy(&self.pending)
+            .trim_end()
+            .to_string();
+        self.pending.clear();
+        line
+    }
+}
 
-    let kvm_available = std::path::Path::new("/dev/kvm").exists();
-    if kvm_available {
-        println!("  Acceleration: KVM (hardware virtualization)");
+/// What the boot test waits for on the serial console and checks once the
+/// system is up, for the live ISO or an installed system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootWatcher {
+    /// Serial output that means the system is up.
+    pub ready: &'static [&'static str],
+    /// Serial output that means the boot is not going to recover.
+    pub failures: &'static [&'static str],
+    /// Lines typed once the system is up, before the checks (a login).
+    pub login: Vec<String>,
+    /// Checks run in the guest before any `--verify-script`.
+    pub checks: &'static str,
+}
+
+/// Serial lines seen until the system was up, failed, or the deadline.
+#[derive(Debug)]
+pub struct WatchOutcome {
+    pub tracker: BootStageTracker,
+    /// Why the boot failed, if a failure pattern or service failure showed.
+    pub failure: Option<String>,
+    /// The last [`RECENT_LINES`] lines, for failure context.
+    pub recent: VecDeque<String>,
+    /// The initramfs diagnostics, between [`INITRAMFS_EMERGENCY_MARKER`]
+    /// and [`INITRAMFS_DIAGNOSTICS_END`], if the initramfs gave up.
+    pub diagnostics: Vec<String>,
+}
+
+impl BootWatcher {
+    /// The live ISO: the test autologin prints [`SHELL_READY_MARKER`].
+    pub fn live() -> Self {
+        Self {
+            ready: &[SHELL_READY_MARKER],
+            failures: FAILURE_PATTERNS,
+            login: Vec::new(),
+            checks: BUILTIN_CHECKS,
+        }
+    }
+
+    /// An installed system: up at the serial getty's login prompt, then
+    /// logged in as root (with `password`, if root has one).
+    pub fn installed(password: Option<&str>) -> Self {
+        let mut login = vec!["root".to_string()];
+        login.extend(password.map(str::to_string));
+        Self {
+            ready: &[LOGIN_PROMPT],
+            failures: INSTALLED_FAILURE_PATTERNS,
+            login,
+            checks: INSTALLED_CHECKS,
+        }
+    }
+
+    /// Watch serial lines from `rx` until the system is up, a failure
+    /// shows, or `timeout` after `start`. Every line is also written to `log`.
+    pub fn watch(
+        &self,
+        rx: &mpsc::Receiver<String>,
+        start: Instant,
+        timeout: Duration,
+        log: &mut dyn Write,
+    ) -> WatchOutcome {
+        let mut tracker = BootStageTracker::with_ready(self.ready);
+        let mut recent: VecDeque<String> = VecDeque::with_capacity(RECENT_LINES);
+        let mut failure = None;
+        let mut diagnostics: Option<Vec<String>> = None;
+
+        while !tracker.shell_ready() && failure.is_none() {
+            let mut remaining = match timeout.checked_sub(start.elapsed()) {
+                Some(r) => r,
+                None => break,
+            };
+            if diagnostics.is_some() {
+                remaining = remaining.min(DIAGNOSTICS_GRACE);
+            }
+            let line = match rx.recv_timeout(remaining) {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if let Err(e) = writeln!(log, "{}", line) {
+                log::warn!("Failed to write the serial log: {}", e);
+            }
+
+            if let Some(stage) = tracker.observe(&line, start.elapsed()) {
+                println!(
+                    "  [{:>5.1}s] {}",
+                    start.elapsed().as_secs_f64(),
+                    stage.name()
+                );
+            }
+            if let Some(lines) = diagnostics.as_mut() {
+                // The block quotes the cmdline and devices; no patterns in it
+                if line.contains(INITRAMFS_DIAGNOSTICS_END) {
+                    failure = Some(initramfs_failure(lines));
+                } else {
+                    lines.push(line.trim_end().to_string());
+                }
+            } else if line.contains(INITRAMFS_EMERGENCY_MARKER) {
+                diagnostics = Some(Vec::new());
+            } else {
+                if let Some(pattern) = self.failures.iter().find(|p| line.contains(*p)) {
+                    failure = Some(format!("'{}' on serial console", pattern));
+                }
+                if let Some(service) = failed_service(line.trim_end()) {
+                    failure = Some(format!("service '{}' failed to start", service));
+                }
+            }
+
+            if recent.len() == RECENT_LINES {
+                recent.pop_front();
+            }
+            recent.push_back(line);
+        }
+        if let (None, Some(lines)) = (&failure, &diagnostics) {
+            failure = Some(initramfs_failure(lines));
+        }
+        let diagnostics = diagnostics.unwrap_or_default();
+        WatchOutcome {
+            tracker,
+            failure,
+            recent,
+            diagnostics,
+        }
+    }
+
+    /// Type the login lines into the guest, then echo [`LOGGED_IN_MARKER`]
+    /// until the shell prints it or `deadline`. Returns whether it did; a
+    /// watcher without login lines is logged in already.
+    pub fn log_in(
+        &self,
+        stdin: &mut dyn Write,
+        rx: &mpsc::Receiver<String>,
+        deadline: Instant,
+        log: &mut dyn Write,
+    ) -> Result<bool> {
+        if self.login.is_empty() {
+            return Ok(true);
+        }
+        type_lines(stdin, &self.login, LOGIN_LINE_DELAY)?;
+
+        // Split with "" so the tty's echo of the command never matches
+        let (head, tail) = LOGGED_IN_MARKER.split_at(9);
+        let echo = format!("echo '{}''{}'", head, tail);
+        while Instant::now() < deadline {
+            writeln!(stdin, "{}", echo).context("Failed to write to the guest serial console")?;
+            stdin.flush()?;
+            let retry = (Instant::now() + LOGIN_RETRY).min(deadline);
+            while let Some(remaining) = retry.checked_duration_since(Instant::now()) {
+                let line = match rx.recv_timeout(remaining) {
+                    Ok(line) => line,
+                    Err(mpsc::RecvTimeoutError::Timeout) => break,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(false),
+                };
+                let _ = writeln!(log, "{}", line);
+                if line.contains(LOGGED_IN_MARKER) {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// Log in, then run `input` (from [`verify_script_input`]) in the
+    /// guest until `deadline`, reporting progress relative to `start`.
+    /// Returns why the checks failed, with the script's output, or `None`
+    /// when they passed; `timeout` is only for the messages.
+    pub fn run_checks(
+        &self,
+        vm: &mut SerialVm,
+        input: &[String],
+        start: Instant,
+        deadline: Instant,
+        timeout: u64,
+        log: &mut dyn Write,
+    ) -> Result<Option<(String, Vec<String>)>> {
+        let SerialVm { stdin, rx, .. } = vm;
+        if !self.login.is_empty() {
+            println!("  [{:>5.1}s] login", start.elapsed().as_secs_f64());
+        }
+        if !self.log_in(stdin, rx, deadline, log)? {
+            return Ok(Some((
+                format!(
+                    "no shell after logging in as root within the {}s timeout \
+                     (wrong --root-password?)",
+                    timeout
+                ),
+                Vec::new(),
+            )));
+        }
+        println!("  [{:>5.1}s] verify script", start.elapsed().as_secs_f64());
+        let outcome = run_verify_script(stdin, rx, input, deadline, log)?;
+        let reason = match outcome.exit_code {
+            Some(0) => return Ok(None),
+            Some(code) => format!("verify script exited with {}", code),
+            None => format!(
+                "verify script did not finish within the {}s timeout",
+                timeout
+            ),
+        };
+        Ok(Some((reason, outcome.output)))
+    }
+}
+
+/// A headless VM whose serial console is the QEMU process's stdio: typed
+/// into through `stdin`, read line by line from `rx`. Killed on drop.
+pub struct SerialVm {
+    child: Child,
+    pub stdin: ChildStdin,
+    pub rx: mpsc::Receiver<String>,
+}
+
+impl SerialVm {
+    /// Spawn `cmd` (a [`VmConfig`] command with [`SerialOutput::Stdio`]) and
+    /// start reading its serial output.
+    pub fn spawn(mut cmd: Command, arch: Arch) -> Result<Self> {
+        cmd.stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+        let mut child = cmd
+            .spawn()
+            .with_context(|| format!("Failed to run {}. Is QEMU installed?", arch.qemu_binary()))?;
+        let stdin = child.stdin.take().context("QEMU stdin not captured")?;
+        let stdout = child.stdout.take().context("QEMU stdout not captured")?;
+
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut stdout = stdout;
+            let mut lines = SerialLines::default();
+            let mut buf = [0u8; 4096];
+            while let Ok(n @ 1..) = stdout.read(&mut buf) {
+                for line in lines.push(&buf[..n]) {
+                    if tx.send(line).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(Self { child, stdin, rx })
+    }
+
+    /// Wait for QEMU to exit (a guest power-off under `-no-reboot`) until
+    /// `deadline`, passing serial lines on to `log`. `None` if it is still
+    /// running.
+    pub fn wait_exit(
+        &mut self,
+        deadline: Instant,
+        log: &mut dyn Write,
+    ) -> Result<Option<ExitStatus>> {
+        loop {
+            while let Ok(line) = self.rx.try_recv() {
+                let _ = writeln!(log, "{}", line);
+            }
+            if let Some(status) = self.child.try_wait()? {
+                // The reader thread is done once stdout closed
+                for line in self.rx.try_iter() {
+                    let _ = writeln!(log, "{}", line);
+                }
+                return Ok(Some(status));
+            }
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            std::thread::sleep(Duration::from_millis(200));
+        }
+    }
+}
+
+impl Drop for SerialVm {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Refuse a release ISO: without the testing profile's serial autologin and
+/// markers, a headless test could only time out.
+pub fn require_testing_iso(iso_path: &Path, output_dir: &Path) -> Result<()> {
+    if !iso_path.exists() {
+        bail!(
+            "ISO not found at {}. Run 'acornos iso' first.",
+            iso_path.display()
+        );
+    }
+    let scratch = output_dir.join(format!("{}.tmp", PROFILE_MARKER));
+    match read_iso_profile(iso_path, &scratch) {
+        Ok(LiveProfile::Testing) => {}
+        Ok(LiveProfile::Release) => bail!(
+            "{} is a release ISO: no serial autologin or test markers, so the \
+             test could only time out.\n\
+             Run 'acornos iso --profile testing' first.",
+            iso_path.display()
+        ),
+        Err(e) => log::warn!("Could not read the ISO's profile: {:#}", e),
+    }
+    Ok(())
+}
+
+/// Boot the ISO (or, with `disk`, an installed system) headless and watch
+/// the serial console until the system is up.
+///
+/// Stage timings are always reported and written to [`BOOT_TEST_SUMMARY`];
+/// budget overruns only fail the test when `enforce_budget` is set. Every
+/// serial line is written to the serial log. The watcher's checks, then
+/// any verify script, run in the guest and a nonzero exit fails the test.
+pub fn test_iso(base_dir: &Path, options: &TestOptions) -> Result<()> {
+    let TestOptions {
+        timeout,
+        budget,
+        enforce_budget,
+        arch,
+        firmware,
+        ..
+    } = *options;
+    let output_dir = crate::paths::output_dir(base_dir);
+    let iso_path = output_dir.join(ISO_FILENAME);
+    let log_path = options
+        .log_path
+        .clone()
+        .unwrap_or_else(|| output_dir.join(TEST_SERIAL_LOG));
+
+    let watcher = match &options.disk {
+        Some(disk) => {
+            if !disk.is_file() {
+                bail!("Disk image not found at {}", disk.display());
+            }
+            if firmware == Firmware::Bios {
+                bail!("--disk boots UEFI only (the installer sets up an EFI GRUB)");
+            }
+            if !options.cmdline.is_empty() {
+                bail!("--cmdline-append boots the live kernel and cannot be used with --disk");
+            }
+            if options.baseline.is_some() {
+                bail!("--baseline compares live ISO boots and cannot be used with --disk");
+            }
+            BootWatcher::installed(options.root_password.as_deref())
+        }
+        None => {
+            require_testing_iso(&iso_path, &output_dir)?;
+            BootWatcher::live()
+        }
+    };
+
+    options.resources.validate()?;
+    let accel = select_accel(arch, options.resources.no_kvm)?;
+    let kvm = accel == Accel::Kvm;
+    let budget = budget.for_host(kvm);
+    // A TCG boot rarely fits the KVM timeout, any more than its budgets
+    let timeout = if kvm {
+        timeout
     } else {
-        println!("  Acceleration: TCG (software emulation - slower)");
+        timeout * u64::from(TCG_BUDGET_FACTOR)
+    };
+
+    // Printed before QEMU starts, so the values line up
+    let mut fields = Fields::new();
+    match &options.disk {
+        Some(disk) => {
+            println!(
+                "Testing installed system boot (headless, timeout {}s)...",
+                timeout
+            );
+            fields.row("Disk", format!("{} (snapshot)", disk.display()));
+        }
+        None => {
+            println!("Testing ISO boot (headless, timeout {}s)...", timeout);
+            fields.row("ISO", iso_path.display().to_string());
+        }
+    }
+    if arch != Arch::X86_64 {
+        fields.row("Arch", format!("{} ({})", arch, arch.qemu_binary()));
     }
+    match accel {
+        Accel::Kvm => fields.row("Acceleration", "KVM"),
+        Accel::Tcg => fields.row(
+            "Acceleration",
+            format!(
+                "TCG (timeout and budgets x{} for software emulation)",
+                TCG_BUDGET_FACTOR
+            ),
+        ),
+    };
 
-    let mut builder = QemuBuilder::new(QEMU_CPU_MODE, QEMU_MEMORY_GB)
-        .cdrom(iso_path.clone())
-        .vga("virtio")
-        .serial_output(SerialOutput::File(
-            output_dir.join(QEMU_SERIAL_LOG).display().to_string(),
-        ));
+    let mut script = watcher.checks.to_string();
+    if let Some(path) = &options.verify_script {
+        script += &std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read verify script {}", path.display()))?;
+        fields.row("Verify script", path.display().to_string());
+    }
+    let verify_input = verify_script_input(&script)?;
+
+    fields.row("Serial log", log_path.display().to_string());
+    let mut log = open_serial_log(&log_path)?;
+
+    let firmware = match firmware {
+        Firmware::Uefi => Some(find_firmware(arch)?),
+        Firmware::Bios if arch != Arch::X86_64 => {
+            bail!("--firmware bios is x86_64-only ({} boots UEFI)", arch)
+        }
+        Firmware::Bios => {
+            fields.row("Boot", "BIOS (SeaBIOS; the ISO must be built with --bios)");
+            None
+        }
+    };
+    // The installed system's disk comes first, for the firmware to boot
+    let mut disks: Vec<VmDisk> = options
+        .disk
+        .iter()
+        .map(|path| VmDisk {
+            path: path.clone(),
+            serial: None,
+        })
+        .collect();
+    if let Some(name) = &options.profile {
+        let profiles = crate::disks::DiskProfiles::load(base_dir)?;
+        let profile_disks = profiles.get(name)?.prepare(&output_dir, false)?;
+        fields.row(
+            "Disks",
+            format!("profile '{}' ({} disks)", name, profile_disks.len()),
+        );
+        disks.extend(profile_disks);
+    }
+    let mut config = VmConfig {
+        iso: options.disk.is_none().then(|| iso_path.clone()),
+        disks,
+        firmware,
+        snapshot: options.disk.is_some(),
+        resources: options.resources.clone(),
+        ..VmConfig::new(arch, accel, SerialOutput::Stdio)
+    };
+    if !options.cmdline.is_empty() {
+        let args = direct_boot_args(&output_dir, arch, &options.cmdline)?;
+        fields.row(
+            "Boot",
+            format!(
+                "kernel directly, cmdline: {}",
+                args.last().map(|a| a.to_string_lossy()).unwrap_or_default()
+            ),
+        );
+        config.boot_args = args;
+    }
+    let cmd = config.command();
+    fields.print(2);
+    options.resources.print(&cmd);
+    let mut vm = SerialVm::spawn(cmd, arch)?;
 
-    // Always include a virtual disk
-    let size = disk_size.unwrap_or_else(|| format!("{}G", QEMU_DISK_GB));
-    let disk_path = output_dir.join(QEMU_DISK_FILENAME);
+    let start = Instant::now();
+    let deadline = Duration::from_secs(timeout);
+    let WatchOutcome {
+        tracker,
+        mut failure,
+        mut recent,
+        diagnostics,
+    } = watcher.watch(&vm.rx, start, deadline, &mut log);
 
-    if !disk_path.exists() {
-        println!("  Creating {} virtual disk...", size);
-        Cmd::new("qemu-img")
-            .args(["create", "-f", "qcow2"])
-            .arg_path(&disk_path)
-            .arg(&size)
-            .error_msg("qemu-img create failed. Install: sudo dnf install qemu-img")
-            .run()?;
+    if failure.is_none() && tracker.shell_ready() {
+        if let Some((reason, output)) = watcher.run_checks(
+            &mut vm,
+            &verify_input,
+            start,
+            start + deadline,
+            timeout,
+            &mut log,
+        )? {
+            if !output.is_empty() {
+                recent = output.into_iter().collect();
+            }
+            failure = Some(reason);
+        }
     }
+    drop(vm);
 
-    println!("  Disk: {}", disk_path.display());
-    builder = builder.disk(disk_path);
+    let passed = failure.is_none() && tracker.shell_ready();
+    let timings = tracker.timings(&budget);
+    print_stage_table(&timings);
 
-    let ovmf_path = find_ovmf().context(
-        "OVMF firmware not found. AcornOS requires UEFI boot.\n\
-         Install OVMF:\n\
-         - Fedora/RHEL: sudo dnf install edk2-ovmf\n\
-         - Debian/Ubuntu: sudo apt install ovmf\n\
-         - Arch: sudo pacman -S edk2-ovmf",
-    )?;
+    let summary = output_dir.join(BOOT_TEST_SUMMARY);
+    std::fs::write(
+        &summary,
+        summary_json(passed, accel, enforce_budget, &timings),
+    )
+    .with_context(|| format!("Failed to write {}", summary.display()))?;
+    println!("\n  Summary: {}", summary.display());
 
-    println!("  Boot: UEFI ({})", ovmf_path.display());
-    builder = builder.uefi(ovmf_path);
+    // The history tracks boots of the ISO
+    if options.disk.is_none() {
+        let record = BootRecord::now(iso_sha512(&iso_path), tracker.milestones(), passed, kvm);
+        match boot_history::append(&output_dir, &record) {
+            Ok(()) => println!(
+                "  History: {}",
+                output_dir.join(BOOT_HISTORY_FILENAME).display()
+            ),
+            Err(e) => log::warn!("{:#}", e),
+        }
+    }
 
-    let status = builder
-        .build()
-        .status()
-        .context("Failed to run qemu-system-x86_64. Is QEMU installed?")?;
+    if !passed {
+        let reason = failure.unwrap_or_else(|| {
+            let last = tracker.last_stage().map(|s| s.name()).unwrap_or("power-on");
+            format!("timed out after {}s (last stage: {})", timeout, last)
+        });
+        if !diagnostics.is_empty() {
+            let hints: String = diagnostics_hints(&diagnostics)
+                .iter()
+                .map(|h| format!("\n  - {}", h))
+                .collect();
+            bail!(
+                "Boot test failed: {}\n\nInitramfs diagnostics:\n  {}\n{}\nfull log: {}",
+                reason,
+                diagnostics.join("\n  "),
+                if hints.is_empty() {
+                    String::new()
+                } else {
+                    format!("\nLikely causes:{}\n", hints)
+                },
+                log_path.display()
+            );
+        }
+        bail!(
+            "Boot test failed: {}\n\nLast serial output:\n  {}\n\nfull log: {}",
+            reason,
+            recent.make_contiguous().join("\n  "),
+            log_path.display()
+        );
+    }
 
-    if !status.success() {
-        bail!("QEMU exited with status: {}", status);
+    let over: Vec<&str> = timings
+        .iter()
+        .filter(|t| t.exceeded())
+        .map(|t| t.name)
+        .collect();
+    if !over.is_empty() {
+        if enforce_budget {
+            bail!(
+                "Boot budget exceeded: {} (full log: {})",
+                over.join(", "),
+                log_path.display()
+            );
+        }
+        log::warn!(
+            "Boot budget exceeded: {} (pass --enforce-boot-budget to fail)",
+            over.join(", ")
+        );
     }
 
+    if let (Some(baseline), Some(shell)) = (options.baseline, tracker.milestones()[2]) {
+        baseline.check(shell)?;
+        println!(
+            "  Baseline: shell ready after {:.1}s (limit {:.1}s)",
+            shell.as_secs_f64(),
+            baseline.limit()
+        );
+    }
+
+    println!(
+        "\nBoot test passed (accelerator: {}, full log: {}).",
+        accel,
+        log_path.display()
+    );
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CANNED_SERIAL: &[(u64, &str)] = &[
+        (1, "BdsDxe: loading Boot0001 \"UEFI QEMU DVD-ROM\""),
+        (2, "BdsDxe: starting Boot0001"),
+        (4, "[    0.000000] Linux version 6.12.1 (builder@acorn)"),
+        (6, "[    1.200000] Run /init as init process"),
+        (7, "   OpenRC 0.55 is starting up Linux 6.12.1"),
+        (9, "acorn login: root (automatic login)"),
+        (10, "___SHELL_READY___"),
+        (11, "___PROMPT___"),
+    ];
+
+    fn replay(lines: &[(u64, &str)]) -> (BootStageTracker, Vec<BootStage>) {
+        let mut tracker = BootStageTracker::new();
+        let reached = lines
+            .iter()
+            .filter_map(|(t, line)| tracker.observe(line, Duration::from_secs(*t)))
+            .collect();
+        (tracker, reached)
+    }
+
+    #[test]
+    fn test_tracker_canned_boot() {
+        let (tracker, reached) = replay(CANNED_SERIAL);
+        assert_eq!(
+            reached,
+            vec![
+                BootStage::Uefi,
+                BootStage::Kernel,
+                BootStage::Init,
+                BootStage::ShellReady
+            ]
+        );
+        assert!(tracker.shell_ready());
+
+        let timings = tracker.timings(&BootBudget::default());
+        let secs: Vec<Option<u64>> = timings
+            .iter()
+            .map(|t| t.measured.map(|m| m.as_secs()))
+            .collect();
+        assert_eq!(secs, vec![Some(1), Some(3), Some(4), Some(10)]);
+        assert!(timings.iter().all(|t| !t.exceeded()));
+        assert_eq!(
+            tracker.milestones().map(|m| m.map(|m| m.as_secs())),
+            [Some(4), Some(7), Some(10)]
+        );
+    }
+
+    #[test]
+    fn test_tracker_budget_exceeded_and_incomplete() {
+        let (tracker, _) = replay(&[
+            (2, "BdsDxe: starting Boot0001"),
+            (5, "Linux version 6.12.1"),
+            (6, "Run /init as init process"),
+            (30, SHELL_READY_MARKER),
+        ]);
+        let over: Vec<&str> = tracker
+            .timings(&BootBudget::default())
+            .into_iter()
+            .filter(|t| t.exceeded())
+            .map(|t| t.name)
+            .collect();
+        assert_eq!(over, vec!["init_to_shell"]);
+
+        // TCG scaling absorbs the same boot
+        let tcg = BootBudget::default().for_host(false);
+        assert!(tracker.timings(&tcg).iter().all(|t| !t.exceeded()));
+
+        let (partial, _) = replay(&CANNED_SERIAL[..3]);
+        assert_eq!(partial.last_stage(), Some(BootStage::Kernel));
+        assert!(partial.timings(&BootBudget::default())[3]
+            .measured
+            .is_none());
+    }
+
+    #[test]
+    fn test_watcher_installed_boot() {
+        let lines = [
+            "BdsDxe: starting Boot0001 \"UEFI Misc Device\"",
+            "   OpenRC 0.55 is starting up Linux 6.12.1",
+            // The live marker is not what an installed system waits for
+            SHELL_READY_MARKER,
+            "Welcome to AcornOS",
+            "myhost login:",
+            "never read",
+        ];
+        let (tx, rx) = mpsc::channel();
+        for line in lines {
+            tx.send(line.to_string()).unwrap();
+        }
+        let watcher = BootWatcher::installed(None);
+        let mut log = Vec::new();
+        let outcome = watcher.watch(&rx, Instant::now(), Duration::from_secs(5), &mut log);
+        assert!(outcome.failure.is_none());
+        assert!(outcome.tracker.shell_ready());
+        assert_eq!(
+            outcome.recent.back().map(String::as_str),
+            Some("myhost login:")
+        );
+        assert_eq!(rx.recv().unwrap(), "never read");
+        assert_eq!(String::from_utf8(log).unwrap().lines().count(), 5);
+
+        // The live watcher stops at the marker
+        for line in lines {
+            tx.send(line.to_string()).unwrap();
+        }
+        let outcome =
+            BootWatcher::live().watch(&rx, Instant::now(), Duration::from_secs(5), &mut Vec::new());
+        assert_eq!(
+            outcome.recent.back().map(String::as_str),
+            Some(SHELL_READY_MARKER)
+        );
+
+        assert_eq!(rx.try_iter().count(), 3);
+
+        tx.send("error: no such partition.".to_string()).unwrap();
+        tx.send("grub rescue> ".to_string()).unwrap();
+        let outcome = watcher.watch(&rx, Instant::now(), Duration::from_secs(5), &mut Vec::new());
+        assert_eq!(
+            outcome.failure.as_deref(),
+            Some("'grub rescue>' on serial console")
+        );
+        assert!(!outcome.tracker.shell_ready());
+    }
+
+    #[test]
+    fn test_watcher_initramfs_emergency() {
+        let lines = [
+            "initramfs: ERROR: Could not find boot device with /live/filesystem.erofs",
+            INITRAMFS_EMERGENCY_MARKER,
+            "reason: Could not find boot device with /live/filesystem.erofs",
+            "searched label: ACORNOS",
+            "cmdline: console=ttyS0 root=LABEL=ACORNOS",
+            "--- modules",
+            "  virtio_blk: loaded",
+            "  sr_mod: not-found",
+            "--- /proc/partitions",
+            "major minor  #blocks  name",
+            "",
+            " 254        0    1048576 vda",
+            "--- blkid",
+            "/dev/vda: LABEL=\"OTHER\" TYPE=\"iso9660\"",
+            INITRAMFS_DIAGNOSTICS_END,
+            "initramfs: Dropping to emergency shell. Type 'exit' to retry boot.",
+        ];
+        let (tx, rx) = mpsc::channel();
+        for line in lines {
+            tx.send(line.to_string()).unwrap();
+        }
+        let outcome =
+            BootWatcher::live().watch(&rx, Instant::now(), Duration::from_secs(5), &mut Vec::new());
+        assert_eq!(
+            outcome.failure.as_deref(),
+            Some(
+                "initramfs dropped to its emergency shell: \
+                 Could not find boot device with /live/filesystem.erofs"
+            )
+        );
+        assert_eq!(outcome.diagnostics.len(), 12);
+        assert_eq!(rx.try_iter().count(), 1);
+        assert_eq!(
+            diagnostics_hints(&outcome.diagnostics),
+            [
+                "module 'sr_mod' is not in the initramfs",
+                "no device is labeled 'ACORNOS' (check the ISO volume label and \
+                 root=LABEL= on the cmdline)",
+            ]
+        );
+
+        // No partitions at all
+        let empty = ["--- /proc/partitions".to_string()];
+        assert_eq!(
+            diagnostics_hints(&empty),
+            ["the kernel sees no block devices (storage driver missing from the initramfs?)"]
+        );
+    }
+
+    #[test]
+    fn test_serial_lines_pass_on_prompts() {
+        let mut lines = SerialLines::default();
+        assert_eq!(
+            lines.push(b"Welcome to AcornOS\r\n\r\nmyho"),
+            ["Welcome to AcornOS", ""]
+        );
+        assert_eq!(lines.push(b"st login: "), ["myhost login:"]);
+        assert_eq!(lines.push(b"root\r\nPassword: "), ["root", "Password:"]);
+        assert!(lines.push(b"myhost:~# ").is_empty());
+        assert_eq!(lines.push(b"\n"), ["myhost:~#"]);
+    }
+
+    #[test]
+    fn test_watcher_log_in() {
+        let watcher = BootWatcher::installed(Some("secret"));
+        assert_eq!(watcher.login, ["root", "secret"]);
+        assert!(verify_script_input(watcher.checks).is_ok());
+
+        let (tx, rx) = mpsc::channel();
+        tx.send("Password: ".to_string()).unwrap();
+        tx.send("myhost:~# echo '___LOGGED''_IN___'".to_string())
+            .unwrap();
+        tx.send(LOGGED_IN_MARKER.to_string()).unwrap();
+        let mut stdin = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(30);
+        assert!(watcher
+            .log_in(&mut stdin, &rx, deadline, &mut Vec::new())
+            .unwrap());
+        assert_eq!(
+            String::from_utf8(stdin).unwrap(),
+            "root\nsecret\necho '___LOGGED''_IN___'\n"
+        );
+
+        // QEMU gone: no shell
+        drop(tx);
+        assert!(!BootWatcher::installed(None)
+            .log_in(&mut Vec::new(), &rx, deadline, &mut Vec::new())
+            .unwrap());
+        // Nothing to type for the live ISO
+        assert!(BootWatcher::live()
+            .log_in(&mut Vec::new(), &rx, deadline, &mut Vec::new())
+            .unwrap());
+    }
+
+    #[test]
+    fn test_verify_script_round_trip() {
+        let input = verify_script_input("nslookup example.org\nexit 3\n").unwrap();
+        assert_eq!(input[0], "cat > /tmp/acorn-verify.sh <<'___VERIFY_EOF___'");
+        assert_eq!(input[1..3], ["nslookup example.org", "exit 3"]);
+        assert_eq!(input[3], VERIFY_SCRIPT_EOF);
+        // The echoed command line must not look like either marker
+        let run = input.last().unwrap();
+        assert!(!run.contains(VERIFY_START_MARKER));
+        assert!(!run.contains(VERIFY_EXIT_PREFIX));
+        assert!(verify_script_input("___VERIFY_EOF___\n").is_err());
+
+        let (tx, rx) = mpsc::channel();
+        for line in [
+            run.as_str(),
+            VERIFY_START_MARKER,
+            "nslookup: can't resolve 'example.org'",
+            "___VERIFY_EXIT:3___",
+            "after",
+        ] {
+            tx.send(line.to_string()).unwrap();
+        }
+        let mut stdin = Vec::new();
+        let mut log = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let outcome = run_verify_script(&mut stdin, &rx, &input, deadline, &mut log).unwrap();
+        assert_eq!(outcome.exit_code, Some(3));
+        assert_eq!(outcome.output, ["nslookup: can't resolve 'example.org'"]);
+        assert!(String::from_utf8(stdin)
+            .unwrap()
+            .ends_with(&format!("{}\n", run)));
+        assert_eq!(parse_verify_exit("  ___VERIFY_EXIT:0___"), Some(0));
+        assert_eq!(parse_verify_exit("echo \"___VERIFY_\"\"EXIT:$?___\""), None);
+    }
+
+    #[test]
+    fn test_builtin_checks_use_the_live_user() {
+        use crate::artifact::live_user::LIVE_USER_NAME;
+
+        let user = format!("id {} ", LIVE_USER_NAME);
+        assert!(BUILTIN_CHECKS.contains(&user));
+        assert!(BUILTIN_CHECKS.contains(&format!("'doas -n id -u' {}", LIVE_USER_NAME)));
+        assert!(verify_script_input(BUILTIN_CHECKS).is_ok());
+    }
+
+    #[test]
+    fn test_failed_service() {
+        assert_eq!(
+            failed_service(" * ERROR: chronyd failed to start"),
+            Some("chronyd")
+        );
+        assert_eq!(failed_service(" * Starting sshd ... [ ok ]"), None);
+        assert_eq!(
+            failed_service(" * ERROR: cannot start sshd as net would not start"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_budget_overrides() {
+        let budget = BootBudget::default()
+            .with_overrides("kernel=20, total=90")
+            .unwrap();
+        assert_eq!(budget.kernel, Duration::from_secs(20));
+        assert_eq!(budget.total, Duration::from_secs(90));
+        assert_eq!(budget.uefi, Duration::from_secs(10));
+
+        assert!(BootBudget::default().with_overrides("bios=5").is_err());
+        assert!(BootBudget::default().with_overrides("kernel").is_err());
+    }
+
+    fn argv(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn test_vm_config_argv() {
+        let dir = tempfile::tempdir().unwrap();
+        let share: VirtfsShare = format!("{}:src", dir.path().display()).parse().unwrap();
+        let options = RunOptions {
+            disk_size: None,
+            forwards: vec!["2222:22".parse().unwrap(), "8080:80".parse().unwrap()],
+            shares: vec![share.clone()],
+            ..Default::default()
+        };
+        options.validate().unwrap();
+        assert!(share
+            .virtfs()
+            .to_string_lossy()
+            .ends_with(",mount_tag=src,security_model=mapped-xattr"));
+
+        // `acornos run`: forwards join the user netdev, extra args come last
+        let config = VmConfig {
+            iso: Some(PathBuf::from("/out/acornos.iso")),
+            disks: vec![VmDisk {
+                path: PathBuf::from("/out/disk.qcow2"),
+                serial: None,
+            }],
+            firmware: Some(PathBuf::from("/usr/share/OVMF/OVMF_CODE.fd")),
+            gui: true,
+            forwards: options.forwards.clone(),
+            shares: options.shares.clone(),
+            snapshot: true,
+            resources: QemuResources {
+                memory: Some("512M".parse().unwrap()),
+                cpus: Some(1),
+                extra_args: vec!["-device".to_string(), "usb-tablet".to_string()],
+                ..Default::default()
+            },
+            ..VmConfig::new(
+                Arch::X86_64,
+                Accel::Kvm,
+                SerialOutput::File("/out/serial.log".to_string()),
+            )
+        };
+        let cmd = config.command();
+        assert_eq!(cmd.get_program(), "qemu-system-x86_64");
+        assert_eq!(
+            cmd.get_args().collect::<Vec<_>>(),
+            argv(&[
+                "-accel",
+                "kvm",
+                "-cpu",
+                QEMU_CPU_MODE,
+                "-m",
+                "512M",
+                "-smp",
+                "1",
+                "-drive",
+                "if=pflash,format=raw,readonly=on,file=/usr/share/OVMF/OVMF_CODE.fd",
+                "-cdrom",
+                "/out/acornos.iso",
+                "-drive",
+                "if=none,id=disk0,format=qcow2,file=/out/disk.qcow2",
+                "-device",
+                "virtio-blk-pci,drive=disk0",
+                "-netdev",
+                "user,id=net0,hostfwd=tcp::2222-:22,hostfwd=tcp::8080-:80",
+                "-device",
+                "virtio-net-pci,netdev=net0",
+                "-vga",
+                "virtio",
+                "-serial",
+                "file:/out/serial.log",
+                "-virtfs",
+                &share.virtfs().to_string_lossy(),
+                "-snapshot",
+                "-device",
+                "usb-tablet",
+            ])
+        );
+
+        // A headless TCG test boot under SeaBIOS with the defaults
+        let mut config = VmConfig::new(Arch::X86_64, Accel::Tcg, SerialOutput::Stdio);
+        config.iso = Some(PathBuf::from("/out/acornos.iso"));
+        config.boot_args = argv(&["-append", "console=ttyS0"]);
+        assert_eq!(
+            config.args(),
+            argv(&[
+                "-cpu",
+                "max",
+                "-m",
+                &format!("{}G", QEMU_MEMORY_GB),
+                "-smp",
+                "4",
+                "-cdrom",
+                "/out/acornos.iso",
+                "-netdev",
+                "user,id=net0",
+                "-device",
+                "virtio-net-pci,netdev=net0",
+                "-display",
+                "none",
+                "-no-reboot",
+                "-serial",
+                "stdio",
+                "-append",
+                "console=ttyS0",
+            ])
+        );
+    }
+
+    #[test]
+    fn test_qemu_resources() {
+        assert_eq!("512M".parse::<MemorySize>().unwrap().mb, 512);
+        assert_eq!("2g".parse::<MemorySize>().unwrap().mb, 2048);
+        assert_eq!("1024".parse::<MemorySize>().unwrap().to_string(), "1024M");
+        assert!("128M"
+            .parse::<MemorySize>()
+            .unwrap_err()
+            .to_string()
+            .contains("256M minimum"));
+        assert!("lots".parse::<MemorySize>().is_err());
+
+        assert!(QemuResources {
+            cpus: Some(0),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+
+        let mut cmd = Command::new("qemu-system-x86_64");
+        cmd.args(["-drive", "file=/tmp/my disk.qcow2", "-m", "512M"]);
+        assert_eq!(
+            command_line(&cmd),
+            "qemu-system-x86_64 -drive 'file=/tmp/my disk.qcow2' -m 512M"
+        );
+    }
+
+    #[test]
+    fn test_pick_host_port() {
+        let taken = TcpListener::bind(("0.0.0.0", 0)).unwrap();
+        let port = taken.local_addr().unwrap().port();
+        let picked = pick_host_port(port).unwrap();
+        assert_ne!(picked, port);
+        drop(taken);
+        assert_eq!(pick_host_port(picked).unwrap(), picked);
+    }
+
+    #[test]
+    fn test_firmware_parse() {
+        assert_eq!("BIOS".parse::<Firmware>().unwrap(), Firmware::Bios);
+        assert_eq!(Firmware::default().to_string(), "uefi");
+        assert!("coreboot".parse::<Firmware>().is_err());
+    }
+
+    #[test]
+    fn test_aarch64_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let firmware = dir.path().join("QEMU_EFI.fd");
+        std::fs::write(&firmware, vec![0u8; 4096]).unwrap();
+        let serial = SerialOutput::File("/tmp/serial.log".to_string());
+        let cmd = VmConfig {
+            iso: Some(PathBuf::from("/out/acornos.iso")),
+            disks: vec![
+                VmDisk {
+                    path: PathBuf::from("/out/my disk,1.qcow2"),
+                    serial: None,
+                },
+                VmDisk {
+                    path: PathBuf::from("/out/home.qcow2"),
+                    serial: Some("acorn-home".to_string()),
+                },
+            ],
+            firmware: Some(firmware.clone()),
+            forwards: vec!["2222:22".parse().unwrap()],
+            ..VmConfig::new(Arch::Aarch64, Accel::Tcg, serial)
+        }
+        .command();
+        assert_eq!(cmd.get_program(), "qemu-system-aarch64");
+        let args: Vec<String> = cmd
+            .get_args()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect();
+        let has = |pair: [&str; 2]| args.windows(2).any(|w| w[0] == pair[0] && w[1] == pair[1]);
+        assert!(has(["-machine", "virt"]));
+        assert!(has(["-bios", &firmware.display().to_string()]));
+        assert!(has([
+            "-drive",
+            "if=none,id=cd0,media=cdrom,readonly=on,file=/out/acornos.iso"
+        ]));
+        // Commas in paths are doubled so QEMU does not split the option there
+        assert!(has([
+            "-drive",
+            "if=none,id=disk0,format=qcow2,file=/out/my disk,,1.qcow2"
+        ]));
+        assert!(has(["-device", "virtio-blk-pci,drive=disk0"]));
+        assert!(has([
+            "-device",
+            "virtio-blk-pci,drive=disk1,serial=acorn-home"
+        ]));
+        let disk0 = args.iter().position(|a| a.contains("id=disk0")).unwrap();
+        assert!(args.iter().position(|a| a.contains("id=disk1")).unwrap() > disk0);
+        assert!(has(["-netdev", "user,id=net0,hostfwd=tcp::2222-:22"]));
+        assert!(has(["-serial", "file:/tmp/serial.log"]));
+        assert!(!args.iter().any(|a| a == "virtio-gpu-pci"));
+
+        // An installed system's disk boots without a CD-ROM
+        let cmd = VmConfig {
+            disks: vec![VmDisk {
+                path: PathBuf::from("/out/installed.qcow2"),
+                serial: None,
+            }],
+            firmware: Some(firmware),
+            ..VmConfig::new(Arch::Aarch64, Accel::Kvm, SerialOutput::Stdio)
+        }
+        .command();
+        let args: Vec<String> = cmd
+            .get_args()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect();
+        assert!(!args
+            .iter()
+            .any(|a| a.contains("cdrom") || a.contains("scsi")));
+        assert!(args
+            .windows(4)
+            .any(|w| w == ["-accel", "kvm", "-cpu", "host"]));
+        assert!(
+            args.contains(&"if=none,id=disk0,format=qcow2,file=/out/installed.qcow2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_kvm_access() {
+        use std::io::{Error, ErrorKind};
+
+        assert_eq!(
+            KvmAccess::from_open(Err(Error::from(ErrorKind::PermissionDenied))),
+            KvmAccess::Denied
+        );
+        assert_eq!(
+            KvmAccess::from_open(Err(Error::from(ErrorKind::NotFound))),
+            KvmAccess::Missing
+        );
+        assert!(matches!(
+            KvmAccess::from_open(Err(Error::other("busy"))),
+            KvmAccess::Failed(e) if e == "busy"
+        ));
+        assert_eq!(select_accel(Arch::X86_64, true).unwrap(), Accel::Tcg);
+    }
+
+    #[test]
+    fn test_run_options_validation() {
+        for bad in ["22", "0:22", "2222:70000", "a:b", ":22"] {
+            assert!(bad.parse::<PortForward>().is_err(), "{}", bad);
+        }
+        assert!("/nonexistent/share".parse::<VirtfsShare>().is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let share: VirtfsShare = dir.path().display().to_string().parse().unwrap();
+        assert_eq!(share.tag, DEFAULT_SHARE_TAG);
+        assert!(format!("{}:bad,tag", dir.path().display())
+            .parse::<VirtfsShare>()
+            .is_err());
+
+        let dup = RunOptions {
+            forwards: vec!["2222:22".parse().unwrap(), "2222:80".parse().unwrap()],
+            ..Default::default()
+        };
+        assert!(dup.validate().is_err());
+
+        for good in ["40G", "512M", "1073741824"] {
+            assert!(validate_disk_size(good).is_ok(), "{}", good);
+        }
+        for bad in ["", "G", "0G", "40GB", "-1G", "4.5G"] {
+            assert!(validate_disk_size(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_serial_log_is_line_flushed_and_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs/test-serial.log");
+
+        let mut log = open_serial_log(&path).unwrap();
+        writeln!(log, "old run").unwrap();
+        drop(log);
+
+        let mut log = open_serial_log(&path).unwrap();
+        writeln!(log, "Linux version 6.12.1").unwrap();
+        writeln!(log, "OpenRC is starting").unwrap();
+        // Readable without dropping (i.e. as if QEMU were killed now)
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "Linux version 6.12.1\nOpenRC is starting\n"
+        );
+        drop(log);
+    }
+
+    #[test]
+    fn test_summary_json() {
+        let (tracker, _) = replay(&CANNED_SERIAL[..4]);
+        let json = summary_json(
+            false,
+            Accel::Kvm,
+            true,
+            &tracker.timings(&BootBudget::default()),
+        );
+        assert!(json.contains("\"passed\": false"));
+        assert!(json.contains("\"accelerator\": \"kvm\",\n  \"kvm\": true"));
+        assert!(json.contains("\"budget_enforced\": true"));
+        assert!(json.contains(
+            "\"kernel\": { \"seconds\": 3.000, \"budget_seconds\": 15, \"within_budget\": true }"
+        ));
+        assert!(json.contains("\"total\": { \"seconds\": null"));
+    }
+
+    #[test]
+    fn test_direct_boot_cmdline() {
+        let plain = direct_boot_cmdline(Arch::X86_64, "ACORNOS", &CmdlineEdit::default());
+        assert!(plain.starts_with("root=LABEL=ACORNOS console=tty0 console=ttyS0,115200"));
+
+        let edit = CmdlineEdit::new(&["loglevel=7 acpi=off".into()], &["quiet".into()]).unwrap();
+        let edited = direct_boot_cmdline(Arch::Aarch64, "TEST", &edit);
+        assert!(edited.starts_with("root=LABEL=TEST console=tty0 console=ttyAMA0,115200"));
+        assert!(edited.ends_with(" loglevel=7 acpi=off"));
+        assert!(!edited.split_whitespace().any(|opt| opt == "quiet"));
+    }
+}
@@ -7,15 +7,49 @@
 
 use std::path::Path;
 
-use distro_spec::acorn::{INITRAMFS_LIVE_OUTPUT, ISO_FILENAME, ROOTFS_NAME};
-
+use distro_builder::alpine::extract::ExtractPaths;
 use distro_builder::cache;
 
+use crate::alpine_source::AlpineSource;
+use crate::arch::Arch;
+use crate::artifact::full_initramfs::INSTALLED_TEMPLATE;
+use crate::artifact::iso_extra::{collect_iso_extras, extra_files, IsoExtra};
+use crate::artifact::live_profile::LiveProfile;
+use crate::artifact::live_ssh::host_public_keys;
+use crate::artifact::netboot::{
+    NetbootOptions, INITRAMFS_NETBOOT_OUTPUT, NETBOOT_DIR, NETBOOT_TEMPLATE,
+};
+use crate::artifact::netinstall::{apk_static, NETINSTALL_TEMPLATE};
+use crate::artifact::RootfsFormat;
+use crate::artifact::{InitramfsCompression, IsoLabel, IsoOptions, IsoVariant};
+use crate::build_info::BuildInfo;
+use crate::component::firmware::{FirmwarePolicy, FIRMWARE_FILE};
+use crate::component::selection::{ComponentSelection, COMPONENTS_FILE};
+use crate::package_overlay::OVERLAY_FILE;
+use crate::paths::OutputPaths;
+use crate::source_rootfs;
+
+/// ISO input hash of `variant`, in the output dir.
+pub fn iso_inputs_hash(variant: IsoVariant) -> &'static str {
+    match variant {
+        IsoVariant::Live => ".iso-inputs.hash",
+        IsoVariant::Netinstall => ".iso-netinstall-inputs.hash",
+    }
+}
+
+/// ISO options hash of `variant`, in the output dir.
+fn iso_options_hash_file(variant: IsoVariant) -> &'static str {
+    match variant {
+        IsoVariant::Live => ".iso-options.hash",
+        IsoVariant::Netinstall => ".iso-netinstall-options.hash",
+    }
+}
+
 /// Check if kernel needs to be compiled.
 ///
 /// Checks if the kernel build artifacts exist and if inputs (kconfig) have changed.
 pub fn kernel_needs_compile(base_dir: &Path) -> bool {
-    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
+    let output_dir = crate::paths::output_dir(base_dir);
     let our_bzimage = output_dir.join("kernel-build/arch/x86/boot/bzImage");
     if !our_bzimage.exists() {
         return true;
@@ -37,9 +71,9 @@ pub fn kernel_needs_compile(base_dir: &Path) -> bool {
 
 /// Check if kernel needs to be installed (bzImage exists but vmlinuz doesn't).
 pub fn kernel_needs_install(base_dir: &Path) -> bool {
-    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
+    let output_dir = crate::paths::output_dir(base_dir);
     let bzimage = output_dir.join("kernel-build/arch/x86/boot/bzImage");
-    let vmlinuz = output_dir.join("staging/boot/vmlinuz");
+    let vmlinuz = OutputPaths::new(&output_dir).kernel();
 
     if !bzimage.exists() {
         return false; // Can't install what doesn't exist
@@ -68,30 +102,26 @@ pub fn cache_kernel_hash(base_dir: &Path) {
     };
 
     if let Some(hash) = cache::hash_files(&inputs) {
-        let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
+        let output_dir = crate::paths::output_dir(base_dir);
         let _ = cache::write_cached_hash(&output_dir.join(".kernel-inputs.hash"), &hash);
     }
 }
 
-/// Check if rootfs (EROFS) needs to be rebuilt.
+/// Check if the rootfs image needs to be rebuilt in `format`.
 ///
-/// Uses hash of key input files. Falls back to mtime if hash file missing.
-pub fn rootfs_needs_rebuild(base_dir: &Path) -> bool {
-    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
-    let rootfs = output_dir.join(ROOTFS_NAME);
+/// Uses hash of key input files plus the format, so switching formats rebuilds.
+/// Falls back to mtime if hash file missing.
+pub fn rootfs_needs_rebuild(base_dir: &Path, format: RootfsFormat) -> bool {
+    let output_dir = crate::paths::output_dir(base_dir);
+    let rootfs = OutputPaths::new(&output_dir).rootfs_image(format);
     let hash_file = output_dir.join(".rootfs-inputs.hash");
 
-    if !rootfs.exists() {
+    // The build step recreates a stale or half-installed source rootfs
+    if !rootfs.exists() || !source_rootfs::is_complete(base_dir) {
         return true;
     }
 
-    // Key files that affect rootfs content
-    // For AcornOS, the rootfs comes from Alpine package extraction
-    let rootfs_marker = base_dir.join("downloads/rootfs/bin/busybox");
-    let rootfs_builder = base_dir.join("src/artifact/rootfs.rs");
-
-    let inputs: Vec<&Path> = vec![&rootfs_marker, &rootfs_builder];
-    let current_hash = match cache::hash_files(&inputs) {
+    let current_hash = match rootfs_hash(base_dir, format) {
         Some(h) => h,
         None => return true,
     };
@@ -99,71 +129,400 @@ pub fn rootfs_needs_rebuild(base_dir: &Path) -> bool {
     cache::needs_rebuild(&current_hash, &hash_file, &rootfs)
 }
 
-/// Check if initramfs needs to be rebuilt.
-pub fn initramfs_needs_rebuild(base_dir: &Path) -> bool {
-    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
-    let initramfs = output_dir.join(INITRAMFS_LIVE_OUTPUT);
+/// Rootfs input hash: key input files, the format, the Alpine source, the
+/// disabled components, the firmware policy, `--reproducible` and the
+/// version and build id of os-release.
+fn rootfs_hash(base_dir: &Path, format: RootfsFormat) -> Option<String> {
+    // Key files that affect rootfs content
+    // For AcornOS, the rootfs comes from Alpine package extraction
+    let rootfs_marker = ExtractPaths::new(base_dir).rootfs.join("bin/busybox");
+    let rootfs_builder = base_dir.join("src/artifact/rootfs.rs");
+    let overlay = base_dir.join(OVERLAY_FILE);
+    let components = base_dir.join(COMPONENTS_FILE);
+    let firmware = base_dir.join(FIRMWARE_FILE);
+
+    let mut inputs: Vec<&Path> = vec![&rootfs_marker, &rootfs_builder];
+    for optional in [&overlay, &components, &firmware] {
+        if optional.exists() {
+            inputs.push(optional);
+        }
+    }
+    let mut hash = format!("{}:{}", format, cache::hash_files(&inputs)?);
+
+    // Another mirror or branch is another package set
+    let source = AlpineSource::from_env().ok()?.fingerprint();
+    if !source.is_empty() {
+        hash.push_str(&format!(":alpine={}", source));
+    }
+
+    // An invalid selection fails the build itself, with a proper error
+    let selection = ComponentSelection::load(base_dir).ok()?.fingerprint();
+    if !selection.is_empty() {
+        hash.push(':');
+        hash.push_str(&selection);
+    }
+
+    // Switching presets (--firmware) changes the rootfs without touching a file
+    let firmware = FirmwarePolicy::load(base_dir).ok()?.fingerprint();
+    if !firmware.is_empty() {
+        hash.push(':');
+        hash.push_str(&firmware);
+    }
+
+    // --reproducible changes the image's times, not its files
+    let reproducible = crate::reproducible::fingerprint();
+    if !reproducible.is_empty() {
+        hash.push(':');
+        hash.push_str(&reproducible);
+    }
+
+    // A new commit is a new BUILD_ID in os-release
+    hash.push(':');
+    hash.push_str(&BuildInfo::detect(base_dir).fingerprint());
+    Some(hash)
+}
+
+/// Check if the live initramfs needs to be rebuilt with `compression`
+/// for the ISO label `label`.
+pub fn initramfs_needs_rebuild(
+    base_dir: &Path,
+    compression: InitramfsCompression,
+    label: &IsoLabel,
+) -> bool {
+    let output_dir = crate::paths::output_dir(base_dir);
+    let initramfs = OutputPaths::new(&output_dir).live_initramfs(compression);
     let hash_file = output_dir.join(".initramfs-inputs.hash");
+
+    if !initramfs.exists() {
+        return true;
+    }
+
+    match initramfs_hash(base_dir, compression, label) {
+        Some(current_hash) => cache::needs_rebuild(&current_hash, &hash_file, &initramfs),
+        None => true,
+    }
+}
+
+/// Hash of the live initramfs inputs. The template is rendered for the
+/// rootfs format and ISO label, so those are included along with the
+/// compression. The persistence tools come from the Alpine rootfs.
+fn initramfs_hash(
+    base_dir: &Path,
+    compression: InitramfsCompression,
+    label: &IsoLabel,
+) -> Option<String> {
     let init_script = base_dir.join("profile/init_tiny.template");
     let busybox = base_dir.join("downloads/busybox-static");
+    let rootfs_marker = ExtractPaths::new(base_dir).rootfs.join("bin/busybox");
     let initramfs_module = base_dir.join("src/artifact/initramfs.rs");
+    let persistence_module = base_dir.join("src/artifact/persistence.rs");
+
+    let inputs: Vec<&Path> = vec![
+        &init_script,
+        &busybox,
+        &rootfs_marker,
+        &initramfs_module,
+        &persistence_module,
+    ];
+    let hash = cache::hash_files(&inputs)?;
+    let format = RootfsFormat::load(&crate::paths::output_dir(base_dir));
+    let mut hash = format!("{}:{}:{}", format, compression, hash);
+    // Default-label hashes predate --label and stay unchanged
+    if !label.is_default() {
+        hash.push_str(&format!(":label={}", label));
+    }
+    Some(hash)
+}
+
+/// Check if the installed-system initramfs needs to be rebuilt.
+pub fn full_initramfs_needs_rebuild(base_dir: &Path, luks: bool) -> bool {
+    let output_dir = crate::paths::output_dir(base_dir);
+    let initramfs = OutputPaths::new(&output_dir).installed_initramfs();
+    let hash_file = output_dir.join(".initramfs-installed-inputs.hash");
 
     if !initramfs.exists() {
         return true;
     }
 
-    let inputs: Vec<&Path> = vec![&init_script, &busybox, &initramfs_module];
-    let current_hash = match cache::hash_files(&inputs) {
-        Some(h) => h,
-        None => return true,
-    };
+    match full_initramfs_hash(base_dir, luks) {
+        Some(current_hash) => cache::needs_rebuild(&current_hash, &hash_file, &initramfs),
+        None => true,
+    }
+}
+
+/// Cache the installed-system initramfs input hash after a successful build.
+pub fn cache_full_initramfs_hash(base_dir: &Path, luks: bool) {
+    if let Some(hash) = full_initramfs_hash(base_dir, luks) {
+        let output_dir = crate::paths::output_dir(base_dir);
+        let _ =
+            cache::write_cached_hash(&output_dir.join(".initramfs-installed-inputs.hash"), &hash);
+    }
+}
+
+/// Hash of the installed initramfs inputs, including the LUKS flag.
+fn full_initramfs_hash(base_dir: &Path, luks: bool) -> Option<String> {
+    let init_script = base_dir.join(INSTALLED_TEMPLATE);
+    let busybox = base_dir.join("downloads/busybox-static");
+    let rootfs_marker = ExtractPaths::new(base_dir).rootfs.join("bin/busybox");
+    let builder = base_dir.join("src/artifact/full_initramfs.rs");
 
-    cache::needs_rebuild(&current_hash, &hash_file, &initramfs)
+    let inputs: Vec<&Path> = vec![&init_script, &busybox, &rootfs_marker, &builder];
+    let hash = cache::hash_files(&inputs)?;
+    Some(format!("luks={}:{}", luks, hash))
+}
+
+/// Check if the netboot directory needs to be rebuilt.
+///
+/// Also rebuilds when the rootfs image or kernel is newer than the netboot
+/// initramfs, since both are copied into the directory.
+pub fn netboot_needs_rebuild(base_dir: &Path, options: &NetbootOptions) -> bool {
+    let output_dir = crate::paths::output_dir(base_dir);
+    let initramfs = output_dir.join(NETBOOT_DIR).join(INITRAMFS_NETBOOT_OUTPUT);
+    let hash_file = output_dir.join(".netboot-inputs.hash");
+    let paths = OutputPaths::new(&output_dir);
+    let rootfs = paths.rootfs_image(RootfsFormat::load(&output_dir));
+    let kernel = paths.kernel();
+
+    if !initramfs.exists()
+        || cache::is_newer(&rootfs, &initramfs)
+        || cache::is_newer(&kernel, &initramfs)
+    {
+        return true;
+    }
+
+    match netboot_hash(base_dir, options) {
+        Some(current_hash) => cache::needs_rebuild(&current_hash, &hash_file, &initramfs),
+        None => true,
+    }
+}
+
+/// Cache the netboot input hash after a successful build.
+pub fn cache_netboot_hash(base_dir: &Path, options: &NetbootOptions) {
+    if let Some(hash) = netboot_hash(base_dir, options) {
+        let output_dir = crate::paths::output_dir(base_dir);
+        let _ = cache::write_cached_hash(&output_dir.join(".netboot-inputs.hash"), &hash);
+    }
+}
+
+/// Hash of the netboot inputs, including the format, base URL and live user.
+fn netboot_hash(base_dir: &Path, options: &NetbootOptions) -> Option<String> {
+    let init_script = base_dir.join(NETBOOT_TEMPLATE);
+    let busybox = base_dir.join("downloads/busybox-static");
+    let builder = base_dir.join("src/artifact/netboot.rs");
+
+    let inputs: Vec<&Path> = vec![&init_script, &busybox, &builder];
+    let hash = cache::hash_files(&inputs)?;
+    let output_dir = crate::paths::output_dir(base_dir);
+    Some(format!(
+        "{}:base-url={}:live-user={}:{}",
+        RootfsFormat::load(&output_dir),
+        options.base_url.as_deref().unwrap_or(""),
+        options.live_user,
+        hash
+    ))
 }
 
 /// Check if ISO needs to be rebuilt.
 pub fn iso_needs_rebuild(base_dir: &Path) -> bool {
-    let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
-    let iso = output_dir.join(ISO_FILENAME);
-    let rootfs = output_dir.join(ROOTFS_NAME);
-    let initramfs = output_dir.join(INITRAMFS_LIVE_OUTPUT);
-    // AcornOS builds its own kernel (same as LevitateOS)
-    let kernel = output_dir.join("staging/boot/vmlinuz");
+    iso_needs_rebuild_with(base_dir, &IsoOptions::default())
+}
 
-    if !iso.exists() {
+/// Check if ISO needs to be rebuilt, including ISO extras and the live user.
+pub fn iso_needs_rebuild_with(base_dir: &Path, options: &IsoOptions) -> bool {
+    if iso_options_changed(base_dir, options) {
         return true;
     }
 
-    // ISO needs rebuild if any component is missing or newer than the ISO
-    !rootfs.exists()
+    let output_dir = crate::paths::output_dir(base_dir);
+    let paths = OutputPaths::new(&output_dir);
+    let iso = paths.iso_variant(options.variant);
+    let rootfs = paths.rootfs_image(RootfsFormat::load(&output_dir));
+    let initramfs = paths.live_initramfs(InitramfsCompression::load(&output_dir));
+    // AcornOS builds its own kernel (same as LevitateOS)
+    let kernel = paths.kernel();
+    let needs_rootfs = options.variant == IsoVariant::Live;
+
+    // ISO needs rebuild if it or any component is missing, or the inputs changed
+    if !iso.exists()
+        || (needs_rootfs && !rootfs.exists())
         || !initramfs.exists()
         || !kernel.exists()
-        || cache::is_newer(&rootfs, &iso)
-        || cache::is_newer(&initramfs, &iso)
-        || cache::is_newer(&kernel, &iso)
+    {
+        return true;
+    }
+    let Some(hash) = iso_hash(base_dir, options) else {
+        return true;
+    };
+    let hash_file = output_dir.join(iso_inputs_hash(options.variant));
+    cache::needs_rebuild(&hash, &hash_file, &iso)
 }
 
-/// Cache the rootfs input hash after a successful build.
-pub fn cache_rootfs_hash(base_dir: &Path) {
-    let rootfs_marker = base_dir.join("downloads/rootfs/bin/busybox");
-    let rootfs_builder = base_dir.join("src/artifact/rootfs.rs");
+/// Hash of the ISO inputs: the rootfs image, live and installed
+/// initramfs, kernel, `profile/live-overlay`, and (off x86_64 or with
+/// `--bios`) the GRUB config generators. The netinstall ISO has the
+/// installer, apk.static and the mirror config in place of the rootfs
+/// image and live system.
+///
+/// The version and build id are the volume application id.
+///
+/// Content hashes, so `touch` or a restore from the artifact store does not
+/// force a rebuild.
+fn iso_hash(base_dir: &Path, options: &IsoOptions) -> Option<String> {
+    let output_dir = crate::paths::output_dir(base_dir);
+    let paths = OutputPaths::new(&output_dir);
+    let live_initramfs = paths.live_initramfs(InitramfsCompression::load(&output_dir));
+    let mut files = match options.variant {
+        IsoVariant::Live => vec![
+            paths.rootfs_image(RootfsFormat::load(&output_dir)),
+            live_initramfs,
+            paths.kernel(),
+        ],
+        IsoVariant::Netinstall => vec![
+            live_initramfs,
+            paths.kernel(),
+            base_dir.join(NETINSTALL_TEMPLATE),
+            base_dir.join(source_rootfs::ALPINE_RECIPE),
+            apk_static(base_dir, options.arch),
+            ExtractPaths::new(base_dir)
+                .rootfs
+                .join("etc/apk/repositories"),
+        ],
+    };
+    if options.variant == IsoVariant::Live {
+        let installed_initramfs = paths.installed_initramfs();
+        if installed_initramfs.exists() {
+            files.push(installed_initramfs);
+        }
+        let overlay = base_dir.join("profile/live-overlay");
+        if overlay.exists() {
+            let overlay = IsoExtra {
+                src: overlay,
+                dest: String::new(),
+            };
+            files.extend(extra_files(&[overlay]).ok()?);
+        }
+    }
+    if options.arch != Arch::X86_64 || options.bios {
+        files.push(base_dir.join("src/artifact/grub_efi.rs"));
+    }
+    if options.bios {
+        files.push(base_dir.join("src/artifact/grub_bios.rs"));
+    }
 
-    let inputs: Vec<&Path> = vec![&rootfs_marker, &rootfs_builder];
-    if let Some(hash) = cache::hash_files(&inputs) {
-        let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
-        let _ = cache::write_cached_hash(&output_dir.join(".rootfs-inputs.hash"), &hash);
+    let inputs: Vec<&Path> = files.iter().map(|f| f.as_path()).collect();
+    let hash = cache::hash_files(&inputs)?;
+    Some(format!(
+        "{}:{}",
+        hash,
+        BuildInfo::detect(base_dir).fingerprint()
+    ))
+}
+
+/// Cache the ISO input hash after a successful ISO build.
+pub fn cache_iso_hash(base_dir: &Path, options: &IsoOptions) {
+    if let Some(hash) = iso_hash(base_dir, options) {
+        let output_dir = crate::paths::output_dir(base_dir);
+        let hash_file = output_dir.join(iso_inputs_hash(options.variant));
+        let _ = cache::write_cached_hash(&hash_file, &hash);
     }
 }
 
-/// Cache the initramfs input hash after a successful build.
-pub fn cache_initramfs_hash(base_dir: &Path) {
-    let init_script = base_dir.join("profile/init_tiny.template");
-    let busybox = base_dir.join("downloads/busybox-static");
-    let initramfs_module = base_dir.join("src/artifact/initramfs.rs");
+/// Cache the rootfs input hash after a successful build.
+pub fn cache_rootfs_hash(base_dir: &Path, format: RootfsFormat) {
+    if let Some(hash) = rootfs_hash(base_dir, format) {
+        let output_dir = crate::paths::output_dir(base_dir);
+        let _ = cache::write_cached_hash(&output_dir.join(".rootfs-inputs.hash"), &hash);
+    }
+}
 
-    let inputs: Vec<&Path> = vec![&init_script, &busybox, &initramfs_module];
-    if let Some(hash) = cache::hash_files(&inputs) {
-        let output_dir = distro_builder::artifact_store::central_output_dir_for_distro(base_dir);
+/// Cache the live initramfs input hash after a successful build.
+pub fn cache_initramfs_hash(base_dir: &Path, compression: InitramfsCompression, label: &IsoLabel) {
+    if let Some(hash) = initramfs_hash(base_dir, compression, label) {
+        let output_dir = crate::paths::output_dir(base_dir);
         let _ = cache::write_cached_hash(&output_dir.join(".initramfs-inputs.hash"), &hash);
     }
 }
+
+/// Hash of the ISO options: extra file contents, their ISO destinations,
+/// the live user and the flags, including the label and `--reproducible`.
+fn iso_options_hash(extras: &[IsoExtra], options: &IsoOptions) -> Option<String> {
+    let dests: Vec<&str> = extras.iter().map(|e| e.dest.as_str()).collect();
+    let files = extra_files(extras).ok()?;
+    let content = if files.is_empty() {
+        "none".to_string()
+    } else {
+        let inputs: Vec<&Path> = files.iter().map(|f| f.as_path()).collect();
+        cache::hash_files(&inputs)?
+    };
+    let mut hash = format!(
+        "{}:{}:live-user={}",
+        content,
+        dests.join(","),
+        options.live_user
+    );
+    // x86_64 hashes predate --arch and stay unchanged
+    if options.arch != Arch::X86_64 {
+        hash.push_str(&format!(":arch={}", options.arch));
+    }
+    if options.with_ukis {
+        hash.push_str(":ukis");
+    }
+    if options.bios {
+        hash.push_str(":bios");
+    }
+    if options.profile != LiveProfile::Testing {
+        hash.push_str(&format!(":profile={}", options.profile));
+    }
+    if !options.label.is_default() {
+        hash.push_str(&format!(":label={}", options.label));
+    }
+    // The effective cmdline is baked into the UKIs and GRUB menus
+    if !options.cmdline.is_empty() {
+        hash.push_str(&format!(":cmdline={}", options.cmdline.fingerprint()));
+    }
+    let reproducible = crate::reproducible::fingerprint();
+    if !reproducible.is_empty() {
+        hash.push_str(&format!(":{}", reproducible));
+    }
+    if options.ssh {
+        // New or rotated host keys need a new authorized_keys
+        let keys = std::env::var_os("HOME")
+            .map(|home| host_public_keys(Path::new(&home)))
+            .unwrap_or_default();
+        let inputs: Vec<&Path> = keys.iter().map(|k| k.as_path()).collect();
+        hash.push_str(&format!(":ssh={}", cache::hash_files(&inputs)?));
+    }
+    Some(hash)
+}
+
+/// Check if the ISO options changed since the last ISO build.
+fn iso_options_changed(base_dir: &Path, options: &IsoOptions) -> bool {
+    let output_dir = crate::paths::output_dir(base_dir);
+    let hash_file = output_dir.join(iso_options_hash_file(options.variant));
+
+    let extras = match collect_iso_extras(base_dir, &options.extras) {
+        Ok(e) => e,
+        Err(_) => return true, // let create_iso surface the error
+    };
+    let cached = std::fs::read_to_string(&hash_file)
+        .map(|h| h.trim().to_string())
+        .ok();
+
+    match (iso_options_hash(&extras, options), cached) {
+        (Some(current), Some(cached)) => current != cached,
+        _ => true,
+    }
+}
+
+/// Cache the ISO options hash after a successful ISO build.
+pub fn cache_iso_options_hash(base_dir: &Path, options: &IsoOptions) {
+    let extras = match collect_iso_extras(base_dir, &options.extras) {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+    if let Some(hash) = iso_options_hash(&extras, options) {
+        let output_dir = crate::paths::output_dir(base_dir);
+        let hash_file = output_dir.join(iso_options_hash_file(options.variant));
+        let _ = cache::write_cached_hash(&hash_file, &hash);
+    }
+}
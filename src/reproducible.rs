@@ -0,0 +1,353 @@
+//! Reproducible builds (`acornos build --reproducible`).
+//!
+//! Two builds from the same inputs used to give different ISOs: staging
+//! files carry the time they were copied, mkfs.erofs and mksquashfs stamp
+//! the image with the build time (mkfs.erofs also with a random UUID), and
+//! xorriso dates the volume and every file.
+//!
+//! With `--reproducible` every timestamp is `SOURCE_DATE_EPOCH` (default:
+//! the commit time of HEAD):
+//!
+//! - staging mtimes are set to it before the image is made
+//! - mkfs.erofs gets `-T` and a UUID derived from it, mksquashfs
+//!   `-mkfs-time`/`-all-time`
+//! - a last xorriso pass sets the volume dates and all file dates
+//! - the live copy of `build-env.json` leaves out where the build ran
+//!
+//! Both variables are exported, so child builds, xorriso and the build
+//! manifest see them too.
+//!
+//! `--verify-reproducible` builds twice into `reproducible/a` and
+//! `reproducible/b` of the output dir and compares the SHA512s of the
+//! rootfs image, the initramfs images and the ISO; the trees are kept for
+//! inspection (e.g. with diffoscope).
+
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use distro_builder::process::Cmd;
+
+use crate::artifact::{InitramfsCompression, RootfsFormat};
+use crate::paths::OutputPaths;
+use crate::progress;
+use crate::signing::sha512_file;
+
+/// Set to `1` by `--reproducible` (for child builds).
+pub const REPRODUCIBLE_ENV: &str = "ACORNOS_REPRODUCIBLE";
+
+/// The timestamp of everything in a reproducible build.
+pub const SOURCE_DATE_EPOCH_ENV: &str = "SOURCE_DATE_EPOCH";
+
+/// Where `--verify-reproducible` builds, under the output dir.
+pub const VERIFY_DIR: &str = "reproducible";
+
+/// `SOURCE_DATE_EPOCH`, else the commit time of HEAD in `base_dir`.
+pub fn source_date_epoch(base_dir: &Path) -> Result<u64> {
+    if let Ok(value) = std::env::var(SOURCE_DATE_EPOCH_ENV) {
+        return parse_epoch(&value);
+    }
+    let result = Cmd::new("git")
+        .args(["log", "-1", "--format=%ct"])
+        .dir(base_dir)
+        .allow_fail()
+        .run();
+    match result {
+        Ok(result) if result.success() => parse_epoch(&result.stdout),
+        _ => bail!(
+            "--reproducible needs ${} when {} is not a git checkout",
+            SOURCE_DATE_EPOCH_ENV,
+            base_dir.display()
+        ),
+    }
+}
+
+fn parse_epoch(value: &str) -> Result<u64> {
+    value
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid ${} '{}'", SOURCE_DATE_EPOCH_ENV, value.trim()))
+}
+
+/// Export `--reproducible` with `epoch` for child builds and image tools.
+///
+/// Must be called before any threads are spawned.
+pub fn export(epoch: u64) {
+    std::env::set_var(REPRODUCIBLE_ENV, "1");
+    std::env::set_var(SOURCE_DATE_EPOCH_ENV, epoch.to_string());
+}
+
+/// Timestamp of this build if it is reproducible.
+pub fn epoch() -> Result<Option<u64>> {
+    if std::env::var(REPRODUCIBLE_ENV).as_deref() != Ok("1") {
+        return Ok(None);
+    }
+    let value = std::env::var(SOURCE_DATE_EPOCH_ENV).with_context(|| {
+        format!(
+            "${} is set without ${}",
+            REPRODUCIBLE_ENV, SOURCE_DATE_EPOCH_ENV
+        )
+    })?;
+    parse_epoch(&value).map(Some)
+}
+
+/// Stable description for rebuild hashes; empty for a normal build.
+pub fn fingerprint() -> String {
+    match epoch() {
+        Ok(Some(epoch)) => format!("reproducible={}", epoch),
+        _ => String::new(),
+    }
+}
+
+/// Set the mtime of everything under `root` (symlinks included) to `epoch`.
+pub fn normalize_mtimes(root: &Path, epoch: u64) -> Result<()> {
+    let status = Command::new("find")
+        .arg(root)
+        .args(["-exec", "touch", "--no-dereference"])
+        .arg(format!("--date=@{}", epoch))
+        .args(["{}", "+"])
+        .status()
+        .context("Failed to run find. Install: sudo dnf install findutils")?;
+    if !status.success() {
+        bail!(
+            "Failed to set the mtimes under {} ({})",
+            root.display(),
+            status
+        );
+    }
+    Ok(())
+}
+
+/// Filesystem UUID derived from `epoch`, so it is the same in every build.
+fn image_uuid(epoch: u64) -> String {
+    format!("00000000-0000-4000-8000-{:012x}", epoch & 0xffff_ffff_ffff)
+}
+
+/// mkfs.erofs arguments fixing the build time and UUID.
+pub fn erofs_args(epoch: u64) -> Vec<String> {
+    vec![format!("-T{}", epoch), format!("-U{}", image_uuid(epoch))]
+}
+
+/// mksquashfs arguments fixing the filesystem and file times.
+pub fn squashfs_args(epoch: u64) -> Vec<String> {
+    vec![
+        "-mkfs-time".to_string(),
+        epoch.to_string(),
+        "-all-time".to_string(),
+        epoch.to_string(),
+    ]
+}
+
+/// xorriso commands setting the volume dates and every file date.
+pub fn xorriso_date_args(epoch: u64) -> Vec<String> {
+    let at = format!("={}", epoch);
+    let mut args = Vec::new();
+    for (kind, date) in [
+        ("c", at.as_str()),
+        ("m", at.as_str()),
+        ("uuid", utc_digits(epoch).as_str()),
+        ("all_file_dates", at.as_str()),
+    ] {
+        args.extend([
+            "-volume_date".to_string(),
+            kind.to_string(),
+            date.to_string(),
+        ]);
+    }
+    args
+}
+
+/// `epoch` as `YYYYMMDDhhmmsscc` in UTC, the form of xorriso's volume UUID.
+pub(crate) fn utc_digits(epoch: u64) -> String {
+    let days = (epoch / 86400) as i64;
+    let secs = epoch % 86400;
+    // Civil date from days since 1970-01-01 (proleptic Gregorian)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}00",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Rewrite the ISO at `input` to `output` with every date set to `epoch`,
+/// replaying its boot images.
+pub fn normalize_iso(input: &Path, output: &Path, epoch: u64) -> Result<()> {
+    let _ = std::fs::remove_file(output);
+    let mut cmd = Command::new("xorriso");
+    cmd.arg("-indev")
+        .arg(input)
+        .arg("-outdev")
+        .arg(output)
+        .args(["-boot_image", "any", "replay"])
+        .args(xorriso_date_args(epoch));
+    progress::run(
+        "ISO dates",
+        &mut cmd,
+        "xorriso failed to normalize the ISO dates",
+    )
+}
+
+/// An artifact of the two `--verify-reproducible` builds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArtifactComparison {
+    pub name: &'static str,
+    /// SHA512 in each build, `None` if it was not built.
+    pub first: Option<String>,
+    pub second: Option<String>,
+}
+
+impl ArtifactComparison {
+    pub fn matches(&self) -> bool {
+        self.first == self.second
+    }
+}
+
+/// The compared artifacts of a build in `paths`, in build order.
+pub fn compared_artifacts(paths: &OutputPaths) -> Vec<(&'static str, PathBuf)> {
+    vec![
+        ("rootfs", paths.rootfs_image(RootfsFormat::load(&paths.dir))),
+        (
+            "initramfs",
+            paths.live_initramfs(InitramfsCompression::load(&paths.dir)),
+        ),
+        ("installed initramfs", paths.installed_initramfs()),
+        ("ISO", paths.iso()),
+    ]
+}
+
+/// SHA512s of the artifacts of two builds.
+pub fn compare_builds(
+    first: &OutputPaths,
+    second: &OutputPaths,
+) -> Result<Vec<ArtifactComparison>> {
+    let hash = |path: &Path| -> Result<Option<String>> {
+        if path.is_file() {
+            sha512_file(path).map(Some)
+        } else {
+            Ok(None)
+        }
+    };
+    compared_artifacts(first)
+        .into_iter()
+        .zip(compared_artifacts(second))
+        .map(|((name, a), (_, b))| {
+            Ok(ArtifactComparison {
+                name,
+                first: hash(&a)?,
+                second: hash(&b)?,
+            })
+        })
+        .collect()
+}
+
+/// Report the comparison; fails if the ISOs differ, naming the first
+/// artifact that did.
+pub fn check_comparison(comparisons: &[ArtifactComparison]) -> Result<()> {
+    let short = |hash: &Option<String>| match hash {
+        Some(hash) => hash.chars().take(16).collect(),
+        None => "(not built)".to_string(),
+    };
+    for c in comparisons {
+        if c.matches() {
+            log::info!("  [OK] {:<20} {}", c.name, short(&c.first));
+        } else {
+            log::info!(
+                "  [DIFF] {:<18} {} != {}",
+                c.name,
+                short(&c.first),
+                short(&c.second)
+            );
+        }
+    }
+    let iso_matches = comparisons
+        .iter()
+        .find(|c| c.name == "ISO")
+        .is_some_and(|c| c.first.is_some() && c.matches());
+    if iso_matches {
+        return Ok(());
+    }
+    match comparisons.iter().find(|c| !c.matches()) {
+        Some(first) => bail!(
+            "Build is not reproducible: the ISOs differ; the first artifact to diverge is the {}",
+            first.name
+        ),
+        None => bail!("Build is not reproducible: no ISO was built"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+
+    #[test]
+    fn test_tool_arguments() {
+        assert_eq!(utc_digits(0), "1970010100000000");
+        assert_eq!(utc_digits(1_700_000_000), "2023111422132000");
+        assert_eq!(utc_digits(951_782_400), "2000022900000000");
+        assert_eq!(
+            erofs_args(1_700_000_000),
+            ["-T1700000000", "-U00000000-0000-4000-8000-00006553f100"]
+        );
+        assert_eq!(squashfs_args(5), ["-mkfs-time", "5", "-all-time", "5"]);
+        let xorriso = xorriso_date_args(1_700_000_000);
+        assert_eq!(xorriso.len(), 12);
+        assert_eq!(&xorriso[6..9], ["-volume_date", "uuid", "2023111422132000"]);
+        assert_eq!(xorriso.last().map(String::as_str), Some("=1700000000"));
+        assert!(parse_epoch("soon").is_err());
+    }
+
+    #[test]
+    fn test_normalize_mtimes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("staging");
+        fs::create_dir_all(root.join("etc")).unwrap();
+        fs::write(root.join("etc/hostname"), "acorn\n").unwrap();
+        std::os::unix::fs::symlink("hostname", root.join("etc/link")).unwrap();
+        normalize_mtimes(&root, 1_700_000_000).unwrap();
+        for path in ["", "etc", "etc/hostname", "etc/link"] {
+            let meta = fs::symlink_metadata(root.join(path)).unwrap();
+            assert_eq!(meta.mtime(), 1_700_000_000, "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_check_comparison() {
+        let artifact = |name, first: &str, second: &str| ArtifactComparison {
+            name,
+            first: Some(first.to_string()),
+            second: Some(second.to_string()),
+        };
+        let same = vec![artifact("rootfs", "a", "a"), artifact("ISO", "c", "c")];
+        assert!(check_comparison(&same).is_ok());
+
+        let diverged = vec![
+            artifact("rootfs", "a", "a"),
+            artifact("initramfs", "b", "x"),
+            artifact("ISO", "c", "y"),
+        ];
+        let err = check_comparison(&diverged).unwrap_err().to_string();
+        assert!(err.ends_with("diverge is the initramfs"), "{}", err);
+
+        let missing = vec![ArtifactComparison {
+            name: "ISO",
+            first: None,
+            second: None,
+        }];
+        assert!(check_comparison(&missing).is_err());
+    }
+}
@@ -0,0 +1,309 @@
+//! Content-addressed cache of the extracted Alpine rootfs.
+//!
+//! A clean checkout re-runs the alpine.rhai/packages.rhai recipes, and apk
+//! takes minutes to install the package tiers into `downloads/rootfs` even
+//! when nothing changed. After the recipes finish, the rootfs (plus the
+//! recipes' version markers, so they see it as built) is packed as a
+//! tar.zst and stored in the artifact store. It is keyed by the effective
+//! pin of the Alpine ISO in use (after `acorn-pins.toml`), the recipe files
+//! holding the package lists, the package overlay (`acorn-packages.toml`),
+//! the content of the Alpine signing keys, and the mirror and branch when
+//! they are not the defaults. An unpinned ISO is never cached.
+//!
+//! The artifact store only holds files, so the directory payload is
+//! packed and unpacked here. GNU tar keeps symlinks, permissions, numeric
+//! ownership, xattrs, and device-less special files such as FIFOs.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::Path;
+
+use distro_builder::artifact_store::ArtifactStore;
+use distro_builder::cache;
+use distro_builder::process::Cmd;
+use distro_spec::acorn::packages::ALPINE_KEYS;
+
+use crate::alpine_source::AlpineSource;
+use crate::arch::Arch;
+use crate::offline::sha256_file;
+use crate::package_overlay::{OVERLAY_FILE, OVERLAY_STATE};
+use crate::store_meta;
+
+/// Artifact store kind for the packed rootfs.
+pub const ROOTFS_CACHE_KIND: &str = "alpine_rootfs";
+
+/// Key file (in the output dir) holding the current cache key.
+pub const ROOTFS_CACHE_KEY_FILE: &str = ".alpine-rootfs-inputs.hash";
+
+/// Entries under `downloads/` that make up the payload.
+///
+/// The version markers are what the recipes' `is_built` checks read.
+pub const CACHED_ENTRIES: &[&str] = &[
+    "rootfs",
+    ".alpine-built-version",
+    ".packages-version",
+    ".packages-manifest",
+    OVERLAY_STATE,
+];
+
+/// Recipe files whose content defines the package set.
+const KEY_INPUTS: &[&str] = &["deps/alpine.rhai", "deps/packages.rhai"];
+
+/// Pin of the Alpine ISO this build uses, if it has one.
+fn iso_pin(base_dir: &Path) -> Option<String> {
+    let source = AlpineSource::from_env().ok()?;
+    crate::download::alpine_iso(base_dir, Arch::from_env().ok()?, &source)
+        .ok()?
+        .sha256
+}
+
+/// SHA256 of the Alpine signing keys, names and content.
+fn keys_hash() -> Option<String> {
+    let text: String = ALPINE_KEYS
+        .iter()
+        .map(|(name, key)| format!("{}\n{}\n", name, key))
+        .collect();
+    // sha256sum hashes files only
+    let tmp = std::env::temp_dir().join(format!(".acornos-keys.{}", std::process::id()));
+    fs::write(&tmp, text).ok()?;
+    let hash = sha256_file(&tmp).ok();
+    let _ = fs::remove_file(&tmp);
+    hash
+}
+
+/// Cache key for the current inputs, or `None` if an input is unreadable
+/// or the ISO is unpinned.
+pub fn rootfs_cache_key(base_dir: &Path) -> Option<String> {
+    let inputs: Vec<_> = KEY_INPUTS.iter().map(|p| base_dir.join(p)).collect();
+    let refs: Vec<&Path> = inputs.iter().map(|p| p.as_path()).collect();
+    let recipes = cache::hash_files(&refs)?;
+    let mut key = format!(
+        "iso={}:recipes={}:keys={}",
+        iso_pin(base_dir)?,
+        recipes,
+        keys_hash()?
+    );
+    // Keys without an overlay stay unchanged
+    let overlay = base_dir.join(OVERLAY_FILE);
+    if overlay.exists() {
+        key.push_str(&format!(":overlay={}", cache::hash_files(&[&overlay])?));
+    }
+    // As do keys for the default mirror and release
+    let source = AlpineSource::from_env().ok()?.fingerprint();
+    if !source.is_empty() {
+        key.push_str(&format!(":alpine={}", source));
+    }
+    // x86_64 keys predate --arch and stay unchanged
+    let arch = Arch::from_env().ok()?;
+    if arch != Arch::X86_64 {
+        key.push_str(&format!(":arch={}", arch));
+    }
+    Some(key)
+}
+
+/// Pack `entries` of `src_dir` into a tar.zst archive.
+pub fn pack_dir(src_dir: &Path, entries: &[&str], archive: &Path) -> Result<()> {
+    let present: Vec<&str> = entries
+        .iter()
+        .copied()
+        .filter(|e| src_dir.join(e).symlink_metadata().is_ok())
+        .collect();
+    if present.is_empty() {
+        bail!("Nothing to pack in {}", src_dir.display());
+    }
+
+    Cmd::new("tar")
+        .args(["--zstd", "--numeric-owner", "--xattrs", "-cpf"])
+        .arg_path(archive)
+        .arg("-C")
+        .arg_path(src_dir)
+        .arg("--")
+        .args(present)
+        .error_msg("tar failed to pack rootfs. Install: sudo dnf install tar zstd")
+        .run()?;
+    Ok(())
+}
+
+/// Unpack a tar.zst archive into `dest_dir`, replacing existing entries.
+///
+/// Extracts into a sibling temp dir first, so a corrupt archive never leaves
+/// a half-restored rootfs behind.
+pub fn unpack_dir(archive: &Path, dest_dir: &Path) -> Result<()> {
+    let tmp = dest_dir.join(".rootfs-cache.restore");
+    let _ = fs::remove_dir_all(&tmp);
+    fs::create_dir_all(&tmp)?;
+
+    let result = Cmd::new("tar")
+        .args(["--zstd", "--numeric-owner", "--xattrs", "-xpf"])
+        .arg_path(archive)
+        .arg("-C")
+        .arg_path(&tmp)
+        .error_msg("tar failed to unpack rootfs cache. Install: sudo dnf install tar zstd")
+        .run();
+    if let Err(e) = result {
+        let _ = fs::remove_dir_all(&tmp);
+        return Err(e);
+    }
+
+    for entry in fs::read_dir(&tmp)? {
+        let entry = entry?;
+        let dest = dest_dir.join(entry.file_name());
+        if dest.is_dir() && !dest.is_symlink() {
+            fs::remove_dir_all(&dest)?;
+        } else if dest.symlink_metadata().is_ok() {
+            fs::remove_file(&dest)?;
+        }
+        fs::rename(entry.path(), &dest)
+            .with_context(|| format!("Failed to restore {}", dest.display()))?;
+    }
+    fs::remove_dir_all(&tmp)?;
+    Ok(())
+}
+
+/// Write the current cache key; returns false if inputs are unreadable.
+fn write_key(base_dir: &Path) -> Result<bool> {
+    let Some(key) = rootfs_cache_key(base_dir) else {
+        return Ok(false);
+    };
+    let output_dir = crate::paths::output_dir(base_dir);
+    fs::create_dir_all(&output_dir)?;
+    fs::write(output_dir.join(ROOTFS_CACHE_KEY_FILE), key)?;
+    Ok(true)
+}
+
+/// Restore `downloads/rootfs` from the store if it is missing.
+///
+/// Returns true if the rootfs was restored.
+pub fn restore_rootfs(store: &ArtifactStore, base_dir: &Path) -> Result<bool> {
+    let downloads = base_dir.join("downloads");
+    if downloads.join("rootfs/bin").exists() || !write_key(base_dir)? {
+        return Ok(false);
+    }
+
+    let output_dir = crate::paths::output_dir(base_dir);
+    let key_file = output_dir.join(ROOTFS_CACHE_KEY_FILE);
+    let archive = output_dir.join("alpine-rootfs.tar.zst");
+    let restored = store_meta::restore_checked(store, ROOTFS_CACHE_KIND, &key_file, &archive)?;
+    if restored {
+        fs::create_dir_all(&downloads)?;
+        let result = unpack_dir(&archive, &downloads);
+        let _ = fs::remove_file(&archive);
+        result?;
+    }
+    Ok(restored)
+}
+
+/// Store `downloads/rootfs` in the artifact store unless this key is already stored.
+///
+/// Returns true if a new payload was stored.
+pub fn store_rootfs(store: &ArtifactStore, base_dir: &Path) -> Result<bool> {
+    let downloads = base_dir.join("downloads");
+    let output_dir = crate::paths::output_dir(base_dir);
+    let key_file = output_dir.join(ROOTFS_CACHE_KEY_FILE);
+    let stored_marker = output_dir.join(".alpine-rootfs-stored.hash");
+
+    if !downloads.join("rootfs/bin").exists() || !write_key(base_dir)? {
+        return Ok(false);
+    }
+    let key = fs::read_to_string(&key_file)?;
+    if fs::read_to_string(&stored_marker).ok().as_deref() == Some(key.as_str()) {
+        return Ok(false);
+    }
+
+    let archive = output_dir.join("alpine-rootfs.tar.zst");
+    pack_dir(&downloads, CACHED_ENTRIES, &archive)?;
+    let meta = iso_pin(base_dir)
+        .map(|pin| ("alpine_iso_sha256".to_string(), pin))
+        .into_iter()
+        .collect();
+    let result = store_meta::store_checked(store, ROOTFS_CACHE_KIND, &key_file, &archive, meta);
+    let _ = fs::remove_file(&archive);
+    result?;
+    fs::write(&stored_marker, key)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::{symlink, MetadataExt, PermissionsExt};
+    use tempfile::tempdir;
+
+    fn mode(path: &Path) -> u32 {
+        fs::symlink_metadata(path).unwrap().mode()
+    }
+
+    #[test]
+    fn test_pack_unpack_fidelity() {
+        let src = tempdir().unwrap();
+        let rootfs = src.path().join("rootfs");
+        fs::create_dir_all(rootfs.join("bin")).unwrap();
+        fs::create_dir_all(rootfs.join("root")).unwrap();
+        fs::create_dir_all(rootfs.join("usr/lib")).unwrap();
+        fs::write(rootfs.join("bin/busybox"), "bb").unwrap();
+        fs::set_permissions(
+            rootfs.join("bin/busybox"),
+            fs::Permissions::from_mode(0o4755),
+        )
+        .unwrap();
+        fs::set_permissions(rootfs.join("root"), fs::Permissions::from_mode(0o700)).unwrap();
+        fs::write(rootfs.join("etc-shadow"), "x").unwrap();
+        fs::set_permissions(rootfs.join("etc-shadow"), fs::Permissions::from_mode(0o600)).unwrap();
+        symlink("/bin/busybox", rootfs.join("bin/sh")).unwrap();
+        symlink("../../bin/busybox", rootfs.join("usr/lib/rel")).unwrap();
+        symlink("/nonexistent", rootfs.join("dangling")).unwrap();
+        Cmd::new("mkfifo")
+            .arg_path(&rootfs.join("initctl"))
+            .run()
+            .unwrap();
+        fs::write(src.path().join(".packages-version"), "1.0.2").unwrap();
+
+        let archive = src.path().join("cache.tar.zst");
+        pack_dir(src.path(), CACHED_ENTRIES, &archive).unwrap();
+
+        let dest = tempdir().unwrap();
+        // Stale content is replaced, unrelated entries are kept
+        fs::create_dir_all(dest.path().join("rootfs/stale")).unwrap();
+        fs::write(dest.path().join("alpine.iso"), "iso").unwrap();
+        unpack_dir(&archive, dest.path()).unwrap();
+
+        let out = dest.path().join("rootfs");
+        assert!(!out.join("stale").exists());
+        assert!(dest.path().join("alpine.iso").exists());
+        assert!(!dest.path().join(".alpine-built-version").exists());
+        assert_eq!(
+            fs::read_to_string(dest.path().join(".packages-version")).unwrap(),
+            "1.0.2"
+        );
+        assert_eq!(
+            fs::read_link(out.join("bin/sh")).unwrap(),
+            Path::new("/bin/busybox")
+        );
+        assert_eq!(
+            fs::read_link(out.join("usr/lib/rel")).unwrap(),
+            Path::new("../../bin/busybox")
+        );
+        assert_eq!(
+            fs::read_link(out.join("dangling")).unwrap(),
+            Path::new("/nonexistent")
+        );
+        assert_eq!(mode(&out.join("bin/busybox")) & 0o7777, 0o4755);
+        assert_eq!(mode(&out.join("root")) & 0o7777, 0o700);
+        assert_eq!(mode(&out.join("etc-shadow")) & 0o7777, 0o600);
+        assert_eq!(mode(&out.join("initctl")) & 0o170000, 0o010000);
+        assert!(!dest.path().join(".rootfs-cache.restore").exists());
+    }
+
+    #[test]
+    fn test_unpack_corrupt_archive_keeps_existing() {
+        let dir = tempdir().unwrap();
+        let archive = dir.path().join("bad.tar.zst");
+        fs::write(&archive, "not an archive").unwrap();
+        let dest = dir.path().join("downloads");
+        fs::create_dir_all(dest.join("rootfs/bin")).unwrap();
+
+        assert!(unpack_dir(&archive, &dest).is_err());
+        assert!(dest.join("rootfs/bin").exists());
+        assert!(!dest.join(".rootfs-cache.restore").exists());
+    }
+}
@@ -0,0 +1,177 @@
+//! `acornos shell`: a shell inside the staged rootfs, without booting QEMU.
+//!
+//! The tree gets /proc, /sys and /dev bind-mounted and is entered with
+//! chroot. The mounts are made in a private mount namespace, so they go
+//! away with the shell however it exits (killed included) and are never
+//! visible on the host. As root that is a plain chroot; otherwise a user
+//! namespace maps the build user to root first.
+//!
+//! acornos replaces itself with the shell (exec), so Ctrl-C in the shell
+//! never reaches a parent that could die with the mounts still set up.
+
+use anyhow::{bail, Context, Result};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::paths::OutputPaths;
+use crate::privilege;
+use distro_builder::alpine::extract::ExtractPaths;
+
+/// Host filesystems bind-mounted into the tree.
+const BIND_MOUNTS: &[&str] = &["proc", "sys", "dev"];
+
+/// Runs in the new mount namespace with the tree as `$1`.
+const ENTER_SCRIPT: &str = r#"set -e
+root="$1"
+for fs in proc sys dev; do
+    mkdir -p "$root/$fs"
+    mount --rbind "/$fs" "$root/$fs"
+done
+exec chroot "$root" /bin/sh -i"#;
+
+/// Tree to enter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellTarget {
+    /// `rootfs-staging` in the output dir (what the image is built from).
+    Staging,
+    /// `downloads/rootfs` (the Alpine rootfs components copy from).
+    Rootfs,
+}
+
+impl ShellTarget {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Staging => "staging",
+            Self::Rootfs => "rootfs",
+        }
+    }
+
+    pub fn path(&self, base_dir: &Path, output_dir: &Path) -> PathBuf {
+        match self {
+            Self::Staging => OutputPaths::new(output_dir).rootfs_staging(),
+            Self::Rootfs => ExtractPaths::new(base_dir).rootfs,
+        }
+    }
+}
+
+/// How the tree is entered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellMode {
+    /// Already root: chroot in a private mount namespace.
+    Chroot,
+    /// Not root: a user namespace mapping the build user to root.
+    UserNamespace,
+}
+
+impl ShellMode {
+    /// Mode for this host, or an error saying how to enable user namespaces.
+    pub fn detect() -> Result<Self> {
+        if privilege::is_root() {
+            Ok(Self::Chroot)
+        } else if privilege::user_namespaces_available() {
+            Ok(Self::UserNamespace)
+        } else {
+            bail!(
+                "Cannot enter the tree: not root and unprivileged user namespaces are unavailable.\n\
+                 Enable them with one of (depending on the distribution):\n  \
+                 sudo sysctl -w kernel.unprivileged_userns_clone=1\n  \
+                 sudo sysctl -w user.max_user_namespaces=28633\n  \
+                 sudo sysctl -w kernel.apparmor_restrict_unprivileged_userns=0\n\
+                 or run 'sudo acornos shell'."
+            )
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Self::Chroot => "chroot as root",
+            Self::UserNamespace => "chroot in a user namespace",
+        }
+    }
+}
+
+/// The command entering `root` with `mode`.
+pub fn shell_command(mode: ShellMode, target: ShellTarget, root: &Path) -> Command {
+    let mut cmd = Command::new("unshare");
+    if mode == ShellMode::UserNamespace {
+        cmd.arg("--map-root-user");
+    }
+    cmd.args(["--mount", "--propagation", "private", "--"])
+        .args(["sh", "-c", ENTER_SCRIPT, "acornos-shell"])
+        .arg(root);
+
+    // A clean environment, and a prompt that is not the host's
+    cmd.env_clear()
+        .env("PATH", "/usr/sbin:/usr/bin:/sbin:/bin")
+        .env("HOME", "/root")
+        .env("PS1", format!("[acornos {}] \\w # ", target.label()));
+    if let Some(term) = std::env::var_os("TERM") {
+        cmd.env("TERM", term);
+    }
+    cmd
+}
+
+/// Refuse trees that are not a (complete) rootfs.
+pub fn check_tree(root: &Path) -> Result<()> {
+    if !root.join("bin/busybox").exists() {
+        bail!(
+            "{} has no bin/busybox; build it first ('acornos build rootfs' or 'acornos download alpine')",
+            root.display()
+        );
+    }
+    Ok(())
+}
+
+/// Replace this process with a shell in `target`; only returns on error.
+pub fn run(base_dir: &Path, output_dir: &Path, target: ShellTarget) -> Result<()> {
+    let root = target.path(base_dir, output_dir);
+    check_tree(&root)?;
+    let mode = ShellMode::detect()?;
+
+    println!(
+        "Entering {} ({}); /{} bind-mounted, undone on exit",
+        root.display(),
+        mode.describe(),
+        BIND_MOUNTS.join(", /")
+    );
+    let err = shell_command(mode, target, &root).exec();
+    Err(err).context("Failed to run unshare (util-linux)")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_shell_command_and_tree_check() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("rootfs-staging");
+        assert!(check_tree(&root)
+            .unwrap_err()
+            .to_string()
+            .contains("no bin/busybox"));
+        std::fs::create_dir_all(root.join("bin")).unwrap();
+        std::fs::write(root.join("bin/busybox"), "").unwrap();
+        check_tree(&root).unwrap();
+
+        let args = |mode| {
+            shell_command(mode, ShellTarget::Staging, &root)
+                .get_args()
+                .map(|a| a.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+        let unprivileged = args(ShellMode::UserNamespace);
+        assert_eq!(unprivileged[..2], ["--map-root-user", "--mount"]);
+        assert_eq!(unprivileged.last().unwrap(), &root.to_string_lossy());
+        assert_eq!(args(ShellMode::Chroot)[0], "--mount");
+
+        let cmd = shell_command(ShellMode::Chroot, ShellTarget::Rootfs, &root);
+        let ps1 = cmd.get_envs().find(|(k, _)| *k == "PS1").unwrap().1;
+        assert_eq!(ps1.unwrap(), "[acornos rootfs] \\w # ");
+        for fs in BIND_MOUNTS {
+            assert!(ENTER_SCRIPT.contains(fs));
+        }
+    }
+}
@@ -0,0 +1,451 @@
+//! Release checksums and signatures for AcornOS ISOs.
+//!
+//! Every ISO gets a `AcornOS.iso.sha512` sidecar. When a signing key is
+//! configured, the sidecar is signed into `AcornOS.iso.sha512.sig`; since the
+//! checksum covers the ISO, one signature authenticates both.
+//!
+//! Signing is configured through the environment:
+//! - `ACORNOS_SIGNING_KEY` - minisign secret key path, or gpg key ID/fingerprint
+//! - `ACORNOS_SIGNING_METHOD` - `minisign` (default) or `gpg`
+//!
+//! External tools are invoked through [`ToolRunner`] so argument construction
+//! and error mapping can be tested without real keys.
+
+use anyhow::{bail, Context, Result};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use distro_builder::process::Cmd;
+
+/// Environment variable holding the signing key.
+pub const SIGNING_KEY_ENV: &str = "ACORNOS_SIGNING_KEY";
+
+/// Environment variable selecting the signing tool.
+pub const SIGNING_METHOD_ENV: &str = "ACORNOS_SIGNING_METHOD";
+
+/// Tool used to sign and verify releases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigningMethod {
+    Minisign,
+    Gpg,
+}
+
+impl FromStr for SigningMethod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "minisign" => Ok(Self::Minisign),
+            "gpg" => Ok(Self::Gpg),
+            other => bail!(
+                "Unknown signing method '{}' (expected minisign or gpg)",
+                other
+            ),
+        }
+    }
+}
+
+impl SigningMethod {
+    fn install_hint(self) -> &'static str {
+        match self {
+            Self::Minisign => "minisign not found. Install: sudo dnf install minisign",
+            Self::Gpg => "gpg not found. Install: sudo dnf install gnupg2",
+        }
+    }
+}
+
+/// Signing key configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SigningConfig {
+    pub method: SigningMethod,
+    pub key: String,
+}
+
+impl SigningConfig {
+    /// Read signing configuration from the environment (`None` if no key is set).
+    pub fn from_env() -> Result<Option<Self>> {
+        Self::from_vars(
+            std::env::var(SIGNING_KEY_ENV).ok(),
+            std::env::var(SIGNING_METHOD_ENV).ok(),
+        )
+    }
+
+    fn from_vars(key: Option<String>, method: Option<String>) -> Result<Option<Self>> {
+        let key = match key.filter(|k| !k.trim().is_empty()) {
+            Some(k) => k,
+            None => return Ok(None),
+        };
+        let method = match method {
+            Some(m) => m.parse()?,
+            None => SigningMethod::Minisign,
+        };
+        Ok(Some(Self { method, key }))
+    }
+}
+
+/// Result of running an external tool.
+#[derive(Debug, Clone, Default)]
+pub struct ToolOutput {
+    pub success: bool,
+    pub stderr: String,
+}
+
+/// Runs external signing tools.
+pub trait ToolRunner {
+    /// Run `program` with `args`; errors only if it could not be started.
+    fn run(&self, program: &str, args: &[OsString]) -> std::io::Result<ToolOutput>;
+}
+
+/// Runs tools on the host.
+pub struct SystemRunner;
+
+impl ToolRunner for SystemRunner {
+    fn run(&self, program: &str, args: &[OsString]) -> std::io::Result<ToolOutput> {
+        let output = std::process::Command::new(program).args(args).output()?;
+        Ok(ToolOutput {
+            success: output.status.success(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        })
+    }
+}
+
+/// Path of the SHA512 sidecar for `iso`.
+pub fn sha512_path(iso: &Path) -> PathBuf {
+    append_ext(iso, "sha512")
+}
+
+/// Path of the signature over the SHA512 sidecar for `iso`.
+pub fn signature_path(iso: &Path) -> PathBuf {
+    append_ext(&sha512_path(iso), "sig")
+}
+
+fn append_ext(path: &Path, ext: &str) -> PathBuf {
+    let mut s = path.as_os_str().to_owned();
+    s.push(".");
+    s.push(ext);
+    PathBuf::from(s)
+}
+
+/// Command line that signs `file` into `sig`.
+fn sign_args(config: &SigningConfig, file: &Path, sig: &Path) -> (&'static str, Vec<OsString>) {
+    let file = file.as_os_str().to_owned();
+    let sig = sig.as_os_str().to_owned();
+    match config.method {
+        SigningMethod::Minisign => (
+            "minisign",
+            vec![
+                "-S".into(),
+                "-s".into(),
+                config.key.clone().into(),
+                "-m".into(),
+                file,
+                "-x".into(),
+                sig,
+            ],
+        ),
+        SigningMethod::Gpg => (
+            "gpg",
+            vec![
+                "--batch".into(),
+                "--yes".into(),
+                "--local-user".into(),
+                config.key.clone().into(),
+                "--output".into(),
+                sig,
+                "--detach-sign".into(),
+                file,
+            ],
+        ),
+    }
+}
+
+/// Command line that verifies `sig` over `file` against `pubkey`.
+fn verify_args(
+    method: SigningMethod,
+    pubkey: &Path,
+    file: &Path,
+    sig: &Path,
+) -> (&'static str, Vec<OsString>) {
+    let pubkey = pubkey.as_os_str().to_owned();
+    let file = file.as_os_str().to_owned();
+    let sig = sig.as_os_str().to_owned();
+    match method {
+        SigningMethod::Minisign => (
+            "minisign",
+            vec![
+                "-V".into(),
+                "-p".into(),
+                pubkey,
+                "-m".into(),
+                file,
+                "-x".into(),
+                sig,
+            ],
+        ),
+        SigningMethod::Gpg => ("gpgv", vec!["--keyring".into(), pubkey, sig, file]),
+    }
+}
+
+/// Run a signing tool, mapping spawn failures and non-zero exits to errors.
+fn run_tool(
+    runner: &dyn ToolRunner,
+    method: SigningMethod,
+    (program, args): (&str, Vec<OsString>),
+    what: &str,
+) -> Result<()> {
+    let output = runner
+        .run(program, &args)
+        .with_context(|| method.install_hint())?;
+    if !output.success {
+        bail!("{} failed ({}): {}", what, program, output.stderr);
+    }
+    Ok(())
+}
+
+/// Sign `file` with the configured key, returning the signature path.
+pub fn sign_file(runner: &dyn ToolRunner, config: &SigningConfig, file: &Path) -> Result<PathBuf> {
+    let sig = append_ext(file, "sig");
+    run_tool(
+        runner,
+        config.method,
+        sign_args(config, file, &sig),
+        "Signing",
+    )?;
+    Ok(sig)
+}
+
+/// Verify the signature `sig` over `file`.
+pub fn verify_signature(
+    runner: &dyn ToolRunner,
+    method: SigningMethod,
+    pubkey: &Path,
+    file: &Path,
+    sig: &Path,
+) -> Result<()> {
+    if !sig.exists() {
+        bail!("Signature not found at {}", sig.display());
+    }
+    run_tool(
+        runner,
+        method,
+        verify_args(method, pubkey, file, sig),
+        "Signature verification",
+    )
+}
+
+/// Compute the SHA512 of a file using `sha512sum`.
+pub(crate) fn sha512_file(path: &Path) -> Result<String> {
+    let result = Cmd::new("sha512sum")
+        .arg_path(path)
+        .error_msg("sha512sum failed. Install coreutils.")
+        .run()?;
+
+    match result.stdout.split_whitespace().next() {
+        Some(hash) => Ok(hash.to_lowercase()),
+        None => bail!("sha512sum produced no output for {}", path.display()),
+    }
+}
+
+/// Write the `sha512sum`-format sidecar for `iso`.
+pub fn write_sha512(iso: &Path) -> Result<PathBuf> {
+    let hash = sha512_file(iso)?;
+    let name = iso
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let sidecar = sha512_path(iso);
+    fs::write(&sidecar, format!("{}  {}\n", hash, name))?;
+    Ok(sidecar)
+}
+
+/// Check `iso` against its SHA512 sidecar.
+pub fn verify_sha512(iso: &Path) -> Result<()> {
+    let sidecar = sha512_path(iso);
+    let content = fs::read_to_string(&sidecar)
+        .with_context(|| format!("Checksum file not found at {}", sidecar.display()))?;
+    let expected = content
+        .split_whitespace()
+        .next()
+        .unwrap_or("")
+        .to_lowercase();
+    let actual = sha512_file(iso)?;
+    if expected != actual {
+        bail!(
+            "SHA512 mismatch for {} (expected {}, got {})",
+            iso.display(),
+            expected,
+            actual
+        );
+    }
+    Ok(())
+}
+
+/// Write the checksum sidecar and sign it if a key is configured.
+pub fn finalize_release(runner: &dyn ToolRunner, iso: &Path) -> Result<()> {
+    let sidecar = write_sha512(iso)?;
+    println!("  Checksum: {}", sidecar.display());
+
+    match SigningConfig::from_env()? {
+        Some(config) => {
+            let sig = sign_file(runner, &config, &sidecar)?;
+            println!("  Signature: {}", sig.display());
+        }
+        None => {
+            // Stale signatures would no longer match the new checksum
+            let _ = fs::remove_file(signature_path(iso));
+            println!("  [SKIP] Release signing ({} not set)", SIGNING_KEY_ENV);
+        }
+    }
+    Ok(())
+}
+
+/// Verify a released ISO: SHA512 sidecar first, then its signature.
+pub fn verify_release(
+    runner: &dyn ToolRunner,
+    iso: &Path,
+    method: SigningMethod,
+    pubkey: &Path,
+) -> Result<()> {
+    if !iso.exists() {
+        bail!("ISO not found at {}", iso.display());
+    }
+    verify_sha512(iso)?;
+    verify_signature(
+        runner,
+        method,
+        pubkey,
+        &sha512_path(iso),
+        &signature_path(iso),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use tempfile::tempdir;
+
+    struct MockRunner {
+        calls: RefCell<Vec<(String, Vec<String>)>>,
+        result: fn() -> std::io::Result<ToolOutput>,
+    }
+
+    impl MockRunner {
+        fn new(result: fn() -> std::io::Result<ToolOutput>) -> Self {
+            Self {
+                calls: RefCell::new(Vec::new()),
+                result,
+            }
+        }
+    }
+
+    impl ToolRunner for MockRunner {
+        fn run(&self, program: &str, args: &[OsString]) -> std::io::Result<ToolOutput> {
+            self.calls.borrow_mut().push((
+                program.to_string(),
+                args.iter()
+                    .map(|a| a.to_string_lossy().into_owned())
+                    .collect(),
+            ));
+            (self.result)()
+        }
+    }
+
+    fn ok() -> std::io::Result<ToolOutput> {
+        Ok(ToolOutput {
+            success: true,
+            stderr: String::new(),
+        })
+    }
+
+    #[test]
+    fn test_signing_config_from_vars() {
+        assert_eq!(SigningConfig::from_vars(None, None).unwrap(), None);
+        assert_eq!(
+            SigningConfig::from_vars(Some(" ".into()), Some("gpg".into())).unwrap(),
+            None
+        );
+        let config = SigningConfig::from_vars(Some("key".into()), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(config.method, SigningMethod::Minisign);
+        assert!(SigningConfig::from_vars(Some("key".into()), Some("pgp".into())).is_err());
+    }
+
+    #[test]
+    fn test_sign_argument_construction() {
+        let runner = MockRunner::new(ok);
+        let file = Path::new("/out/AcornOS.iso.sha512");
+
+        let minisign = SigningConfig {
+            method: SigningMethod::Minisign,
+            key: "/keys/acorn.key".into(),
+        };
+        let sig = sign_file(&runner, &minisign, file).unwrap();
+        assert_eq!(sig, PathBuf::from("/out/AcornOS.iso.sha512.sig"));
+
+        let gpg = SigningConfig {
+            method: SigningMethod::Gpg,
+            key: "ABCD1234".into(),
+        };
+        sign_file(&runner, &gpg, file).unwrap();
+
+        let calls = runner.calls.borrow();
+        assert_eq!(calls[0].0, "minisign");
+        assert_eq!(
+            calls[0].1.join(" "),
+            "-S -s /keys/acorn.key -m /out/AcornOS.iso.sha512 -x /out/AcornOS.iso.sha512.sig"
+        );
+        assert_eq!(calls[1].0, "gpg");
+        assert!(calls[1].1.join(" ").ends_with(
+            "--local-user ABCD1234 --output /out/AcornOS.iso.sha512.sig --detach-sign /out/AcornOS.iso.sha512"
+        ));
+    }
+
+    #[test]
+    fn test_tool_error_mapping() {
+        let missing = MockRunner::new(|| Err(std::io::ErrorKind::NotFound.into()));
+        let config = SigningConfig {
+            method: SigningMethod::Minisign,
+            key: "k".into(),
+        };
+        let err = sign_file(&missing, &config, Path::new("f")).unwrap_err();
+        assert!(format!("{:#}", err).contains("Install: sudo dnf install minisign"));
+
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("AcornOS.iso.sha512");
+        let sig = append_ext(&file, "sig");
+        fs::write(&sig, "sig").unwrap();
+        let bad = MockRunner::new(|| {
+            Ok(ToolOutput {
+                success: false,
+                stderr: "BAD signature".into(),
+            })
+        });
+        let err = verify_signature(&bad, SigningMethod::Gpg, Path::new("pub.gpg"), &file, &sig)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("gpgv") && err.contains("BAD signature"));
+        assert_eq!(bad.calls.borrow()[0].1[..2], ["--keyring", "pub.gpg"]);
+    }
+
+    #[test]
+    fn test_sha512_sidecar_roundtrip() {
+        let dir = tempdir().unwrap();
+        let iso = dir.path().join("AcornOS.iso");
+        fs::write(&iso, "acorn").unwrap();
+
+        let sidecar = write_sha512(&iso).unwrap();
+        let content = fs::read_to_string(&sidecar).unwrap();
+        assert!(content.ends_with("  AcornOS.iso\n"));
+        verify_sha512(&iso).unwrap();
+
+        fs::write(&iso, "tampered").unwrap();
+        assert!(verify_sha512(&iso)
+            .unwrap_err()
+            .to_string()
+            .contains("SHA512 mismatch"));
+    }
+}
@@ -0,0 +1,265 @@
+//! Completeness of the Alpine rootfs in `downloads/rootfs`.
+//!
+//! deps/alpine.rhai installs Tier 0 into `rootfs.work` and renames it to
+//! `rootfs` only after apk succeeded and the post-checks passed (apk
+//! database present, busybox executable). The rename carries a
+//! `.rootfs-complete` stamp holding the SHA256 of the Tier 0 package list,
+//! so an interrupted apk run (Ctrl-C, disk full) never leaves a rootfs
+//! that looks built. Without this, a half-populated rootfs surfaced much
+//! later as missing binaries in the component executor.
+//!
+//! A rootfs with no stamp predates it: it is validated once and stamped,
+//! or recreated like one whose stamp is stale.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use distro_builder::alpine::extract::ExtractPaths;
+
+use crate::apk::INSTALLED_DB;
+use crate::arch::Arch;
+
+/// Stamp inside the rootfs, written by deps/alpine.rhai.
+pub const ROOTFS_STAMP: &str = ".rootfs-complete";
+
+/// Recipe holding the Tier 0 package list.
+///
+/// Canonical source: deps/alpine.rhai (`base_packages`).
+pub const ALPINE_RECIPE: &str = "deps/alpine.rhai";
+
+/// Condition of the source rootfs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RootfsState {
+    /// Stamped for the current package list.
+    Complete,
+    /// No rootfs at all.
+    Missing,
+    /// Stamped for another package list.
+    Stale,
+    /// No stamp, but the post-checks pass: a rootfs from before the stamp.
+    Legacy,
+    /// No stamp and a failed post-check, e.g. an interrupted apk run.
+    Incomplete(String),
+}
+
+/// Tier 0 packages from the `let tier0 = "...";` line of the recipe, with
+/// `busybox-static` on arches busybox.net has no static binary for.
+pub fn base_packages(recipe: &str, arch: Arch) -> Option<Vec<String>> {
+    let line = recipe
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("let tier0 = "))?;
+    let list = line.trim_end_matches(';').trim_matches('"');
+    let mut packages: Vec<String> = list.split_whitespace().map(str::to_string).collect();
+    if arch != Arch::X86_64 {
+        packages.push("busybox-static".to_string());
+        packages.push("apk-tools-static".to_string());
+    }
+    Some(packages)
+}
+
+/// SHA256 of the package list as the recipe hashes it (space-separated).
+pub fn package_list_hash(packages: &[String]) -> Result<String> {
+    let mut child = Command::new("sha256sum")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("sha256sum failed. Install coreutils.")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(packages.join(" ").as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match stdout.split_whitespace().next() {
+        Some(hash) if output.status.success() => Ok(hash.to_lowercase()),
+        _ => bail!("sha256sum produced no hash for the package list"),
+    }
+}
+
+/// Tier 0 packages of the current recipe and arch.
+pub(crate) fn current_packages(base_dir: &Path) -> Result<Vec<String>> {
+    let path = base_dir.join(ALPINE_RECIPE);
+    let recipe =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    base_packages(&recipe, Arch::from_env()?)
+        .with_context(|| format!("No tier0 package list in {}", ALPINE_RECIPE))
+}
+
+/// The post-checks: what is wrong with `rootfs`, or `None` if usable.
+pub fn problem(rootfs: &Path) -> Option<String> {
+    if !rootfs.join(INSTALLED_DB).is_file() {
+        return Some("apk database missing".to_string());
+    }
+    let executable = fs::metadata(rootfs.join("bin/busybox")).is_ok_and(|meta| {
+        use std::os::unix::fs::PermissionsExt;
+        meta.is_file() && meta.permissions().mode() & 0o111 != 0
+    });
+    if !executable {
+        return Some("bin/busybox missing or not executable".to_string());
+    }
+    None
+}
+
+/// Classify `rootfs` against the package list hash `hash`.
+pub fn state(rootfs: &Path, hash: &str, packages: &[String]) -> RootfsState {
+    if !rootfs.is_dir() {
+        return RootfsState::Missing;
+    }
+    if let Ok(stamp) = fs::read_to_string(rootfs.join(ROOTFS_STAMP)) {
+        return if stamp.trim() == hash {
+            RootfsState::Complete
+        } else {
+            RootfsState::Stale
+        };
+    }
+    if let Some(problem) = problem(rootfs) {
+        return RootfsState::Incomplete(problem);
+    }
+    let world = fs::read_to_string(rootfs.join("etc/apk/world")).unwrap_or_default();
+    match packages
+        .iter()
+        .find(|p| !world.split_whitespace().any(|w| w == p.as_str()))
+    {
+        Some(missing) => RootfsState::Incomplete(format!("{} not in etc/apk/world", missing)),
+        None => RootfsState::Legacy,
+    }
+}
+
+/// State of `downloads/rootfs` for the current recipe, with the hash its
+/// stamp must hold.
+fn current_state(base_dir: &Path) -> Result<(RootfsState, String)> {
+    let packages = current_packages(base_dir)?;
+    let hash = package_list_hash(&packages)?;
+    let rootfs = ExtractPaths::new(base_dir).rootfs;
+    Ok((state(&rootfs, &hash, &packages), hash))
+}
+
+/// Whether `downloads/rootfs` is stamped for the current package list.
+pub fn is_complete(base_dir: &Path) -> bool {
+    matches!(current_state(base_dir), Ok((RootfsState::Complete, _)))
+}
+
+/// Make sure `downloads/rootfs` is complete before building from it.
+///
+/// A legacy rootfs that passes validation is stamped; a stale or
+/// incomplete one is recreated through the recipes.
+pub fn ensure_complete(base_dir: &Path) -> Result<()> {
+    let rootfs = ExtractPaths::new(base_dir).rootfs;
+    let (state, hash) = current_state(base_dir)?;
+    match state {
+        RootfsState::Complete => Ok(()),
+        RootfsState::Missing => bail!(
+            "Rootfs not found at {}.\n\
+             Run 'acornos download alpine' first.",
+            rootfs.display()
+        ),
+        RootfsState::Legacy => {
+            fs::write(rootfs.join(ROOTFS_STAMP), &hash)
+                .with_context(|| format!("Failed to stamp {}", rootfs.display()))?;
+            log::info!("  [OK] Validated rootfs from an earlier build");
+            Ok(())
+        }
+        RootfsState::Stale | RootfsState::Incomplete(_) => {
+            let why = match &state {
+                RootfsState::Incomplete(problem) => format!("incomplete ({})", problem),
+                _ => "built for another package list".to_string(),
+            };
+            log::warn!("Rootfs at {} is {}; recreating", rootfs.display(), why);
+            recreate(base_dir)?;
+            match current_state(base_dir)?.0 {
+                RootfsState::Complete => Ok(()),
+                other => bail!(
+                    "Rootfs at {} is still not complete after recreating it ({:?}).\n\
+                     Run 'acornos download alpine --force'.",
+                    rootfs.display(),
+                    other
+                ),
+            }
+        }
+    }
+}
+
+/// Rerun the recipes that create `downloads/rootfs`, as
+/// `acornos download alpine` does. The alpine recipe replaces a rootfs
+/// without a matching stamp and clears the package markers, so the
+/// supplementary tiers are installed again.
+fn recreate(base_dir: &Path) -> Result<()> {
+    Arch::from_env()?.check_build_host()?;
+    let alpine = distro_builder::recipe::alpine::alpine(base_dir)?;
+    distro_builder::alpine::keys::install_keys(
+        &alpine.rootfs,
+        distro_spec::acorn::packages::ALPINE_KEYS,
+    )?;
+    crate::apk_install::install_supplementary(base_dir)?;
+    distro_builder::recipe::packages(base_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::tempdir;
+
+    fn recipe() -> String {
+        fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join(ALPINE_RECIPE)).unwrap()
+    }
+
+    #[test]
+    fn test_base_packages_from_recipe() {
+        let x86 = base_packages(&recipe(), Arch::X86_64).unwrap();
+        assert_eq!(x86[0], "alpine-base");
+        assert!(x86.contains(&"linux-lts".to_string()));
+        assert!(!x86.contains(&"busybox-static".to_string()));
+        let arm = base_packages(&recipe(), Arch::Aarch64).unwrap();
+        assert!(arm.ends_with(&["busybox-static".into(), "apk-tools-static".into()]));
+
+        let hash = package_list_hash(&["alpine-base".into(), "openrc".into()]).unwrap();
+        assert_eq!(hash.len(), 64);
+        assert_ne!(hash, package_list_hash(&["alpine-base".into()]).unwrap());
+    }
+
+    #[test]
+    fn test_state_of_interrupted_legacy_and_stamped_rootfs() {
+        let dir = tempdir().unwrap();
+        let rootfs = dir.path().join("rootfs");
+        let packages = vec!["alpine-base".to_string(), "openrc".to_string()];
+        assert_eq!(state(&rootfs, "h", &packages), RootfsState::Missing);
+
+        // apk interrupted before writing its database
+        fs::create_dir_all(rootfs.join("bin")).unwrap();
+        fs::write(rootfs.join("bin/busybox"), "").unwrap();
+        assert_eq!(
+            state(&rootfs, "h", &packages),
+            RootfsState::Incomplete("apk database missing".into())
+        );
+
+        let db = rootfs.join(INSTALLED_DB);
+        fs::create_dir_all(db.parent().unwrap()).unwrap();
+        fs::write(&db, "").unwrap();
+        assert_eq!(
+            state(&rootfs, "h", &packages),
+            RootfsState::Incomplete("bin/busybox missing or not executable".into())
+        );
+
+        fs::set_permissions(
+            rootfs.join("bin/busybox"),
+            fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+        fs::create_dir_all(rootfs.join("etc/apk")).unwrap();
+        fs::write(rootfs.join("etc/apk/world"), "alpine-base\n").unwrap();
+        assert_eq!(
+            state(&rootfs, "h", &packages),
+            RootfsState::Incomplete("openrc not in etc/apk/world".into())
+        );
+
+        fs::write(rootfs.join("etc/apk/world"), "alpine-base\nopenrc\nvim\n").unwrap();
+        assert_eq!(state(&rootfs, "h", &packages), RootfsState::Legacy);
+
+        fs::write(rootfs.join(ROOTFS_STAMP), "h\n").unwrap();
+        assert_eq!(state(&rootfs, "h", &packages), RootfsState::Complete);
+        assert_eq!(state(&rootfs, "other", &packages), RootfsState::Stale);
+    }
+}
@@ -0,0 +1,432 @@
+//! Integrity metadata for artifact-store entries.
+//!
+//! The artifact store restores whatever file it holds for a key, so a
+//! truncated entry would be restored silently and end up in the ISO. Every
+//! stored artifact's SHA256 and size go into the store's metadata map and
+//! into a second store entry, `<kind>_checksum`, under the same key; a
+//! restore is only accepted if the restored file matches it. An entry
+//! without a checksum (stored before checksums existed, or by another
+//! tool) is a miss, so the artifact is rebuilt and stored again.
+//!
+//! A copy of each checksum also goes under `.store-meta/` next to the key
+//! files, as the index `acornos store verify` walks: it restores every
+//! entry listed there and reports it as valid, stale (its inputs changed
+//! since), corrupt, or missing from the store.
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::offline::sha256_file;
+use crate::pipeline::ArtifactCache;
+
+/// Index of stored entries, next to the key files in the output dir.
+pub const STORE_META_DIR: &str = ".store-meta";
+
+/// Scratch directory for `store verify`.
+const VERIFY_TMP: &str = ".store-verify.tmp";
+
+/// Hash and size of a stored artifact.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryMeta {
+    pub sha256: String,
+    pub size: u64,
+}
+
+impl EntryMeta {
+    pub fn of_file(path: &Path) -> Result<Self> {
+        Ok(Self {
+            sha256: sha256_file(path)?,
+            size: fs::metadata(path)
+                .with_context(|| format!("Failed to stat {}", path.display()))?
+                .len(),
+        })
+    }
+
+    /// Entries for the store's metadata map.
+    pub fn to_map(&self) -> BTreeMap<String, String> {
+        BTreeMap::from([
+            ("sha256".to_string(), self.sha256.clone()),
+            ("size".to_string(), self.size.to_string()),
+        ])
+    }
+}
+
+impl fmt::Display for EntryMeta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sha256 {} ({} bytes)", self.sha256, self.size)
+    }
+}
+
+/// What was stored for one kind and key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sidecar {
+    pub kind: String,
+    /// Key file name in the output dir (`.rootfs-inputs.hash`).
+    pub key_file: String,
+    /// Key file content when stored.
+    pub key: String,
+    pub meta: EntryMeta,
+}
+
+impl Sidecar {
+    /// Sidecar for `meta` stored as `kind` under the key in `key_file`;
+    /// `None` without a readable key.
+    fn new(kind: &str, key_file: &Path, meta: &EntryMeta) -> Option<Self> {
+        Some(Self {
+            kind: kind.to_string(),
+            key_file: key_file
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            key: read_key(key_file)?,
+            meta: meta.clone(),
+        })
+    }
+
+    fn path(meta_dir: &Path, kind: &str, key: &str) -> PathBuf {
+        let name: String = key
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || "._-".contains(c) {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        meta_dir.join(kind).join(name)
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        let fields: BTreeMap<&str, &str> = text.lines().filter_map(|l| l.split_once('=')).collect();
+        Some(Self {
+            kind: fields.get("kind")?.to_string(),
+            key_file: fields.get("key_file")?.to_string(),
+            key: fields.get("key")?.to_string(),
+            meta: EntryMeta {
+                sha256: fields.get("sha256")?.to_string(),
+                size: fields.get("size")?.parse().ok()?,
+            },
+        })
+    }
+
+    fn to_text(&self) -> String {
+        format!(
+            "kind={}\nkey_file={}\nkey={}\nsha256={}\nsize={}\n",
+            self.kind, self.key_file, self.key, self.meta.sha256, self.meta.size
+        )
+    }
+}
+
+/// Index directory for the key file at `key_file`.
+fn meta_dir(key_file: &Path) -> PathBuf {
+    key_file.with_file_name(STORE_META_DIR)
+}
+
+/// Store kind holding the checksum of a `kind` entry.
+fn checksum_kind(kind: &str) -> String {
+    format!("{}_checksum", kind)
+}
+
+/// Scratch file for the checksum entry of `out`, next to it.
+fn checksum_scratch(out: &Path) -> PathBuf {
+    let name = out
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    out.with_file_name(format!(".{}.checksum", name))
+}
+
+/// Current content of a key file, if any.
+fn read_key(key_file: &Path) -> Option<String> {
+    fs::read_to_string(key_file)
+        .ok()
+        .map(|k| k.trim().to_string())
+        .filter(|k| !k.is_empty())
+}
+
+/// Store `out` as `kind` under the key in `key_file`, its checksum with it
+/// and in the index. `extra` goes into the entry's metadata map besides
+/// the checksum.
+pub fn store_checked(
+    store: &dyn ArtifactCache,
+    kind: &str,
+    key_file: &Path,
+    out: &Path,
+    extra: BTreeMap<String, String>,
+) -> Result<()> {
+    let meta = EntryMeta::of_file(out)?;
+    let mut map = meta.to_map();
+    map.extend(extra);
+    store.store(kind, key_file, out, map)?;
+    // Without a readable key there is nothing to record it under
+    let Some(sidecar) = Sidecar::new(kind, key_file, &meta) else {
+        return Ok(());
+    };
+
+    let scratch = checksum_scratch(out);
+    fs::write(&scratch, sidecar.to_text())
+        .with_context(|| format!("Failed to write {}", scratch.display()))?;
+    let result = store.store(&checksum_kind(kind), key_file, &scratch, BTreeMap::new());
+    let _ = fs::remove_file(&scratch);
+    result?;
+
+    let path = Sidecar::path(&meta_dir(key_file), kind, &sidecar.key);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, sidecar.to_text())
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// The checksum stored with the `kind` entry under the key in `key_file`.
+fn restore_checksum(
+    store: &dyn ArtifactCache,
+    kind: &str,
+    key_file: &Path,
+    out: &Path,
+) -> Result<Option<Sidecar>> {
+    let scratch = checksum_scratch(out);
+    let _ = fs::remove_file(&scratch);
+    let text = if store.restore(&checksum_kind(kind), key_file, &scratch)? {
+        fs::read_to_string(&scratch).ok()
+    } else {
+        None
+    };
+    let _ = fs::remove_file(&scratch);
+    let key = read_key(key_file);
+    Ok(text
+        .and_then(|t| Sidecar::parse(&t))
+        .filter(|s| s.kind == kind && Some(&s.key) == key.as_ref()))
+}
+
+/// Restore `out` for `kind` and check it against the checksum stored with
+/// it. Without a checksum this is a miss; on a mismatch `out` is removed
+/// and a warning names both hashes.
+pub fn restore_checked(
+    store: &dyn ArtifactCache,
+    kind: &str,
+    key_file: &Path,
+    out: &Path,
+) -> Result<bool> {
+    let Some(stored) = restore_checksum(store, kind, key_file, out)? else {
+        log::debug!("  {} has no stored checksum; not restoring", kind);
+        return Ok(false);
+    };
+    if !store.restore(kind, key_file, out)? {
+        return Ok(false);
+    }
+    let actual = EntryMeta::of_file(out)?;
+    if actual == stored.meta {
+        return Ok(true);
+    }
+    log::warn!(
+        "Artifact store entry for {} is corrupt: expected {}, restored {}; rebuilding",
+        kind,
+        stored.meta,
+        actual
+    );
+    let _ = fs::remove_file(out);
+    Ok(false)
+}
+
+/// State of one stored entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryStatus {
+    /// Intact and matching the current inputs.
+    Valid,
+    /// Intact, but the inputs have changed since it was stored.
+    Stale,
+    /// The restored file does not match; holds what was restored.
+    Corrupt(EntryMeta),
+    /// No longer in the store, or stored without its checksum.
+    Missing,
+}
+
+impl EntryStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Valid => "VALID",
+            Self::Stale => "STALE",
+            Self::Corrupt(_) => "CORRUPT",
+            Self::Missing => "MISSING",
+        }
+    }
+}
+
+/// One entry checked by [`verify_entries`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryReport {
+    pub sidecar: Sidecar,
+    pub status: EntryStatus,
+}
+
+/// Restore every entry in the index of `output_dir` into a scratch dir and
+/// check it against the checksum stored with it, sorted by kind and key.
+pub fn verify_entries(store: &dyn ArtifactCache, output_dir: &Path) -> Result<Vec<EntryReport>> {
+    let mut sidecars = Vec::new();
+    let root = output_dir.join(STORE_META_DIR);
+    for kind_dir in fs::read_dir(&root).into_iter().flatten().flatten() {
+        for file in fs::read_dir(kind_dir.path())?.flatten() {
+            match fs::read_to_string(file.path())
+                .ok()
+                .and_then(|t| Sidecar::parse(&t))
+            {
+                Some(sidecar) => sidecars.push(sidecar),
+                None => log::warn!("Ignoring unreadable {}", file.path().display()),
+            }
+        }
+    }
+    sidecars.sort_by(|a, b| (&a.kind, &a.key).cmp(&(&b.kind, &b.key)));
+
+    let tmp = output_dir.join(VERIFY_TMP);
+    let _ = fs::remove_dir_all(&tmp);
+    fs::create_dir_all(&tmp)?;
+    let result = sidecars
+        .into_iter()
+        .map(|sidecar| {
+            let key_file = tmp.join(&sidecar.key_file);
+            let out = tmp.join("artifact");
+            fs::write(&key_file, &sidecar.key)?;
+            let _ = fs::remove_file(&out);
+            let stored = restore_checksum(store, &sidecar.kind, &key_file, &out)?;
+            let (sidecar, status) = match stored {
+                Some(stored) if store.restore(&stored.kind, &key_file, &out)? => {
+                    let actual = EntryMeta::of_file(&out)?;
+                    let status = if actual != stored.meta {
+                        EntryStatus::Corrupt(actual)
+                    } else if read_key(&output_dir.join(&stored.key_file)).as_ref()
+                        != Some(&stored.key)
+                    {
+                        EntryStatus::Stale
+                    } else {
+                        EntryStatus::Valid
+                    };
+                    (stored, status)
+                }
+                _ => (sidecar, EntryStatus::Missing),
+            };
+            Ok(EntryReport { sidecar, status })
+        })
+        .collect();
+    let _ = fs::remove_dir_all(&tmp);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::bail;
+    use tempfile::tempdir;
+
+    /// Store holding files by (kind, key).
+    struct DirStore(PathBuf);
+
+    impl DirStore {
+        fn entry(&self, kind: &str, key: &Path) -> PathBuf {
+            self.0
+                .join(kind)
+                .join(fs::read_to_string(key).unwrap().trim())
+        }
+    }
+
+    impl ArtifactCache for DirStore {
+        fn restore(&self, kind: &str, key: &Path, out: &Path) -> Result<bool> {
+            let entry = self.entry(kind, key);
+            if !entry.exists() {
+                return Ok(false);
+            }
+            fs::copy(entry, out)?;
+            Ok(true)
+        }
+
+        fn store(
+            &self,
+            kind: &str,
+            key: &Path,
+            out: &Path,
+            meta: BTreeMap<String, String>,
+        ) -> Result<()> {
+            assert!(kind.ends_with("_checksum") || meta.contains_key("sha256"));
+            let entry = self.entry(kind, key);
+            fs::create_dir_all(entry.parent().unwrap())?;
+            fs::copy(out, entry)?;
+            Ok(())
+        }
+
+        fn restore_kernel(&self, _key: &Path, _staging: &Path) -> Result<bool> {
+            bail!("not a kernel store")
+        }
+    }
+
+    #[test]
+    fn test_restore_checked_and_entries_verified() {
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("output");
+        fs::create_dir_all(&output).unwrap();
+        let store = DirStore(dir.path().join("store"));
+        let key = output.join(".rootfs-inputs.hash");
+        let image = output.join("filesystem.erofs");
+
+        // Store two versions of the rootfs and an initramfs
+        let stored = |kind: &str, key_file: &Path, key: &str, data: &str| {
+            fs::write(key_file, key).unwrap();
+            fs::write(&image, data).unwrap();
+            store_checked(&store, kind, key_file, &image, BTreeMap::new()).unwrap();
+        };
+        stored(
+            "initramfs",
+            &output.join(".initramfs-inputs.hash"),
+            "i1",
+            "initramfs",
+        );
+        stored("rootfs", &key, "erofs:old", "old rootfs");
+        stored("rootfs", &key, "erofs:new", "new rootfs");
+        let checksum = fs::read_to_string(store.0.join("rootfs_checksum/erofs:new")).unwrap();
+        assert!(checksum.contains("key=erofs:new\n") && checksum.contains("size=10\n"));
+
+        // An intact entry restores
+        fs::remove_file(&image).unwrap();
+        assert!(restore_checked(&store, "rootfs", &key, &image).unwrap());
+        assert_eq!(fs::read_to_string(&image).unwrap(), "new rootfs");
+
+        // A truncated one is rejected and removed
+        fs::write(store.0.join("rootfs/erofs:new"), "new").unwrap();
+        fs::remove_file(store.0.join("initramfs/i1")).unwrap();
+        assert!(!restore_checked(&store, "rootfs", &key, &image).unwrap());
+        assert!(!image.exists());
+
+        let reports = verify_entries(&store, &output).unwrap();
+        let statuses: Vec<(&str, &str, &str)> = reports
+            .iter()
+            .map(|r| {
+                (
+                    r.sidecar.kind.as_str(),
+                    r.sidecar.key.as_str(),
+                    r.status.label(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("initramfs", "i1", "MISSING"),
+                ("rootfs", "erofs:new", "CORRUPT"),
+                ("rootfs", "erofs:old", "STALE"),
+            ]
+        );
+        assert!(!output.join(VERIFY_TMP).exists());
+
+        // Without its checksum in the store, an intact entry is a miss, even
+        // with the local index in place
+        fs::write(store.0.join("rootfs/erofs:new"), "new rootfs").unwrap();
+        fs::remove_file(store.0.join("rootfs_checksum/erofs:new")).unwrap();
+        assert!(!restore_checked(&store, "rootfs", &key, &image).unwrap());
+        assert!(!image.exists());
+        let reports = verify_entries(&store, &output).unwrap();
+        assert_eq!(reports[1].status, EntryStatus::Missing);
+    }
+}
@@ -0,0 +1,292 @@
+//! Colors and column alignment for the CLI output (`--no-color`).
+//!
+//! Status markers (`[OK]`, `[WARN]`, `[SKIP]`, `[FAIL]`, `[RESTORE]`) are
+//! colored when stdout is a terminal, unless `--no-color` is given or
+//! `NO_COLOR` is set (<https://no-color.org>). Piped output, including the
+//! output of child builds, stays plain.
+//!
+//! Columns are padded by display width, not by `len()`: ANSI escapes take
+//! no columns and emoji and CJK characters take two, so a colored marker or
+//! an emoji in a label does not push the rest of the line out of line.
+
+use std::borrow::Cow;
+use std::fmt::Write as _;
+use std::io::{BufRead, IsTerminal, Write as _};
+
+/// Disables colors when set to anything non-empty (also set by
+/// `--no-color`, for child builds).
+pub const NO_COLOR_ENV: &str = "NO_COLOR";
+
+/// Export `--no-color` for child builds.
+///
+/// Must be called before any threads are spawned.
+pub fn export_no_color(no_color: bool) {
+    if no_color {
+        std::env::set_var(NO_COLOR_ENV, "1");
+    }
+}
+
+/// Whether output to stdout is colored.
+pub fn color_enabled() -> bool {
+    std::env::var_os(NO_COLOR_ENV).is_none_or(|v| v.is_empty()) && std::io::stdout().is_terminal()
+}
+
+/// Outcome shown by a status marker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Warn,
+    Skip,
+    Fail,
+    Restore,
+}
+
+impl Status {
+    const ALL: [Status; 5] = [Self::Ok, Self::Warn, Self::Skip, Self::Fail, Self::Restore];
+
+    /// The marker, e.g. `[OK]`.
+    pub fn marker(self) -> &'static str {
+        match self {
+            Self::Ok => "[OK]",
+            Self::Warn => "[WARN]",
+            Self::Skip => "[SKIP]",
+            Self::Fail => "[FAIL]",
+            Self::Restore => "[RESTORE]",
+        }
+    }
+
+    fn ansi(self) -> &'static str {
+        match self {
+            Self::Ok => "32",
+            Self::Warn | Self::Skip => "33",
+            Self::Fail => "1;31",
+            Self::Restore => "36",
+        }
+    }
+
+    /// The status of a marker at the start of `text`.
+    fn leading(text: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|s| {
+            text.strip_prefix(s.marker())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+        })
+    }
+}
+
+/// Width of the widest marker, so the text after markers lines up.
+const MARKER_WIDTH: usize = "[RESTORE]".len();
+
+/// `text` in the color of `status`, if colors are on.
+pub fn paint(status: Status, text: &str) -> String {
+    paint_if(color_enabled(), status, text)
+}
+
+fn paint_if(color: bool, status: Status, text: &str) -> String {
+    if color {
+        format!("\x1b[{}m{}\x1b[0m", status.ansi(), text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// The marker of `status`, colored and padded to the widest marker.
+pub fn marker(status: Status) -> String {
+    pad(&paint(status, status.marker()), MARKER_WIDTH)
+}
+
+/// A log or progress line with its leading marker colored and padded, so
+/// the names after `[SKIP]`, `[OK]` and `[RESTORE]` form a column.
+pub fn decorate(line: &str) -> Cow<'_, str> {
+    decorate_if(color_enabled(), line)
+}
+
+fn decorate_if(color: bool, line: &str) -> Cow<'_, str> {
+    let rest = line.trim_start();
+    let indent = &line[..line.len() - rest.len()];
+    match Status::leading(rest) {
+        Some(status) => {
+            let after = rest[status.marker().len()..].trim_start_matches(' ');
+            let marker = pad(&paint_if(color, status, status.marker()), MARKER_WIDTH);
+            Cow::Owned(format!("{}{} {}", indent, marker, after))
+        }
+        None => Cow::Borrowed(line),
+    }
+}
+
+/// Columns `text` takes in a terminal.
+pub fn width(text: &str) -> usize {
+    let mut columns = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // CSI sequence: up to and including the final letter
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+            continue;
+        }
+        columns += char_width(c);
+    }
+    columns
+}
+
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        // Combining marks, zero width joiner, variation selectors
+        0x0300..=0x036F | 0x200B..=0x200F | 0xFE00..=0xFE0F => 0,
+        0x1100..=0x115F
+        | 0x2600..=0x27BF
+        | 0x2E80..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1FAFF
+        | 0x20000..=0x3FFFD => 2,
+        _ if c.is_control() => 0,
+        _ => 1,
+    }
+}
+
+/// `text` padded with spaces to `columns`.
+pub fn pad(text: &str, columns: usize) -> String {
+    let mut padded = text.to_string();
+    padded.extend(std::iter::repeat_n(
+        ' ',
+        columns.saturating_sub(width(text)),
+    ));
+    padded
+}
+
+/// `title` underlined to its width.
+pub fn heading(title: &str) -> String {
+    format!("{}\n{}", title, "=".repeat(width(title)))
+}
+
+/// `lines` in a box sized to the widest line.
+pub fn boxed(lines: &[&str]) -> String {
+    let inner = lines.iter().map(|l| width(l)).max().unwrap_or(0);
+    let rule = "─".repeat(inner + 2);
+    let mut out = format!("┌{}┐\n", rule);
+    for line in lines {
+        let _ = writeln!(out, "│ {} │", pad(line, inner));
+    }
+    let _ = write!(out, "└{}┘", rule);
+    out
+}
+
+/// Ask on stdin; anything but y/yes declines.
+pub fn confirm(question: &str) -> std::io::Result<bool> {
+    print!("{} [y/N] ", question);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(
+        answer.trim().to_ascii_lowercase().as_str(),
+        "y" | "yes"
+    ))
+}
+
+/// `label: value` lines with the values in one column.
+#[derive(Debug, Default)]
+pub struct Fields {
+    rows: Vec<(String, String)>,
+}
+
+impl Fields {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a row; an empty label continues the row above.
+    pub fn row(&mut self, label: &str, value: impl Into<String>) -> &mut Self {
+        self.rows.push((label.to_string(), value.into()));
+        self
+    }
+
+    /// The rows, each indented by `indent` spaces.
+    pub fn render(&self, indent: usize) -> String {
+        let label_width = self
+            .rows
+            .iter()
+            .filter(|(label, _)| !label.is_empty())
+            .map(|(label, _)| width(label) + 1)
+            .max()
+            .unwrap_or(0);
+        let mut out = String::new();
+        for (label, value) in &self.rows {
+            let label = if label.is_empty() {
+                String::new()
+            } else {
+                format!("{}:", label)
+            };
+            let _ = writeln!(
+                out,
+                "{}{} {}",
+                " ".repeat(indent),
+                pad(&label, label_width),
+                value
+            );
+        }
+        out
+    }
+
+    /// Print the rows, each indented by `indent` spaces.
+    pub fn print(&self, indent: usize) {
+        print!("{}", self.render(indent));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_width_ignores_escapes_and_counts_wide_chars() {
+        assert_eq!(width("[OK]"), 4);
+        assert_eq!(width(&paint_if(true, Status::Fail, "[FAIL]")), 6);
+        assert_eq!(width("🚀 Boot"), 7);
+        assert_eq!(width("✔\u{fe0f}"), 2);
+        let padded = pad(&paint_if(true, Status::Ok, "ok"), 4);
+        assert_eq!(width(&padded), 4);
+        assert!(padded.ends_with("\x1b[0m  "));
+        assert_eq!(
+            boxed(&["🚀 QEMU", "ssh -p 2222"]),
+            "┌─────────────┐\n│ 🚀 QEMU     │\n│ ssh -p 2222 │\n└─────────────┘"
+        );
+    }
+
+    #[test]
+    fn test_decorate_aligns_markers() {
+        assert_eq!(
+            decorate_if(false, "\n[SKIP] kernel already built"),
+            "\n[SKIP]    kernel already built"
+        );
+        assert_eq!(
+            decorate_if(false, "  [RESTORE] rootfs restored"),
+            "  [RESTORE] rootfs restored"
+        );
+        assert_eq!(
+            decorate_if(true, "[OK] iso"),
+            "\x1b[32m[OK]\x1b[0m      iso"
+        );
+        assert_eq!(decorate_if(true, "[OKAY] iso"), "[OKAY] iso");
+        assert_eq!(decorate_if(true, "Building"), "Building");
+    }
+
+    #[test]
+    fn test_fields_align_values() {
+        let mut fields = Fields::new();
+        fields
+            .row("OS Name", "AcornOS")
+            .row("Init System", "openrc")
+            .row("", "continued");
+        assert_eq!(
+            fields.render(2),
+            "  OS Name:     AcornOS\n  Init System: openrc\n               continued\n"
+        );
+    }
+}
@@ -0,0 +1,323 @@
+//! Build phase timings (`output/build-timings.json`).
+//!
+//! distro-builder's `Timer` only prints elapsed time. Phases here are also
+//! recorded into a process-wide [`TimingCollector`]; `acornos build` writes
+//! the collected phases (start offset, duration, skipped-by-cache) to the
+//! output dir and prints the slowest ones.
+//!
+//! The rootfs and initramfs builds run as child processes (see
+//! [`crate::parallel`]). Children inherit `ACORNOS_TIMINGS_FILE` and append
+//! each phase they record to it, one tab-separated line per phase; the
+//! parent merges that file before writing the JSON.
+
+use anyhow::{Context, Result};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use distro_builder::timing::Timer;
+
+use crate::manifest::{json_str, parse_json, JsonValue};
+
+/// Timings of the last full build, in the output dir.
+pub const TIMINGS_FILENAME: &str = "build-timings.json";
+
+/// Phase lines appended by child builds during `acornos build`.
+pub const TIMINGS_PART_FILENAME: &str = ".build-timings.part";
+
+/// File child processes append their phases to.
+pub const TIMINGS_ENV: &str = "ACORNOS_TIMINGS_FILE";
+
+/// Phases listed in the end-of-build summary.
+pub const SLOWEST_SHOWN: usize = 5;
+
+/// One timed (or skipped) build phase.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhaseTiming {
+    pub name: String,
+    /// Wall-clock start, milliseconds since the Unix epoch.
+    pub start_ms: u64,
+    pub duration_ms: u64,
+    /// Skipped because the inputs were unchanged.
+    pub skipped: bool,
+}
+
+impl PhaseTiming {
+    /// `name<TAB>start_ms<TAB>duration_ms<TAB>skipped`.
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}",
+            self.name.replace(['\t', '\n'], " "),
+            self.start_ms,
+            self.duration_ms,
+            self.skipped
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        Some(Self {
+            name: fields.next()?.to_string(),
+            start_ms: fields.next()?.parse().ok()?,
+            duration_ms: fields.next()?.parse().ok()?,
+            skipped: fields.next()?.parse().ok()?,
+        })
+    }
+}
+
+/// Phases recorded during a build.
+#[derive(Debug, Default)]
+pub struct TimingCollector {
+    phases: Mutex<Vec<PhaseTiming>>,
+}
+
+impl TimingCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a phase, forwarding it to `ACORNOS_TIMINGS_FILE` if set.
+    pub fn record(&self, phase: PhaseTiming) {
+        if let Some(path) = std::env::var_os(TIMINGS_ENV) {
+            // Best effort: a lost line only costs a row in the report
+            if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+                let _ = writeln!(file, "{}", phase.to_line());
+            }
+        }
+        self.phases
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(phase);
+    }
+
+    /// Add phases appended to `path` by child processes.
+    pub fn merge_file(&self, path: &Path) {
+        let Ok(content) = fs::read_to_string(path) else {
+            return;
+        };
+        let mut phases = self.phases.lock().unwrap_or_else(|e| e.into_inner());
+        phases.extend(content.lines().filter_map(PhaseTiming::from_line));
+    }
+
+    /// Recorded phases, ordered by start time.
+    pub fn phases(&self) -> Vec<PhaseTiming> {
+        let mut phases = self
+            .phases
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        phases.sort_by_key(|p| p.start_ms);
+        phases
+    }
+
+    /// Write `build-timings.json` for a build that started at `build_start_ms`.
+    pub fn write_json(&self, path: &Path, build_start_ms: u64, total_ms: u64) -> Result<()> {
+        fs::write(path, timings_json(&self.phases(), build_start_ms, total_ms))
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// The process-wide collector.
+pub fn collector() -> &'static TimingCollector {
+    static COLLECTOR: OnceLock<TimingCollector> = OnceLock::new();
+    COLLECTOR.get_or_init(TimingCollector::new)
+}
+
+/// Milliseconds since the Unix epoch.
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Record a phase that started at `started` and just ended, without printing.
+pub fn record_since(name: &str, started: Instant) {
+    let elapsed = started.elapsed();
+    collector().record(PhaseTiming {
+        name: name.to_string(),
+        start_ms: now_ms().saturating_sub(elapsed.as_millis() as u64),
+        duration_ms: elapsed.as_millis() as u64,
+        skipped: false,
+    });
+}
+
+/// Record a phase skipped because its inputs were unchanged.
+pub fn record_skipped(name: &str) {
+    collector().record(PhaseTiming {
+        name: name.to_string(),
+        start_ms: now_ms(),
+        duration_ms: 0,
+        skipped: true,
+    });
+}
+
+/// distro-builder's [`Timer`] that also records into the collector.
+pub struct PhaseTimer {
+    name: String,
+    started: Instant,
+    timer: Timer,
+}
+
+impl PhaseTimer {
+    pub fn start(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            started: Instant::now(),
+            timer: Timer::start(name),
+        }
+    }
+
+    /// Print the elapsed time and record the phase.
+    pub fn finish(self) {
+        self.timer.finish();
+        record_since(&self.name, self.started);
+    }
+}
+
+/// Render the timings JSON; phase starts are offsets from the build start.
+pub fn timings_json(phases: &[PhaseTiming], build_start_ms: u64, total_ms: u64) -> String {
+    let rows: Vec<String> = phases
+        .iter()
+        .map(|p| {
+            format!(
+                "    {{ \"name\": {}, \"start_ms\": {}, \"duration_ms\": {}, \"skipped\": {} }}",
+                json_str(&p.name),
+                p.start_ms.saturating_sub(build_start_ms),
+                p.duration_ms,
+                p.skipped
+            )
+        })
+        .collect();
+    format!(
+        "{{\n  \"build_start_ms\": {},\n  \"total_ms\": {},\n  \"phases\": [\n{}\n  ]\n}}\n",
+        build_start_ms,
+        total_ms,
+        rows.join(",\n")
+    )
+}
+
+/// Phases from a `build-timings.json` written by [`timings_json`].
+///
+/// Returns the total build time and the phases (starts relative to the
+/// build start).
+pub fn parse_timings_json(json: &str) -> Option<(u64, Vec<PhaseTiming>)> {
+    let timings = parse_json(json)?;
+    let total = timings
+        .get("total_ms")
+        .and_then(JsonValue::as_u64)
+        .unwrap_or(0);
+    let phases: Vec<PhaseTiming> = timings
+        .get("phases")
+        .and_then(JsonValue::as_array)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|phase| {
+            Some(PhaseTiming {
+                name: phase.get("name")?.as_str()?.to_string(),
+                start_ms: phase.get("start_ms")?.as_u64()?,
+                duration_ms: phase.get("duration_ms")?.as_u64()?,
+                skipped: phase.get("skipped").and_then(JsonValue::as_bool) == Some(true),
+            })
+        })
+        .collect();
+    (total > 0 || !phases.is_empty()).then_some((total, phases))
+}
+
+/// The `n` slowest phases that actually ran, slowest first.
+pub fn slowest(phases: &[PhaseTiming], n: usize) -> Vec<&PhaseTiming> {
+    let mut ran: Vec<&PhaseTiming> = phases.iter().filter(|p| !p.skipped).collect();
+    ran.sort_by_key(|p| std::cmp::Reverse(p.duration_ms));
+    ran.truncate(n);
+    ran
+}
+
+/// `1.2s` / `3.4m`.
+pub fn format_duration(ms: u64) -> String {
+    let secs = Duration::from_millis(ms).as_secs_f64();
+    if secs >= 60.0 {
+        format!("{:.1}m", secs / 60.0)
+    } else {
+        format!("{:.1}s", secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn phase(name: &str, start_ms: u64, duration_ms: u64, skipped: bool) -> PhaseTiming {
+        PhaseTiming {
+            name: name.to_string(),
+            start_ms,
+            duration_ms,
+            skipped,
+        }
+    }
+
+    #[test]
+    fn test_collector_merges_child_phases() {
+        let dir = tempfile::tempdir().unwrap();
+        let part = dir.path().join(TIMINGS_PART_FILENAME);
+        fs::write(
+            &part,
+            format!(
+                "{}\nnot a phase\n{}\n",
+                phase("component: Filesystem", 1_500, 40, false).to_line(),
+                phase("rootfs image", 2_000, 9_000, false).to_line()
+            ),
+        )
+        .unwrap();
+
+        let collector = TimingCollector::new();
+        collector
+            .phases
+            .lock()
+            .unwrap()
+            .push(phase("ISO", 12_000, 30_000, false));
+        collector
+            .phases
+            .lock()
+            .unwrap()
+            .push(phase("Initramfs", 1_000, 0, true));
+        collector.merge_file(&part);
+        collector.merge_file(&dir.path().join("missing"));
+
+        let names: Vec<String> = collector.phases().into_iter().map(|p| p.name).collect();
+        assert_eq!(
+            names,
+            ["Initramfs", "component: Filesystem", "rootfs image", "ISO"]
+        );
+
+        let phases = collector.phases();
+        let top: Vec<&str> = slowest(&phases, 2)
+            .into_iter()
+            .map(|p| p.name.as_str())
+            .collect();
+        assert_eq!(top, ["ISO", "rootfs image"]);
+    }
+
+    #[test]
+    fn test_timings_json_round_trip() {
+        let phases = vec![
+            phase("Initramfs", 1_000, 0, true),
+            phase("component: \"quoted\", {x}", 1_500, 40, false),
+            phase("ISO", 12_000, 30_000, false),
+        ];
+        let json = timings_json(&phases, 1_000, 42_000);
+        assert!(json.contains("{ \"name\": \"ISO\", \"start_ms\": 11000, \"duration_ms\": 30000, \"skipped\": false }"));
+
+        let (total, parsed) = parse_timings_json(&json).unwrap();
+        assert_eq!(total, 42_000);
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(parsed[0].start_ms, 0);
+        assert!(parsed[0].skipped);
+        assert_eq!(parsed[1].name, "component: \"quoted\", {x}");
+        assert!(parse_timings_json("not json").is_none());
+
+        assert_eq!(format_duration(1_234), "1.2s");
+        assert_eq!(format_duration(90_000), "1.5m");
+    }
+}
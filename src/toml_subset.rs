@@ -0,0 +1,171 @@
+//! The subset of TOML read by the project-root config files
+//! (`components.toml`, `disks.toml`, `firmware.toml`, `acorn-pins.toml`,
+//! `acorn-packages.toml`).
+//!
+//! Each file has its own few sections and keys, so this only splits the
+//! text into headers and `key = value` entries, with `#` comments (outside
+//! strings) stripped and arrays allowed to span lines. Values are typed by
+//! the file that reads them, through [`Value`], so its errors can say what
+//! it expected; every error names the line.
+
+use anyhow::{bail, Context, Result};
+
+/// One header or entry of the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    /// `[name]`
+    Table(String),
+    /// `[[name]]`
+    ArrayTable(String),
+    /// `key = value`; a quoted key is unquoted.
+    Entry { key: String, value: Value },
+}
+
+/// An item and the line it starts on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub lineno: usize,
+    pub item: Item,
+}
+
+/// A value as written, without its comment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Value(String);
+
+impl Value {
+    pub fn raw(&self) -> &str {
+        &self.0
+    }
+
+    /// `"text"` as `text`; `None` for anything else.
+    pub fn string(&self) -> Option<String> {
+        unquote(&self.0).map(str::to_string)
+    }
+
+    /// `true` or `false`.
+    pub fn bool(&self) -> Option<bool> {
+        match self.0.as_str() {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        }
+    }
+
+    /// `["a", "b"]` as its strings (a trailing comma is allowed).
+    pub fn strings(&self) -> Option<Vec<String>> {
+        let body = self.0.strip_prefix('[')?.strip_suffix(']')?;
+        body.split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| unquote(item).map(str::to_string))
+            .collect()
+    }
+}
+
+/// Split `text` into headers and entries.
+pub fn parse(text: &str) -> Result<Vec<Line>> {
+    let mut items = Vec::new();
+    let mut lines = text.lines().enumerate();
+    while let Some((index, line)) = lines.next() {
+        let lineno = index + 1;
+        let code = strip_comment(line).trim();
+        if code.is_empty() {
+            continue;
+        }
+        let item = if let Some(header) = code.strip_prefix("[[") {
+            let name = header
+                .strip_suffix("]]")
+                .with_context(|| format!("line {}: unterminated section header", lineno))?;
+            Item::ArrayTable(name.trim().to_string())
+        } else if let Some(header) = code.strip_prefix('[') {
+            let name = header
+                .strip_suffix(']')
+                .with_context(|| format!("line {}: unterminated section header", lineno))?;
+            Item::Table(name.trim().to_string())
+        } else {
+            let (key, value) = code
+                .split_once('=')
+                .with_context(|| format!("line {}: expected `key = value`", lineno))?;
+            let key = key.trim().trim_matches('"').to_string();
+            let mut value = value.trim().to_string();
+            // Arrays may span lines
+            while value.starts_with('[') && !value.ends_with(']') {
+                let (_, next) = lines.next().with_context(|| {
+                    format!("line {}: key `{}`: unterminated array", lineno, key)
+                })?;
+                value.push(' ');
+                value.push_str(strip_comment(next).trim());
+            }
+            if key.is_empty() {
+                bail!("line {}: expected `key = value`", lineno);
+            }
+            Item::Entry {
+                key,
+                value: Value(value),
+            }
+        };
+        items.push(Line { lineno, item });
+    }
+    Ok(items)
+}
+
+/// Strip a `#` comment that is not inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// `"value"` without its quotes; `None` if unquoted or holding a quote.
+fn unquote(value: &str) -> Option<&str> {
+    let inner = value.strip_prefix('"')?.strip_suffix('"')?;
+    (!inner.contains('"')).then_some(inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_items() {
+        let lines = parse(
+            "# top\n[add]\npackages = [\"a\", # first\n  \"b\",\n]\n\
+             \"vim\" = \"9.1#0\"  # pinned\n\n[[two-disks.disk]]\nssh = false\n",
+        )
+        .unwrap();
+        let linenos: Vec<usize> = lines.iter().map(|l| l.lineno).collect();
+        assert_eq!(linenos, [2, 3, 6, 8, 9]);
+        assert_eq!(lines[0].item, Item::Table("add".into()));
+        let Item::Entry { key, value } = &lines[1].item else {
+            panic!("{:?}", lines[1]);
+        };
+        assert_eq!(key, "packages");
+        assert_eq!(value.strings().unwrap(), ["a", "b"]);
+        let Item::Entry { key, value } = &lines[2].item else {
+            panic!("{:?}", lines[2]);
+        };
+        assert_eq!(
+            (key.as_str(), value.string().as_deref()),
+            ("vim", Some("9.1#0"))
+        );
+        assert_eq!(lines[3].item, Item::ArrayTable("two-disks.disk".into()));
+        let Item::Entry { value, .. } = &lines[4].item else {
+            panic!("{:?}", lines[4]);
+        };
+        assert_eq!(value.bool(), Some(false));
+        assert_eq!(value.string(), None);
+
+        let err = |text: &str| format!("{:#}", parse(text).unwrap_err());
+        assert!(err("[add\n").contains("line 1: unterminated section header"));
+        assert!(err("\nvim\n").contains("line 2: expected `key = value`"));
+        assert!(err("packages = [\"a\",\n").contains("key `packages`: unterminated array"));
+        assert_eq!(Value("[iwlwifi]".into()).strings(), None);
+        assert_eq!(Value("\"a\"b\"".into()).string(), None);
+    }
+}
@@ -0,0 +1,241 @@
+//! Copying host directory trees into the image.
+//!
+//! Component trees come from the Alpine rootfs, but the profile and live
+//! overlays are edited by hand and may hold whatever was in the checkout:
+//! a fifo left by a test, a symlink made with an absolute host path. Only
+//! regular files, directories and symlinks are copied; anything else is
+//! skipped with a warning (reading a fifo would hang the build).
+//!
+//! Symlinks are checked against the image root. An absolute target inside
+//! the tree being copied is a host path, and is rewritten relative so it
+//! still resolves in the image; other absolute targets are image paths
+//! (`/bin/busybox`) and kept. A relative target climbing out of the image
+//! root fails the copy, naming the link. Dangling links are copied as they
+//! are, since the file may come from another component.
+//!
+//! Regular files go through a [`Copier`], so trees from the rootfs are
+//! reflinked (or hardlinked) where the filesystem allows.
+
+use anyhow::{bail, Context, Result};
+use std::fs::{self, FileType};
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Component, Path, PathBuf};
+
+use crate::fast_copy::Copier;
+
+/// Copy `src` to `dst`, which lies under the image root `root`.
+///
+/// Logs a warning but continues if `src` doesn't exist; optional config
+/// directories (like etc/udev/rules.d) are copied this way.
+pub fn copy_tree(src: &Path, dst: &Path, root: &Path) -> Result<()> {
+    copy_tree_with(src, dst, root, &Copier::new(false))
+}
+
+/// [`copy_tree`], with the files copied by `copier`.
+pub fn copy_tree_with(src: &Path, dst: &Path, root: &Path, copier: &Copier) -> Result<()> {
+    if !src.exists() {
+        log::warn!("copy_tree: source not found: {}", src.display());
+        return Ok(());
+    }
+    let image_top = dst
+        .strip_prefix(root)
+        .with_context(|| format!("{} is not under {}", dst.display(), root.display()))?;
+
+    if src.is_file() {
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        copier.copy(src, dst)?;
+        return Ok(());
+    }
+
+    copy_dir(src, dst, src, image_top, copier)
+}
+
+fn copy_dir(
+    src: &Path,
+    dst: &Path,
+    src_top: &Path,
+    image_top: &Path,
+    copier: &Copier,
+) -> Result<()> {
+    fs::create_dir_all(dst).with_context(|| format!("Failed to create {}", dst.display()))?;
+
+    // Sorted, so the tree is created in the same order in every build
+    let mut entries: Vec<_> = fs::read_dir(src)
+        .and_then(|entries| entries.collect::<Result<_, _>>())
+        .with_context(|| format!("Failed to read {}", src.display()))?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let src_path = entry.path();
+        let dst_path = dst.join(entry.file_name());
+        let file_type = entry
+            .file_type()
+            .with_context(|| format!("Failed to stat {}", src_path.display()))?;
+
+        if let Some(kind) = special_file(&file_type) {
+            log::warn!("Skipping {} ({})", src_path.display(), kind);
+        } else if file_type.is_symlink() {
+            let target = link_target(&src_path, src_top, image_top)?;
+            if dst_path.exists() || dst_path.is_symlink() {
+                fs::remove_file(&dst_path)
+                    .with_context(|| format!("Failed to replace {}", dst_path.display()))?;
+            }
+            std::os::unix::fs::symlink(&target, &dst_path)
+                .with_context(|| format!("Failed to copy symlink {}", src_path.display()))?;
+        } else if file_type.is_dir() {
+            copy_dir(&src_path, &dst_path, src_top, image_top, copier)?;
+        } else {
+            copier.copy(&src_path, &dst_path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// What a file that is not copied is (fifo, socket, device), if it is one.
+pub fn special_file(file_type: &FileType) -> Option<&'static str> {
+    if file_type.is_fifo() {
+        Some("fifo")
+    } else if file_type.is_socket() {
+        Some("socket")
+    } else if file_type.is_block_device() {
+        Some("block device")
+    } else if file_type.is_char_device() {
+        Some("character device")
+    } else {
+        None
+    }
+}
+
+/// Target to give the copy of the symlink `link`, a path under `src_top`,
+/// when `src_top` lands at `image_top` (relative to the image root).
+pub fn link_target(link: &Path, src_top: &Path, image_top: &Path) -> Result<PathBuf> {
+    let target =
+        fs::read_link(link).with_context(|| format!("Failed to read {}", link.display()))?;
+    let link_dir = image_top.join(link.strip_prefix(src_top)?);
+    let link_dir = link_dir.parent().unwrap_or(Path::new(""));
+
+    if target.is_absolute() {
+        return Ok(match target.strip_prefix(src_top) {
+            Ok(rel) => relative_path(link_dir, &image_top.join(rel)),
+            Err(_) => target,
+        });
+    }
+    if normalize(&link_dir.join(&target)).is_none() {
+        bail!(
+            "Symlink {} -> {} points outside the image",
+            link.display(),
+            target.display()
+        );
+    }
+    Ok(target)
+}
+
+/// `path` with `.` and `..` resolved; `None` if it climbs above its start.
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                if !out.pop() {
+                    return None;
+                }
+            }
+            Component::Normal(name) => out.push(name),
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+    Some(out)
+}
+
+/// Relative path from the directory `from` to `to`, both image-relative.
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let from = normalize(from).unwrap_or_default();
+    let to = normalize(to).unwrap_or_default();
+    let common = from
+        .components()
+        .zip(to.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut rel = PathBuf::new();
+    for _ in common..from.components().count() {
+        rel.push("..");
+    }
+    rel.extend(to.components().skip(common));
+    if rel.as_os_str().is_empty() {
+        rel.push(".");
+    }
+    rel
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+    use std::process::Command;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_copy_tree_skips_special_files_and_fixes_links() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("live-overlay");
+        let root = dir.path().join("staging");
+        fs::create_dir_all(src.join("etc/conf.d")).unwrap();
+        fs::write(src.join("etc/conf.d/real"), "acorn").unwrap();
+        let status = Command::new("mkfifo")
+            .arg(src.join("etc/pipe"))
+            .status()
+            .unwrap();
+        assert!(status.success());
+        // Made with the host path of the checkout
+        symlink(src.join("etc/conf.d/real"), src.join("etc/host-link")).unwrap();
+        symlink("/bin/busybox", src.join("etc/image-link")).unwrap();
+        symlink("missing", src.join("etc/conf.d/dangling")).unwrap();
+
+        copy_tree(&src, &root.join("etc/live"), &root).unwrap();
+        let dst = root.join("etc/live/etc");
+        assert_eq!(
+            fs::read_to_string(dst.join("conf.d/real")).unwrap(),
+            "acorn"
+        );
+        assert!(!dst.join("pipe").exists() && !dst.join("pipe").is_symlink());
+        assert_eq!(
+            fs::read_link(dst.join("host-link")).unwrap(),
+            Path::new("conf.d/real")
+        );
+        assert_eq!(fs::read_to_string(dst.join("host-link")).unwrap(), "acorn");
+        assert_eq!(
+            fs::read_link(dst.join("image-link")).unwrap(),
+            Path::new("/bin/busybox")
+        );
+        assert_eq!(
+            fs::read_link(dst.join("conf.d/dangling")).unwrap(),
+            Path::new("missing")
+        );
+
+        symlink("../../../../passwd", src.join("etc/escape")).unwrap();
+        let err = copy_tree(&src, &root.join("etc/live"), &root)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains(&format!("{}", src.join("etc/escape").display())));
+        assert!(err.contains("points outside the image"), "{}", err);
+    }
+
+    #[test]
+    fn test_relative_path() {
+        assert_eq!(
+            relative_path(Path::new("etc"), Path::new("usr/share/zoneinfo/UTC")),
+            Path::new("../usr/share/zoneinfo/UTC")
+        );
+        assert_eq!(
+            relative_path(Path::new("etc/live"), Path::new("etc/live/x")),
+            Path::new("x")
+        );
+        assert_eq!(
+            relative_path(Path::new("etc"), Path::new("etc")),
+            Path::new(".")
+        );
+        assert_eq!(normalize(Path::new("etc/../../x")), None);
+    }
+}
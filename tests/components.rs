@@ -0,0 +1,209 @@
+//! Components run against a synthetic source rootfs
+//! ([`fixtures::mini_rootfs`]), checking the shape of the staging tree.
+
+mod fixtures;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use acornos::component::executor::missing_sources;
+use acornos::component::service_graph::{Anomaly, ServiceGraph};
+use acornos::component::{Component, APK, BUSYBOX, CHRONY, FILESYSTEM, OPENRC};
+use acornos::license_report::LicenseReport;
+use distro_spec::shared::busybox::{COMMON_APPLETS, SBIN_APPLETS};
+use distro_spec::shared::components::{FHS_SYMLINKS, VAR_SYMLINKS};
+use fixtures::mini_rootfs::{MiniRootfs, STUB_LIBRARIES};
+
+const BASE: &[&Component] = &[&FILESYSTEM, &BUSYBOX, &OPENRC];
+
+#[test]
+fn test_fixture_has_every_source() {
+    let rootfs = MiniRootfs::new();
+    for component in BASE.iter().chain([&&CHRONY, &&APK]) {
+        let missing: Vec<String> = component
+            .ops
+            .iter()
+            .flat_map(|op| missing_sources(&rootfs.source, op))
+            .collect();
+        assert!(missing.is_empty(), "{}: {:?}", component.name, missing);
+    }
+}
+
+#[test]
+fn test_filesystem_and_busybox() {
+    let rootfs = MiniRootfs::new();
+    rootfs.run(&[&FILESYSTEM, &BUSYBOX]).unwrap();
+    let staging = &rootfs.staging;
+
+    for (link, target) in FHS_SYMLINKS.iter().chain(VAR_SYMLINKS) {
+        assert_eq!(
+            fs::read_link(staging.join(link)).ok().as_deref(),
+            Some(Path::new(target)),
+            "{}",
+            link
+        );
+    }
+    let mode = |p: &str| fs::metadata(staging.join(p)).unwrap().permissions().mode() & 0o7777;
+    assert_eq!(mode("tmp"), 0o1777);
+    assert_eq!(mode("root"), 0o700);
+
+    let busybox = rootfs.resolve("usr/bin/busybox").expect("busybox staged");
+    for applet in COMMON_APPLETS {
+        assert_eq!(
+            rootfs.resolve(Path::new("bin").join(applet)).as_ref(),
+            Some(&busybox),
+            "{}",
+            applet
+        );
+    }
+    for applet in SBIN_APPLETS {
+        assert_eq!(
+            rootfs.resolve(Path::new("sbin").join(applet)).as_ref(),
+            Some(&busybox),
+            "{}",
+            applet
+        );
+    }
+
+    // busybox's loader and libc, and the libraries only loaded at runtime
+    let libc = rootfs.resolve("lib/libc.musl-x86_64.so.1").unwrap();
+    assert_eq!(libc, rootfs.resolve("lib/ld-musl-x86_64.so.1").unwrap());
+    for lib in STUB_LIBRARIES {
+        assert!(
+            rootfs.resolve(Path::new("lib").join(lib)).is_some(),
+            "{}",
+            lib
+        );
+    }
+}
+
+#[test]
+fn test_runlevels_point_at_init_scripts() {
+    let rootfs = MiniRootfs::new();
+    let components = [&FILESYSTEM, &BUSYBOX, &OPENRC, &CHRONY];
+    rootfs.run(&components).unwrap();
+
+    let runlevels = rootfs.staging.join("etc/runlevels");
+    let mut links = 0;
+    for level in fs::read_dir(&runlevels).unwrap().flatten() {
+        for link in fs::read_dir(level.path()).unwrap().flatten() {
+            let rel = link
+                .path()
+                .strip_prefix(&rootfs.staging)
+                .unwrap()
+                .to_owned();
+            let script = rootfs
+                .resolve(&rel)
+                .unwrap_or_else(|| panic!("{} is dangling", rel.display()));
+            assert!(script.is_file(), "{}", rel.display());
+            assert_eq!(
+                script.parent(),
+                Some(rootfs.staging.join("etc/init.d").as_path()),
+                "{}",
+                rel.display()
+            );
+            links += 1;
+        }
+    }
+    let enables = components
+        .iter()
+        .flat_map(|c| c.ops)
+        .filter(|op| matches!(op, acornos::component::Op::OpenrcEnable(..)))
+        .count();
+    assert_eq!(links, enables);
+
+    // The fixture's depend() blocks are all met by what is enabled
+    let graph = ServiceGraph::load(&rootfs.staging, &components).unwrap();
+    let problems: Vec<String> = graph
+        .anomalies()
+        .into_iter()
+        .filter(|a| !matches!(a, Anomaly::NeverEnabled { .. }))
+        .map(|a| a.to_string())
+        .collect();
+    assert!(problems.is_empty(), "{:?}", problems);
+    let sysinit = graph.order("sysinit");
+    let position = |s: &str| sysinit.iter().position(|x| x == s).unwrap();
+    assert!(position("sysfs") < position("mdev"));
+    assert!(position("mdev") < position("hwdrivers"));
+}
+
+#[test]
+fn test_accounts_added_once_on_rerun() {
+    let rootfs = MiniRootfs::new();
+    rootfs
+        .run(&[&FILESYSTEM, &BUSYBOX, &OPENRC, &CHRONY])
+        .unwrap();
+    // An incremental build runs the component again into the same staging
+    rootfs.run(&[&CHRONY]).unwrap();
+
+    let count = |file: &str, name: &str| {
+        fs::read_to_string(rootfs.staging.join("etc").join(file))
+            .unwrap()
+            .lines()
+            .filter(|l| l.split(':').next() == Some(name))
+            .count()
+    };
+    for file in ["passwd", "group", "shadow"] {
+        assert_eq!(count(file, "chrony"), 1, "{}", file);
+        assert_eq!(count(file, "root"), 1, "{}", file);
+    }
+    assert_eq!(count("passwd", "nobody"), 1);
+
+    let passwd = fs::read_to_string(rootfs.staging.join("etc/passwd")).unwrap();
+    assert!(passwd.contains("chrony:x:123:123:"), "{}", passwd);
+    assert!(rootfs.resolve("etc/chrony/chrony.conf").is_some());
+    assert_eq!(
+        fs::read_link(rootfs.staging.join("usr/sbin/chronyc")).unwrap(),
+        Path::new("chronyd")
+    );
+}
+
+#[test]
+fn test_apk_works_on_the_installed_system() {
+    let rootfs = MiniRootfs::new();
+    rootfs.run(&[&FILESYSTEM, &APK]).unwrap();
+    let staging = &rootfs.staging;
+
+    let repositories = fs::read_to_string(staging.join("etc/apk/repositories")).unwrap();
+    assert!(repositories.lines().count() == 2, "{}", repositories);
+    assert!(repositories.ends_with("/community\n"), "{}", repositories);
+    assert!(!repositories.contains("apks"), "{}", repositories);
+    assert_eq!(
+        fs::read_to_string(staging.join("etc/apk/world")).unwrap(),
+        "alpine-base\nchrony\n"
+    );
+    assert!(staging.join("lib/apk/db/installed").is_file());
+    assert!(!staging.join("lib/apk/db/lock").exists());
+
+    // Only the keys can be missing, where distro-spec ships none
+    let problems: Vec<String> = acornos::apk::staged_problems(staging)
+        .into_iter()
+        .filter(|p| !p.starts_with("etc/apk/keys"))
+        .collect();
+    assert!(problems.is_empty(), "{:?}", problems);
+}
+
+#[test]
+fn test_copied_files_attributed_to_packages() {
+    let rootfs = MiniRootfs::new();
+    let state = rootfs
+        .run(&[&FILESYSTEM, &BUSYBOX, &OPENRC, &CHRONY])
+        .unwrap();
+    let report = LicenseReport::load(&rootfs.source, &state.copied()).unwrap();
+
+    let names: Vec<&str> = report.package_names().collect();
+    assert!(names.contains(&"busybox"), "{:?}", names);
+    assert!(names.contains(&"chrony"), "{:?}", names);
+    // chrony.conf, chronyd and the chronyc link; the init script is not in
+    // the fixture's database
+    let chrony = report.packages.iter().find(|(p, _)| p.name == "chrony");
+    assert_eq!(
+        chrony.map(|(p, n)| (p.license.as_str(), *n)),
+        Some(("GPL-2.0-only", 3))
+    );
+    assert!(report
+        .unattributed
+        .contains(&"etc/init.d/chronyd".to_string()));
+    assert!(report.render().contains("busybox"));
+}
@@ -0,0 +1,226 @@
+//! Minimal fake Alpine rootfs for running components without the real one.
+//!
+//! Lays out `<base_dir>/downloads/rootfs` in a tempdir with just what the
+//! filesystem, busybox, openrc and chrony components copy:
+//!
+//! - `bin/busybox` - the static musl test binary from `testdata/`, with the
+//!   musl loader and `libc.musl` link next to stub libraries in `lib/`
+//! - `etc/init.d/*` - one script per service, each with a `depend()` block
+//! - the OpenRC binaries, `usr/libexec/rc` and `etc/rc.conf`
+//! - `lib/apk/db/installed` with the packages the components install, and
+//!   `etc/apk/world`
+//! - `etc/passwd`, `etc/group` and `etc/shadow` with the base accounts
+
+use anyhow::Result;
+use std::ffi::OsString;
+use std::fs;
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::{Component as PathComponent, Path, PathBuf};
+use tempfile::TempDir;
+
+use acornos::component::executor::{execute, ExecState};
+use acornos::component::{BuildContext, Component};
+use distro_builder::alpine::extract::ExtractPaths;
+use distro_builder::{LicenseTracker, PackageManager};
+
+/// Init scripts and the body of their `depend()` blocks.
+pub const INIT_SCRIPTS: &[(&str, &str)] = &[
+    ("bootmisc", "need localmount\n\tbefore logger"),
+    ("chronyd", "use net dns\n\tafter firewall"),
+    ("devfs", "provide dev-mount\n\tbefore dev"),
+    ("dmesg", "before dev"),
+    ("fsck", "use dev clock modules"),
+    ("hostname", "keyword -prefix -lxc"),
+    ("hwclock", "provide clock\n\tbefore *"),
+    ("hwdrivers", "need dev\n\tbefore checkfs fsck"),
+    ("killprocs", "after *"),
+    ("local", "after *"),
+    ("localmount", "need fsck root\n\tuse lvm modules"),
+    (
+        "mdev",
+        "provide dev\n\tneed sysfs dev-mount\n\tbefore checkfs fsck",
+    ),
+    ("modules", "before hwdrivers"),
+    ("mount-ro", "need killprocs savecache"),
+    ("mtab", "need root"),
+    ("networking", "provide net\n\tneed localmount"),
+    ("procfs", "use modules"),
+    ("root", "need fsck"),
+    ("savecache", "after killprocs"),
+    ("seedrng", "need localmount"),
+    ("swap", "before localmount"),
+    ("swclock", "provide clock"),
+    ("sysctl", "before bootmisc"),
+    ("sysfs", "keyword -vserver"),
+];
+
+/// Stub libraries in the source `lib/`, besides the musl loader.
+pub const STUB_LIBRARIES: &[&str] = &["libz.so.1", "libcrypto.so.3"];
+
+/// `lib/apk/db/installed` of the fixture.
+const INSTALLED_DB: &str = "\
+P:alpine-base\nV:3.23.0-r0\nL:MIT\n\n\
+P:busybox\nV:1.37.0-r0\nL:GPL-2.0-only\nF:bin\nR:busybox\n\n\
+P:musl\nV:1.2.5-r10\nL:MIT\nF:lib\nR:ld-musl-x86_64.so.1\nR:libc.musl-x86_64.so.1\n\n\
+P:chrony\nV:4.6-r0\nL:GPL-2.0-only\nF:etc/chrony\nR:chrony.conf\n\
+F:usr/sbin\nR:chronyd\nR:chronyc\n\n";
+
+/// A fake rootfs in a tempdir, with an empty staging dir beside it.
+pub struct MiniRootfs {
+    _dir: TempDir,
+    /// Project dir holding `downloads/rootfs`.
+    pub base_dir: PathBuf,
+    /// The fake Alpine rootfs.
+    pub source: PathBuf,
+    /// Where the components install to.
+    pub staging: PathBuf,
+}
+
+impl MiniRootfs {
+    /// Build the fixture.
+    pub fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let base_dir = dir.path().join("acorn");
+        let source = ExtractPaths::new(&base_dir).rootfs;
+        let staging = dir.path().join("staging");
+        fs::create_dir_all(&staging).unwrap();
+        let rootfs = Self {
+            _dir: dir,
+            base_dir,
+            source,
+            staging,
+        };
+        rootfs.populate();
+        rootfs
+    }
+
+    fn populate(&self) {
+        let busybox = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/hello-musl-x86_64");
+        self.copy("bin/busybox", &busybox, 0o755);
+
+        // As in Alpine: libc.musl is a link to the loader
+        self.write("lib/ld-musl-x86_64.so.1", "loader", 0o755);
+        symlink(
+            "ld-musl-x86_64.so.1",
+            self.source.join("lib/libc.musl-x86_64.so.1"),
+        )
+        .unwrap();
+        for lib in STUB_LIBRARIES {
+            self.write(&format!("lib/{}", lib), "not elf", 0o755);
+        }
+
+        for (name, depend) in INIT_SCRIPTS {
+            let script = format!(
+                "#!/sbin/openrc-run\n\ndescription=\"{} (fixture)\"\n\ndepend() {{\n\t{}\n}}\n",
+                name, depend
+            );
+            self.write(&format!("etc/init.d/{}", name), &script, 0o755);
+        }
+        for bin in ["openrc", "openrc-init", "openrc-run", "openrc-shutdown"] {
+            self.write(&format!("sbin/{}", bin), "#!/bin/sh\n", 0o755);
+        }
+        self.write("usr/libexec/rc/sh/functions.sh", "# rc functions\n", 0o644);
+        self.write("usr/libexec/rc/bin/einfo", "#!/bin/sh\n", 0o755);
+        self.write("etc/rc.conf", "rc_parallel=\"NO\"\n", 0o644);
+
+        self.write("lib/apk/db/installed", INSTALLED_DB, 0o644);
+        self.write("lib/apk/db/lock", "", 0o600);
+        self.write("etc/apk/world", "chrony\nalpine-base\n", 0o644);
+        self.write(
+            "etc/chrony/chrony.conf",
+            "pool pool.ntp.org iburst\n",
+            0o644,
+        );
+        self.write("usr/sbin/chronyd", "#!/bin/sh\n", 0o755);
+        symlink("chronyd", self.source.join("usr/sbin/chronyc")).unwrap();
+
+        self.write(
+            "etc/passwd",
+            "root:x:0:0:root:/root:/bin/sh\nnobody:x:65534:65534:nobody:/:/sbin/nologin\n",
+            0o644,
+        );
+        self.write(
+            "etc/group",
+            "root:x:0:root\nwheel:x:10:root\nnobody:x:65534:\n",
+            0o644,
+        );
+        self.write("etc/shadow", "root:*:19000:0:::::\n", 0o640);
+    }
+
+    fn write(&self, rel: &str, content: &str, mode: u32) {
+        let path = self.source.join(rel);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    fn copy(&self, rel: &str, from: &Path, mode: u32) {
+        let path = self.source.join(rel);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::copy(from, &path).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    /// Build context for the fixture and its staging dir.
+    pub fn ctx(&self) -> BuildContext {
+        BuildContext::new(&self.base_dir, &self.staging, "the mini rootfs fixture").unwrap()
+    }
+
+    /// Run `components` in order into staging, as one build would, and
+    /// return what the build recorded.
+    pub fn run(&self, components: &[&Component]) -> Result<ExecState> {
+        let ctx = self.ctx();
+        let tracker = LicenseTracker::new(self.source.clone(), PackageManager::Apk);
+        let state = ExecState::default();
+        for component in components {
+            execute(&ctx, component, &tracker, &state)?;
+        }
+        Ok(state)
+    }
+
+    /// `rel` in staging with every symlink followed inside staging
+    /// (absolute targets are relative to staging, not the host).
+    pub fn resolve(&self, rel: impl AsRef<Path>) -> Option<PathBuf> {
+        resolve_in(&self.staging, rel.as_ref())
+    }
+}
+
+/// `rel` under `root` with its symlinks followed inside `root`.
+pub fn resolve_in(root: &Path, rel: &Path) -> Option<PathBuf> {
+    let names = |path: &Path| -> Vec<OsString> {
+        path.components()
+            .rev()
+            .filter_map(|c| match c {
+                PathComponent::Normal(name) => Some(name.to_owned()),
+                PathComponent::ParentDir => Some("..".into()),
+                _ => None,
+            })
+            .collect()
+    };
+    let mut pending = names(rel);
+    let mut current = root.to_path_buf();
+    let mut hops = 0;
+    while let Some(name) = pending.pop() {
+        if name == ".." {
+            if current != root {
+                current.pop();
+            }
+            continue;
+        }
+        let next = current.join(&name);
+        match fs::read_link(&next) {
+            Ok(target) => {
+                hops += 1;
+                if hops > 40 {
+                    return None;
+                }
+                if target.is_absolute() {
+                    current = root.to_path_buf();
+                }
+                pending.extend(names(&target));
+            }
+            Err(_) => current = next,
+        }
+    }
+    current.exists().then_some(current)
+}